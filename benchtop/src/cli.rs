@@ -20,6 +20,11 @@ pub enum Commands {
     ///
     /// This will not reset the database unless `--reset` is provided.
     Run(RunParams),
+    /// Migrate an sp-trie state stored in RocksDB into a fresh NOMT database.
+    ///
+    /// Both roots are printed once the migration has completed.
+    #[cfg(feature = "sp-trie")]
+    Migrate(MigrateParams),
 }
 
 impl Display for Backend {
@@ -70,6 +75,62 @@ pub struct RunParams {
     pub reset: bool,
 }
 
+/// Parameters to the migrate command.
+#[cfg(feature = "sp-trie")]
+#[derive(Debug, Args)]
+pub struct MigrateParams {
+    /// Path to the RocksDB database holding the sp-trie state.
+    #[clap(default_value = "sp_trie_db")]
+    #[arg(long)]
+    pub source: String,
+
+    /// The number of columns of the source database.
+    #[clap(default_value = "2")]
+    #[arg(long)]
+    pub columns: u32,
+
+    /// The column of the source database holding the trie nodes.
+    #[clap(default_value = "0")]
+    #[arg(long)]
+    pub column: u32,
+
+    /// The hex-encoded state root to migrate.
+    ///
+    /// Leave it empty to use the root stored by the benchtop sp-trie backend.
+    #[arg(long)]
+    pub root: Option<String>,
+
+    /// Hash keys with SHA-256 to obtain key paths, instead of requiring 32-byte keys.
+    #[clap(default_value = "false")]
+    #[arg(long)]
+    pub hash_keys: bool,
+
+    /// Path to the NOMT database to create. It must be empty.
+    #[clap(default_value = "nomt_db")]
+    #[arg(long)]
+    pub target: String,
+
+    /// The number of keys committed to NOMT at once.
+    #[clap(default_value = "100000", value_parser=clap::value_parser!(u64).range(1..))]
+    #[arg(long)]
+    pub batch_size: u64,
+
+    /// The number of threads to use in NOMT Merkle commit.
+    #[clap(default_value = "1")]
+    #[arg(long = "commit-concurrency")]
+    pub commit_concurrency: usize,
+
+    /// Number of io_uring instances (or I/O threads on non-Linux).
+    #[clap(default_value = "3")]
+    #[arg(long = "io-workers")]
+    pub io_workers: usize,
+
+    /// Whether to read back every migrated key from NOMT and compare it against the source.
+    #[clap(default_value = "false")]
+    #[arg(long)]
+    pub verify: bool,
+}
+
 #[derive(Clone, Debug, Args)]
 pub struct WorkloadParams {
     /// Workload used by benchmarks.
@@ -1,6 +1,8 @@
 mod backend;
 mod cli;
 mod custom_workload;
+#[cfg(feature = "sp-trie")]
+mod migrate;
 mod nomt;
 
 #[cfg(feature = "sov-db")]
@@ -23,6 +25,8 @@ pub fn main() -> Result<()> {
     match cli.command {
         Commands::Init(params) => init(params),
         Commands::Run(params) => run(params),
+        #[cfg(feature = "sp-trie")]
+        Commands::Migrate(params) => migrate::migrate(params),
     }
 }
 
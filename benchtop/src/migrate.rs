@@ -0,0 +1,163 @@
+//! Migration of an existing sp-trie state into NOMT.
+//!
+//! The source is a kvdb-rocksdb database holding sp-trie nodes under prefixed keys, as laid out
+//! by the sp-trie backend of benchtop or by the state column of a Substrate node. Every key-value
+//! pair reachable from the source root is bulk-loaded into a fresh NOMT database in batches.
+//!
+//! Child tries are not followed: their roots are migrated as ordinary values.
+
+use crate::cli::MigrateParams;
+use anyhow::{anyhow, bail, Result};
+use hash_db::{HashDBRef, Prefix};
+use kvdb::KeyValueDB;
+use kvdb_rocksdb::{Database, DatabaseConfig};
+use nomt::{hasher::Blake3Hasher, trie::KeyPath, KeyReadWrite, Nomt, Options, SessionParams};
+use sha2::Digest;
+use sp_trie::{DBValue, LayoutV1};
+use std::sync::Arc;
+use trie_db::{Trie, TrieDBBuilder};
+
+type Hasher = sp_core::Blake2Hasher;
+type Hash = sp_core::H256;
+
+// The column and key under which the benchtop sp-trie backend stores its root.
+const COL_ROOT: u32 = 1;
+const ROOT_KEY: &[u8] = b"root";
+
+struct SourceDB {
+    kvdb: Arc<dyn KeyValueDB>,
+    column: u32,
+}
+
+impl HashDBRef<Hasher, DBValue> for SourceDB {
+    fn get(&self, key: &Hash, prefix: Prefix) -> Option<DBValue> {
+        let key = sp_trie::prefixed_key::<Hasher>(key, prefix);
+        self.kvdb
+            .get(self.column, &key)
+            .expect("Database backend error")
+    }
+
+    fn contains(&self, key: &Hash, prefix: Prefix) -> bool {
+        self.get(key, prefix).is_some()
+    }
+}
+
+pub fn migrate(params: MigrateParams) -> Result<()> {
+    let db_cfg = DatabaseConfig::with_columns(params.columns);
+    let kvdb: Arc<dyn KeyValueDB> = Arc::new(Database::open(&db_cfg, &params.source)?);
+    let source_root = match params.root {
+        Some(ref root) => parse_root(root)?,
+        None => match kvdb.get(COL_ROOT, ROOT_KEY)? {
+            Some(r) if r.len() >= 32 => Hash::from_slice(&r[..32]),
+            _ => bail!("no root found in source database, provide one with --root"),
+        },
+    };
+    let source = SourceDB {
+        kvdb,
+        column: params.column,
+    };
+
+    let mut opts = Options::new();
+    opts.path(&params.target);
+    opts.commit_concurrency(params.commit_concurrency);
+    opts.io_workers(params.io_workers);
+    let nomt = Nomt::<Blake3Hasher>::open(opts)?;
+    if !nomt.is_empty() {
+        bail!("target NOMT database {} is not empty", params.target);
+    }
+
+    let trie = TrieDBBuilder::<LayoutV1<Hasher>>::new(&source, &source_root).build();
+
+    let batch_size = params.batch_size as usize;
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut migrated = 0u64;
+    for item in trie.iter().map_err(|e| anyhow!("{:?}", e))? {
+        let (key, value) = item.map_err(|e| anyhow!("{:?}", e))?;
+        batch.push((key_path(&key, params.hash_keys)?, value));
+        if batch.len() == batch_size {
+            migrated += commit_batch(&nomt, &mut batch)?;
+            println!("migrated {} keys", migrated);
+        }
+    }
+    migrated += commit_batch(&nomt, &mut batch)?;
+
+    if params.verify {
+        let read_session = nomt.begin_read_session();
+        let mut verified = 0u64;
+        for item in trie.iter().map_err(|e| anyhow!("{:?}", e))? {
+            let (key, value) = item.map_err(|e| anyhow!("{:?}", e))?;
+            let key_path = key_path(&key, params.hash_keys)?;
+            if read_session.read(key_path)?.as_ref() != Some(&value) {
+                bail!(
+                    "value mismatch for key {}",
+                    array_bytes::bytes2hex("0x", &key)
+                );
+            }
+            verified += 1;
+        }
+        if verified != migrated {
+            bail!(
+                "key count mismatch: {} migrated, {} in source",
+                migrated,
+                verified
+            );
+        }
+        println!("verified {} keys", verified);
+    }
+
+    println!("migrated {} keys", migrated);
+    println!(
+        "sp-trie root: {}",
+        array_bytes::bytes2hex("0x", source_root.as_bytes())
+    );
+    println!(
+        "nomt root: {}",
+        array_bytes::bytes2hex("0x", nomt.root().into_inner())
+    );
+
+    Ok(())
+}
+
+// Commit a batch of key-value pairs to NOMT and return the number of committed keys.
+fn commit_batch(nomt: &Nomt<Blake3Hasher>, batch: &mut Vec<(KeyPath, Vec<u8>)>) -> Result<u64> {
+    if batch.is_empty() {
+        return Ok(0);
+    }
+
+    batch.sort_by_key(|(k, _)| *k);
+    if batch.windows(2).any(|w| w[0].0 == w[1].0) {
+        bail!("duplicate key path in source state");
+    }
+
+    let len = batch.len() as u64;
+    let session = nomt.begin_session(SessionParams::default());
+    let actuals = batch
+        .drain(..)
+        .map(|(k, v)| (k, KeyReadWrite::Write(Some(v))))
+        .collect();
+    session.finish(actuals)?.commit(nomt)?;
+    Ok(len)
+}
+
+// Keys of the benchtop sp-trie backend are already hashed and are used as they are. Arbitrary
+// keys, such as those of a Substrate state, need to be hashed.
+fn key_path(key: &[u8], hash_keys: bool) -> Result<KeyPath> {
+    if hash_keys {
+        return Ok(sha2::Sha256::digest(key).into());
+    }
+
+    key.try_into().map_err(|_| {
+        anyhow!(
+            "key {} is not 32 bytes long, use --hash-keys",
+            array_bytes::bytes2hex("0x", key)
+        )
+    })
+}
+
+fn parse_root(root: &str) -> Result<Hash> {
+    let bytes = array_bytes::hex2bytes(root).map_err(|e| anyhow!("invalid root: {:?}", e))?;
+    if bytes.len() != 32 {
+        bail!("invalid root: expected 32 bytes, got {}", bytes.len());
+    }
+    Ok(Hash::from_slice(&bytes))
+}
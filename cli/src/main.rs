@@ -0,0 +1,121 @@
+//! Command-line tools for NOMT databases.
+//!
+//! Commands:
+//!   - `repro <file>`: replay a repro file written by differential testing (see
+//!     `nomt::differential`) against fresh databases and the reference implementation, and report
+//!     the first divergence.
+//!
+//! Tracing is printed to stderr and filtered with `RUST_LOG`, which defaults to `nomt=trace`: every
+//! write and every commit of the replay is logged, along with the timing of each commit.
+
+use anyhow::{bail, Result};
+use clap::{Parser, Subcommand};
+use nomt::{
+    differential::{Differential, Repro},
+    hasher::Blake3Hasher,
+    CommitLogThresholds, Nomt, Options,
+};
+use std::{path::PathBuf, time::Duration};
+use tracing_subscriber::EnvFilter;
+
+#[derive(Parser)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Replay a repro file and report the first divergence from the reference implementation.
+    Repro {
+        /// The repro file.
+        file: PathBuf,
+
+        /// The directory to create the databases of the replay in. A temporary directory, removed
+        /// afterwards, by default.
+        #[arg(long)]
+        dir: Option<PathBuf>,
+
+        /// Write the repro of the divergence, shrunk further, to this file.
+        #[arg(long)]
+        shrunk: Option<PathBuf>,
+    },
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("nomt=trace"));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+
+    match cli.command {
+        Command::Repro { file, dir, shrunk } => repro(file, dir, shrunk),
+    }
+}
+
+fn repro(file: PathBuf, dir: Option<PathBuf>, shrunk: Option<PathBuf>) -> Result<()> {
+    let repro = Repro::read(&file)?;
+    println!(
+        "replaying {}: {} batches, {} writes",
+        file.display(),
+        repro.batches.len(),
+        repro.writes()
+    );
+
+    let (dir, _temp_dir) = match dir {
+        Some(dir) => {
+            std::fs::create_dir_all(&dir)?;
+            (dir, None)
+        }
+        None => {
+            let temp_dir = tempfile::tempdir()?;
+            (temp_dir.path().to_path_buf(), Some(temp_dir))
+        }
+    };
+
+    // every replay, including those made while shrinking, gets a fresh database with a
+    // deterministic layout, and logs all of its commits.
+    let mut replays = 0;
+    let open = move || {
+        let path = dir.join(format!("replay-{}", replays));
+        replays += 1;
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+        let mut o = Options::new();
+        o.path(path);
+        o.deterministic_layout(true);
+        o.bitbox_seed([0; 16]);
+        o.commit_log_thresholds(CommitLogThresholds {
+            duration: Some(Duration::ZERO),
+            ..CommitLogThresholds::default()
+        });
+        Nomt::open(o)
+    };
+    let mut differential = Differential::<Blake3Hasher>::new(open)?;
+
+    for (index, batch) in repro.batches.into_iter().enumerate() {
+        let writes = batch.len();
+        match differential.commit(batch) {
+            Ok(root) => println!(
+                "batch {}: {} writes, root {}",
+                index,
+                writes,
+                hex::encode(root.into_inner())
+            ),
+            Err(divergence) => {
+                if let Some(shrunk) = shrunk {
+                    divergence.repro.write(&shrunk)?;
+                    println!("shrunk repro written to {}", shrunk.display());
+                }
+                bail!("{}", divergence);
+            }
+        }
+    }
+
+    println!("no divergence");
+    Ok(())
+}
@@ -0,0 +1,265 @@
+//! Compatibility layer for Ethereum-derived state.
+//!
+//! This maps Ethereum accounts and storage slots onto NOMT key paths and translates NOMT path
+//! proofs into the shape of an `eth_getProof` response.
+//!
+//! # Key mapping
+//!
+//! Ethereum keeps one trie for accounts and a separate trie per account for storage. NOMT has a
+//! single trie, so both are placed in it:
+//!
+//!   - The key path of an account is `keccak256(address)`, as in the secure account trie.
+//!   - The key path of a storage slot is `keccak256(address ++ slot)`.
+//!
+//! Values are opaque to NOMT. Embedders are free to store RLP-encoded accounts.
+//!
+//! # Proof translation
+//!
+//! `eth_getProof` returns, for every proven key, the list of trie node preimages from the root
+//! down to the node containing the key, such that each node's hash is referenced by its parent.
+//! The same is done for NOMT paths:
+//!
+//!   - For each internal node on the path, from the root downwards, the 64-byte preimage
+//!     `left ++ right`.
+//!   - Finally, the 64-byte leaf preimage `key_path ++ value_hash` if the path ends in a leaf.
+//!     If the path ends in a terminator, nothing is appended: the last internal node references
+//!     the terminator directly.
+//!
+//! The hash of the first node is the state root. This format is verified by
+//! [`verify_proof_nodes`].
+
+use alloc::vec::Vec;
+use bitvec::prelude::*;
+use sha3::{Digest, Keccak256};
+
+use crate::{
+    hasher::NodeHasher,
+    proof::{PathProof, PathProofTerminal},
+    trie::{InternalData, KeyPath, LeafData, Node, ValueHash, TERMINATOR},
+};
+
+/// An Ethereum address.
+pub type Address = [u8; 20];
+
+/// An Ethereum storage slot.
+pub type StorageSlot = [u8; 32];
+
+/// The key path of an account.
+pub fn account_key_path(address: &Address) -> KeyPath {
+    Keccak256::digest(address).into()
+}
+
+/// The key path of a storage slot of an account.
+pub fn storage_key_path(address: &Address, slot: &StorageSlot) -> KeyPath {
+    let mut hasher = Keccak256::new();
+    hasher.update(address);
+    hasher.update(slot);
+    hasher.finalize().into()
+}
+
+/// Translate a path proof into a list of node preimages, ordered from the root downwards.
+///
+/// See the module docs for the format.
+pub fn proof_nodes<H: NodeHasher>(proof: &PathProof) -> Vec<Vec<u8>> {
+    let path = proof.terminal.path();
+    let mut nodes = Vec::with_capacity(proof.siblings.len() + 1);
+
+    let mut node = match proof.terminal {
+        PathProofTerminal::Leaf(ref leaf) => {
+            nodes.push(leaf_preimage(leaf));
+            H::hash_leaf(leaf)
+        }
+        PathProofTerminal::Terminator(_) => TERMINATOR,
+    };
+
+    // siblings are in descending order by depth, so walk the path backwards.
+    for (depth, sibling) in proof.siblings.iter().enumerate().rev() {
+        let internal = if path[depth] {
+            InternalData {
+                left: *sibling,
+                right: node,
+            }
+        } else {
+            InternalData {
+                left: node,
+                right: *sibling,
+            }
+        };
+        let mut preimage = Vec::with_capacity(64);
+        preimage.extend_from_slice(&internal.left);
+        preimage.extend_from_slice(&internal.right);
+        nodes.push(preimage);
+        node = H::hash_internal(&internal);
+    }
+
+    nodes.reverse();
+    nodes
+}
+
+/// Errors in verifying a list of proof nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofNodesError {
+    /// A node was not 64 bytes long.
+    MalformedNode,
+    /// A node's hash was not referenced by its parent, or the first node did not hash to the root.
+    HashMismatch,
+    /// The proof ended in a leaf which is not on the path of the key.
+    WrongLeaf,
+    /// The proof ended before reaching a leaf or terminator.
+    Incomplete,
+}
+
+/// Verify a list of proof nodes, produced by [`proof_nodes`], for a key against a root.
+///
+/// Returns the value hash stored under the key or `None` if the key has no value.
+pub fn verify_proof_nodes<H: NodeHasher>(
+    root: Node,
+    key_path: &KeyPath,
+    nodes: &[Vec<u8>],
+) -> Result<Option<ValueHash>, ProofNodesError> {
+    let path = key_path.view_bits::<Msb0>();
+    let mut expected = root;
+
+    for (depth, node) in nodes.iter().enumerate() {
+        let (left, right) = split_node(node)?;
+
+        // the last node may be a leaf.
+        if depth == nodes.len() - 1 && expected != TERMINATOR {
+            let leaf = LeafData {
+                key_path: left,
+                value_hash: right,
+            };
+            if H::hash_leaf(&leaf) == expected {
+                if leaf.key_path.view_bits::<Msb0>()[..depth] != path[..depth] {
+                    return Err(ProofNodesError::WrongLeaf);
+                }
+                return Ok((leaf.key_path == *key_path).then_some(leaf.value_hash));
+            }
+        }
+
+        let internal = InternalData { left, right };
+        if H::hash_internal(&internal) != expected {
+            return Err(ProofNodesError::HashMismatch);
+        }
+        expected = if path[depth] {
+            internal.right
+        } else {
+            internal.left
+        };
+    }
+
+    if expected == TERMINATOR {
+        Ok(None)
+    } else {
+        Err(ProofNodesError::Incomplete)
+    }
+}
+
+fn leaf_preimage(leaf: &LeafData) -> Vec<u8> {
+    let mut preimage = Vec::with_capacity(64);
+    preimage.extend_from_slice(&leaf.key_path);
+    preimage.extend_from_slice(&leaf.value_hash);
+    preimage
+}
+
+fn split_node(node: &[u8]) -> Result<(Node, Node), ProofNodesError> {
+    if node.len() != 64 {
+        return Err(ProofNodesError::MalformedNode);
+    }
+    let mut left = [0; 32];
+    let mut right = [0; 32];
+    left.copy_from_slice(&node[..32]);
+    right.copy_from_slice(&node[32..]);
+    Ok((left, right))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hasher::Blake3Hasher, proof::PathProof, trie_pos::TriePosition};
+
+    fn leaf(key_path: KeyPath, value: u8) -> LeafData {
+        LeafData {
+            key_path,
+            value_hash: [value; 32],
+        }
+    }
+
+    #[test]
+    fn leaf_proof_roundtrip() {
+        let mut key_path = [0u8; 32];
+        key_path[0] = 0b0100_0000;
+        let leaf = leaf(key_path, 1);
+        let proof = PathProof {
+            terminal: PathProofTerminal::Leaf(leaf.clone()),
+            siblings: vec![[1; 32], [2; 32]],
+        };
+        // the path goes left, then right.
+        let below = Blake3Hasher::hash_internal(&InternalData {
+            left: [2; 32],
+            right: Blake3Hasher::hash_leaf(&leaf),
+        });
+        let root = Blake3Hasher::hash_internal(&InternalData {
+            left: below,
+            right: [1; 32],
+        });
+
+        let nodes = proof_nodes::<Blake3Hasher>(&proof);
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(
+            verify_proof_nodes::<Blake3Hasher>(root, &key_path, &nodes),
+            Ok(Some([1; 32]))
+        );
+
+        // a different key sharing the path is proven absent.
+        let mut other = key_path;
+        other[31] = 1;
+        assert_eq!(
+            verify_proof_nodes::<Blake3Hasher>(root, &other, &nodes),
+            Ok(None)
+        );
+
+        // a key off the path is rejected.
+        let mut off_path = key_path;
+        off_path[0] = 0b1000_0000;
+        assert!(verify_proof_nodes::<Blake3Hasher>(root, &off_path, &nodes).is_err());
+
+        assert_eq!(
+            verify_proof_nodes::<Blake3Hasher>([9; 32], &key_path, &nodes),
+            Err(ProofNodesError::HashMismatch)
+        );
+    }
+
+    #[test]
+    fn terminator_proof_roundtrip() {
+        let key_path = [0u8; 32];
+        let proof = PathProof {
+            terminal: PathProofTerminal::Terminator(TriePosition::from_path_and_depth(key_path, 1)),
+            siblings: vec![[3; 32]],
+        };
+        let root = Blake3Hasher::hash_internal(&InternalData {
+            left: TERMINATOR,
+            right: [3; 32],
+        });
+
+        let nodes = proof_nodes::<Blake3Hasher>(&proof);
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(
+            verify_proof_nodes::<Blake3Hasher>(root, &key_path, &nodes),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn key_paths_are_distinct() {
+        let address = [7; 20];
+        assert_ne!(
+            account_key_path(&address),
+            storage_key_path(&address, &[0; 32])
+        );
+        assert_ne!(
+            storage_key_path(&address, &[0; 32]),
+            storage_key_path(&address, &[1; 32])
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! Utilities for working with [`KeyPath`]s.
+//!
+//! Key paths are ordered lexicographically, which is the same as treating them as big-endian
+//! 256-bit integers or as paths through the binary trie, most significant bit first.
+//!
+//! ```
+//! use nomt_core::key_path;
+//!
+//! let a = key_path::from_hex(&"aa".repeat(32)).unwrap();
+//! let b = key_path::successor(&a).unwrap();
+//! assert_eq!(key_path::predecessor(&b), Some(a));
+//! assert_eq!(key_path::common_prefix_len(&a, &b), 255);
+//! assert!(key_path::to_hex(&b).ends_with("ab"));
+//! ```
+
+use crate::{trie::KeyPath, trie_pos::TriePosition};
+use alloc::string::String;
+
+/// Format a key path as a `0x`-prefixed hex string.
+pub fn to_hex(path: &KeyPath) -> String {
+    let mut s = String::with_capacity(66);
+    s.push_str("0x");
+    s.push_str(&hex::encode(path));
+    s
+}
+
+/// Parse a key path from 64 hex digits, with or without a `0x` prefix.
+pub fn from_hex(s: &str) -> Result<KeyPath, hex::FromHexError> {
+    let mut path = [0; 32];
+    hex::decode_to_slice(s.strip_prefix("0x").unwrap_or(s), &mut path)?;
+    Ok(path)
+}
+
+/// The number of leading bits shared by both key paths.
+pub fn common_prefix_len(a: &KeyPath, b: &KeyPath) -> usize {
+    a.iter()
+        .zip(b.iter())
+        .position(|(a, b)| a != b)
+        .map_or(256, |i| i * 8 + (a[i] ^ b[i]).leading_zeros() as usize)
+}
+
+/// The key path immediately after this one, or `None` if this is the last key path.
+pub fn successor(path: &KeyPath) -> Option<KeyPath> {
+    let mut next = *path;
+    for byte in next.iter_mut().rev() {
+        let (b, overflow) = byte.overflowing_add(1);
+        *byte = b;
+        if !overflow {
+            return Some(next);
+        }
+    }
+    None
+}
+
+/// The key path immediately before this one, or `None` if this is the first key path.
+pub fn predecessor(path: &KeyPath) -> Option<KeyPath> {
+    let mut prev = *path;
+    for byte in prev.iter_mut().rev() {
+        let (b, overflow) = byte.overflowing_sub(1);
+        *byte = b;
+        if !overflow {
+            return Some(prev);
+        }
+    }
+    None
+}
+
+/// The position of the node at the given depth along the key path. A depth of zero is the root.
+pub fn to_trie_position(path: &KeyPath, depth: u16) -> TriePosition {
+    if depth == 0 {
+        TriePosition::new()
+    } else {
+        TriePosition::from_path_and_depth(*path, depth)
+    }
+}
+
+/// The smallest key path under the given position: its path followed by zeros.
+pub fn from_trie_position(pos: &TriePosition) -> KeyPath {
+    pos.to_path_and_depth().0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trip() {
+        let mut path = [0; 32];
+        path[0] = 0xab;
+        path[31] = 0x01;
+        let s = to_hex(&path);
+        assert_eq!(s.len(), 66);
+        assert_eq!(from_hex(&s), Ok(path));
+        assert_eq!(from_hex(&s[2..]), Ok(path));
+        assert!(from_hex("0xab").is_err());
+        assert!(from_hex(&"zz".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn common_prefix() {
+        let a = [0; 32];
+        let mut b = [0; 32];
+        assert_eq!(common_prefix_len(&a, &b), 256);
+        b[31] = 1;
+        assert_eq!(common_prefix_len(&a, &b), 255);
+        b[2] = 0b0010_0000;
+        assert_eq!(common_prefix_len(&a, &b), 18);
+        b[0] = 0x80;
+        assert_eq!(common_prefix_len(&a, &b), 0);
+    }
+
+    #[test]
+    fn successor_and_predecessor() {
+        assert_eq!(predecessor(&[0; 32]), None);
+        assert_eq!(successor(&[0xff; 32]), None);
+
+        let mut path = [0; 32];
+        path[30] = 0x01;
+        path[31] = 0xff;
+        let mut next = [0; 32];
+        next[30] = 0x02;
+        assert_eq!(successor(&path), Some(next));
+        assert_eq!(predecessor(&next), Some(path));
+    }
+
+    #[test]
+    fn trie_position_conversion() {
+        let mut path = [0xff; 32];
+        assert!(to_trie_position(&path, 0).is_root());
+
+        let pos = to_trie_position(&path, 10);
+        assert_eq!(pos.depth(), 10);
+        path[1] = 0b1100_0000;
+        path[2..].fill(0);
+        assert_eq!(from_trie_position(&pos), path);
+    }
+}
@@ -4,16 +4,23 @@
 //! manner.
 //!
 //! The core types and proof verification routines of this crate do not require the
-//! standard library, but do require Rust's alloc crate.
+//! standard library, but do require Rust's alloc crate. They compile to
+//! `wasm32-unknown-unknown`, and the `wasm` feature exposes proof verification to JavaScript.
 
 #![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
 
 extern crate alloc;
 
+#[cfg(feature = "eth")]
+pub mod eth;
 pub mod hasher;
+pub mod key_path;
+pub mod mmr;
 pub mod page;
 pub mod page_id;
 pub mod proof;
 pub mod trie;
 pub mod trie_pos;
 pub mod update;
+#[cfg(feature = "wasm")]
+pub mod wasm;
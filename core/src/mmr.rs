@@ -0,0 +1,279 @@
+//! A Merkle mountain range: an append-only commitment to a sequence of leaves.
+//!
+//! The range is a list of perfect binary trees of decreasing size, one for every set bit of the
+//! number of leaves. Appending a leaf adds a tree of one leaf and merges the trailing trees of
+//! equal size, so a leaf never moves once appended and the nodes are only ever added at the end.
+//!
+//! Nodes are numbered in post-order: every node comes after its children, and the nodes created
+//! by an append are contiguous. The root commits to the number of leaves and to the roots of the
+//! trees, the peaks, bagged from right to left.
+//!
+//! ```
+//! use nomt_core::{hasher::Blake3Hasher, mmr};
+//!
+//! let mut peaks = Vec::new();
+//! let mut nodes = Vec::new();
+//! for i in 0u64..5 {
+//!     let leaf = mmr::hash_leaf::<Blake3Hasher>(&i.to_le_bytes());
+//!     nodes.extend(mmr::append::<Blake3Hasher>(i, &mut peaks, leaf));
+//! }
+//! assert_eq!(nodes.len() as u64, mmr::node_count(5));
+//! let root = mmr::root::<Blake3Hasher>(5, &peaks);
+//!
+//! let (siblings, peaks) = mmr::proof_positions(3, 5).unwrap();
+//! let proof = mmr::MmrProof {
+//!     leaf_index: 3,
+//!     leaves: 5,
+//!     siblings: siblings.iter().map(|p| nodes[*p as usize]).collect(),
+//!     peaks: peaks.iter().map(|p| nodes[*p as usize]).collect(),
+//! };
+//! let leaf = mmr::hash_leaf::<Blake3Hasher>(&3u64.to_le_bytes());
+//! assert!(proof.verify::<Blake3Hasher>(leaf, root));
+//! ```
+
+use crate::{
+    hasher::ValueHasher,
+    trie::{Node, TERMINATOR},
+};
+use alloc::vec::Vec;
+
+const LEAF_TAG: u8 = 0;
+const PARENT_TAG: u8 = 1;
+const ROOT_TAG: u8 = 2;
+
+/// The number of nodes of a range with the given number of leaves.
+pub fn node_count(leaves: u64) -> u64 {
+    2 * leaves - leaves.count_ones() as u64
+}
+
+/// The position of the leaf with the given index.
+pub fn leaf_position(leaf_index: u64) -> u64 {
+    node_count(leaf_index)
+}
+
+/// The positions of the peaks of a range with the given number of leaves, from left to right.
+pub fn peak_positions(leaves: u64) -> Vec<u64> {
+    let mut positions = Vec::with_capacity(leaves.count_ones() as usize);
+    let mut end = 0;
+    for height in (0..64).rev() {
+        if leaves & (1 << height) != 0 {
+            end += (2 << height) - 1;
+            positions.push(end - 1);
+        }
+    }
+    positions
+}
+
+/// Hash the data of a leaf.
+pub fn hash_leaf<H: ValueHasher>(data: &[u8]) -> Node {
+    let mut buf = Vec::with_capacity(1 + data.len());
+    buf.push(LEAF_TAG);
+    buf.extend_from_slice(data);
+    H::hash_value(&buf)
+}
+
+/// Hash the children of a node.
+pub fn hash_parent<H: ValueHasher>(left: &Node, right: &Node) -> Node {
+    let mut buf = [0; 65];
+    buf[0] = PARENT_TAG;
+    buf[1..33].copy_from_slice(left);
+    buf[33..].copy_from_slice(right);
+    H::hash_value(&buf)
+}
+
+/// The root of a range with the given number of leaves and peaks. The root of an empty range is
+/// the [`TERMINATOR`].
+pub fn root<H: ValueHasher>(leaves: u64, peaks: &[Node]) -> Node {
+    let Some((last, rest)) = peaks.split_last() else {
+        return TERMINATOR;
+    };
+    let bagged = rest
+        .iter()
+        .rev()
+        .fold(*last, |acc, peak| hash_parent::<H>(peak, &acc));
+
+    let mut buf = [0; 41];
+    buf[0] = ROOT_TAG;
+    buf[1..9].copy_from_slice(&leaves.to_le_bytes());
+    buf[9..].copy_from_slice(&bagged);
+    H::hash_value(&buf)
+}
+
+/// Append a leaf hash to a range with the given number of leaves, updating its peaks.
+///
+/// Returns the nodes created, in order of position, starting with the leaf at
+/// [`node_count`]`(leaves)`.
+pub fn append<H: ValueHasher>(leaves: u64, peaks: &mut Vec<Node>, leaf: Node) -> Vec<Node> {
+    let merges = leaves.trailing_ones() as usize;
+    let mut nodes = Vec::with_capacity(merges + 1);
+    nodes.push(leaf);
+
+    let mut node = leaf;
+    for _ in 0..merges {
+        // UNWRAP: there is a peak for every merge, by the trailing set bits of the leaf count.
+        let left = peaks.pop().unwrap();
+        node = hash_parent::<H>(&left, &node);
+        nodes.push(node);
+    }
+    peaks.push(node);
+    nodes
+}
+
+// Find the tree holding a leaf. Returns the index of its peak, the number of the leaf within the
+// tree, the height of the tree and the position of its first node.
+fn locate(leaf_index: u64, leaves: u64) -> Option<(usize, u64, u32, u64)> {
+    if leaf_index >= leaves {
+        return None;
+    }
+    let mut first_leaf = 0;
+    let mut first_position = 0;
+    let mut peak_index = 0;
+    for height in (0..64).rev() {
+        if leaves & (1 << height) == 0 {
+            continue;
+        }
+        let tree_leaves = 1u64 << height;
+        if leaf_index < first_leaf + tree_leaves {
+            return Some((peak_index, leaf_index - first_leaf, height, first_position));
+        }
+        first_leaf += tree_leaves;
+        first_position += 2 * tree_leaves - 1;
+        peak_index += 1;
+    }
+    None
+}
+
+/// The positions of the nodes proving a leaf: the siblings along its path to its peak, bottom-up,
+/// and the peaks. `None` if the leaf is out of range.
+pub fn proof_positions(leaf_index: u64, leaves: u64) -> Option<(Vec<u64>, Vec<u64>)> {
+    let (_, local_index, height, first_position) = locate(leaf_index, leaves)?;
+
+    let mut siblings = Vec::with_capacity(height as usize);
+    let mut position = leaf_position(local_index);
+    for level in 0..height {
+        let subtree_size = (2u64 << level) - 1;
+        if local_index & (1 << level) == 0 {
+            // a left child: the sibling is the next subtree and the parent follows it.
+            siblings.push(first_position + position + subtree_size);
+            position += subtree_size + 1;
+        } else {
+            // a right child: the sibling is the previous subtree and the parent follows this.
+            siblings.push(first_position + position - subtree_size);
+            position += 1;
+        }
+    }
+    Some((siblings, peak_positions(leaves)))
+}
+
+/// A proof that a leaf is part of a range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshDeserialize, borsh::BorshSerialize)
+)]
+pub struct MmrProof {
+    /// The index of the leaf.
+    pub leaf_index: u64,
+    /// The number of leaves of the range.
+    pub leaves: u64,
+    /// The siblings along the path from the leaf to its peak, bottom-up.
+    pub siblings: Vec<Node>,
+    /// The peaks of the range, from left to right.
+    pub peaks: Vec<Node>,
+}
+
+impl MmrProof {
+    /// Verify that the leaf hash is at the index of the proof in the range with the given root.
+    pub fn verify<H: ValueHasher>(&self, leaf: Node, root: Node) -> bool {
+        let Some((peak_index, local_index, height, _)) = locate(self.leaf_index, self.leaves)
+        else {
+            return false;
+        };
+        if self.siblings.len() != height as usize
+            || self.peaks.len() != self.leaves.count_ones() as usize
+        {
+            return false;
+        }
+
+        let peak = self
+            .siblings
+            .iter()
+            .enumerate()
+            .fold(leaf, |node, (level, sibling)| {
+                if local_index & (1 << level) == 0 {
+                    hash_parent::<H>(&node, sibling)
+                } else {
+                    hash_parent::<H>(sibling, &node)
+                }
+            });
+        self.peaks[peak_index] == peak && self::root::<H>(self.leaves, &self.peaks) == root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher::Blake3Hasher;
+
+    fn build(leaves: u64) -> (Vec<Node>, Vec<Node>) {
+        let mut peaks = Vec::new();
+        let mut nodes = Vec::new();
+        for i in 0..leaves {
+            let leaf = hash_leaf::<Blake3Hasher>(&i.to_le_bytes());
+            assert_eq!(nodes.len() as u64, leaf_position(i));
+            nodes.extend(append::<Blake3Hasher>(i, &mut peaks, leaf));
+        }
+        (nodes, peaks)
+    }
+
+    #[test]
+    fn positions() {
+        assert_eq!(node_count(0), 0);
+        assert_eq!(node_count(4), 7);
+        assert_eq!(node_count(5), 8);
+        assert_eq!(leaf_position(2), 3);
+        assert_eq!(peak_positions(0), Vec::<u64>::new());
+        assert_eq!(peak_positions(7), vec![6, 9, 10]);
+        assert_eq!(proof_positions(1, 4), Some((vec![0, 5], vec![6])));
+        assert_eq!(proof_positions(6, 7), Some((vec![], vec![6, 9, 10])));
+        assert_eq!(proof_positions(7, 7), None);
+    }
+
+    #[test]
+    fn peaks_are_stored_nodes() {
+        for leaves in 0..70 {
+            let (nodes, peaks) = build(leaves);
+            assert_eq!(nodes.len() as u64, node_count(leaves));
+            let stored: Vec<_> = peak_positions(leaves)
+                .into_iter()
+                .map(|p| nodes[p as usize])
+                .collect();
+            assert_eq!(stored, peaks);
+        }
+        assert_eq!(root::<Blake3Hasher>(0, &[]), TERMINATOR);
+    }
+
+    #[test]
+    fn proofs_verify() {
+        let leaves = 37;
+        let (nodes, peaks) = build(leaves);
+        let root = root::<Blake3Hasher>(leaves, &peaks);
+
+        for i in 0..leaves {
+            let (siblings, peaks) = proof_positions(i, leaves).unwrap();
+            let mut proof = MmrProof {
+                leaf_index: i,
+                leaves,
+                siblings: siblings.iter().map(|p| nodes[*p as usize]).collect(),
+                peaks: peaks.iter().map(|p| nodes[*p as usize]).collect(),
+            };
+            let leaf = hash_leaf::<Blake3Hasher>(&i.to_le_bytes());
+            assert!(proof.verify::<Blake3Hasher>(leaf, root));
+
+            let other = hash_leaf::<Blake3Hasher>(&(i + 1).to_le_bytes());
+            assert!(!proof.verify::<Blake3Hasher>(other, root));
+            proof.leaves += 1;
+            assert!(!proof.verify::<Blake3Hasher>(leaf, root));
+        }
+    }
+}
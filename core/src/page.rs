@@ -4,7 +4,7 @@
 //! a predictable paged representation regardless of the information in the trie.
 //!
 //! Each page is 4096 bytes and stores up to 126 nodes plus a unique 32-byte page identifier,
-//! with 32 bytes left over.
+//! with 32 bytes left over. The store keeps a header with a checksum in those spare bytes.
 //!
 //! A page stores a rootless sub-tree with depth 6: that is, it stores up to
 //! 2 + 4 + 8 + 16 + 32 + 64 nodes at known positions.
@@ -53,7 +53,7 @@ pub const NUM_CHILDREN: usize = MAX_CHILD_INDEX as usize + 1;
 ///
 /// Each page can be thought of a root-less binary tree. The leaves of that tree are roots of
 /// subtrees stored in subsequent pages. There are 64 (2^[`DEPTH`]) children in each page.
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ChildPageIndex(u8);
 
 impl ChildPageIndex {
@@ -64,11 +64,58 @@ impl ChildPageIndex {
         Some(Self(index))
     }
 
+    /// Iterate over all the child indices of a page, in ascending order.
+    pub fn all() -> impl DoubleEndedIterator<Item = Self> + ExactSizeIterator {
+        (0..=MAX_CHILD_INDEX).map(Self)
+    }
+
+    /// Read a child index from a slice of exactly [`DEPTH`] bits.
+    pub fn from_bits(bits: &BitSlice<u8, Msb0>) -> Option<Self> {
+        if bits.len() != DEPTH {
+            return None;
+        }
+        Some(Self(bits.load_be::<u8>()))
+    }
+
+    /// The index of the child page a key path descends into from the page at the given depth,
+    /// with the root page at depth 0. `None` if the page is at the maximum depth.
+    pub fn from_key_path(key_path: &KeyPath, page_depth: usize) -> Option<Self> {
+        if page_depth >= MAX_PAGE_DEPTH {
+            return None;
+        }
+        let start = page_depth * DEPTH;
+        Self::from_bits(&key_path.view_bits::<Msb0>()[start..start + DEPTH])
+    }
+
     pub fn to_u8(self) -> u8 {
         self.0
     }
 }
 
+/// The value is too large to be a [`ChildPageIndex`].
+#[derive(Debug, PartialEq)]
+pub struct InvalidChildPageIndex;
+
+impl TryFrom<u8> for ChildPageIndex {
+    type Error = InvalidChildPageIndex;
+
+    fn try_from(index: u8) -> Result<Self, Self::Error> {
+        Self::new(index).ok_or(InvalidChildPageIndex)
+    }
+}
+
+impl From<ChildPageIndex> for u8 {
+    fn from(index: ChildPageIndex) -> u8 {
+        index.0
+    }
+}
+
+impl From<ChildPageIndex> for usize {
+    fn from(index: ChildPageIndex) -> usize {
+        index.0 as usize
+    }
+}
+
 impl Clone for PageId {
     fn clone(&self) -> Self {
         let mut new_path = ArrayVec::new();
@@ -256,7 +303,7 @@ pub enum ChildPageIdError {
 /// Iterator of PageIds over a KeyPath,
 /// PageIds will be lazily constructed as needed
 pub struct PageIdsIterator {
-    key_path: Uint<256, 4>,
+    key_path: KeyPath,
     page_id: Option<PageId>,
 }
 
@@ -264,7 +311,7 @@ impl PageIdsIterator {
     /// Create a PageIds Iterator over a KeyPath
     pub fn new(key_path: KeyPath) -> Self {
         Self {
-            key_path: Uint::from_be_bytes(key_path),
+            key_path,
             page_id: Some(ROOT_PAGE_ID),
         }
     }
@@ -276,10 +323,8 @@ impl Iterator for PageIdsIterator {
     fn next(&mut self) -> Option<Self::Item> {
         let prev = self.page_id.take()?;
 
-        // unwrap: `new` can't return an error because the key_path is shifted.
-        let child_index = ChildPageIndex::new(self.key_path.byte(31) >> 2).unwrap();
-        self.key_path <<= 6;
-        self.page_id = prev.child_page_id(child_index).ok();
+        self.page_id = ChildPageIndex::from_key_path(&self.key_path, prev.depth())
+            .and_then(|child_index| prev.child_page_id(child_index).ok());
         Some(prev)
     }
 }
@@ -287,8 +332,8 @@ impl Iterator for PageIdsIterator {
 #[cfg(test)]
 mod tests {
     use super::{
-        ChildPageIdError, ChildPageIndex, InvalidPageIdBytes, Msb0, PageId, PageIdsIterator, Uint,
-        HIGHEST_ENCODED_42, MAX_CHILD_INDEX, ROOT_PAGE_ID,
+        ChildPageIdError, ChildPageIndex, InvalidChildPageIndex, InvalidPageIdBytes, Msb0, PageId,
+        PageIdsIterator, Uint, HIGHEST_ENCODED_42, MAX_CHILD_INDEX, ROOT_PAGE_ID,
     };
     use bitvec::prelude::*;
 
@@ -518,4 +563,31 @@ mod tests {
         }
         assert_eq!(min_page.max_key_path(), key_path);
     }
+
+    #[test]
+    fn child_page_index_conversions() {
+        let all: Vec<u8> = ChildPageIndex::all().map(u8::from).collect();
+        assert_eq!(all, (0..=MAX_CHILD_INDEX).collect::<Vec<_>>());
+
+        assert_eq!(ChildPageIndex::try_from(63), Ok(ChildPageIndex(63)));
+        assert_eq!(ChildPageIndex::try_from(64), Err(InvalidChildPageIndex));
+        assert_eq!(usize::from(ChildPageIndex(17)), 17);
+
+        let key_path = [0b0000_0100, 0b0001_0000, 0, 0b1111_1111];
+        let mut full = [0; 32];
+        full[..4].copy_from_slice(&key_path);
+        full[31] = 0b0011_0000;
+        let child = |depth| ChildPageIndex::from_key_path(&full, depth).map(u8::from);
+        assert_eq!(child(0), Some(1));
+        assert_eq!(child(1), Some(1));
+        assert_eq!(child(2), Some(0));
+        assert_eq!(child(4), Some(0b111111));
+        assert_eq!(child(41), Some(0b000011));
+        assert_eq!(child(42), None);
+
+        assert_eq!(
+            ChildPageIndex::from_bits(&full.view_bits::<Msb0>()[..5]),
+            None
+        );
+    }
 }
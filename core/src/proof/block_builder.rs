@@ -0,0 +1,129 @@
+//! Stateless re-execution of a block against a witness of its pre-state.
+//!
+//! A [`BlockBuilder`] is given path proofs for every key a block touches. It verifies them
+//! against the pre-state root, answers reads of those keys, collects the writes of the block and
+//! computes the post-state root from them, without access to the rest of the trie.
+
+use alloc::collections::BTreeMap;
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+use bitvec::prelude::*;
+use core::marker::PhantomData;
+
+use super::{
+    verify_update, KeyOutOfScope, PathProof, PathProofVerificationError, PathUpdate,
+    VerifiedPathProof, VerifyUpdateError,
+};
+use crate::{
+    hasher::NodeHasher,
+    trie::{KeyPath, Node, ValueHash},
+};
+
+/// Applies the writes of a block to a proven pre-state to compute the post-state root.
+///
+/// The result depends only on the pre-state root and the final value written to every key, not
+/// on the order of the writes.
+pub struct BlockBuilder<H> {
+    prev_root: Node,
+    // sorted by path.
+    paths: Vec<VerifiedPathProof>,
+    writes: BTreeMap<KeyPath, Option<ValueHash>>,
+    _marker: PhantomData<H>,
+}
+
+impl<H: NodeHasher> BlockBuilder<H> {
+    /// Create a new builder from proofs of the paths to the keys touched by the block.
+    ///
+    /// The proofs may be given in any order and may contain duplicates. Fails if any proof
+    /// doesn't verify against `prev_root`.
+    pub fn new(
+        prev_root: Node,
+        path_proofs: impl IntoIterator<Item = PathProof>,
+    ) -> Result<Self, PathProofVerificationError> {
+        let mut path_proofs: Vec<_> = path_proofs.into_iter().collect();
+        path_proofs.sort_by(|a, b| a.terminal.path().cmp(b.terminal.path()));
+        path_proofs.dedup_by(|a, b| a.terminal.path() == b.terminal.path());
+
+        let paths = path_proofs
+            .iter()
+            .map(|proof| proof.verify::<H>(proof.terminal.path(), prev_root))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(BlockBuilder {
+            prev_root,
+            paths,
+            writes: BTreeMap::new(),
+            _marker: PhantomData,
+        })
+    }
+
+    /// The root of the pre-state.
+    pub fn prev_root(&self) -> Node {
+        self.prev_root
+    }
+
+    /// Read the hash of the value of a key, including the writes made so far. `None` means the
+    /// key has no value.
+    ///
+    /// Fails if none of the proofs cover the key.
+    pub fn read(&self, key: &KeyPath) -> Result<Option<ValueHash>, KeyOutOfScope> {
+        let path = &self.paths[self.path_index(key)?];
+        if let Some(value) = self.writes.get(key) {
+            return Ok(*value);
+        }
+        Ok(path
+            .terminal()
+            .filter(|leaf| &leaf.key_path == key)
+            .map(|leaf| leaf.value_hash))
+    }
+
+    /// Write the hash of the new value of a key. `None` deletes the key. A later write to the same
+    /// key replaces an earlier one.
+    ///
+    /// Fails if none of the proofs cover the key.
+    pub fn write(&mut self, key: KeyPath, value: Option<ValueHash>) -> Result<(), KeyOutOfScope> {
+        self.path_index(&key)?;
+        self.writes.insert(key, value);
+        Ok(())
+    }
+
+    /// Compute the root of the post-state.
+    pub fn finish(self) -> Result<Node, VerifyUpdateError> {
+        let mut updates: Vec<PathUpdate> = Vec::new();
+        for (key, value) in &self.writes {
+            let (key, value) = (*key, *value);
+            // UNWRAP: keys are checked to be in scope when written.
+            let index = self.path_index(&key).unwrap();
+            match updates.last_mut() {
+                Some(update) if update.inner.path() == self.paths[index].path() => {
+                    update.ops.push((key, value));
+                }
+                _ => updates.push(PathUpdate {
+                    inner: self.paths[index].clone(),
+                    ops: vec![(key, value)],
+                }),
+            }
+        }
+
+        if updates.is_empty() {
+            return Ok(self.prev_root);
+        }
+        verify_update::<H>(self.prev_root, &updates)
+    }
+
+    // The index of the path which leads to the key. Paths are disjoint and sorted, so this is the
+    // last path not greater than the key, if that path is a prefix of it.
+    fn path_index(&self, key: &KeyPath) -> Result<usize, KeyOutOfScope> {
+        let key_bits = key.view_bits::<Msb0>();
+        let index = self
+            .paths
+            .partition_point(|p| p.path() <= key_bits)
+            .checked_sub(1)
+            .ok_or(KeyOutOfScope)?;
+        if key_bits.starts_with(self.paths[index].path()) {
+            Ok(index)
+        } else {
+            Err(KeyOutOfScope)
+        }
+    }
+}
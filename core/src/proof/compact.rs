@@ -0,0 +1,298 @@
+//! A compact encoding of multi-proofs.
+//!
+//! Proof size is dominated by sibling hashes, so the encoding spends as little as possible on
+//! anything else: the structure of the proof is described by bitmaps rather than per-node flags,
+//! and nodes which can be implied aren't encoded at all. A multi-proof is encoded as:
+//!
+//! ```text
+//! path_count: u32
+//! terminator_bitmap: [u8; ceil(path_count / 8)]  # bit `i` set: path `i` ends in a terminator
+//! paths: for each path:
+//!     depth: u16
+//!     if a leaf: key_path: [u8; 32], value_hash: [u8; 32]
+//!     if a terminator: path: [u8; ceil(depth / 8)]  # bits beyond `depth` are zero
+//! sibling_count: u32
+//! sibling_bitmap: [u8; ceil(sibling_count / 8)]  # bit `i` set: sibling `i` is a terminator
+//! siblings: [[u8; 32]; number of unset bits in sibling_bitmap]
+//! ```
+//!
+//! Integers are little-endian and bitmaps are most-significant-bit first.
+
+use alloc::vec::Vec;
+use bitvec::prelude::*;
+
+use super::{MultiPathProof, MultiProof, PathProofTerminal};
+use crate::{
+    trie::{LeafData, Node, TERMINATOR},
+    trie_pos::TriePosition,
+};
+
+/// Errors in decoding a compact multi-proof.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input was shorter than expected.
+    UnexpectedEnd,
+    /// The input was longer than expected.
+    TrailingBytes,
+    /// The decoded item had an invalid value.
+    InvalidValue,
+}
+
+/// Encode a multi-proof.
+pub fn encode_multi_proof(proof: &MultiProof) -> Vec<u8> {
+    let mut out = Vec::with_capacity(encoded_len(proof));
+
+    out.extend_from_slice(&(proof.paths.len() as u32).to_le_bytes());
+    let terminators = proof
+        .paths
+        .iter()
+        .map(|p| matches!(p.terminal, PathProofTerminal::Terminator(_)));
+    push_bitmap(&mut out, terminators);
+
+    for path in &proof.paths {
+        out.extend_from_slice(&(path.depth as u16).to_le_bytes());
+        match path.terminal {
+            PathProofTerminal::Leaf(ref leaf) => {
+                out.extend_from_slice(&leaf.key_path);
+                out.extend_from_slice(&leaf.value_hash);
+            }
+            PathProofTerminal::Terminator(ref pos) => {
+                // the raw path may have bits set beyond the depth.
+                let mut path = [0u8; 32];
+                path.view_bits_mut::<Msb0>()[..pos.depth() as usize].copy_from_bitslice(pos.path());
+                out.extend_from_slice(&path[..prefix_len(pos.depth() as usize)]);
+            }
+        }
+    }
+
+    out.extend_from_slice(&(proof.siblings.len() as u32).to_le_bytes());
+    push_bitmap(&mut out, proof.siblings.iter().map(|s| *s == TERMINATOR));
+    for sibling in proof.siblings.iter().filter(|s| **s != TERMINATOR) {
+        out.extend_from_slice(sibling);
+    }
+
+    out
+}
+
+/// The length of the compact encoding of a multi-proof, without encoding it.
+pub fn encoded_len(proof: &MultiProof) -> usize {
+    let paths: usize = proof
+        .paths
+        .iter()
+        .map(|path| {
+            2 + match path.terminal {
+                PathProofTerminal::Leaf(_) => 64,
+                PathProofTerminal::Terminator(ref pos) => prefix_len(pos.depth() as usize),
+            }
+        })
+        .sum();
+    let siblings = proof.siblings.iter().filter(|s| **s != TERMINATOR).count();
+
+    4 + bitmap_len(proof.paths.len()) + paths + 4 + bitmap_len(proof.siblings.len()) + siblings * 32
+}
+
+/// Decode a multi-proof.
+///
+/// This only checks that the encoding is well-formed. The proof itself is checked when it is
+/// verified.
+pub fn decode_multi_proof(data: &[u8]) -> Result<MultiProof, DecodeError> {
+    let mut reader = Reader { data };
+
+    let path_count = reader.read_u32()? as usize;
+    let terminators = reader.read_bitmap(path_count)?;
+    let mut paths = Vec::with_capacity(path_count.min(data.len() / 2));
+    for is_terminator in terminators {
+        let depth = reader.read_u16()?;
+        if depth > 256 {
+            return Err(DecodeError::InvalidValue);
+        }
+        let terminal = if is_terminator {
+            let prefix = reader.take(prefix_len(depth as usize))?;
+            let mut path = [0u8; 32];
+            path[..prefix.len()].copy_from_slice(prefix);
+            if path.view_bits::<Msb0>()[depth as usize..].any() {
+                return Err(DecodeError::InvalidValue);
+            }
+            PathProofTerminal::Terminator(match depth {
+                0 => TriePosition::new(),
+                _ => TriePosition::from_path_and_depth(path, depth),
+            })
+        } else {
+            PathProofTerminal::Leaf(LeafData {
+                key_path: reader.read_node()?,
+                value_hash: reader.read_node()?,
+            })
+        };
+        paths.push(MultiPathProof {
+            terminal,
+            depth: depth as usize,
+        });
+    }
+
+    let sibling_count = reader.read_u32()? as usize;
+    let terminators = reader.read_bitmap(sibling_count)?;
+    let siblings = terminators
+        .into_iter()
+        .map(|is_terminator| {
+            if is_terminator {
+                Ok(TERMINATOR)
+            } else {
+                reader.read_node()
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if !reader.data.is_empty() {
+        return Err(DecodeError::TrailingBytes);
+    }
+
+    Ok(MultiProof { paths, siblings })
+}
+
+fn prefix_len(depth: usize) -> usize {
+    depth.div_ceil(8)
+}
+
+fn bitmap_len(bits: usize) -> usize {
+    bits.div_ceil(8)
+}
+
+fn push_bitmap(out: &mut Vec<u8>, bits: impl Iterator<Item = bool>) {
+    let bitmap: BitVec<u8, Msb0> = bits.collect();
+    out.extend_from_slice(bitmap.as_raw_slice());
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.data.len() < len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn read_u16(&mut self) -> Result<u16, DecodeError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, DecodeError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_node(&mut self) -> Result<Node, DecodeError> {
+        let mut node = [0; 32];
+        node.copy_from_slice(self.take(32)?);
+        Ok(node)
+    }
+
+    // Reads a bitmap of `bits` bits, rejecting any set padding bits.
+    fn read_bitmap(&mut self, bits: usize) -> Result<BitVec<u8, Msb0>, DecodeError> {
+        let mut bitmap = BitVec::<u8, Msb0>::from_slice(self.take(bitmap_len(bits))?);
+        if bitmap[bits..].any() {
+            return Err(DecodeError::InvalidValue);
+        }
+        bitmap.truncate(bits);
+        Ok(bitmap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::PathProof;
+
+    fn roundtrip(proof: &MultiProof) -> Vec<u8> {
+        let encoded = encode_multi_proof(proof);
+        assert_eq!(encoded.len(), encoded_len(proof));
+        let decoded = decode_multi_proof(&encoded).unwrap();
+        assert_eq!(&decoded, proof);
+        encoded
+    }
+
+    #[test]
+    fn multi_proof_roundtrip() {
+        let leaf = |key: u8| {
+            let mut key_path = [0; 32];
+            key_path[0] = key;
+            PathProof {
+                terminal: PathProofTerminal::Leaf(LeafData {
+                    key_path,
+                    value_hash: [key; 32],
+                }),
+                siblings: vec![[7; 32], TERMINATOR, [8; 32]],
+            }
+        };
+        let terminator = PathProof {
+            terminal: PathProofTerminal::Terminator(TriePosition::from_path_and_depth(
+                [0b1110_0000; 32],
+                3,
+            )),
+            siblings: vec![[7; 32], [9; 32], [10; 32]],
+        };
+
+        let proof =
+            MultiProof::from_path_proofs(vec![leaf(0b0000_0000), leaf(0b0100_0000), terminator]);
+        let encoded = roundtrip(&proof);
+
+        // two leaves and one terminator, each with their depth.
+        let paths = 2 * (2 + 64) + (2 + 1);
+        let siblings = proof.siblings.iter().filter(|s| **s != TERMINATOR).count();
+        assert_eq!(encoded.len(), 4 + 1 + paths + 4 + 1 + siblings * 32);
+
+        roundtrip(&MultiProof {
+            paths: vec![],
+            siblings: vec![],
+        });
+        roundtrip(&MultiProof::from_path_proofs(vec![PathProof {
+            terminal: PathProofTerminal::Terminator(TriePosition::new()),
+            siblings: vec![],
+        }]));
+    }
+
+    #[test]
+    fn malformed_input() {
+        let proof = MultiProof::from_path_proofs(vec![PathProof {
+            terminal: PathProofTerminal::Terminator(TriePosition::from_path_and_depth(
+                [0b1000_0000; 32],
+                1,
+            )),
+            siblings: vec![[1; 32]],
+        }]);
+        let encoded = encode_multi_proof(&proof);
+
+        assert_eq!(
+            decode_multi_proof(&encoded[..encoded.len() - 1]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert_eq!(
+            decode_multi_proof(&trailing),
+            Err(DecodeError::TrailingBytes)
+        );
+
+        // the terminator path has bits set beyond its depth.
+        let mut bad_path = encoded.clone();
+        bad_path[4 + 1 + 2] |= 0b0100_0000;
+        assert_eq!(
+            decode_multi_proof(&bad_path),
+            Err(DecodeError::InvalidValue)
+        );
+
+        // the terminator bitmap has padding bits set.
+        let mut bad_bitmap = encoded;
+        bad_bitmap[4] |= 0b0000_0001;
+        assert_eq!(
+            decode_multi_proof(&bad_bitmap),
+            Err(DecodeError::InvalidValue)
+        );
+    }
+}
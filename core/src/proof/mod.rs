@@ -6,8 +6,14 @@
 //!
 //! Using the types and functions exposed from this module, you can verify the value of a single
 //! key within the trie ([`PathProof`]), the values of multiple keys ([`MultiProof`]), or the result
-//! of updating a trie with a set of changes ([`verify_update`]).
+//! of updating a trie with a set of changes ([`verify_update`]). A [`BlockBuilder`] packages the
+//! latter for re-executing a block against a witness of the keys it touches.
+//!
+//! Path proofs and change-sets can be encoded with RLP (the `rlp` feature) or SSZ (the `ssz`
+//! feature), in addition to borsh. Multi-proofs have a [`compact`] encoding, which keeps the
+//! structure of the proof in bitmaps.
 
+pub use block_builder::BlockBuilder;
 pub use multi_proof::{
     verify as verify_multi_proof, MultiPathProof, MultiProof, MultiProofVerificationError,
     VerifiedMultiProof,
@@ -17,5 +23,11 @@ pub use path_proof::{
     PathUpdate, VerifiedPathProof, VerifyUpdateError,
 };
 
+mod block_builder;
+pub mod compact;
 mod multi_proof;
 mod path_proof;
+#[cfg(feature = "rlp")]
+pub mod rlp;
+#[cfg(feature = "ssz")]
+pub mod ssz;
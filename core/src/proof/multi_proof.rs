@@ -18,7 +18,7 @@ use bitvec::prelude::*;
 use core::cmp::Ordering;
 
 /// This struct includes the terminal node and its depth
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MultiPathProof {
     /// Terminal node
     pub terminal: PathProofTerminal,
@@ -27,7 +27,7 @@ pub struct MultiPathProof {
 }
 
 /// A proof of multiple paths through the trie.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MultiProof {
     /// List of all provable paths. These are sorted in ascending order by bit-path
     pub paths: Vec<MultiPathProof>,
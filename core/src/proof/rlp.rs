@@ -0,0 +1,328 @@
+//! RLP encodings of proofs and change-sets.
+//!
+//! - A [`PathProof`] is encoded as the list `[terminal, [sibling, ..]]`.
+//! - A leaf terminal is encoded as `[0, key_path, value_hash]`.
+//! - A terminator terminal is encoded as `[1, path, depth]`, where `path` is the full 32-byte
+//!   path of which only the first `depth` bits are meaningful.
+//! - A change-set is encoded as a list of `[key_path, value_hash]` pairs, where a deletion is
+//!   encoded with the empty string in place of the value hash.
+//!
+//! Integers are encoded as minimal big-endian byte strings, as usual in RLP.
+
+use alloc::vec::Vec;
+
+use super::{PathProof, PathProofTerminal};
+use crate::{
+    trie::{KeyPath, LeafData, ValueHash},
+    trie_pos::TriePosition,
+};
+
+/// Errors in decoding RLP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input ended unexpectedly.
+    UnexpectedEnd,
+    /// There were trailing bytes after the encoded item.
+    TrailingBytes,
+    /// A list was found where a string was expected or vice versa.
+    UnexpectedKind,
+    /// A length or integer was not minimally encoded.
+    NonCanonical,
+    /// The decoded item had an invalid value.
+    InvalidValue,
+}
+
+/// Encode a path proof.
+pub fn encode_path_proof(proof: &PathProof) -> Vec<u8> {
+    let terminal = match proof.terminal {
+        PathProofTerminal::Leaf(ref leaf) => encode_list(&[
+            encode_uint(0),
+            encode_bytes(&leaf.key_path),
+            encode_bytes(&leaf.value_hash),
+        ]),
+        PathProofTerminal::Terminator(ref pos) => encode_list(&[
+            encode_uint(1),
+            encode_bytes(&pos.raw_path()),
+            encode_uint(pos.depth() as u64),
+        ]),
+    };
+    let siblings: Vec<_> = proof.siblings.iter().map(|s| encode_bytes(s)).collect();
+    encode_list(&[terminal, encode_list(&siblings)])
+}
+
+/// Decode a path proof.
+pub fn decode_path_proof(data: &[u8]) -> Result<PathProof, DecodeError> {
+    let items = decode_list(complete(data)?)?;
+    let [terminal, siblings] = items[..] else {
+        return Err(DecodeError::InvalidValue);
+    };
+
+    let terminal = match decode_list(terminal)?[..] {
+        [tag, a, b] => match decode_uint(tag)? {
+            0 => PathProofTerminal::Leaf(LeafData {
+                key_path: decode_hash(a)?,
+                value_hash: decode_hash(b)?,
+            }),
+            1 => {
+                let path = decode_hash(a)?;
+                PathProofTerminal::Terminator(match decode_uint(b)? {
+                    0 => TriePosition::new(),
+                    depth @ 1..=256 => TriePosition::from_path_and_depth(path, depth as u16),
+                    _ => return Err(DecodeError::InvalidValue),
+                })
+            }
+            _ => return Err(DecodeError::InvalidValue),
+        },
+        _ => return Err(DecodeError::InvalidValue),
+    };
+
+    let siblings = decode_list(siblings)?
+        .into_iter()
+        .map(decode_hash)
+        .collect::<Result<Vec<_>, _>>()?;
+    if siblings.len() > 256 {
+        return Err(DecodeError::InvalidValue);
+    }
+
+    Ok(PathProof { terminal, siblings })
+}
+
+/// Encode a change-set of writes. `None` denotes a deletion.
+pub fn encode_change_set(changes: &[(KeyPath, Option<ValueHash>)]) -> Vec<u8> {
+    let items: Vec<_> = changes
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Some(v) => encode_bytes(v),
+                None => encode_bytes(&[]),
+            };
+            encode_list(&[encode_bytes(key), value])
+        })
+        .collect();
+    encode_list(&items)
+}
+
+/// Decode a change-set of writes.
+pub fn decode_change_set(data: &[u8]) -> Result<Vec<(KeyPath, Option<ValueHash>)>, DecodeError> {
+    decode_list(complete(data)?)?
+        .into_iter()
+        .map(|item| match decode_list(item)?[..] {
+            [key, value] => {
+                let value = match decode_bytes(value)? {
+                    [] => None,
+                    _ => Some(decode_hash(value)?),
+                };
+                Ok((decode_hash(key)?, value))
+            }
+            _ => Err(DecodeError::InvalidValue),
+        })
+        .collect()
+}
+
+fn encode_length(len: usize, offset: u8, out: &mut Vec<u8>) {
+    if len < 56 {
+        out.push(offset + len as u8);
+    } else {
+        let len_bytes = (len as u64).to_be_bytes();
+        let skip = len_bytes.iter().take_while(|b| **b == 0).count();
+        out.push(offset + 55 + (8 - skip) as u8);
+        out.extend_from_slice(&len_bytes[skip..]);
+    }
+}
+
+fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len() + 9);
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        out.push(bytes[0]);
+    } else {
+        encode_length(bytes.len(), 0x80, &mut out);
+        out.extend_from_slice(bytes);
+    }
+    out
+}
+
+fn encode_uint(value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|b| **b == 0).count();
+    encode_bytes(&bytes[skip..])
+}
+
+fn encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let len = items.iter().map(Vec::len).sum();
+    let mut out = Vec::with_capacity(len + 9);
+    encode_length(len, 0xc0, &mut out);
+    for item in items {
+        out.extend_from_slice(item);
+    }
+    out
+}
+
+enum Item<'a> {
+    Bytes(&'a [u8]),
+    List(&'a [u8]),
+}
+
+// Split the first item off the input, returning it and the remainder.
+fn split_item(data: &[u8]) -> Result<(Item<'_>, &[u8]), DecodeError> {
+    let (&prefix, rest) = data.split_first().ok_or(DecodeError::UnexpectedEnd)?;
+
+    let (is_list, header_len, len) = match prefix {
+        0x00..=0x7f => return Ok((Item::Bytes(&data[..1]), rest)),
+        0x80..=0xb7 => (false, 0, (prefix - 0x80) as usize),
+        0xb8..=0xbf => (false, (prefix - 0xb7) as usize, 0),
+        0xc0..=0xf7 => (true, 0, (prefix - 0xc0) as usize),
+        0xf8..=0xff => (true, (prefix - 0xf7) as usize, 0),
+    };
+
+    let (len, rest) = if header_len == 0 {
+        (len, rest)
+    } else {
+        if rest.len() < header_len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (len_bytes, rest) = rest.split_at(header_len);
+        if len_bytes[0] == 0 || header_len > 8 {
+            return Err(DecodeError::NonCanonical);
+        }
+        let len = len_bytes.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+        if len < 56 {
+            return Err(DecodeError::NonCanonical);
+        }
+        (
+            usize::try_from(len).map_err(|_| DecodeError::UnexpectedEnd)?,
+            rest,
+        )
+    };
+
+    if rest.len() < len {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    let (payload, rest) = rest.split_at(len);
+    if !is_list && len == 1 && payload[0] < 0x80 {
+        return Err(DecodeError::NonCanonical);
+    }
+
+    let item = if is_list {
+        Item::List(payload)
+    } else {
+        Item::Bytes(payload)
+    };
+    Ok((item, rest))
+}
+
+// Check that the data contains exactly one item.
+fn complete(data: &[u8]) -> Result<&[u8], DecodeError> {
+    let (_, rest) = split_item(data)?;
+    if rest.is_empty() {
+        Ok(data)
+    } else {
+        Err(DecodeError::TrailingBytes)
+    }
+}
+
+// Decode a list into its raw, still-encoded items.
+fn decode_list(data: &[u8]) -> Result<Vec<&[u8]>, DecodeError> {
+    let Item::List(mut payload) = split_item(data)?.0 else {
+        return Err(DecodeError::UnexpectedKind);
+    };
+    let mut items = Vec::new();
+    while !payload.is_empty() {
+        let (_, rest) = split_item(payload)?;
+        items.push(&payload[..payload.len() - rest.len()]);
+        payload = rest;
+    }
+    Ok(items)
+}
+
+fn decode_bytes(data: &[u8]) -> Result<&[u8], DecodeError> {
+    match split_item(data)?.0 {
+        Item::Bytes(bytes) => Ok(bytes),
+        Item::List(_) => Err(DecodeError::UnexpectedKind),
+    }
+}
+
+fn decode_hash(data: &[u8]) -> Result<[u8; 32], DecodeError> {
+    decode_bytes(data)?
+        .try_into()
+        .map_err(|_| DecodeError::InvalidValue)
+}
+
+fn decode_uint(data: &[u8]) -> Result<u64, DecodeError> {
+    let bytes = decode_bytes(data)?;
+    if bytes.len() > 8 {
+        return Err(DecodeError::InvalidValue);
+    }
+    if bytes.first() == Some(&0) {
+        return Err(DecodeError::NonCanonical);
+    }
+    Ok(bytes.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_encodings() {
+        assert_eq!(encode_uint(0), [0x80]);
+        assert_eq!(encode_uint(15), [0x0f]);
+        assert_eq!(encode_uint(1024), [0x82, 0x04, 0x00]);
+        assert_eq!(encode_bytes(b"dog"), [0x83, b'd', b'o', b'g']);
+        assert_eq!(encode_list(&[]), [0xc0]);
+
+        let long = [0xaa; 56];
+        let encoded = encode_bytes(&long);
+        assert_eq!(&encoded[..2], &[0xb8, 56]);
+        assert_eq!(decode_bytes(&encoded).unwrap(), &long[..]);
+    }
+
+    #[test]
+    fn path_proof_roundtrip() {
+        let leaf = PathProof {
+            terminal: PathProofTerminal::Leaf(LeafData {
+                key_path: [1; 32],
+                value_hash: [2; 32],
+            }),
+            siblings: vec![[3; 32]; 10],
+        };
+        let decoded = decode_path_proof(&encode_path_proof(&leaf)).unwrap();
+        assert_eq!(decoded.terminal, leaf.terminal);
+        assert_eq!(decoded.siblings, leaf.siblings);
+
+        let terminator = PathProof {
+            terminal: PathProofTerminal::Terminator(TriePosition::from_path_and_depth(
+                [0b1010_0000; 32],
+                3,
+            )),
+            siblings: vec![[4; 32]; 3],
+        };
+        let decoded = decode_path_proof(&encode_path_proof(&terminator)).unwrap();
+        assert_eq!(decoded.terminal, terminator.terminal);
+        assert_eq!(decoded.siblings, terminator.siblings);
+
+        let root = PathProof {
+            terminal: PathProofTerminal::Terminator(TriePosition::new()),
+            siblings: vec![],
+        };
+        let decoded = decode_path_proof(&encode_path_proof(&root)).unwrap();
+        assert_eq!(decoded.terminal, root.terminal);
+    }
+
+    #[test]
+    fn change_set_roundtrip() {
+        let changes = vec![([1; 32], Some([2; 32])), ([3; 32], None)];
+        let encoded = encode_change_set(&changes);
+        assert_eq!(decode_change_set(&encoded).unwrap(), changes);
+
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert_eq!(
+            decode_change_set(&trailing),
+            Err(DecodeError::TrailingBytes)
+        );
+        assert_eq!(
+            decode_change_set(&encoded[..encoded.len() - 1]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+    }
+}
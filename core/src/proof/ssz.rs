@@ -0,0 +1,260 @@
+//! SSZ encodings of proofs and change-sets.
+//!
+//! The encodings correspond to the following SSZ types:
+//!
+//! ```text
+//! class Leaf(Container):
+//!     key_path: Bytes32
+//!     value_hash: Bytes32
+//!
+//! class Terminator(Container):
+//!     path: Bytes32  # only the first `depth` bits are meaningful
+//!     depth: uint16
+//!
+//! class PathProof(Container):
+//!     terminal: Union[Leaf, Terminator]
+//!     siblings: List[Bytes32, 256]
+//!
+//! class Change(Container):
+//!     key_path: Bytes32
+//!     value_hash: Union[None, Bytes32]  # None denotes a deletion
+//!
+//! ChangeSet = List[Change, MAX_CHANGES]
+//! ```
+
+use alloc::vec::Vec;
+
+use super::{PathProof, PathProofTerminal};
+use crate::{
+    trie::{KeyPath, LeafData, ValueHash},
+    trie_pos::TriePosition,
+};
+
+const OFFSET_SIZE: usize = 4;
+
+/// Errors in decoding SSZ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input was shorter than expected.
+    UnexpectedEnd,
+    /// The input was longer than expected.
+    TrailingBytes,
+    /// An offset pointed outside of the input or was out of order.
+    InvalidOffset,
+    /// A union selector was out of range.
+    InvalidSelector,
+    /// The decoded item had an invalid value.
+    InvalidValue,
+}
+
+/// Encode a path proof.
+pub fn encode_path_proof(proof: &PathProof) -> Vec<u8> {
+    let mut terminal = Vec::with_capacity(65);
+    match proof.terminal {
+        PathProofTerminal::Leaf(ref leaf) => {
+            terminal.push(0);
+            terminal.extend_from_slice(&leaf.key_path);
+            terminal.extend_from_slice(&leaf.value_hash);
+        }
+        PathProofTerminal::Terminator(ref pos) => {
+            terminal.push(1);
+            terminal.extend_from_slice(&pos.raw_path());
+            terminal.extend_from_slice(&pos.depth().to_le_bytes());
+        }
+    }
+
+    let fixed_len = 2 * OFFSET_SIZE;
+    let mut out = Vec::with_capacity(fixed_len + terminal.len() + proof.siblings.len() * 32);
+    out.extend_from_slice(&(fixed_len as u32).to_le_bytes());
+    out.extend_from_slice(&((fixed_len + terminal.len()) as u32).to_le_bytes());
+    out.extend_from_slice(&terminal);
+    for sibling in &proof.siblings {
+        out.extend_from_slice(sibling);
+    }
+    out
+}
+
+/// Decode a path proof.
+pub fn decode_path_proof(data: &[u8]) -> Result<PathProof, DecodeError> {
+    let fields = decode_offsets(data, 2, 2 * OFFSET_SIZE)?;
+    let (terminal, siblings) = (fields[0], fields[1]);
+
+    let terminal = match terminal.split_first() {
+        Some((0, leaf)) => {
+            if leaf.len() != 64 {
+                return Err(DecodeError::InvalidValue);
+            }
+            PathProofTerminal::Leaf(LeafData {
+                key_path: hash(&leaf[..32]),
+                value_hash: hash(&leaf[32..]),
+            })
+        }
+        Some((1, terminator)) => {
+            if terminator.len() != 34 {
+                return Err(DecodeError::InvalidValue);
+            }
+            let path = hash(&terminator[..32]);
+            let depth = u16::from_le_bytes([terminator[32], terminator[33]]);
+            PathProofTerminal::Terminator(match depth {
+                0 => TriePosition::new(),
+                1..=256 => TriePosition::from_path_and_depth(path, depth),
+                _ => return Err(DecodeError::InvalidValue),
+            })
+        }
+        Some(_) => return Err(DecodeError::InvalidSelector),
+        None => return Err(DecodeError::UnexpectedEnd),
+    };
+
+    if siblings.len() % 32 != 0 || siblings.len() / 32 > 256 {
+        return Err(DecodeError::InvalidValue);
+    }
+    let siblings = siblings.chunks_exact(32).map(hash).collect();
+
+    Ok(PathProof { terminal, siblings })
+}
+
+/// Encode a change-set of writes. `None` denotes a deletion.
+pub fn encode_change_set(changes: &[(KeyPath, Option<ValueHash>)]) -> Vec<u8> {
+    // every change is variable-size due to the union, so the list begins with offsets.
+    let change_len = |value: &Option<ValueHash>| 32 + 1 + value.map_or(0, |_| 32);
+    let mut out = Vec::with_capacity(
+        changes
+            .iter()
+            .map(|(_, v)| OFFSET_SIZE + change_len(v))
+            .sum(),
+    );
+
+    let mut offset = changes.len() * OFFSET_SIZE;
+    for (_, value) in changes {
+        out.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += change_len(value);
+    }
+    for (key, value) in changes {
+        out.extend_from_slice(key);
+        match value {
+            Some(value) => {
+                out.push(1);
+                out.extend_from_slice(value);
+            }
+            None => out.push(0),
+        }
+    }
+    out
+}
+
+/// Decode a change-set of writes.
+pub fn decode_change_set(data: &[u8]) -> Result<Vec<(KeyPath, Option<ValueHash>)>, DecodeError> {
+    if data.is_empty() {
+        return Ok(Vec::new());
+    }
+    if data.len() < OFFSET_SIZE {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    // the first offset determines the number of elements.
+    let first = read_offset(data, 0)?;
+    if first % OFFSET_SIZE != 0 || first == 0 {
+        return Err(DecodeError::InvalidOffset);
+    }
+
+    decode_offsets(data, first / OFFSET_SIZE, first)?
+        .into_iter()
+        .map(|change| {
+            if change.len() < 33 {
+                return Err(DecodeError::UnexpectedEnd);
+            }
+            let key = hash(&change[..32]);
+            let value = match (change[32], &change[33..]) {
+                (0, []) => None,
+                (1, value) if value.len() == 32 => Some(hash(value)),
+                (0 | 1, _) => return Err(DecodeError::InvalidValue),
+                _ => return Err(DecodeError::InvalidSelector),
+            };
+            Ok((key, value))
+        })
+        .collect()
+}
+
+fn read_offset(data: &[u8], at: usize) -> Result<usize, DecodeError> {
+    let bytes = data
+        .get(at..at + OFFSET_SIZE)
+        .ok_or(DecodeError::UnexpectedEnd)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+}
+
+// Split `data` into `n` variable-size parts, given by leading offsets. The first offset must be
+// equal to `fixed_len`.
+fn decode_offsets(data: &[u8], n: usize, fixed_len: usize) -> Result<Vec<&[u8]>, DecodeError> {
+    let mut offsets = Vec::with_capacity(n + 1);
+    for i in 0..n {
+        offsets.push(read_offset(data, i * OFFSET_SIZE)?);
+    }
+    offsets.push(data.len());
+
+    if offsets[0] != fixed_len {
+        return Err(DecodeError::InvalidOffset);
+    }
+    if offsets.windows(2).any(|w| w[0] > w[1]) || offsets[n - 1] > data.len() {
+        return Err(DecodeError::InvalidOffset);
+    }
+
+    Ok(offsets.windows(2).map(|w| &data[w[0]..w[1]]).collect())
+}
+
+fn hash(bytes: &[u8]) -> [u8; 32] {
+    let mut hash = [0; 32];
+    hash.copy_from_slice(bytes);
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_proof_roundtrip() {
+        let leaf = PathProof {
+            terminal: PathProofTerminal::Leaf(LeafData {
+                key_path: [1; 32],
+                value_hash: [2; 32],
+            }),
+            siblings: vec![[3; 32]; 10],
+        };
+        let encoded = encode_path_proof(&leaf);
+        assert_eq!(encoded.len(), 8 + 65 + 320);
+        let decoded = decode_path_proof(&encoded).unwrap();
+        assert_eq!(decoded.terminal, leaf.terminal);
+        assert_eq!(decoded.siblings, leaf.siblings);
+
+        let terminator = PathProof {
+            terminal: PathProofTerminal::Terminator(TriePosition::from_path_and_depth(
+                [0b1010_0000; 32],
+                3,
+            )),
+            siblings: vec![[4; 32]; 3],
+        };
+        let decoded = decode_path_proof(&encode_path_proof(&terminator)).unwrap();
+        assert_eq!(decoded.terminal, terminator.terminal);
+        assert_eq!(decoded.siblings, terminator.siblings);
+
+        assert_eq!(
+            decode_path_proof(&encoded[..encoded.len() - 1]).err(),
+            Some(DecodeError::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn change_set_roundtrip() {
+        let changes = vec![([1; 32], Some([2; 32])), ([3; 32], None)];
+        let encoded = encode_change_set(&changes);
+        assert_eq!(encoded.len(), 8 + 65 + 33);
+        assert_eq!(decode_change_set(&encoded).unwrap(), changes);
+        assert_eq!(decode_change_set(&[]).unwrap(), vec![]);
+
+        let mut bad_selector = encoded.clone();
+        bad_selector[8 + 32] = 2;
+        assert_eq!(
+            decode_change_set(&bad_selector),
+            Err(DecodeError::InvalidSelector)
+        );
+    }
+}
@@ -1,3 +1,23 @@
+//! Navigation of the binary trie, as laid out in pages.
+//!
+//! A [`TriePosition`] is a path of up to 256 bits from the root. Along with the path it tracks
+//! where the node at that position is stored: the page it lands in and the index of the node
+//! within that page. Every [`DEPTH`] bits of the path cross into a child page.
+//!
+//! ```
+//! use nomt_core::{page_id::ROOT_PAGE_ID, trie_pos::TriePosition};
+//!
+//! let mut pos = TriePosition::new();
+//! pos.down(true);
+//! pos.down(false);
+//! assert_eq!(pos.depth(), 2);
+//! assert_eq!(pos.page_id_and_node_index(), Some((ROOT_PAGE_ID, 4)));
+//!
+//! let (path, depth) = pos.to_path_and_depth();
+//! assert_eq!(TriePosition::from_path_and_depth(path, depth), pos);
+//! assert_eq!(pos.parent(), Some(TriePosition::from_path_and_depth(path, 1)));
+//! ```
+
 use crate::{
     page::DEPTH,
     page_id::{ChildPageIndex, PageId, ROOT_PAGE_ID},
@@ -74,16 +94,20 @@ impl TriePosition {
                 _ => panic!("invalid character in bit string"),
             }
         }
-        let node_index = node_index(&bitvec);
-        let depth = bitvec.len() as u16;
-        bitvec.resize(256, false);
-        // Unwrap: resized to 256 bit, or 32 bytes, above.
-        let path = bitvec.as_raw_slice().try_into().unwrap();
-        Self {
-            path,
-            depth,
-            node_index,
+        if bitvec.is_empty() {
+            return Self::new();
         }
+        Self::from_bitslice(&bitvec)
+    }
+
+    /// Get the path and the depth of the position, with the bits of the path beyond the depth
+    /// cleared.
+    ///
+    /// This is the inverse of [`Self::from_path_and_depth`], except at the root.
+    pub fn to_path_and_depth(&self) -> (KeyPath, u16) {
+        let mut path = [0; 32];
+        path.view_bits_mut::<Msb0>()[..self.depth as usize].copy_from_bitslice(self.path());
+        (path, self.depth)
     }
 
     /// Whether the position is at the root.
@@ -108,6 +132,35 @@ impl TriePosition {
         self.path
     }
 
+    /// Get the position of the left (`false`) or right (`true`) child.
+    ///
+    /// Panics on depth out of range.
+    pub fn child(&self, bit: bool) -> Self {
+        let mut child = self.clone();
+        child.down(bit);
+        child
+    }
+
+    /// Get the position of the parent. Returns `None` at the root.
+    pub fn parent(&self) -> Option<Self> {
+        if self.is_root() {
+            return None;
+        }
+        let mut parent = self.clone();
+        parent.up(1);
+        Some(parent)
+    }
+
+    /// Get the position of the sibling. Returns `None` at the root.
+    pub fn sibling_position(&self) -> Option<Self> {
+        if self.is_root() {
+            return None;
+        }
+        let mut sibling = self.clone();
+        sibling.sibling();
+        Some(sibling)
+    }
+
     /// Move the position down by 1, towards either the left or right child.
     ///
     /// Panics on depth out of range.
@@ -189,8 +242,8 @@ impl TriePosition {
                 return Some(page_id);
             }
 
-            // UNWRAP: 6 bits never overflows child page index
-            let child_index = ChildPageIndex::new(chunk.load_be::<u8>()).unwrap();
+            // UNWRAP: chunks are exactly DEPTH bits.
+            let child_index = ChildPageIndex::from_bits(chunk).unwrap();
 
             // UNWRAP: trie position never overflows page tree.
             page_id = page_id.child_page_id(child_index).unwrap();
@@ -199,6 +252,12 @@ impl TriePosition {
         Some(page_id)
     }
 
+    /// Get the page ID this position lands in along with the index of the node within the page.
+    /// Returns `None` at the root, which isn't stored in any page.
+    pub fn page_id_and_node_index(&self) -> Option<(PageId, usize)> {
+        self.page_id().map(|page_id| (page_id, self.node_index))
+    }
+
     /// Get the child page index, relative to the current page,
     /// where the children of the current node are stored.
     ///
@@ -354,6 +413,7 @@ impl ChildNodeIndices {
 #[cfg(test)]
 mod tests {
     use super::TriePosition;
+    use crate::page_id::{ChildPageIndex, ROOT_PAGE_ID};
 
     #[test]
     fn path_can_go_deeper_255_bit() {
@@ -366,4 +426,41 @@ mod tests {
         assert_eq!(p.depth as usize, 255);
         p.down(false);
     }
+
+    #[test]
+    fn navigation() {
+        let root = TriePosition::new();
+        assert_eq!(root.parent(), None);
+        assert_eq!(root.sibling_position(), None);
+        assert_eq!(root.page_id_and_node_index(), None);
+
+        let pos = TriePosition::from_str("1011010");
+        assert_eq!(pos.parent(), Some(TriePosition::from_str("101101")));
+        assert_eq!(
+            pos.sibling_position(),
+            Some(TriePosition::from_str("1011011"))
+        );
+        assert_eq!(pos.child(true), TriePosition::from_str("10110101"));
+
+        // the 7th bit lands in the first layer of the child page 0b101101.
+        let child_page = ROOT_PAGE_ID
+            .child_page_id(ChildPageIndex::new(0b101101).unwrap())
+            .unwrap();
+        assert_eq!(pos.page_id_and_node_index(), Some((child_page, 0)));
+        assert_eq!(
+            pos.parent().unwrap().page_id_and_node_index(),
+            Some((ROOT_PAGE_ID, 62 + 0b101101))
+        );
+
+        let (path, depth) = pos.to_path_and_depth();
+        assert_eq!(depth, 7);
+        assert_eq!(path[0], 0b1011_0100);
+        assert!(path[1..].iter().all(|b| *b == 0));
+        assert_eq!(TriePosition::from_path_and_depth(path, depth), pos);
+
+        // bits beyond the depth are cleared, even after moving up.
+        let mut up = TriePosition::from_str("11111111");
+        up.up(4);
+        assert_eq!(up.to_path_and_depth().0[0], 0b1111_0000);
+    }
 }
@@ -0,0 +1,79 @@
+//! JavaScript bindings for proof verification, for use by light clients running in a browser.
+//!
+//! Proofs are passed in their borsh encoding. Hashers are selected by name: either `"blake3"` or
+//! `"sha2"`.
+//!
+//! Build with `cargo build -p nomt-core --target wasm32-unknown-unknown --features wasm` and
+//! generate the JavaScript glue with `wasm-bindgen`.
+
+use alloc::vec::Vec;
+use bitvec::prelude::*;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    hasher::{Blake3Hasher, NodeHasher, Sha2Hasher, ValueHasher},
+    proof::{PathProof, PathProofVerificationError},
+    trie::{KeyPath, Node},
+};
+
+/// Verify a borsh-encoded [`PathProof`] for the given 32-byte key path against a 32-byte root.
+///
+/// Returns the hash of the value stored under the key, or `undefined` if the proof shows that the
+/// key has no value. Throws if the proof is malformed, invalid, or does not cover the key.
+#[wasm_bindgen(js_name = verifyPathProof)]
+pub fn verify_path_proof(
+    hasher: &str,
+    proof: &[u8],
+    key_path: &[u8],
+    root: &[u8],
+) -> Result<Option<Vec<u8>>, JsError> {
+    match hasher {
+        "blake3" => verify_path_proof_inner::<Blake3Hasher>(proof, key_path, root),
+        "sha2" => verify_path_proof_inner::<Sha2Hasher>(proof, key_path, root),
+        _ => Err(JsError::new("unknown hasher")),
+    }
+}
+
+/// Hash a value with the given hasher, for comparison against the output of
+/// [`verify_path_proof`].
+#[wasm_bindgen(js_name = hashValue)]
+pub fn hash_value(hasher: &str, value: &[u8]) -> Result<Vec<u8>, JsError> {
+    match hasher {
+        "blake3" => Ok(Blake3Hasher::hash_value(value).to_vec()),
+        "sha2" => Ok(Sha2Hasher::hash_value(value).to_vec()),
+        _ => Err(JsError::new("unknown hasher")),
+    }
+}
+
+fn verify_path_proof_inner<H: NodeHasher>(
+    proof: &[u8],
+    key_path: &[u8],
+    root: &[u8],
+) -> Result<Option<Vec<u8>>, JsError> {
+    let key_path: KeyPath = key_path
+        .try_into()
+        .map_err(|_| JsError::new("key path must be 32 bytes"))?;
+    let root: Node = root
+        .try_into()
+        .map_err(|_| JsError::new("root must be 32 bytes"))?;
+    let proof: PathProof =
+        borsh::from_slice(proof).map_err(|_| JsError::new("malformed path proof"))?;
+
+    let verified = proof
+        .verify::<H>(key_path.view_bits::<Msb0>(), root)
+        .map_err(|e| match e {
+            PathProofVerificationError::TooManySiblings => JsError::new("too many siblings"),
+            PathProofVerificationError::RootMismatch => JsError::new("root mismatch"),
+        })?;
+
+    match verified.terminal() {
+        Some(leaf) if leaf.key_path == key_path => Ok(Some(leaf.value_hash.to_vec())),
+        _ => {
+            let absent = verified
+                .confirm_nonexistence(&key_path)
+                .map_err(|_| JsError::new("key out of scope of proof"))?;
+            debug_assert!(absent);
+            Ok(None)
+        }
+    }
+}
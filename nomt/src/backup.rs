@@ -0,0 +1,340 @@
+//! Incremental backups based on commit deltas.
+//!
+//! When a backup log is configured, every commit appends a record with the value changes it made
+//! to the log. A database can be restored from a full snapshot (see [`crate::dump`]) followed by
+//! the deltas recorded after it, up to a chosen commit.
+//!
+//! The log begins with `MAGIC` and a version byte, followed by records. Each record is laid out
+//! as:
+//!   - payload length (8 bytes, little-endian)
+//!   - previous root (32 bytes)
+//!   - new root (32 bytes)
+//!   - number of changes (4 bytes, little-endian)
+//!   - changes, each made of a key path (32 bytes), a tag (1 byte) which is 0 for deletions and
+//!     1 for insertions, and for insertions the value length (4 bytes, little-endian) and value.
+//!
+//! Records are chained by their roots: a record applies on top of the state with its previous
+//! root. A torn record at the end of the log, left over by a crash while appending, is discarded.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    beatree::ValueChange, dump, trie::KeyPath, HashAlgorithm, KeyReadWrite, Nomt, Root,
+    SessionParams, Value,
+};
+
+const MAGIC: [u8; 8] = *b"NOMTBKUP";
+const VERSION: u8 = 1;
+const HEADER_LEN: u64 = 9;
+
+/// A single commit recorded in the backup log.
+pub(crate) struct Delta {
+    pub prev_root: Root,
+    pub root: Root,
+    pub changes: Vec<(KeyPath, Option<Value>)>,
+}
+
+/// The append-only log of commit deltas.
+pub(crate) struct BackupLog {
+    file: Mutex<File>,
+}
+
+impl BackupLog {
+    /// Open the backup log at the given path, creating it if it doesn't exist.
+    ///
+    /// Any torn record at the end of the log is truncated.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        if file.metadata()?.len() == 0 {
+            let mut header = MAGIC.to_vec();
+            header.push(VERSION);
+            file.write_all(&header)?;
+            file.sync_all()?;
+        } else {
+            let mut reader = DeltaReader::new(BufReader::new(&file))?;
+            while reader.next_delta()?.is_some() {}
+            let valid_len = reader.offset;
+            if valid_len != file.metadata()?.len() {
+                file.set_len(valid_len)?;
+                file.sync_all()?;
+            }
+        }
+        file.seek(SeekFrom::End(0))?;
+
+        Ok(BackupLog {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Append a record for a commit changing the root from `prev_root` to `root`.
+    pub fn append(
+        &self,
+        prev_root: Root,
+        root: Root,
+        changes: &[(KeyPath, ValueChange)],
+    ) -> anyhow::Result<()> {
+        let mut payload = Vec::new();
+        payload.extend_from_slice(&prev_root.into_inner());
+        payload.extend_from_slice(&root.into_inner());
+        payload.extend_from_slice(&(changes.len() as u32).to_le_bytes());
+        for (key, change) in changes {
+            payload.extend_from_slice(key);
+            match change {
+                ValueChange::Delete => payload.push(0),
+                ValueChange::Insert(value) | ValueChange::InsertOverflow(value, _) => {
+                    payload.push(1);
+                    payload.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                    payload.extend_from_slice(value);
+                }
+            }
+        }
+
+        let mut record = Vec::with_capacity(8 + payload.len());
+        record.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        record.extend_from_slice(&payload);
+
+        let mut file = self.file.lock();
+        file.write_all(&record)?;
+        file.sync_data()?;
+        Ok(())
+    }
+}
+
+/// Restore an empty database from the snapshot at `snapshot` and the backup log at `deltas`.
+///
+/// The deltas recorded after the snapshot are applied in order, up to and including the commit
+/// resulting in `target`, or all of them if `target` is `None`. The chain of deltas is checked
+/// before anything is written. Returns the root of the restored state.
+pub(crate) fn restore<T: HashAlgorithm>(
+    nomt: &Nomt<T>,
+    snapshot: &Path,
+    deltas: &Path,
+    target: Option<Root>,
+) -> anyhow::Result<Root> {
+    let snapshot_root = dump::Reader::open(snapshot)?.header().root;
+
+    // First pass: find the number of deltas to apply.
+    let mut reader = DeltaReader::open(deltas)?;
+    let mut root = snapshot_root;
+    let mut to_apply = 0;
+    while target != Some(root) {
+        let Some(delta) = reader.next_delta()? else {
+            break;
+        };
+        if to_apply == 0 && delta.prev_root != root {
+            // deltas recorded before the snapshot was taken.
+            continue;
+        }
+        if delta.prev_root != root {
+            anyhow::bail!("restore: gap in backup log after root {}", root);
+        }
+        root = delta.root;
+        to_apply += 1;
+    }
+    if let Some(target) = target {
+        if target != root {
+            anyhow::bail!("restore: target root {} not found in backup log", target);
+        }
+    }
+
+    nomt.import(snapshot)?;
+
+    // Second pass: apply the deltas.
+    let mut reader = DeltaReader::open(deltas)?;
+    let mut root = snapshot_root;
+    let mut applied = 0;
+    while applied < to_apply {
+        // UNWRAP: the first pass ensures there are enough deltas.
+        let delta = reader.next_delta()?.unwrap();
+        if delta.prev_root != root {
+            continue;
+        }
+
+        let mut actuals: Vec<_> = delta
+            .changes
+            .into_iter()
+            .map(|(key, value)| (key, KeyReadWrite::Write(value)))
+            .collect();
+        actuals.sort_by_key(|(key, _)| *key);
+
+        let session = nomt.begin_session(SessionParams::default());
+        let finished = session.finish(actuals)?;
+        if finished.root() != delta.root {
+            anyhow::bail!(
+                "restore: root mismatch. backup log: {}, computed: {}",
+                delta.root,
+                finished.root(),
+            );
+        }
+        finished.commit(nomt)?;
+
+        root = delta.root;
+        applied += 1;
+    }
+
+    Ok(root)
+}
+
+/// A reader over the records of a backup log.
+pub(crate) struct DeltaReader<R> {
+    inner: R,
+    // the offset just past the last complete record.
+    offset: u64,
+}
+
+impl DeltaReader<BufReader<File>> {
+    /// Open the backup log at the given path for reading.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        DeltaReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> DeltaReader<R> {
+    fn new(mut inner: R) -> anyhow::Result<Self> {
+        let mut header = [0; HEADER_LEN as usize];
+        inner.read_exact(&mut header)?;
+        if header[..8] != MAGIC {
+            anyhow::bail!("not a NOMT backup log");
+        }
+        if header[8] != VERSION {
+            anyhow::bail!("unsupported backup log version {}", header[8]);
+        }
+        Ok(DeltaReader {
+            inner,
+            offset: HEADER_LEN,
+        })
+    }
+
+    /// Read the next delta. Returns `None` at the end of the log or at a torn record.
+    pub fn next_delta(&mut self) -> anyhow::Result<Option<Delta>> {
+        let mut len = [0; 8];
+        if !read_or_eof(&mut self.inner, &mut len)? {
+            return Ok(None);
+        }
+        // read through `take` so that a garbage length in a torn record doesn't lead to a huge
+        // allocation.
+        let len = u64::from_le_bytes(len);
+        let mut payload = Vec::new();
+        (&mut self.inner).take(len).read_to_end(&mut payload)?;
+        if payload.len() as u64 != len {
+            return Ok(None);
+        }
+
+        let delta = decode_payload(&payload)
+            .ok_or_else(|| anyhow::anyhow!("corrupted backup log record at {}", self.offset))?;
+        self.offset += 8 + payload.len() as u64;
+        Ok(Some(delta))
+    }
+}
+
+// Fill the buffer. Returns `false` if the end of the input is reached before the buffer is full.
+fn read_or_eof(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn decode_payload(mut payload: &[u8]) -> Option<Delta> {
+    fn take<'a>(payload: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+        if payload.len() < n {
+            return None;
+        }
+        let (head, tail) = payload.split_at(n);
+        *payload = tail;
+        Some(head)
+    }
+    fn take_array<const N: usize>(payload: &mut &[u8]) -> Option<[u8; N]> {
+        take(payload, N).map(|bytes| bytes.try_into().unwrap())
+    }
+
+    let prev_root = Root(take_array(&mut payload)?);
+    let root = Root(take_array(&mut payload)?);
+    let count = u32::from_le_bytes(take_array(&mut payload)?);
+    let mut changes = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let key = take_array(&mut payload)?;
+        let value = match take(&mut payload, 1)?[0] {
+            0 => None,
+            1 => {
+                let len = u32::from_le_bytes(take_array(&mut payload)?) as usize;
+                Some(take(&mut payload, len)?.to_vec())
+            }
+            _ => return None,
+        };
+        changes.push((key, value));
+    }
+
+    if !payload.is_empty() {
+        return None;
+    }
+
+    Some(Delta {
+        prev_root,
+        root,
+        changes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BackupLog, DeltaReader};
+    use crate::{beatree::ValueChange, Root};
+    use std::io::Write;
+
+    #[test]
+    fn torn_record_is_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup");
+
+        let log = BackupLog::open(&path).unwrap();
+        log.append(
+            Root([0; 32]),
+            Root([1; 32]),
+            &[
+                ([1; 32], ValueChange::Insert(vec![1, 2, 3])),
+                ([2; 32], ValueChange::Delete),
+            ],
+        )
+        .unwrap();
+        drop(log);
+
+        // simulate a crash while appending a record.
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(&[100, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3])
+            .unwrap();
+        drop(file);
+
+        let log = BackupLog::open(&path).unwrap();
+        log.append(Root([1; 32]), Root([2; 32]), &[]).unwrap();
+        drop(log);
+
+        let mut reader = DeltaReader::open(&path).unwrap();
+        let first = reader.next_delta().unwrap().unwrap();
+        assert_eq!(first.root, Root([1; 32]));
+        assert_eq!(
+            first.changes,
+            vec![([1; 32], Some(vec![1, 2, 3])), ([2; 32], None)]
+        );
+        let second = reader.next_delta().unwrap().unwrap();
+        assert_eq!(second.prev_root, Root([1; 32]));
+        assert!(second.changes.is_empty());
+        assert!(reader.next_delta().unwrap().is_none());
+    }
+}
@@ -83,6 +83,12 @@ impl FreeList {
         BTreeSet::from_iter(pns)
     }
 
+    /// Get an iterator over the free pages in the free-list, not including the free-list pages
+    /// themselves.
+    pub fn free_pages(&self) -> impl Iterator<Item = PageNumber> + '_ {
+        self.portions.iter().flat_map(|(_, pns)| pns).copied()
+    }
+
     pub fn head_pn(&self) -> Option<PageNumber> {
         self.portions.last().map(|(head_pn, _)| head_pn).copied()
     }
@@ -3,7 +3,7 @@ use crate::io::{self, page_pool::FatPage, IoCommand, IoKind, PagePool, PAGE_SIZE
 use crossbeam_channel::{Receiver, Sender};
 use parking_lot::{ArcMutexGuard, Mutex};
 use std::{
-    collections::BTreeSet,
+    collections::{BTreeSet, HashSet},
     fs::File,
     os::{
         fd::{AsRawFd, RawFd},
@@ -45,6 +45,27 @@ pub const FREELIST_EMPTY: PageNumber = PageNumber(0);
 pub struct Store {
     file: Arc<File>,
     sync: Arc<Mutex<StoreSync>>,
+    growth: GrowthPolicy,
+    discard_freed: bool,
+}
+
+/// How the file of a store is extended once full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrowthPolicy {
+    /// The number of pages the file is extended by at a time.
+    pub extent_pages: u32,
+    /// Whether to allocate the blocks of every extent up front, rather than leaving the file
+    /// sparse until the pages are written.
+    pub allocate: bool,
+}
+
+impl Default for GrowthPolicy {
+    fn default() -> Self {
+        GrowthPolicy {
+            extent_pages: GROW_STORE_BY_PAGES,
+            allocate: false,
+        }
+    }
 }
 
 impl Store {
@@ -54,6 +75,8 @@ impl Store {
         file: Arc<File>,
         bump: PageNumber,
         free_list_head: Option<PageNumber>,
+        growth: GrowthPolicy,
+        discard_freed: bool,
     ) -> anyhow::Result<Self> {
         let file_size = file.metadata()?.size() as usize;
 
@@ -61,11 +84,15 @@ impl Store {
             free_list: FreeList::read(page_pool, &file, free_list_head)?,
             bump,
             max_bump: PageNumber((file_size / PAGE_SIZE) as u32),
+            to_discard: Vec::new(),
+            to_reclaim: BTreeSet::new(),
         };
 
         Ok(Store {
             file,
             sync: Arc::new(Mutex::new(sync)),
+            growth,
+            discard_freed,
         })
     }
 
@@ -90,6 +117,27 @@ impl Store {
         self.sync.lock().free_list.all_tracked_pages()
     }
 
+    /// Get the pages of the store as of the last sync: the next page number, the free pages, not
+    /// including the pages storing the free-list, and the pages waiting to be reclaimed.
+    ///
+    /// Deadlocks if sync is ongoing.
+    pub fn occupancy(&self) -> (PageNumber, BTreeSet<PageNumber>, BTreeSet<PageNumber>) {
+        let sync = self.sync.lock();
+        (
+            sync.bump,
+            sync.free_list.free_pages().collect(),
+            sync.to_reclaim.clone(),
+        )
+    }
+
+    /// Reclaim pages which are neither used nor free. They are added to the free-list by the next
+    /// sync. The caller must ensure that nothing refers to them.
+    ///
+    /// Deadlocks if sync is ongoing.
+    pub fn reclaim(&self, pages: impl IntoIterator<Item = PageNumber>) {
+        self.sync.lock().to_reclaim.extend(pages);
+    }
+
     /// Start synchronization. This produces two handles,
     /// a [`SyncAllocator`] and a [`SyncFinisher`].
     ///
@@ -97,17 +145,32 @@ impl Store {
     /// should be used to update the embedded free-list and prepare the writes for that purpose.
     ///
     /// This will block if another sync is in progress.
+    ///
+    /// The pages freed by the previous sync are discarded here, if enabled. The caller must ensure
+    /// that nothing refers to them anymore.
     pub fn start_sync(&self) -> (SyncAllocator, SyncFinisher) {
-        let sync = Mutex::lock_arc(&self.sync);
+        let mut sync = Mutex::lock_arc(&self.sync);
+
+        // the previous sync has concluded, so the pages it freed are unreachable. they may be
+        // reused by this sync, so they are discarded before anything is allocated.
+        let to_discard = std::mem::take(&mut sync.to_discard);
+        if cfg!(debug_assertions) && !to_discard.is_empty() {
+            let free: HashSet<_> = sync.free_list.free_pages().collect();
+            assert!(to_discard.iter().all(|pn| free.contains(pn)));
+        }
+        discard(&self.file, to_discard);
         let (sync_tx, sync_rx) = crossbeam_channel::bounded(1);
 
         let finisher = SyncFinisher {
             file: self.file.clone(),
+            growth: self.growth,
+            discard_freed: self.discard_freed,
             sync_finish: sync_rx,
         };
 
         let allocator = SyncAllocator {
             file: self.file.clone(),
+            growth: self.growth,
             inner: Arc::new(SyncAllocatorInner {
                 max_bump: AtomicU32::new(sync.max_bump.0),
                 set_len_lock: Mutex::new(sync.max_bump),
@@ -164,12 +227,16 @@ struct StoreSync {
     max_bump: PageNumber,
     /// the free-list of pages.
     free_list: FreeList,
+    /// the pages freed by the last sync, to be discarded once it has concluded.
+    to_discard: Vec<PageNumber>,
+    /// orphaned pages to be added to the free-list by the next sync.
+    to_reclaim: BTreeSet<PageNumber>,
 }
 
 type StoreSyncGuard = ArcMutexGuard<parking_lot::RawMutex, StoreSync>;
 
-// Grow the store by 32MB at a time.
-const GROW_STORE_BY_PAGES: u32 = 8192;
+// Grow the store by 32MB at a time, unless configured otherwise.
+pub const GROW_STORE_BY_PAGES: u32 = 8192;
 
 /// The sync allocator can be used by multiple threads to prospectively allocate pages in the store.
 ///
@@ -178,6 +245,7 @@ const GROW_STORE_BY_PAGES: u32 = 8192;
 #[derive(Clone)]
 pub struct SyncAllocator {
     file: Arc<File>,
+    growth: GrowthPolicy,
     inner: Arc<SyncAllocatorInner>,
 }
 
@@ -211,7 +279,7 @@ impl SyncAllocator {
                 return Ok(pn);
             }
 
-            *set_len_guard = grow(&self.file, pn)?;
+            *set_len_guard = grow(&self.file, pn, self.growth)?;
 
             // note that we only write the atomic while the mutex guard is live.
             self.inner
@@ -258,18 +326,65 @@ impl Drop for SyncAllocatorInner {
 
 /// Grow the file to include the given page number.
 ///
-/// The file is extended to the next chunk boundary defined by
-/// GROW_STORE_BY_PAGES.
+/// The file is extended to the next extent boundary defined by the growth policy.
 ///
-/// If `page` is already aligned, an extra chunk is added.
+/// If `page` is already aligned, an extra extent is added.
 ///
 /// Returns the new boundary (the last accessible page) or an I/O error.
-fn grow(file: &File, page: PageNumber) -> std::io::Result<PageNumber> {
-    let next_bump = (page.0 + GROW_STORE_BY_PAGES - 1).next_multiple_of(GROW_STORE_BY_PAGES);
-    file.set_len(next_bump as u64 * PAGE_SIZE as u64)?;
+fn grow(file: &File, page: PageNumber, growth: GrowthPolicy) -> std::io::Result<PageNumber> {
+    let extent = growth.extent_pages;
+    let next_bump = (page.0 + extent - 1).next_multiple_of(extent);
+    extend(file, next_bump, growth.allocate)?;
     Ok(PageNumber(next_bump))
 }
 
+/// Return the space of the given pages to the filesystem and, through it, the device.
+///
+/// Best-effort: does nothing where the filesystem doesn't support punching holes.
+fn discard(file: &File, mut pages: Vec<PageNumber>) {
+    pages.sort_unstable();
+    pages.dedup();
+
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            // punch a hole for every run of consecutive pages.
+            for run in pages.chunk_by(|a, b| a.0 + 1 == b.0) {
+                let offset = run[0].0 as u64 * PAGE_SIZE as u64;
+                let len = run.len() as u64 * PAGE_SIZE as u64;
+                if crate::sys::linux::punch_hole(file, offset, len).is_err() {
+                    break;
+                }
+            }
+        } else {
+            let _ = file;
+        }
+    }
+}
+
+/// Extend the file to hold at least the given number of pages. Does nothing if it is already
+/// large enough.
+///
+/// If `allocate` is true, the blocks of the new part of the file are allocated up front on a
+/// best-effort basis, falling back to a sparse extension where the filesystem doesn't support it.
+pub fn extend(file: &File, pages: u32, allocate: bool) -> std::io::Result<()> {
+    let len = file.metadata()?.size();
+    let new_len = pages as u64 * PAGE_SIZE as u64;
+    if new_len <= len {
+        return Ok(());
+    }
+
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            if allocate && crate::sys::linux::falloc_extend(file, len, new_len - len).is_ok() {
+                return Ok(());
+            }
+        } else {
+            let _ = allocate;
+        }
+    }
+    file.set_len(new_len)
+}
+
 struct Finish {
     sync: StoreSyncGuard,
     allocations: usize,
@@ -281,20 +396,23 @@ struct Finish {
 /// This does not actually perform any writes, except to alter the length of the store file.
 pub struct SyncFinisher {
     file: Arc<File>,
+    growth: GrowthPolicy,
+    discard_freed: bool,
     sync_finish: Receiver<Finish>,
 }
 
 impl SyncFinisher {
     /// Finish the sync, updating the store metadata.
     ///
-    /// Provide a vector of all freed pages.
+    /// Provide a vector of all freed pages. The pages waiting to be reclaimed are freed along with
+    /// them.
     /// This produces a final set of pages to write to update the embedded free-list in the store.
     ///
     /// This returns an error only if the file could not be extended.
     pub fn finish(
         self,
         page_pool: &PagePool,
-        freed: Vec<PageNumber>,
+        mut freed: Vec<PageNumber>,
     ) -> std::io::Result<(Vec<(PageNumber, FatPage)>, StoreMeta)> {
         // Block on `sync_finish`.
         // UNWRAP: `SyncAllocator` sends the guard when dropped. We assume it is not leaked.
@@ -308,12 +426,16 @@ impl SyncFinisher {
 
         // remaining allocations all logically incremented bump.
         let mut next_bump = PageNumber(sync.bump.0 + bumps as u32);
+        freed.extend(std::mem::take(&mut sync.to_reclaim));
+        if self.discard_freed {
+            sync.to_discard = freed.clone();
+        }
         let freelist_pages = sync.free_list.commit(page_pool, freed, &mut next_bump);
 
         // writing the free-list pages might require more bumps, which may require growing the file
         // further.
         if next_bump.0 > max_bump.0 {
-            max_bump = grow(&self.file, next_bump)?;
+            max_bump = grow(&self.file, next_bump, self.growth)?;
         }
 
         sync.bump = next_bump;
@@ -0,0 +1,139 @@
+//! Accounting of the leaf store and collection of its orphaned pages.
+//!
+//! The btree is updated copy-on-write: the leaf and overflow pages holding overwritten or deleted
+//! values are freed by the sync which replaces them and reused by later syncs. A page which is
+//! neither reachable from the btree nor tracked by the free-list is orphaned, and its space is lost
+//! until it is reclaimed. Orphaned pages are found by marking every page reachable from the branch
+//! index and sweeping the pages allocated below the bump.
+
+use anyhow::bail;
+use bitvec::prelude::*;
+
+use crate::io::PAGE_SIZE;
+
+use super::{
+    allocator::{PageNumber, Store, StoreReader},
+    index::Index,
+    leaf::node::LeafNode,
+    ops::overflow,
+};
+
+/// Statistics about the space used by the value store. See [`crate::Nomt::collect_value_garbage`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ValueStoreStats {
+    /// The number of stored values.
+    pub values: u64,
+    /// The number of stored values too large to fit in a leaf, which are kept in overflow pages.
+    pub overflow_values: u64,
+    /// The total size of the stored values, in bytes.
+    pub value_bytes: u64,
+    /// The number of pages ever allocated, not counting the reserved first page.
+    pub allocated_pages: u64,
+    /// The number of leaf pages.
+    pub leaf_pages: u64,
+    /// The number of overflow pages.
+    pub overflow_pages: u64,
+    /// The number of free pages, which held values since overwritten or deleted and are reused by
+    /// later commits.
+    pub free_pages: u64,
+    /// The number of pages storing the free-list.
+    pub free_list_pages: u64,
+    /// The number of orphaned pages, which are neither reachable nor free.
+    pub orphaned_pages: u64,
+    /// The number of orphaned pages returned to the free-list by the next commit.
+    pub reclaiming_pages: u64,
+}
+
+impl ValueStoreStats {
+    /// The number of bytes of free and orphaned pages, which hold no stored value.
+    pub fn reclaimable_bytes(&self) -> u64 {
+        (self.free_pages + self.orphaned_pages) * PAGE_SIZE as u64
+    }
+
+    /// The number of bytes of orphaned pages.
+    pub fn orphaned_bytes(&self) -> u64 {
+        self.orphaned_pages * PAGE_SIZE as u64
+    }
+}
+
+/// Mark the pages reachable from the index and sweep the store for orphaned pages. If `reclaim`
+/// is true, the orphaned pages are handed to the store to be freed by the next sync.
+///
+/// No sync may run concurrently, and the index must be the one of the last sync.
+pub fn collect(
+    bbn_index: &Index,
+    leaf_store: &Store,
+    leaf_reader: &StoreReader,
+    reclaim: bool,
+) -> anyhow::Result<ValueStoreStats> {
+    let (bump, free, reclaiming) = leaf_store.occupancy();
+    let tracked = leaf_store.all_tracked_freelist_pages();
+    let mut stats = ValueStoreStats {
+        allocated_pages: bump.0.saturating_sub(1) as u64,
+        free_pages: free.len() as u64,
+        free_list_pages: (tracked.len() - free.len()) as u64,
+        ..ValueStoreStats::default()
+    };
+
+    let mut marked = bitvec![0; bump.0 as usize];
+    let mut mark = |pn: PageNumber| -> anyhow::Result<()> {
+        if pn.is_nil() || pn.0 >= bump.0 {
+            bail!("leaf store page {} is reachable but not allocated", pn.0);
+        }
+        if marked.replace(pn.0 as usize, true) {
+            bail!("leaf store page {} is reachable twice", pn.0);
+        }
+        if tracked.contains(&pn) || reclaiming.contains(&pn) {
+            bail!("leaf store page {} is reachable but free", pn.0);
+        }
+        Ok(())
+    };
+
+    let mut overflow_pages = Vec::new();
+    for (_, branch) in bbn_index.clone().into_iter() {
+        for i in 0..branch.n() as usize {
+            let leaf_pn = PageNumber(branch.node_pointer(i));
+            mark(leaf_pn)?;
+            stats.leaf_pages += 1;
+
+            let leaf = LeafNode {
+                inner: leaf_reader.query(leaf_pn),
+            };
+            for j in 0..leaf.n() {
+                stats.values += 1;
+                let (value, is_overflow) = leaf.value(j);
+                if !is_overflow {
+                    stats.value_bytes += value.len() as u64;
+                    continue;
+                }
+
+                let (value_size, _, _) = overflow::decode_cell(value);
+                stats.overflow_values += 1;
+                stats.value_bytes += value_size as u64;
+
+                overflow_pages.clear();
+                overflow::pages(value, leaf_reader, &mut overflow_pages);
+                for &pn in &overflow_pages {
+                    mark(pn)?;
+                }
+                stats.overflow_pages += overflow_pages.len() as u64;
+            }
+        }
+    }
+
+    // everything allocated is either reachable, free, storing the free-list or orphaned.
+    let orphaned = (1..bump.0)
+        .map(PageNumber)
+        .filter(|pn| !marked[pn.0 as usize] && !tracked.contains(pn))
+        .collect::<Vec<_>>();
+    stats.orphaned_pages = orphaned.len() as u64;
+
+    if reclaim {
+        leaf_store.reclaim(orphaned);
+        stats.reclaiming_pages = stats.orphaned_pages;
+    } else {
+        stats.reclaiming_pages = reclaiming.len() as u64;
+    }
+
+    Ok(stats)
+}
@@ -43,7 +43,7 @@ impl Index {
         self.first_key_map.insert(separator, branch)
     }
 
-    #[cfg(test)]
+    /// Iterate over all branches, in the order of their separators.
     pub fn into_iter(self) -> impl Iterator<Item = (Key, Arc<BranchNode>)> {
         self.first_key_map.into_iter()
     }
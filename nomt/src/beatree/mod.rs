@@ -5,21 +5,23 @@ use crossbeam_channel::{Receiver, Sender};
 use imbl::OrdMap;
 
 use leaf::node::MAX_LEAF_VALUE_SIZE;
+pub use leaf::node::MAX_OVERFLOW_VALUE_SIZE as MAX_VALUE_SIZE;
 use nomt_core::trie::ValueHash;
 use ops::overflow;
 use parking_lot::{ArcMutexGuard, Condvar, Mutex, RwLock};
-use std::{fs::File, mem, path::Path, sync::Arc};
+use std::{collections::HashMap, fs::File, mem, path::Path, sync::Arc};
 use threadpool::ThreadPool;
 
 use crate::{
     io::{fsyncer::Fsyncer, FatPage, IoHandle, IoPool, PagePool},
-    task::{join_task, spawn_task, TaskResult},
+    task::{join_fallible_task, join_task, spawn_task, TaskResult},
 };
 
 pub mod iterator;
 
 mod allocator;
 mod branch;
+mod gc;
 mod index;
 mod leaf;
 mod leaf_cache;
@@ -27,7 +29,8 @@ mod ops;
 
 mod writeout;
 
-pub use allocator::PageNumber;
+pub use allocator::{GrowthPolicy, PageNumber};
+pub use gc::ValueStoreStats;
 use index::Index;
 pub use iterator::BeatreeIterator;
 use leaf_cache::LeafCache;
@@ -40,6 +43,18 @@ pub use ops::bit_ops::*;
 
 pub type Key = [u8; 32];
 
+// The maximum number of leaf loads in flight during `ReadTransaction::lookup_many`.
+const MAX_LOOKUP_MANY_INFLIGHT: usize = 256;
+
+/// The leaves accessed by a lookup.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LeafAccesses {
+    /// The leaves found in the leaf cache.
+    pub cached: usize,
+    /// The leaves read from disk.
+    pub fetched: usize,
+}
+
 #[derive(Clone)]
 pub struct Tree {
     read_transaction_counter: ReadTransactionCounter,
@@ -91,6 +106,9 @@ impl Tree {
         ln_file: Arc<File>,
         commit_concurrency: usize,
         leaf_cache_size: usize,
+        growth: GrowthPolicy,
+        ln_preallocate_pages: u32,
+        discard_freed: bool,
     ) -> Result<Tree> {
         let ln_freelist_pn = Some(ln_freelist_pn)
             .map(PageNumber)
@@ -102,9 +120,26 @@ impl Tree {
         let ln_bump = PageNumber(ln_bump);
         let bbn_bump = PageNumber(bbn_bump);
 
-        let leaf_store = Store::open(&page_pool, ln_file.clone(), ln_bump, ln_freelist_pn)?;
+        // the leaf store holds almost all of the data, so it alone is preallocated.
+        allocator::extend(&ln_file, ln_preallocate_pages, growth.allocate)?;
 
-        let bbn_store = Store::open(&page_pool, bbn_file.clone(), bbn_bump, bbn_freelist_pn)?;
+        let leaf_store = Store::open(
+            &page_pool,
+            ln_file.clone(),
+            ln_bump,
+            ln_freelist_pn,
+            growth,
+            discard_freed,
+        )?;
+
+        let bbn_store = Store::open(
+            &page_pool,
+            bbn_file.clone(),
+            bbn_bump,
+            bbn_freelist_pn,
+            growth,
+            discard_freed,
+        )?;
 
         let bbn_freelist_tracked = bbn_store.all_tracked_freelist_pages();
         let index = ops::reconstruct(
@@ -142,17 +177,19 @@ impl Tree {
     }
 
     /// Lookup a key in the btree. This blocks the current thread.
-    pub fn lookup(&self, key: Key) -> Option<Vec<u8>> {
+    ///
+    /// Also returns the leaves accessed by the lookup.
+    pub fn lookup(&self, key: Key) -> (Option<Vec<u8>>, LeafAccesses) {
         let shared = self.shared.read();
 
         // First look up in the primary staging which contains the most recent changes.
         if let Some(val) = shared.primary_staging.get(&key) {
-            return val.as_option().map(|v| v.to_vec());
+            return (val.as_option().map(|v| v.to_vec()), LeafAccesses::default());
         }
 
         // Then check the secondary staging which is a bit older, but fresher still than the btree.
         if let Some(val) = shared.secondary_staging.as_ref().and_then(|x| x.get(&key)) {
-            return val.as_option().map(|v| v.to_vec());
+            return (val.as_option().map(|v| v.to_vec()), LeafAccesses::default());
         }
 
         // Finally, look up in the btree.
@@ -165,6 +202,27 @@ impl Tree {
         .unwrap()
     }
 
+    /// Lookup a key in the btree without reading the pages of an overflow value, whose hash is
+    /// stored in its leaf. This blocks the current thread.
+    pub fn lookup_shallow(&self, key: Key) -> (Option<ShallowValue>, LeafAccesses) {
+        let shared = self.shared.read();
+
+        if let Some(val) = shared.primary_staging.get(&key) {
+            return (val.to_shallow(), LeafAccesses::default());
+        }
+
+        if let Some(val) = shared.secondary_staging.as_ref().and_then(|x| x.get(&key)) {
+            return (val.to_shallow(), LeafAccesses::default());
+        }
+
+        ops::lookup_shallow_blocking(
+            key,
+            &shared.bbn_index,
+            &shared.leaf_cache,
+            &shared.leaf_store_rd,
+        )
+    }
+
     /// Returns a controller for the sync process. This is blocked by other `sync`s running as well
     /// as the existence of any read transactions.
     pub fn sync(&self) -> SyncController {
@@ -187,6 +245,25 @@ impl Tree {
         }
     }
 
+    /// Scan the leaf store for orphaned pages, which are neither reachable nor free, and report how
+    /// its space is used. If `reclaim` is true, the orphaned pages are freed by the next sync.
+    ///
+    /// This reads every leaf. It blocks until an ongoing sync is done and blocks new syncs until it
+    /// is done itself.
+    pub fn collect_garbage(&self, reclaim: bool) -> Result<ValueStoreStats> {
+        // holding the sync lock keeps the index consistent with the store.
+        let _sync = self.sync.lock();
+        let (bbn_index, leaf_store, leaf_reader) = {
+            let shared = self.shared.read();
+            (
+                shared.bbn_index.clone(),
+                shared.leaf_store.clone(),
+                shared.leaf_store_rd.clone(),
+            )
+        };
+        gc::collect(&bbn_index, &leaf_store, &leaf_reader, reclaim)
+    }
+
     /// Initiate a new read transaction, as-of the current state of the last commit.
     /// This blocks new sync operations from starting until it is dropped.
     pub fn read_transaction(&self) -> ReadTransaction {
@@ -366,6 +443,23 @@ impl ValueChange {
             ValueChange::Insert(ref v) | ValueChange::InsertOverflow(ref v, _) => Some(&v[..]),
         }
     }
+
+    fn to_shallow(&self) -> Option<ShallowValue> {
+        match self {
+            ValueChange::Delete => None,
+            ValueChange::Insert(ref v) => Some(ShallowValue::Inline(v.clone())),
+            ValueChange::InsertOverflow(_, value_hash) => Some(ShallowValue::Overflow(*value_hash)),
+        }
+    }
+}
+
+/// A value looked up without reading overflow pages. See [`Tree::lookup_shallow`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShallowValue {
+    /// A value small enough to be stored in its leaf.
+    Inline(Vec<u8>),
+    /// The hash of a value stored in overflow pages.
+    Overflow(ValueHash),
 }
 
 /// Data generated during update
@@ -472,7 +566,7 @@ impl SyncController {
     ///
     /// This must be called after [`Self::begin_sync`].
     pub fn wait_pre_meta(&mut self) -> std::io::Result<SyncData> {
-        join_task(&self.begin_sync_result_rx)?;
+        join_fallible_task(&self.begin_sync_result_rx)?;
         self.inner.sync.bbn_fsync.wait()?;
         self.inner.sync.ln_fsync.wait()?;
 
@@ -532,6 +626,131 @@ impl ReadTransaction {
         )
     }
 
+    /// Read the full value referenced by an overflow cell produced by an iterator created from
+    /// this read transaction. This blocks the current thread.
+    pub fn read_overflow(&self, cell: &[u8]) -> Vec<u8> {
+        ops::overflow::read_blocking(cell, &self.inner.leaf_store)
+    }
+
+    /// Open a reader over the value stored under the key. This blocks the current thread.
+    ///
+    /// Overflow values are read one page at a time as the reader is consumed, instead of being
+    /// loaded into memory at once. The reader keeps this read transaction alive.
+    pub fn value_reader(&self, key: Key) -> Option<ValueReader> {
+        let staged = self.inner.primary_staging.get(&key).or_else(|| {
+            self.inner
+                .secondary_staging
+                .as_ref()
+                .and_then(|x| x.get(&key))
+        });
+        if let Some(val) = staged {
+            return val.as_option().map(|v| ValueReader::from_value(v.to_vec()));
+        }
+
+        let leaf_pn = ops::partial_lookup(key, &self.inner.bbn_index)?;
+        let leaf = match self.inner.leaf_cache.get(leaf_pn) {
+            Some(leaf) => leaf,
+            None => {
+                let leaf = Arc::new(leaf::node::LeafNode {
+                    inner: self.inner.leaf_store.query(leaf_pn),
+                });
+                self.inner.leaf_cache.insert(leaf_pn, leaf.clone());
+                leaf
+            }
+        };
+
+        let (value, is_overflow) = leaf.get(&key)?;
+        if !is_overflow {
+            return Some(ValueReader::from_value(value.to_vec()));
+        }
+
+        let reader = overflow::StreamReader::new(value, self.inner.leaf_store.clone());
+        Some(ValueReader {
+            len: reader.value_size(),
+            inner: ValueReaderInner::Overflow(reader),
+            _read_tx: Some(self.clone()),
+        })
+    }
+
+    /// Look up many keys at once. This blocks the current thread.
+    ///
+    /// The leaves holding the keys are loaded concurrently along the handle, and every leaf is
+    /// loaded only once no matter how many of the keys it holds. Overflow values are read with
+    /// blocking I/O once their leaf has been loaded.
+    ///
+    /// The handle must not be used for anything else while this is running. Also returns the
+    /// leaves accessed by the lookups.
+    pub fn lookup_many(
+        &self,
+        keys: &[Key],
+        io_handle: &IoHandle,
+    ) -> std::io::Result<(Vec<Option<Vec<u8>>>, LeafAccesses)> {
+        let mut values = vec![None; keys.len()];
+        let mut accesses = LeafAccesses::default();
+
+        // Group the keys by the leaf which might hold them.
+        let mut by_leaf: HashMap<PageNumber, Vec<usize>> = HashMap::new();
+        for (i, key) in keys.iter().enumerate() {
+            let staged = self.inner.primary_staging.get(key).or_else(|| {
+                self.inner
+                    .secondary_staging
+                    .as_ref()
+                    .and_then(|x| x.get(key))
+            });
+            if let Some(val) = staged {
+                values[i] = val.as_option().map(|v| v.to_vec());
+                continue;
+            }
+
+            if let Some(leaf_pn) = ops::partial_lookup(*key, &self.inner.bbn_index) {
+                by_leaf.entry(leaf_pn).or_default().push(i);
+            }
+        }
+
+        let finish = |values: &mut Vec<Option<Vec<u8>>>, leaf: &leaf::node::LeafNode, waiting| {
+            for i in waiting {
+                values[i] = ops::finish_lookup_blocking(keys[i], leaf, &self.inner.leaf_store);
+            }
+        };
+
+        let mut to_load = by_leaf.into_iter();
+        let mut in_flight: HashMap<u64, (AsyncLeafLoad, Vec<usize>)> = HashMap::new();
+        let mut next_user_data = 0;
+        loop {
+            while in_flight.len() < MAX_LOOKUP_MANY_INFLIGHT {
+                let Some((leaf_pn, waiting)) = to_load.next() else {
+                    break;
+                };
+                match self.load_leaf_async(leaf_pn, io_handle, next_user_data) {
+                    Ok(leaf) => {
+                        accesses.cached += 1;
+                        finish(&mut values, &leaf.inner, waiting)
+                    }
+                    Err(leaf_load) => {
+                        accesses.fetched += 1;
+                        in_flight.insert(next_user_data, (leaf_load, waiting));
+                        next_user_data += 1;
+                    }
+                }
+            }
+
+            if in_flight.is_empty() {
+                break;
+            }
+
+            // UNWRAP: I/O pool is not expected to hangup.
+            let complete_io = io_handle.recv().unwrap();
+            complete_io.result?;
+            // UNWRAP: completions only arrive for the loads submitted above.
+            let (leaf_load, waiting) = in_flight.remove(&complete_io.command.user_data).unwrap();
+            // UNWRAP: the I/O command submitted by `load_leaf_async` is always a `Read`.
+            let leaf = leaf_load.finish_inner(complete_io.command.kind.unwrap_buf());
+            finish(&mut values, &leaf, waiting);
+        }
+
+        Ok((values, accesses))
+    }
+
     /// Initiate an asynchronous leaf page fetch. This may return immediately if the leaf is cached.
     ///
     /// This is an error-prone, low-level API you should not use unless you know what you are doing.
@@ -618,6 +837,52 @@ impl Drop for ReadTransactionInner {
     }
 }
 
+/// A reader over a single value, created with [`ReadTransaction::value_reader`].
+///
+/// This implements [`std::io::Read`]. Large values are read from disk as they are consumed, while
+/// small values are held in memory.
+pub struct ValueReader {
+    inner: ValueReaderInner,
+    len: usize,
+    // keeps the pages of an overflow value from being reused while they are read.
+    _read_tx: Option<ReadTransaction>,
+}
+
+enum ValueReaderInner {
+    InMemory(std::io::Cursor<Vec<u8>>),
+    Overflow(overflow::StreamReader),
+}
+
+impl ValueReader {
+    /// Create a reader over a value held in memory.
+    pub fn from_value(value: Vec<u8>) -> Self {
+        ValueReader {
+            len: value.len(),
+            inner: ValueReaderInner::InMemory(std::io::Cursor::new(value)),
+            _read_tx: None,
+        }
+    }
+
+    /// The total size of the value in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the value is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl std::io::Read for ValueReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.inner {
+            ValueReaderInner::InMemory(ref mut cursor) => cursor.read(buf),
+            ValueReaderInner::Overflow(ref mut reader) => reader.read(buf),
+        }
+    }
+}
+
 /// A type representing a pending leaf load. This keeps the associated read transaction alive
 /// throughout its lifetime.
 pub struct AsyncLeafLoad {
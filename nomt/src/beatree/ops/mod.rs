@@ -11,7 +11,7 @@ use super::{
     index::Index,
     leaf::node::LeafNode,
     leaf_cache::LeafCache,
-    Key,
+    Key, LeafAccesses, ShallowValue,
 };
 
 pub(crate) mod bit_ops;
@@ -71,30 +71,70 @@ pub fn finish_lookup_async(
         .transpose()
 }
 
-/// Lookup a key in the btree using blocking I/O.
+/// Lookup a key in the btree using blocking I/O. Also returns the leaves accessed by the lookup.
 pub fn lookup_blocking(
     key: Key,
     bbn_index: &Index,
     leaf_cache: &LeafCache,
     leaf_store: &StoreReader,
-) -> Result<Option<Vec<u8>>> {
+) -> Result<(Option<Vec<u8>>, LeafAccesses)> {
+    let mut accesses = LeafAccesses::default();
     let leaf_pn = match partial_lookup(key, bbn_index) {
-        None => return Ok(None),
+        None => return Ok((None, accesses)),
         Some(pn) => pn,
     };
 
-    let leaf = match leaf_cache.get(leaf_pn) {
-        Some(leaf) => leaf,
+    let leaf = fetch_leaf_blocking(leaf_pn, leaf_cache, leaf_store, &mut accesses);
+    Ok((finish_lookup_blocking(key, &leaf, leaf_store), accesses))
+}
+
+/// Lookup a key in the btree using blocking I/O, without reading the pages of an overflow value.
+/// Also returns the leaves accessed by the lookup.
+pub fn lookup_shallow_blocking(
+    key: Key,
+    bbn_index: &Index,
+    leaf_cache: &LeafCache,
+    leaf_store: &StoreReader,
+) -> (Option<ShallowValue>, LeafAccesses) {
+    let mut accesses = LeafAccesses::default();
+    let leaf_pn = match partial_lookup(key, bbn_index) {
+        None => return (None, accesses),
+        Some(pn) => pn,
+    };
+
+    let leaf = fetch_leaf_blocking(leaf_pn, leaf_cache, leaf_store, &mut accesses);
+    let value = leaf.get(&key).map(|(v, is_overflow)| {
+        if is_overflow {
+            let (_, value_hash, _) = overflow::decode_cell(v);
+            ShallowValue::Overflow(value_hash)
+        } else {
+            ShallowValue::Inline(v.to_vec())
+        }
+    });
+    (value, accesses)
+}
+
+// Get a leaf from the cache, or read it with blocking I/O and cache it.
+fn fetch_leaf_blocking(
+    leaf_pn: PageNumber,
+    leaf_cache: &LeafCache,
+    leaf_store: &StoreReader,
+    accesses: &mut LeafAccesses,
+) -> Arc<LeafNode> {
+    match leaf_cache.get(leaf_pn) {
+        Some(leaf) => {
+            accesses.cached += 1;
+            leaf
+        }
         None => {
+            accesses.fetched += 1;
             let leaf = Arc::new(LeafNode {
                 inner: leaf_store.query(leaf_pn),
             });
             leaf_cache.insert(leaf_pn, leaf.clone());
             leaf
         }
-    };
-
-    Ok(finish_lookup_blocking(key, &leaf, leaf_store))
+    }
 }
 
 /// Binary search a branch node for the child node containing the key. This returns the last child
@@ -200,6 +200,71 @@ pub fn read_blocking(cell: &[u8], leaf_reader: &StoreReader) -> Vec<u8> {
     value
 }
 
+/// A blocking reader which reads a large value one page at a time, instead of all at once.
+pub struct StreamReader {
+    page_numbers: Vec<PageNumber>,
+    total_pages: usize,
+    // the index of the next page to read.
+    next_page: usize,
+    // the page being read, along with the current and end positions of its value bytes.
+    page: Option<(FatPage, usize, usize)>,
+    store_reader: StoreReader,
+    value_size: usize,
+}
+
+impl StreamReader {
+    /// Create a new stream reader.
+    pub fn new(cell: &[u8], store_reader: StoreReader) -> Self {
+        let (value_size, _, cell_pages) = decode_cell(cell);
+        let total_pages = total_needed_pages(value_size);
+
+        let mut page_numbers = Vec::with_capacity(total_pages);
+        page_numbers.extend(cell_pages);
+
+        StreamReader {
+            page_numbers,
+            total_pages,
+            next_page: 0,
+            page: None,
+            store_reader,
+            value_size,
+        }
+    }
+
+    /// The size of the value.
+    pub fn value_size(&self) -> usize {
+        self.value_size
+    }
+}
+
+impl std::io::Read for StreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if let Some((ref page, ref mut pos, end)) = self.page {
+                if *pos < end {
+                    let n = std::cmp::min(buf.len(), end - *pos);
+                    buf[..n].copy_from_slice(&page[*pos..*pos + n]);
+                    *pos += n;
+                    return Ok(n);
+                }
+            }
+
+            if self.next_page == self.total_pages {
+                return Ok(0);
+            }
+
+            let page = self.store_reader.query(self.page_numbers[self.next_page]);
+            self.next_page += 1;
+            let (page_pns, bytes) = parse_page(&page);
+            let n_bytes = bytes.len();
+            let before = self.page_numbers.len();
+            self.page_numbers.extend(page_pns);
+            let start = HEADER_SIZE + (self.page_numbers.len() - before) * 4;
+            self.page = Some((page, start, start + n_bytes));
+        }
+    }
+}
+
 /// A non-blocking reader for an overflow value.
 pub struct AsyncReader {
     value: Vec<u8>,
@@ -302,25 +367,34 @@ impl AsyncReader {
 ///
 /// This only logically deletes the pages.
 pub fn delete(cell: &[u8], leaf_reader: &StoreReader, freed: &mut Vec<PageNumber>) {
+    pages(cell, leaf_reader, freed)
+}
+
+/// Append the numbers of all the pages of an overflow value to `pns`. Reads the pages holding
+/// page numbers which don't fit in the cell.
+pub fn pages(cell: &[u8], leaf_reader: &StoreReader, pns: &mut Vec<PageNumber>) {
     let (value_size, _, cell_pages) = decode_cell(cell);
     let total_pages = total_needed_pages(value_size);
 
-    let start = freed.len();
-    freed.extend(cell_pages);
+    let start = pns.len();
+    pns.extend(cell_pages);
 
-    for i in 0..total_pages {
-        let page = leaf_reader.query(freed[start + i]);
-        let (page_pns, bytes) = parse_page(&page);
-        freed.extend(page_pns);
+    // pages beyond the cell are only referenced when the cell is full.
+    if total_pages > MAX_OVERFLOW_CELL_NODE_POINTERS {
+        for i in 0..total_pages {
+            let page = leaf_reader.query(pns[start + i]);
+            let (page_pns, bytes) = parse_page(&page);
+            pns.extend(page_pns);
 
-        // stop at the first page containing value data. no more pages will have more
-        // page numbers to release.
-        if bytes.len() > 0 {
-            break;
+            // stop at the first page containing value data. no more pages will have more
+            // page numbers.
+            if !bytes.is_empty() {
+                break;
+            }
         }
     }
 
-    assert_eq!(freed.len() - start, total_pages);
+    assert_eq!(pns.len() - start, total_pages);
 }
 
 fn parse_page<'a>(page: &'a FatPage) -> (impl Iterator<Item = PageNumber> + 'a, &'a [u8]) {
@@ -10,7 +10,7 @@ use crate::{
         index::Index,
         Key,
     },
-    task::{join_task, spawn_task},
+    task::{join_fallible_task, spawn_task},
 };
 
 use super::branch_updater::{BaseBranch, BranchUpdater, DigestResult as BranchDigestResult};
@@ -96,7 +96,7 @@ pub fn run(
     let mut output = BranchStageOutput::default();
 
     for _ in 0..num_workers {
-        let worker_output = join_task(&worker_result_rx)?;
+        let worker_output = join_fallible_task(&worker_result_rx)?;
         apply_bbn_changes(bbn_index, &mut output, worker_output);
     }
 
@@ -23,7 +23,7 @@ use crate::beatree::{
     Key, ValueChange,
 };
 use crate::io::{IoCommand, IoHandle, IoKind};
-use crate::task::{join_task, spawn_task};
+use crate::task::{join_fallible_task, spawn_task};
 
 /// Tracker of all changes that happen to leaves during an update
 pub type LeavesTracker = super::NodesTracker<LeafNode>;
@@ -172,7 +172,7 @@ pub fn run(
     output.submitted_io += overflow_io;
 
     for _ in 0..num_workers {
-        let worker_output = join_task(&worker_result_rx)?;
+        let worker_output = join_fallible_task(&worker_result_rx)?;
         apply_worker_changes(&leaf_reader, &mut output, worker_output);
     }
 
@@ -1,6 +1,6 @@
 use crate::{
     beatree::{
-        allocator::{PageNumber, Store, StoreReader},
+        allocator::{GrowthPolicy, PageNumber, Store, StoreReader},
         branch::{self, node::BranchNode, BRANCH_NODE_BODY_SIZE, BRANCH_NODE_SIZE},
         leaf::{
             self,
@@ -92,6 +92,8 @@ impl TreeData {
             self.ln_fd.clone(),
             PageNumber(self.ln_bump),
             Some(PageNumber(self.ln_freelist_pn)),
+            GrowthPolicy::default(),
+            false,
         )
         .unwrap()
     }
@@ -122,9 +124,25 @@ fn init_beatree() -> TreeData {
         .map(|key| (key, vec![170u8; rng.gen_range(500..MAX_LEAF_VALUE_SIZE)]))
         .collect();
 
-    let leaf_store = Store::open(&PAGE_POOL, ln_fd.clone(), PageNumber(1), None).unwrap();
+    let leaf_store = Store::open(
+        &PAGE_POOL,
+        ln_fd.clone(),
+        PageNumber(1),
+        None,
+        GrowthPolicy::default(),
+        false,
+    )
+    .unwrap();
 
-    let bbn_store = Store::open(&PAGE_POOL, bbn_fd.clone(), PageNumber(1), None).unwrap();
+    let bbn_store = Store::open(
+        &PAGE_POOL,
+        bbn_fd.clone(),
+        PageNumber(1),
+        None,
+        GrowthPolicy::default(),
+        false,
+    )
+    .unwrap();
 
     let (sync_data, bbn_index, _) = super::update(
         initial_items
@@ -525,6 +543,8 @@ fn branch_stage_inner(input: StageInputs) -> TestResult {
         bbn_fd.clone(),
         PageNumber(SEPARATORS.len() as u32),
         None,
+        GrowthPolicy::default(),
+        false,
     )
     .unwrap();
 
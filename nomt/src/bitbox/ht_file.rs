@@ -1,6 +1,9 @@
 /// The HT file.
 ///
 /// The file that stores the hash-table buckets and the meta map.
+///
+/// A shadow-paged file holds two copies of the meta map, each followed by the slot bits saying
+/// which of the two pages of every bucket is in use, and then two pages for every bucket.
 use super::meta_map::MetaMap;
 use crate::io::{self, PagePool, PAGE_SIZE};
 use std::{
@@ -14,65 +17,127 @@ pub struct HTOffsets {
     // the number of pages to add to a page number to find its real location in the file,
     // taking account of the meta page and meta byte pages.
     data_page_offset: u64,
+    // the number of pages taken by a copy of the meta bytes and slot bits, if shadow-paged.
+    shadow_copy_pages: Option<u64>,
+    meta_byte_pages: u64,
 }
 
 impl HTOffsets {
-    /// Returns the page number of the `ix`th item in the data section of the store.
-    pub fn data_page_index(&self, ix: u64) -> u64 {
-        self.data_page_offset + ix
+    /// Returns the page number of the `ix`th item in the data section of the store, in the given
+    /// slot. The slot is always 0 unless the store is shadow-paged.
+    pub fn data_page_index(&self, ix: u64, slot: u64) -> u64 {
+        match self.shadow_copy_pages {
+            Some(_) => self.data_page_offset + ix * 2 + slot,
+            None => self.data_page_offset + ix,
+        }
+    }
+
+    /// Returns the page number of the `ix`th item in the meta bytes section of the store, in the
+    /// given copy. The copy is always 0 unless the store is shadow-paged.
+    pub fn meta_bytes_index(&self, ix: u64, copy: u32) -> u64 {
+        self.shadow_copy_pages.unwrap_or(0) * copy as u64 + ix
     }
 
-    /// Returns the page number of the `ix`th item in the meta bytes section of the store.
-    pub fn meta_bytes_index(&self, ix: u64) -> u64 {
-        ix
+    /// Returns the page number of the `ix`th item in the slot bits section of the given copy.
+    ///
+    /// Only shadow-paged stores have slot bits.
+    pub fn slot_bits_index(&self, ix: u64, copy: u32) -> u64 {
+        self.meta_bytes_index(self.meta_byte_pages + ix, copy)
     }
 }
 
-fn expected_file_len(num_pages: u32) -> u64 {
-    (num_meta_byte_pages(num_pages) + num_pages) as u64 * PAGE_SIZE as u64
+fn expected_file_len(num_pages: u32, shadow_paging: bool) -> u64 {
+    let pages = if shadow_paging {
+        2 * (num_meta_byte_pages(num_pages) + num_slot_bit_pages(num_pages) + num_pages)
+    } else {
+        num_meta_byte_pages(num_pages) + num_pages
+    };
+    pages as u64 * PAGE_SIZE as u64
 }
 
 fn num_meta_byte_pages(num_pages: u32) -> u32 {
     (num_pages + 4095) / PAGE_SIZE as u32
 }
 
+fn num_slot_bit_pages(num_pages: u32) -> u32 {
+    num_pages.div_ceil(PAGE_SIZE as u32 * 8)
+}
+
 /// Opens the HT file, checks its length and reads the meta map.
+///
+/// A shadow-paged file is given the copy of the meta map to read.
 pub fn open(
     num_pages: u32,
+    shadow_copy: Option<u32>,
     page_pool: &PagePool,
     ht_fd: &File,
 ) -> anyhow::Result<(HTOffsets, MetaMap)> {
-    if ht_fd.metadata()?.len() != expected_file_len(num_pages) {
+    if ht_fd.metadata()?.len() != expected_file_len(num_pages, shadow_copy.is_some()) {
         anyhow::bail!("Store corrupted; unexpected file length");
     }
 
     let num_meta_byte_pages = num_meta_byte_pages(num_pages);
-    let mut meta_bytes = Vec::with_capacity(num_meta_byte_pages as usize * PAGE_SIZE);
-    for pn in 0..num_meta_byte_pages {
-        let extra_meta_page = io::read_page(page_pool, ht_fd, pn as u64)?;
-        meta_bytes.extend_from_slice(&*extra_meta_page);
+    let num_slot_bit_pages = num_slot_bit_pages(num_pages);
+    let shadow_copy_pages = shadow_copy.map(|_| (num_meta_byte_pages + num_slot_bit_pages) as u64);
+    let offsets = HTOffsets {
+        data_page_offset: match shadow_copy_pages {
+            Some(copy_pages) => copy_pages * 2,
+            None => num_meta_byte_pages as u64,
+        },
+        shadow_copy_pages,
+        meta_byte_pages: num_meta_byte_pages as u64,
+    };
+
+    let copy = shadow_copy.unwrap_or(0);
+    let meta_bytes = read_pages(
+        page_pool,
+        ht_fd,
+        (0..num_meta_byte_pages as u64).map(|ix| offsets.meta_bytes_index(ix, copy)),
+    )?;
+    let mut meta_map = MetaMap::from_bytes(meta_bytes, num_pages as usize);
+    if shadow_copy.is_some() {
+        let slot_bits = read_pages(
+            page_pool,
+            ht_fd,
+            (0..num_slot_bit_pages as u64).map(|ix| offsets.slot_bits_index(ix, copy)),
+        )?;
+        meta_map = meta_map.with_slot_bits(slot_bits);
     }
 
-    let data_page_offset = num_meta_byte_pages as u64;
-    Ok((
-        HTOffsets { data_page_offset },
-        MetaMap::from_bytes(meta_bytes, num_pages as usize),
-    ))
+    Ok((offsets, meta_map))
+}
+
+fn read_pages(
+    page_pool: &PagePool,
+    ht_fd: &File,
+    pns: impl Iterator<Item = u64>,
+) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for pn in pns {
+        let page = io::read_page(page_pool, ht_fd, pn)?;
+        bytes.extend_from_slice(&page);
+    }
+    Ok(bytes)
 }
 
 /// Creates the store file. Fails if store file already exists.
 ///
 /// Lays out the meta page. If `preallocate` is true, preallocates the blocks for the file.
-pub fn create(path: PathBuf, num_pages: u32, preallocate: bool) -> std::io::Result<()> {
+pub fn create(
+    path: PathBuf,
+    num_pages: u32,
+    preallocate: bool,
+    shadow_paging: bool,
+) -> std::io::Result<()> {
     let start = std::time::Instant::now();
     let ht_path = path.join("ht");
     let ht_file = OpenOptions::new().write(true).create(true).open(ht_path)?;
 
-    // number of pages + pages required for meta bits.
-    let page_count = num_pages + num_meta_byte_pages(num_pages);
-    let len = page_count as usize * PAGE_SIZE;
+    // number of pages + pages required for meta bits, twice if shadow-paged.
+    let len = expected_file_len(num_pages, shadow_paging);
+    let page_count = len / PAGE_SIZE as u64;
 
-    resize_and_prealloc(&ht_file, len as u64, preallocate)?;
+    resize_and_prealloc(&ht_file, len, preallocate)?;
 
     ht_file.sync_all()?;
     drop(ht_file);
@@ -4,13 +4,15 @@ const EMPTY: u8 = 0b0000_0000;
 const TOMBSTONE: u8 = 0b0111_1111;
 const FULL_MASK: u8 = 0b1000_0000;
 
-fn full_entry(hash: u64) -> u8 {
+pub fn full_entry(hash: u64) -> u8 {
     (hash >> 57) as u8 ^ FULL_MASK
 }
 
 pub struct MetaMap {
     buckets: usize,
     bitvec: Vec<u8>,
+    // for shadow paging, one bit for each bucket saying which of its two pages is in use.
+    slot_bits: Option<Vec<u8>>,
 }
 
 impl MetaMap {
@@ -20,9 +22,18 @@ impl MetaMap {
         MetaMap {
             buckets,
             bitvec: meta_bytes,
+            slot_bits: None,
         }
     }
 
+    // Attach the slot bits of a shadow-paged hash-table.
+    pub fn with_slot_bits(mut self, slot_bits: Vec<u8>) -> Self {
+        assert_eq!(slot_bits.len() % 4096, 0);
+        assert!(slot_bits.len() * 8 >= self.buckets);
+        self.slot_bits = Some(slot_bits);
+        self
+    }
+
     pub fn full_count(&self) -> usize {
         self.bitvec
             .iter()
@@ -68,4 +79,40 @@ impl MetaMap {
         let end = start + 4096;
         &self.bitvec[start..end]
     }
+
+    // the number of pages of the metamap.
+    pub fn page_count(&self) -> usize {
+        self.bitvec.len() / 4096
+    }
+
+    // the slot of the page in use by a bucket. always 0 unless shadow-paged.
+    pub fn slot(&self, bucket: usize) -> u64 {
+        match self.slot_bits {
+            Some(ref bits) => (bits[bucket / 8] >> (bucket % 8)) as u64 & 1,
+            None => 0,
+        }
+    }
+
+    // switch a bucket of a shadow-paged hash-table to its other slot.
+    pub fn flip_slot(&mut self, bucket: usize) {
+        // UNWRAP: only called when shadow-paged.
+        self.slot_bits.as_mut().unwrap()[bucket / 8] ^= 1 << (bucket % 8);
+    }
+
+    // get the page index of a bucket in the slot bits.
+    pub fn slot_page_index(&self, bucket: usize) -> usize {
+        bucket / (4096 * 8)
+    }
+
+    // get a page-sized slice of the slot bits.
+    pub fn slot_page_slice(&self, page_index: usize) -> &[u8] {
+        // UNWRAP: only called when shadow-paged.
+        let bits = self.slot_bits.as_ref().unwrap();
+        &bits[page_index * 4096..(page_index + 1) * 4096]
+    }
+
+    // the number of pages of the slot bits.
+    pub fn slot_page_count(&self) -> usize {
+        self.slot_bits.as_ref().map_or(0, |bits| bits.len() / 4096)
+    }
 }
@@ -1,4 +1,4 @@
-use crossbeam_channel::{Receiver, Sender};
+use crossbeam_channel::{Receiver, Sender, TrySendError};
 use nomt_core::page_id::PageId;
 use parking_lot::{ArcRwLockReadGuard, Mutex, RwLock};
 use std::{
@@ -16,6 +16,7 @@ use threadpool::ThreadPool;
 use crate::{
     io::{self, page_pool::FatPage, IoCommand, IoHandle, IoKind, PagePool, PAGE_SIZE},
     page_cache::{Page, PageCache},
+    page_header,
     store::{BucketInfo, DirtyPage},
     task::{join_task, spawn_task, TaskResult},
 };
@@ -47,7 +48,7 @@ impl std::error::Error for BucketExhaustion {}
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct BucketIndex(u64);
 
-#[cfg(test)]
+#[cfg(any(test, feature = "benchmarks"))]
 impl BucketIndex {
     pub fn new(index: u64) -> Self {
         BucketIndex(index)
@@ -108,26 +109,41 @@ pub struct Shared {
     ht_fd: File,
     sync_tp: ThreadPool,
     capacity: usize,
+    // for shadow paging, the meta map pages changed by the last sync, which the copy written by
+    // the next sync lacks. `None` before the first sync, which writes out the whole copy.
+    shadow: Option<Mutex<Option<ChangedMetaPages>>>,
+}
+
+// The pages of the meta map changed by a sync.
+struct ChangedMetaPages {
+    meta_bytes: HashSet<usize>,
+    slot_bits: HashSet<usize>,
 }
 
 impl DB {
     /// Opens an existing bitbox database.
+    ///
+    /// A shadow-paged database reads the copy of the meta map written by the given sync and has
+    /// nothing to recover.
     pub fn open(
         sync_seqn: u32,
         num_pages: u32,
         seed: [u8; 16],
+        shadow_paging: bool,
         page_pool: PagePool,
         ht_fd: File,
         wal_fd: File,
     ) -> anyhow::Result<Self> {
-        let (store, mut meta_map) = match ht_file::open(num_pages, &page_pool, &ht_fd) {
+        let shadow_copy = shadow_paging.then_some(sync_seqn % 2);
+        let (store, mut meta_map) = match ht_file::open(num_pages, shadow_copy, &page_pool, &ht_fd)
+        {
             Ok(x) => x,
             Err(e) => {
                 anyhow::bail!("encountered error in opening store: {e:?}");
             }
         };
 
-        if wal_fd.metadata()?.len() > 0 {
+        if !shadow_paging && wal_fd.metadata()?.len() > 0 {
             recover(
                 sync_seqn,
                 &ht_fd,
@@ -155,6 +171,7 @@ impl DB {
                 ht_fd,
                 sync_tp: ThreadPool::with_name("bitbox-sync".into(), 2),
                 capacity,
+                shadow: shadow_paging.then(|| Mutex::new(None)),
             }),
         })
     }
@@ -188,7 +205,12 @@ impl DB {
 
         let mut meta_map = self.shared.meta_map.write();
 
+        // a shadow-paged sync writes the copy of the meta map not read by the last one and
+        // doesn't use the WAL.
+        let shadow_copy = self.shared.shadow.as_ref().map(|_| sync_seqn % 2);
+
         let mut changed_meta_pages = HashSet::new();
+        let mut changed_slot_pages = HashSet::new();
         let mut ht_pages = Vec::new();
         let mut cache_updates = Vec::new();
 
@@ -210,7 +232,9 @@ impl DB {
                 changed_meta_pages.insert(meta_map.page_index(bucket as usize));
                 cache_updates.push((page_id.clone(), None));
 
-                wal_blob_builder.write_clear(bucket);
+                if shadow_copy.is_none() {
+                    wal_blob_builder.write_clear(bucket);
+                }
             } else {
                 // Allocate the bucket, if one is necessary.
                 let (meta_map_changed, bucket) = match dirty_page.bucket {
@@ -243,16 +267,23 @@ impl DB {
                     changed_meta_pages.insert(meta_map.page_index(bucket as usize));
                 }
 
-                wal_blob_builder.write_update(
-                    page_id.encode(),
-                    &dirty_page.diff,
-                    dirty_page
-                        .diff
-                        .pack_changed_nodes(dirty_page.page.page_data()),
-                    bucket,
-                );
+                if shadow_copy.is_some() {
+                    // never overwrite the page in use: write to the other slot.
+                    meta_map.flip_slot(bucket as usize);
+                    changed_slot_pages.insert(meta_map.slot_page_index(bucket as usize));
+                } else {
+                    wal_blob_builder.write_update(
+                        page_id.encode(),
+                        &dirty_page.diff,
+                        dirty_page
+                            .diff
+                            .pack_changed_nodes(dirty_page.page.page_data()),
+                        bucket,
+                    );
+                }
 
-                let pn = self.shared.store.data_page_index(bucket);
+                let slot = meta_map.slot(bucket as usize);
+                let pn = self.shared.store.data_page_index(bucket, slot);
                 cache_updates.push((
                     page_id.clone(),
                     Some((dirty_page.page.clone(), BucketIndex(bucket))),
@@ -261,10 +292,42 @@ impl DB {
             }
         }
 
+        if let Some(ref shadow) = self.shared.shadow {
+            // the copy being written lacks the changes of the last sync as well. Record the
+            // changes of this one for the next.
+            let changed = ChangedMetaPages {
+                meta_bytes: changed_meta_pages.clone(),
+                slot_bits: changed_slot_pages.clone(),
+            };
+            match shadow.lock().replace(changed) {
+                Some(last) => {
+                    changed_meta_pages.extend(last.meta_bytes);
+                    changed_slot_pages.extend(last.slot_bits);
+                }
+                None => {
+                    changed_meta_pages.extend(0..meta_map.page_count());
+                    changed_slot_pages.extend(0..meta_map.slot_page_count());
+                }
+            }
+        }
+
+        let copy = shadow_copy.unwrap_or(0);
         for changed_meta_page in changed_meta_pages {
             let mut buf = page_pool.alloc_fat_page();
             buf[..].copy_from_slice(meta_map.page_slice(changed_meta_page));
-            let pn = self.shared.store.meta_bytes_index(changed_meta_page as u64);
+            let pn = self
+                .shared
+                .store
+                .meta_bytes_index(changed_meta_page as u64, copy);
+            ht_pages.push((pn, Arc::new(buf)));
+        }
+        for changed_slot_page in changed_slot_pages {
+            let mut buf = page_pool.alloc_fat_page();
+            buf[..].copy_from_slice(meta_map.slot_page_slice(changed_slot_page));
+            let pn = self
+                .shared
+                .store
+                .slot_bits_index(changed_slot_page as u64, copy);
             ht_pages.push((pn, Arc::new(buf)));
         }
 
@@ -322,12 +385,15 @@ impl SyncController {
 
     /// Begins the sync process.
     ///
+    /// The I/O handle is used to write out the HT pages of a shadow-paged database.
+    ///
     /// Non-blocking.
     pub fn begin_sync(
         &mut self,
         sync_seqn: u32,
         page_cache: PageCache,
         updated_pages: impl IntoIterator<Item = (PageId, DirtyPage)> + Send + 'static,
+        io_handle: IoHandle,
     ) {
         let page_pool = self.db.shared.page_pool.clone();
         let bitbox = self.db.clone();
@@ -348,9 +414,14 @@ impl SyncController {
             )?;
             drop(wal_blob_builder);
 
-            // Set the hash-table pages before spawning WAL writeout so they don't race with it.
-            *ht_to_write.lock() = Some(ht_pages);
-            Self::spawn_wal_writeout(pre_meta_result_tx, bitbox);
+            if bitbox.shared.shadow.is_some() {
+                // none of the pages is in use by the last sync, so they are written right away.
+                Self::spawn_ht_writeout(pre_meta_result_tx, bitbox, io_handle, ht_pages);
+            } else {
+                // Set the hash-table pages before spawning WAL writeout so they don't race with it.
+                *ht_to_write.lock() = Some(ht_pages);
+                Self::spawn_wal_writeout(pre_meta_result_tx, bitbox);
+            }
 
             // perform cache updates: insert changes and evict old pages.
             // evict and drop old pages outside of the critical path.
@@ -379,9 +450,22 @@ impl SyncController {
         spawn_task(&tp, wal_writeout_task, pre_meta_result_tx);
     }
 
+    fn spawn_ht_writeout(
+        pre_meta_result_tx: Sender<TaskResult<std::io::Result<()>>>,
+        bitbox: DB,
+        io_handle: IoHandle,
+        ht_pages: Vec<(u64, Arc<FatPage>)>,
+    ) {
+        let tp = bitbox.shared.sync_tp.clone();
+        let ht_writeout_task =
+            move || writeout::write_ht(io_handle, &bitbox.shared.ht_fd, ht_pages);
+
+        spawn_task(&tp, ht_writeout_task, pre_meta_result_tx);
+    }
+
     /// Wait for the pre-meta operations to complete.
     ///
-    /// This includes WAL file to be written out.
+    /// This includes WAL file to be written out, or the HT pages if shadow-paged.
     ///
     /// Must be invoked by the sync thread. Blocking.
     pub fn wait_pre_meta(&self) -> anyhow::Result<()> {
@@ -390,11 +474,15 @@ impl SyncController {
         Ok(())
     }
 
-    /// Write out the HT pages and truncate the WAL file.
+    /// Write out the HT pages and truncate the WAL file. Does nothing if shadow-paged, the
+    /// manifest update having switched to the pages already written.
     ///
     /// Has to be called after the manifest is updated. Must be invoked by the sync
     /// thread. Blocking.
     pub fn post_meta(&self, io_handle: IoHandle) -> std::io::Result<()> {
+        if self.db.shared.shadow.is_some() {
+            return Ok(());
+        }
         let ht_pages = self.ht_to_write.lock().take().unwrap();
         // Writeout the HT pages and truncate the WAL file.
         //
@@ -427,7 +515,7 @@ fn recover(
     meta_map: &mut MetaMap,
     seed: [u8; 16],
 ) -> anyhow::Result<()> {
-    use crate::bitbox::wal::WalBlobReader;
+    use crate::{bitbox::wal::WalBlobReader, fatal::FatalError};
     use std::io::{Seek, SeekFrom};
 
     wal_fd.seek(SeekFrom::Start(0))?;
@@ -448,6 +536,16 @@ fn recover(
     let mut changed_meta_page_ixs = HashSet::new();
 
     while let Some(entry) = wal_reader.read_entry()? {
+        let (wal::WalEntry::Clear { bucket } | wal::WalEntry::Update { bucket, .. }) = entry;
+        if bucket >= meta_map.len() as u64 {
+            return Err(FatalError::Corrupted(format!(
+                "WAL entry for bucket {} of {}",
+                bucket,
+                meta_map.len()
+            ))
+            .into());
+        }
+
         match entry {
             wal::WalEntry::Clear { bucket } => {
                 meta_map.set_tombstone(bucket as usize);
@@ -476,7 +574,7 @@ fn recover(
                 // - for each index of a bit in a diff that equals to 1, copy the changed node into
                 //   the page.
                 // - store the changed page.
-                let pn = ht_offsets.data_page_index(bucket);
+                let pn = ht_offsets.data_page_index(bucket, 0);
 
                 let mut page = io::read_page(page_pool, ht_fd, pn)?;
                 if page_diff.count() != changed_nodes.len() {
@@ -490,6 +588,7 @@ fn recover(
 
                 // Label the page.
                 page[PAGE_SIZE - 32..].copy_from_slice(&page_id);
+                page_header::stamp(&mut page);
 
                 ht_fd.write_all_at(&page, pn * PAGE_SIZE as u64)?;
             }
@@ -507,7 +606,7 @@ fn recover(
             let page_data = page.as_mut_slice();
             page_data[..].copy_from_slice(meta_map.page_slice(changed_meta_page_ix));
 
-            let pn = ht_offsets.meta_bytes_index(changed_meta_page_ix as u64);
+            let pn = ht_offsets.meta_bytes_index(changed_meta_page_ix as u64, 0);
             ht_fd.write_all_at(page_data, pn * PAGE_SIZE as u64)?;
 
             page_pool.dealloc(page);
@@ -540,6 +639,7 @@ impl PageLoader {
         PageLoad {
             probe_sequence: ProbeSequence::new(&page_id, &self.meta_map, &self.shared.seed),
             page_id,
+            seed: self.shared.seed,
             state: PageLoadState::Pending,
         }
     }
@@ -558,32 +658,62 @@ impl PageLoader {
     /// [`PageLoad::try_complete`] to verify whether the hash-table probe has completed or must be
     /// tried again.
     pub fn probe(&self, load: &mut PageLoad, io_handle: &IoHandle, user_data: u64) -> bool {
+        let Some(command) = self.next_probe(load, user_data) else {
+            return false;
+        };
+
+        // UNWRAP: I/O pool is not expected to hangup.
+        io_handle.send(command).unwrap();
+        load.state = PageLoadState::Submitted;
+        true
+    }
+
+    /// Like [`Self::probe`], but gives up instead of submitting the read while the I/O pool is
+    /// under backpressure. Returns `None` in that case, and the page load must be discarded.
+    pub fn try_probe(
+        &self,
+        load: &mut PageLoad,
+        io_handle: &IoHandle,
+        user_data: u64,
+    ) -> Option<bool> {
+        let Some(command) = self.next_probe(load, user_data) else {
+            return Some(false);
+        };
+
+        match io_handle.try_submit(command) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => return None,
+            Err(TrySendError::Disconnected(_)) => panic!("I/O pool hung up"),
+        }
+        load.state = PageLoadState::Submitted;
+        Some(true)
+    }
+
+    // The read of the next bucket which may hold the page, or `None` if the page doesn't exist.
+    fn next_probe(&self, load: &mut PageLoad, user_data: u64) -> Option<IoCommand> {
         let bucket = loop {
             match load.probe_sequence.next(&self.meta_map) {
                 ProbeResult::Tombstone(_) => continue,
-                ProbeResult::Empty(_) => return false,
+                ProbeResult::Empty(_) => return None,
                 ProbeResult::PossibleHit(bucket) => break BucketIndex(bucket),
             }
         };
 
-        let data_page_index = self.shared.store.data_page_index(bucket.0);
+        let slot = self.meta_map.slot(bucket.0 as usize);
+        let data_page_index = self.shared.store.data_page_index(bucket.0, slot);
 
         let page = self.shared.page_pool.alloc_fat_page();
-        let command = IoCommand {
+        Some(IoCommand {
             kind: IoKind::Read(self.shared.ht_fd.as_raw_fd(), data_page_index, page),
             user_data,
-        };
-
-        // UNWRAP: I/O pool is not expected to hangup.
-        io_handle.send(command).unwrap();
-        load.state = PageLoadState::Submitted;
-        true
+        })
     }
 }
 
 pub struct PageLoad {
     page_id: PageId,
     probe_sequence: ProbeSequence,
+    seed: [u8; 16],
     state: PageLoadState,
 }
 
@@ -600,15 +730,50 @@ impl PageLoad {
     ///
     /// If this returns `Some`, then the load has completed and this struct may be discarded.
     /// Otherwise, you must continue with [`PageLoader::probe`].
-    pub fn try_complete(&mut self, page: FatPage) -> Option<(FatPage, BucketIndex)> {
+    ///
+    /// Fails if the page was found but its header doesn't check out, or if the bucket holds a page
+    /// which can't be stored there.
+    pub fn try_complete(
+        &mut self,
+        page: FatPage,
+    ) -> std::io::Result<Option<(FatPage, BucketIndex)>> {
         assert!(self.needs_completion());
         if page[PAGE_SIZE - 32..] == self.page_id.encode() {
-            Some((page, BucketIndex(self.probe_sequence.bucket())))
+            page_header::check(&page).map_err(|e| {
+                std::io::Error::new(e.kind(), format!("page {:?}: {}", self.page_id, e))
+            })?;
+            Ok(Some((page, BucketIndex(self.probe_sequence.bucket()))))
+        } else if let Some(misdirected) = self.misdirected(&page) {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "page {:?}: bucket {} holds {}",
+                    self.page_id,
+                    self.probe_sequence.bucket(),
+                    misdirected,
+                ),
+            ))
         } else {
             self.state = PageLoadState::Pending;
-            None
+            Ok(None)
         }
     }
+
+    // A bucket is probed only if its meta-map entry matches the hash of the page ID, so any page
+    // in the bucket must hash to the same entry. A page which doesn't was misdirected by the disk.
+    // Returns a description of the page in that case.
+    fn misdirected(&self, page: &FatPage) -> Option<String> {
+        // UNWRAP: slice is 32 bytes long.
+        let raw_page_id: [u8; 32] = page[PAGE_SIZE - 32..].try_into().unwrap();
+        let Ok(page_id) = PageId::decode(raw_page_id) else {
+            return Some("an invalid page ID".to_string());
+        };
+        let hash = hash_raw_page_id(raw_page_id, &self.seed);
+        if meta_map::full_entry(hash) != meta_map::full_entry(self.probe_sequence.hash) {
+            return Some(format!("page {:?} which belongs elsewhere", page_id));
+        }
+        None
+    }
 }
 
 /// Describes the utilization of buckets in the hash-table at a point in time.
@@ -726,3 +891,80 @@ impl ProbeSequence {
         self.bucket
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        hash_page_id,
+        meta_map::{self, MetaMap},
+        PageLoad, PageLoadState, ProbeSequence,
+    };
+    use crate::io::{PagePool, PAGE_SIZE};
+    use nomt_core::page_id::{ChildPageIndex, PageId, ROOT_PAGE_ID};
+
+    fn submitted_load(page_id: &PageId, seed: [u8; 16]) -> PageLoad {
+        let meta_map = MetaMap::from_bytes(vec![0; 4096], 4096);
+        PageLoad {
+            probe_sequence: ProbeSequence::new(page_id, &meta_map, &seed),
+            page_id: page_id.clone(),
+            seed,
+            state: PageLoadState::Submitted,
+        }
+    }
+
+    #[test]
+    fn misdirected_page_is_reported() {
+        let page_pool = PagePool::new();
+        let seed = [1; 16];
+        let page_id = ROOT_PAGE_ID
+            .child_page_id(ChildPageIndex::new(0).unwrap())
+            .unwrap();
+        let tag = |page_id: &PageId| meta_map::full_entry(hash_page_id(page_id, &seed));
+
+        let labeled = |label: [u8; 32]| {
+            let mut page = page_pool.alloc_fat_page();
+            page.fill(0);
+            page[PAGE_SIZE - 32..].copy_from_slice(&label);
+            page
+        };
+
+        let mut load = submitted_load(&page_id, seed);
+        assert!(load
+            .try_complete(labeled(page_id.encode()))
+            .unwrap()
+            .is_some());
+
+        // a page which could share the bucket is a miss, one which can't is an error.
+        let candidates: Vec<PageId> = (0..64)
+            .flat_map(|i| (0..64).map(move |j| (i, j)))
+            .map(|(i, j)| {
+                let child = |page_id: &PageId, i| {
+                    page_id
+                        .child_page_id(ChildPageIndex::new(i).unwrap())
+                        .unwrap()
+                };
+                child(&child(&ROOT_PAGE_ID, i), j)
+            })
+            .collect();
+        let colliding = candidates
+            .iter()
+            .find(|other| tag(other) == tag(&page_id))
+            .unwrap();
+        let elsewhere = candidates
+            .iter()
+            .find(|other| tag(other) != tag(&page_id))
+            .unwrap();
+
+        let mut load = submitted_load(&page_id, seed);
+        assert!(load
+            .try_complete(labeled(colliding.encode()))
+            .unwrap()
+            .is_none());
+
+        let mut load = submitted_load(&page_id, seed);
+        assert!(load.try_complete(labeled(elsewhere.encode())).is_err());
+
+        let mut load = submitted_load(&page_id, seed);
+        assert!(load.try_complete(labeled([0xff; 32])).is_err());
+    }
+}
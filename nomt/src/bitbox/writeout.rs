@@ -12,13 +12,14 @@ use std::{
     sync::Arc,
 };
 
-use crate::io::{FatPage, IoCommand, IoHandle, IoKind};
+use crate::io::{coalesce_writes, FatPage, IoCommand, IoHandle, IoKind};
 
 pub(super) fn write_wal(mut wal_fd: &File, wal_blob: &[u8]) -> std::io::Result<()> {
     wal_fd.set_len(0)?;
     wal_fd.seek(SeekFrom::Start(0))?;
     wal_fd.write_all(wal_blob)?;
-    wal_fd.sync_all()?;
+    // the size of the file is among the metadata synced by fdatasync.
+    wal_fd.sync_data()?;
     Ok(())
 }
 
@@ -29,7 +30,7 @@ pub(super) fn truncate_wal(mut wal_fd: &File, do_sync: bool) -> std::io::Result<
     wal_fd.set_len(0)?;
     wal_fd.seek(SeekFrom::Start(0))?;
     if do_sync {
-        wal_fd.sync_all()?;
+        wal_fd.sync_data()?;
     }
     Ok(())
 }
@@ -37,27 +38,44 @@ pub(super) fn truncate_wal(mut wal_fd: &File, do_sync: bool) -> std::io::Result<
 pub(super) fn write_ht(
     io_handle: IoHandle,
     ht_fd: &File,
-    mut ht: Vec<(u64, Arc<FatPage>)>,
+    ht: Vec<(u64, Arc<FatPage>)>,
 ) -> std::io::Result<()> {
-    let mut sent = 0;
+    let mut in_flight = 0;
 
-    ht.sort_unstable_by_key(|item| item.0);
-    for (pn, page) in ht {
+    // pages of clustered subtrees tend to land next to each other; write them together.
+    for kind in coalesce_writes(ht_fd.as_raw_fd(), ht) {
+        io_handle.send(IoCommand { kind, user_data: 0 }).unwrap();
+        in_flight += 1;
+    }
+
+    // write out every range as soon as it is written, leaving little for the final sync to do.
+    while in_flight > 0 {
+        let complete = io_handle.recv().unwrap();
+        complete.result?;
+        in_flight -= 1;
+
+        let sync = match complete.command.kind {
+            IoKind::WriteArc(fd, pn, _) => IoKind::SyncRange(fd, pn, 1),
+            IoKind::WriteRun(fd, pn, ref run) => IoKind::SyncRange(fd, pn, run.len() as u32),
+            _ => continue,
+        };
         io_handle
             .send(IoCommand {
-                kind: IoKind::WriteArc(ht_fd.as_raw_fd(), pn, page),
+                kind: sync,
                 user_data: 0,
             })
             .unwrap();
-        sent += 1;
-    }
-
-    while sent > 0 {
-        io_handle.recv().unwrap();
-        sent -= 1;
+        in_flight += 1;
     }
 
-    ht_fd.sync_all()?;
+    // the size of the file never changes, so no more than its data needs to be synced.
+    io_handle
+        .send(IoCommand {
+            kind: IoKind::Fdatasync(ht_fd.as_raw_fd()),
+            user_data: 0,
+        })
+        .unwrap();
+    io_handle.recv().unwrap().result?;
 
     Ok(())
 }
@@ -0,0 +1,84 @@
+//! Witnesses of the pre-state of a block, for stateless re-execution.
+//!
+//! A sequencer which knows the keys a block will touch calls [`crate::Nomt::block_witness`] to
+//! prove them. The [`BlockWitness`] carries everything a stateless executor needs: the values of
+//! the keys and proofs of their paths. [`BlockWitness::builder`] checks one against the other and
+//! yields a [`BlockBuilder`], which the block's writes are applied to in order to compute the
+//! post-state root.
+
+use anyhow::{bail, ensure};
+use nomt_core::{
+    proof::{BlockBuilder, PathProof},
+    trie::KeyPath,
+};
+
+use crate::{HashAlgorithm, KeyReadWrite, Nomt, Root, SessionParams, Value, WitnessMode};
+
+/// The pre-state of the keys touched by a block.
+pub struct BlockWitness {
+    /// The root of the pre-state.
+    pub prev_root: Root,
+    /// Proofs of the paths to all of the keys. A path may lead to several keys.
+    pub path_proofs: Vec<PathProof>,
+    /// The value of every key in the pre-state, in key order. `None` means no value.
+    pub values: Vec<(KeyPath, Option<Value>)>,
+}
+
+impl BlockWitness {
+    /// Create a builder over the pre-state, after checking the path proofs against the root and
+    /// the values against the path proofs.
+    pub fn builder<H: HashAlgorithm>(&self) -> anyhow::Result<BlockBuilder<H>> {
+        let builder = match BlockBuilder::<H>::new(
+            self.prev_root.into_inner(),
+            self.path_proofs.iter().cloned(),
+        ) {
+            Ok(builder) => builder,
+            Err(e) => bail!("invalid path proof: {:?}", e),
+        };
+
+        for (key, value) in &self.values {
+            let Ok(proven) = builder.read(key) else {
+                bail!("no path proof for key {:?}", key);
+            };
+            ensure!(
+                proven == value.as_ref().map(|v| H::hash_value(v)),
+                "value of key {:?} doesn't match its path proof",
+                key,
+            );
+        }
+        Ok(builder)
+    }
+}
+
+pub(crate) fn block_witness<T: HashAlgorithm>(
+    nomt: &Nomt<T>,
+    params: SessionParams,
+    keys: impl IntoIterator<Item = KeyPath>,
+) -> anyhow::Result<BlockWitness> {
+    let mut keys: Vec<_> = keys.into_iter().collect();
+    keys.sort_unstable();
+    keys.dedup();
+
+    // reading every key in a session yields a witness of all of their paths. the session is
+    // never committed.
+    let session = nomt.begin_session(params.witness_mode(WitnessMode::read_write()));
+    let values = keys
+        .into_iter()
+        .map(|key| Ok((key, session.read(key)?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let prev_root = session.prev_root;
+
+    let actuals = values
+        .iter()
+        .map(|(key, value)| (*key, KeyReadWrite::Read(value.clone())))
+        .collect();
+    let mut finished = session.finish(actuals)?;
+    // UNWRAP: the session was started with a witness.
+    let witness = finished.take_witness().unwrap();
+
+    Ok(BlockWitness {
+        prev_root,
+        path_proofs: witness.path_proofs.into_iter().map(|p| p.inner).collect(),
+        values,
+    })
+}
@@ -0,0 +1,90 @@
+//! Limits on the resources used by a single commit.
+
+use nomt_core::page_id::PageId;
+
+use crate::{beatree, store::DirtyPage};
+
+/// Limits on the resources used by a single commit. See [`crate::Options::commit_limits`].
+///
+/// A commit exceeding any of the limits fails with [`CommitLimitExceeded`] before anything is
+/// written. `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CommitLimits {
+    /// The maximum number of keys changed by a commit.
+    pub max_keys: Option<u64>,
+    /// The maximum number of trie pages written by a commit, including deleted pages.
+    pub max_pages: Option<u64>,
+    /// The maximum total size of the values written by a commit, in bytes.
+    pub max_value_bytes: Option<u64>,
+}
+
+/// A resource limited by [`CommitLimits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitLimit {
+    /// The number of changed keys.
+    Keys,
+    /// The number of written pages.
+    Pages,
+    /// The total size of the written values.
+    ValueBytes,
+}
+
+/// The error returned by a commit exceeding one of the configured [`CommitLimits`].
+///
+/// Commits return [`anyhow::Error`], from which this can be recovered with
+/// [`anyhow::Error::downcast_ref`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommitLimitExceeded {
+    /// The exceeded limit.
+    pub limit: CommitLimit,
+    /// The amount used by the commit.
+    pub actual: u64,
+    /// The configured maximum.
+    pub max: u64,
+}
+
+impl std::fmt::Display for CommitLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let what = match self.limit {
+            CommitLimit::Keys => "changed keys",
+            CommitLimit::Pages => "written pages",
+            CommitLimit::ValueBytes => "written value bytes",
+        };
+        write!(
+            f,
+            "commit limit exceeded: {} {}, maximum {}",
+            self.actual, what, self.max
+        )
+    }
+}
+
+impl std::error::Error for CommitLimitExceeded {}
+
+impl CommitLimits {
+    /// Check the changes of a commit against the limits.
+    pub(crate) fn check(
+        &self,
+        pages: &[(PageId, DirtyPage)],
+        values: &[(beatree::Key, beatree::ValueChange)],
+    ) -> Result<(), CommitLimitExceeded> {
+        let check = |limit, actual: u64, max: Option<u64>| match max {
+            Some(max) if actual > max => Err(CommitLimitExceeded { limit, actual, max }),
+            _ => Ok(()),
+        };
+
+        check(CommitLimit::Keys, values.len() as u64, self.max_keys)?;
+        check(CommitLimit::Pages, pages.len() as u64, self.max_pages)?;
+        if self.max_value_bytes.is_some() {
+            let value_bytes = values
+                .iter()
+                .map(|(_, change)| match change {
+                    beatree::ValueChange::Delete => 0,
+                    beatree::ValueChange::Insert(value)
+                    | beatree::ValueChange::InsertOverflow(value, _) => value.len() as u64,
+                })
+                .sum();
+            check(CommitLimit::ValueBytes, value_bytes, self.max_value_bytes)?;
+        }
+        Ok(())
+    }
+}
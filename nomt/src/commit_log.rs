@@ -0,0 +1,170 @@
+//! Logging of large and slow commits.
+//!
+//! A commit exceeding any of the configured [`CommitLogThresholds`] is summarized in a single
+//! structured record emitted through [`tracing`] with the `nomt::commit` target: its size, the
+//! time spent in each phase and the subtrees of the page tree it wrote the most pages to.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use nomt_core::page_id::PageId;
+
+use crate::{beatree, store::DirtyPage};
+
+/// The number of subtrees named by the log record of a commit.
+const LOGGED_SUBTREES: usize = 5;
+
+/// Thresholds above which a commit is logged. See [`crate::Options::commit_log_thresholds`].
+///
+/// A commit is logged if it exceeds any of the thresholds. `None` disables a threshold.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CommitLogThresholds {
+    /// The number of keys changed by a commit.
+    pub keys: Option<u64>,
+    /// The number of trie pages written by a commit, including deleted pages.
+    pub pages: Option<u64>,
+    /// The time taken by a commit, from the call to `commit` until it returns.
+    pub duration: Option<Duration>,
+}
+
+impl CommitLogThresholds {
+    fn is_enabled(&self) -> bool {
+        self.keys.is_some() || self.pages.is_some() || self.duration.is_some()
+    }
+}
+
+/// Times the phases of a single commit and logs it if it exceeds the thresholds.
+pub(crate) struct CommitLog {
+    thresholds: CommitLogThresholds,
+    start: Instant,
+    last: Instant,
+    phases: [Duration; Phase::COUNT],
+    changes: Changes,
+}
+
+#[derive(Default)]
+struct Changes {
+    keys: u64,
+    deleted_keys: u64,
+    value_bytes: u64,
+    pages: u64,
+    largest_subtrees: Vec<(u8, usize)>,
+}
+
+/// A phase of a commit.
+#[derive(Clone, Copy)]
+pub(crate) enum Phase {
+    /// Waiting for ongoing sessions and commits, collecting the changes, checking the limits,
+    /// verifying and signing.
+    Prepare,
+    /// Writing the rollback delta and appending to the sidecar indexes.
+    Journal,
+    /// Writing the changes and syncing them to disk.
+    Sync,
+    /// Applying the sidecar indexes and writing the backup log.
+    Finish,
+}
+
+impl Phase {
+    const COUNT: usize = 4;
+}
+
+impl CommitLog {
+    /// Start timing a commit. Nothing is recorded if no threshold is set.
+    pub fn start(thresholds: &CommitLogThresholds) -> Self {
+        let now = Instant::now();
+        CommitLog {
+            thresholds: *thresholds,
+            start: now,
+            last: now,
+            phases: [Duration::ZERO; Phase::COUNT],
+            changes: Changes::default(),
+        }
+    }
+
+    /// Record the changes written by the commit.
+    pub fn changes(
+        &mut self,
+        pages: &[(PageId, DirtyPage)],
+        values: &[(beatree::Key, beatree::ValueChange)],
+    ) {
+        if !self.thresholds.is_enabled() {
+            return;
+        }
+        let mut changes = Changes {
+            keys: values.len() as u64,
+            pages: pages.len() as u64,
+            largest_subtrees: largest_subtrees(pages),
+            ..Changes::default()
+        };
+        for (_, change) in values {
+            match change {
+                beatree::ValueChange::Insert(value)
+                | beatree::ValueChange::InsertOverflow(value, _) => {
+                    changes.value_bytes += value.len() as u64
+                }
+                beatree::ValueChange::Delete => changes.deleted_keys += 1,
+            }
+        }
+        self.changes = changes;
+    }
+
+    /// Mark the end of a phase, which began when the previous phase ended.
+    pub fn end(&mut self, phase: Phase) {
+        let now = Instant::now();
+        self.phases[phase as usize] += now - self.last;
+        self.last = now;
+    }
+
+    /// Log the commit if it exceeded any of the thresholds.
+    pub fn finish(self, sequence: u64) {
+        let duration = self.start.elapsed();
+        let changes = self.changes;
+        if !exceeds(changes.keys, self.thresholds.keys)
+            && !exceeds(changes.pages, self.thresholds.pages)
+            && !exceeds(duration, self.thresholds.duration)
+        {
+            return;
+        }
+
+        let [prepare, journal, sync, finish] = self.phases;
+        tracing::warn!(
+            target: "nomt::commit",
+            sequence,
+            keys = changes.keys,
+            deleted_keys = changes.deleted_keys,
+            value_bytes = changes.value_bytes,
+            pages = changes.pages,
+            duration_us = duration.as_micros() as u64,
+            prepare_us = prepare.as_micros() as u64,
+            journal_us = journal.as_micros() as u64,
+            sync_us = sync.as_micros() as u64,
+            finish_us = finish.as_micros() as u64,
+            largest_subtrees = ?changes.largest_subtrees,
+            "large or slow commit",
+        );
+    }
+}
+
+fn exceeds<T: PartialOrd>(actual: T, threshold: Option<T>) -> bool {
+    threshold.is_some_and(|max| actual > max)
+}
+
+/// The subtrees below the root page with the most written pages, as pairs of the index of the
+/// child of the root page and the number of pages, largest first.
+fn largest_subtrees(pages: &[(PageId, DirtyPage)]) -> Vec<(u8, usize)> {
+    let mut counts = HashMap::new();
+    for (page_id, _) in pages {
+        if page_id.depth() > 0 {
+            *counts
+                .entry(page_id.child_index_at_level(0).to_u8())
+                .or_insert(0) += 1;
+        }
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts.truncate(LOGGED_SUBTREES);
+    counts
+}
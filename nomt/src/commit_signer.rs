@@ -0,0 +1,45 @@
+//! Signatures of the roots made by commits.
+//!
+//! A signer lets the holder of a key attest to every root committed by the database. The
+//! signature is stored in the metadata along with the root and passed to the commit sink, so
+//! consumers of the changes can authenticate the state they are given without trusting the
+//! channel it came through. See [`crate::Options::commit_signer`].
+
+use crate::Root;
+
+/// The maximum length of a signature, in bytes.
+pub const MAX_SIGNATURE_LEN: usize = 256;
+
+/// A signer of the roots made by commits.
+///
+/// This is implemented for closures taking the root and returning the signature.
+pub trait CommitSigner: Send + Sync {
+    /// Sign the root of the trie after a commit.
+    ///
+    /// This is called once per commit, in commit order, before the changes are applied to the
+    /// database. If this returns an error, the commit is aborted and the database is unchanged.
+    /// The signature must be at most [`MAX_SIGNATURE_LEN`] bytes long.
+    fn sign(&self, root: Root) -> anyhow::Result<Vec<u8>>;
+}
+
+impl<F> CommitSigner for F
+where
+    F: Fn(Root) -> anyhow::Result<Vec<u8>> + Send + Sync,
+{
+    fn sign(&self, root: Root) -> anyhow::Result<Vec<u8>> {
+        self(root)
+    }
+}
+
+/// Sign the root of a commit, checking the length of the signature.
+pub(crate) fn sign(signer: &dyn CommitSigner, root: Root) -> anyhow::Result<Vec<u8>> {
+    let signature = signer.sign(root)?;
+    if signature.len() > MAX_SIGNATURE_LEN {
+        anyhow::bail!(
+            "Signature of {} bytes exceeds the maximum of {} bytes",
+            signature.len(),
+            MAX_SIGNATURE_LEN
+        );
+    }
+    Ok(signature)
+}
@@ -0,0 +1,101 @@
+//! Sinks receiving the changes made by every commit.
+//!
+//! A sink lets an embedder persist the trie pages and values written by NOMT into storage it
+//! already manages, such as an existing database, and use NOMT as a merklization layer on top of
+//! it. See [`crate::Options::commit_sink`].
+
+use nomt_core::{page_id::PageId, trie::KeyPath};
+
+use crate::{beatree, page_diff::PageDiff, store::DirtyPage, Root};
+
+/// A change to a page of the trie.
+#[derive(Debug, Clone, Copy)]
+pub struct PageChange<'a> {
+    /// The encoded ID of the page, as given by [`nomt_core::page_id::PageId::encode`].
+    pub page_id: [u8; 32],
+    /// The new contents of the page, or `None` if the page was deleted.
+    pub data: Option<&'a [u8]>,
+    /// The nodes of the page which changed. Applying the changed nodes to the previous contents
+    /// of the page gives the new contents.
+    pub diff: &'a PageDiff,
+}
+
+impl<'a> PageChange<'a> {
+    pub(crate) fn new(page_id: &PageId, data: &'a [u8], diff: &'a PageDiff) -> Self {
+        PageChange {
+            page_id: page_id.encode(),
+            data: if diff.cleared() { None } else { Some(data) },
+            diff,
+        }
+    }
+}
+
+/// A change to a value.
+#[derive(Debug, Clone, Copy)]
+pub struct ValueChange<'a> {
+    /// The key path of the value.
+    pub key_path: KeyPath,
+    /// The new value, or `None` if the value was deleted.
+    pub value: Option<&'a [u8]>,
+}
+
+/// The changes made by a single commit.
+#[derive(Debug, Clone)]
+pub struct CommitChanges<'a> {
+    /// The root of the trie before the commit.
+    pub prev_root: Root,
+    /// The root of the trie after the commit.
+    pub root: Root,
+    /// The changed pages of the trie, in no particular order.
+    pub pages: Vec<PageChange<'a>>,
+    /// The changed values, in no particular order.
+    pub values: Vec<ValueChange<'a>>,
+    /// The signature of the root, if a signer is set. See [`crate::Options::commit_signer`].
+    pub signature: Option<&'a [u8]>,
+}
+
+/// A destination for the changes made by commits, in addition to the database itself.
+pub trait CommitSink: Send + Sync {
+    /// Write the changes made by a commit.
+    ///
+    /// This is called once per commit, in commit order, before the changes are applied to the
+    /// database. If this returns an error, the commit is aborted and the database is unchanged.
+    fn write(&self, changes: &CommitChanges) -> anyhow::Result<()>;
+}
+
+/// Write the changes of a commit to the sink.
+pub(crate) fn write(
+    sink: &dyn CommitSink,
+    prev_root: Root,
+    root: Root,
+    pages: &[(PageId, DirtyPage)],
+    values: &[(beatree::Key, beatree::ValueChange)],
+    signature: Option<&[u8]>,
+) -> anyhow::Result<()> {
+    let pages = pages
+        .iter()
+        .map(|(page_id, dirty_page)| {
+            PageChange::new(page_id, dirty_page.page.page_data(), &dirty_page.diff)
+        })
+        .collect();
+
+    let values = values
+        .iter()
+        .map(|(key_path, change)| ValueChange {
+            key_path: *key_path,
+            value: match change {
+                beatree::ValueChange::Delete => None,
+                beatree::ValueChange::Insert(value)
+                | beatree::ValueChange::InsertOverflow(value, _) => Some(&value[..]),
+            },
+        })
+        .collect();
+
+    sink.write(&CommitChanges {
+        prev_root,
+        root,
+        pages,
+        values,
+        signature,
+    })
+}
@@ -0,0 +1,293 @@
+//! Verification of the pages written by a commit. See [`crate::Options::verify_commits`].
+//!
+//! The merkle update computes every changed node and the new root in one pass. This re-derives
+//! every changed internal node from its children, as found in the written pages, and the root
+//! from the root page. It shares nothing with the update except the pages, so a bug or memory
+//! corruption in the update is caught before the pages reach the disk.
+
+use std::collections::HashMap;
+
+use nomt_core::{
+    hasher::NodeHasher,
+    page::NODES_PER_PAGE,
+    page_id::{ChildPageIndex, PageId, ROOT_PAGE_ID},
+    trie::{self, InternalData, Node, TERMINATOR},
+};
+
+use crate::{
+    page_cache::{PageCache, PageMut},
+    store::{BucketInfo, DirtyPage, Store},
+    Root,
+};
+
+// The index of the first node of the bottom layer of a page.
+const BOTTOM_LAYER_START: usize = NODES_PER_PAGE / 2 - 1;
+
+/// Check that the changed nodes of the pages written by a commit hash up to `root`.
+///
+/// The top nodes of pages not written by the commit are loaded with `load_top_nodes`.
+pub(crate) fn verify<H: NodeHasher>(
+    root: Root,
+    pages: &[(PageId, DirtyPage)],
+    mut load_top_nodes: impl FnMut(&PageId) -> anyhow::Result<Option<(Node, Node)>>,
+) -> anyhow::Result<()> {
+    let written: HashMap<&PageId, &DirtyPage> = pages
+        .iter()
+        .map(|(page_id, page)| (page_id, page))
+        .collect();
+
+    // The top two nodes of a page, as of this commit.
+    let mut top_nodes = |page_id: &PageId| -> anyhow::Result<(Node, Node)> {
+        match written.get(page_id) {
+            Some(dirty) if dirty.diff.cleared() => Ok((TERMINATOR, TERMINATOR)),
+            Some(dirty) => Ok((dirty.page.node(0), dirty.page.node(1))),
+            None => Ok(load_top_nodes(page_id)?.unwrap_or((TERMINATOR, TERMINATOR))),
+        }
+    };
+
+    for (page_id, dirty) in pages {
+        if dirty.diff.cleared() {
+            continue;
+        }
+
+        for index in (0..NODES_PER_PAGE).filter(|i| dirty.diff.changed(*i)) {
+            let node = dirty.page.node(index);
+            if !trie::is_internal::<H>(&node) {
+                continue;
+            }
+
+            let (left, right) = if index < BOTTOM_LAYER_START {
+                let left = index * 2 + 2;
+                (dirty.page.node(left), dirty.page.node(left + 1))
+            } else {
+                // UNWRAP: there are as many nodes in the bottom layer as child pages.
+                let child_index = ChildPageIndex::new((index - BOTTOM_LAYER_START) as u8).unwrap();
+                match page_id.child_page_id(child_index) {
+                    Ok(child_page_id) => top_nodes(&child_page_id)?,
+                    Err(_) => anyhow::bail!(
+                        "commit verification failed: internal node {} at the maximum depth in \
+                         page {:?}",
+                        index,
+                        page_id,
+                    ),
+                }
+            };
+
+            if H::hash_internal(&InternalData { left, right }) != node {
+                anyhow::bail!(
+                    "commit verification failed: node {} of page {:?} doesn't match its children",
+                    index,
+                    page_id,
+                );
+            }
+        }
+    }
+
+    if written.contains_key(&ROOT_PAGE_ID) {
+        let (left, right) = top_nodes(&ROOT_PAGE_ID)?;
+        let matches = if left == TERMINATOR && right == TERMINATOR {
+            // the root is empty or a single leaf, neither of which is stored in pages.
+            !trie::is_internal::<H>(&root.into_inner())
+        } else {
+            H::hash_internal(&InternalData { left, right }) == root.into_inner()
+        };
+        if !matches {
+            anyhow::bail!(
+                "commit verification failed: root {} doesn't match the root page",
+                root
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that the pages the commit assumed to be fresh don't exist yet.
+///
+/// The merkle update creates the pages below positions which were terminal empty, without looking
+/// them up. A page which nevertheless exists would be shadowed by the fresh one and its bucket
+/// leaked. `exists` tells whether a committed page exists.
+pub(crate) fn verify_fresh(
+    pages: &[(PageId, DirtyPage)],
+    mut exists: impl FnMut(&PageId) -> anyhow::Result<bool>,
+) -> anyhow::Result<()> {
+    for (page_id, dirty) in pages {
+        let fresh = match dirty.bucket {
+            BucketInfo::Known(_) => false,
+            BucketInfo::FreshWithNoDependents => true,
+            // dependent on a page of an earlier overlay, which exists once that is committed.
+            BucketInfo::FreshOrDependent(ref maybe) => maybe.get().is_none(),
+        };
+        if fresh && exists(page_id)? {
+            anyhow::bail!(
+                "commit verification failed: page {:?} was assumed fresh but exists",
+                page_id,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Load the top two nodes of a committed page from the page cache or the store.
+pub(crate) fn load_top_nodes(
+    page_cache: &PageCache,
+    store: &Store,
+    page_id: &PageId,
+) -> anyhow::Result<Option<(Node, Node)>> {
+    if let Some((page, _)) = page_cache.get(page_id.clone()) {
+        return Ok(Some((page.node(0), page.node(1))));
+    }
+    Ok(store.load_page(page_id.clone())?.map(|(page, _)| {
+        let page = PageMut::pristine_with_data(page);
+        (page.node(0), page.node(1))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify, verify_fresh, BOTTOM_LAYER_START};
+    use crate::{
+        hasher::Blake3Hasher,
+        io::PagePool,
+        page_cache::PageMut,
+        page_diff::PageDiff,
+        store::{BucketIndex, BucketInfo, DirtyPage, SharedMaybeBucketIndex},
+        Root,
+    };
+    use nomt_core::{
+        hasher::NodeHasher,
+        page_id::{ChildPageIndex, PageId, ROOT_PAGE_ID},
+        trie::{InternalData, LeafData, Node},
+    };
+
+    fn leaf(byte: u8) -> Node {
+        Blake3Hasher::hash_leaf(&LeafData {
+            key_path: [byte; 32],
+            value_hash: [byte; 32],
+        })
+    }
+
+    fn internal(left: Node, right: Node) -> Node {
+        Blake3Hasher::hash_internal(&InternalData { left, right })
+    }
+
+    fn dirty_page(page_pool: &PagePool, page_id: &PageId, nodes: &[(usize, Node)]) -> DirtyPage {
+        let mut page = PageMut::pristine_empty(page_pool, page_id);
+        let mut diff = PageDiff::default();
+        for (index, node) in nodes {
+            page.set_node(*index, *node);
+            diff.set_changed(*index);
+        }
+        DirtyPage {
+            page: page.freeze(),
+            diff,
+            bucket: BucketInfo::FreshWithNoDependents,
+        }
+    }
+
+    fn no_pages(_: &PageId) -> anyhow::Result<Option<(Node, Node)>> {
+        Ok(None)
+    }
+
+    #[test]
+    fn root_page() {
+        let page_pool = PagePool::new();
+        let (left, right) = (internal(leaf(1), leaf(2)), leaf(3));
+        let pages = vec![(
+            ROOT_PAGE_ID,
+            dirty_page(
+                &page_pool,
+                &ROOT_PAGE_ID,
+                &[(0, left), (1, right), (2, leaf(1)), (3, leaf(2))],
+            ),
+        )];
+
+        let root = Root(internal(left, right));
+        verify::<Blake3Hasher>(root, &pages, no_pages).unwrap();
+        assert!(verify::<Blake3Hasher>(Root(internal(right, left)), &pages, no_pages).is_err());
+
+        // a node which doesn't match its children.
+        let pages = vec![(
+            ROOT_PAGE_ID,
+            dirty_page(
+                &page_pool,
+                &ROOT_PAGE_ID,
+                &[(0, left), (1, right), (2, leaf(1)), (3, leaf(4))],
+            ),
+        )];
+        assert!(verify::<Blake3Hasher>(root, &pages, no_pages).is_err());
+    }
+
+    #[test]
+    fn bottom_layer_uses_child_pages() {
+        let page_pool = PagePool::new();
+        let child_page_id = ROOT_PAGE_ID
+            .child_page_id(ChildPageIndex::new(0).unwrap())
+            .unwrap();
+        let child_top = (leaf(1), leaf(2));
+        let pages = vec![(
+            ROOT_PAGE_ID,
+            dirty_page(
+                &page_pool,
+                &ROOT_PAGE_ID,
+                &[(BOTTOM_LAYER_START, internal(child_top.0, child_top.1))],
+            ),
+        )];
+        let load = |page_id: &PageId| {
+            assert_eq!(page_id, &child_page_id);
+            Ok(Some(child_top))
+        };
+
+        // the root isn't checked, as the root page's top nodes weren't written.
+        verify::<Blake3Hasher>(Root([0; 32]), &pages, load).unwrap();
+        assert!(verify::<Blake3Hasher>(Root([0; 32]), &pages, no_pages).is_err());
+
+        // a written child page takes precedence over the committed one.
+        let mut pages = pages;
+        pages.push((
+            child_page_id.clone(),
+            dirty_page(&page_pool, &child_page_id, &[(0, leaf(1)), (1, leaf(3))]),
+        ));
+        assert!(verify::<Blake3Hasher>(Root([0; 32]), &pages, load).is_err());
+    }
+
+    #[test]
+    fn fresh_pages_must_not_exist() {
+        let page_pool = PagePool::new();
+        let child_page_id = |index| {
+            ROOT_PAGE_ID
+                .child_page_id(ChildPageIndex::new(index).unwrap())
+                .unwrap()
+        };
+        let page = |page_id: PageId, bucket| {
+            let mut dirty = dirty_page(&page_pool, &page_id, &[]);
+            dirty.bucket = bucket;
+            (page_id, dirty)
+        };
+
+        let pages = vec![
+            page(child_page_id(0), BucketInfo::Known(BucketIndex::new(1))),
+            page(child_page_id(1), BucketInfo::FreshWithNoDependents),
+            page(
+                child_page_id(2),
+                BucketInfo::FreshOrDependent(SharedMaybeBucketIndex::new(None)),
+            ),
+            page(
+                child_page_id(3),
+                BucketInfo::FreshOrDependent(SharedMaybeBucketIndex::new(Some(BucketIndex::new(
+                    2,
+                )))),
+            ),
+        ];
+
+        // pages with a known bucket, or allocated by an earlier overlay, may exist.
+        let existing = [child_page_id(0), child_page_id(3)];
+        verify_fresh(&pages, |page_id| Ok(existing.contains(page_id))).unwrap();
+
+        for index in [1, 2] {
+            let existing = child_page_id(index);
+            assert!(verify_fresh(&pages, |page_id| Ok(page_id == &existing)).is_err());
+        }
+    }
+}
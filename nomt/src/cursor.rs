@@ -0,0 +1,359 @@
+//! Iteration over ranges of keys, resumable from a cursor.
+//!
+//! Keys are always visited in ascending order, so iterating the same range at the same root
+//! yields the same entries in the same order. A [`Cursor`] records the root and the position of
+//! the next entry. It can be encoded to bytes, handed to a client of a paginated endpoint and
+//! given back later, possibly to another process, to resume the iteration with
+//! [`crate::ReadSession::resume`], as long as the database is still at the same root.
+
+use nomt_core::{key_path, trie::KeyPath};
+use std::{collections::VecDeque, marker::PhantomData};
+
+use crate::{
+    beatree::{
+        self, iterator::IterOutput, AsyncLeafLoad, BeatreeIterator, LeafNodeRef, PageNumber,
+    },
+    io::IoHandle,
+    store::Store,
+    Root, Value,
+};
+
+/// The length of an encoded [`Cursor`].
+pub const CURSOR_LEN: usize = 98;
+
+const CURSOR_VERSION: u8 = 1;
+
+/// The maximum number of shards the key space can be split into. See
+/// [`crate::ReadSession::iter_shards`].
+pub const MAX_ITER_SHARDS: usize = 1 << SHARD_PREFIX_BITS;
+
+const SHARD_PREFIX_BITS: usize = 16;
+
+/// Split the key space into the given number of disjoint, consecutive ranges of approximately
+/// equal size, each covering a run of 16-bit key prefixes.
+pub(crate) fn shard_ranges(num_shards: usize) -> Vec<(KeyPath, Option<KeyPath>)> {
+    assert!(num_shards > 0 && num_shards <= MAX_ITER_SHARDS);
+
+    // As with the page cache shards, we assume keys are uniformly distributed.
+    // The first `remainder` shards get `part + 1` prefixes and the rest get `part`.
+    let part = MAX_ITER_SHARDS / num_shards;
+    let remainder = MAX_ITER_SHARDS % num_shards;
+    let prefix_key = |prefix: usize| -> Option<KeyPath> {
+        (prefix < MAX_ITER_SHARDS).then(|| {
+            let mut key = KeyPath::default();
+            key[..2].copy_from_slice(&(prefix as u16).to_be_bytes());
+            key
+        })
+    };
+
+    (0..num_shards)
+        .map(|shard_index| {
+            let start = part * shard_index + shard_index.min(remainder);
+            let end = part * (shard_index + 1) + (shard_index + 1).min(remainder);
+            // UNWRAP: the start of a shard is always a valid prefix.
+            (prefix_key(start).unwrap(), prefix_key(end))
+        })
+        .collect()
+}
+
+/// The position of an iteration over a range of keys. See [`KeyValueIter::cursor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    root: Root,
+    next: Option<KeyPath>,
+    end: Option<KeyPath>,
+}
+
+impl Cursor {
+    /// The root of the trie the iteration is over.
+    pub fn root(&self) -> Root {
+        self.root
+    }
+
+    /// The smallest key of the range which wasn't visited yet, or `None` if the iteration is
+    /// complete.
+    pub fn next_key(&self) -> Option<KeyPath> {
+        self.next
+    }
+
+    /// Whether every key of the range was visited. This may only become `true` once the iterator
+    /// returned `None`, if the range has no more entries but the last visited key isn't the end
+    /// of the range.
+    pub fn is_complete(&self) -> bool {
+        self.next.is_none()
+    }
+
+    /// Encode the cursor to bytes.
+    ///
+    /// The encoding is a version byte, the root, a byte of flags telling whether the next key and
+    /// the end of the range are present, then the next key and the end of the range, zeroed when
+    /// absent.
+    pub fn encode(&self) -> [u8; CURSOR_LEN] {
+        let mut buf = [0; CURSOR_LEN];
+        buf[0] = CURSOR_VERSION;
+        buf[1..33].copy_from_slice(&self.root.into_inner());
+        buf[33] = self.next.is_some() as u8 | (self.end.is_some() as u8) << 1;
+        buf[34..66].copy_from_slice(&self.next.unwrap_or_default());
+        buf[66..98].copy_from_slice(&self.end.unwrap_or_default());
+        buf
+    }
+
+    /// Decode a cursor encoded with [`Cursor::encode`]. Returns `None` if the bytes are not a
+    /// valid encoding.
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() != CURSOR_LEN || buf[0] != CURSOR_VERSION || buf[33] > 0b11 {
+            return None;
+        }
+        // UNWRAP: the slices have the right lengths.
+        let key = |start: usize| -> KeyPath { buf[start..start + 32].try_into().unwrap() };
+        Some(Cursor {
+            root: Root::from(key(1)),
+            next: (buf[33] & 1 != 0).then(|| key(34)),
+            end: (buf[33] & 2 != 0).then(|| key(66)),
+        })
+    }
+}
+
+/// An iterator over the entries of a range of keys, in ascending key order. Created by
+/// [`crate::ReadSession::iter_range`] and related methods.
+///
+/// Items are errors only if I/O fails, after which the iteration ends. Commits are blocked while
+/// the iterator is alive, like they are by the session it borrows.
+///
+/// By default, leaves are loaded one at a time as the iteration reaches them. When streaming large
+/// parts of the state, use [`KeyValueIter::read_ahead`] to keep several loads in flight.
+pub struct KeyValueIter<'a> {
+    read_tx: beatree::ReadTransaction,
+    iterator: BeatreeIterator,
+    io_handle: IoHandle,
+    cursor: Cursor,
+    read_ahead: usize,
+    // leaf loads for the leaves needed next by the iterator, in order.
+    pending: VecDeque<PendingLeaf>,
+    next_user_data: u64,
+    _session: PhantomData<&'a ()>,
+}
+
+struct PendingLeaf {
+    page_number: PageNumber,
+    user_data: u64,
+    state: PendingLeafState,
+}
+
+enum PendingLeafState {
+    Loading(AsyncLeafLoad),
+    Ready(LeafNodeRef),
+}
+
+impl KeyValueIter<'_> {
+    pub(crate) fn new(store: &Store, mut cursor: Cursor) -> Self {
+        cursor.next = cursor
+            .next
+            .filter(|next| cursor.end.is_none_or(|end| *next < end));
+        let read_tx = store.read_transaction();
+        let iterator = read_tx.iterator(cursor.next.unwrap_or_default(), cursor.end);
+        KeyValueIter {
+            read_tx,
+            iterator,
+            io_handle: store.io_pool().make_handle(),
+            cursor,
+            read_ahead: 1,
+            pending: VecDeque::new(),
+            next_user_data: 0,
+            _session: PhantomData,
+        }
+    }
+
+    pub(crate) fn new_range(
+        store: &Store,
+        root: Root,
+        start: KeyPath,
+        end: Option<KeyPath>,
+    ) -> Self {
+        let cursor = Cursor {
+            root,
+            next: Some(start),
+            end,
+        };
+        Self::new(store, cursor)
+    }
+
+    /// Keep loads of up to the given number of leaves in flight, following the order in which the
+    /// iteration visits them. Default: 1, which loads leaves only when they are reached.
+    ///
+    /// Loading ahead lets the I/O pool and the device work on many pages at once, so iterating
+    /// over leaves which are not cached is bound by bandwidth rather than by the latency of each
+    /// read. Leaves which were loaded ahead but not visited, for example because the iteration
+    /// was stopped early, are wasted reads.
+    pub fn read_ahead(mut self, leaves: usize) -> Self {
+        self.read_ahead = leaves.max(1);
+        self
+    }
+
+    /// The position of the iteration, from which it can be resumed.
+    pub fn cursor(&self) -> Cursor {
+        self.cursor
+    }
+
+    // Load the leaf the iterator is blocked on, along with the leaves after it, up to the read-ahead.
+    fn load_needed_leaf(&mut self) -> std::io::Result<LeafNodeRef> {
+        let mut needed = self.iterator.needed_leaves();
+        // UNWRAP: when blocked, needed leaf always exists.
+        let page_number = needed.next().unwrap();
+
+        // the pending loads are for consecutive needed leaves, so drop those before this one.
+        match self
+            .pending
+            .iter()
+            .position(|pending| pending.page_number == page_number)
+        {
+            Some(i) => drop(self.pending.drain(..i)),
+            None => self.pending.clear(),
+        }
+
+        let to_load = std::iter::once(page_number)
+            .chain(needed)
+            .skip(self.pending.len())
+            .take(self.read_ahead.saturating_sub(self.pending.len()));
+        for page_number in to_load {
+            let user_data = self.next_user_data;
+            self.next_user_data += 1;
+            let state = match self
+                .read_tx
+                .load_leaf_async(page_number, &self.io_handle, user_data)
+            {
+                Ok(leaf) => PendingLeafState::Ready(leaf),
+                Err(leaf_load) => PendingLeafState::Loading(leaf_load),
+            };
+            self.pending.push_back(PendingLeaf {
+                page_number,
+                user_data,
+                state,
+            });
+        }
+
+        // UNWRAP: the needed leaf was either pending already or just loaded.
+        while let PendingLeafState::Loading(_) = self.pending.front().unwrap().state {
+            // UNWRAP: a load of the needed leaf is in flight.
+            let complete_io = self.io_handle.recv().unwrap();
+            complete_io.result?;
+
+            // completions of dropped loads are ignored.
+            let Some(i) = self
+                .pending
+                .iter()
+                .position(|pending| pending.user_data == complete_io.command.user_data)
+            else {
+                continue;
+            };
+            // UNWRAP: the index was just found.
+            let mut pending = self.pending.remove(i).unwrap();
+            if let PendingLeafState::Loading(leaf_load) = pending.state {
+                // UNWRAP: the I/O command submitted by `load_leaf_async` is always a `Read`.
+                let leaf = leaf_load.finish(complete_io.command.kind.unwrap_buf());
+                pending.state = PendingLeafState::Ready(leaf);
+            }
+            self.pending.insert(i, pending);
+        }
+
+        // UNWRAP: the needed leaf is pending and ready.
+        match self.pending.pop_front().unwrap().state {
+            PendingLeafState::Ready(leaf) => Ok(leaf),
+            PendingLeafState::Loading(_) => unreachable!(),
+        }
+    }
+
+    fn visited(&mut self, key: &KeyPath) {
+        let end = self.cursor.end;
+        self.cursor.next =
+            key_path::successor(key).filter(|next| end.is_none_or(|end| *next < end));
+    }
+}
+
+impl Iterator for KeyValueIter<'_> {
+    type Item = anyhow::Result<(KeyPath, Value)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cursor.next?;
+        loop {
+            match self.iterator.next() {
+                None => {
+                    self.cursor.next = None;
+                    return None;
+                }
+                Some(IterOutput::Blocked) => match self.load_needed_leaf() {
+                    Ok(leaf) => self.iterator.provide_leaf(leaf),
+                    Err(e) => {
+                        self.cursor.next = None;
+                        return Some(Err(e.into()));
+                    }
+                },
+                Some(IterOutput::Item(key, value)) => {
+                    let value = value.to_vec();
+                    self.visited(&key);
+                    return Some(Ok((key, value)));
+                }
+                Some(IterOutput::OverflowItem(key, _, cell)) => {
+                    let value = self.read_tx.read_overflow(cell);
+                    self.visited(&key);
+                    return Some(Ok((key, value)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{shard_ranges, Cursor, CURSOR_LEN, MAX_ITER_SHARDS};
+    use crate::Root;
+
+    #[test]
+    fn shard_ranges_cover_key_space() {
+        for num_shards in [1, 2, 3, 7, 64, 1000, MAX_ITER_SHARDS] {
+            let ranges = shard_ranges(num_shards);
+            assert_eq!(ranges.len(), num_shards);
+            assert_eq!(ranges[0].0, [0; 32]);
+            assert_eq!(ranges[num_shards - 1].1, None);
+            for pair in ranges.windows(2) {
+                assert!(pair[0].0 < pair[1].0);
+                assert_eq!(pair[0].1, Some(pair[1].0));
+            }
+        }
+
+        let ranges = shard_ranges(3);
+        assert_eq!(ranges[1].0[..2], [0x55, 0x56]);
+        assert_eq!(ranges[2].0[..2], [0xaa, 0xab]);
+    }
+
+    #[test]
+    fn cursor_encoding() {
+        let cursors = [
+            Cursor {
+                root: Root::from([1; 32]),
+                next: Some([2; 32]),
+                end: Some([3; 32]),
+            },
+            Cursor {
+                root: Root::from([1; 32]),
+                next: Some([0; 32]),
+                end: None,
+            },
+            Cursor {
+                root: Root::from([1; 32]),
+                next: None,
+                end: None,
+            },
+        ];
+        for cursor in cursors {
+            assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor));
+        }
+
+        let mut buf = cursors[0].encode();
+        assert_eq!(Cursor::decode(&buf[..CURSOR_LEN - 1]), None);
+        buf[33] = 4;
+        assert_eq!(Cursor::decode(&buf), None);
+        buf[0] = 0;
+        assert_eq!(Cursor::decode(&buf), None);
+    }
+}
@@ -0,0 +1,556 @@
+//! Differential testing of NOMT against a reference implementation.
+//!
+//! A [`Differential`] commits every batch both to a database and to a [`Reference`], and checks
+//! after each commit that the roots are equal and that every key written by the batch reads back
+//! the same value from both. The default reference, [`BTreeReference`], keeps the state in a
+//! sorted map and computes its root from scratch.
+//!
+//! On the first divergence, the batches committed so far are shrunk to a smaller sequence which
+//! still diverges, by replaying candidates against fresh databases and references. The result is
+//! reported as a [`Divergence`].
+//!
+//! The sequence is a [`Repro`], which can be written to a file and replayed later, for example
+//! with `nomt-cli repro <file>`. Repro files are text, one line per batch or write, so that they can
+//! be attached to bug reports and shrunk further by hand:
+//!
+//! ```text
+//! nomt-repro 1
+//! batch
+//! put <key path> <value>
+//! delete <key path>
+//! ```
+//!
+//! Key paths and values are lowercase hex. Every batch starts with a `batch` line, followed by its
+//! writes in order.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write as _},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use nomt_core::{trie::KeyPath, update::build_trie};
+
+use crate::{HashAlgorithm, KeyReadWrite, Nomt, Root, SessionParams, Value};
+
+/// The maximum number of candidate sequences replayed while shrinking a divergence.
+pub const MAX_SHRINK_ATTEMPTS: usize = 256;
+
+/// A batch of writes, where `None` deletes the key. A later write to the same key in the batch
+/// overrides an earlier one.
+pub type Batch = Vec<(KeyPath, Option<Value>)>;
+
+const REPRO_HEADER: &str = "nomt-repro 1";
+
+/// A sequence of batches which reproduces a divergence when committed to a fresh database.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Repro {
+    /// The batches, in the order they are committed.
+    pub batches: Vec<Batch>,
+}
+
+impl Repro {
+    /// Parse a repro from its text representation.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let bad_line = |number: usize, line: &str| {
+            anyhow::anyhow!("invalid repro line {}: {:?}", number + 1, line)
+        };
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, line)) if line == REPRO_HEADER => {}
+            Some((number, line)) => return Err(bad_line(number, line)),
+            None => anyhow::bail!("empty repro"),
+        }
+
+        let mut batches: Vec<Batch> = Vec::new();
+        for (number, line) in lines {
+            if line == "batch" {
+                batches.push(Vec::new());
+                continue;
+            }
+            let write = if let Some(write) = line.strip_prefix("put ") {
+                write.split_once(' ').and_then(|(key, value)| {
+                    Some((parse_hex::<32>(key)?, Some(parse_hex_vec(value)?)))
+                })
+            } else if let Some(key) = line.strip_prefix("delete ") {
+                parse_hex::<32>(key).map(|key| (key, None))
+            } else {
+                None
+            };
+            match (write, batches.last_mut()) {
+                (Some(write), Some(batch)) => batch.push(write),
+                _ => return Err(bad_line(number, line)),
+            }
+        }
+        Ok(Repro { batches })
+    }
+
+    /// The text representation of the repro.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        out.push_str(REPRO_HEADER);
+        out.push('\n');
+        for batch in &self.batches {
+            out.push_str("batch\n");
+            for (key, value) in batch {
+                match value {
+                    Some(value) => {
+                        let _ = writeln!(out, "put {} {}", hex(key), hex(value));
+                    }
+                    None => {
+                        let _ = writeln!(out, "delete {}", hex(key));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Read a repro from a file.
+    pub fn read(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("failed to read repro {}: {}", path.display(), e))?;
+        Self::parse(&text)
+    }
+
+    /// Write the repro to a file.
+    pub fn write(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_text())?;
+        Ok(())
+    }
+
+    /// The total number of writes in all batches.
+    pub fn writes(&self) -> usize {
+        self.batches.iter().map(Vec::len).sum()
+    }
+}
+
+/// An implementation of the state which NOMT is compared against.
+pub trait Reference {
+    /// Apply a batch of writes.
+    fn apply(&mut self, batch: &[(KeyPath, Option<Value>)]);
+    /// Read the value of a key.
+    fn read(&self, key: &KeyPath) -> Option<Value>;
+    /// The root of the trie holding the state.
+    fn root(&self) -> Root;
+}
+
+/// A reference holding the state in a sorted map and building the trie from scratch for its root.
+pub struct BTreeReference<H> {
+    state: BTreeMap<KeyPath, Value>,
+    _marker: PhantomData<H>,
+}
+
+impl<H> Default for BTreeReference<H> {
+    fn default() -> Self {
+        BTreeReference {
+            state: BTreeMap::new(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<H: HashAlgorithm> Reference for BTreeReference<H> {
+    fn apply(&mut self, batch: &[(KeyPath, Option<Value>)]) {
+        for (key, value) in batch {
+            match value {
+                Some(value) => self.state.insert(*key, value.clone()),
+                None => self.state.remove(key),
+            };
+        }
+    }
+
+    fn read(&self, key: &KeyPath) -> Option<Value> {
+        self.state.get(key).cloned()
+    }
+
+    fn root(&self) -> Root {
+        let ops = self
+            .state
+            .iter()
+            .map(|(key, value)| (*key, H::hash_value(value)));
+        Root::from(build_trie::<H>(0, ops, |_| {}))
+    }
+}
+
+/// The way NOMT diverged from the reference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DivergenceKind {
+    /// The roots differ after the commit.
+    Root {
+        /// The root of the database.
+        nomt: Root,
+        /// The root of the reference.
+        reference: Root,
+    },
+    /// A key reads back a different value.
+    Read {
+        /// The key read.
+        key: KeyPath,
+        /// The value read from the database.
+        nomt: Option<Value>,
+        /// The value read from the reference.
+        reference: Option<Value>,
+    },
+    /// The database returned an error.
+    Error(String),
+}
+
+/// The first divergence of NOMT from the reference.
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    /// The index of the batch whose commit diverged, or the number of batches committed before a
+    /// diverging read.
+    pub batch: usize,
+    /// How the database diverged on that batch.
+    pub kind: DivergenceKind,
+    /// A sequence of batches which diverges too when committed to a fresh database and reference.
+    /// For a divergence found on commit, its last batch is the one which diverges. For one found by
+    /// [`Differential::read`], it is every batch committed so far, unshrunk.
+    pub repro: Repro,
+    /// The file the repro was written to. See [`Differential::write_repros_to`].
+    pub repro_file: Option<PathBuf>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "divergence at batch {}: ", self.batch)?;
+        match &self.kind {
+            DivergenceKind::Root { nomt, reference } => write!(
+                f,
+                "root {} differs from reference root {}",
+                hex(&nomt.into_inner()),
+                hex(&reference.into_inner())
+            )?,
+            DivergenceKind::Read {
+                key,
+                nomt,
+                reference,
+            } => write!(
+                f,
+                "key {} reads {:?}, reference reads {:?}",
+                hex(key),
+                nomt.as_deref().map(hex),
+                reference.as_deref().map(hex)
+            )?,
+            DivergenceKind::Error(e) => write!(f, "{}", e)?,
+        }
+        write!(
+            f,
+            " (repro: {} batches, {} writes",
+            self.repro.batches.len(),
+            self.repro.writes()
+        )?;
+        if let Some(path) = &self.repro_file {
+            write!(f, ", written to {}", path.display())?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+/// Opens a fresh, empty database for every call.
+pub type OpenFn<T> = Box<dyn FnMut() -> anyhow::Result<Nomt<T>>>;
+
+/// Runs every batch against both a database and a reference, and compares them after each commit.
+pub struct Differential<T: HashAlgorithm, R = BTreeReference<T>> {
+    open: OpenFn<T>,
+    nomt: Nomt<T>,
+    reference: R,
+    history: Vec<Batch>,
+    repro_dir: Option<PathBuf>,
+}
+
+impl<T: HashAlgorithm, R: Reference + Default> Differential<T, R> {
+    /// Create a differential runner. `open` must return a fresh, empty database on every call:
+    /// it is called once now, and once for every candidate replayed while shrinking a divergence.
+    pub fn new(
+        mut open: impl FnMut() -> anyhow::Result<Nomt<T>> + 'static,
+    ) -> anyhow::Result<Self> {
+        let nomt = open()?;
+        Ok(Differential {
+            open: Box::new(open),
+            nomt,
+            reference: R::default(),
+            history: Vec::new(),
+            repro_dir: None,
+        })
+    }
+
+    /// Write the repro of a divergence to a new file in `dir`, which must exist, when it is
+    /// reported.
+    pub fn write_repros_to(&mut self, dir: impl Into<PathBuf>) {
+        self.repro_dir = Some(dir.into());
+    }
+
+    /// The database under test.
+    pub fn nomt(&self) -> &Nomt<T> {
+        &self.nomt
+    }
+
+    /// The reference.
+    pub fn reference(&self) -> &R {
+        &self.reference
+    }
+
+    /// Commit a batch to both the database and the reference and compare them, returning the
+    /// common root.
+    ///
+    /// Once a divergence has been reported, the runner must not be used any further.
+    pub fn commit(&mut self, batch: Batch) -> Result<Root, Box<Divergence>> {
+        let index = self.history.len();
+        for (key, value) in &batch {
+            tracing::trace!(
+                target: "nomt::differential",
+                batch = index,
+                key = hex(key),
+                value = value.as_deref().map(hex),
+            );
+        }
+        let result = commit_and_compare(&self.nomt, &mut self.reference, &batch);
+        tracing::debug!(
+            target: "nomt::differential",
+            batch = index,
+            writes = batch.len(),
+            root = result.as_ref().ok().map(|root| hex(&root.into_inner())),
+        );
+        self.history.push(batch);
+        match result {
+            Ok(root) => Ok(root),
+            Err(kind) => Err(self.divergence(kind)),
+        }
+    }
+
+    /// Read a key from both the database and the reference and compare the values.
+    pub fn read(&mut self, key: KeyPath) -> Result<Option<Value>, Box<Divergence>> {
+        compare_read(&self.nomt, &self.reference, key).map_err(|kind| {
+            let repro = Repro {
+                batches: std::mem::take(&mut self.history),
+            };
+            self.report(repro.batches.len(), kind, repro)
+        })
+    }
+
+    fn divergence(&mut self, kind: DivergenceKind) -> Box<Divergence> {
+        let history = std::mem::take(&mut self.history);
+        let batch = history.len() - 1;
+        let mut shrinker = Shrinker {
+            open: &mut self.open,
+            attempts: 0,
+            _marker: PhantomData::<R>,
+        };
+        let repro = Repro {
+            batches: shrinker.shrink(history),
+        };
+        self.report(batch, kind, repro)
+    }
+
+    fn report(&self, batch: usize, kind: DivergenceKind, repro: Repro) -> Box<Divergence> {
+        let repro_file = self.repro_dir.as_ref().and_then(|dir| {
+            let millis = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis());
+            let path = dir.join(format!("divergence-{}-{}.repro", millis, batch));
+            match repro.write(&path) {
+                Ok(()) => Some(path),
+                Err(e) => {
+                    tracing::warn!(
+                        target: "nomt::differential",
+                        "failed to write repro to {}: {:#}",
+                        path.display(),
+                        e,
+                    );
+                    None
+                }
+            }
+        });
+        let divergence = Divergence {
+            batch,
+            kind,
+            repro,
+            repro_file,
+        };
+        tracing::warn!(target: "nomt::differential", "{}", divergence);
+        Box::new(divergence)
+    }
+}
+
+// Commit the batch to both and compare the roots and the values of the keys written.
+fn commit_and_compare<T: HashAlgorithm>(
+    nomt: &Nomt<T>,
+    reference: &mut impl Reference,
+    batch: &[(KeyPath, Option<Value>)],
+) -> Result<Root, DivergenceKind> {
+    // the last write to a key wins.
+    let writes: BTreeMap<KeyPath, Option<Value>> = batch.iter().cloned().collect();
+    let actuals = writes
+        .iter()
+        .map(|(key, value)| (*key, KeyReadWrite::Write(value.clone())))
+        .collect();
+    let error = |e: anyhow::Error| DivergenceKind::Error(format!("{:#}", e));
+    nomt.begin_session(SessionParams::default())
+        .finish(actuals)
+        .map_err(error)?
+        .commit(nomt)
+        .map_err(error)?;
+    reference.apply(batch);
+
+    let (root, reference_root) = (nomt.root(), reference.root());
+    if root != reference_root {
+        return Err(DivergenceKind::Root {
+            nomt: root,
+            reference: reference_root,
+        });
+    }
+    for key in writes.keys() {
+        compare_read(nomt, reference, *key)?;
+    }
+    Ok(root)
+}
+
+fn compare_read<T: HashAlgorithm>(
+    nomt: &Nomt<T>,
+    reference: &impl Reference,
+    key: KeyPath,
+) -> Result<Option<Value>, DivergenceKind> {
+    let value = nomt
+        .read(key)
+        .map_err(|e| DivergenceKind::Error(format!("{:#}", e)))?;
+    let reference_value = reference.read(&key);
+    if value != reference_value {
+        return Err(DivergenceKind::Read {
+            key,
+            nomt: value,
+            reference: reference_value,
+        });
+    }
+    Ok(value)
+}
+
+// Shrinks a diverging sequence of batches by replaying smaller candidates.
+struct Shrinker<'a, T: HashAlgorithm, R> {
+    open: &'a mut OpenFn<T>,
+    attempts: usize,
+    _marker: PhantomData<R>,
+}
+
+impl<T: HashAlgorithm, R: Reference + Default> Shrinker<'_, T, R> {
+    // Shrink the sequence in three steps: collapse the batches before the diverging one into the
+    // single batch setting up the same state, then drop writes from the diverging batch, then drop
+    // writes from the setup batch. Only candidates whose last batch diverges are kept.
+    fn shrink(&mut self, mut batches: Vec<Batch>) -> Vec<Batch> {
+        // UNWRAP: the diverging batch is always in the sequence.
+        let last = batches.pop().unwrap();
+        let mut setup = {
+            let mut state = BTreeMap::new();
+            for (key, value) in batches.iter().flatten() {
+                state.insert(*key, value.clone());
+            }
+            state
+                .into_iter()
+                .filter(|(_, value)| value.is_some())
+                .collect::<Batch>()
+        };
+        let mut prefix = batches;
+
+        if self.diverges(&[], &last) {
+            prefix = Vec::new();
+            setup = Vec::new();
+        } else if self.diverges(&[setup.clone()], &last) {
+            prefix = vec![setup.clone()];
+        } else {
+            // the divergence depends on the history, not just the state.
+            setup = Vec::new();
+        }
+
+        let last = self.shrink_batch(last, |last| (prefix.clone(), last.to_vec()));
+        if !setup.is_empty() {
+            let setup = self.shrink_batch(setup, |setup| {
+                let prefix = if setup.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![setup.to_vec()]
+                };
+                (prefix, last.clone())
+            });
+            prefix = if setup.is_empty() {
+                Vec::new()
+            } else {
+                vec![setup]
+            };
+        }
+
+        prefix.push(last);
+        prefix
+    }
+
+    // Remove chunks of writes from the batch, halving the chunk size down to single writes, as
+    // long as the candidate built from the remaining writes diverges.
+    fn shrink_batch(
+        &mut self,
+        mut batch: Batch,
+        candidate: impl Fn(&[(KeyPath, Option<Value>)]) -> (Vec<Batch>, Batch),
+    ) -> Batch {
+        let mut chunk = batch.len().div_ceil(2).max(1);
+        loop {
+            let mut start = 0;
+            while start < batch.len() && batch.len() > 1 {
+                let end = (start + chunk).min(batch.len());
+                let mut smaller = batch.clone();
+                smaller.drain(start..end);
+                let (prefix, last) = candidate(&smaller);
+                if self.diverges(&prefix, &last) {
+                    batch = smaller;
+                } else {
+                    start = end;
+                }
+            }
+            if chunk == 1 || self.attempts >= MAX_SHRINK_ATTEMPTS {
+                return batch;
+            }
+            chunk = chunk.div_ceil(2);
+        }
+    }
+
+    // Whether committing the batches to a fresh database and reference diverges on the last one.
+    // Failing to open a database or running out of attempts counts as not diverging, so that the
+    // candidate is rejected.
+    fn diverges(&mut self, prefix: &[Batch], last: &[(KeyPath, Option<Value>)]) -> bool {
+        if self.attempts >= MAX_SHRINK_ATTEMPTS {
+            return false;
+        }
+        self.attempts += 1;
+        let Ok(nomt) = (self.open)() else {
+            return false;
+        };
+        let mut reference = R::default();
+        for batch in prefix {
+            if commit_and_compare(&nomt, &mut reference, batch).is_err() {
+                return false;
+            }
+        }
+        commit_and_compare(&nomt, &mut reference, last).is_err()
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex_vec(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_hex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    parse_hex_vec(s)?.try_into().ok()
+}
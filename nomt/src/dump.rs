@@ -0,0 +1,432 @@
+//! Flat dumps of the key-value state.
+//!
+//! A dump consists of a header followed by a body holding every (key, value) pair in key order.
+//!
+//! The header is never compressed and is laid out as:
+//!   - `MAGIC` (8 bytes)
+//!   - format version (1 byte)
+//!   - compression (1 byte): 0 for none, 1 for zstd
+//!   - root (32 bytes)
+//!
+//! Each entry of the body is laid out as:
+//!   - key path (32 bytes)
+//!   - value length (4 bytes, little-endian)
+//!   - value
+//!
+//! The body ends at the end of the file. If compression is used, the entire body is a single
+//! compressed stream.
+//!
+//! Dumps are produced by [`crate::Nomt::export`] and loaded by [`crate::Nomt::import`].
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    beatree::{self, iterator::IterOutput},
+    manifest,
+    store::Store,
+    trie::KeyPath,
+    HashAlgorithm, KeyReadWrite, Nomt, Root, SessionParams, Value,
+};
+
+/// The magic bytes at the beginning of every dump.
+pub const MAGIC: [u8; 8] = *b"NOMTDUMP";
+
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 8 + 1 + 1 + 32;
+
+// The number of entries committed at once during an import.
+const IMPORT_BATCH_SIZE: usize = 64 * 1024;
+
+/// The compression applied to the body of a dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// The body is not compressed.
+    None,
+    /// The body is compressed with zstd.
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    fn to_byte(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => 1,
+        }
+    }
+
+    fn from_byte(byte: u8) -> anyhow::Result<Self> {
+        match byte {
+            0 => Ok(Compression::None),
+            #[cfg(feature = "zstd")]
+            1 => Ok(Compression::Zstd),
+            #[cfg(not(feature = "zstd"))]
+            1 => anyhow::bail!("dump is compressed with zstd, but the zstd feature is disabled"),
+            _ => anyhow::bail!("unknown dump compression {}", byte),
+        }
+    }
+}
+
+/// The header of a dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    /// The root of the trie the dump was taken from.
+    pub root: Root,
+    /// The compression applied to the body.
+    pub compression: Compression,
+}
+
+impl Header {
+    fn encode(&self) -> [u8; HEADER_LEN] {
+        let mut buf = [0; HEADER_LEN];
+        buf[..8].copy_from_slice(&MAGIC);
+        buf[8] = VERSION;
+        buf[9] = self.compression.to_byte();
+        buf[10..].copy_from_slice(&self.root.into_inner());
+        buf
+    }
+
+    fn decode(buf: &[u8; HEADER_LEN]) -> anyhow::Result<Self> {
+        if buf[..8] != MAGIC {
+            anyhow::bail!("not a NOMT dump");
+        }
+        if buf[8] != VERSION {
+            anyhow::bail!("unsupported dump version {}", buf[8]);
+        }
+        let compression = Compression::from_byte(buf[9])?;
+        let mut root = [0; 32];
+        root.copy_from_slice(&buf[10..]);
+        Ok(Header {
+            root: Root(root),
+            compression,
+        })
+    }
+}
+
+enum BodyWriter<W: Write> {
+    Plain(W),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> BodyWriter<W> {
+    fn new(inner: W, compression: Compression) -> std::io::Result<Self> {
+        Ok(match compression {
+            Compression::None => BodyWriter::Plain(inner),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => BodyWriter::Zstd(zstd::Encoder::new(inner, 0)?),
+        })
+    }
+
+    fn finish(self) -> std::io::Result<W> {
+        match self {
+            BodyWriter::Plain(inner) => Ok(inner),
+            #[cfg(feature = "zstd")]
+            BodyWriter::Zstd(encoder) => encoder.finish(),
+        }
+    }
+
+    fn as_write(&mut self) -> &mut dyn Write {
+        match self {
+            BodyWriter::Plain(ref mut inner) => inner,
+            #[cfg(feature = "zstd")]
+            BodyWriter::Zstd(ref mut encoder) => encoder,
+        }
+    }
+
+    fn write_entry(&mut self, key: &KeyPath, value: &[u8]) -> std::io::Result<()> {
+        let value_len: u32 = value
+            .len()
+            .try_into()
+            .map_err(|_| std::io::Error::other("value too large for dump"))?;
+        let writer = self.as_write();
+        writer.write_all(key)?;
+        writer.write_all(&value_len.to_le_bytes())?;
+        writer.write_all(value)
+    }
+}
+
+/// Write all (key, value) pairs visible through the read transaction to a dump at `path`, along
+/// with its manifest.
+///
+/// `root` must be the root of the trie at the time the read transaction was created.
+/// Returns the number of exported entries.
+pub(crate) fn export(
+    store: &Store,
+    read_tx: beatree::ReadTransaction,
+    root: Root,
+    path: &Path,
+    compression: Compression,
+) -> anyhow::Result<u64> {
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(&Header { root, compression }.encode())?;
+    let mut body = BodyWriter::new(file, compression)?;
+
+    let exported = for_each_entry(store, &read_tx, |key, value| {
+        body.write_entry(key, value).map_err(Into::into)
+    })?;
+
+    let mut file = body.finish()?;
+    file.flush()?;
+    file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    manifest::emit(path, root)?;
+
+    Ok(exported)
+}
+
+/// Visit all (key, value) pairs visible through the read transaction, in key order.
+/// Returns the number of visited entries.
+pub(crate) fn for_each_entry(
+    store: &Store,
+    read_tx: &beatree::ReadTransaction,
+    mut visit: impl FnMut(&KeyPath, &[u8]) -> anyhow::Result<()>,
+) -> anyhow::Result<u64> {
+    let mut iterator = read_tx.iterator(beatree::Key::default(), None);
+    let io_handle = store.io_pool().make_handle();
+    let mut visited = 0;
+
+    loop {
+        match iterator.next() {
+            None => break,
+            Some(IterOutput::Blocked) => {
+                // UNWRAP: when blocked, needed leaf always exists.
+                let leaf = match read_tx.load_leaf_async(
+                    iterator.needed_leaves().next().unwrap(),
+                    &io_handle,
+                    0,
+                ) {
+                    Ok(leaf_node) => leaf_node,
+                    Err(leaf_load) => {
+                        // UNWRAP: `Err` indicates a request was sent.
+                        let complete_io = io_handle.recv().unwrap();
+                        complete_io.result?;
+
+                        // UNWRAP: the I/O command submitted by `load_leaf_async` is always a `Read`
+                        leaf_load.finish(complete_io.command.kind.unwrap_buf())
+                    }
+                };
+
+                iterator.provide_leaf(leaf);
+            }
+            Some(IterOutput::Item(key, value)) => {
+                visit(&key, value)?;
+                visited += 1;
+            }
+            Some(IterOutput::OverflowItem(key, _, cell)) => {
+                let value = read_tx.read_overflow(cell);
+                visit(&key, &value)?;
+                visited += 1;
+            }
+        }
+    }
+
+    Ok(visited)
+}
+
+/// Visit the keys visible through the read transaction starting at `start`, in order, until the
+/// visitor returns `false`. Returns `true` if every key was visited.
+///
+/// Unlike [`for_each_entry`], this doesn't read overflow values.
+pub(crate) fn scan_keys(
+    store: &Store,
+    read_tx: &beatree::ReadTransaction,
+    start: KeyPath,
+    mut visit: impl FnMut(KeyPath) -> bool,
+) -> anyhow::Result<bool> {
+    let mut iterator = read_tx.iterator(start, None);
+    let io_handle = store.io_pool().make_handle();
+
+    loop {
+        let key = match iterator.next() {
+            None => return Ok(true),
+            Some(IterOutput::Blocked) => {
+                // UNWRAP: when blocked, needed leaf always exists.
+                let leaf = match read_tx.load_leaf_async(
+                    iterator.needed_leaves().next().unwrap(),
+                    &io_handle,
+                    0,
+                ) {
+                    Ok(leaf_node) => leaf_node,
+                    Err(leaf_load) => {
+                        // UNWRAP: `Err` indicates a request was sent.
+                        let complete_io = io_handle.recv().unwrap();
+                        complete_io.result?;
+
+                        // UNWRAP: the I/O command submitted by `load_leaf_async` is always a `Read`
+                        leaf_load.finish(complete_io.command.kind.unwrap_buf())
+                    }
+                };
+
+                iterator.provide_leaf(leaf);
+                continue;
+            }
+            Some(IterOutput::Item(key, _)) => key,
+            Some(IterOutput::OverflowItem(key, _, _)) => key,
+        };
+
+        if !visit(key) {
+            return Ok(false);
+        }
+    }
+}
+
+/// Load the dump at `path` into an empty database. Returns the number of imported entries.
+///
+/// The root of the dump is computed and checked against the header before anything is written.
+pub(crate) fn import<T: HashAlgorithm>(nomt: &Nomt<T>, path: &Path) -> anyhow::Result<u64> {
+    if !nomt.is_empty() {
+        anyhow::bail!("import: database is not empty");
+    }
+
+    // First pass: verify ordering and the root without touching the database.
+    let mut reader = Reader::open(path)?;
+    let expected_root = reader.header().root;
+    let mut error = None;
+    let mut prev_key: Option<KeyPath> = None;
+    let ops = std::iter::from_fn(|| match reader.next_entry() {
+        Ok(Some((key, _))) if prev_key.is_some_and(|prev| prev >= key) => {
+            error = Some(anyhow::anyhow!(
+                "import: dump entries are not in strict key order"
+            ));
+            None
+        }
+        Ok(Some((key, value))) => {
+            prev_key = Some(key);
+            Some((key, T::hash_value(&value)))
+        }
+        Ok(None) => None,
+        Err(e) => {
+            error = Some(e);
+            None
+        }
+    });
+    let computed_root = nomt_core::update::build_trie::<T>(0, ops, |_| {});
+    if let Some(e) = error {
+        return Err(e);
+    }
+    if computed_root != expected_root.into_inner() {
+        anyhow::bail!(
+            "import: root mismatch. header: {}, computed: {}",
+            expected_root,
+            Root(computed_root),
+        );
+    }
+
+    // Second pass: load the entries in batches.
+    let mut reader = Reader::open(path)?;
+    let mut imported = 0;
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    loop {
+        let entry = reader.next_entry()?;
+        let done = entry.is_none();
+        if let Some((key, value)) = entry {
+            batch.push((key, KeyReadWrite::Write(Some(value))));
+        }
+
+        if batch.len() == IMPORT_BATCH_SIZE || (done && !batch.is_empty()) {
+            imported += batch.len() as u64;
+            let session = nomt.begin_session(SessionParams::default());
+            session.finish(std::mem::take(&mut batch))?.commit(nomt)?;
+        }
+
+        if done {
+            break;
+        }
+    }
+
+    if nomt.root() != expected_root {
+        anyhow::bail!(
+            "import: root mismatch after import. header: {}, database: {}",
+            expected_root,
+            nomt.root(),
+        );
+    }
+
+    Ok(imported)
+}
+
+/// A reader over the entries of a dump, in key order.
+pub struct Reader {
+    header: Header,
+    body: Box<dyn Read + Send>,
+}
+
+impl Reader {
+    /// Open the dump at the given path and read its header.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut header = [0; HEADER_LEN];
+        file.read_exact(&mut header)?;
+        let header = Header::decode(&header)?;
+
+        let body: Box<dyn Read + Send> = match header.compression {
+            Compression::None => Box::new(file),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Box::new(zstd::Decoder::with_buffer(file)?),
+        };
+
+        Ok(Reader { header, body })
+    }
+
+    /// The header of the dump.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Read the next entry of the dump. Returns `None` once all entries have been read.
+    pub fn next_entry(&mut self) -> anyhow::Result<Option<(KeyPath, Value)>> {
+        let mut key = [0; 32];
+        let mut read = 0;
+        while read < key.len() {
+            match self.body.read(&mut key[read..])? {
+                0 if read == 0 => return Ok(None),
+                0 => anyhow::bail!("dump truncated in the middle of an entry"),
+                n => read += n,
+            }
+        }
+
+        let mut value_len = [0; 4];
+        self.body.read_exact(&mut value_len)?;
+        let mut value = vec![0; u32::from_le_bytes(value_len) as usize];
+        self.body.read_exact(&mut value)?;
+        Ok(Some((key, value)))
+    }
+}
+
+impl Iterator for Reader {
+    type Item = anyhow::Result<(KeyPath, Value)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Compression, Header, HEADER_LEN, MAGIC};
+    use crate::Root;
+
+    #[test]
+    fn header_roundtrip() {
+        let header = Header {
+            root: Root([7; 32]),
+            compression: Compression::None,
+        };
+        let encoded = header.encode();
+        assert_eq!(encoded.len(), HEADER_LEN);
+        assert_eq!(Header::decode(&encoded).unwrap(), header);
+
+        let mut bad_magic = encoded;
+        bad_magic[0] = !MAGIC[0];
+        assert!(Header::decode(&bad_magic).is_err());
+
+        let mut bad_compression = encoded;
+        bad_compression[9] = 0xff;
+        assert!(Header::decode(&bad_compression).is_err());
+    }
+}
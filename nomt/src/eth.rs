@@ -0,0 +1,105 @@
+//! Generation of `eth_getProof`-shaped proofs.
+//!
+//! See [`nomt_core::eth`] for the key mapping and the translation of NOMT proofs into lists of
+//! node preimages.
+
+use nomt_core::eth as core_eth;
+
+use crate::{HashAlgorithm, KeyReadWrite, Nomt, Root, SessionParams, Value, WitnessMode};
+
+pub use nomt_core::eth::{
+    account_key_path, proof_nodes, storage_key_path, verify_proof_nodes, Address, ProofNodesError,
+    StorageSlot,
+};
+
+/// A proof of an account and some of its storage slots, mirroring an `eth_getProof` response.
+#[derive(Debug, Clone)]
+pub struct EthProof {
+    /// The root the proof was generated against.
+    pub root: Root,
+    /// The address of the account.
+    pub address: Address,
+    /// The value stored for the account, if any.
+    pub account_value: Option<Value>,
+    /// The node preimages along the path to the account, from the root downwards.
+    pub account_proof: Vec<Vec<u8>>,
+    /// Proofs of the requested storage slots, in the order they were requested.
+    pub storage_proof: Vec<EthStorageProof>,
+}
+
+/// A proof of a single storage slot.
+#[derive(Debug, Clone)]
+pub struct EthStorageProof {
+    /// The storage slot.
+    pub key: StorageSlot,
+    /// The value stored in the slot, if any.
+    pub value: Option<Value>,
+    /// The node preimages along the path to the slot, from the root downwards.
+    pub proof: Vec<Vec<u8>>,
+}
+
+/// Prove an account and a set of its storage slots against the current root of the database.
+///
+/// This will block if there are any ongoing commits or rollbacks.
+pub fn get_proof<T: HashAlgorithm>(
+    nomt: &Nomt<T>,
+    address: Address,
+    slots: &[StorageSlot],
+) -> anyhow::Result<EthProof> {
+    let account_key = core_eth::account_key_path(&address);
+    let slot_keys: Vec<_> = slots
+        .iter()
+        .map(|slot| core_eth::storage_key_path(&address, slot))
+        .collect();
+
+    let session =
+        nomt.begin_session(SessionParams::default().witness_mode(WitnessMode::read_write()));
+
+    let mut reads = Vec::with_capacity(slot_keys.len() + 1);
+    for key in std::iter::once(account_key).chain(slot_keys.iter().copied()) {
+        session.warm_up(key);
+        let value = session.read(key)?;
+        reads.push((key, KeyReadWrite::Read(value)));
+    }
+    reads.sort_by_key(|(key, _)| *key);
+    reads.dedup_by_key(|(key, _)| *key);
+
+    let mut finished = session.finish(reads.clone())?;
+    let root = finished.root();
+    // UNWRAP: the session was created with a witness.
+    let witness = finished.take_witness().unwrap();
+
+    // Find the proof and value for a key. Every read key is witnessed.
+    let prove = |key: &[u8; 32]| -> (Option<Value>, Vec<Vec<u8>>) {
+        // UNWRAP: `reads` is sorted and contains every key.
+        let index = reads.binary_search_by_key(key, |(k, _)| *k).unwrap();
+        let value = reads[index].1.last_value().map(|v| v.to_vec());
+        // UNWRAP: witnessed reads are in the same order as `reads`.
+        let witnessed = &witness.operations.reads[index];
+        debug_assert_eq!(&witnessed.key, key);
+        let path = &witness.path_proofs[witnessed.path_index];
+        (value, core_eth::proof_nodes::<T>(&path.inner))
+    };
+
+    let (account_value, account_proof) = prove(&account_key);
+    let storage_proof = slots
+        .iter()
+        .zip(&slot_keys)
+        .map(|(slot, key)| {
+            let (value, proof) = prove(key);
+            EthStorageProof {
+                key: *slot,
+                value,
+                proof,
+            }
+        })
+        .collect();
+
+    Ok(EthProof {
+        root,
+        address,
+        account_value,
+        account_proof,
+        storage_proof,
+    })
+}
@@ -0,0 +1,314 @@
+//! An index of the commit which last wrote every key, for expiring state.
+//!
+//! When enabled with [`crate::Options::expiry_index`], every commit records the sequence number
+//! it is assigned (see [`crate::Nomt::current_sequence`]) against the keys it inserts or updates
+//! and drops the keys it deletes. The index is kept in memory ordered by sequence number, so the
+//! keys last written before a given commit can be enumerated oldest first without scanning the
+//! trie, e.g. by embedders charging state rent.
+//!
+//! The index is persisted in the `expiry_index` file of the database directory. The file begins
+//! with `MAGIC` and a version byte, followed by records. Each record is laid out as:
+//!   - payload length (8 bytes, little-endian)
+//!   - sequence number of the commit (8 bytes, little-endian)
+//!   - number of keys (4 bytes, little-endian)
+//!   - keys, each made of a key path (32 bytes) and a tag (1 byte) which is 0 for deletions and
+//!     1 for writes.
+//!
+//! A record is appended before the commit it describes is synced. When opening, records of
+//! commits which didn't land and a torn record at the end of the file are discarded. The file is
+//! rewritten with one record per live sequence number once it has grown to twice the size it
+//! would have when rewritten.
+//!
+//! Enabling the index on a database with keys records all of them as written by the last commit.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::{File, OpenOptions},
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use parking_lot::Mutex;
+
+use crate::{beatree::ValueChange, dump, store::Store, trie::KeyPath};
+
+const EXPIRY_INDEX_FILE: &str = "expiry_index";
+const EXPIRY_INDEX_TMP_FILE: &str = "expiry_index.tmp";
+const MAGIC: [u8; 8] = *b"NOMTEXPI";
+const VERSION: u8 = 1;
+const HEADER_LEN: u64 = 9;
+const KEY_ENTRY_LEN: u64 = 33;
+const RECORD_HEADER_LEN: u64 = 8 + 8 + 4;
+
+/// The keys written by a commit, to be applied to the index once the commit is synced.
+pub(crate) struct Touched {
+    sequence: u64,
+    keys: Vec<(KeyPath, bool)>,
+}
+
+pub(crate) struct ExpiryIndex {
+    db_dir_path: PathBuf,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    file: File,
+    file_len: u64,
+    by_key: HashMap<KeyPath, u64>,
+    by_sequence: BTreeMap<u64, BTreeSet<KeyPath>>,
+}
+
+impl ExpiryIndex {
+    /// Open the index of the database at `db_dir_path`, whose last commit has the given sequence
+    /// number, creating it if it doesn't exist.
+    pub fn open(db_dir_path: &Path, store: &Store, sequence: u64) -> anyhow::Result<Self> {
+        let path = db_dir_path.join(EXPIRY_INDEX_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        let mut inner = Inner {
+            file_len: file.metadata()?.len(),
+            file: file.try_clone()?,
+            by_key: HashMap::new(),
+            by_sequence: BTreeMap::new(),
+        };
+
+        if inner.file_len < HEADER_LEN {
+            // the file is new or its creation was interrupted.
+            file.set_len(0)?;
+            let mut header = MAGIC.to_vec();
+            header.push(VERSION);
+            file.write_all(&header)?;
+            inner.file_len = HEADER_LEN;
+
+            // index the keys written before the index was enabled.
+            let mut keys = Vec::new();
+            dump::scan_keys(
+                store,
+                &store.read_transaction(),
+                KeyPath::default(),
+                |key| {
+                    keys.push((key, true));
+                    true
+                },
+            )?;
+            if !keys.is_empty() {
+                let touched = Touched { sequence, keys };
+                inner.append(&touched)?;
+                inner.apply(touched);
+            }
+            file.sync_all()?;
+        } else {
+            let mut reader = RecordReader::new(BufReader::new(&file))?;
+            while let Some(touched) = reader.next_record()? {
+                if touched.sequence > sequence {
+                    // the commit didn't land. neither did any after it.
+                    break;
+                }
+                inner.apply(touched);
+            }
+            let valid_len = reader.offset;
+            if valid_len != inner.file_len {
+                file.set_len(valid_len)?;
+                file.sync_all()?;
+                inner.file_len = valid_len;
+            }
+        }
+        inner.file.seek(SeekFrom::End(0))?;
+
+        Ok(ExpiryIndex {
+            db_dir_path: db_dir_path.to_path_buf(),
+            inner: Mutex::new(inner),
+        })
+    }
+
+    /// Persist the keys written by the commit which will be assigned the given sequence number.
+    ///
+    /// The returned keys must be passed to [`Self::apply`] once the commit is synced.
+    pub fn append(
+        &self,
+        sequence: u64,
+        changes: &[(KeyPath, ValueChange)],
+    ) -> anyhow::Result<Touched> {
+        let touched = Touched {
+            sequence,
+            keys: changes
+                .iter()
+                .map(|(key, change)| (*key, !matches!(change, ValueChange::Delete)))
+                .collect(),
+        };
+        self.inner.lock().append(&touched)?;
+        Ok(touched)
+    }
+
+    /// Apply the keys written by a synced commit to the index.
+    pub fn apply(&self, touched: Touched) -> anyhow::Result<()> {
+        let mut inner = self.inner.lock();
+        inner.apply(touched);
+
+        let compacted_len = inner.compacted_len();
+        if inner.file_len > 2 * compacted_len {
+            inner.compact(&self.db_dir_path)?;
+        }
+        Ok(())
+    }
+
+    /// The sequence number of the commit which last wrote the key, if it exists.
+    pub fn last_written(&self, key: &KeyPath) -> Option<u64> {
+        self.inner.lock().by_key.get(key).copied()
+    }
+
+    /// Up to `limit` keys last written by commits with sequence numbers lower than `sequence`,
+    /// along with those sequence numbers, least recently written first.
+    pub fn written_before(&self, sequence: u64, limit: usize) -> Vec<(KeyPath, u64)> {
+        self.inner
+            .lock()
+            .by_sequence
+            .range(..sequence)
+            .flat_map(|(sequence, keys)| keys.iter().map(move |key| (*key, *sequence)))
+            .take(limit)
+            .collect()
+    }
+}
+
+impl Inner {
+    fn append(&mut self, touched: &Touched) -> anyhow::Result<()> {
+        let record = encode_record(touched.sequence, touched.keys.iter().copied());
+        self.file.write_all(&record)?;
+        self.file.sync_data()?;
+        self.file_len += record.len() as u64;
+        Ok(())
+    }
+
+    fn apply(&mut self, touched: Touched) {
+        for (key, written) in touched.keys {
+            if let Some(prev) = self.by_key.remove(&key) {
+                if let Some(keys) = self.by_sequence.get_mut(&prev) {
+                    keys.remove(&key);
+                    if keys.is_empty() {
+                        self.by_sequence.remove(&prev);
+                    }
+                }
+            }
+            if written {
+                self.by_key.insert(key, touched.sequence);
+                self.by_sequence
+                    .entry(touched.sequence)
+                    .or_default()
+                    .insert(key);
+            }
+        }
+    }
+
+    // The length of the file holding one record per live sequence number.
+    fn compacted_len(&self) -> u64 {
+        HEADER_LEN
+            + self.by_sequence.len() as u64 * RECORD_HEADER_LEN
+            + self.by_key.len() as u64 * KEY_ENTRY_LEN
+    }
+
+    // Replace the file atomically with one holding a record per live sequence number.
+    fn compact(&mut self, db_dir_path: &Path) -> anyhow::Result<()> {
+        let tmp_path = db_dir_path.join(EXPIRY_INDEX_TMP_FILE);
+        let mut buf = MAGIC.to_vec();
+        buf.push(VERSION);
+        for (sequence, keys) in &self.by_sequence {
+            buf.extend(encode_record(
+                *sequence,
+                keys.iter().map(|key| (*key, true)),
+            ));
+        }
+
+        let mut tmp_file = File::create(&tmp_path)?;
+        tmp_file.write_all(&buf)?;
+        tmp_file.sync_all()?;
+        std::fs::rename(&tmp_path, db_dir_path.join(EXPIRY_INDEX_FILE))?;
+        File::open(db_dir_path)?.sync_all()?;
+
+        tmp_file.seek(SeekFrom::End(0))?;
+        self.file = tmp_file;
+        self.file_len = buf.len() as u64;
+        Ok(())
+    }
+}
+
+fn encode_record(sequence: u64, keys: impl ExactSizeIterator<Item = (KeyPath, bool)>) -> Vec<u8> {
+    let payload_len = 8 + 4 + keys.len() as u64 * KEY_ENTRY_LEN;
+    let mut record = Vec::with_capacity(8 + payload_len as usize);
+    record.extend_from_slice(&payload_len.to_le_bytes());
+    record.extend_from_slice(&sequence.to_le_bytes());
+    record.extend_from_slice(&(keys.len() as u32).to_le_bytes());
+    for (key, written) in keys {
+        record.extend_from_slice(&key);
+        record.push(written as u8);
+    }
+    record
+}
+
+// A reader over the records of the index file.
+struct RecordReader<R> {
+    inner: R,
+    // the offset just past the last complete record.
+    offset: u64,
+}
+
+impl<R: Read> RecordReader<R> {
+    fn new(mut inner: R) -> anyhow::Result<Self> {
+        let mut header = [0u8; HEADER_LEN as usize];
+        inner.read_exact(&mut header)?;
+        if header[..8] != MAGIC {
+            anyhow::bail!("expiry index: bad magic");
+        }
+        if header[8] != VERSION {
+            anyhow::bail!("expiry index: unsupported version {}", header[8]);
+        }
+        Ok(RecordReader {
+            inner,
+            offset: HEADER_LEN,
+        })
+    }
+
+    // Read the next record. Returns `None` at the end of the file or at a torn record.
+    fn next_record(&mut self) -> anyhow::Result<Option<Touched>> {
+        let mut len_buf = [0u8; 8];
+        if !read_full(&mut self.inner, &mut len_buf)? {
+            return Ok(None);
+        }
+        let payload_len = u64::from_le_bytes(len_buf);
+        let mut payload = vec![0u8; payload_len as usize];
+        if payload_len < 12 || !read_full(&mut self.inner, &mut payload)? {
+            return Ok(None);
+        }
+
+        // UNWRAP: the payload is at least 12 bytes long.
+        let sequence = u64::from_le_bytes(payload[..8].try_into().unwrap());
+        let count = u32::from_le_bytes(payload[8..12].try_into().unwrap()) as u64;
+        if payload_len != 12 + count * KEY_ENTRY_LEN {
+            anyhow::bail!("expiry index: malformed record at offset {}", self.offset);
+        }
+        let keys = payload[12..]
+            .chunks_exact(KEY_ENTRY_LEN as usize)
+            // UNWRAP: the chunks are a key path followed by a tag.
+            .map(|entry| (entry[..32].try_into().unwrap(), entry[32] != 0))
+            .collect();
+
+        self.offset += 8 + payload_len;
+        Ok(Some(Touched { sequence, keys }))
+    }
+}
+
+// Fill the buffer. Returns `false` if the end of the input is reached first.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..])? {
+            0 => return Ok(false),
+            n => read += n,
+        }
+    }
+    Ok(true)
+}
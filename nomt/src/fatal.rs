@@ -0,0 +1,62 @@
+//! Errors standing in for conditions which would otherwise abort the process.
+//!
+//! Data read from disk is validated before it is used to index into memory or size an
+//! allocation, and malformed data is reported as [`FatalError::Corrupted`].
+//!
+//! Other panics indicate a broken internal invariant. By default they propagate: a panic on a
+//! worker thread is resumed on the thread waiting for the worker. With the `panic-free` feature,
+//! panics on worker threads are instead returned as [`FatalError::Panicked`] by the operation which
+//! was waiting for them, and a panic during a commit poisons the database and is returned the same
+//! way. This lets embedders which must never abort shut down cleanly. The feature requires
+//! panics to unwind, i.e. it has no effect with `panic = "abort"`.
+
+use std::any::Any;
+
+/// An error returned instead of panicking. It can be recovered from the [`anyhow::Error`]s
+/// returned by NOMT with [`anyhow::Error::downcast_ref`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FatalError {
+    /// Data read from disk is malformed.
+    Corrupted(String),
+    /// A panic was caught. Only returned with the `panic-free` feature.
+    Panicked(String),
+}
+
+impl std::fmt::Display for FatalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FatalError::Corrupted(what) => write!(f, "corrupted data: {}", what),
+            FatalError::Panicked(message) => write!(f, "panicked: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for FatalError {}
+
+impl From<FatalError> for std::io::Error {
+    fn from(e: FatalError) -> Self {
+        std::io::Error::other(e)
+    }
+}
+
+/// Run the closure, returning a panic as [`FatalError::Panicked`] with the `panic-free` feature
+/// and propagating it otherwise.
+pub(crate) fn catch<R>(f: impl FnOnce() -> R) -> Result<R, FatalError> {
+    if cfg!(feature = "panic-free") {
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).map_err(from_panic)
+    } else {
+        Ok(f())
+    }
+}
+
+/// Turn the payload of a panic into a [`FatalError::Panicked`] carrying its message.
+pub(crate) fn from_panic(payload: Box<dyn Any + Send>) -> FatalError {
+    let message = match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic payload".to_string(),
+        },
+    };
+    FatalError::Panicked(message)
+}
@@ -0,0 +1,312 @@
+//! Golden fixtures of the stored format.
+//!
+//! A fixture records a set of entries along with the root and the stored pages of the database
+//! holding them, as produced by [`Fixture::generate`]. Fixtures checked in alongside tests make any
+//! change to the page format, the placement of nodes or the hashing show up as an explicit diff of
+//! the fixture files.
+//!
+//! Fixtures are text files meant to be reviewed in diffs:
+//!
+//! ```text
+//! nomt-fixture 1
+//! root <root>
+//! entry <key path> <value>
+//! page <page ID>
+//!   <chunk index> <chunk>
+//! ```
+//!
+//! All byte strings are lowercase hex. Entries are sorted by key path and pages by page ID. A page
+//! ID is written as the child indices leading to the page, separated by slashes, or `root` for
+//! the root page. A page is split into 32-byte chunks, the node slots followed by the header and
+//! the page ID, and only the chunks which aren't all zeros are listed, so that a changed node is a
+//! single changed line.
+
+use std::{collections::BTreeMap, fmt::Write as _, path::Path};
+
+use nomt_core::page_id::{ChildPageIndex, PageId, ROOT_PAGE_ID};
+
+use crate::{
+    io::PAGE_SIZE, page_cache::PageMut, page_utilization::BOTTOM_LAYER_START, store::Store,
+    trie::KeyPath, HashAlgorithm, KeyReadWrite, Nomt, Options, Root, SessionParams, Value,
+};
+
+const HEADER: &str = "nomt-fixture 1";
+const CHUNK_SIZE: usize = 32;
+const CHUNKS_PER_PAGE: usize = PAGE_SIZE / CHUNK_SIZE;
+
+/// A set of entries along with the root and the stored pages of a database holding them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixture {
+    /// The root of the trie.
+    pub root: Root,
+    /// The entries, sorted by key path.
+    pub entries: Vec<(KeyPath, Value)>,
+    /// The raw bytes of every stored page.
+    pub pages: BTreeMap<PageId, Vec<u8>>,
+}
+
+impl Fixture {
+    /// Generate the fixture for a set of entries by committing them to a new database in `dir`,
+    /// which must not exist, and reading back its pages.
+    ///
+    /// The database has a deterministic layout, so the fixture only depends on the entries, the
+    /// hash algorithm and the format of the store.
+    pub fn generate<T: HashAlgorithm>(
+        dir: impl AsRef<Path>,
+        mut entries: Vec<(KeyPath, Value)>,
+    ) -> anyhow::Result<Self> {
+        let dir = dir.as_ref();
+        if dir.exists() {
+            anyhow::bail!("fixture directory {} already exists", dir.display());
+        }
+        entries.sort_by_key(|(key, _)| *key);
+        if entries.windows(2).any(|w| w[0].0 == w[1].0) {
+            anyhow::bail!("fixture entries have duplicate keys");
+        }
+
+        let mut o = Options::new();
+        o.path(dir);
+        o.commit_concurrency(1);
+        o.deterministic_layout(true);
+        o.bitbox_seed([0; 16]);
+        o.hashtable_buckets(1000);
+        let nomt = Nomt::<T>::open(o)?;
+        if !entries.is_empty() {
+            let actuals = entries
+                .iter()
+                .map(|(key, value)| (*key, KeyReadWrite::Write(Some(value.clone()))))
+                .collect();
+            nomt.begin_session(SessionParams::default())
+                .finish(actuals)?
+                .commit(&nomt)?;
+        }
+
+        Ok(Fixture {
+            root: nomt.root(),
+            pages: stored_pages(&nomt.store)?,
+            entries,
+        })
+    }
+
+    /// Parse a fixture from its text representation.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut lines = text.lines().enumerate().peekable();
+        let bad_line = |number: usize, line: &str| {
+            anyhow::anyhow!("invalid fixture line {}: {:?}", number + 1, line)
+        };
+
+        match lines.next() {
+            Some((_, line)) if line == HEADER => {}
+            Some((number, line)) => return Err(bad_line(number, line)),
+            None => anyhow::bail!("empty fixture"),
+        }
+        let root = match lines.next() {
+            Some((number, line)) => line
+                .strip_prefix("root ")
+                .and_then(parse_hex::<32>)
+                .ok_or_else(|| bad_line(number, line))?,
+            None => anyhow::bail!("fixture has no root"),
+        };
+
+        let mut entries = Vec::new();
+        let mut pages = BTreeMap::new();
+        while let Some((number, line)) = lines.next() {
+            if let Some(entry) = line.strip_prefix("entry ") {
+                let (key, value) = entry
+                    .split_once(' ')
+                    .and_then(|(key, value)| Some((parse_hex::<32>(key)?, parse_hex_vec(value)?)))
+                    .ok_or_else(|| bad_line(number, line))?;
+                entries.push((key, value));
+            } else if let Some(page_id) = line.strip_prefix("page ") {
+                let page_id = parse_page_id(page_id).ok_or_else(|| bad_line(number, line))?;
+                let mut page = vec![0; PAGE_SIZE];
+                while let Some((number, line)) = lines.next_if(|(_, line)| line.starts_with("  ")) {
+                    let (index, chunk) = line[2..]
+                        .split_once(' ')
+                        .and_then(|(index, chunk)| {
+                            Some((
+                                index.parse::<usize>().ok()?,
+                                parse_hex::<CHUNK_SIZE>(chunk)?,
+                            ))
+                        })
+                        .filter(|(index, _)| *index < CHUNKS_PER_PAGE)
+                        .ok_or_else(|| bad_line(number, line))?;
+                    page[index * CHUNK_SIZE..(index + 1) * CHUNK_SIZE].copy_from_slice(&chunk);
+                }
+                if pages.insert(page_id, page).is_some() {
+                    return Err(bad_line(number, line));
+                }
+            } else {
+                return Err(bad_line(number, line));
+            }
+        }
+
+        Ok(Fixture {
+            root: Root::from(root),
+            entries,
+            pages,
+        })
+    }
+
+    /// The text representation of the fixture.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{}", HEADER);
+        let _ = writeln!(out, "root {}", hex(&self.root.into_inner()));
+        for (key, value) in &self.entries {
+            let _ = writeln!(out, "entry {} {}", hex(key), hex(value));
+        }
+        for (page_id, page) in &self.pages {
+            let _ = writeln!(out, "page {}", page_id_string(page_id));
+            for (index, chunk) in page.chunks(CHUNK_SIZE).enumerate() {
+                if chunk.iter().any(|byte| *byte != 0) {
+                    let _ = writeln!(out, "  {} {}", index, hex(chunk));
+                }
+            }
+        }
+        out
+    }
+
+    /// Describe the differences from the expected fixture, one per line. Empty if the fixtures
+    /// are equal.
+    pub fn diff(&self, expected: &Fixture) -> Vec<String> {
+        let mut diffs = Vec::new();
+        if self.entries != expected.entries {
+            diffs.push("entries differ".to_string());
+        }
+        if self.root != expected.root {
+            diffs.push(format!(
+                "root: expected {}, got {}",
+                hex(&expected.root.into_inner()),
+                hex(&self.root.into_inner())
+            ));
+        }
+        for (page_id, expected_page) in &expected.pages {
+            let page_name = page_id_string(page_id);
+            let Some(page) = self.pages.get(page_id) else {
+                diffs.push(format!("page {}: missing", page_name));
+                continue;
+            };
+            let chunks = page
+                .chunks(CHUNK_SIZE)
+                .zip(expected_page.chunks(CHUNK_SIZE));
+            for (index, (chunk, expected_chunk)) in chunks.enumerate() {
+                if chunk != expected_chunk {
+                    diffs.push(format!(
+                        "page {} chunk {}: expected {}, got {}",
+                        page_name,
+                        index,
+                        hex(expected_chunk),
+                        hex(chunk)
+                    ));
+                }
+            }
+        }
+        for page_id in self.pages.keys() {
+            if !expected.pages.contains_key(page_id) {
+                diffs.push(format!("page {}: unexpected", page_id_string(page_id)));
+            }
+        }
+        diffs
+    }
+}
+
+// Read every page reachable from the root page.
+fn stored_pages(store: &Store) -> anyhow::Result<BTreeMap<PageId, Vec<u8>>> {
+    let mut pages = BTreeMap::new();
+    let mut stack = vec![ROOT_PAGE_ID];
+    while let Some(page_id) = stack.pop() {
+        let Some((page, _)) = store.load_page(page_id.clone())? else {
+            continue;
+        };
+        let page = PageMut::pristine_with_data(page);
+        for child_index in ChildPageIndex::all() {
+            if page.node(BOTTOM_LAYER_START + usize::from(child_index))
+                == nomt_core::trie::TERMINATOR
+            {
+                continue;
+            }
+            if let Ok(child_page_id) = page_id.child_page_id(child_index) {
+                stack.push(child_page_id);
+            }
+        }
+        pages.insert(page_id, page.page_data().to_vec());
+    }
+    Ok(pages)
+}
+
+fn page_id_string(page_id: &PageId) -> String {
+    if *page_id == ROOT_PAGE_ID {
+        return "root".to_string();
+    }
+    (0..page_id.depth())
+        .map(|level| page_id.child_index_at_level(level).to_u8().to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn parse_page_id(s: &str) -> Option<PageId> {
+    if s == "root" {
+        return Some(ROOT_PAGE_ID);
+    }
+    let mut page_id = ROOT_PAGE_ID;
+    for index in s.split('/') {
+        let index = ChildPageIndex::new(index.parse().ok()?)?;
+        page_id = page_id.child_page_id(index).ok()?;
+    }
+    Some(page_id)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex_vec(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_hex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    parse_hex_vec(s)?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_page_id, Fixture, PAGE_SIZE};
+    use crate::Root;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn text_round_trip() {
+        let mut page = vec![0; PAGE_SIZE];
+        page[0] = 1;
+        page[33] = 0xab;
+        page[PAGE_SIZE - 1] = 7;
+        let mut pages = BTreeMap::new();
+        pages.insert(parse_page_id("root").unwrap(), page.clone());
+        pages.insert(parse_page_id("3/63").unwrap(), page);
+        let fixture = Fixture {
+            root: Root::from([9; 32]),
+            entries: vec![([1; 32], vec![]), ([2; 32], vec![0, 255])],
+            pages,
+        };
+
+        let text = fixture.to_text();
+        assert_eq!(
+            text.lines().filter(|line| line.starts_with("  ")).count(),
+            6
+        );
+        assert!(text.contains("\npage 3/63\n  0 01000000"));
+        assert_eq!(Fixture::parse(&text).unwrap(), fixture);
+        assert!(fixture.diff(&fixture).is_empty());
+
+        assert!(Fixture::parse("nomt-fixture 2\n").is_err());
+        assert!(Fixture::parse(&text.replace("page 3/63", "page 3/64")).is_err());
+        assert!(Fixture::parse(&text.replace("  0 01", "  128 01")).is_err());
+    }
+}
@@ -0,0 +1,207 @@
+//! A tree of uncommitted block candidates, kept as [`Overlay`]s.
+//!
+//! Consensus clients commonly build several competing blocks on top of the last finalized state
+//! before one of them is chosen. [`ForkTree`] keeps each candidate as an overlay, keyed by an
+//! identifier chosen by the user (e.g. the block hash), and remembers the parent of each. It
+//! provides the ancestors needed to build on top of any candidate and commits the chain leading
+//! to a candidate once it becomes canonical.
+//!
+//! Candidates which do not descend from a committed candidate can never be committed, since they
+//! are built on a state that is no longer current, so they are discarded when a chain is
+//! committed. Descendants of the committed candidate are retained.
+
+use crate::{HashAlgorithm, Nomt, Overlay};
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+struct Head<Id> {
+    overlay: Overlay,
+    // `None` if the overlay is built directly on top of the committed state.
+    parent: Option<Id>,
+    children: Vec<Id>,
+}
+
+/// A set of uncommitted heads, each built either on the committed state or on another head.
+pub struct ForkTree<Id> {
+    heads: HashMap<Id, Head<Id>>,
+}
+
+impl<Id> Default for ForkTree<Id> {
+    fn default() -> Self {
+        ForkTree {
+            heads: HashMap::new(),
+        }
+    }
+}
+
+impl<Id: Clone + Eq + Hash + std::fmt::Debug> ForkTree<Id> {
+    /// Create an empty fork tree.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an overlay under the given identifier.
+    ///
+    /// `parent` is the identifier of the head the overlay was built on, or `None` if it was built
+    /// on the committed state. The sessions producing the overlay should use
+    /// [`ForkTree::ancestors`] of the parent, which provides the complete set of live ancestors.
+    ///
+    /// Returns an error if the identifier is already in use, if the parent is unknown, or if the
+    /// overlay was not built on the root of its parent.
+    pub fn insert(&mut self, id: Id, parent: Option<Id>, overlay: Overlay) -> anyhow::Result<()> {
+        if self.heads.contains_key(&id) {
+            anyhow::bail!("Head {:?} already exists", id);
+        }
+        if let Some(ref parent) = parent {
+            let Some(parent_head) = self.heads.get_mut(parent) else {
+                anyhow::bail!("Parent {:?} of head {:?} is unknown", parent, id);
+            };
+            if parent_head.overlay.root() != overlay.prev_root() {
+                anyhow::bail!(
+                    "Head {:?} was not built on its parent {:?} (expected previous root {:?}, got {:?})",
+                    id,
+                    parent,
+                    parent_head.overlay.root(),
+                    overlay.prev_root(),
+                );
+            }
+            parent_head.children.push(id.clone());
+        }
+        self.heads.insert(
+            id,
+            Head {
+                overlay,
+                parent,
+                children: Vec::new(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Get the overlay of a head.
+    pub fn get(&self, id: &Id) -> Option<&Overlay> {
+        self.heads.get(id).map(|head| &head.overlay)
+    }
+
+    /// Whether the tree contains the given head.
+    pub fn contains(&self, id: &Id) -> bool {
+        self.heads.contains_key(id)
+    }
+
+    /// The number of uncommitted heads in the tree.
+    pub fn len(&self) -> usize {
+        self.heads.len()
+    }
+
+    /// Whether the tree is empty.
+    pub fn is_empty(&self) -> bool {
+        self.heads.is_empty()
+    }
+
+    /// The identifiers of the heads which have no children, i.e. the tips of every fork.
+    pub fn tips(&self) -> impl Iterator<Item = &Id> {
+        self.heads
+            .iter()
+            .filter(|(_, head)| head.children.is_empty())
+            .map(|(id, _)| id)
+    }
+
+    /// The parent of a head, or `None` if the head is unknown or built on the committed state.
+    pub fn parent(&self, id: &Id) -> Option<&Id> {
+        self.heads.get(id).and_then(|head| head.parent.as_ref())
+    }
+
+    /// The overlays of a head and all its uncommitted ancestors, in descending order.
+    ///
+    /// This is the set of ancestors to pass to [`crate::SessionParams::overlay`] in order to build
+    /// on top of the head. Returns `None` if the head is unknown.
+    pub fn ancestors(&self, id: &Id) -> Option<Vec<&Overlay>> {
+        let mut head = self.heads.get(id)?;
+        let mut ancestors = vec![&head.overlay];
+        while let Some(ref parent) = head.parent {
+            head = &self.heads[parent];
+            ancestors.push(&head.overlay);
+        }
+        Some(ancestors)
+    }
+
+    /// Discard a head along with all of its descendants, returning their identifiers.
+    pub fn remove(&mut self, id: &Id) -> Vec<Id> {
+        let Some(parent) = self.heads.get(id).map(|head| head.parent.clone()) else {
+            return Vec::new();
+        };
+        if let Some(parent) = parent {
+            // UNWRAP: the parent of a head is always present.
+            let siblings = &mut self.heads.get_mut(&parent).unwrap().children;
+            siblings.retain(|child| child != id);
+        }
+        let mut removed = Vec::new();
+        self.remove_subtree(id.clone(), &mut removed);
+        removed
+    }
+
+    /// Commit the chain of heads leading to the given head, oldest first, making it the
+    /// committed state.
+    ///
+    /// Heads which descend from the given head are retained and are afterwards built on the
+    /// committed state. All other heads are discarded, since they can no longer be committed.
+    /// Returns the identifiers of the discarded heads.
+    ///
+    /// Returns an error if the head is unknown or if a commit fails. If a commit fails, the head
+    /// being committed and its descendants are discarded, while the heads committed before it
+    /// remain committed.
+    pub fn set_canonical<T: HashAlgorithm>(
+        &mut self,
+        nomt: &Nomt<T>,
+        id: &Id,
+    ) -> anyhow::Result<Vec<Id>> {
+        if !self.heads.contains_key(id) {
+            anyhow::bail!("Head {:?} is unknown", id);
+        }
+
+        let mut chain = vec![id.clone()];
+        while let Some(parent) = self.parent(chain.last().unwrap()) {
+            chain.push(parent.clone());
+        }
+
+        let mut discarded = Vec::new();
+        for id in chain.into_iter().rev() {
+            // UNWRAP: every head of the chain is present until it is committed.
+            let head = self.heads.remove(&id).unwrap();
+            if let Err(e) = head.overlay.commit(nomt) {
+                for child in head.children {
+                    self.remove_subtree(child, &mut Vec::new());
+                }
+                return Err(e);
+            }
+
+            // The committed head was built on the committed state, so every other head built on
+            // the committed state is a competing fork.
+            let competing = self
+                .heads
+                .iter()
+                .filter(|(other, other_head)| other_head.parent.is_none() && **other != id)
+                .map(|(other, _)| other.clone())
+                .collect::<Vec<_>>();
+            for other in competing {
+                self.remove_subtree(other, &mut discarded);
+            }
+            for child in head.children {
+                // UNWRAP: the children of a head are always present.
+                self.heads.get_mut(&child).unwrap().parent = None;
+            }
+        }
+        Ok(discarded)
+    }
+
+    fn remove_subtree(&mut self, id: Id, removed: &mut Vec<Id>) {
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if let Some(head) = self.heads.remove(&id) {
+                stack.extend(head.children);
+                removed.push(id);
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Health and readiness reporting. See [`crate::Nomt::health`].
+
+use std::time::{Duration, SystemTime};
+
+use crate::io::IoHealth;
+
+/// The health of a database at a point in time.
+///
+/// [`Health::is_live`] and [`Health::is_ready`] are meant to back liveness and readiness probes:
+/// a database which isn't live won't recover without being reopened, while one which isn't ready
+/// may recover on its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Health {
+    /// The state of the I/O workers. Shared with other instances using the same
+    /// [`crate::SharedIoPool`].
+    pub io: IoHealth,
+    /// The number of I/O commands of this instance which haven't completed yet.
+    pub io_in_flight: usize,
+    /// The number of I/O commands of this instance past which it is under backpressure.
+    pub io_in_flight_limit: usize,
+    /// The number of I/O commands waiting for a worker, including those of other instances
+    /// sharing the I/O workers.
+    pub io_queued: usize,
+    /// The time the last commit was synced to disk, if there was one since the database was
+    /// opened.
+    pub last_sync: Option<SystemTime>,
+    /// The number of bytes available on the file system holding the database, or `None` if it
+    /// couldn't be determined.
+    pub disk_available: Option<u64>,
+    /// Whether a commit failed, which fails every later commit until the database is reopened.
+    pub poisoned: bool,
+    /// Whether the database was opened read-only.
+    pub read_only: bool,
+}
+
+impl Health {
+    /// Whether the database can still make progress: it isn't poisoned and has I/O workers
+    /// running.
+    pub fn is_live(&self) -> bool {
+        !self.poisoned && self.io.alive > 0
+    }
+
+    /// Whether the database is fit to serve: it is live, all I/O workers are running, the
+    /// instance isn't under I/O backpressure and, unless read-only, at least `min_disk_available`
+    /// bytes are available on disk.
+    pub fn is_ready(&self, min_disk_available: u64) -> bool {
+        self.is_live()
+            && self.io.is_healthy()
+            && self.io_in_flight < self.io_in_flight_limit
+            && (self.read_only
+                || self
+                    .disk_available
+                    .is_some_and(|available| available >= min_disk_available))
+    }
+
+    /// The time elapsed since the last commit was synced to disk, if any.
+    pub fn since_last_sync(&self) -> Option<Duration> {
+        // a clock set backwards counts as no time elapsed.
+        self.last_sync
+            .map(|last_sync| last_sync.elapsed().unwrap_or(Duration::ZERO))
+    }
+}
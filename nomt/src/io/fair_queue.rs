@@ -0,0 +1,467 @@
+//! The queue of I/O commands feeding the I/O workers.
+//!
+//! Every [`super::IoPool`] submits through its own lane. Workers take commands from the lanes in
+//! weighted round-robin order: a lane at the front of the rotation may issue as many commands as
+//! its weight before yielding to the next lane with queued commands. With a single lane this is a
+//! plain FIFO queue.
+//!
+//! Within a lane, commands are issued in order of their [`IoPriority`], and in FIFO order among
+//! the same priority. Queued commands may be cancelled before they are issued.
+//!
+//! Lanes are unbounded, but each has a limit on its commands queued or in flight. Past it, the
+//! lane is under backpressure: [`LaneSender::try_send`] refuses further commands, while
+//! [`LaneSender::send`] still queues them.
+
+use super::{CompleteIo, IoCommand, IoPacket, IoPriority};
+use crate::metrics::{Metric, Metrics};
+use crossbeam_channel::{RecvError, RecvTimeoutError, Sender, TryRecvError};
+use parking_lot::{Condvar, Mutex};
+use slab::Slab;
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+pub struct FairQueue {
+    state: Mutex<State>,
+    ready: Condvar,
+}
+
+struct State {
+    lanes: Slab<Lane>,
+    /// The lanes with queued commands, in rotation order.
+    active: VecDeque<usize>,
+    /// The number of open lanes. The queue is disconnected once this is zero and all commands
+    /// were taken.
+    open_lanes: usize,
+}
+
+struct Lane {
+    weight: u32,
+    /// The commands the lane may still issue before yielding to the next lane.
+    credit: u32,
+    /// The queued commands, by priority.
+    queues: [VecDeque<IoPacket>; IoPriority::COUNT],
+    closed: bool,
+}
+
+impl Lane {
+    fn is_empty(&self) -> bool {
+        self.queues.iter().all(VecDeque::is_empty)
+    }
+
+    fn pop(&mut self) -> Option<IoPacket> {
+        self.queues.iter_mut().find_map(VecDeque::pop_front)
+    }
+}
+
+impl FairQueue {
+    pub fn new() -> Arc<Self> {
+        Arc::new(FairQueue {
+            state: Mutex::new(State {
+                lanes: Slab::new(),
+                active: VecDeque::new(),
+                open_lanes: 0,
+            }),
+            ready: Condvar::new(),
+        })
+    }
+
+    /// Open a new lane with the given weight and in-flight limit. The lane is closed when the
+    /// sender is dropped.
+    pub fn add_lane(self: &Arc<Self>, weight: u32, limit: usize, metrics: Metrics) -> LaneSender {
+        assert!(weight > 0);
+        let mut state = self.state.lock();
+        state.open_lanes += 1;
+        let lane = state.lanes.insert(Lane {
+            weight,
+            credit: 0,
+            queues: Default::default(),
+            closed: false,
+        });
+        LaneSender {
+            queue: self.clone(),
+            lane,
+            in_flight: Arc::new(InFlight {
+                count: Mutex::new(0),
+                limit,
+                idle: Condvar::new(),
+                metrics,
+            }),
+        }
+    }
+
+    /// The number of commands queued in all lanes which haven't been taken by a worker yet.
+    pub fn queued(&self) -> usize {
+        let state = self.state.lock();
+        state
+            .lanes
+            .iter()
+            .map(|(_, lane)| lane.queues.iter().map(VecDeque::len).sum::<usize>())
+            .sum()
+    }
+
+    /// Block until a command is available. Fails once the queue is disconnected.
+    pub fn recv(&self) -> Result<IoPacket, RecvError> {
+        let mut state = self.state.lock();
+        loop {
+            if let Some(packet) = state.pop() {
+                return Ok(packet);
+            }
+            if state.open_lanes == 0 {
+                return Err(RecvError);
+            }
+            self.ready.wait(&mut state);
+        }
+    }
+
+    /// Block until a command is available or the timeout elapses.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<IoPacket, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.lock();
+        loop {
+            if let Some(packet) = state.pop() {
+                return Ok(packet);
+            }
+            if state.open_lanes == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            if self.ready.wait_until(&mut state, deadline).timed_out() {
+                return state.pop().ok_or(RecvTimeoutError::Timeout);
+            }
+        }
+    }
+
+    /// Take a command if one is available.
+    pub fn try_recv(&self) -> Result<IoPacket, TryRecvError> {
+        let mut state = self.state.lock();
+        match state.pop() {
+            Some(packet) => Ok(packet),
+            None if state.open_lanes == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+}
+
+impl State {
+    fn pop(&mut self) -> Option<IoPacket> {
+        let &lane_id = self.active.front()?;
+        let lane = &mut self.lanes[lane_id];
+        // UNWRAP: active lanes have queued commands.
+        let packet = lane.pop().unwrap();
+        lane.credit -= 1;
+        if lane.is_empty() {
+            self.active.pop_front();
+            if lane.closed {
+                self.lanes.remove(lane_id);
+            }
+        } else if lane.credit == 0 {
+            lane.credit = lane.weight;
+            self.active.rotate_left(1);
+        }
+        Some(packet)
+    }
+}
+
+/// Submits commands into a lane of a [`FairQueue`].
+pub struct LaneSender {
+    queue: Arc<FairQueue>,
+    lane: usize,
+    in_flight: Arc<InFlight>,
+}
+
+impl LaneSender {
+    pub fn send(
+        &self,
+        command: IoCommand,
+        priority: IoPriority,
+        completion_sender: Sender<CompleteIo>,
+    ) {
+        {
+            let mut count = self.in_flight.count.lock();
+            if *count >= self.in_flight.limit {
+                self.in_flight.metrics.count(Metric::IoBackpressure);
+            }
+            *count += 1;
+        }
+        self.push(command, priority, completion_sender);
+    }
+
+    /// Send a command unless the lane is at its in-flight limit, in which case it is returned.
+    pub fn try_send(
+        &self,
+        command: IoCommand,
+        priority: IoPriority,
+        completion_sender: Sender<CompleteIo>,
+    ) -> Result<(), IoCommand> {
+        {
+            let mut count = self.in_flight.count.lock();
+            if *count >= self.in_flight.limit {
+                self.in_flight.metrics.count(Metric::IoBackpressure);
+                return Err(command);
+            }
+            *count += 1;
+        }
+        self.push(command, priority, completion_sender);
+        Ok(())
+    }
+
+    /// Whether the lane is at its in-flight limit.
+    pub fn would_block(&self) -> bool {
+        *self.in_flight.count.lock() >= self.in_flight.limit
+    }
+
+    // Queue a command already counted as in flight.
+    fn push(
+        &self,
+        command: IoCommand,
+        priority: IoPriority,
+        completion_sender: Sender<CompleteIo>,
+    ) {
+        let packet = IoPacket {
+            command,
+            completion_sender,
+            in_flight: InFlightGuard(self.in_flight.clone()),
+        };
+
+        let mut guard = self.queue.state.lock();
+        let state = &mut *guard;
+        let lane = &mut state.lanes[self.lane];
+        if lane.is_empty() {
+            lane.credit = lane.weight;
+            state.active.push_back(self.lane);
+        }
+        lane.queues[priority as usize].push_back(packet);
+        drop(guard);
+        self.queue.ready.notify_one();
+    }
+
+    /// Remove a queued command with the given completion channel and user data, before it is
+    /// issued. Returns `None` if there is no such command, for example because it was issued
+    /// already.
+    pub fn cancel(
+        &self,
+        completion_sender: &Sender<CompleteIo>,
+        user_data: u64,
+    ) -> Option<IoCommand> {
+        let mut guard = self.queue.state.lock();
+        let state = &mut *guard;
+        let lane = &mut state.lanes[self.lane];
+        let packet = lane.queues.iter_mut().find_map(|queue| {
+            let pos = queue.iter().position(|packet| {
+                packet.command.user_data == user_data
+                    && packet.completion_sender.same_channel(completion_sender)
+            })?;
+            queue.remove(pos)
+        })?;
+        if lane.is_empty() {
+            state.active.retain(|lane_id| *lane_id != self.lane);
+        }
+        drop(guard);
+
+        // dropping the guard releases the command from the in-flight count.
+        let IoPacket {
+            command, in_flight, ..
+        } = packet;
+        drop(in_flight);
+        Some(command)
+    }
+
+    /// The commands of this lane which haven't completed yet.
+    pub fn in_flight(&self) -> Arc<InFlight> {
+        self.in_flight.clone()
+    }
+}
+
+impl Drop for LaneSender {
+    fn drop(&mut self) {
+        let mut state = self.queue.state.lock();
+        state.open_lanes -= 1;
+        let lane = &mut state.lanes[self.lane];
+        if lane.is_empty() {
+            state.lanes.remove(self.lane);
+        } else {
+            lane.closed = true;
+        }
+        drop(state);
+        // wake up the workers so they can notice the queue being disconnected.
+        self.queue.ready.notify_all();
+    }
+}
+
+/// The commands of a lane which were sent but haven't completed yet.
+pub struct InFlight {
+    count: Mutex<usize>,
+    limit: usize,
+    idle: Condvar,
+    metrics: Metrics,
+}
+
+impl InFlight {
+    /// The number of commands of the lane which haven't completed yet.
+    pub fn count(&self) -> usize {
+        *self.count.lock()
+    }
+
+    /// The number of commands past which the lane is under backpressure.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Block until every command of the lane has completed.
+    pub fn wait_idle(&self) {
+        let mut count = self.count.lock();
+        self.idle.wait_while(&mut count, |count| *count > 0);
+    }
+}
+
+/// Counts a command as in flight until dropped. Dropped only after the completion was sent.
+pub struct InFlightGuard(Arc<InFlight>);
+
+impl InFlightGuard {
+    /// The metrics of the instance which sent the command.
+    pub fn metrics(&self) -> &Metrics {
+        &self.0.metrics
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut count = self.0.count.lock();
+        *count -= 1;
+        if *count == 0 {
+            self.0.idle.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FairQueue;
+    use crate::{
+        io::{IoCommand, IoKind, IoPriority, PagePool},
+        metrics::{Metric, Metrics},
+    };
+    use crossbeam_channel::{RecvError, TryRecvError};
+
+    #[test]
+    fn lanes_are_served_by_weight() {
+        let page_pool = PagePool::new();
+        let (completion_tx, _completion_rx) = crossbeam_channel::unbounded();
+        let queue = FairQueue::new();
+        let heavy = queue.add_lane(2, 64, Metrics::new(false));
+        let light = queue.add_lane(1, 64, Metrics::new(false));
+
+        let send = |lane: &super::LaneSender, user_data: u64| {
+            let kind = IoKind::Read(0, 0, page_pool.alloc_fat_page());
+            lane.send(
+                IoCommand { kind, user_data },
+                IoPriority::Normal,
+                completion_tx.clone(),
+            );
+        };
+        for i in 0..6 {
+            send(&heavy, i);
+        }
+        for i in 0..3 {
+            send(&light, 100 + i);
+        }
+
+        let order: Vec<u64> = (0..9)
+            .map(|_| queue.try_recv().unwrap().command.user_data)
+            .collect();
+        assert_eq!(order, vec![0, 1, 100, 2, 3, 101, 4, 5, 102]);
+        assert!(matches!(queue.try_recv(), Err(TryRecvError::Empty)));
+
+        // commands queued on a closed lane are still served before disconnecting.
+        let in_flight = light.in_flight();
+        send(&light, 103);
+        drop(light);
+        drop(heavy);
+        let packet = queue.recv().unwrap();
+        assert_eq!(packet.command.user_data, 103);
+        assert!(matches!(queue.recv(), Err(RecvError)));
+
+        drop(packet);
+        in_flight.wait_idle();
+    }
+
+    #[test]
+    fn lane_reports_backpressure() {
+        let page_pool = PagePool::new();
+        let (completion_tx, _completion_rx) = crossbeam_channel::unbounded();
+        let queue = FairQueue::new();
+        let metrics = Metrics::new(true);
+        let lane = queue.add_lane(1, 2, metrics.clone());
+
+        let command = |user_data: u64| IoCommand {
+            kind: IoKind::Read(0, 0, page_pool.alloc_fat_page()),
+            user_data,
+        };
+        assert!(lane
+            .try_send(command(0), IoPriority::Normal, completion_tx.clone())
+            .is_ok());
+        assert!(!lane.would_block());
+        assert!(lane
+            .try_send(command(1), IoPriority::Normal, completion_tx.clone())
+            .is_ok());
+        assert!(lane.would_block());
+        let refused = lane
+            .try_send(command(2), IoPriority::Normal, completion_tx.clone())
+            .unwrap_err();
+        assert_eq!(refused.user_data, 2);
+
+        // sending still queues the command, past the limit.
+        lane.send(command(3), IoPriority::Normal, completion_tx.clone());
+        assert_eq!(metrics.counter(Metric::IoBackpressure), Some(2));
+
+        // completions release the backpressure.
+        let packets: Vec<_> = (0..3).map(|_| queue.try_recv().unwrap()).collect();
+        assert!(lane.would_block());
+        drop(packets);
+        assert!(!lane.would_block());
+    }
+
+    #[test]
+    fn lane_serves_priorities_and_cancels() {
+        let page_pool = PagePool::new();
+        let (completion_tx, _completion_rx) = crossbeam_channel::unbounded();
+        let (other_tx, _other_rx) = crossbeam_channel::unbounded();
+        let queue = FairQueue::new();
+        let lane = queue.add_lane(1, 64, Metrics::new(false));
+
+        let send = |user_data: u64, priority, completion_tx: &crossbeam_channel::Sender<_>| {
+            let kind = IoKind::Read(0, 0, page_pool.alloc_fat_page());
+            lane.send(
+                IoCommand { kind, user_data },
+                priority,
+                completion_tx.clone(),
+            );
+        };
+        send(0, IoPriority::Speculative, &completion_tx);
+        send(1, IoPriority::Normal, &completion_tx);
+        send(2, IoPriority::Speculative, &completion_tx);
+        send(3, IoPriority::Read, &completion_tx);
+        send(4, IoPriority::Normal, &completion_tx);
+        send(2, IoPriority::Speculative, &other_tx);
+
+        // only commands sent with the same completion channel are cancelled.
+        let cancelled = lane.cancel(&completion_tx, 2).unwrap();
+        assert_eq!(cancelled.user_data, 2);
+        assert!(lane.cancel(&completion_tx, 2).is_none());
+
+        let order: Vec<u64> = (0..5)
+            .map(|_| queue.try_recv().unwrap().command.user_data)
+            .collect();
+        assert_eq!(order, vec![3, 1, 4, 0, 2]);
+        assert!(lane.cancel(&completion_tx, 0).is_none());
+
+        // cancelling the last queued command deactivates the lane.
+        send(5, IoPriority::Speculative, &completion_tx);
+        assert!(lane.cancel(&completion_tx, 5).is_some());
+        assert!(matches!(queue.try_recv(), Err(TryRecvError::Empty)));
+        send(6, IoPriority::Normal, &completion_tx);
+        assert_eq!(queue.try_recv().unwrap().command.user_data, 6);
+    }
+}
@@ -1,58 +1,103 @@
-use super::{CompleteIo, IoCommand, IoKind, IoKindResult, IoPacket, PagePool, PAGE_SIZE};
-use crossbeam_channel::{Receiver, Sender, TryRecvError};
+use super::{
+    supervisor::Supervisor, CompleteIo, FairQueue, InFlightGuard, IoCommand, IoKind, IoKindResult,
+    IoPacket, PagePool, RetryPolicy, PAGE_SIZE,
+};
+use crate::{fatal::FatalError, metrics::Metric};
+use crossbeam_channel::{RecvTimeoutError, Sender, TryRecvError};
 use io_uring::{cqueue, opcode, squeue, types, IoUring};
 use slab::Slab;
-use std::collections::VecDeque;
+use std::{collections::VecDeque, sync::Arc, time::Instant};
 use threadpool::ThreadPool;
 
-const RING_CAPACITY: u32 = 1024;
-
-// max number of inflight requests is bounded by the slab.
-const MAX_IN_FLIGHT: usize = RING_CAPACITY as usize;
-
 struct PendingIo {
     command: IoCommand,
     completion_sender: Sender<CompleteIo>,
+    in_flight: InFlightGuard,
+    retries: u32,
 }
 
-pub fn start_io_worker(
-    page_pool: PagePool,
-    io_workers_tp: &ThreadPool,
-    io_workers: usize,
-) -> Sender<IoPacket> {
-    // main bound is from the pending slab.
-    let (command_tx, command_rx) = crossbeam_channel::unbounded();
-
-    start_workers(page_pool, io_workers_tp, command_rx, io_workers);
+// A command waiting for its next retry.
+struct RetryIo {
+    packet: IoPacket,
+    retries: u32,
+    not_before: Instant,
+}
 
-    command_tx
+// The commands owned by a worker. Kept outside of the worker so they can be failed if it panics.
+struct Owned {
+    pending: Slab<PendingIo>,
+    retries: VecDeque<RetryIo>,
 }
 
-fn start_workers(
+// main bound is from the pending slab.
+pub fn start_io_worker(
     page_pool: PagePool,
     io_workers_tp: &ThreadPool,
-    command_rx: Receiver<IoPacket>,
+    command_rx: Arc<FairQueue>,
     io_workers: usize,
+    queue_depth: usize,
+    retry_policy: RetryPolicy,
+    supervisor: Arc<Supervisor>,
 ) {
     for _ in 0..io_workers {
         io_workers_tp.execute({
             let page_pool = page_pool.clone();
             let command_rx = command_rx.clone();
-            move || run_worker(page_pool, command_rx)
+            let supervisor = supervisor.clone();
+            move || {
+                let mut owned = Owned {
+                    pending: Slab::with_capacity(queue_depth),
+                    retries: VecDeque::new(),
+                };
+                supervisor.run(
+                    &mut owned,
+                    |owned| run_worker(&command_rx, queue_depth, retry_policy, owned),
+                    fail_all,
+                );
+
+                // Why the `drop` here? Well, recall that the iou accepts commands parametrized
+                // with buffers. These buffers are allocated in the page pool. If the page pool is
+                // dropped before the ring is dropped, then that's a use-after-free.
+                //
+                // So in other words, we plumb `page_pool` all the way here and drop it here only
+                // to ensure safety.
+                drop(page_pool);
+            }
         });
     }
 }
 
-fn run_worker(page_pool: PagePool, command_rx: Receiver<IoPacket>) {
-    let mut pending: Slab<PendingIo> = Slab::with_capacity(MAX_IN_FLIGHT);
+// Fail the commands of a worker which panicked. Its ring was dropped while unwinding.
+fn fail_all(owned: &mut Owned, error: &FatalError) -> usize {
+    let pending = owned.pending.drain().map(|pending| IoPacket {
+        command: pending.command,
+        completion_sender: pending.completion_sender,
+        in_flight: pending.in_flight,
+    });
+    let retries = owned.retries.drain(..).map(|retry| retry.packet);
+    let mut failed = 0;
+    for packet in pending.chain(retries) {
+        super::fail_command(packet, error);
+        failed += 1;
+    }
+    failed
+}
+
+// max number of inflight requests is bounded by the slab, holding up to `queue_depth` requests.
+fn run_worker(
+    command_rx: &FairQueue,
+    queue_depth: usize,
+    retry_policy: RetryPolicy,
+    owned: &mut Owned,
+) {
+    let Owned { pending, retries } = owned;
 
     let mut ring = IoUring::<squeue::Entry, cqueue::Entry>::builder()
         .setup_single_issuer()
-        .build(RING_CAPACITY)
+        .build(queue_depth as u32)
         .expect("Error building io_uring");
 
     let (submitter, mut submit_queue, mut complete_queue) = ring.split();
-    let mut retries = VecDeque::<IoPacket>::new();
 
     // Indicates whether the worker detected that it should shutdown.
     let mut shutdown = false;
@@ -68,6 +113,8 @@ fn run_worker(page_pool: PagePool, command_rx: Receiver<IoPacket>) {
                 let PendingIo {
                     command,
                     completion_sender,
+                    in_flight,
+                    retries: prev_retries,
                 } = pending.remove(completion_event.user_data() as usize);
 
                 // io_uring never uses errno to pass back error information.
@@ -77,31 +124,34 @@ fn run_worker(page_pool: PagePool, command_rx: Receiver<IoPacket>) {
                 let io_uring_res = completion_event.result();
                 let syscall_result = if io_uring_res >= 0 { io_uring_res } else { -1 };
 
-                let result = match command.kind.get_result(syscall_result as isize) {
+                let os_err = || std::io::Error::from_raw_os_error(io_uring_res.abs());
+                let result = match command.kind.get_result(syscall_result as isize, os_err) {
                     IoKindResult::Ok => Ok(()),
-                    IoKindResult::Err => Err(std::io::Error::from_raw_os_error(io_uring_res.abs())),
-                    IoKindResult::Retry => {
-                        retries.push_back(IoPacket {
-                            command,
-                            completion_sender,
-                        });
-                        continue;
-                    }
+                    IoKindResult::Err(e) => Err(e),
+                    IoKindResult::Retry(e) => match retry_policy.delay(prev_retries + 1) {
+                        Some(delay) => {
+                            in_flight.metrics().count(Metric::IoRetries);
+                            retries.push_back(RetryIo {
+                                packet: IoPacket {
+                                    command,
+                                    completion_sender,
+                                    in_flight,
+                                },
+                                retries: prev_retries + 1,
+                                not_before: Instant::now() + delay,
+                            });
+                            continue;
+                        }
+                        None => Err(e),
+                    },
                 };
 
                 let complete = CompleteIo { command, result };
                 let _ = completion_sender.send(complete);
+                drop(in_flight);
             }
-        } else if shutdown {
+        } else if shutdown && retries.is_empty() {
             // No pending IOs and we are shutting down. That means we can exit the worker.
-            //
-            // Why the `drop` here? Well, recall that the iou accepts commands parametrized with
-            // buffers. These buffers are allocated in the page pool. If the page pool is dropped
-            // before the ring is dropped, then that's a use-after-free.
-            //
-            // So in other words, we plumb `page_pool` all the way here and drop it here only to
-            // ensure safety.
-            drop(page_pool);
             return;
         }
 
@@ -109,23 +159,41 @@ fn run_worker(page_pool: PagePool, command_rx: Receiver<IoPacket>) {
         let mut to_submit = false;
 
         submit_queue.sync();
-        while pending.len() < MAX_IN_FLIGHT && !submit_queue.is_full() {
-            let next_io = if !retries.is_empty() {
+        while pending.len() < queue_depth && !submit_queue.is_full() {
+            let now = Instant::now();
+            let (next_io, prev_retries) = if retries.front().is_some_and(|r| r.not_before <= now) {
                 // re-apply partially failed reads and writes
-                // unwrap: known not empty
-                retries.pop_front().unwrap()
-            } else if pending.is_empty() {
-                // block on new I/O if nothing in-flight.
-                match command_rx.recv() {
-                    Ok(command) => command,
-                    Err(_) => {
+                // UNWRAP: known not empty
+                let retry = retries.pop_front().unwrap();
+                (retry.packet, retry.retries)
+            } else if pending.is_empty() && !shutdown {
+                // block on new I/O if nothing in-flight, or until the next retry is due.
+                let next = match retries.front() {
+                    Some(retry) => command_rx.recv_timeout(retry.not_before - now),
+                    None => command_rx
+                        .recv()
+                        .map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                match next {
+                    Ok(command) => (command, 0),
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => {
                         shutdown = true;
                         break;
                     }
                 }
+            } else if pending.is_empty() {
+                // shutting down. finish the retries still waiting.
+                match retries.front() {
+                    Some(retry) => {
+                        std::thread::sleep(retry.not_before - now);
+                        continue;
+                    }
+                    None => break,
+                }
             } else {
                 match command_rx.try_recv() {
-                    Ok(command) => command,
+                    Ok(command) => (command, 0),
                     Err(TryRecvError::Empty) => break,
                     Err(TryRecvError::Disconnected) => {
                         shutdown = true;
@@ -138,6 +206,8 @@ fn run_worker(page_pool: PagePool, command_rx: Receiver<IoPacket>) {
             let pending_index = pending.insert(PendingIo {
                 command: next_io.command,
                 completion_sender: next_io.completion_sender,
+                in_flight: next_io.in_flight,
+                retries: prev_retries,
             });
 
             let entry = submission_entry(&mut pending.get_mut(pending_index).unwrap().command)
@@ -152,7 +222,7 @@ fn run_worker(page_pool: PagePool, command_rx: Receiver<IoPacket>) {
             submit_queue.sync();
         }
 
-        let wait = if pending.len() == MAX_IN_FLIGHT { 1 } else { 0 };
+        let wait = if pending.len() == queue_depth { 1 } else { 0 };
 
         // Do submit handling EINTR.
         loop {
@@ -188,5 +258,27 @@ fn submission_entry(command: &mut IoCommand) -> squeue::Entry {
                 .offset(page_index * PAGE_SIZE as u64)
                 .build()
         }
+        IoKind::WriteRun(fd, page_index, ref run) => {
+            let iovecs = run.iovecs();
+            opcode::Writev::new(types::Fd(fd), iovecs.as_ptr(), iovecs.len() as u32)
+                .offset(page_index * PAGE_SIZE as u64)
+                .build()
+        }
+        IoKind::Fsync(fd) => opcode::Fsync::new(types::Fd(fd)).build(),
+        IoKind::Fdatasync(fd) => opcode::Fsync::new(types::Fd(fd))
+            .flags(types::FsyncFlags::DATASYNC)
+            .build(),
+        IoKind::SyncRange(fd, page_index, len) => {
+            // UNWRAP: ranges are far smaller than 4GiB.
+            let len = len.checked_mul(PAGE_SIZE as u32).unwrap();
+            opcode::SyncFileRange::new(types::Fd(fd), len)
+                .offset(page_index * PAGE_SIZE as u64)
+                .flags(
+                    libc::SYNC_FILE_RANGE_WAIT_BEFORE
+                        | libc::SYNC_FILE_RANGE_WRITE
+                        | libc::SYNC_FILE_RANGE_WAIT_AFTER,
+                )
+                .build()
+        }
     }
 }
@@ -1,14 +1,18 @@
 #[cfg(not(target_family = "unix"))]
 std::compile_error!("NOMT only supports Unix-based OSs");
 
-use crossbeam_channel::{Receiver, RecvError, SendError, Sender, TryRecvError};
+use crate::{fatal::FatalError, metrics::Metrics};
+use crossbeam_channel::{Receiver, RecvError, SendError, Sender, TryRecvError, TrySendError};
+use fair_queue::{FairQueue, InFlight, InFlightGuard, LaneSender};
 use page_pool::Page;
 use std::{
     fmt,
     fs::File,
     os::fd::RawFd,
     sync::{Arc, Weak},
+    time::Duration,
 };
+use supervisor::Supervisor;
 use threadpool::ThreadPool;
 
 #[cfg(target_os = "linux")]
@@ -19,18 +23,108 @@ mod platform;
 #[path = "unix.rs"]
 mod platform;
 
+mod fair_queue;
 pub mod fsyncer;
 pub mod page_pool;
+mod supervisor;
 
 pub const PAGE_SIZE: usize = 4096;
 
+/// The most I/O commands each I/O worker keeps in flight, unless configured otherwise.
+pub const DEFAULT_IO_QUEUE_DEPTH: usize = 1024;
+
 pub use page_pool::{FatPage, PagePool};
+pub use supervisor::IoHealth;
 
 pub enum IoKind {
     Read(RawFd, u64, FatPage),
     Write(RawFd, u64, FatPage),
     WriteArc(RawFd, u64, Arc<FatPage>),
     WriteRaw(RawFd, u64, Page),
+    /// Write a run of pages to consecutive page numbers, starting at the given one.
+    WriteRun(RawFd, u64, PageRun),
+    /// Flush the data and metadata of a file to the device, like `fsync(2)`.
+    Fsync(RawFd),
+    /// Flush the data of a file to the device, along with only the metadata needed to read it
+    /// back, like `fdatasync(2)`.
+    Fdatasync(RawFd),
+    /// Write out the dirty pages among the given number of pages, starting at the given page
+    /// number, and wait for them to be written, like `sync_file_range(2)`.
+    ///
+    /// Neither the metadata of the file nor the write cache of the device are flushed, so this
+    /// must be followed by an [`IoKind::Fdatasync`] for durability. It makes the latter cheaper
+    /// when issued for every range written. A no-op on platforms other than Linux.
+    SyncRange(RawFd, u64, u32),
+}
+
+/// The most pages coalesced into a single [`IoKind::WriteRun`].
+pub const MAX_RUN_PAGES: usize = 64;
+
+/// A run of pages written with a single vectored write.
+pub struct PageRun {
+    pages: Vec<Arc<FatPage>>,
+    // Point into `pages`, whose buffers don't move.
+    iovecs: Vec<libc::iovec>,
+}
+
+// SAFETY: the iovecs only point into the pages owned by the run.
+unsafe impl Send for PageRun {}
+unsafe impl Sync for PageRun {}
+
+impl PageRun {
+    /// Create a run out of pages to be written to consecutive page numbers.
+    pub fn new(pages: Vec<Arc<FatPage>>) -> Self {
+        let iovecs = pages
+            .iter()
+            .map(|page| libc::iovec {
+                iov_base: page.as_ptr() as *mut libc::c_void,
+                iov_len: PAGE_SIZE,
+            })
+            .collect();
+        PageRun { pages, iovecs }
+    }
+
+    /// The number of pages in the run.
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    fn iovecs(&self) -> &[libc::iovec] {
+        &self.iovecs
+    }
+}
+
+/// Turn page writes into write commands, merging pages with consecutive page numbers into runs
+/// of up to [`MAX_RUN_PAGES`] pages.
+///
+/// The writes are sorted by page number, so that the commands are issued in on-disk order.
+pub fn coalesce_writes(fd: RawFd, mut writes: Vec<(u64, Arc<FatPage>)>) -> Vec<IoKind> {
+    writes.sort_unstable_by_key(|(pn, _)| *pn);
+
+    let mut kinds = Vec::new();
+    let mut run_start = 0;
+    let mut run = Vec::new();
+    let mut flush = |run_start: u64, run: &mut Vec<Arc<FatPage>>| match run.len() {
+        0 => {}
+        // UNWRAP: the run has one page.
+        1 => kinds.push(IoKind::WriteArc(fd, run_start, run.pop().unwrap())),
+        _ => kinds.push(IoKind::WriteRun(
+            fd,
+            run_start,
+            PageRun::new(std::mem::take(run)),
+        )),
+    };
+
+    for (pn, page) in writes {
+        if run.is_empty() || pn != run_start + run.len() as u64 || run.len() == MAX_RUN_PAGES {
+            flush(run_start, &mut run);
+            run_start = pn;
+        }
+        run.push(page);
+    }
+    flush(run_start, &mut run);
+
+    kinds
 }
 
 impl fmt::Debug for IoKind {
@@ -40,14 +134,24 @@ impl fmt::Debug for IoKind {
             IoKind::Write(fd, pn, _) => write!(f, "Write(fd={}, pn={})", fd, pn),
             IoKind::WriteArc(fd, pn, _) => write!(f, "WriteArc(fd={}, pn={})", fd, pn),
             IoKind::WriteRaw(fd, pn, _) => write!(f, "WriteRaw(fd={}, pn={})", fd, pn),
+            IoKind::WriteRun(fd, pn, run) => {
+                write!(f, "WriteRun(fd={}, pn={}, len={})", fd, pn, run.len())
+            }
+            IoKind::Fsync(fd) => write!(f, "Fsync(fd={})", fd),
+            IoKind::Fdatasync(fd) => write!(f, "Fdatasync(fd={})", fd),
+            IoKind::SyncRange(fd, pn, len) => {
+                write!(f, "SyncRange(fd={}, pn={}, len={})", fd, pn, len)
+            }
         }
     }
 }
 
 pub enum IoKindResult {
     Ok,
-    Err,
-    Retry,
+    Err(std::io::Error),
+    /// The command failed with a transient error and may be retried. The error is surfaced if
+    /// the command runs out of retries.
+    Retry(std::io::Error),
 }
 
 impl IoKind {
@@ -56,11 +160,17 @@ impl IoKind {
             IoKind::Read(_, _, buf) | IoKind::Write(_, _, buf) => buf,
             IoKind::WriteArc(_, _, _) => panic!("attempted to extract owned buf from write_arc"),
             IoKind::WriteRaw(_, _, _) => panic!("attempted to extract buf from write_raw"),
+            IoKind::WriteRun(_, _, _) => panic!("attempted to extract buf from write_run"),
+            IoKind::Fsync(_) | IoKind::Fdatasync(_) | IoKind::SyncRange(_, _, _) => {
+                panic!("attempted to extract buf from sync")
+            }
         }
     }
 
-    /// Expected to be called directly after a failing syscall. IOW, errno should not clobbered.
-    pub fn get_result(&self, res: isize) -> IoKindResult {
+    /// Classify the result of the syscall executing the command. `os_err` gives the error of a
+    /// failed syscall.
+    pub fn get_result(&self, res: isize, os_err: impl FnOnce() -> std::io::Error) -> IoKindResult {
+        let short_transfer = || std::io::Error::other("short transfer");
         match self {
             // pread returns 0 if the file has been read till the end of file
             //
@@ -69,21 +179,87 @@ impl IoKind {
             // there should be no unexpected end-of-file that is not aligned with PAGE_SIZE
             // when all previous writes have succeeded.
             IoKind::Read(_, _, _) if res == 0 => IoKindResult::Ok,
+            // a short vectored write is retried as a whole, like a short write of a single page.
+            IoKind::WriteRun(_, _, run) if res == (run.len() * PAGE_SIZE) as isize => {
+                IoKindResult::Ok
+            }
+            IoKind::WriteRun(_, _, _) if res >= 0 => IoKindResult::Retry(short_transfer()),
+            // syncs return 0 on success.
+            IoKind::Fsync(_) | IoKind::Fdatasync(_) | IoKind::SyncRange(_, _, _) if res == 0 => {
+                IoKindResult::Ok
+            }
             // pread and pwrite return the number of bytes read or written
             _ if res == PAGE_SIZE as isize => IoKindResult::Ok,
             _ if res == -1 => {
-                let os_err = std::io::Error::last_os_error();
-                if matches!(os_err.kind(), std::io::ErrorKind::Interrupted) {
-                    IoKindResult::Retry
+                let os_err = os_err();
+                if matches!(
+                    os_err.kind(),
+                    std::io::ErrorKind::Interrupted | std::io::ErrorKind::WouldBlock
+                ) {
+                    IoKindResult::Retry(os_err)
                 } else {
-                    IoKindResult::Err
+                    IoKindResult::Err(os_err)
                 }
             }
-            _ => IoKindResult::Retry,
+            _ => IoKindResult::Retry(short_transfer()),
         }
     }
 }
 
+/// The order in which the queued I/O commands of an instance are issued.
+///
+/// Commands of a higher priority are issued first. This only orders the commands waiting for the
+/// I/O workers: a command already issued isn't preempted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoPriority {
+    /// Reads which a user is waiting on.
+    Read = 0,
+    /// Everything else, like the loads and writes of commits.
+    Normal = 1,
+    /// Speculative loads, which may turn out to be unneeded.
+    Speculative = 2,
+}
+
+impl IoPriority {
+    /// The number of priorities.
+    pub const COUNT: usize = 3;
+}
+
+/// How I/O commands failing with a transient error, such as `EINTR`, `EAGAIN` or a short
+/// transfer after a device stall, are retried before the error is surfaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The most times a single command is retried.
+    pub max_retries: u32,
+    /// The delay before the first retry. It doubles with every further retry of the same command,
+    /// up to [`MAX_RETRY_BACKOFF`].
+    pub backoff: Duration,
+}
+
+/// The longest delay between two retries of a command.
+pub const MAX_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 16,
+            backoff: Duration::from_micros(100),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before the given retry of a command, starting from 1, or `None` if the command
+    /// is out of retries.
+    pub fn delay(&self, retry: u32) -> Option<Duration> {
+        if retry > self.max_retries {
+            return None;
+        }
+        let factor = 1u32.checked_shl(retry - 1).unwrap_or(u32::MAX);
+        Some(self.backoff.saturating_mul(factor).min(MAX_RETRY_BACKOFF))
+    }
+}
+
 pub struct IoCommand {
     pub kind: IoKind,
     // note: this isn't passed to io_uring, it's higher-level userdata.
@@ -98,42 +274,171 @@ pub struct CompleteIo {
 struct IoPacket {
     command: IoCommand,
     completion_sender: Sender<CompleteIo>,
+    in_flight: InFlightGuard,
+}
+
+// Complete a command with an error on behalf of a worker which panicked.
+fn fail_command(packet: IoPacket, error: &FatalError) {
+    let complete = CompleteIo {
+        command: packet.command,
+        result: Err(error.clone().into()),
+    };
+    let _ = packet.completion_sender.send(complete);
+    drop(packet.in_flight);
 }
 
 /// Create an I/O worker managing an io_uring and sending responses back via channels to a number
 /// of handles.
-pub fn start_io_pool(io_workers: usize, page_pool: PagePool) -> IoPool {
-    let io_workers_tp = ThreadPool::with_name("io-worker".to_string(), io_workers);
-    let sender = platform::start_io_worker(page_pool.clone(), &io_workers_tp, io_workers);
-    let sender = Some(Arc::new(sender));
-    IoPool {
-        sender,
-        page_pool,
-        io_workers_tp,
-    }
+pub fn start_io_pool(
+    io_workers: usize,
+    queue_depth: usize,
+    page_pool: PagePool,
+    retry_policy: RetryPolicy,
+    metrics: Metrics,
+) -> IoPool {
+    let workers = IoWorkers::start(io_workers, queue_depth, page_pool, retry_policy, false);
+    IoPool::new(Arc::new(workers), 1, metrics)
 }
 
 #[cfg(test)]
 pub fn start_test_io_pool(io_workers: usize, page_pool: PagePool) -> IoPool {
-    start_io_pool(io_workers, page_pool)
+    start_io_pool(
+        io_workers,
+        DEFAULT_IO_QUEUE_DEPTH,
+        page_pool,
+        RetryPolicy::default(),
+        Metrics::new(false),
+    )
+}
+
+/// I/O workers, possibly serving multiple instances.
+struct IoWorkers {
+    queue: Arc<FairQueue>,
+    page_pool: PagePool,
+    io_workers_tp: ThreadPool,
+    supervisor: Arc<Supervisor>,
+    /// The most commands all the workers keep in flight together.
+    capacity: usize,
+    /// A lane keeping the workers running while no instance uses them. `None` if the workers
+    /// serve a single instance.
+    keep_alive: Option<LaneSender>,
+}
+
+impl IoWorkers {
+    fn start(
+        io_workers: usize,
+        queue_depth: usize,
+        page_pool: PagePool,
+        retry_policy: RetryPolicy,
+        shared: bool,
+    ) -> Self {
+        let io_workers_tp = ThreadPool::with_name("io-worker".to_string(), io_workers);
+        let queue = FairQueue::new();
+        let capacity = io_workers * queue_depth;
+        let keep_alive = shared.then(|| queue.add_lane(1, capacity, Metrics::new(false)));
+        let supervisor = Arc::new(Supervisor::new(io_workers));
+        platform::start_io_worker(
+            page_pool.clone(),
+            &io_workers_tp,
+            queue.clone(),
+            io_workers,
+            queue_depth,
+            retry_policy,
+            supervisor.clone(),
+        );
+        IoWorkers {
+            queue,
+            page_pool,
+            io_workers_tp,
+            supervisor,
+            capacity,
+            keep_alive,
+        }
+    }
+}
+
+/// A pool of I/O workers which can be shared by multiple NOMT instances in the process.
+///
+/// The instances are scheduled fairly, in proportion to the weight each was opened with, so one
+/// instance issuing a lot of I/O can't starve the others. See [`crate::Options::io_pool`].
+///
+/// The workers shut down once the pool and all the instances using it are dropped.
+#[derive(Clone)]
+pub struct SharedIoPool {
+    workers: Arc<IoWorkers>,
+}
+
+impl SharedIoPool {
+    /// Start a pool with the given number of io_uring instances, or I/O threads on non-Linux
+    /// platforms.
+    ///
+    /// Each io_uring keeps up to [`DEFAULT_IO_QUEUE_DEPTH`] commands in flight.
+    pub fn new(io_workers: usize, retry_policy: RetryPolicy) -> Self {
+        Self::with_queue_depth(io_workers, DEFAULT_IO_QUEUE_DEPTH, retry_policy)
+    }
+
+    /// Start a pool with the given number of io_uring instances, each keeping up to
+    /// `queue_depth` commands in flight. See [`crate::Options::io_queue_depth`].
+    pub fn with_queue_depth(
+        io_workers: usize,
+        queue_depth: usize,
+        retry_policy: RetryPolicy,
+    ) -> Self {
+        assert!(io_workers > 0);
+        assert!(queue_depth > 0);
+        let workers =
+            IoWorkers::start(io_workers, queue_depth, PagePool::new(), retry_policy, true);
+        SharedIoPool {
+            workers: Arc::new(workers),
+        }
+    }
+
+    /// Open a lane of the pool for an instance.
+    pub(crate) fn io_pool(&self, weight: u32, metrics: Metrics) -> IoPool {
+        IoPool::new(self.workers.clone(), weight, metrics)
+    }
+
+    /// The page pool which the instances using this I/O pool allocate from.
+    pub(crate) fn page_pool(&self) -> &PagePool {
+        &self.workers.page_pool
+    }
+}
+
+impl fmt::Debug for SharedIoPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedIoPool").finish_non_exhaustive()
+    }
 }
 
 /// A manager for the broader I/O pool. This can be used to create new I/O handles.
 pub struct IoPool {
-    /// Sender to send I/O commands to the I/O workers.
+    /// Sender to send I/O commands to the I/O workers, through the lane of this pool.
     ///
     /// Every IoHandle is created with a weak reference to this sender, the only one non-transient
-    /// strong reference is held by this struct. We say "non-transient" because the channel might
+    /// strong reference is held by this struct. We say "non-transient" because the sender might
     /// occasionally be upgraded when sending message.
     ///
-    /// Upon shutdown, this only reference is dropped by `take`ing it, causing the channel to close
-    /// and the I/O workers to shut down.
-    sender: Option<Arc<Sender<IoPacket>>>,
+    /// Upon shutdown, this only reference is dropped by `take`ing it, closing the lane. The I/O
+    /// workers shut down once all lanes are closed.
+    sender: Option<Arc<LaneSender>>,
+    in_flight: Arc<InFlight>,
     page_pool: PagePool,
-    io_workers_tp: ThreadPool,
+    workers: Option<Arc<IoWorkers>>,
+    supervisor: Arc<Supervisor>,
 }
 
 impl IoPool {
+    fn new(workers: Arc<IoWorkers>, weight: u32, metrics: Metrics) -> Self {
+        let sender = workers.queue.add_lane(weight, workers.capacity, metrics);
+        IoPool {
+            in_flight: sender.in_flight(),
+            sender: Some(Arc::new(sender)),
+            page_pool: workers.page_pool.clone(),
+            supervisor: workers.supervisor.clone(),
+            workers: Some(workers),
+        }
+    }
+
     /// Create a new I/O handle.
     ///
     /// This will panic if the I/O pool has been shut down.
@@ -146,6 +451,7 @@ impl IoPool {
         let sender = Arc::downgrade(sender);
         IoHandle {
             sender,
+            priority: IoPriority::Normal,
             completion_sender,
             completion_receiver,
         }
@@ -155,15 +461,41 @@ impl IoPool {
         &self.page_pool
     }
 
+    /// The state of the I/O workers.
+    pub fn health(&self) -> IoHealth {
+        self.supervisor.health()
+    }
+
+    /// The commands sent through this pool which haven't completed yet.
+    pub fn in_flight(&self) -> &InFlight {
+        &self.in_flight
+    }
+
+    /// The commands queued by all the pools sharing the I/O workers, which haven't been taken by
+    /// a worker yet. Zero after shutdown.
+    pub fn queued(&self) -> usize {
+        self.workers
+            .as_ref()
+            .map_or(0, |workers| workers.queue.queued())
+    }
+
     /// Initiate the shutdown procedure.
     ///
-    /// This will return only after all the I/O workers are shut down.
+    /// This will return only after all the I/O commands sent through this pool are completed, and
+    /// the I/O workers are shut down unless they are shared with another instance.
     pub fn shutdown(&mut self) {
         // There is only a single strong reference to the sender, dropping it will close the
-        // channel, causing the I/O workers to shut down.
+        // lane.
         let sender = self.sender.take().unwrap();
         drop(sender);
-        self.io_workers_tp.join();
+        self.in_flight.wait_idle();
+
+        // UNWRAP: taken only here, and shutdown is called once.
+        if let Some(mut workers) = Arc::into_inner(self.workers.take().unwrap()) {
+            // No instance uses the workers anymore, closing the last lane shuts them down.
+            drop(workers.keep_alive.take());
+            workers.io_workers_tp.join();
+        }
     }
 }
 
@@ -176,9 +508,12 @@ impl IoPool {
 /// stream of completions.
 ///
 /// This is safe to use across multiple threads, but care must be taken by the user for correctness.
+///
+/// Commands are sent with the priority of the handle, [`IoPriority::Normal`] unless set otherwise.
 #[derive(Clone)]
 pub struct IoHandle {
-    sender: Weak<Sender<IoPacket>>,
+    sender: Weak<LaneSender>,
+    priority: IoPriority,
     completion_sender: Sender<CompleteIo>,
     completion_receiver: Receiver<CompleteIo>,
 }
@@ -190,12 +525,42 @@ impl IoHandle {
             Some(sender) => sender,
             None => return Err(SendError(command)),
         };
+        sender.send(command, self.priority, self.completion_sender.clone());
+        Ok(())
+    }
+
+    /// Send an I/O command unless the I/O pool is under backpressure, see [`Self::would_block`].
+    ///
+    /// Like [`Self::send`], this never blocks the thread. Refused commands are counted in the
+    /// metrics.
+    pub fn try_submit(&self, command: IoCommand) -> Result<(), TrySendError<IoCommand>> {
+        let sender = match self.sender.upgrade() {
+            Some(sender) => sender,
+            None => return Err(TrySendError::Disconnected(command)),
+        };
         sender
-            .send(IoPacket {
-                command,
-                completion_sender: self.completion_sender.clone(),
-            })
-            .map_err(|SendError(packet)| SendError(packet.command))
+            .try_send(command, self.priority, self.completion_sender.clone())
+            .map_err(TrySendError::Full)
+    }
+
+    /// Whether the I/O pool has as many commands of this instance queued or in flight as the
+    /// I/O workers can keep in flight. Further commands are queued rather than issued right away.
+    ///
+    /// This is meant for deferring work which isn't needed yet, like speculative loads.
+    pub fn would_block(&self) -> bool {
+        self.sender
+            .upgrade()
+            .is_some_and(|sender| sender.would_block())
+    }
+
+    /// Cancel a command sent along this handle or its clones which hasn't been issued yet.
+    ///
+    /// Returns the command if it was cancelled, in which case no completion will be received for
+    /// it. `None` means that the command was issued already, or never sent.
+    pub fn cancel(&self, user_data: u64) -> Option<IoCommand> {
+        self.sender
+            .upgrade()?
+            .cancel(&self.completion_sender, user_data)
     }
 
     /// Block the current thread on receiving an I/O completion.
@@ -224,10 +589,21 @@ impl IoHandle {
         let (completion_sender, completion_receiver) = crossbeam_channel::unbounded();
         IoHandle {
             sender: self.sender.clone(),
+            priority: self.priority,
             completion_sender,
             completion_receiver,
         }
     }
+
+    /// Creates a clone of this handle which sends commands with the given priority.
+    ///
+    /// Completions are received by both, like with any other clone.
+    pub fn with_priority(&self, priority: IoPriority) -> IoHandle {
+        IoHandle {
+            priority,
+            ..self.clone()
+        }
+    }
 }
 
 /// Read a page from the file at the given page number.
@@ -237,3 +613,105 @@ pub fn read_page(page_pool: &PagePool, fd: &File, pn: u64) -> std::io::Result<Fa
     fd.read_exact_at(&mut page[..], pn * PAGE_SIZE as u64)?;
     Ok(page)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        coalesce_writes, IoKind, IoKindResult, PagePool, RetryPolicy, MAX_RETRY_BACKOFF,
+        MAX_RUN_PAGES, PAGE_SIZE,
+    };
+    use std::{sync::Arc, time::Duration};
+
+    #[test]
+    fn retry_backoff_doubles_up_to_cap() {
+        let policy = RetryPolicy {
+            max_retries: 40,
+            backoff: Duration::from_micros(100),
+        };
+        assert_eq!(policy.delay(1), Some(Duration::from_micros(100)));
+        assert_eq!(policy.delay(2), Some(Duration::from_micros(200)));
+        assert_eq!(policy.delay(4), Some(Duration::from_micros(800)));
+        assert_eq!(policy.delay(12), Some(MAX_RETRY_BACKOFF));
+        assert_eq!(policy.delay(40), Some(MAX_RETRY_BACKOFF));
+        assert_eq!(policy.delay(41), None);
+    }
+
+    #[test]
+    fn transient_errors_are_retried() {
+        let page_pool = PagePool::new();
+        let read = IoKind::Read(0, 0, page_pool.alloc_fat_page());
+        let os_err = |errno| move || std::io::Error::from_raw_os_error(errno);
+
+        assert!(matches!(
+            read.get_result(PAGE_SIZE as isize, os_err(0)),
+            IoKindResult::Ok
+        ));
+        assert!(matches!(
+            read.get_result(100, os_err(0)),
+            IoKindResult::Retry(_)
+        ));
+        assert!(matches!(
+            read.get_result(-1, os_err(libc::EINTR)),
+            IoKindResult::Retry(_)
+        ));
+        assert!(matches!(
+            read.get_result(-1, os_err(libc::EAGAIN)),
+            IoKindResult::Retry(_)
+        ));
+        assert!(matches!(
+            read.get_result(-1, os_err(libc::EIO)),
+            IoKindResult::Err(_)
+        ));
+    }
+
+    #[test]
+    fn syncs_succeed_with_zero() {
+        let os_err = |errno| move || std::io::Error::from_raw_os_error(errno);
+        for sync in [
+            IoKind::Fsync(0),
+            IoKind::Fdatasync(0),
+            IoKind::SyncRange(0, 10, 4),
+        ] {
+            assert!(matches!(sync.get_result(0, os_err(0)), IoKindResult::Ok));
+            assert!(matches!(
+                sync.get_result(-1, os_err(libc::EINTR)),
+                IoKindResult::Retry(_)
+            ));
+            assert!(matches!(
+                sync.get_result(-1, os_err(libc::EIO)),
+                IoKindResult::Err(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn coalesce_adjacent_writes() {
+        let page_pool = PagePool::new();
+        let pns = [9, 3, 4, 20, 5, 8, 100]
+            .into_iter()
+            .chain(200..200 + MAX_RUN_PAGES as u64 + 1);
+        let writes = pns
+            .map(|pn| (pn, Arc::new(page_pool.alloc_fat_page())))
+            .collect();
+
+        let kinds: Vec<_> = coalesce_writes(0, writes)
+            .into_iter()
+            .map(|kind| match kind {
+                IoKind::WriteArc(_, pn, _) => (pn, 1),
+                IoKind::WriteRun(_, pn, run) => (pn, run.len()),
+                _ => panic!("unexpected kind {:?}", kind),
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (3, 3),
+                (8, 2),
+                (20, 1),
+                (100, 1),
+                (200, MAX_RUN_PAGES),
+                (200 + MAX_RUN_PAGES as u64, 1),
+            ]
+        );
+    }
+}
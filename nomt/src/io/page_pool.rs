@@ -133,11 +133,25 @@ struct Inner {
     freelist: RwLock<Vec<Page>>,
     // The local freelist for the current thread used to avoid contention on the global freelist.
     tls_freelist: ThreadLocal<RefCell<Vec<Page>>>,
+    // Whether pages are zeroed when allocated.
+    zeroed: bool,
 }
 
 impl PagePool {
     /// Creates a new empty page pool.
     pub fn new() -> Self {
+        Self::with_zeroing(false)
+    }
+
+    /// Creates a new empty page pool which zeroes every page it allocates.
+    ///
+    /// Used where the bytes written to disk must not depend on whatever a page held before, at
+    /// the cost of a memset per allocation.
+    pub fn new_zeroed() -> Self {
+        Self::with_zeroing(true)
+    }
+
+    fn with_zeroing(zeroed: bool) -> Self {
         let regions = std::array::from_fn(|_| AtomicPtr::new(std::ptr::null_mut()));
         // The capacity is chosen to be large enough to fit 4 times as much as 50k pages.
         let freelist = RwLock::new(Vec::with_capacity(200000));
@@ -147,6 +161,7 @@ impl PagePool {
                 n_regions: AtomicU32::new(0),
                 freelist,
                 tls_freelist: ThreadLocal::new(),
+                zeroed,
             }),
         }
     }
@@ -162,8 +177,18 @@ impl PagePool {
 
     /// Allocates a new [`Page`].
     ///
-    /// The contents of the page are undefined.
+    /// The contents of the page are undefined, unless the pool was created with
+    /// [`PagePool::new_zeroed`].
     pub fn alloc(&self) -> Page {
+        let page = self.alloc_uninit();
+        if self.inner.zeroed {
+            // SAFETY: the page was just allocated, so nothing else refers to it.
+            unsafe { page.as_mut_slice().fill(0) };
+        }
+        page
+    }
+
+    fn alloc_uninit(&self) -> Page {
         // fast path: try to serve request from the thread-local freelist.
         let mut tls_freelist = self.tls_freelist();
         if let Some(page) = tls_freelist.pop() {
@@ -0,0 +1,154 @@
+//! Supervision of the I/O workers.
+//!
+//! A panic on an I/O worker would otherwise drop the commands it owns without sending their
+//! completions, leaving the threads waiting on them blocked forever, and shrink the pool for the
+//! rest of the process. Instead, each worker runs under a [`Supervisor`], which catches the panic,
+//! fails the commands owned by the worker with [`FatalError::Panicked`] and restarts the worker on
+//! the same thread. This requires panics to unwind.
+
+use crate::fatal::{self, FatalError};
+use parking_lot::Mutex;
+use std::{
+    panic::AssertUnwindSafe,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+/// The longest a worker waits before restarting after consecutive panics.
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The state of the I/O workers. See [`crate::Nomt::io_health`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IoHealth {
+    /// The number of I/O workers the pool was started with.
+    pub workers: usize,
+    /// The number of I/O workers currently running. Lower than `workers` while a worker restarts
+    /// and after the pool shut down.
+    pub alive: usize,
+    /// The number of times a worker panicked and was restarted.
+    pub restarts: u64,
+    /// The number of commands failed because the worker owning them panicked.
+    pub failed_commands: u64,
+    /// The message of the last panic of a worker, if any.
+    pub last_panic: Option<String>,
+}
+
+impl IoHealth {
+    /// Whether every worker is running.
+    pub fn is_healthy(&self) -> bool {
+        self.alive == self.workers
+    }
+}
+
+/// Restarts the I/O workers of a pool when they panic and keeps track of their health.
+pub(super) struct Supervisor {
+    workers: usize,
+    alive: AtomicUsize,
+    restarts: AtomicU64,
+    failed_commands: AtomicU64,
+    last_panic: Mutex<Option<String>>,
+}
+
+impl Supervisor {
+    /// Create a supervisor for `workers` workers, each of which must be [run](Self::run) once.
+    pub fn new(workers: usize) -> Self {
+        Supervisor {
+            workers,
+            // workers count as alive from the start, so a pool whose threads haven't been
+            // scheduled yet is healthy.
+            alive: AtomicUsize::new(workers),
+            restarts: AtomicU64::new(0),
+            failed_commands: AtomicU64::new(0),
+            last_panic: Mutex::new(None),
+        }
+    }
+
+    /// Run a worker on the current thread until it returns.
+    ///
+    /// The commands owned by the worker must be kept in `state`. Whenever `work` panics,
+    /// `fail_all` is called to fail every command left in `state` with the given error and return
+    /// how many there were, and `work` is called again after a backoff.
+    pub fn run<S>(
+        &self,
+        state: &mut S,
+        mut work: impl FnMut(&mut S),
+        mut fail_all: impl FnMut(&mut S, &FatalError) -> usize,
+    ) {
+        let mut consecutive_panics = 0u32;
+        loop {
+            let payload = match std::panic::catch_unwind(AssertUnwindSafe(|| work(state))) {
+                Ok(()) => break,
+                Err(payload) => payload,
+            };
+            self.alive.fetch_sub(1, Ordering::Relaxed);
+
+            let error = fatal::from_panic(payload);
+            let failed = fail_all(state, &error);
+            self.failed_commands
+                .fetch_add(failed as u64, Ordering::Relaxed);
+            if let FatalError::Panicked(ref message) = error {
+                *self.last_panic.lock() = Some(message.clone());
+            }
+            self.restarts.fetch_add(1, Ordering::Relaxed);
+
+            // back off in case the worker panics on every start.
+            consecutive_panics += 1;
+            let backoff = Duration::from_millis(10)
+                .saturating_mul(1 << consecutive_panics.min(16))
+                .min(MAX_RESTART_BACKOFF);
+            std::thread::sleep(backoff);
+            self.alive.fetch_add(1, Ordering::Relaxed);
+        }
+        self.alive.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn health(&self) -> IoHealth {
+        IoHealth {
+            workers: self.workers,
+            alive: self.alive.load(Ordering::Relaxed),
+            restarts: self.restarts.load(Ordering::Relaxed),
+            failed_commands: self.failed_commands.load(Ordering::Relaxed),
+            last_panic: self.last_panic.lock().clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Supervisor;
+    use crate::fatal::FatalError;
+
+    #[test]
+    fn panicking_worker_fails_its_commands_and_restarts() {
+        let supervisor = Supervisor::new(1);
+        let mut runs = 0;
+        let mut owned = Vec::new();
+        let mut failed = Vec::new();
+        supervisor.run(
+            &mut owned,
+            |owned| {
+                runs += 1;
+                owned.extend([1, 2, 3]);
+                if runs == 1 {
+                    panic!("worker broke");
+                }
+                owned.clear();
+            },
+            |owned, error| {
+                assert_eq!(error, &FatalError::Panicked("worker broke".to_string()));
+                failed.append(owned);
+                failed.len()
+            },
+        );
+
+        assert_eq!(runs, 2);
+        assert_eq!(failed, vec![1, 2, 3]);
+        let health = supervisor.health();
+        assert_eq!(health.restarts, 1);
+        assert_eq!(health.failed_commands, 3);
+        assert_eq!(health.last_panic.as_deref(), Some("worker broke"));
+        // the worker returned.
+        assert_eq!(health.alive, 0);
+        assert!(!health.is_healthy());
+    }
+}
@@ -1,47 +1,92 @@
-use super::{CompleteIo, IoCommand, IoKind, IoKindResult, IoPacket, PagePool, PAGE_SIZE};
-use crossbeam_channel::{Receiver, Sender};
+use super::{
+    supervisor::Supervisor, FairQueue, IoCommand, IoKind, IoKindResult, IoPacket, PagePool,
+    RetryPolicy, PAGE_SIZE,
+};
+use crate::metrics::{Metric, Metrics};
+use std::sync::Arc;
 use threadpool::ThreadPool;
 
 pub fn start_io_worker(
     page_pool: PagePool,
     io_workers_tp: &ThreadPool,
+    command_rx: Arc<FairQueue>,
     io_workers: usize,
-) -> Sender<IoPacket> {
-    let (command_tx, command_rx) = crossbeam_channel::unbounded();
-
+    // every thread executes one command at a time.
+    _queue_depth: usize,
+    retry_policy: RetryPolicy,
+    supervisor: Arc<Supervisor>,
+) {
     for _ in 0..io_workers {
-        spawn_worker_thread(page_pool.clone(), io_workers_tp, command_rx.clone());
+        spawn_worker_thread(
+            page_pool.clone(),
+            io_workers_tp,
+            command_rx.clone(),
+            retry_policy,
+            supervisor.clone(),
+        );
     }
-
-    command_tx
 }
 
 fn spawn_worker_thread(
     page_pool: PagePool,
     io_workers_tp: &ThreadPool,
-    command_rx: Receiver<IoPacket>,
+    command_rx: Arc<FairQueue>,
+    retry_policy: RetryPolicy,
+    supervisor: Arc<Supervisor>,
 ) {
-    let work = move || loop {
-        let Ok(packet) = command_rx.recv() else {
-            // Why the `drop` here?
-            //
-            // `command_rx` receives the IoPacket's which are ultimately parameterized by buffers.
-            // Those buffers are allocated in the `page_pool`. If the `page_pool` is deallocated
-            // before this worker thread is done, that's a use-after-free.
-            //
-            // So in other words, we plumb `page_pool` all the way here and drop it here only to
-            // ensure safety.
-            drop(page_pool);
-            return;
-        };
-        let complete = execute(packet.command);
-        let _ = packet.completion_sender.send(complete);
+    let work = move || {
+        // The command being executed, failed if the worker panics.
+        let mut owned: Option<IoPacket> = None;
+        supervisor.run(
+            &mut owned,
+            |owned| loop {
+                let Ok(packet) = command_rx.recv() else {
+                    return;
+                };
+                let packet = owned.insert(packet);
+                let result = execute(
+                    &mut packet.command,
+                    &retry_policy,
+                    packet.in_flight.metrics(),
+                );
+                // UNWRAP: just inserted.
+                let packet = owned.take().unwrap();
+                let complete = super::CompleteIo {
+                    command: packet.command,
+                    result,
+                };
+                let _ = packet.completion_sender.send(complete);
+                drop(packet.in_flight);
+            },
+            |owned, error| match owned.take() {
+                Some(packet) => {
+                    super::fail_command(packet, error);
+                    1
+                }
+                None => 0,
+            },
+        );
+
+        // Why the `drop` here?
+        //
+        // `command_rx` receives the IoPacket's which are ultimately parameterized by buffers.
+        // Those buffers are allocated in the `page_pool`. If the `page_pool` is deallocated
+        // before this worker thread is done, that's a use-after-free.
+        //
+        // So in other words, we plumb `page_pool` all the way here and drop it here only to
+        // ensure safety.
+        drop(page_pool);
     };
 
     io_workers_tp.execute(work);
 }
 
-fn execute(mut command: IoCommand) -> CompleteIo {
+fn execute(
+    command: &mut IoCommand,
+    retry_policy: &RetryPolicy,
+    metrics: &Metrics,
+) -> std::io::Result<()> {
+    let mut retries = 0;
     let result = loop {
         let res = match command.kind {
             IoKind::Read(fd, page_index, ref mut page) => unsafe {
@@ -77,13 +122,37 @@ fn execute(mut command: IoCommand) -> CompleteIo {
                     (page_index * PAGE_SIZE as u64) as libc::off_t,
                 )
             },
+            IoKind::WriteRun(fd, page_index, ref run) => unsafe {
+                let iovecs = run.iovecs();
+                libc::pwritev(
+                    fd,
+                    iovecs.as_ptr(),
+                    iovecs.len() as libc::c_int,
+                    (page_index * PAGE_SIZE as u64) as libc::off_t,
+                )
+            },
+            IoKind::Fsync(fd) => unsafe { libc::fsync(fd) as isize },
+            // macOS has no fdatasync.
+            #[cfg(target_vendor = "apple")]
+            IoKind::Fdatasync(fd) => unsafe { libc::fsync(fd) as isize },
+            #[cfg(not(target_vendor = "apple"))]
+            IoKind::Fdatasync(fd) => unsafe { libc::fdatasync(fd) as isize },
+            // there is no ranged sync, the following fdatasync syncs the range as well.
+            IoKind::SyncRange(_, _, _) => 0,
         };
-        match command.kind.get_result(res) {
+        match command.kind.get_result(res, std::io::Error::last_os_error) {
             IoKindResult::Ok => break Ok(()),
-            IoKindResult::Err => break Err(std::io::Error::last_os_error()),
-            IoKindResult::Retry => (),
+            IoKindResult::Err(e) => break Err(e),
+            IoKindResult::Retry(e) => {
+                retries += 1;
+                let Some(delay) = retry_policy.delay(retries) else {
+                    break Err(e);
+                };
+                metrics.count(Metric::IoRetries);
+                std::thread::sleep(delay);
+            }
         }
     };
 
-    CompleteIo { command, result }
+    result
 }
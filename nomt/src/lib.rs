@@ -2,15 +2,22 @@
 
 //! A Nearly-Optimal Merkle Trie Database.
 
+use beatree::LeafAccesses;
 use bitvec::prelude::*;
+use commit_log::{CommitLog, Phase};
 use io::PagePool;
 use metrics::{Metric, Metrics};
-use std::{mem, sync::Arc};
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    mem,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use merkle::{UpdatePool, Updater};
 use nomt_core::{
     hasher::{NodeHasher, ValueHasher},
-    page_id::ROOT_PAGE_ID,
+    page_id::{PageId, ROOT_PAGE_ID},
     proof::PathProof,
     trie::{InternalData, KeyPath, LeafData, Node, ValueHash, TERMINATOR},
     trie_pos::TriePosition,
@@ -18,16 +25,45 @@ use nomt_core::{
 use overlay::{LiveOverlay, OverlayMarker};
 use page_cache::PageCache;
 use parking_lot::{ArcRwLockReadGuard, Mutex, RwLock};
-use store::{Store, ValueTransaction};
+use session_stats::SessionCounters;
+use store::{CommitRecord, Store, ValueTransaction};
+use value_verify::ValueVerifier;
 
 // CARGO HACK: silence lint; this is used in integration tests
 
+pub use beatree::{ValueReader, ValueStoreStats};
+pub use block_witness::BlockWitness;
+pub use commit_limits::{CommitLimit, CommitLimitExceeded, CommitLimits};
+pub use commit_log::CommitLogThresholds;
+pub use commit_signer::{CommitSigner, MAX_SIGNATURE_LEN};
+pub use commit_sink::{CommitChanges, CommitSink, PageChange, ValueChange};
+pub use cursor::{Cursor, KeyValueIter, CURSOR_LEN, MAX_ITER_SHARDS};
+pub use fatal::FatalError;
+pub use fork_tree::ForkTree;
+pub use health::Health;
+pub use io::{IoHealth, RetryPolicy, SharedIoPool};
+pub use node_hook::{NodePreimage, NodePreimageHook};
 pub use nomt_core::hasher;
+pub use nomt_core::key_path;
+pub use nomt_core::mmr;
 pub use nomt_core::proof;
 pub use nomt_core::trie;
-pub use options::{Options, PanicOnSyncMode};
+pub use options::{CommitStrategy, Options, PanicOnSyncMode, RetentionPolicy};
 pub use overlay::{InvalidAncestors, Overlay};
+pub use page_cache::PageCacheStats;
+pub use page_diff::PageDiff;
+pub use page_heatmap::{PageAccessReport, SubtreeAccesses};
+pub use page_utilization::PageUtilization;
+pub use root_index::RootInfo;
+pub use session_stats::{SessionStats, SlowRead};
+pub use state_usage::{StateUsage, StateUsageDelta};
 pub use store::HashTableUtilization;
+pub use sub_session::{Conflict, ConflictKind, MergeConflicts, SubSession};
+pub use trie_export::{TrieExportFormat, MAX_TRIE_EXPORT_DEPTH};
+pub use trie_stats::{TrieStats, TrieStatsMode};
+pub use value_verify::ValueCorruption;
+pub use view::{HistoricalIter, HistoricalView};
+pub use write_batch::{WriteBatch, WriteBatchError};
 
 // beatree module needs to be exposed to be benchmarked and fuzzed
 #[cfg(any(feature = "benchmarks", feature = "fuzz"))]
@@ -36,20 +72,59 @@ pub mod beatree;
 #[cfg(not(any(feature = "benchmarks", feature = "fuzz")))]
 mod beatree;
 
+// the page cache benchmarks are exposed to be run from the benches directory.
+#[cfg(feature = "benchmarks")]
+pub use page_cache::benches as page_cache_benches;
+
+mod backup;
 mod bitbox;
+mod block_witness;
+mod commit_limits;
+mod commit_log;
+mod commit_signer;
+mod commit_sink;
+mod commit_verify;
+mod cursor;
+pub mod differential;
+pub mod dump;
+#[cfg(feature = "eth")]
+pub mod eth;
+mod expiry;
+mod fatal;
+pub mod fixture;
+mod fork_tree;
+mod health;
+pub mod manifest;
 mod merkle;
 mod metrics;
+mod node_hook;
 mod options;
 mod overlay;
 mod page_cache;
 mod page_diff;
+mod page_header;
+mod page_heatmap;
 mod page_region;
+mod page_utilization;
+pub mod proof_pool;
+pub mod replay;
 mod rollback;
+mod root_index;
 mod rw_pass_cell;
 mod seglog;
+mod session_stats;
+pub mod snapshot;
+mod state_usage;
 mod store;
+mod sub_session;
 mod sys;
 mod task;
+mod trie_export;
+mod trie_stats;
+mod value_gc;
+mod value_verify;
+mod view;
+mod write_batch;
 
 mod io;
 
@@ -58,6 +133,12 @@ const MAX_COMMIT_CONCURRENCY: usize = 64;
 /// A full value stored within the trie.
 pub type Value = Vec<u8>;
 
+/// The maximum size of a value in bytes, 512MiB.
+///
+/// Values larger than a fraction of a page are transparently split into chunks stored outside
+/// of the leaves holding their keys. They can be read incrementally with [`Session::read_stream`].
+pub const MAX_VALUE_SIZE: usize = beatree::MAX_VALUE_SIZE;
+
 struct Shared {
     /// The current root of the trie.
     root: Root,
@@ -218,6 +299,12 @@ impl Root {
     }
 }
 
+impl From<[u8; 32]> for Root {
+    fn from(root: [u8; 32]) -> Self {
+        Root(root)
+    }
+}
+
 impl std::fmt::Display for Root {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         for byte in &self.0[0..4] {
@@ -255,15 +342,24 @@ pub struct Nomt<T: HashAlgorithm> {
     /// Used to protect the multiple-readers-one-writer API
     access_lock: Arc<RwLock<()>>,
     metrics: Metrics,
+    backup: Option<backup::BackupLog>,
+    expiry: Option<expiry::ExpiryIndex>,
+    root_index: Option<root_index::RootIndex>,
+    state_usage: Option<state_usage::StateUsageIndex>,
+    value_gc: Option<value_gc::ValueGc>,
+    commit_sink: Option<Arc<dyn CommitSink>>,
+    commit_signer: Option<Arc<dyn CommitSigner>>,
+    commit_limits: CommitLimits,
+    commit_log_thresholds: CommitLogThresholds,
+    verify_commits: bool,
+    verify_values: bool,
     _marker: std::marker::PhantomData<T>,
 }
 
 impl<T: HashAlgorithm> Nomt<T> {
     /// Open the database with the given options.
     pub fn open(mut o: Options) -> anyhow::Result<Self> {
-        if o.commit_concurrency == 0 {
-            anyhow::bail!("commit concurrency must be greater than zero".to_string());
-        }
+        o.validate()?;
 
         if o.commit_concurrency > MAX_COMMIT_CONCURRENCY {
             o.commit_concurrency = MAX_COMMIT_CONCURRENCY;
@@ -271,11 +367,62 @@ impl<T: HashAlgorithm> Nomt<T> {
 
         let metrics = Metrics::new(o.metrics);
 
-        let page_pool = PagePool::new();
-        let store = Store::open(&o, page_pool.clone())?;
+        let page_pool = match o.shared_io_pool {
+            Some((ref pool, _)) => pool.page_pool().clone(),
+            None if o.deterministic_layout => PagePool::new_zeroed(),
+            None => PagePool::new(),
+        };
+        let store = Store::open(&o, page_pool.clone(), metrics.clone())?;
         let root_page = store.load_page(ROOT_PAGE_ID)?;
         let page_cache = PageCache::new(root_page, &o, metrics.clone());
-        let root = compute_root_node::<T>(&page_cache, &store);
+        let root = match store.committed_root() {
+            Some(root) => {
+                check_root_node::<T>(&page_cache, root)?;
+                root
+            }
+            None => compute_root_node::<T>(&page_cache, &store),
+        };
+
+        let backup = o
+            .backup_log
+            .as_deref()
+            .map(backup::BackupLog::open)
+            .transpose()?;
+        let expiry = if o.expiry_index {
+            Some(expiry::ExpiryIndex::open(
+                &o.path,
+                &store,
+                store.sync_seqn() as u64,
+            )?)
+        } else {
+            None
+        };
+        let root_index = if o.root_index {
+            Some(root_index::RootIndex::open(
+                &o.path,
+                Root(root),
+                store.sync_seqn() as u64,
+            )?)
+        } else {
+            None
+        };
+        let state_usage = o
+            .state_usage_prefix_len
+            .map(|prefix_len| {
+                state_usage::StateUsageIndex::open(
+                    &o.path,
+                    &store,
+                    store.sync_seqn() as u64,
+                    prefix_len,
+                    o.state_usage_history,
+                )
+            })
+            .transpose()?;
+
+        let value_gc = o
+            .value_gc_interval
+            .map(|interval| value_gc::ValueGc::start(store.clone(), interval))
+            .transpose()?;
 
         if o.prepopulate_page_cache {
             let io_handle = store.io_pool().make_handle();
@@ -283,7 +430,12 @@ impl<T: HashAlgorithm> Nomt<T> {
         }
 
         Ok(Self {
-            merkle_update_pool: UpdatePool::new(o.commit_concurrency, o.warm_up),
+            merkle_update_pool: UpdatePool::new(
+                o.commit_concurrency,
+                o.warm_up,
+                o.page_prefetch_depth,
+                o.node_preimage_hook,
+            ),
             page_cache,
             page_pool,
             store,
@@ -293,6 +445,17 @@ impl<T: HashAlgorithm> Nomt<T> {
             })),
             access_lock: Arc::new(RwLock::new(())),
             metrics,
+            backup,
+            expiry,
+            root_index,
+            state_usage,
+            value_gc,
+            commit_sink: o.commit_sink,
+            commit_signer: o.commit_signer,
+            commit_limits: o.commit_limits,
+            commit_log_thresholds: o.commit_log_thresholds,
+            verify_commits: o.verify_commits,
+            verify_values: o.verify_values,
             _marker: std::marker::PhantomData,
         })
     }
@@ -314,8 +477,68 @@ impl<T: HashAlgorithm> Nomt<T> {
     /// This is used for testing for now.
     #[doc(hidden)]
     pub fn read(&self, path: KeyPath) -> anyhow::Result<Option<Value>> {
+        self.read_inner(path, self.verify_values)
+    }
+
+    /// Returns the value stored under the given key, verified against the trie.
+    ///
+    /// Fails with a [`ValueCorruption`] if the value store returns a value which doesn't match
+    /// the trie. See [`Options::verify_values`].
+    #[doc(hidden)]
+    pub fn read_verified(&self, path: KeyPath) -> anyhow::Result<Option<Value>> {
+        self.read_inner(path, true)
+    }
+
+    fn read_inner(&self, path: KeyPath, verify: bool) -> anyhow::Result<Option<Value>> {
+        let _guard = self.access_lock.read();
+        let value = self.store.load_value(path)?.0;
+        if let (true, Some(value)) = (verify, &value) {
+            self.value_verifier().verify::<T>(path, value)?;
+        }
+        Ok(value)
+    }
+
+    fn value_verifier(&self) -> ValueVerifier {
+        ValueVerifier::new(self.page_cache.clone(), self.store.clone(), self.root())
+    }
+
+    /// Whether a value is stored under the given key.
+    ///
+    /// This follows the path of the key down the trie to its terminal node. A terminator answers
+    /// without touching the value store, and a leaf takes a single lookup of the key in the value
+    /// store, which never reads the pages of a large value. This is cheaper than [`Nomt::read`]
+    /// for checking existence. Fails only if I/O fails.
+    pub fn contains(&self, path: KeyPath) -> anyhow::Result<bool> {
         let _guard = self.access_lock.read();
-        self.store.load_value(path)
+        if !self.terminal_is_leaf(&path)? {
+            return Ok(false);
+        }
+        Ok(self.store.load_value_shallow(path).is_some())
+    }
+
+    /// The hash of the value stored under the given key, or `None` if there is no value.
+    ///
+    /// Like [`Nomt::contains`], this never reads the pages of a large value: their hash is stored
+    /// alongside them. Small values are hashed.
+    pub fn value_hash(&self, path: KeyPath) -> anyhow::Result<Option<ValueHash>> {
+        let _guard = self.access_lock.read();
+        if !self.terminal_is_leaf(&path)? {
+            return Ok(None);
+        }
+        Ok(self
+            .store
+            .load_value_shallow(path)
+            .map(|value| match value {
+                beatree::ShallowValue::Inline(value) => T::hash_value(&value),
+                beatree::ShallowValue::Overflow(value_hash) => value_hash,
+            }))
+    }
+
+    // Whether the terminal node on the path of the key is a leaf.
+    fn terminal_is_leaf(&self, path: &KeyPath) -> anyhow::Result<bool> {
+        let root = self.root().into_inner();
+        let node = value_verify::terminal_node::<T>(&self.page_cache, &self.store, root, path)?;
+        Ok(trie::is_leaf::<T>(&node))
     }
 
     /// Returns the current sync sequence number.
@@ -324,6 +547,135 @@ impl<T: HashAlgorithm> Nomt<T> {
         self.store.sync_seqn()
     }
 
+    /// Returns the sequence number of the last commit, or 0 if nothing was ever committed.
+    ///
+    /// Every commit, including those made by [`Nomt::rollback`], is assigned the next sequence
+    /// number and is durable once the sequence number is current. The sequence number is stored
+    /// alongside the root and survives reopening the database.
+    pub fn current_sequence(&self) -> u64 {
+        self.store.sync_seqn() as u64
+    }
+
+    /// Block until the commit with the given sequence number is durable or the timeout elapses.
+    /// Returns whether the commit is durable.
+    ///
+    /// This is useful for coordinating with a log kept outside of NOMT, from threads other than
+    /// the one committing.
+    pub fn wait_for_sequence(&self, sequence: u64, timeout: std::time::Duration) -> bool {
+        let Ok(sync_seqn) = u32::try_from(sequence) else {
+            return false;
+        };
+        self.store.wait_sync_seqn(sync_seqn, timeout)
+    }
+
+    /// Up to `limit` keys which weren't written by the commit with the given sequence number or
+    /// any later one, along with the sequence number of the commit which last wrote them, least
+    /// recently written first. Deleted keys are not returned.
+    ///
+    /// Fails if the database wasn't opened with [`Options::expiry_index`].
+    pub fn expired_keys(&self, sequence: u64, limit: usize) -> anyhow::Result<Vec<(KeyPath, u64)>> {
+        match self.expiry {
+            Some(ref expiry) => Ok(expiry.written_before(sequence, limit)),
+            None => anyhow::bail!("the expiry index is not enabled"),
+        }
+    }
+
+    /// The sequence number of the commit which last wrote the key, or `None` if the key doesn't
+    /// exist.
+    ///
+    /// Fails if the database wasn't opened with [`Options::expiry_index`].
+    pub fn last_written(&self, key: KeyPath) -> anyhow::Result<Option<u64>> {
+        match self.expiry {
+            Some(ref expiry) => Ok(expiry.last_written(&key)),
+            None => anyhow::bail!("the expiry index is not enabled"),
+        }
+    }
+
+    /// The last commit which produced the given root, or `None` if no commit recorded by the
+    /// index produced it.
+    ///
+    /// Fails if the database wasn't opened with [`Options::root_index`].
+    pub fn lookup_root(&self, root: Root) -> anyhow::Result<Option<RootInfo>> {
+        match self.root_index {
+            Some(ref root_index) => Ok(root_index.lookup(root)),
+            None => anyhow::bail!("the root index is not enabled"),
+        }
+    }
+
+    /// The number of keys and value bytes stored under the prefix.
+    ///
+    /// Fails if the database wasn't opened with [`Options::state_usage`] or if the prefix is
+    /// longer than the tracked prefixes.
+    pub fn state_usage(&self, prefix: &BitSlice<u8, Msb0>) -> anyhow::Result<StateUsage> {
+        match self.state_usage {
+            Some(ref state_usage) => state_usage.usage(prefix),
+            None => anyhow::bail!("state usage is not tracked"),
+        }
+    }
+
+    /// The keys and value bytes added and removed under the prefix by the commits after the one
+    /// with the given sequence number.
+    ///
+    /// Fails if the database wasn't opened with [`Options::state_usage`], if the prefix is longer
+    /// than the tracked prefixes or if the changes of some of the commits are no longer retained.
+    /// See [`Options::state_usage_history`].
+    pub fn state_usage_since(
+        &self,
+        prefix: &BitSlice<u8, Msb0>,
+        sequence: u64,
+    ) -> anyhow::Result<StateUsageDelta> {
+        match self.state_usage {
+            Some(ref state_usage) => state_usage.delta_since(prefix, sequence),
+            None => anyhow::bail!("state usage is not tracked"),
+        }
+    }
+
+    /// The identifier of the last commit, if it was given one with [`SessionParams::commit_id`].
+    ///
+    /// This is `None` after a commit without an identifier, including overlay commits and
+    /// rollbacks.
+    pub fn last_commit_id(&self) -> Option<u64> {
+        self.store.last_commit().map(|(id, _)| id)
+    }
+
+    /// The signature of the root of the last commit, made by the signer set with
+    /// [`Options::commit_signer`].
+    ///
+    /// This is `None` if the last commit was made without a signer.
+    pub fn root_signature(&self) -> Option<Vec<u8>> {
+        self.store.root_signature()
+    }
+
+    /// The fencing token held by this writer. See [`Options::fencing_token`].
+    ///
+    /// This is `None` if the database was opened read-only.
+    pub fn fencing_token(&self) -> Option<u64> {
+        self.store.fencing_token()
+    }
+
+    /// The number of leaves of the Merkle mountain range kept alongside the trie, as of the last
+    /// commit. Leaves are appended with [`Session::append_mmr_leaf`].
+    pub fn mmr_leaves(&self) -> u64 {
+        self.store.mmr().0
+    }
+
+    /// The root of the Merkle mountain range, as of the last commit. This is the [`TERMINATOR`]
+    /// if no leaf was appended.
+    ///
+    /// The range is append-only: it is not affected by [`Nomt::rollback`].
+    pub fn mmr_root(&self) -> Node {
+        self.store.mmr().1
+    }
+
+    /// Prove a leaf of the Merkle mountain range against [`Nomt::mmr_root`]. Returns `None` if the
+    /// leaf is out of range.
+    ///
+    /// The proof is verified against the hash of the data of the leaf, given by
+    /// [`mmr::hash_leaf`].
+    pub fn mmr_proof(&self, leaf_index: u64) -> anyhow::Result<Option<mmr::MmrProof>> {
+        self.store.mmr_proof(leaf_index)
+    }
+
     /// Whether the database is poisoned.
     ///
     /// A database becomes poisoned when an error occurred during a commit operation.
@@ -337,6 +689,22 @@ impl<T: HashAlgorithm> Nomt<T> {
         self.store.is_poisoned()
     }
 
+    /// Whether the database was opened read-only. See [`Options::read_only`].
+    pub fn is_read_only(&self) -> bool {
+        self.store.is_read_only()
+    }
+
+    /// Derive the key path of a key.
+    ///
+    /// If the database was created with [`Options::keyed_key_paths`], this is a hash of the key
+    /// keyed by the database's secret. Otherwise, it is a plain hash of the key.
+    pub fn key_path(&self, key: &[u8]) -> KeyPath {
+        match self.store.key_secret() {
+            Some(key_secret) => *blake3::keyed_hash(key_secret, key).as_bytes(),
+            None => *blake3::hash(key).as_bytes(),
+        }
+    }
+
     /// Create a new [`Session`] object with the given parameters.
     ///
     /// This will block if there are any ongoing commits or rollbacks. Multiple sessions may
@@ -351,6 +719,11 @@ impl<T: HashAlgorithm> Nomt<T> {
     /// and permit a changeset to be committed either directly to the database or into an
     /// in-memory [`Overlay`].
     pub fn begin_session(&self, params: SessionParams) -> Session<T> {
+        // the guard must be taken before the root is read, or a commit landing in between would
+        // leave the session with the root preceding the state it reads.
+        let access_guard = params
+            .take_global_guard
+            .then(|| RwLock::read_arc(&self.access_lock));
         let live_overlay = params.overlay;
 
         let store = self.store.clone();
@@ -379,20 +752,208 @@ impl<T: HashAlgorithm> Nomt<T> {
             rollback_delta,
             overlay: live_overlay,
             witness_mode: params.witness,
-            access_guard: params
-                .take_global_guard
-                .then(|| RwLock::read_arc(&self.access_lock)),
+            access_guard,
             prev_root: Root(prev_root),
+            value_verifier: self.value_verifier(),
+            verify_values: self.verify_values,
+            commit_id: params.commit_id,
+            updates: Mutex::new(BTreeMap::new()),
+            read_counters: SessionCounters::new(params.slow_read_threshold),
+            mmr_leaves: Mutex::new(Vec::new()),
             _marker: std::marker::PhantomData,
         }
     }
 
+    /// Create a new [`ReadSession`] over the last committed state.
+    ///
+    /// This will block if there are any ongoing commits or rollbacks. Like [`Session`]s, read
+    /// sessions prevent writes to the database while they are live.
+    ///
+    /// Unlike a [`Session`], a read session cannot be used to produce a changeset and does not
+    /// spin up any merkle update machinery, which makes it cheap to create.
+    pub fn begin_read_session(&self) -> ReadSession<T> {
+        let access_guard = RwLock::read_arc(&self.access_lock);
+        ReadSession {
+            store: self.store.clone(),
+            metrics: self.metrics.clone(),
+            // UNWRAP: empty live overlay always valid.
+            overlay: LiveOverlay::new(None).unwrap(),
+            root: self.root(),
+            value_verifier: self.value_verifier(),
+            verify_values: self.verify_values,
+            _access_guard: access_guard,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Create a read-only [`HistoricalView`] of the trie as of the given root.
+    ///
+    /// The root must be the current root or one that can be reached by rolling back the commits
+    /// recorded in the rollback log. Like [`ReadSession`]s, views prevent writes to the database
+    /// while they are live.
+    ///
+    /// Fails if the root is not the current one and the DB is not configured for rollback or the
+    /// root is not in the rollback log.
+    pub fn view_at(&self, root: Root) -> anyhow::Result<HistoricalView<'_, T>> {
+        let access_guard = RwLock::read_arc(&self.access_lock);
+        HistoricalView::new(self, root, access_guard)
+    }
+
+    /// Prove the current values of the keys a block will touch, for the block to be executed
+    /// statelessly. See [`BlockWitness::builder`].
+    ///
+    /// Like [`Nomt::begin_session`], this blocks while there are ongoing commits.
+    pub fn block_witness(
+        &self,
+        keys: impl IntoIterator<Item = KeyPath>,
+    ) -> anyhow::Result<BlockWitness> {
+        block_witness::block_witness(self, SessionParams::default(), keys)
+    }
+
+    /// Export the entire key-value state to a flat dump file at `path`.
+    ///
+    /// Entries are written in key order and the dump header records the root of the exported
+    /// state. See the [`dump`] module for the format. Returns the number of exported entries.
+    ///
+    /// A [`manifest::Manifest`] of the dump is written next to it, at
+    /// [`manifest::manifest_path`], so that receivers can verify the dump chunk by chunk.
+    ///
+    /// This blocks commits only while the export is being set up, but the database cannot be
+    /// synced to disk until the export is complete.
+    pub fn export(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        compression: dump::Compression,
+    ) -> anyhow::Result<u64> {
+        let (root, read_tx) = {
+            let _guard = self.access_lock.read();
+            (self.root(), self.store.read_transaction())
+        };
+        dump::export(&self.store, read_tx, root, path.as_ref(), compression)
+    }
+
+    /// Export the current key-value state to a snapshot at `path`, which can be opened and
+    /// queried with [`snapshot::Snapshot::open`] without opening the database.
+    /// Returns the number of exported entries.
+    ///
+    /// Like [`Nomt::export`], this writes a manifest next to the snapshot and blocks commits only
+    /// while the export is being set up.
+    pub fn export_snapshot(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<u64> {
+        let (root, read_tx) = {
+            let _guard = self.access_lock.read();
+            (self.root(), self.store.read_transaction())
+        };
+        snapshot::export(&self.store, read_tx, root, path.as_ref())
+    }
+
+    /// Compute statistics about the shape of the trie, such as the number of leaves and their
+    /// depth, to monitor state growth and the effect of how keys are derived.
+    ///
+    /// The shape is derived from the stored keys, either by scanning all of them or by sampling,
+    /// depending on `mode`. Like [`Nomt::export`], this doesn't block commits.
+    pub fn trie_stats(&self, mode: TrieStatsMode) -> anyhow::Result<TrieStats> {
+        let read_tx = {
+            let _guard = self.access_lock.read();
+            self.store.read_transaction()
+        };
+        trie_stats::trie_stats(&self.store, read_tx, mode)
+    }
+
+    /// Export a region of the committed trie for visual inspection, as graphviz DOT or JSON: the
+    /// node at `prefix` and the nodes up to `depth` levels below it, along with the pages holding
+    /// them and the key paths of the leaves. See [`TrieExportFormat`].
+    ///
+    /// Fails if `depth` exceeds [`MAX_TRIE_EXPORT_DEPTH`] or if the trie ends above the prefix.
+    /// This blocks commits until it is done.
+    pub fn export_trie(
+        &self,
+        prefix: &BitSlice<u8, Msb0>,
+        depth: usize,
+        format: TrieExportFormat,
+    ) -> anyhow::Result<String> {
+        let _guard = self.access_lock.read();
+        let read_tx = self.store.read_transaction();
+        trie_export::export_trie::<T>(
+            &self.store,
+            &read_tx,
+            self.root().into_inner(),
+            prefix,
+            depth,
+            format,
+        )
+    }
+
+    /// Load a flat dump file produced by [`Nomt::export`] into this database, which must be empty.
+    ///
+    /// The root of the entries in the dump is computed and verified against the root recorded in
+    /// its header before anything is written, and the import is refused on a mismatch. The entries
+    /// are then committed in batches. Returns the number of imported entries.
+    pub fn import(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<u64> {
+        dump::import(self, path.as_ref())
+    }
+
+    /// Restore this database, which must be empty, from a snapshot produced by [`Nomt::export`]
+    /// and a backup log recorded with [`Options::backup_log`].
+    ///
+    /// The commits recorded in the backup log after the snapshot was taken are replayed, up to
+    /// and including the commit resulting in `target`, or all of them if `target` is `None`.
+    /// Returns the root of the restored state.
+    pub fn restore(
+        &self,
+        snapshot: impl AsRef<std::path::Path>,
+        backup_log: impl AsRef<std::path::Path>,
+        target: Option<Root>,
+    ) -> anyhow::Result<Root> {
+        backup::restore(self, snapshot.as_ref(), backup_log.as_ref(), target)
+    }
+
+    /// Pin a root, marking it as a historical root which must remain reconstructible.
+    ///
+    /// The rollback deltas needed to roll back to a pinned root are never pruned by the retention
+    /// policy, and [`Nomt::rollback`] refuses to roll back past a pinned root. The backup log, if
+    /// any, is never pruned, so it can always restore a pinned root it recorded.
+    ///
+    /// Pins are persisted in the database directory and kept across restarts. Returns `false` if
+    /// the root was pinned already. Fails in read-only mode or if the pins cannot be persisted.
+    pub fn pin_root(&self, root: Root) -> anyhow::Result<bool> {
+        self.store.root_pins().pin(root.into_inner())
+    }
+
+    /// Remove a pin set with [`Nomt::pin_root`]. Returns `false` if the root wasn't pinned.
+    pub fn unpin_root(&self, root: Root) -> anyhow::Result<bool> {
+        self.store.root_pins().unpin(root.into_inner())
+    }
+
+    /// The roots pinned with [`Nomt::pin_root`], in ascending order.
+    pub fn pinned_roots(&self) -> Vec<Root> {
+        self.store
+            .root_pins()
+            .roots()
+            .into_iter()
+            .map(Root::from)
+            .collect()
+    }
+
+    /// Delete every key in a single commit, resetting the root to empty.
+    ///
+    /// This is an ordinary commit: it is atomic, can be rolled back and is recorded by the backup
+    /// log and commit sink, if any. The pages and value storage freed by it are reused by later
+    /// commits, but the database files are not shrunk.
+    ///
+    /// Fails if another commit lands while the keys are being deleted.
+    pub fn reset(&self) -> anyhow::Result<()> {
+        let session = self.begin_session(SessionParams::default());
+        session.delete_prefix(BitSlice::empty())?;
+        session.finish(Vec::new())?.commit(self)?;
+        Ok(())
+    }
+
     /// Perform a rollback of the last `n` commits.
     ///
     /// This function will block until all ongoing commits or [`Session`]s are finished.
     ///
-    /// Fails if the DB is not configured for rollback or doesn't have enough commits logged to
-    /// rollback.
+    /// Fails if the DB is not configured for rollback, doesn't have enough commits logged to
+    /// rollback, or if rolling back would discard a root pinned with [`Nomt::pin_root`].
     pub fn rollback(&self, n: usize) -> anyhow::Result<()> {
         if n == 0 {
             return Ok(());
@@ -403,7 +964,7 @@ impl<T: HashAlgorithm> Nomt<T> {
         let Some(rollback) = self.store.rollback() else {
             anyhow::bail!("rollback: not enabled");
         };
-        let Some(traceback) = rollback.truncate(n)? else {
+        let Some(traceback) = rollback.truncate(n, self.root().into_inner())? else {
             anyhow::bail!("rollback: not enough logged for rolling back");
         };
 
@@ -437,10 +998,110 @@ impl<T: HashAlgorithm> Nomt<T> {
         self.metrics.clone()
     }
 
+    /// Get a report of the sampled page cache accesses, showing which parts of the trie are
+    /// accessed most and where the cache misses.
+    ///
+    /// Returns `None` unless sampling is enabled with [`Options::page_access_sampling`].
+    pub fn page_access_report(&self) -> Option<PageAccessReport> {
+        self.page_cache.access_report()
+    }
+
+    /// Pin a page of the trie in the page cache, so that it is never evicted, for example a page
+    /// known to be on a hot path. Pins nest: the page is kept until unpinned as many times.
+    ///
+    /// The page is kept once it is loaded. Pinned pages count against
+    /// [`Options::page_cache_size`].
+    pub fn pin_page(&self, page_id: PageId) {
+        self.page_cache.pin(page_id);
+    }
+
+    /// Unpin a page pinned with [`Nomt::pin_page`]. Returns `false` if the page wasn't pinned.
+    pub fn unpin_page(&self, page_id: &PageId) -> bool {
+        self.page_cache.unpin(page_id)
+    }
+
     /// Get the hash-table space utilization.
     pub fn hash_table_utilization(&self) -> HashTableUtilization {
         self.store.hash_table_utilization()
     }
+
+    /// Report the health of the database, e.g. for liveness and readiness probes.
+    ///
+    /// This doesn't block on ongoing sessions or commits.
+    pub fn health(&self) -> Health {
+        let io_pool = self.store.io_pool();
+        Health {
+            io: io_pool.health(),
+            io_in_flight: io_pool.in_flight().count(),
+            io_in_flight_limit: io_pool.in_flight().limit(),
+            io_queued: io_pool.queued(),
+            last_sync: self.store.last_sync(),
+            disk_available: self.store.disk_available().ok(),
+            poisoned: self.store.is_poisoned(),
+            read_only: self.store.is_read_only(),
+        }
+    }
+
+    /// Check the health of the I/O workers.
+    ///
+    /// A worker which panics fails the I/O it was performing, which in turn fails the session or
+    /// commit waiting on it, and is restarted. The workers are counted once per pool, so this
+    /// covers other instances using the same [`SharedIoPool`].
+    pub fn io_health(&self) -> IoHealth {
+        self.store.io_pool().health()
+    }
+
+    /// Report how full the stored pages are, to spot sparse parts of the page tree.
+    ///
+    /// This walks every stored page and blocks commits until it is done.
+    pub fn page_utilization(&self) -> anyhow::Result<PageUtilization> {
+        let _guard = self.access_lock.read();
+        page_utilization::page_utilization(&self.store)
+    }
+
+    /// Scan the value store for orphaned pages and report how its space is used, including the
+    /// bytes which hold no stored value and could be reclaimed.
+    ///
+    /// The pages of overwritten and deleted values are freed by the commit which replaces them
+    /// and reused by later commits. An orphaned page is neither reachable nor free, so its space
+    /// is lost to reuse. If `reclaim` is true, the orphaned pages are freed by the next commit.
+    /// Scans can also be run in the background, see [`Options::value_gc_interval`].
+    ///
+    /// This reads every leaf of the value store and blocks commits from syncing until it is done.
+    /// Fails if the database is poisoned, or read-only and asked to reclaim.
+    pub fn collect_value_garbage(&self, reclaim: bool) -> anyhow::Result<ValueStoreStats> {
+        self.store.collect_value_garbage(reclaim)
+    }
+
+    /// The statistics of the last background scan of the value store. `None` if background scans
+    /// are disabled or none has completed yet. See [`Options::value_gc_interval`].
+    pub fn value_gc_stats(&self) -> Option<ValueStoreStats> {
+        self.value_gc.as_ref().and_then(|gc| gc.last_stats())
+    }
+
+    /// Get statistics about the page cache, such as the number of permanently resident pages.
+    pub fn page_cache_stats(&self) -> PageCacheStats {
+        self.page_cache.stats()
+    }
+
+    /// Drop every page from the page cache which isn't pinned or permanently resident, e.g. on
+    /// memory pressure or when switching to another fork.
+    ///
+    /// This blocks until all ongoing sessions and commits have finished.
+    pub fn clear_page_cache(&self) {
+        let _guard = self.access_lock.write();
+        self.page_cache.clear();
+    }
+
+    /// Evict the least recently used pages from the page cache until the pages which aren't
+    /// permanently resident take up at most `bytes`. Pinned pages count against the size, but are
+    /// kept. The cache grows back up to [`Options::page_cache_size`] as pages are loaded.
+    ///
+    /// This blocks until all ongoing sessions and commits have finished.
+    pub fn shrink_page_cache(&self, bytes: usize) {
+        let _guard = self.access_lock.write();
+        self.page_cache.shrink_to(bytes);
+    }
 }
 
 /// A configuration type used to inform NOMT whether to generate witnesses of accessed data.
@@ -467,6 +1128,8 @@ pub struct SessionParams {
 
     witness: WitnessMode,
     overlay: LiveOverlay,
+    commit_id: Option<u64>,
+    slow_read_threshold: Option<Duration>,
 }
 
 impl Default for SessionParams {
@@ -477,6 +1140,8 @@ impl Default for SessionParams {
             witness: WitnessMode::disabled(),
             // UNWRAP: empty live overlay always valid.
             overlay: LiveOverlay::new(None).unwrap(),
+            commit_id: None,
+            slow_read_threshold: None,
         }
     }
 }
@@ -508,11 +1173,32 @@ impl SessionParams {
         self.overlay = LiveOverlay::new(ancestors)?;
         Ok(self)
     }
+
+    /// Identify the commit of this session, e.g. by its position in a log kept outside of NOMT.
+    /// Default: None
+    ///
+    /// Committing a session with the identifier of the last commit is a no-op, as long as the
+    /// session was based on the same previous root as that commit or on the root it produced.
+    /// This lets the last entry of a log be replayed after a crash without knowing whether it was
+    /// applied. See [`Nomt::last_commit_id`].
+    pub fn commit_id(mut self, id: u64) -> Self {
+        self.commit_id = Some(id);
+        self
+    }
+
+    /// Log the reads of the session which take longer than the given duration. Default: None
+    ///
+    /// See [`Session::slow_reads`].
+    pub fn slow_read_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_read_threshold = Some(threshold);
+        self
+    }
 }
 
 /// A session presents a way of interaction with the trie.
 ///
-/// The session enables the application to perform reads and prepare writes.
+/// The session enables the application to perform reads and prepare writes. For sessions which
+/// only ever read, see [`ReadSession`].
 ///
 /// When the session is finished, the application can confirm the changes by calling
 /// [`Session::finish`] or others and create a [`Witness`] that can be used to prove the
@@ -526,6 +1212,15 @@ pub struct Session<T: HashAlgorithm> {
     witness_mode: WitnessMode,
     access_guard: Option<ArcRwLockReadGuard<parking_lot::RawRwLock, ()>>,
     prev_root: Root,
+    // verifies values against the committed trie, which can't change while the session is live.
+    value_verifier: ValueVerifier,
+    verify_values: bool,
+    commit_id: Option<u64>,
+    // the keys changed with `update`.
+    updates: Mutex<BTreeMap<KeyPath, KeyReadWrite>>,
+    read_counters: SessionCounters,
+    // the hashes of the leaves appended to the Merkle mountain range.
+    mmr_leaves: Mutex<Vec<Node>>,
     _marker: std::marker::PhantomData<T>,
 }
 
@@ -547,13 +1242,169 @@ impl<T: HashAlgorithm> Session<T> {
 
     /// Synchronously read the value stored under the given key.
     ///
-    /// Returns `None` if the value is not stored under the given key. Fails only if I/O fails.
+    /// Returns `None` if the value is not stored under the given key. Fails only if I/O fails,
+    /// or with a [`ValueCorruption`] if [`Options::verify_values`] is set.
     pub fn read(&self, path: KeyPath) -> anyhow::Result<Option<Value>> {
-        let _maybe_guard = self.metrics.record(Metric::ValueFetchTime);
-        if let Some(value_change) = self.overlay.value(&path) {
-            return Ok(value_change.as_option().map(|v| v.to_vec()));
+        self.read_inner(path, self.verify_values)
+    }
+
+    /// Synchronously read the value stored under the given key, verifying it against the trie
+    /// regardless of [`Options::verify_values`].
+    ///
+    /// Fails with a [`ValueCorruption`] if the value store returns a value which doesn't match
+    /// the trie.
+    pub fn read_verified(&self, path: KeyPath) -> anyhow::Result<Option<Value>> {
+        self.read_inner(path, true)
+    }
+
+    fn read_inner(&self, path: KeyPath, verify: bool) -> anyhow::Result<Option<Value>> {
+        let start = Instant::now();
+        let (value, accesses) = read_value(&self.store, &self.overlay, &self.metrics, path)?;
+        if verify {
+            verify_values::<T>(&self.value_verifier, &self.overlay, &[path], &[&value])?;
         }
-        self.store.load_value(path)
+        self.read_counters
+            .record(&[path], accesses, start.elapsed());
+        Ok(value)
+    }
+
+    /// Atomically update the value stored under the given key.
+    ///
+    /// The closure is given the latest value of the key: the value left by an earlier update of
+    /// the key within this session, or else the stored value. The value it returns, or `None` to
+    /// delete the key, is included in the changes when the session is finished, so keys updated
+    /// here must not be passed to [`Session::finish`]. Concurrent updates are applied one after
+    /// another, so none of them is lost. Fails only if I/O fails.
+    pub fn update(
+        &self,
+        path: KeyPath,
+        f: impl FnOnce(Option<Value>) -> Option<Value>,
+    ) -> anyhow::Result<()> {
+        let mut updates = self.updates.lock();
+        let read_write = match updates.entry(path) {
+            Entry::Occupied(o) => o.into_mut(),
+            Entry::Vacant(v) => {
+                self.warm_up(path);
+                v.insert(KeyReadWrite::Read(self.read(path)?))
+            }
+        };
+        let new_value = f(read_write.last_value().map(|v| v.to_vec()));
+        read_write.write(new_value);
+        Ok(())
+    }
+
+    /// Create a speculative sub-session, which reads the state of this session and records its
+    /// own reads and writes. Sub-sessions may be used concurrently and are combined with
+    /// [`Session::merge`].
+    pub fn sub_session(&self) -> SubSession<'_, T> {
+        SubSession::new(self)
+    }
+
+    /// Combine sub-sessions into the actual reads and writes to finish this session with, as if
+    /// the sub-sessions were executed one after another in the given order.
+    ///
+    /// Fails with all [`MergeConflicts`] if a sub-session read or wrote a key written by an
+    /// earlier one. Keys accessed in sub-sessions must not be updated with [`Session::update`].
+    pub fn merge<'a>(
+        &'a self,
+        sub_sessions: impl IntoIterator<Item = SubSession<'a, T>>,
+    ) -> Result<Vec<(KeyPath, KeyReadWrite)>, MergeConflicts> {
+        sub_session::merge(sub_sessions)
+    }
+
+    /// Delete every key starting with the given prefix, e.g. all the storage of a contract.
+    ///
+    /// The deletions are included in the changes when the session is finished, like those made
+    /// with [`Session::update`], and the pages of the subtree holding the keys are deleted along
+    /// with it. Keys deleted here must not be passed to [`Session::finish`]. Returns the number of
+    /// deleted keys. Fails only if I/O fails.
+    pub fn delete_prefix(&self, prefix: &BitSlice<u8, Msb0>) -> anyhow::Result<usize> {
+        if prefix.len() > 256 {
+            anyhow::bail!("prefix of {} bits is longer than a key", prefix.len());
+        }
+        let mut raw_path = KeyPath::default();
+        raw_path.view_bits_mut::<Msb0>()[..prefix.len()].copy_from_bitslice(prefix);
+        let (start, end) = merkle::range_bounds(raw_path, prefix.len());
+        let in_range = |key: &KeyPath| end.is_none_or(|end| *key < end);
+
+        let mut updates = self.updates.lock();
+
+        // Whether each key in the range exists, as of the overlay and the updates so far.
+        let mut exists: BTreeMap<KeyPath, bool> = BTreeMap::new();
+        dump::scan_keys(&self.store, &self.store.read_transaction(), start, |key| {
+            if in_range(&key) {
+                exists.insert(key, true);
+            }
+            in_range(&key)
+        })?;
+        for (key, change) in self.overlay.value_iter(start, end) {
+            exists.insert(key, change.as_option().is_some());
+        }
+        for (key, read_write) in updates.range(start..).take_while(|(k, _)| in_range(k)) {
+            exists.insert(*key, read_write.last_value().is_some());
+        }
+
+        let mut deleted = 0;
+        for key in exists.into_iter().filter(|(_, e)| *e).map(|(k, _)| k) {
+            self.warm_up(key);
+            match updates.entry(key) {
+                Entry::Occupied(mut o) => o.get_mut().write(None),
+                Entry::Vacant(v) => {
+                    v.insert(KeyReadWrite::Write(None));
+                }
+            }
+            deleted += 1;
+        }
+        Ok(deleted)
+    }
+
+    /// Synchronously read the values stored under many keys at once.
+    ///
+    /// This is faster than reading the keys one by one, as the loads are issued concurrently and
+    /// keys stored close to each other share loads. The values are returned in the order of the
+    /// given keys. Fails only if I/O fails, or with a [`ValueCorruption`] if
+    /// [`Options::verify_values`] is set.
+    pub fn read_many(&self, paths: &[KeyPath]) -> anyhow::Result<Vec<Option<Value>>> {
+        let start = Instant::now();
+        let (values, accesses) = read_values(&self.store, &self.overlay, &self.metrics, paths)?;
+        if self.verify_values {
+            let values: Vec<_> = values.iter().collect();
+            verify_values::<T>(&self.value_verifier, &self.overlay, paths, &values)?;
+        }
+        self.read_counters.record(paths, accesses, start.elapsed());
+        Ok(values)
+    }
+
+    /// Open a reader over the value stored under the given key.
+    ///
+    /// Unlike [`Session::read`], large values are not loaded into memory at once but read from
+    /// disk as the reader is consumed. Returns `None` if the value is not stored under the given
+    /// key.
+    pub fn read_stream(&self, path: KeyPath) -> anyhow::Result<Option<ValueReader>> {
+        let start = Instant::now();
+        let reader = read_value_stream(&self.store, &self.overlay, &self.metrics, path)?;
+        self.read_counters
+            .record(&[path], LeafAccesses::default(), start.elapsed());
+        Ok(reader)
+    }
+
+    /// Statistics about the reads made through this session so far, including those made by
+    /// [`Session::update`].
+    pub fn stats(&self) -> SessionStats {
+        self.read_counters.stats()
+    }
+
+    /// The reads made through this session so far which took longer than the threshold set with
+    /// [`SessionParams::slow_read_threshold`], in the order they finished. Holds up to
+    /// 1024 reads, further slow reads are only counted in [`SessionStats::slow_reads`].
+    pub fn slow_reads(&self) -> Vec<SlowRead> {
+        self.read_counters.slow_reads()
+    }
+
+    /// Append a leaf to the Merkle mountain range kept alongside the trie. Leaves are appended in
+    /// the order of the calls when the session is committed. See [`Nomt::mmr_root`].
+    pub fn append_mmr_leaf(&self, data: &[u8]) {
+        self.mmr_leaves.lock().push(mmr::hash_leaf::<T>(data));
     }
 
     /// Signals that the given key is going to be written to. Relevant only if rollback is enabled.
@@ -603,6 +1454,22 @@ impl<T: HashAlgorithm> Session<T> {
                 );
             }
         }
+        let actuals = merge_updates(actuals, mem::take(self.updates.get_mut()))?;
+
+        for (_, read_write) in &actuals {
+            let written = match read_write {
+                KeyReadWrite::Write(value) | KeyReadWrite::ReadThenWrite(_, value) => value,
+                KeyReadWrite::Read(_) => continue,
+            };
+            if let Some(value) = written.as_ref().filter(|v| v.len() > MAX_VALUE_SIZE) {
+                anyhow::bail!(
+                    "value of {} bytes exceeds the maximum of {} bytes",
+                    value.len(),
+                    MAX_VALUE_SIZE,
+                );
+            }
+        }
+
         let rollback_delta = self
             .rollback_delta
             .take()
@@ -631,9 +1498,305 @@ impl<T: HashAlgorithm> Session<T> {
             rollback_delta,
             parent_overlay: self.overlay,
             prev_root: self.prev_root,
+            commit_id: self.commit_id,
+            mmr_leaves: self.mmr_leaves.into_inner(),
             take_global_guard: self.access_guard.is_some(),
         })
     }
+
+    /// Finish the session with the accesses recorded in the batch. See [`Session::finish`].
+    pub fn finish_batch(self, batch: WriteBatch) -> anyhow::Result<FinishedSession> {
+        self.finish(batch.into_actuals())
+    }
+
+    /// Evaluate the session without committing it. Takes the same arguments as
+    /// [`Session::finish`].
+    ///
+    /// This computes the root the session would produce and the size of the changes which would
+    /// be written, and then discards them, e.g. so block proposers can compare candidate sets of
+    /// transactions. Nothing is persisted and the database is unaffected.
+    pub fn dry_run(self, actuals: Vec<(KeyPath, KeyReadWrite)>) -> anyhow::Result<DryRun> {
+        let finished = self.finish(actuals)?;
+        let mut dry_run = DryRun {
+            root: finished.root(),
+            pages: finished.merkle_output.updated_pages.iter().count(),
+            value_writes: 0,
+            value_deletes: 0,
+            value_bytes: 0,
+            stats: finished.stats(),
+        };
+        for (_, change) in finished.value_transaction.iter() {
+            match change {
+                beatree::ValueChange::Delete => dry_run.value_deletes += 1,
+                beatree::ValueChange::Insert(value)
+                | beatree::ValueChange::InsertOverflow(value, _) => {
+                    dry_run.value_writes += 1;
+                    dry_run.value_bytes += value.len() as u64;
+                }
+            }
+        }
+        Ok(dry_run)
+    }
+}
+
+/// The outcome of a session which was evaluated without being committed. See
+/// [`Session::dry_run`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DryRun {
+    /// The root the session would produce.
+    pub root: Root,
+    /// The number of pages of the trie which would be written.
+    pub pages: usize,
+    /// The number of values which would be inserted or updated.
+    pub value_writes: usize,
+    /// The number of values which would be deleted.
+    pub value_deletes: usize,
+    /// The total size in bytes of the values which would be inserted or updated.
+    pub value_bytes: u64,
+    /// Statistics about the changes, as returned by [`FinishedSession::stats`].
+    pub stats: CommitStats,
+}
+
+impl DryRun {
+    /// An estimate of the bytes which committing the session would write: the pages of the trie
+    /// and the values. This excludes the b-tree nodes holding the values and the write-ahead logs.
+    pub fn estimated_bytes(&self) -> u64 {
+        self.pages as u64 * io::PAGE_SIZE as u64 + self.value_bytes
+    }
+}
+
+/// A read-only session.
+///
+/// Read sessions observe the state of the trie as of their creation and statically cannot be used
+/// to prepare writes. Create one with [`Nomt::begin_read_session`].
+pub struct ReadSession<T: HashAlgorithm> {
+    store: Store,
+    metrics: Metrics,
+    overlay: LiveOverlay,
+    root: Root,
+    value_verifier: ValueVerifier,
+    verify_values: bool,
+    _access_guard: ArcRwLockReadGuard<parking_lot::RawRwLock, ()>,
+    _marker: std::marker::PhantomData<T>,
+}
+
+impl<T: HashAlgorithm> ReadSession<T> {
+    /// The root of the trie observed by this session.
+    pub fn root(&self) -> Root {
+        self.root
+    }
+
+    /// Synchronously read the value stored under the given key.
+    ///
+    /// Returns `None` if the value is not stored under the given key. Fails only if I/O fails.
+    pub fn read(&self, path: KeyPath) -> anyhow::Result<Option<Value>> {
+        self.read_inner(path, self.verify_values)
+    }
+
+    /// Synchronously read the value stored under the given key, verifying it against the trie.
+    ///
+    /// See [`Session::read_verified`].
+    pub fn read_verified(&self, path: KeyPath) -> anyhow::Result<Option<Value>> {
+        self.read_inner(path, true)
+    }
+
+    fn read_inner(&self, path: KeyPath, verify: bool) -> anyhow::Result<Option<Value>> {
+        let value = read_value(&self.store, &self.overlay, &self.metrics, path)?.0;
+        if verify {
+            verify_values::<T>(&self.value_verifier, &self.overlay, &[path], &[&value])?;
+        }
+        Ok(value)
+    }
+
+    /// Synchronously read the values stored under many keys at once.
+    ///
+    /// See [`Session::read_many`].
+    pub fn read_many(&self, paths: &[KeyPath]) -> anyhow::Result<Vec<Option<Value>>> {
+        let values = read_values(&self.store, &self.overlay, &self.metrics, paths)?.0;
+        if self.verify_values {
+            let values_ref: Vec<_> = values.iter().collect();
+            verify_values::<T>(&self.value_verifier, &self.overlay, paths, &values_ref)?;
+        }
+        Ok(values)
+    }
+
+    /// Open a reader over the value stored under the given key.
+    ///
+    /// See [`Session::read_stream`].
+    pub fn read_stream(&self, path: KeyPath) -> anyhow::Result<Option<ValueReader>> {
+        read_value_stream(&self.store, &self.overlay, &self.metrics, path)
+    }
+
+    /// Iterate the entries with keys from `start` up to, but excluding, `end`, in ascending key
+    /// order. The range extends to the last key if `end` is `None`.
+    ///
+    /// The iteration can be interrupted and resumed later from [`KeyValueIter::cursor`].
+    pub fn iter_range(&self, start: KeyPath, end: Option<KeyPath>) -> KeyValueIter<'_> {
+        KeyValueIter::new_range(&self.store, self.root, start, end)
+    }
+
+    /// Iterate the entries with keys starting with the given prefix, in ascending key order.
+    ///
+    /// Fails if the prefix is longer than a key.
+    pub fn iter_prefix(&self, prefix: &BitSlice<u8, Msb0>) -> anyhow::Result<KeyValueIter<'_>> {
+        if prefix.len() > 256 {
+            anyhow::bail!("prefix of {} bits is longer than a key", prefix.len());
+        }
+        let mut raw_path = KeyPath::default();
+        raw_path.view_bits_mut::<Msb0>()[..prefix.len()].copy_from_bitslice(prefix);
+        let (start, end) = merkle::range_bounds(raw_path, prefix.len());
+        Ok(self.iter_range(start, end))
+    }
+
+    /// Resume an iteration from a cursor, which may have been created by another session or
+    /// process.
+    ///
+    /// Fails if the cursor was created at a root other than the root of this session, as the
+    /// entries may have changed since.
+    pub fn resume(&self, cursor: &Cursor) -> anyhow::Result<KeyValueIter<'_>> {
+        if cursor.root() != self.root {
+            anyhow::bail!(
+                "Cursor is at root {:?}, but the session is at root {:?}",
+                cursor.root(),
+                self.root
+            );
+        }
+        Ok(KeyValueIter::new(&self.store, *cursor))
+    }
+
+    /// Split the iteration over all entries into the given number of iterators over disjoint
+    /// ranges of keys, in ascending key order. Chaining the iterators visits every entry once.
+    ///
+    /// The ranges cover runs of key prefixes of about equal size, so with uniformly distributed
+    /// keys every shard holds about the same number of entries. Every iterator has its own read
+    /// transaction and I/O handle, so the shards can be iterated on different threads, and each
+    /// can be resumed from its own cursor.
+    ///
+    /// Fails if the number of shards is zero or more than [`MAX_ITER_SHARDS`].
+    pub fn iter_shards(&self, num_shards: usize) -> anyhow::Result<Vec<KeyValueIter<'_>>> {
+        if num_shards == 0 || num_shards > MAX_ITER_SHARDS {
+            anyhow::bail!(
+                "number of shards must be between 1 and {}, got {}",
+                MAX_ITER_SHARDS,
+                num_shards
+            );
+        }
+        Ok(cursor::shard_ranges(num_shards)
+            .into_iter()
+            .map(|(start, end)| self.iter_range(start, end))
+            .collect())
+    }
+}
+
+// Merge the keys changed with `Session::update` into the sorted actuals.
+fn merge_updates(
+    actuals: Vec<(KeyPath, KeyReadWrite)>,
+    updates: BTreeMap<KeyPath, KeyReadWrite>,
+) -> anyhow::Result<Vec<(KeyPath, KeyReadWrite)>> {
+    if updates.is_empty() {
+        return Ok(actuals);
+    }
+
+    let mut merged = Vec::with_capacity(actuals.len() + updates.len());
+    let mut updates = updates.into_iter().peekable();
+    for (path, read_write) in actuals {
+        while let Some(update) = updates.next_if(|(p, _)| *p < path) {
+            merged.push(update);
+        }
+        if updates.next_if(|(p, _)| *p == path).is_some() {
+            anyhow::bail!("actuals contain a key updated within the session");
+        }
+        merged.push((path, read_write));
+    }
+    merged.extend(updates);
+    Ok(merged)
+}
+
+fn read_values(
+    store: &Store,
+    overlay: &LiveOverlay,
+    metrics: &Metrics,
+    paths: &[KeyPath],
+) -> anyhow::Result<(Vec<Option<Value>>, LeafAccesses)> {
+    let _maybe_guard = metrics.record(Metric::ValueFetchTime);
+    let mut values = vec![None; paths.len()];
+    let mut to_load = Vec::new();
+    for (i, path) in paths.iter().enumerate() {
+        match overlay.value(path) {
+            Some(value_change) => values[i] = value_change.as_option().map(|v| v.to_vec()),
+            None => to_load.push(i),
+        }
+    }
+
+    let keys: Vec<KeyPath> = to_load.iter().map(|i| paths[*i]).collect();
+    let io_handle = store
+        .io_pool()
+        .make_handle()
+        .with_priority(io::IoPriority::Read);
+    let (loaded, accesses) = store.read_transaction().lookup_many(&keys, &io_handle)?;
+    for (i, value) in to_load.into_iter().zip(loaded) {
+        values[i] = value;
+    }
+    Ok((values, accesses))
+}
+
+fn read_value(
+    store: &Store,
+    overlay: &LiveOverlay,
+    metrics: &Metrics,
+    path: KeyPath,
+) -> anyhow::Result<(Option<Value>, LeafAccesses)> {
+    let _maybe_guard = metrics.record(Metric::ValueFetchTime);
+    if let Some(value_change) = overlay.value(&path) {
+        return Ok((
+            value_change.as_option().map(|v| v.to_vec()),
+            LeafAccesses::default(),
+        ));
+    }
+    store.load_value(path)
+}
+
+// Verify the values of the keys which were read from the value store rather than the overlay.
+fn verify_values<T: HashAlgorithm>(
+    verifier: &ValueVerifier,
+    overlay: &LiveOverlay,
+    paths: &[KeyPath],
+    values: &[&Option<Value>],
+) -> anyhow::Result<()> {
+    for (path, value) in paths.iter().zip(values) {
+        if let Some(value) = value {
+            if overlay.value(path).is_none() {
+                verifier.verify::<T>(*path, value)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_value_stream(
+    store: &Store,
+    overlay: &LiveOverlay,
+    metrics: &Metrics,
+    path: KeyPath,
+) -> anyhow::Result<Option<ValueReader>> {
+    let _maybe_guard = metrics.record(Metric::ValueFetchTime);
+    if let Some(value_change) = overlay.value(&path) {
+        return Ok(value_change
+            .as_option()
+            .map(|v| ValueReader::from_value(v.to_vec())));
+    }
+    Ok(store.read_transaction().value_reader(path))
+}
+
+/// Statistics about the changes made by a session. See [`FinishedSession::stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommitStats {
+    /// The number of writes which left their key as it was: writes of the value the key already
+    /// had and deletions of keys which didn't exist. These don't touch the trie.
+    pub skipped_writes: usize,
+    /// The size in bytes of the session's witness, when encoded as a compact multi-proof. See
+    /// [`nomt_core::proof::compact`]. `None` if the session didn't collect a witness.
+    pub witness_size: Option<usize>,
 }
 
 /// A finished session.
@@ -649,6 +1812,8 @@ pub struct FinishedSession {
     rollback_delta: Option<rollback::Delta>,
     parent_overlay: LiveOverlay,
     prev_root: Root,
+    commit_id: Option<u64>,
+    mmr_leaves: Vec<Node>,
     // INTERNAL: whether to take a write guard while committing. always true except during rollback.
     take_global_guard: bool,
 }
@@ -659,6 +1824,29 @@ impl FinishedSession {
         Root(self.merkle_output.root)
     }
 
+    /// The pages of the trie changed by this session, which will be written when the session is
+    /// committed.
+    ///
+    /// This allows embedders to replicate or persist the changes by their own means, e.g. by
+    /// shipping the changed nodes of every page along with its [`PageDiff`].
+    pub fn page_changes(&self) -> Vec<PageChange<'_>> {
+        self.merkle_output
+            .updated_pages
+            .iter()
+            .map(|updated| {
+                PageChange::new(&updated.page_id, updated.page.page_data(), &updated.diff)
+            })
+            .collect()
+    }
+
+    /// Statistics about the changes made by this session.
+    pub fn stats(&self) -> CommitStats {
+        CommitStats {
+            skipped_writes: self.merkle_output.skipped_writes,
+            witness_size: self.merkle_output.witness_size,
+        }
+    }
+
     /// Take the witness, if any.
     ///
     /// If this session was configured with proving  (see [`SessionParams::witness_mode`]),
@@ -683,6 +1871,7 @@ impl FinishedSession {
             updated_pages,
             values,
             self.rollback_delta,
+            self.mmr_leaves,
         )
     }
 
@@ -690,12 +1879,63 @@ impl FinishedSession {
     ///
     /// This function will block until all ongoing sessions and commits have finished.
     ///
+    /// Returns the sequence number of the commit. See [`Nomt::current_sequence`].
+    ///
     /// This will return an error if I/O fails or if the changeset is no longer valid.
     /// The changeset may be invalidated if another competing session, overlay, or rollback was
     /// committed.
-    pub fn commit<T: HashAlgorithm>(self, nomt: &Nomt<T>) -> Result<(), anyhow::Error> {
+    ///
+    /// If the session has the commit identifier of the last commit, nothing is written and the
+    /// sequence number of the last commit is returned. See [`SessionParams::commit_id`].
+    pub fn commit<T: HashAlgorithm>(self, nomt: &Nomt<T>) -> Result<u64, anyhow::Error> {
+        if nomt.is_read_only() {
+            anyhow::bail!("Database is opened read-only");
+        }
+        let mut log = CommitLog::start(&nomt.commit_log_thresholds);
+
         let _write_guard = self.take_global_guard.then(|| nomt.access_lock.write());
 
+        if let Some(id) = self.commit_id {
+            if let Some((last_id, last_parent)) = nomt.store.last_commit() {
+                if id == last_id {
+                    // either a duplicate of the last commit or a replay of it on top of its result.
+                    let duplicate =
+                        self.prev_root.into_inner() == last_parent && self.root() == nomt.root();
+                    if !duplicate && self.prev_root != nomt.root() {
+                        anyhow::bail!("Commit {} was already applied with different changes", id);
+                    }
+                    return Ok(nomt.current_sequence());
+                }
+            }
+        }
+        let last_commit = self.commit_id.map(|id| (id, self.prev_root.into_inner()));
+
+        let root = Root(self.merkle_output.root);
+        let values: Vec<_> = self.value_transaction.into_iter().collect();
+        let pages: Vec<_> = self
+            .merkle_output
+            .updated_pages
+            .into_frozen_iter(/* into_overlay */ false)
+            .collect();
+        nomt.commit_limits.check(&pages, &values)?;
+        if nomt.verify_commits {
+            commit_verify::verify::<T>(root, &pages, |page_id| {
+                commit_verify::load_top_nodes(&nomt.page_cache, &nomt.store, page_id)
+            })?;
+            commit_verify::verify_fresh(&pages, |page_id| {
+                let top_nodes =
+                    commit_verify::load_top_nodes(&nomt.page_cache, &nomt.store, page_id)?;
+                Ok(top_nodes.is_some())
+            })?;
+        }
+
+        let signature = nomt
+            .commit_signer
+            .as_ref()
+            .map(|signer| commit_signer::sign(&**signer, root))
+            .transpose()?;
+        let mmr_append = nomt.store.mmr_append::<T>(&self.mmr_leaves);
+
         {
             let mut shared = nomt.shared.lock();
             if shared.root != self.prev_root {
@@ -705,23 +1945,81 @@ impl FinishedSession {
                     shared.root
                 );
             }
-            shared.root = Root(self.merkle_output.root);
+            if let Some(ref sink) = nomt.commit_sink {
+                commit_sink::write(
+                    &**sink,
+                    self.prev_root,
+                    root,
+                    &pages,
+                    &values,
+                    signature.as_deref(),
+                )?;
+            }
+            shared.root = root;
             shared.last_commit_marker = None;
         }
+        log.changes(&pages, &values);
+        log.end(Phase::Prepare);
 
         if let Some(rollback_delta) = self.rollback_delta {
             // UNWRAP: if rollback_delta is `Some`, then rollback must be also `Some`.
             let rollback = nomt.store.rollback().unwrap();
-            rollback.commit(rollback_delta)?;
+            rollback.commit(self.prev_root.into_inner(), rollback_delta)?;
         }
 
-        nomt.store.commit(
-            self.value_transaction.into_iter(),
-            nomt.page_cache.clone(),
-            self.merkle_output
-                .updated_pages
-                .into_frozen_iter(/* into_overlay */ false),
-        )
+        let backup_values = nomt.backup.as_ref().map(|_| values.clone());
+        let next_sequence = nomt.current_sequence() + 1;
+        let touched = nomt
+            .expiry
+            .as_ref()
+            .map(|expiry| expiry.append(next_sequence, &values))
+            .transpose()?;
+        let usage_changes = nomt
+            .state_usage
+            .as_ref()
+            .map(|state_usage| state_usage.append(&nomt.store, next_sequence, &values))
+            .transpose()?;
+        let recorded_root = nomt
+            .root_index
+            .as_ref()
+            .map(|root_index| {
+                root_index.append(
+                    next_sequence,
+                    self.prev_root,
+                    root,
+                    last_commit.map(|(id, _)| id),
+                )
+            })
+            .transpose()?;
+
+        let record = CommitRecord {
+            root: root.into_inner(),
+            last_commit,
+            signature,
+            mmr_append,
+        };
+        log.end(Phase::Journal);
+        let sequence = nomt
+            .store
+            .commit(record, values, nomt.page_cache.clone(), pages)?;
+        log.end(Phase::Sync);
+
+        if let (Some(expiry), Some(touched)) = (&nomt.expiry, touched) {
+            expiry.apply(touched)?;
+        }
+        if let (Some(state_usage), Some(changes)) = (&nomt.state_usage, usage_changes) {
+            state_usage.apply(changes)?;
+        }
+        if let (Some(root_index), Some(recorded)) = (&nomt.root_index, recorded_root) {
+            root_index.apply(recorded);
+        }
+
+        if let (Some(backup), Some(values)) = (&nomt.backup, backup_values) {
+            backup.append(self.prev_root, root, &values)?;
+        }
+        log.end(Phase::Finish);
+        log.finish(sequence as u64);
+        Ok(sequence as u64)
     }
 }
 
@@ -730,10 +2028,16 @@ impl Overlay {
     ///
     /// This function will block until all ongoing sessions and commits have finished.
     ///
+    /// Returns the sequence number of the commit. See [`Nomt::current_sequence`].
+    ///
     /// This will return an error if I/O fails or if the changeset is no longer valid, or if the
     /// overlay has an uncommitted parent. An overlay may be invalidated by a competing commit or
     /// rollback.
-    pub fn commit<T: HashAlgorithm>(self, nomt: &Nomt<T>) -> anyhow::Result<()> {
+    pub fn commit<T: HashAlgorithm>(self, nomt: &Nomt<T>) -> anyhow::Result<u64> {
+        if nomt.is_read_only() {
+            anyhow::bail!("Database is opened read-only");
+        }
+        let mut log = CommitLog::start(&nomt.commit_log_thresholds);
         if !self.parent_matches_marker(nomt.shared.lock().last_commit_marker.as_ref()) {
             anyhow::bail!("Overlay parent not committed");
         }
@@ -749,12 +2053,30 @@ impl Overlay {
             .iter()
             .map(|(k, v)| (k.clone(), v.clone()))
             .collect();
+        nomt.commit_limits.check(&page_changes, &values)?;
+        if nomt.verify_commits {
+            commit_verify::verify::<T>(root, &page_changes, |page_id| {
+                commit_verify::load_top_nodes(&nomt.page_cache, &nomt.store, page_id)
+            })?;
+            commit_verify::verify_fresh(&page_changes, |page_id| {
+                let top_nodes =
+                    commit_verify::load_top_nodes(&nomt.page_cache, &nomt.store, page_id)?;
+                Ok(top_nodes.is_some())
+            })?;
+        }
         let rollback_delta = self.rollback_delta().map(|delta| delta.clone());
 
         let _write_guard = nomt.access_lock.write();
 
         let marker = self.mark_committed();
 
+        let signature = nomt
+            .commit_signer
+            .as_ref()
+            .map(|signer| commit_signer::sign(&**signer, root))
+            .transpose()?;
+        let mmr_append = nomt.store.mmr_append::<T>(self.mmr_leaves());
+
         {
             let mut shared = nomt.shared.lock();
             if shared.root != self.prev_root() {
@@ -764,18 +2086,75 @@ impl Overlay {
                     shared.root
                 );
             }
+            if let Some(ref sink) = nomt.commit_sink {
+                commit_sink::write(
+                    &**sink,
+                    self.prev_root(),
+                    root,
+                    &page_changes,
+                    &values,
+                    signature.as_deref(),
+                )?;
+            }
             shared.root = root;
             shared.last_commit_marker = Some(marker);
         }
+        log.changes(&page_changes, &values);
+        log.end(Phase::Prepare);
 
         if let Some(rollback_delta) = rollback_delta {
             // UNWRAP: if rollback_delta is `Some`, then rollback must be also `Some`.
             let rollback = nomt.store.rollback().unwrap();
-            rollback.commit(rollback_delta)?;
+            rollback.commit(self.prev_root().into_inner(), rollback_delta)?;
         }
 
-        nomt.store
-            .commit(values, nomt.page_cache.clone(), page_changes)
+        let backup_values = nomt.backup.as_ref().map(|_| values.clone());
+        let prev_root = self.prev_root();
+        let next_sequence = nomt.current_sequence() + 1;
+        let touched = nomt
+            .expiry
+            .as_ref()
+            .map(|expiry| expiry.append(next_sequence, &values))
+            .transpose()?;
+        let usage_changes = nomt
+            .state_usage
+            .as_ref()
+            .map(|state_usage| state_usage.append(&nomt.store, next_sequence, &values))
+            .transpose()?;
+        let recorded_root = nomt
+            .root_index
+            .as_ref()
+            .map(|root_index| root_index.append(next_sequence, prev_root, root, None))
+            .transpose()?;
+
+        let record = CommitRecord {
+            root: root.into_inner(),
+            last_commit: None,
+            signature,
+            mmr_append,
+        };
+        log.end(Phase::Journal);
+        let sequence = nomt
+            .store
+            .commit(record, values, nomt.page_cache.clone(), page_changes)?;
+        log.end(Phase::Sync);
+
+        if let (Some(expiry), Some(touched)) = (&nomt.expiry, touched) {
+            expiry.apply(touched)?;
+        }
+        if let (Some(state_usage), Some(changes)) = (&nomt.state_usage, usage_changes) {
+            state_usage.apply(changes)?;
+        }
+        if let (Some(root_index), Some(recorded)) = (&nomt.root_index, recorded_root) {
+            root_index.apply(recorded);
+        }
+
+        if let (Some(backup), Some(values)) = (&nomt.backup, backup_values) {
+            backup.append(prev_root, root, &values)?;
+        }
+        log.end(Phase::Finish);
+        log.finish(sequence as u64);
+        Ok(sequence as u64)
     }
 }
 
@@ -788,6 +2167,29 @@ pub trait HashAlgorithm: ValueHasher + NodeHasher {}
 
 impl<T: ValueHasher + NodeHasher> HashAlgorithm for T {}
 
+/// Check the root recorded in the metadata against the top of the root page, if the root is an
+/// internal node.
+fn check_root_node<H: HashAlgorithm>(page_cache: &PageCache, root: Node) -> anyhow::Result<()> {
+    let Some((root_page, _)) = page_cache.get(ROOT_PAGE_ID) else {
+        return Ok(());
+    };
+    let left = root_page.node(0);
+    let right = root_page.node(1);
+    if left == TERMINATOR && right == TERMINATOR {
+        return Ok(());
+    }
+
+    let page_root = H::hash_internal(&InternalData { left, right });
+    if page_root != root {
+        anyhow::bail!(
+            "root page does not match the committed root (expected {:?}, got {:?})",
+            Root(root),
+            Root(page_root)
+        );
+    }
+    Ok(())
+}
+
 fn compute_root_node<H: HashAlgorithm>(page_cache: &PageCache, store: &Store) -> Node {
     // 3 cases.
     // 1: root page is empty and beatree is empty. in this case, root is the TERMINATOR.
@@ -859,5 +2261,6 @@ mod tests {
         fn is_sync<T: Sync>() {}
 
         is_sync::<crate::Session<Blake3Hasher>>();
+        is_sync::<crate::ReadSession<Blake3Hasher>>();
     }
 }
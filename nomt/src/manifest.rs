@@ -0,0 +1,261 @@
+//! Manifests of exported files, for verifying them chunk by chunk.
+//!
+//! [`crate::Nomt::export`] and [`crate::Nomt::export_snapshot`] write a manifest next to the
+//! exported file, at the path given by [`manifest_path`]. The manifest splits the file into chunks
+//! of a fixed size and holds a hash for every chunk. The hashes are chained: the hash of a chunk
+//! covers the hash of the previous chunk, and the hash before the first chunk covers the root of
+//! the exported state, the chunk size and the length of the file. The last hash, the
+//! [`Manifest::head`], thus commits to the root and to the whole file.
+//!
+//! A receiver that obtained the manifest from a trusted source, or checked its head against a
+//! trusted value, can verify every chunk as soon as it's downloaded with
+//! [`Manifest::verify_chunk`], instead of only after restoring the full state.
+//!
+//! A manifest is laid out as:
+//!   - `MAGIC` (8 bytes)
+//!   - format version (1 byte)
+//!   - root (32 bytes)
+//!   - chunk size (4 bytes, little-endian)
+//!   - file length (8 bytes, little-endian)
+//!   - the hash of every chunk (32 bytes each), in order
+//!
+//! Hashes are BLAKE3.
+
+use std::{
+    fs::File,
+    io::{BufReader, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::Root;
+
+/// The magic bytes at the beginning of every manifest.
+pub const MAGIC: [u8; 8] = *b"NOMTMNFT";
+
+/// The chunk size of the manifests written by exports.
+pub const DEFAULT_CHUNK_SIZE: u32 = 1 << 20;
+
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 8 + 1 + 32 + 4 + 8;
+
+/// The path of the manifest written for a file exported to `path`: the same path with
+/// `.manifest` appended.
+pub fn manifest_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut path = path.as_ref().as_os_str().to_owned();
+    path.push(".manifest");
+    PathBuf::from(path)
+}
+
+/// The chunk hashes of an exported file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    root: Root,
+    chunk_size: u32,
+    file_len: u64,
+    chunk_hashes: Vec<[u8; 32]>,
+}
+
+impl Manifest {
+    /// Build the manifest of the file at `path`, exported from the state with the given root.
+    ///
+    /// Fails if the chunk size is zero.
+    pub fn build(path: impl AsRef<Path>, root: Root, chunk_size: u32) -> anyhow::Result<Self> {
+        if chunk_size == 0 {
+            anyhow::bail!("manifest chunk size must not be zero");
+        }
+        let mut file = BufReader::new(File::open(path)?);
+        let file_len = file.get_ref().metadata()?.len();
+
+        let mut manifest = Manifest {
+            root,
+            chunk_size,
+            file_len,
+            chunk_hashes: Vec::new(),
+        };
+        let mut chunk = vec![0; chunk_size as usize];
+        for index in 0..manifest.chunk_count() {
+            let chunk = &mut chunk[..manifest.chunk_len(index)];
+            file.read_exact(chunk)?;
+            let hash = manifest.chain(index, chunk);
+            manifest.chunk_hashes.push(hash);
+        }
+        Ok(manifest)
+    }
+
+    /// The root of the exported state.
+    pub fn root(&self) -> Root {
+        self.root
+    }
+
+    /// The size of every chunk but the last, which may be shorter.
+    pub fn chunk_size(&self) -> u32 {
+        self.chunk_size
+    }
+
+    /// The length of the exported file.
+    pub fn file_len(&self) -> u64 {
+        self.file_len
+    }
+
+    /// The number of chunks of the exported file.
+    pub fn chunk_count(&self) -> usize {
+        self.file_len.div_ceil(self.chunk_size as u64) as usize
+    }
+
+    /// The length of the chunk with the given index, which must be less than the number of
+    /// chunks.
+    pub fn chunk_len(&self, index: usize) -> usize {
+        let start = index as u64 * self.chunk_size as u64;
+        (self.file_len - start).min(self.chunk_size as u64) as usize
+    }
+
+    /// The hash committing to the root, the chunk size, the file length and every chunk.
+    pub fn head(&self) -> [u8; 32] {
+        self.chunk_hashes
+            .last()
+            .copied()
+            .unwrap_or_else(|| self.seed())
+    }
+
+    /// Verify the chunk with the given index, which starts at `index * chunk_size` in the file.
+    ///
+    /// Chunks can be verified in any order. Returns `false` if the index is out of range or the
+    /// chunk doesn't match.
+    pub fn verify_chunk(&self, index: usize, chunk: &[u8]) -> bool {
+        index < self.chunk_count()
+            && chunk.len() == self.chunk_len(index)
+            && self.chain(index, chunk) == self.chunk_hashes[index]
+    }
+
+    // The hash of the chunk with the given index, chained to the hash of the previous chunk.
+    fn chain(&self, index: usize, chunk: &[u8]) -> [u8; 32] {
+        let prev = match index {
+            0 => self.seed(),
+            _ => self.chunk_hashes[index - 1],
+        };
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&prev);
+        hasher.update(chunk);
+        *hasher.finalize().as_bytes()
+    }
+
+    fn seed(&self) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&MAGIC);
+        hasher.update(&self.root.into_inner());
+        hasher.update(&self.chunk_size.to_le_bytes());
+        hasher.update(&self.file_len.to_le_bytes());
+        *hasher.finalize().as_bytes()
+    }
+
+    /// Encode the manifest to bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_LEN + self.chunk_hashes.len() * 32);
+        buf.extend_from_slice(&MAGIC);
+        buf.push(VERSION);
+        buf.extend_from_slice(&self.root.into_inner());
+        buf.extend_from_slice(&self.chunk_size.to_le_bytes());
+        buf.extend_from_slice(&self.file_len.to_le_bytes());
+        for hash in &self.chunk_hashes {
+            buf.extend_from_slice(hash);
+        }
+        buf
+    }
+
+    /// Decode a manifest encoded with [`Manifest::encode`].
+    pub fn decode(buf: &[u8]) -> anyhow::Result<Self> {
+        if buf.len() < HEADER_LEN || buf[..8] != MAGIC {
+            anyhow::bail!("not a NOMT manifest");
+        }
+        if buf[8] != VERSION {
+            anyhow::bail!("unsupported manifest version {}", buf[8]);
+        }
+        // UNWRAPs: the slices have the right lengths.
+        let root = Root::from(<[u8; 32]>::try_from(&buf[9..41]).unwrap());
+        let chunk_size = u32::from_le_bytes(buf[41..45].try_into().unwrap());
+        let file_len = u64::from_le_bytes(buf[45..53].try_into().unwrap());
+        if chunk_size == 0 {
+            anyhow::bail!("manifest chunk size is zero");
+        }
+
+        let hashes = &buf[HEADER_LEN..];
+        let mut manifest = Manifest {
+            root,
+            chunk_size,
+            file_len,
+            chunk_hashes: Vec::new(),
+        };
+        if hashes.len() as u64 != manifest.chunk_count() as u64 * 32 {
+            anyhow::bail!(
+                "manifest has {} bytes of hashes, but {} chunks",
+                hashes.len(),
+                manifest.chunk_count()
+            );
+        }
+        manifest.chunk_hashes = hashes
+            .chunks_exact(32)
+            // UNWRAP: the chunks are 32 bytes long.
+            .map(|hash| hash.try_into().unwrap())
+            .collect();
+        Ok(manifest)
+    }
+
+    /// Read the manifest at the given path.
+    pub fn read(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::decode(&std::fs::read(path)?)
+    }
+
+    /// Write the manifest to the given path.
+    pub fn write(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(&self.encode())?;
+        file.sync_all()?;
+        Ok(())
+    }
+}
+
+/// Build the manifest of a file exported to `path` and write it to [`manifest_path`].
+pub(crate) fn emit(path: &Path, root: Root) -> anyhow::Result<()> {
+    Manifest::build(path, root, DEFAULT_CHUNK_SIZE)?.write(manifest_path(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{manifest_path, Manifest};
+    use crate::Root;
+
+    #[test]
+    fn chunks_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export");
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 7) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let manifest = Manifest::build(&path, Root::from([1; 32]), 64).unwrap();
+        assert_eq!(manifest.chunk_count(), 16);
+        assert_eq!(manifest.chunk_len(15), 1000 - 15 * 64);
+        for (index, chunk) in data.chunks(64).enumerate().rev() {
+            assert!(manifest.verify_chunk(index, chunk));
+        }
+        assert!(!manifest.verify_chunk(1, &data[..64]));
+        assert!(!manifest.verify_chunk(16, &[]));
+        let mut corrupt = data[64..128].to_vec();
+        corrupt[3] ^= 1;
+        assert!(!manifest.verify_chunk(1, &corrupt));
+
+        manifest.write(manifest_path(&path)).unwrap();
+        assert_eq!(Manifest::read(manifest_path(&path)).unwrap(), manifest);
+
+        // the chain commits to the root: the hashes don't verify under another root.
+        let mut encoded = manifest.encode();
+        encoded[9] ^= 1;
+        let forged = Manifest::decode(&encoded).unwrap();
+        assert!(!forged.verify_chunk(0, &data[..64]));
+        let other = Manifest::build(&path, forged.root(), 64).unwrap();
+        assert_ne!(other.head(), manifest.head());
+
+        let encoded = manifest.encode();
+        assert!(Manifest::decode(&encoded[..encoded.len() - 1]).is_err());
+        assert!(Manifest::build(&path, Root::from([1; 32]), 0).is_err());
+    }
+}
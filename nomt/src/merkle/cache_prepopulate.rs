@@ -8,7 +8,7 @@ use crate::{
     store::{PageLoad, PageLoader, Store},
 };
 
-use nomt_core::page_id::{ChildPageIndex, PageId, MAX_PAGE_DEPTH, NUM_CHILDREN, ROOT_PAGE_ID};
+use nomt_core::page_id::{ChildPageIndex, PageId, MAX_PAGE_DEPTH, ROOT_PAGE_ID};
 
 /// Prepopulate the given number of levels of the page tree into the page cache.
 ///
@@ -38,7 +38,7 @@ pub fn prepopulate(
         let load = &mut loads[load_index];
 
         // UNWRAP: all submitted requests are of kind Read(FatPage).
-        if let Some((page, bucket)) = load.try_complete(complete_io.command.kind.unwrap_buf()) {
+        if let Some((page, bucket)) = load.try_complete(complete_io.command.kind.unwrap_buf())? {
             completed += 1;
             page_cache.insert(
                 load.page_id().clone(),
@@ -69,10 +69,7 @@ fn dispatch_recursive(
         return Ok(());
     }
 
-    for child_index in 0..NUM_CHILDREN {
-        // UNWRAP: all indices up to NUM_CHILDREN are allowed.
-        let child_index = ChildPageIndex::new(child_index as u8).unwrap();
-
+    for child_index in ChildPageIndex::all() {
         // UNWRAP: depth is not out of bounds and child index is valid.
         let child_page_id = page_id.child_page_id(child_index).unwrap();
 
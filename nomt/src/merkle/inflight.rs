@@ -0,0 +1,211 @@
+//! Deduplication of speculative page loads across sessions.
+//!
+//! Every seeker speculatively loads the pages below the pages it has to read from disk. Sessions
+//! warming up overlapping keys at the same time would otherwise load the same pages twice. The
+//! [`InflightPages`] registry is shared by all seekers and records the pages being loaded
+//! speculatively. A seeker about to load a page which is already in flight takes a reference to it
+//! instead and finds the page in the page cache once the load completes.
+//!
+//! Each seeker accounts for the loads it depends on with its own [`FetchTicket`]. The seeker
+//! which issued a load only drops it when no other seeker holds a reference, so cancelling its
+//! own prefetches never takes a page away from another session. References are released when the
+//! load completes, when the seeker no longer needs the page and when the ticket is dropped.
+
+use nomt_core::page_id::PageId;
+use parking_lot::Mutex;
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+/// The registry of speculative page loads in flight, shared by all seekers.
+#[derive(Clone, Default)]
+pub struct InflightPages {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<PageId, Entry>,
+    next_generation: u64,
+}
+
+struct Entry {
+    // distinguishes successive loads of the same page.
+    generation: u64,
+    // the number of tickets holding the entry, including the one which issued the load.
+    refs: usize,
+}
+
+impl InflightPages {
+    /// Create a ticket for a seeker.
+    pub fn ticket(&self) -> FetchTicket {
+        FetchTicket {
+            inflight: self.clone(),
+            owned: HashSet::new(),
+            shared: HashMap::new(),
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.inner.lock().entries.len()
+    }
+}
+
+/// The speculative loads a single seeker issued or depends on.
+pub struct FetchTicket {
+    inflight: InflightPages,
+    // pages loaded by this seeker.
+    owned: HashSet<PageId>,
+    // pages loaded by other seekers, with the generation of the load.
+    shared: HashMap<PageId, u64>,
+}
+
+impl FetchTicket {
+    /// Register interest in a speculative load of the page. Returns `true` if the caller should
+    /// load the page, or `false` if another seeker is already loading it.
+    ///
+    /// A caller which gets `true` must eventually call [`Self::finish`] for the page.
+    pub fn acquire(&mut self, page_id: &PageId) -> bool {
+        if self.owned.contains(page_id) || self.shared.contains_key(page_id) {
+            return false;
+        }
+
+        let mut inner = self.inflight.inner.lock();
+        let generation = inner.next_generation;
+        match inner.entries.get_mut(page_id) {
+            Some(entry) => {
+                entry.refs += 1;
+                self.shared.insert(page_id.clone(), entry.generation);
+                false
+            }
+            None => {
+                inner.next_generation += 1;
+                inner.entries.insert(
+                    page_id.clone(),
+                    Entry {
+                        generation,
+                        refs: 1,
+                    },
+                );
+                self.owned.insert(page_id.clone());
+                true
+            }
+        }
+    }
+
+    /// Whether a load issued by this seeker is needed by other seekers as well.
+    pub fn is_shared(&self, page_id: &PageId) -> bool {
+        self.owned.contains(page_id)
+            && self
+                .inflight
+                .inner
+                .lock()
+                .entries
+                .get(page_id)
+                .is_some_and(|entry| entry.refs > 1)
+    }
+
+    /// Conclude a load issued by this seeker, whether it completed, found nothing or was
+    /// cancelled. References held by other seekers are dropped along with it.
+    pub fn finish(&mut self, page_id: &PageId) {
+        if self.owned.remove(page_id) {
+            self.inflight.inner.lock().entries.remove(page_id);
+        }
+    }
+
+    /// Release the references to loads of `page_id` and its descendants issued by other seekers.
+    pub fn release_subtree(&mut self, page_id: &PageId) {
+        let released: Vec<_> = self
+            .shared
+            .keys()
+            .filter(|shared| shared.is_descendant_of(page_id))
+            .cloned()
+            .collect();
+        if released.is_empty() {
+            return;
+        }
+
+        let mut inner = self.inflight.inner.lock();
+        for page_id in released {
+            // UNWRAP: the key was just found.
+            let generation = self.shared.remove(&page_id).unwrap();
+            release(&mut inner, &page_id, generation);
+        }
+    }
+}
+
+impl Drop for FetchTicket {
+    fn drop(&mut self) {
+        let mut inner = self.inflight.inner.lock();
+        for page_id in self.owned.drain() {
+            // the loads of a dropped seeker never complete.
+            inner.entries.remove(&page_id);
+        }
+        for (page_id, generation) in self.shared.drain() {
+            release(&mut inner, &page_id, generation);
+        }
+    }
+}
+
+fn release(inner: &mut Inner, page_id: &PageId, generation: u64) {
+    // the entry may already be gone, or belong to a later load of the same page.
+    if let Some(entry) = inner.entries.get_mut(page_id) {
+        if entry.generation == generation {
+            entry.refs -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InflightPages;
+    use nomt_core::page_id::{ChildPageIndex, ROOT_PAGE_ID};
+
+    fn child(index: u8) -> nomt_core::page_id::PageId {
+        ROOT_PAGE_ID
+            .child_page_id(ChildPageIndex::new(index).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn loads_are_deduplicated_and_refcounted() {
+        let inflight = InflightPages::default();
+        let mut a = inflight.ticket();
+        let mut b = inflight.ticket();
+
+        assert!(a.acquire(&child(1)));
+        assert!(!a.acquire(&child(1)));
+        assert!(!a.is_shared(&child(1)));
+
+        assert!(!b.acquire(&child(1)));
+        assert!(a.is_shared(&child(1)));
+        assert!(!b.is_shared(&child(1)));
+
+        // `b` no longer needs the page, so `a` may cancel its load.
+        b.release_subtree(&ROOT_PAGE_ID);
+        assert!(!a.is_shared(&child(1)));
+        a.finish(&child(1));
+        assert_eq!(inflight.len(), 0);
+
+        // a reference to an earlier load of the page doesn't count towards a later one.
+        assert!(a.acquire(&child(2)));
+        assert!(!b.acquire(&child(2)));
+        a.finish(&child(2));
+        assert!(a.acquire(&child(2)));
+        b.release_subtree(&child(2));
+        assert!(!a.is_shared(&child(2)));
+        assert!(b.acquire(&child(3)));
+        assert!(!a.acquire(&child(3)));
+        assert!(b.is_shared(&child(3)));
+
+        // dropping a ticket drops its loads and its references.
+        drop(a);
+        assert_eq!(inflight.len(), 1);
+        assert!(!b.is_shared(&child(3)));
+        let mut c = inflight.ticket();
+        assert!(c.acquire(&child(2)));
+    }
+}
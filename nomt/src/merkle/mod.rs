@@ -6,8 +6,10 @@ use crossbeam::channel::{self, Receiver, Sender};
 use page_set::FrozenSharedPageSet;
 use parking_lot::Mutex;
 
+use inflight::InflightPages;
 use nomt_core::{
     page_id::PageId,
+    proof::{compact, MultiProof},
     trie::{self, KeyPath, Node, ValueHash},
     trie_pos::TriePosition,
 };
@@ -17,16 +19,18 @@ use std::{collections::HashMap, sync::Arc};
 
 use crate::{
     io::PagePool,
+    node_hook::NodePreimageHook,
     overlay::LiveOverlay,
     page_cache::{Page, PageCache, ShardIndex},
     rw_pass_cell::WritePassEnvelope,
     store::{BucketIndex, DirtyPage, SharedMaybeBucketIndex, Store},
-    task::{join_task, spawn_task, TaskResult},
+    task::{join_fallible_task, spawn_task, TaskResult},
     HashAlgorithm, Witness, WitnessedOperations, WitnessedPath, WitnessedRead, WitnessedWrite,
 };
 use threadpool::ThreadPool;
 
 mod cache_prepopulate;
+mod inflight;
 mod page_set;
 mod page_walker;
 mod seek;
@@ -34,11 +38,17 @@ mod worker;
 
 pub use cache_prepopulate::prepopulate as prepopulate_cache;
 pub use page_walker::UpdatedPage;
+pub use seek::range_bounds;
 
 /// Updated pages produced by update workers.
 pub struct UpdatedPages(Vec<Vec<UpdatedPage>>);
 
 impl UpdatedPages {
+    /// Iterate all the pages without freezing them.
+    pub fn iter(&self) -> impl Iterator<Item = &UpdatedPage> {
+        self.0.iter().flatten()
+    }
+
     /// Freeze, label, and iterate all the pages.
     ///
     /// Pages are 'labeled' by placing the page ID into the page data itself prior to freezing.
@@ -128,6 +138,9 @@ impl KeyReadWrite {
 pub struct UpdatePool {
     worker_tp: ThreadPool,
     do_warm_up: bool,
+    prefetch_depth: usize,
+    inflight: InflightPages,
+    preimage_hook: Option<Arc<dyn NodePreimageHook>>,
 }
 
 impl UpdatePool {
@@ -136,13 +149,21 @@ impl UpdatePool {
     /// # Panics
     ///
     /// Panics if `num_workers` is zero.
-    pub fn new(num_workers: usize, do_warm_up: bool) -> Self {
+    pub fn new(
+        num_workers: usize,
+        do_warm_up: bool,
+        prefetch_depth: usize,
+        preimage_hook: Option<Arc<dyn NodePreimageHook>>,
+    ) -> Self {
         UpdatePool {
             worker_tp: threadpool::Builder::new()
                 .num_threads(num_workers)
                 .thread_name("nomt-commit".to_string())
                 .build(),
             do_warm_up,
+            prefetch_depth,
+            inflight: InflightPages::default(),
+            preimage_hook,
         }
     }
 
@@ -166,6 +187,8 @@ impl UpdatePool {
             overlay: overlay.clone(),
             store: store.clone(),
             root,
+            prefetch_depth: self.prefetch_depth,
+            inflight: self.inflight.clone(),
         };
 
         let warm_up = if self.do_warm_up {
@@ -182,6 +205,9 @@ impl UpdatePool {
             store,
             page_pool,
             overlay,
+            prefetch_depth: self.prefetch_depth,
+            inflight: self.inflight.clone(),
+            preimage_hook: self.preimage_hook.clone(),
         }
     }
 }
@@ -197,6 +223,9 @@ pub struct Updater {
     store: Store,
     page_pool: PagePool,
     overlay: LiveOverlay,
+    prefetch_depth: usize,
+    inflight: InflightPages,
+    preimage_hook: Option<Arc<dyn NodePreimageHook>>,
 }
 
 impl Updater {
@@ -225,6 +254,7 @@ impl Updater {
             overlay: self.overlay.clone(),
             read_write,
             root_page_pending: Mutex::new(Vec::with_capacity(64)),
+            preimage_hook: self.preimage_hook.clone(),
         });
 
         let num_workers = self.page_cache.shard_count();
@@ -232,7 +262,7 @@ impl Updater {
 
         // receive warm-ups from worker.
         let (warm_ups, warm_page_set) = if let Some(ref warm_up) = self.warm_up {
-            let output = join_task(&warm_up.output_rx)?;
+            let output = join_fallible_task(&warm_up.output_rx)?;
             (output.paths, Some(output.pages))
         } else {
             (HashMap::new(), None)
@@ -257,6 +287,8 @@ impl Updater {
                 root: self.root,
                 warm_ups: warm_ups.clone(),
                 warm_page_set: warm_page_set.clone(),
+                prefetch_depth: self.prefetch_depth,
+                inflight: self.inflight.clone(),
                 command,
             };
             spawn_updater::<H>(&self.worker_tp, params, worker_tx.clone());
@@ -291,12 +323,13 @@ impl UpdateHandle {
         });
 
         let mut updated_pages = Vec::new();
+        let mut skipped_writes = 0;
 
         let mut path_proof_offset = 0;
         let mut witnessed_start = 0;
 
         for _ in 0..self.num_workers {
-            let output = join_task(&self.worker_rx)?;
+            let output = join_fallible_task(&self.worker_rx)?;
 
             if let Some(root) = output.root {
                 assert!(new_root.is_none());
@@ -304,6 +337,7 @@ impl UpdateHandle {
             }
 
             updated_pages.push(output.updated_pages);
+            skipped_writes += output.skipped_writes;
 
             // if the Commit worker collected the witnessed paths
             // then we need to aggregate them
@@ -352,15 +386,41 @@ impl UpdateHandle {
             }
         }
 
+        let witness_size = maybe_witness.as_ref().map(compact_witness_size);
+
         // UNWRAP: one thread always produces the root.
         Ok(Output {
             root: new_root.unwrap(),
             updated_pages: UpdatedPages(updated_pages),
             witness: maybe_witness,
+            witness_size,
+            skipped_writes,
         })
     }
 }
 
+// The size of the witness as a compactly encoded multi-proof, which is how it'd be shipped.
+fn compact_witness_size(witness: &Witness) -> usize {
+    let proof = if witness.path_proofs.is_empty() {
+        MultiProof {
+            paths: Vec::new(),
+            siblings: Vec::new(),
+        }
+    } else {
+        // workers' paths are gathered in the order they finish, and a terminal may be witnessed
+        // by more than one of them.
+        let mut path_proofs: Vec<_> = witness
+            .path_proofs
+            .iter()
+            .map(|p| p.inner.clone())
+            .collect();
+        path_proofs.sort_by(|a, b| a.terminal.path().cmp(b.terminal.path()));
+        path_proofs.dedup_by(|a, b| a.terminal.path() == b.terminal.path());
+        MultiProof::from_path_proofs(path_proofs)
+    };
+    compact::encoded_len(&proof)
+}
+
 /// The output of a commit operation.
 pub struct Output {
     /// The new root.
@@ -369,6 +429,10 @@ pub struct Output {
     pub updated_pages: UpdatedPages,
     /// Optional witness
     pub witness: Option<Witness>,
+    /// The size of the witness, if any, as a compactly encoded multi-proof.
+    pub witness_size: Option<usize>,
+    /// The number of writes which didn't change the trie.
+    pub skipped_writes: usize,
 }
 
 struct UpdateCommand {
@@ -398,6 +462,7 @@ struct WorkerOutput {
     root: Option<Node>,
     witnessed_paths: Option<Vec<(WitnessedPath, Option<trie::LeafData>, usize)>>,
     updated_pages: Vec<UpdatedPage>,
+    skipped_writes: usize,
 }
 
 impl WorkerOutput {
@@ -406,6 +471,7 @@ impl WorkerOutput {
             root: None,
             witnessed_paths: if witness { Some(Vec::new()) } else { None },
             updated_pages: Vec::new(),
+            skipped_writes: 0,
         }
     }
 }
@@ -417,6 +483,8 @@ struct UpdateShared {
     root_page_pending: Mutex<Vec<(TriePosition, RootPagePending)>>,
     overlay: LiveOverlay,
     witness: bool,
+    // called with every node hashed by the workers.
+    preimage_hook: Option<Arc<dyn NodePreimageHook>>,
 }
 
 impl UpdateShared {
@@ -54,9 +54,11 @@ use nomt_core::{
 
 use crate::{
     merkle::BucketInfo,
+    node_hook::{NodePreimage, NodePreimageHook},
     page_cache::{Page, PageMut},
     page_diff::PageDiff,
 };
+use std::sync::Arc;
 
 /// The output of the page walker.
 pub enum Output {
@@ -83,6 +85,16 @@ pub struct UpdatedPage {
     pub bucket_info: BucketInfo,
 }
 
+/// Whether the pages entered when moving down the trie are known not to exist yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FreshnessHint {
+    /// The pages lie below a position which was terminal before the update, so they can't exist.
+    /// They are created empty with [`PageSet::fresh`] rather than looked up.
+    Fresh,
+    /// The pages may exist and must be in the page set.
+    Existing,
+}
+
 /// A set of pages that the page walker draws upon.
 pub trait PageSet {
     /// Get a page from the set. `None` if it isn't exist.
@@ -110,6 +122,8 @@ pub struct PageWalker<H> {
     sibling_stack: Vec<(Node, usize)>,
     prev_node: Option<Node>, // the node at `self.position` which was replaced in a previous call
 
+    preimage_hook: Option<Arc<dyn NodePreimageHook>>,
+
     _marker: std::marker::PhantomData<H>,
 }
 
@@ -127,10 +141,17 @@ impl<H: NodeHasher> PageWalker<H> {
             stack: Vec::new(),
             sibling_stack: Vec::new(),
             prev_node: None,
+            preimage_hook: None,
             _marker: std::marker::PhantomData,
         }
     }
 
+    /// Call the given hook with every node hashed by the walker.
+    pub fn with_preimage_hook(mut self, preimage_hook: Option<Arc<dyn NodePreimageHook>>) -> Self {
+        self.preimage_hook = preimage_hook;
+        self
+    }
+
     /// Advance to a given trie position and replace the terminal node there with a trie
     /// based on the provided key-value pairs.
     ///
@@ -241,6 +262,18 @@ impl<H: NodeHasher> PageWalker<H> {
             let up = control.up();
             let mut down = control.down();
 
+            if let Some(ref hook) = self.preimage_hook {
+                match control {
+                    WriteNode::Leaf { ref leaf_data, .. } => {
+                        hook.node_hashed(node, NodePreimage::Leaf(leaf_data))
+                    }
+                    WriteNode::Internal {
+                        ref internal_data, ..
+                    } => hook.node_hashed(node, NodePreimage::Internal(internal_data)),
+                    WriteNode::Terminator => {}
+                }
+            }
+
             if let WriteNode::Internal {
                 ref internal_data, ..
             } = control
@@ -276,14 +309,15 @@ impl<H: NodeHasher> PageWalker<H> {
             if !fresh && !down.is_empty() {
                 // first bit is only fresh if we are at the start position and the start is at the
                 // end of its page (or at the root). after that, definitely is.
-                self.down(
-                    page_set,
-                    &down[..1],
-                    self.position.depth_in_page() == DEPTH || self.position.is_root(),
-                );
-                self.down(page_set, &down[1..], true);
+                let hint = if self.position.depth_in_page() == DEPTH || self.position.is_root() {
+                    FreshnessHint::Fresh
+                } else {
+                    FreshnessHint::Existing
+                };
+                self.down(page_set, &down[..1], hint);
+                self.down(page_set, &down[1..], FreshnessHint::Fresh);
             } else {
-                self.down(page_set, &down, true);
+                self.down(page_set, &down, FreshnessHint::Fresh);
             }
 
             if self.position.is_root() {
@@ -315,10 +349,15 @@ impl<H: NodeHasher> PageWalker<H> {
     }
 
     // move the current position down, hinting whether the location is guaranteed to be fresh.
-    fn down(&mut self, page_set: &impl PageSet, bit_path: &BitSlice<u8, Msb0>, fresh: bool) {
+    fn down(
+        &mut self,
+        page_set: &impl PageSet,
+        bit_path: &BitSlice<u8, Msb0>,
+        hint: FreshnessHint,
+    ) {
         for bit in bit_path.iter().by_vals() {
             if self.position.is_root() {
-                let (page, bucket_info) = if fresh {
+                let (page, bucket_info) = if hint == FreshnessHint::Fresh {
                     page_set.fresh(&ROOT_PAGE_ID)
                 } else {
                     // UNWRAP: all pages on the path to the node should be in the cache.
@@ -343,7 +382,7 @@ impl<H: NodeHasher> PageWalker<H> {
                 // UNWRAP: we never overflow the page stack.
                 let child_page_id = parent_page_id.child_page_id(child_page_index).unwrap();
 
-                let stack_item = if fresh {
+                let stack_item = if hint == FreshnessHint::Fresh {
                     let (page, bucket_info) = page_set.fresh(&child_page_id);
                     UpdatedPage {
                         page_id: child_page_id,
@@ -375,8 +414,13 @@ impl<H: NodeHasher> PageWalker<H> {
 
     /// Conclude walking and updating and return an output - either a new root, or a list
     /// of node changes to apply to the parent page.
+    ///
+    /// The headers of the updated pages are written, as they won't be changed further.
     pub fn conclude(mut self) -> Output {
         self.compact_up(None);
+        for updated_page in &mut self.updated_pages {
+            updated_page.page.stamp_header();
+        }
         if self.parent_page.is_none() {
             Output::Root(self.root, self.updated_pages)
         } else {
@@ -487,7 +531,11 @@ impl<H: NodeHasher> PageWalker<H> {
                     }
                 };
 
-                H::hash_internal(&node_data)
+                let node = H::hash_internal(&node_data);
+                if let Some(ref hook) = self.preimage_hook {
+                    hook.node_hashed(node, NodePreimage::Internal(&node_data));
+                }
+                node
             }
         }
     }
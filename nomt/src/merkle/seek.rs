@@ -6,24 +6,28 @@ use crate::{
         AsyncLeafLoad, BeatreeIterator, LeafNodeRef, PageNumber, ReadTransaction as BeatreeReadTx,
         ValueChange,
     },
-    io::{CompleteIo, FatPage, IoHandle},
+    io::{CompleteIo, FatPage, IoHandle, IoPriority},
     page_cache::{Page, PageCache, PageMut},
     store::{BucketIndex, PageLoad, PageLoader},
     HashAlgorithm,
 };
 
-use super::{page_set::PageSet, BucketInfo, LiveOverlay};
+use super::{
+    inflight::{FetchTicket, InflightPages},
+    page_set::PageSet,
+    BucketInfo, LiveOverlay,
+};
 
 use nomt_core::{
     page::DEPTH,
-    page_id::{PageId, ROOT_PAGE_ID},
+    page_id::{PageId, PageIdsIterator, ROOT_PAGE_ID},
     trie::{self, KeyPath, Node},
     trie_pos::TriePosition,
 };
 
 use std::collections::{
     hash_map::{Entry, HashMap},
-    VecDeque,
+    HashSet, VecDeque,
 };
 
 use bitvec::prelude::*;
@@ -66,7 +70,7 @@ impl SeekRequest {
 
         if let RequestState::FetchingLeaf(_, _) = request.state {
             // we must advance the iterator until blocked.
-            request.continue_leaf_fetch::<H>(None);
+            request.continue_leaf_fetch::<H>(overlay, None);
         }
 
         request
@@ -76,6 +80,11 @@ impl SeekRequest {
         self.ios += 1;
     }
 
+    // Whether the request has found its terminal node. It may still be fetching the leaf.
+    fn is_seeking(&self) -> bool {
+        matches!(self.state, RequestState::Seeking)
+    }
+
     fn is_completed(&self) -> bool {
         match self.state {
             RequestState::Seeking => false,
@@ -147,11 +156,15 @@ impl SeekRequest {
         }
 
         if do_leaf_fetch {
-            self.continue_leaf_fetch::<H>(None);
+            self.continue_leaf_fetch::<H>(overlay, None);
         }
     }
 
-    fn continue_leaf_fetch<H: HashAlgorithm>(&mut self, leaf: Option<LeafNodeRef>) {
+    fn continue_leaf_fetch<H: HashAlgorithm>(
+        &mut self,
+        overlay: &LiveOverlay,
+        leaf: Option<LeafNodeRef>,
+    ) {
         let RequestState::FetchingLeaf(ref mut iter, _) = self.state else {
             panic!("called continue_leaf_fetch without active iterator");
         };
@@ -160,13 +173,21 @@ impl SeekRequest {
             iter.provide_leaf(leaf);
         }
 
-        let (key, value_hash) = match iter.next() {
-            None => panic!("leaf must exist position={}", self.position.path()),
-            Some(IterOutput::Blocked) => return,
-            Some(IterOutput::Item(key, value)) => {
-                (key, H::hash_value(&value)) // hash
+        let (key, value_hash) = loop {
+            let (key, value_hash) = match iter.next() {
+                None => panic!("leaf must exist position={}", self.position.path()),
+                Some(IterOutput::Blocked) => return,
+                Some(IterOutput::Item(key, value)) => {
+                    (key, H::hash_value(&value)) // hash
+                }
+                Some(IterOutput::OverflowItem(key, value_hash, _)) => (key, value_hash),
+            };
+
+            // a key changed within the overlay must have been deleted by it, or it would have
+            // been found in the overlay. it is still in the database, so skip it.
+            if overlay.value(&key).is_none() {
+                break (key, value_hash);
             }
-            Some(IterOutput::OverflowItem(key, value_hash, _)) => (key, value_hash),
         };
 
         self.state = RequestState::Completed(Some(trie::LeafData {
@@ -217,7 +238,9 @@ impl RequestState {
     }
 }
 
-fn range_bounds(raw_path: KeyPath, depth: usize) -> (KeyPath, Option<KeyPath>) {
+/// The half-open range of keys starting with the first `depth` bits of `raw_path`, whose
+/// remaining bits must be zero. The end is `None` if the range extends to the last key.
+pub fn range_bounds(raw_path: KeyPath, depth: usize) -> (KeyPath, Option<KeyPath>) {
     if depth == 0 {
         return (KeyPath::default(), None);
     }
@@ -252,6 +275,8 @@ enum IoQuery {
 
 enum IoRequest {
     Merkle(PageLoad),
+    // A speculative load of a page which may not exist. See `Seeker::prefetch_below`.
+    MerklePrefetch(PageLoad),
     Leaf(AsyncLeafLoad),
 }
 
@@ -264,12 +289,18 @@ enum IoRequest {
 /// children of the request.
 ///
 /// Requests are completed in the order they are submitted.
+///
+/// Whenever a page has to be loaded from disk, the pages below it along the path of the key are
+/// loaded speculatively, up to `prefetch_depth` levels down. This overlaps the loads of all the
+/// pages on the path instead of waiting for each one before requesting the next.
 pub struct Seeker<H: HashAlgorithm> {
     root: Node,
     beatree_read_transaction: BeatreeReadTx,
     page_cache: PageCache,
     overlay: LiveOverlay,
     io_handle: IoHandle,
+    /// Sends speculative loads, at a lower priority. Shares the completions of `io_handle`.
+    prefetch_io_handle: IoHandle,
     page_loader: PageLoader,
     processed: usize,
     requests: VecDeque<SeekRequest>,
@@ -279,7 +310,12 @@ pub struct Seeker<H: HashAlgorithm> {
     idle_requests: VecDeque<usize>,
     /// FIFO, pushed onto back.
     idle_page_loads: VecDeque<usize>,
+    /// Slab indices of in-flight speculative loads which are no longer needed.
+    cancelled_prefetches: HashSet<usize>,
+    /// The speculative loads issued by this seeker or by others which this seeker relies on.
+    fetch_ticket: FetchTicket,
     record_siblings: bool,
+    prefetch_depth: usize,
     _marker: std::marker::PhantomData<H>,
 }
 
@@ -299,6 +335,7 @@ impl<H: HashAlgorithm> Seeker<H> {
             beatree_read_transaction,
             page_cache,
             overlay,
+            prefetch_io_handle: io_handle.with_priority(IoPriority::Speculative),
             io_handle,
             page_loader,
             processed: 0,
@@ -307,11 +344,27 @@ impl<H: HashAlgorithm> Seeker<H> {
             io_slab: Slab::new(),
             idle_requests: VecDeque::new(),
             idle_page_loads: VecDeque::new(),
+            cancelled_prefetches: HashSet::new(),
+            fetch_ticket: InflightPages::default().ticket(),
             record_siblings,
+            prefetch_depth: 0,
             _marker: std::marker::PhantomData,
         }
     }
 
+    /// Set the number of levels of pages to load speculatively below each page loaded from disk.
+    pub fn with_prefetch_depth(mut self, prefetch_depth: usize) -> Self {
+        self.prefetch_depth = prefetch_depth;
+        self
+    }
+
+    /// Skip speculative loads of pages which other seekers sharing the registry are already
+    /// loading.
+    pub fn with_inflight_pages(mut self, inflight: &InflightPages) -> Self {
+        self.fetch_ticket = inflight.ticket();
+        self
+    }
+
     pub fn is_empty(&self) -> bool {
         self.requests.is_empty()
     }
@@ -415,6 +468,27 @@ impl<H: HashAlgorithm> Seeker<H> {
 
     // submit a page load which is currently in the slab, but idle.
     fn submit_idle_page_load(&mut self, slab_index: usize) {
+        if let IoRequest::MerklePrefetch(ref mut page_load) = self.io_slab[slab_index] {
+            if !self
+                .page_loader
+                .probe(page_load, &self.prefetch_io_handle, slab_index as u64)
+            {
+                // the speculatively loaded page doesn't exist.
+                let IoRequest::MerklePrefetch(page_load) = self.io_slab.remove(slab_index) else {
+                    unreachable!()
+                };
+                self.fetch_ticket.finish(page_load.page_id());
+                let waiters = self
+                    .io_waiters
+                    .remove(&IoQuery::MerklePage(page_load.page_id().clone()));
+
+                // PANIC: requests only wait on pages they have seeked to, which must exist.
+                // See below.
+                assert!(waiters.is_none_or(|w| w.is_empty()));
+            }
+            return;
+        }
+
         if let IoRequest::Merkle(ref mut page_load) = self.io_slab[slab_index] {
             if !self
                 .page_loader
@@ -441,9 +515,13 @@ impl<H: HashAlgorithm> Seeker<H> {
             request_index - self.processed
         };
 
-        let request = &mut self.requests[i];
+        let key = self.requests[i].key;
 
-        while let Some(query) = request.next_query() {
+        loop {
+            let request = &mut self.requests[i];
+            let Some(query) = request.next_query() else {
+                break;
+            };
             match query {
                 IoQuery::MerklePage(page_id) => {
                     let maybe_in_memory =
@@ -456,6 +534,9 @@ impl<H: HashAlgorithm> Seeker<H> {
                             &page,
                             self.record_siblings,
                         );
+                        if !request.is_seeking() {
+                            self.cancel_prefetch_below(key, &page_id);
+                        }
                         page_set.insert(page_id, page, bucket_info);
                         continue;
                     }
@@ -476,6 +557,7 @@ impl<H: HashAlgorithm> Seeker<H> {
                     vacant_entry.insert(vec![request_index]);
                     let slab_index = self.io_slab.insert(IoRequest::Merkle(load));
                     self.submit_idle_page_load(slab_index);
+                    self.prefetch_below(key, &page_id);
                     return;
                 }
                 IoQuery::LeafPage(page_number) => {
@@ -495,7 +577,7 @@ impl<H: HashAlgorithm> Seeker<H> {
                         slab_index as u64,
                     ) {
                         Ok(leaf) => {
-                            request.continue_leaf_fetch::<H>(Some(leaf));
+                            request.continue_leaf_fetch::<H>(&self.overlay, Some(leaf));
                             continue;
                         }
                         Err(leaf_load) => {
@@ -511,17 +593,143 @@ impl<H: HashAlgorithm> Seeker<H> {
         }
     }
 
+    // Speculatively load the pages below `page_id` along the path to `key`, which are likely to be
+    // needed once `page_id` has been loaded. Pages which are in memory or already being loaded are
+    // skipped, and so is everything while the I/O pool is under backpressure.
+    fn prefetch_below(&mut self, key: KeyPath, page_id: &PageId) {
+        let page_ids = PageIdsIterator::new(key)
+            .skip(page_id.depth() + 1)
+            .take(self.prefetch_depth);
+
+        for page_id in page_ids {
+            if !self.has_room() || self.io_handle.would_block() {
+                return;
+            }
+
+            let query = IoQuery::MerklePage(page_id.clone());
+            if self.io_waiters.contains_key(&query)
+                || super::get_in_memory_page(&self.overlay, &self.page_cache, &page_id).is_some()
+            {
+                continue;
+            }
+            if !self.fetch_ticket.acquire(&page_id) {
+                // another seeker is loading the page into the page cache.
+                continue;
+            }
+
+            let mut load = self.page_loader.start_load(page_id.clone());
+            let slab_index = self.io_slab.vacant_key();
+            match self
+                .page_loader
+                .try_probe(&mut load, &self.prefetch_io_handle, slab_index as u64)
+            {
+                Some(true) => {}
+                // the speculatively loaded page doesn't exist.
+                Some(false) => {
+                    self.fetch_ticket.finish(&page_id);
+                    continue;
+                }
+                // the I/O pool filled up in the meantime.
+                None => {
+                    self.fetch_ticket.finish(&page_id);
+                    return;
+                }
+            }
+            self.io_waiters.insert(query, Vec::new());
+            self.io_slab.insert(IoRequest::MerklePrefetch(load));
+        }
+    }
+
+    // Cancel the speculative loads below `page_id` along the path to `key`, once the request for
+    // `key` has found its terminal node in `page_id`. Everything below the terminal node is created
+    // fresh by the update, so the pages can't exist.
+    fn cancel_prefetch_below(&mut self, key: KeyPath, page_id: &PageId) {
+        if self.prefetch_depth == 0 {
+            return;
+        }
+        if let Some(child_page_id) = PageIdsIterator::new(key).nth(page_id.depth() + 1) {
+            self.cancel_prefetch_subtree(&child_page_id);
+        }
+    }
+
+    /// Cancel the speculative loads of `page_id` and its descendants which no request waits on.
+    ///
+    /// Loads which haven't been submitted or are still queued for the I/O workers are dropped.
+    /// Loads in flight are dropped on completion, instead of probing further buckets or entering
+    /// the page cache. Loads which other seekers rely on are kept.
+    pub fn cancel_prefetch_subtree(&mut self, page_id: &PageId) {
+        self.fetch_ticket.release_subtree(page_id);
+
+        let cancelled: Vec<usize> = self
+            .io_slab
+            .iter()
+            .filter_map(|(slab_index, request)| match request {
+                IoRequest::MerklePrefetch(page_load)
+                    if page_load.page_id().is_descendant_of(page_id) =>
+                {
+                    Some(slab_index)
+                }
+                _ => None,
+            })
+            .filter(|slab_index| !self.cancelled_prefetches.contains(slab_index))
+            .collect();
+
+        for slab_index in cancelled {
+            let IoRequest::MerklePrefetch(ref page_load) = self.io_slab[slab_index] else {
+                unreachable!()
+            };
+            let cancelled_page_id = page_load.page_id().clone();
+            let query = IoQuery::MerklePage(cancelled_page_id.clone());
+            if self.io_waiters.get(&query).is_some_and(|w| !w.is_empty()) {
+                // a request reached the page after all.
+                continue;
+            }
+            if self.fetch_ticket.is_shared(&cancelled_page_id) {
+                // another seeker is waiting for the page to enter the page cache.
+                continue;
+            }
+
+            if let Some(pos) = self.idle_page_loads.iter().position(|i| *i == slab_index) {
+                self.idle_page_loads.remove(pos);
+                self.io_slab.remove(slab_index);
+                self.io_waiters.remove(&query);
+                self.fetch_ticket.finish(&cancelled_page_id);
+            } else if self.io_handle.cancel(slab_index as u64).is_some() {
+                self.io_slab.remove(slab_index);
+                self.io_waiters.remove(&query);
+                self.fetch_ticket.finish(&cancelled_page_id);
+            } else {
+                self.cancelled_prefetches.insert(slab_index);
+            }
+        }
+    }
+
     fn handle_completion(&mut self, page_set: &mut PageSet, io: CompleteIo) -> std::io::Result<()> {
         io.result?;
         let slab_index = io.command.user_data as usize;
 
+        if self.cancelled_prefetches.remove(&slab_index) {
+            let IoRequest::MerklePrefetch(ref page_load) = self.io_slab[slab_index] else {
+                unreachable!()
+            };
+            let cancelled_page_id = page_load.page_id().clone();
+            let query = IoQuery::MerklePage(cancelled_page_id.clone());
+            // unless a request reached the page after all.
+            if self.io_waiters.get(&query).is_none_or(|w| w.is_empty()) {
+                self.io_slab.remove(slab_index);
+                self.io_waiters.remove(&query);
+                self.fetch_ticket.finish(&cancelled_page_id);
+                return Ok(());
+            }
+        }
+
         // UNWRAP: requests are submitted with slab indices that are populated and never cleared
         // until this point is reached.
         match self.io_slab.get_mut(slab_index).unwrap() {
-            IoRequest::Merkle(merkle_load) => {
+            IoRequest::Merkle(merkle_load) | IoRequest::MerklePrefetch(merkle_load) => {
                 // UNWRAP: page loader always submits a `Read` command that yields a fat page.
                 let page = io.command.kind.unwrap_buf();
-                match merkle_load.try_complete(page) {
+                match merkle_load.try_complete(page)? {
                     Some((page, bucket)) => {
                         self.handle_merkle_page_and_continue(page_set, slab_index, page, bucket)
                     }
@@ -544,28 +752,37 @@ impl<H: HashAlgorithm> Seeker<H> {
         page_data: FatPage,
         bucket_index: BucketIndex,
     ) {
-        let IoRequest::Merkle(page_load) = self.io_slab.remove(slab_index) else {
+        let (IoRequest::Merkle(page_load) | IoRequest::MerklePrefetch(page_load)) =
+            self.io_slab.remove(slab_index)
+        else {
             panic!()
         };
 
         let page = PageMut::pristine_with_data(page_data).freeze();
 
-        // insert the page into the page cache and working page set.
+        // insert the page into the page cache.
         let page = self
             .page_cache
             .insert(page_load.page_id().clone(), page.clone(), bucket_index);
+        self.fetch_ticket.finish(page_load.page_id());
+
+        // UNWRAP: every page load has an entry, possibly without waiters if speculative.
+        let waiters = self
+            .io_waiters
+            .remove(&IoQuery::MerklePage(page_load.page_id().clone()))
+            .unwrap();
+
+        // speculatively loaded pages enter the working page set only once a request reaches them.
+        if waiters.is_empty() {
+            return;
+        }
         page_set.insert(
             page_load.page_id().clone(),
             page.clone(),
             BucketInfo::Known(bucket_index),
         );
 
-        for waiting_request in self
-            .io_waiters
-            .remove(&IoQuery::MerklePage(page_load.page_id().clone()))
-            .into_iter()
-            .flatten()
-        {
+        for waiting_request in waiters {
             if waiting_request < self.processed {
                 continue;
             }
@@ -581,7 +798,11 @@ impl<H: HashAlgorithm> Seeker<H> {
                 self.record_siblings,
             );
 
-            if !request.is_completed() {
+            if !request.is_seeking() {
+                let key = request.key;
+                self.cancel_prefetch_below(key, page_load.page_id());
+            }
+            if !self.requests[idx].is_completed() {
                 self.idle_requests.push_back(waiting_request);
             }
         }
@@ -607,7 +828,7 @@ impl<H: HashAlgorithm> Seeker<H> {
             let request = &mut self.requests[idx];
             assert!(!request.is_completed());
 
-            request.continue_leaf_fetch::<H>(Some(leaf.clone()));
+            request.continue_leaf_fetch::<H>(&self.overlay, Some(leaf.clone()));
             if !request.is_completed() {
                 self.idle_requests.push_back(waiting_request);
             }
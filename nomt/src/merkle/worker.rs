@@ -15,7 +15,7 @@ use crossbeam::channel::{Receiver, Select, TryRecvError};
 use nomt_core::{
     page_id::ROOT_PAGE_ID,
     proof::PathProofTerminal,
-    trie::{KeyPath, Node, ValueHash},
+    trie::{KeyPath, LeafData, Node, ValueHash},
 };
 
 use std::{
@@ -24,6 +24,7 @@ use std::{
 };
 
 use super::{
+    inflight::InflightPages,
     page_set::{FrozenSharedPageSet, PageSet},
     page_walker::{Output, PageWalker},
     seek::{Seek, Seeker},
@@ -47,6 +48,8 @@ pub(super) struct UpdateParams {
     pub root: Node,
     pub warm_ups: Arc<HashMap<KeyPath, Seek>>,
     pub warm_page_set: Option<FrozenSharedPageSet>,
+    pub prefetch_depth: usize,
+    pub inflight: InflightPages,
     pub command: UpdateCommand,
 }
 
@@ -55,6 +58,8 @@ pub(super) struct WarmUpParams {
     pub overlay: LiveOverlay,
     pub store: Store,
     pub root: Node,
+    pub prefetch_depth: usize,
+    pub inflight: InflightPages,
 }
 
 pub(super) fn run_warm_up<H: HashAlgorithm>(
@@ -78,7 +83,9 @@ pub(super) fn run_warm_up<H: HashAlgorithm>(
         io_handle,
         page_loader,
         true,
-    );
+    )
+    .with_prefetch_depth(params.prefetch_depth)
+    .with_inflight_pages(&params.inflight);
 
     warm_up_phase(page_io_receiver, seeker, page_set, warmup_rx, finish_rx)
 }
@@ -91,8 +98,9 @@ pub(super) fn run_update<H: HashAlgorithm>(params: UpdateParams) -> std::io::Res
         root,
         warm_ups,
         warm_page_set,
+        prefetch_depth,
+        inflight,
         command,
-        ..
     } = params;
 
     let seeker = Seeker::<H>::new(
@@ -103,7 +111,9 @@ pub(super) fn run_update<H: HashAlgorithm>(params: UpdateParams) -> std::io::Res
         store.io_pool().make_handle(),
         store.page_loader(),
         command.shared.witness,
-    );
+    )
+    .with_prefetch_depth(prefetch_depth)
+    .with_inflight_pages(&inflight);
 
     update::<H>(
         root,
@@ -222,7 +232,8 @@ fn update<H: HashAlgorithm>(
     };
 
     let pending_ops = shared.take_root_pending();
-    let mut root_page_updater = PageWalker::<H>::new(root, None);
+    let mut root_page_updater =
+        PageWalker::<H>::new(root, None).with_preimage_hook(shared.preimage_hook.clone());
 
     // Ensure the root page updater holds the root page. It is possible that this worker did not
     // seek any keys, and therefore the root page would not have been populated yet.
@@ -242,9 +253,17 @@ fn update<H: HashAlgorithm>(
                 range_end,
                 prev_terminal,
             } => {
-                let ops = subtrie_ops(&shared.read_write[range_start..range_end]);
-                let ops = nomt_core::update::leaf_ops_spliced(prev_terminal, &ops);
-                root_page_updater.advance_and_replace(&page_set, trie_pos.clone(), ops.clone());
+                let (ops, skipped) = subtrie_ops(
+                    &shared.read_write[range_start..range_end],
+                    prev_terminal.as_ref(),
+                );
+                output.skipped_writes += skipped;
+                if ops.is_empty() {
+                    root_page_updater.advance(trie_pos.clone());
+                } else {
+                    let ops = nomt_core::update::leaf_ops_spliced(prev_terminal, &ops);
+                    root_page_updater.advance_and_replace(&page_set, trie_pos.clone(), ops);
+                }
             }
         }
     }
@@ -298,11 +317,14 @@ impl<H: HashAlgorithm> RangeUpdater<H> {
             .binary_search_by_key(&key_range_end, |x| x.0)
             .unwrap_or_else(|i| i);
 
+        let page_walker = PageWalker::<H>::new(root, Some(ROOT_PAGE_ID))
+            .with_preimage_hook(shared.preimage_hook.clone());
+
         RangeUpdater {
             shared,
             write_pass,
             region,
-            page_walker: PageWalker::<H>::new(root, Some(ROOT_PAGE_ID)),
+            page_walker,
             range_start,
             range_end,
         }
@@ -383,9 +405,13 @@ impl<H: HashAlgorithm> RangeUpdater<H> {
 
         // attempt to advance the trie walker. if it fails, pocket away for later.
         let ops = if has_writes {
-            Some(subtrie_ops(
+            let (ops, skipped) = subtrie_ops(
                 &self.shared.read_write[start_index..next_index],
-            ))
+                seek_result.terminal.as_ref(),
+            );
+            output.skipped_writes += skipped;
+            // a batch of no-op writes leaves the terminal as it is.
+            (!ops.is_empty()).then_some(ops)
         } else {
             None
         };
@@ -530,14 +556,28 @@ impl<H: HashAlgorithm> RangeUpdater<H> {
     }
 }
 
-fn subtrie_ops(read_write: &[(KeyPath, KeyReadWrite)]) -> Vec<(KeyPath, Option<ValueHash>)> {
-    read_write
+// The writes of a batch which change the trie below the terminal, along with the number of
+// writes which don't: those of the value a key already has and deletions of absent keys.
+fn subtrie_ops(
+    read_write: &[(KeyPath, KeyReadWrite)],
+    terminal: Option<&LeafData>,
+) -> (Vec<(KeyPath, Option<ValueHash>)>, usize) {
+    let mut skipped = 0;
+    let ops = read_write
         .iter()
         .filter_map(|(key, read_write)| match read_write {
             KeyReadWrite::Write(val) | KeyReadWrite::ReadThenWrite(val) => {
+                let existing = terminal
+                    .filter(|leaf| &leaf.key_path == key)
+                    .map(|leaf| leaf.value_hash);
+                if existing == *val {
+                    skipped += 1;
+                    return None;
+                }
                 Some((key.clone(), val.clone()))
             }
             KeyReadWrite::Read => None,
         })
-        .collect::<Vec<_>>()
+        .collect::<Vec<_>>();
+    (ops, skipped)
 }
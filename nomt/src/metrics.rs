@@ -20,6 +20,12 @@ pub enum Metric {
     PageFetchTime,
     /// Timer used to record average value fetch time during reads
     ValueFetchTime,
+    /// Counter of I/O commands retried after a transient error
+    IoRetries,
+    /// Counter of page cache lookups which had to wait for a shard lock held by another thread
+    PageCacheLockContention,
+    /// Counter of I/O commands submitted or refused while the I/O pool was under backpressure
+    IoBackpressure,
 }
 
 struct ActiveMetrics {
@@ -27,6 +33,9 @@ struct ActiveMetrics {
     page_cache_misses: AtomicU64,
     page_fetch_time: Timer,
     value_fetch_time: Timer,
+    io_retries: AtomicU64,
+    page_cache_lock_contention: AtomicU64,
+    io_backpressure: AtomicU64,
 }
 
 impl Metrics {
@@ -39,6 +48,9 @@ impl Metrics {
                     page_cache_misses: AtomicU64::new(0),
                     page_fetch_time: Timer::new(),
                     value_fetch_time: Timer::new(),
+                    io_retries: AtomicU64::new(0),
+                    page_cache_lock_contention: AtomicU64::new(0),
+                    io_backpressure: AtomicU64::new(0),
                 }))
             } else {
                 None
@@ -54,6 +66,9 @@ impl Metrics {
             let counter = match metric {
                 Metric::PageRequests => &metrics.page_requests,
                 Metric::PageCacheMisses => &metrics.page_cache_misses,
+                Metric::IoRetries => &metrics.io_retries,
+                Metric::PageCacheLockContention => &metrics.page_cache_lock_contention,
+                Metric::IoBackpressure => &metrics.io_backpressure,
                 _ => panic!("Specified metric is not a Counter"),
             };
 
@@ -61,6 +76,24 @@ impl Metrics {
         }
     }
 
+    /// Returns the current value of the Counter specified by the input, if active
+    ///
+    /// panics if the specified [`Metric`] is not a Counter
+    pub fn counter(&self, metric: Metric) -> Option<u64> {
+        self.metrics.as_ref().map(|metrics| {
+            let counter = match metric {
+                Metric::PageRequests => &metrics.page_requests,
+                Metric::PageCacheMisses => &metrics.page_cache_misses,
+                Metric::IoRetries => &metrics.io_retries,
+                Metric::PageCacheLockContention => &metrics.page_cache_lock_contention,
+                Metric::IoBackpressure => &metrics.io_backpressure,
+                _ => panic!("Specified metric is not a Counter"),
+            };
+
+            counter.load(Ordering::Relaxed)
+        })
+    }
+
     /// Returns a guard that, when dropped, will record the time passed since creation
     ///
     /// panics if the specified [`Metric`] is not a Timer
@@ -102,6 +135,21 @@ impl Metrics {
             if let Some(mean) = metrics.value_fetch_time.mean() {
                 println!("  value fetch mean      {}", pretty_display_ns(mean));
             }
+
+            let io_retries = metrics.io_retries.load(Ordering::Relaxed);
+            if io_retries != 0 {
+                println!("  I/O retries           {}", io_retries);
+            }
+
+            let contention = metrics.page_cache_lock_contention.load(Ordering::Relaxed);
+            if contention != 0 {
+                println!("  page cache contention {}", contention);
+            }
+
+            let backpressure = metrics.io_backpressure.load(Ordering::Relaxed);
+            if backpressure != 0 {
+                println!("  I/O backpressure      {}", backpressure);
+            }
         } else {
             println!("Metrics collection was not activated")
         }
@@ -0,0 +1,39 @@
+//! Hooks observing the nodes hashed by commits.
+//!
+//! A hook is given every node computed while updating the trie, along with its preimage, so that
+//! external systems can keep a database of nodes for auditing, or feed the hashing work to a
+//! prover, without reimplementing the update. See [`crate::Options::node_preimage_hook`].
+
+use nomt_core::trie::{InternalData, LeafData, Node};
+
+/// The data a node is the hash of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodePreimage<'a> {
+    /// A leaf node, the hash of its key path and value hash.
+    Leaf(&'a LeafData),
+    /// An internal node, the hash of its two children.
+    Internal(&'a InternalData),
+}
+
+/// A hook called with every node hashed during a commit.
+///
+/// This is implemented for closures taking the node and its preimage.
+pub trait NodePreimageHook: Send + Sync {
+    /// Called when a node is hashed.
+    ///
+    /// This is called from the commit workers, concurrently and in no particular order, while
+    /// the trie is updated. The nodes include every node of the new trie which didn't exist
+    /// before the commit, but also nodes hashed along the way which the final trie doesn't hold,
+    /// and a node may be reported more than once. The hook should be fast, as it holds up the
+    /// commit.
+    fn node_hashed(&self, node: Node, preimage: NodePreimage<'_>);
+}
+
+impl<F> NodePreimageHook for F
+where
+    F: Fn(Node, NodePreimage<'_>) + Send + Sync,
+{
+    fn node_hashed(&self, node: Node, preimage: NodePreimage<'_>) {
+        self(node, preimage)
+    }
+}
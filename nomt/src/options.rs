@@ -1,4 +1,64 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use crate::{
+    io::DEFAULT_IO_QUEUE_DEPTH, CommitLimits, CommitLogThresholds, CommitSigner, CommitSink,
+    NodePreimageHook, RetryPolicy, SharedIoPool,
+};
+
+// Level 4 would use ≈64GiB of RAM.
+const MAX_PAGE_CACHE_UPPER_LEVELS: usize = 3;
+
+// The largest io_uring supported by the kernel.
+const MAX_IO_QUEUE_DEPTH: usize = 32768;
+// The limits keep the sizes in pages within the range of page numbers.
+const MAX_VALUES_GROWTH_EXTENT: usize = 4096;
+const MAX_VALUES_PREALLOCATE_SIZE: usize = 8 * 1024 * 1024;
+
+// Options which can be set from configuration files and the environment.
+const CONFIG_KEYS: &[&str] = &[
+    "path",
+    "commit_concurrency",
+    "io_workers",
+    "metrics",
+    "hashtable_buckets",
+    "rollback",
+    "max_rollback_log_len",
+    "rollback_retention_secs",
+    "warm_up",
+    "preallocate_ht",
+    "commit_strategy",
+    "preallocate_values",
+    "values_preallocate_size",
+    "values_growth_extent",
+    "discard_freed_pages",
+    "value_gc_interval_secs",
+    "page_cache_size",
+    "leaf_cache_size",
+    "prepopulate_page_cache",
+    "page_cache_upper_levels",
+    "backup_log",
+    "page_access_sampling",
+    "page_prefetch_depth",
+    "read_only",
+    "max_commit_keys",
+    "max_commit_pages",
+    "max_commit_value_bytes",
+    "commit_log_keys",
+    "commit_log_pages",
+    "commit_log_duration_millis",
+    "verify_commits",
+    "verify_values",
+    "keyed_key_paths",
+    "io_max_retries",
+    "io_retry_backoff_micros",
+    "io_queue_depth",
+    "fencing_token",
+    "deterministic_layout",
+    "expiry_index",
+    "root_index",
+    "state_usage_prefix_len",
+    "state_usage_history",
+];
 
 /// Options when opening a [`crate::Nomt`] instance.
 pub struct Options {
@@ -14,11 +74,23 @@ pub struct Options {
     pub(crate) bitbox_seed: [u8; 16],
     pub(crate) panic_on_sync: Option<PanicOnSyncMode>,
     pub(crate) rollback: bool,
-    /// The maximum number of commits that can be rolled back.
-    pub(crate) max_rollback_log_len: u32,
+    /// Which commits can be rolled back.
+    pub(crate) rollback_retention: RetentionPolicy,
     pub(crate) warm_up: bool,
     /// Whether to preallocate the hashtable file.
     pub(crate) preallocate_ht: bool,
+    /// How a new database makes the changes to the hashtable durable.
+    pub(crate) commit_strategy: CommitStrategy,
+    /// Whether to allocate the blocks of the value store files as they are extended.
+    pub(crate) preallocate_values: bool,
+    /// The size the leaf file of the value store is extended to on opening, in MiB.
+    pub(crate) values_preallocate_size: usize,
+    /// The size the files of the value store are extended by once full, in MiB.
+    pub(crate) values_growth_extent: usize,
+    /// Whether to return the space of freed value store pages to the filesystem.
+    pub(crate) discard_freed_pages: bool,
+    /// How often orphaned pages of the value store are collected in the background.
+    pub(crate) value_gc_interval: Option<Duration>,
     /// The maximum size of the page cache specified in MiB, rounded down
     /// to the nearest byte multiple of [`crate::io::PAGE_SIZE`].
     pub(crate) page_cache_size: usize,
@@ -29,6 +101,47 @@ pub struct Options {
     /// This incurs some I/O on startup but leads to predictable worst-case performance.
     pub(crate) prepopulate_page_cache: bool,
     pub(crate) page_cache_upper_levels: usize,
+    /// The path of the log which every commit is appended to for incremental backups.
+    pub(crate) backup_log: Option<PathBuf>,
+    /// One in every this many page cache accesses is sampled. 0 disables sampling.
+    pub(crate) page_access_sampling: u32,
+    /// The number of levels of pages below a page being fetched to fetch speculatively.
+    pub(crate) page_prefetch_depth: usize,
+    /// The sink which the changes of every commit are written to.
+    pub(crate) commit_sink: Option<Arc<dyn CommitSink>>,
+    /// The signer of the root of every commit.
+    pub(crate) commit_signer: Option<Arc<dyn CommitSigner>>,
+    /// The hook called with every node hashed during a commit.
+    pub(crate) node_preimage_hook: Option<Arc<dyn NodePreimageHook>>,
+    /// Whether to open the database without the ability to commit.
+    pub(crate) read_only: bool,
+    /// The limits on the resources used by a single commit.
+    pub(crate) commit_limits: CommitLimits,
+    /// The thresholds above which a commit is logged.
+    pub(crate) commit_log_thresholds: CommitLogThresholds,
+    /// Whether to check the pages written by every commit against its root.
+    pub(crate) verify_commits: bool,
+    /// Whether to verify every value read from the value store against the trie.
+    pub(crate) verify_values: bool,
+    /// Whether a new database gets a secret for deriving key paths.
+    pub(crate) keyed_key_paths: bool,
+    /// The fencing token of this writer. `None` takes the token after the previous writer's.
+    pub(crate) fencing_token: Option<u64>,
+    /// How I/O commands failing with a transient error are retried.
+    pub(crate) io_retry_policy: RetryPolicy,
+    /// The most I/O commands each I/O worker keeps in flight.
+    pub(crate) io_queue_depth: usize,
+    /// The I/O pool shared with other instances and the weight of this instance in it.
+    pub(crate) shared_io_pool: Option<(SharedIoPool, u32)>,
+    /// Whether the placement of pages on disk depends only on the commits applied.
+    pub(crate) deterministic_layout: bool,
+    /// Whether to index keys by the sequence number of the commit which last wrote them.
+    pub(crate) expiry_index: bool,
+    pub(crate) root_index: bool,
+    /// The length in bits of the prefixes under which state usage is tracked, if it is.
+    pub(crate) state_usage_prefix_len: Option<u8>,
+    /// The number of commits whose state usage changes are retained.
+    pub(crate) state_usage_history: usize,
 }
 
 impl Options {
@@ -47,16 +160,293 @@ impl Options {
             bitbox_seed,
             panic_on_sync: None,
             rollback: false,
-            max_rollback_log_len: 100,
+            rollback_retention: RetentionPolicy::Commits(100),
             warm_up: false,
             preallocate_ht: true,
+            commit_strategy: CommitStrategy::Wal,
+            preallocate_values: true,
+            values_preallocate_size: 0,
+            values_growth_extent: 32,
+            discard_freed_pages: true,
+            value_gc_interval: None,
             page_cache_size: 256,
             leaf_cache_size: 256,
             prepopulate_page_cache: false,
             page_cache_upper_levels: 2,
+            backup_log: None,
+            page_access_sampling: 0,
+            page_prefetch_depth: 1,
+            commit_sink: None,
+            commit_signer: None,
+            node_preimage_hook: None,
+            read_only: false,
+            commit_limits: CommitLimits::default(),
+            commit_log_thresholds: CommitLogThresholds::default(),
+            verify_commits: false,
+            verify_values: false,
+            keyed_key_paths: false,
+            fencing_token: None,
+            io_retry_policy: RetryPolicy::default(),
+            io_queue_depth: DEFAULT_IO_QUEUE_DEPTH,
+            shared_io_pool: None,
+            deterministic_layout: false,
+            expiry_index: false,
+            root_index: false,
+            state_usage_prefix_len: None,
+            state_usage_history: 1024,
         }
     }
 
+    /// Create `Options` tuned for throughput on machines with plenty of cores and memory.
+    ///
+    /// Uses all available cores for committing, large caches and warm-ups, and prepopulates the
+    /// upper levels of the page cache on startup.
+    pub fn high_throughput() -> Self {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let mut o = Self::new();
+        o.commit_concurrency(cores.min(crate::MAX_COMMIT_CONCURRENCY));
+        o.io_workers(cores.clamp(3, 16));
+        o.page_cache_size(2048);
+        o.leaf_cache_size(1024);
+        o.warm_up(true);
+        o.prepopulate_page_cache(true);
+        o
+    }
+
+    /// Create `Options` tuned for a small memory footprint.
+    ///
+    /// Uses small caches, keeps only the first level of pages permanently cached and uses a
+    /// single commit worker.
+    pub fn low_memory() -> Self {
+        let mut o = Self::new();
+        o.commit_concurrency(1);
+        o.io_workers(1);
+        o.page_cache_size(32);
+        o.leaf_cache_size(32);
+        o.page_cache_upper_levels(1);
+        o
+    }
+
+    /// Create `Options` for nodes which need to retain a long history of commits.
+    ///
+    /// Enables rollback with a long rollback log.
+    pub fn archival() -> Self {
+        let mut o = Self::new();
+        o.rollback(true);
+        o.max_rollback_log_len(10_000);
+        o
+    }
+
+    /// Check that the options are consistent with each other.
+    ///
+    /// This is called when opening a database, but may be called earlier to detect
+    /// misconfiguration.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.commit_concurrency == 0 {
+            anyhow::bail!("commit concurrency must be greater than zero");
+        }
+        if self.io_workers == 0 {
+            anyhow::bail!("io workers must be greater than zero");
+        }
+        if self.io_queue_depth == 0 || self.io_queue_depth > MAX_IO_QUEUE_DEPTH {
+            anyhow::bail!(
+                "io queue depth ({}) must be between 1 and {}",
+                self.io_queue_depth,
+                MAX_IO_QUEUE_DEPTH,
+            );
+        }
+        if self.values_growth_extent == 0 || self.values_growth_extent > MAX_VALUES_GROWTH_EXTENT {
+            anyhow::bail!(
+                "values growth extent ({}MiB) must be between 1MiB and {}MiB",
+                self.values_growth_extent,
+                MAX_VALUES_GROWTH_EXTENT,
+            );
+        }
+        if self.values_preallocate_size > MAX_VALUES_PREALLOCATE_SIZE {
+            anyhow::bail!(
+                "values preallocate size ({}MiB) must be at most {}MiB",
+                self.values_preallocate_size,
+                MAX_VALUES_PREALLOCATE_SIZE,
+            );
+        }
+        if self.bitbox_num_pages == 0 {
+            anyhow::bail!("hashtable buckets must be greater than zero");
+        }
+        if self.page_cache_size == 0 {
+            anyhow::bail!("page cache size must be at least 1MiB");
+        }
+        if self.leaf_cache_size == 0 {
+            anyhow::bail!("leaf cache size must be at least 1MiB");
+        }
+        if self.page_cache_upper_levels > MAX_PAGE_CACHE_UPPER_LEVELS {
+            anyhow::bail!(
+                "page cache upper levels ({}) must be at most {}",
+                self.page_cache_upper_levels,
+                MAX_PAGE_CACHE_UPPER_LEVELS,
+            );
+        }
+        if self.rollback && self.rollback_retention == RetentionPolicy::Commits(0) {
+            anyhow::bail!(
+                "max rollback log length must be greater than zero when rollback is enabled"
+            );
+        }
+        if self
+            .shared_io_pool
+            .as_ref()
+            .is_some_and(|(_, weight)| *weight == 0)
+        {
+            anyhow::bail!("io pool weight must be greater than zero");
+        }
+        if self.read_only && (self.backup_log.is_some() || self.commit_sink.is_some()) {
+            anyhow::bail!("a backup log or commit sink cannot be used with a read-only database");
+        }
+        if self.deterministic_layout && self.shared_io_pool.is_some() {
+            anyhow::bail!("a deterministic layout cannot be used with a shared io pool");
+        }
+        if self.read_only && self.expiry_index {
+            anyhow::bail!("an expiry index cannot be used with a read-only database");
+        }
+        if self.read_only && self.root_index {
+            anyhow::bail!("a root index cannot be used with a read-only database");
+        }
+        if self.read_only && self.state_usage_prefix_len.is_some() {
+            anyhow::bail!("state usage cannot be tracked with a read-only database");
+        }
+        if self.state_usage_prefix_len.is_some_and(|len| len > 64) {
+            anyhow::bail!("state usage prefixes cannot be longer than 64 bits");
+        }
+        if self.read_only && self.fencing_token.is_some() {
+            anyhow::bail!("a fencing token cannot be used with a read-only database");
+        }
+        if self.read_only && self.value_gc_interval.is_some() {
+            anyhow::bail!("value store garbage cannot be collected in a read-only database");
+        }
+        if self.value_gc_interval == Some(Duration::ZERO) {
+            anyhow::bail!("value gc interval must be greater than zero");
+        }
+        Ok(())
+    }
+
+    /// Load `Options` from a TOML file, then apply overrides from the environment.
+    ///
+    /// The file contains top-level keys named after the setters of this type, e.g.
+    ///
+    /// ```toml
+    /// path = "/var/lib/nomt"
+    /// commit_concurrency = 8
+    /// page_cache_size = 1024
+    /// warm_up = true
+    /// ```
+    ///
+    /// Keys which are not present keep their default values. See
+    /// [`Options::apply_env_overrides`] for the environment variables which are consulted.
+    #[cfg(feature = "config")]
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+        let table: toml::Table = contents
+            .parse()
+            .map_err(|e| anyhow::anyhow!("failed to parse {}: {}", path.display(), e))?;
+
+        let mut o = Self::new();
+        for (key, value) in table {
+            let value = match value {
+                toml::Value::String(s) => s,
+                toml::Value::Integer(i) => i.to_string(),
+                toml::Value::Boolean(b) => b.to_string(),
+                other => anyhow::bail!("unsupported {} value for {}", other.type_str(), key),
+            };
+            o.set(&key, &value)?;
+        }
+        o.apply_env_overrides()?;
+        Ok(o)
+    }
+
+    /// Override options from environment variables.
+    ///
+    /// Every option which can be set from a configuration file can be overridden by the variable
+    /// of the same name, upper-cased and prefixed with `NOMT_`, e.g. `NOMT_PAGE_CACHE_SIZE=1024`.
+    pub fn apply_env_overrides(&mut self) -> anyhow::Result<()> {
+        for key in CONFIG_KEYS {
+            let var = format!("NOMT_{}", key.to_uppercase());
+            if let Ok(value) = std::env::var(&var) {
+                self.set(key, &value)
+                    .map_err(|e| anyhow::anyhow!("invalid value for {}: {}", var, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Set an option by its configuration key.
+    fn set(&mut self, key: &str, value: &str) -> anyhow::Result<()> {
+        fn parse<T: std::str::FromStr>(key: &str, value: &str) -> anyhow::Result<T> {
+            value
+                .parse()
+                .map_err(|_| anyhow::anyhow!("invalid value for {}: {:?}", key, value))
+        }
+
+        match key {
+            "path" => self.path = PathBuf::from(value),
+            "commit_concurrency" => self.commit_concurrency = parse(key, value)?,
+            "io_workers" => self.io_workers = parse(key, value)?,
+            "metrics" => self.metrics = parse(key, value)?,
+            "hashtable_buckets" => self.bitbox_num_pages = parse(key, value)?,
+            "rollback" => self.rollback = parse(key, value)?,
+            "max_rollback_log_len" => {
+                self.rollback_retention = RetentionPolicy::Commits(parse(key, value)?)
+            }
+            "rollback_retention_secs" => {
+                self.rollback_retention =
+                    RetentionPolicy::Duration(Duration::from_secs(parse(key, value)?))
+            }
+            "warm_up" => self.warm_up = parse(key, value)?,
+            "preallocate_ht" => self.preallocate_ht = parse(key, value)?,
+            "commit_strategy" => self.commit_strategy = parse(key, value)?,
+            "preallocate_values" => self.preallocate_values = parse(key, value)?,
+            "values_preallocate_size" => self.values_preallocate_size = parse(key, value)?,
+            "values_growth_extent" => self.values_growth_extent = parse(key, value)?,
+            "discard_freed_pages" => self.discard_freed_pages = parse(key, value)?,
+            "value_gc_interval_secs" => {
+                self.value_gc_interval = Some(Duration::from_secs(parse(key, value)?))
+            }
+            "page_cache_size" => self.page_cache_size = parse(key, value)?,
+            "leaf_cache_size" => self.leaf_cache_size = parse(key, value)?,
+            "prepopulate_page_cache" => self.prepopulate_page_cache = parse(key, value)?,
+            "page_cache_upper_levels" => self.page_cache_upper_levels = parse(key, value)?,
+            "backup_log" => self.backup_log = Some(PathBuf::from(value)),
+            "page_access_sampling" => self.page_access_sampling = parse(key, value)?,
+            "page_prefetch_depth" => self.page_prefetch_depth = parse(key, value)?,
+            "read_only" => self.read_only = parse(key, value)?,
+            "max_commit_keys" => self.commit_limits.max_keys = Some(parse(key, value)?),
+            "max_commit_pages" => self.commit_limits.max_pages = Some(parse(key, value)?),
+            "commit_log_keys" => self.commit_log_thresholds.keys = Some(parse(key, value)?),
+            "commit_log_pages" => self.commit_log_thresholds.pages = Some(parse(key, value)?),
+            "commit_log_duration_millis" => {
+                self.commit_log_thresholds.duration =
+                    Some(Duration::from_millis(parse(key, value)?))
+            }
+            "verify_commits" => self.verify_commits = parse(key, value)?,
+            "verify_values" => self.verify_values = parse(key, value)?,
+            "keyed_key_paths" => self.keyed_key_paths = parse(key, value)?,
+            "io_max_retries" => self.io_retry_policy.max_retries = parse(key, value)?,
+            "io_retry_backoff_micros" => {
+                self.io_retry_policy.backoff = Duration::from_micros(parse(key, value)?)
+            }
+            "io_queue_depth" => self.io_queue_depth = parse(key, value)?,
+            "fencing_token" => self.fencing_token = Some(parse(key, value)?),
+            "deterministic_layout" => self.deterministic_layout = parse(key, value)?,
+            "expiry_index" => self.expiry_index = parse(key, value)?,
+            "root_index" => self.root_index = parse(key, value)?,
+            "state_usage_prefix_len" => self.state_usage_prefix_len = Some(parse(key, value)?),
+            "state_usage_history" => self.state_usage_history = parse(key, value)?,
+            "max_commit_value_bytes" => {
+                self.commit_limits.max_value_bytes = Some(parse(key, value)?)
+            }
+            _ => anyhow::bail!("unknown option: {}", key),
+        }
+        Ok(())
+    }
+
     /// Set the path to the directory where the trie is stored.
     pub fn path(&mut self, path: impl Into<PathBuf>) {
         self.path = path.into();
@@ -86,6 +476,54 @@ impl Options {
         self.io_workers = io_workers;
     }
 
+    /// Set how I/O commands failing with a transient error are retried.
+    ///
+    /// Reads and writes interrupted by a signal, failing with `EAGAIN` or transferring less than
+    /// a page are retried with an exponential backoff, up to a number of times per command.
+    /// Then the error is surfaced. Retries are counted in the metrics.
+    ///
+    /// Default: up to 16 retries, starting with a backoff of 100µs.
+    pub fn io_retry_policy(&mut self, policy: RetryPolicy) {
+        self.io_retry_policy = policy;
+    }
+
+    /// Set the most I/O commands each I/O worker keeps in flight at once.
+    ///
+    /// Further commands are queued until earlier ones complete. Once an instance has as many
+    /// commands queued or in flight as all the workers can keep in flight, it is under
+    /// backpressure: speculative page loads are skipped until the queue drains. Commands
+    /// submitted under backpressure are counted in the metrics.
+    ///
+    /// Must be between 1 and 32768. Default: 1024.
+    pub fn io_queue_depth(&mut self, io_queue_depth: usize) {
+        self.io_queue_depth = io_queue_depth;
+    }
+
+    /// Use an I/O pool shared with other instances in the process instead of starting one.
+    ///
+    /// The instances sharing the pool are served in proportion to their weights: while several
+    /// instances have I/O queued, one with weight 2 gets twice as many commands issued as one with
+    /// weight 1. [`Self::io_workers`], [`Self::io_retry_policy`] and [`Self::io_queue_depth`] are
+    /// ignored, the pool's own are used.
+    ///
+    /// Must be more than 0. Default: none, a pool is started for the instance.
+    pub fn io_pool(&mut self, pool: SharedIoPool, weight: u32) {
+        self.shared_io_pool = Some((pool, weight));
+    }
+
+    /// Make the placement of pages on disk a function of the commits applied only.
+    ///
+    /// Two databases created with the same [`Self::hashtable_buckets`] and [`Self::bitbox_seed`]
+    /// and applying the same sequence of commits then have byte-identical store files, whatever
+    /// the number of commit workers. Hashtable buckets are allocated in page ID order, the value
+    /// store is updated by a single worker and new pages are zeroed before use. The layout
+    /// produced with this option is stable across versions.
+    ///
+    /// Cannot be combined with [`Self::io_pool`]. Default: false.
+    pub fn deterministic_layout(&mut self, deterministic_layout: bool) {
+        self.deterministic_layout = deterministic_layout;
+    }
+
     /// Set the number of hashtable buckets to use when creating the database.
     pub fn hashtable_buckets(&mut self, hashtable_buckets: u32) {
         self.bitbox_num_pages = hashtable_buckets;
@@ -110,13 +548,196 @@ impl Options {
         self.rollback = rollback;
     }
 
+    /// Set the path of a log to which the value changes of every commit are appended.
+    ///
+    /// Together with a snapshot taken with [`crate::Nomt::export`], the log allows restoring the
+    /// database to any later commit with [`crate::Nomt::restore`]. The log is created if it
+    /// doesn't exist.
+    ///
+    /// Default: none.
+    pub fn backup_log(&mut self, path: impl Into<PathBuf>) {
+        self.backup_log = Some(path.into());
+    }
+
+    /// Set to `true` to open an existing database for reading only.
+    ///
+    /// Any number of processes may open the same database read-only at the same time, for example
+    /// to serve queries from sandboxed workers. They all see the root the database had when it
+    /// was opened. A database opened read-only can't be opened for writing, and vice versa, until
+    /// it is closed: the writer updates the files in place. Commits and rollbacks fail, and the
+    /// database must not need recovery from a crash.
+    ///
+    /// Default: false.
+    pub fn read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Set limits on the resources used by a single commit, such as the number of changed keys.
+    ///
+    /// A commit exceeding a limit fails with [`crate::CommitLimitExceeded`] before anything is
+    /// written, which lets blockchains enforce block resource limits at the state layer.
+    ///
+    /// Default: unlimited.
+    pub fn commit_limits(&mut self, limits: CommitLimits) {
+        self.commit_limits = limits;
+    }
+
+    /// Set the thresholds above which a commit is logged.
+    ///
+    /// A commit changing more keys, writing more pages or taking longer than any of the thresholds
+    /// is summarized in a structured record emitted at the warn level with the `nomt::commit`
+    /// target through the [`tracing`] facade. The record holds the sizes of the commit, the time
+    /// spent in each of its phases and the subtrees it wrote the most pages to.
+    ///
+    /// Default: no commit is logged.
+    pub fn commit_log_thresholds(&mut self, thresholds: CommitLogThresholds) {
+        self.commit_log_thresholds = thresholds;
+    }
+
+    /// Set to `true` to verify the pages written by every commit before they are written.
+    ///
+    /// Every changed node is re-derived from its children and the root from the root page,
+    /// independently of the merkle update which produced them, and the pages the update created
+    /// without looking them up are checked not to exist yet. A commit failing verification is
+    /// aborted. This guards against silent corruption on canary nodes at the cost of hashing
+    /// every changed node again and possibly loading some child pages.
+    ///
+    /// Default: false.
+    pub fn verify_commits(&mut self, verify_commits: bool) {
+        self.verify_commits = verify_commits;
+    }
+
+    /// Set to `true` to verify every value read from the value store against the trie.
+    ///
+    /// The value is hashed into the leaf of its key and compared with the trie, and a mismatch
+    /// fails the read with a [`crate::ValueCorruption`]. This costs hashing the value and
+    /// following the path of the key down the trie on every read, so it can also be requested for
+    /// single reads with `read_verified` instead. Values read with `read_stream` and values
+    /// changed by an overlay are not verified.
+    ///
+    /// Default: false.
+    pub fn verify_values(&mut self, verify_values: bool) {
+        self.verify_values = verify_values;
+    }
+
+    /// Set to `true` to create the database with a random secret for deriving key paths.
+    ///
+    /// [`crate::Nomt::key_path`] then derives key paths with a hash keyed by the secret, so that
+    /// nobody without access to the database files can choose keys whose paths share long
+    /// prefixes and deepen the trie. The trie commits to the derived key paths as usual, so
+    /// proofs stay valid; a verifier is given the key path along with the proof.
+    ///
+    /// Only has an effect when the database is created. The secret is kept alongside the other
+    /// files and must be backed up with them.
+    ///
+    /// Default: false.
+    pub fn keyed_key_paths(&mut self, keyed_key_paths: bool) {
+        self.keyed_key_paths = keyed_key_paths;
+    }
+
+    /// Set to `true` to index keys by the sequence number of the commit which last wrote them.
+    ///
+    /// [`crate::Nomt::expired_keys`] then enumerates the keys not written since a given commit,
+    /// least recently written first, without scanning the trie, e.g. to charge state rent. The
+    /// index is kept in memory and persisted in the database directory. Enabling it on an existing
+    /// database records all keys as written by the last commit.
+    ///
+    /// Default: false.
+    pub fn expiry_index(&mut self, expiry_index: bool) {
+        self.expiry_index = expiry_index;
+    }
+
+    /// Set to `true` to index the roots produced by commits.
+    ///
+    /// [`crate::Nomt::lookup_root`] then resolves a root to the sequence number, previous root and
+    /// identifier of the last commit which produced it, e.g. to decide how far to roll back. The
+    /// index is kept in memory and persisted in the database directory, growing by one record per
+    /// commit. Enabling it on an existing database records only the current root.
+    ///
+    /// Default: false.
+    pub fn root_index(&mut self, root_index: bool) {
+        self.root_index = root_index;
+    }
+
+    /// Track the number of keys and value bytes under every prefix of the given length in bits.
+    ///
+    /// [`crate::Nomt::state_usage`] then returns the usage under any prefix up to that length and
+    /// [`crate::Nomt::state_usage_since`] what recent commits added and removed under it, e.g. to
+    /// charge rent or enforce per-account quotas. Every commit looks up the prior values of the
+    /// keys it writes. Enabling tracking on an existing database, or changing the length, scans
+    /// the entire database.
+    ///
+    /// Must be at most 64. Default: none, state usage is not tracked.
+    pub fn state_usage(&mut self, prefix_len: u8) {
+        self.state_usage_prefix_len = Some(prefix_len);
+    }
+
+    /// Set the number of recent commits whose state usage changes are retained for
+    /// [`crate::Nomt::state_usage_since`].
+    ///
+    /// Default: 1024.
+    pub fn state_usage_history(&mut self, commits: usize) {
+        self.state_usage_history = commits;
+    }
+
+    /// Set the fencing token of this writer, typically the epoch of its lease issued by the
+    /// coordinator of a failover.
+    ///
+    /// Every writer opening the database stores its token in the database directory, and checks
+    /// before each sync that the stored token is still its own. A primary which resumes after a
+    /// new primary took over, for example after a network partition, thus fails to commit
+    /// instead of overwriting the new primary's changes. Opening fails if the token is less than
+    /// the one of the previous writer. [`crate::Nomt::fencing_token`] returns the token in use.
+    ///
+    /// Default: none, in which case the writer takes the token after the previous writer's.
+    pub fn fencing_token(&mut self, fencing_token: u64) {
+        self.fencing_token = Some(fencing_token);
+    }
+
+    /// Set a sink which the page and value changes of every commit are written to, before they
+    /// are applied to the database. A commit is aborted if the sink fails to write its changes.
+    ///
+    /// Default: none.
+    pub fn commit_sink(&mut self, sink: Arc<dyn CommitSink>) {
+        self.commit_sink = Some(sink);
+    }
+
+    /// Set a signer which signs the root of every commit. The signature is stored along with the
+    /// root and given to the commit sink, and the signature of the last commit is returned by
+    /// [`crate::Nomt::root_signature`]. A commit is aborted if the signer fails.
+    ///
+    /// Default: none.
+    pub fn commit_signer(&mut self, signer: Arc<dyn CommitSigner>) {
+        self.commit_signer = Some(signer);
+    }
+
+    /// Set a hook which is called with every node hashed while committing, along with the
+    /// preimage of the node. This allows external systems to keep a database of trie nodes or
+    /// to feed the hashing work to a prover. See [`NodePreimageHook`].
+    ///
+    /// Default: none.
+    pub fn node_preimage_hook(&mut self, hook: Arc<dyn NodePreimageHook>) {
+        self.node_preimage_hook = Some(hook);
+    }
+
     /// Set the maximum number of commits that can be rolled back.
     ///
+    /// This is equivalent to setting the [`RetentionPolicy::Commits`] retention policy.
     /// Only relevant if rollback is enabled.
     ///
     /// Default: 100.
     pub fn max_rollback_log_len(&mut self, max_rollback_log_len: u32) {
-        self.max_rollback_log_len = max_rollback_log_len;
+        self.rollback_retention = RetentionPolicy::Commits(max_rollback_log_len);
+    }
+
+    /// Set the policy determining which commits can be rolled back.
+    ///
+    /// Deltas of older commits are pruned after each commit, unless they are needed to roll back
+    /// to a root pinned with [`crate::Nomt::pin_root`]. Only relevant if rollback is enabled.
+    ///
+    /// Default: `RetentionPolicy::Commits(100)`.
+    pub fn rollback_retention(&mut self, rollback_retention: RetentionPolicy) {
+        self.rollback_retention = rollback_retention;
     }
 
     /// Configure whether merkle page fetches should be warmed up while sessions are ongoing.
@@ -139,6 +760,75 @@ impl Options {
         self.preallocate_ht = preallocate_ht;
     }
 
+    /// Sets how the changes a commit makes to the hashtable are made durable.
+    ///
+    /// See [`CommitStrategy`] for the trade-offs. Only has an effect when the database is created;
+    /// an existing database keeps the strategy it was created with.
+    ///
+    /// Default: [`CommitStrategy::Wal`].
+    pub fn commit_strategy(&mut self, commit_strategy: CommitStrategy) {
+        self.commit_strategy = commit_strategy;
+    }
+
+    /// Sets whether to allocate the disk space of the value store files up front.
+    ///
+    /// The files of the value store are extended in large extents as they fill up, see
+    /// [`Self::values_growth_extent`]. If set to `true`, NOMT tries to allocate the blocks of every
+    /// extent as well, so that filesystems like ext4 and XFS lay them out contiguously instead of
+    /// allocating blocks page by page as they are written.
+    ///
+    /// Default: `true`.
+    pub fn preallocate_values(&mut self, preallocate_values: bool) {
+        self.preallocate_values = preallocate_values;
+    }
+
+    /// Sets the size in MiB the leaf file of the value store is extended to when opening the
+    /// database, which holds almost all of the values.
+    ///
+    /// Setting this to the expected size of the store avoids growing the file while state grows
+    /// rapidly. A file which is already larger is left as it is.
+    ///
+    /// Must be at most 8TiB. Default: 0, the file is only extended as it fills up.
+    pub fn values_preallocate_size(&mut self, values_preallocate_size: usize) {
+        self.values_preallocate_size = values_preallocate_size;
+    }
+
+    /// Sets the size in MiB by which the files of the value store are extended once full.
+    ///
+    /// Must be between 1MiB and 4GiB. Default: 32MiB.
+    pub fn values_growth_extent(&mut self, values_growth_extent: usize) {
+        self.values_growth_extent = values_growth_extent;
+    }
+
+    /// Sets whether to return the space of freed pages of the value store to the filesystem.
+    ///
+    /// Pages freed by a commit are kept for reuse by later commits. If set to `true`, NOMT punches
+    /// holes into the files where they are, once they can no longer be read, so that the
+    /// filesystem releases their blocks and the device learns they are unused through TRIM. This
+    /// keeps the space used on disk close to the size of the live data after large deletions.
+    /// Freed pages are reused all the same, at the cost of allocating their blocks again.
+    ///
+    /// Set to `false` on devices where discarding is slow. Has no effect where the filesystem
+    /// doesn't support punching holes.
+    ///
+    /// Default: `true`.
+    pub fn discard_freed_pages(&mut self, discard_freed_pages: bool) {
+        self.discard_freed_pages = discard_freed_pages;
+    }
+
+    /// Sets how often the value store is scanned for orphaned pages in the background.
+    ///
+    /// An orphaned page is neither reachable from the value store nor free, so its space is lost
+    /// to reuse. Every scan returns the orphaned pages it finds to the free-list with the next
+    /// commit, and its statistics are kept for [`crate::Nomt::value_gc_stats`]. A scan reads
+    /// every leaf of the value store and blocks commits from syncing until it is done. Scans can
+    /// also be run on demand with [`crate::Nomt::collect_value_garbage`].
+    ///
+    /// Default: `None`, no background scans.
+    pub fn value_gc_interval(&mut self, value_gc_interval: Option<Duration>) {
+        self.value_gc_interval = value_gc_interval;
+    }
+
     /// Sets the size of the page cache in MiB.
     ///
     /// This does not count the memory used by the upper levels of the page
@@ -172,12 +862,35 @@ impl Options {
     /// cached.
     ///
     /// Each level adds 64x the RAM burden of the previous.
-    /// Level 1 uses ≈256KiB, level 2 ≈16MiB, level 3 ≈1GiB.
+    /// Level 1 uses ≈256KiB, level 2 ≈16MiB, level 3 ≈1GiB. At most 3 levels are supported.
     ///
     /// Default: 2
     pub fn page_cache_upper_levels(&mut self, upper_levels: usize) {
         self.page_cache_upper_levels = upper_levels;
     }
+
+    /// Sets the rate at which page cache accesses are sampled for the access report, see
+    /// [`crate::Nomt::page_access_report`]. One in every `rate` accesses is sampled.
+    ///
+    /// Sampling adds a little overhead to every page lookup.
+    ///
+    /// Default: 0, which disables sampling.
+    pub fn page_access_sampling(&mut self, rate: u32) {
+        self.page_access_sampling = rate;
+    }
+
+    /// Sets how many levels of pages below a page being fetched from disk are fetched
+    /// speculatively, along the path of the key being looked up.
+    ///
+    /// Fetching a page normally requires its parent page to be loaded first. Speculative fetches
+    /// overlap these loads, which hides disk latency when few pages are cached, at the cost of
+    /// some wasted I/O for pages which turn out not to be needed.
+    /// Setting this to 0 disables speculative fetches.
+    ///
+    /// Default: 1
+    pub fn page_prefetch_depth(&mut self, depth: usize) {
+        self.page_prefetch_depth = depth;
+    }
 }
 
 #[test]
@@ -186,6 +899,109 @@ fn page_size_is_4096() {
     assert_eq!(crate::io::PAGE_SIZE, 4096);
 }
 
+#[test]
+fn set_by_config_key() {
+    let mut o = Options::new();
+    o.set("page_cache_size", "1024").unwrap();
+    o.set("warm_up", "true").unwrap();
+    o.set("path", "/tmp/nomt").unwrap();
+    o.set("commit_strategy", "shadow_paging").unwrap();
+    assert_eq!(o.page_cache_size, 1024);
+    assert!(o.warm_up);
+    assert_eq!(o.commit_strategy, CommitStrategy::ShadowPaging);
+    assert_eq!(o.path, PathBuf::from("/tmp/nomt"));
+
+    assert!(o.set("page_cache_size", "lots").is_err());
+    assert!(o.set("no_such_option", "1").is_err());
+
+    for key in CONFIG_KEYS {
+        // every documented key must be settable.
+        if let Err(e) = o.set(key, "") {
+            assert!(!e.to_string().starts_with("unknown option"), "{}", key);
+        }
+    }
+}
+
+#[test]
+fn presets_are_valid() {
+    Options::new().validate().unwrap();
+    Options::high_throughput().validate().unwrap();
+    Options::low_memory().validate().unwrap();
+    Options::archival().validate().unwrap();
+}
+
+#[test]
+fn validate_rejects_inconsistent_options() {
+    let mut o = Options::new();
+    o.commit_concurrency(0);
+    assert!(o.validate().is_err());
+
+    let mut o = Options::new();
+    o.page_cache_size(0);
+    assert!(o.validate().is_err());
+
+    let mut o = Options::new();
+    o.page_cache_upper_levels(4);
+    assert!(o.validate().is_err());
+
+    let mut o = Options::new();
+    o.rollback(true);
+    o.max_rollback_log_len(0);
+    assert!(o.validate().is_err());
+
+    let mut o = Options::new();
+    o.read_only(true);
+    o.backup_log("/tmp/nomt-backup");
+    assert!(o.validate().is_err());
+
+    let mut o = Options::new();
+    o.value_gc_interval(Some(Duration::ZERO));
+    assert!(o.validate().is_err());
+}
+
+/// A policy determining which commits are retained in the rollback log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionPolicy {
+    /// Keep the given number of most recent commits.
+    Commits(u32),
+    /// Keep the commits made within the given duration.
+    ///
+    /// The number of retained commits is unbounded. Commits logged by versions which did not
+    /// record commit times are considered to be made when the database is opened.
+    Duration(Duration),
+}
+
+/// How the changes a commit makes to the hashtable are made durable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitStrategy {
+    /// Write the changes to a write-ahead log, then update the hashtable pages in place.
+    ///
+    /// Only the changed nodes of every page are logged, but every changed page is written twice
+    /// and the log must be replayed when opening the database after a crash.
+    Wal,
+    /// Never overwrite the pages of the last commit: every bucket of the hashtable has two slots
+    /// and a changed page is written to the one not in use, along with a second copy of the
+    /// bucket metadata. Writing the metadata of the commit then switches to the new pages
+    /// atomically.
+    ///
+    /// Every changed page is written once and there is nothing to recover after a crash, but
+    /// the hashtable file takes twice the space and the pages of a bucket alternate between two
+    /// locations.
+    ShadowPaging,
+}
+
+impl std::str::FromStr for CommitStrategy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "wal" => Ok(CommitStrategy::Wal),
+            "shadow_paging" => Ok(CommitStrategy::ShadowPaging),
+            _ => Err(()),
+        }
+    }
+}
+
 /// Modes for panicking during sync.
 #[derive(Clone, Copy)]
 pub enum PanicOnSyncMode {
@@ -70,6 +70,11 @@ impl Overlay {
         self.inner.rollback_delta.as_ref()
     }
 
+    /// Get the hashes of the leaves appended to the Merkle mountain range by this overlay.
+    pub(super) fn mmr_leaves(&self) -> &[Node] {
+        &self.inner.mmr_leaves
+    }
+
     /// Mark the overlay as committed and return a marker.
     pub(super) fn mark_committed(&self) -> OverlayMarker {
         let status = self.inner.data.status.clone();
@@ -87,6 +92,7 @@ struct OverlayInner {
     // ordered by recency.
     ancestor_data: Vec<Weak<Data>>,
     rollback_delta: Option<crate::rollback::Delta>,
+    mmr_leaves: Vec<Node>,
 }
 
 /// A marker indicating the overlay uniquely, until dropped. Used to enforce commit order.
@@ -373,6 +379,7 @@ impl LiveOverlay {
         page_changes: HashMap<PageId, DirtyPage>,
         value_changes: HashMap<KeyPath, ValueChange>,
         rollback_delta: Option<crate::rollback::Delta>,
+        mmr_leaves: Vec<Node>,
     ) -> Overlay {
         let new_seqn = self.parent.as_ref().map_or(0, |p| p.seqn + 1);
 
@@ -410,6 +417,7 @@ impl LiveOverlay {
                 seqn: new_seqn,
                 ancestor_data,
                 rollback_delta,
+                mmr_leaves,
             }),
         }
     }
@@ -462,6 +470,7 @@ mod tests {
             HashMap::new(),
             HashMap::new(),
             None,
+            Vec::new(),
         );
         let a1 = LiveOverlay::new(None).unwrap().finish(
             [1; 32],
@@ -469,6 +478,7 @@ mod tests {
             HashMap::new(),
             HashMap::new(),
             None,
+            Vec::new(),
         );
 
         let mut ancestors = VecDeque::new();
@@ -479,6 +489,7 @@ mod tests {
             HashMap::new(),
             HashMap::new(),
             None,
+            Vec::new(),
         );
         ancestors.push_front(b);
 
@@ -497,6 +508,7 @@ mod tests {
             HashMap::new(),
             HashMap::new(),
             None,
+            Vec::new(),
         );
 
         let mut ancestors = VecDeque::new();
@@ -507,6 +519,7 @@ mod tests {
             HashMap::new(),
             HashMap::new(),
             None,
+            Vec::new(),
         );
         ancestors.push_front(b);
         let c = LiveOverlay::new(&ancestors).unwrap().finish(
@@ -515,6 +528,7 @@ mod tests {
             HashMap::new(),
             HashMap::new(),
             None,
+            Vec::new(),
         );
         ancestors.push_front(c);
 
@@ -546,6 +560,7 @@ mod tests {
             HashMap::new(),
             HashMap::new(),
             None,
+            Vec::new(),
         );
 
         let mut ancestors = VecDeque::new();
@@ -556,6 +571,7 @@ mod tests {
             HashMap::new(),
             HashMap::new(),
             None,
+            Vec::new(),
         );
 
         drop(ancestors);
@@ -571,6 +587,7 @@ mod tests {
             HashMap::new(),
             HashMap::new(),
             None,
+            Vec::new(),
         );
 
         let mut ancestors = VecDeque::new();
@@ -581,6 +598,7 @@ mod tests {
             HashMap::new(),
             HashMap::new(),
             None,
+            Vec::new(),
         );
         ancestors[0].inner.data.status.commit();
         drop(ancestors);
@@ -597,6 +615,7 @@ mod tests {
             HashMap::new(),
             HashMap::new(),
             None,
+            Vec::new(),
         );
 
         let mut ancestors = VecDeque::new();
@@ -607,6 +626,7 @@ mod tests {
             HashMap::new(),
             HashMap::new(),
             None,
+            Vec::new(),
         );
         ancestors.push_front(b);
         let c = LiveOverlay::new(&ancestors).unwrap().finish(
@@ -615,6 +635,7 @@ mod tests {
             HashMap::new(),
             HashMap::new(),
             None,
+            Vec::new(),
         );
         ancestors.push_front(c);
 
@@ -646,15 +667,25 @@ mod tests {
 
         let page_map = vec![(ROOT_PAGE_ID, page1a)].into_iter().collect();
         let value_map = vec![(key1, value1a)].into_iter().collect();
-        let a = LiveOverlay::new(None)
-            .unwrap()
-            .finish([0; 32], [1; 32], page_map, value_map, None);
+        let a = LiveOverlay::new(None).unwrap().finish(
+            [0; 32],
+            [1; 32],
+            page_map,
+            value_map,
+            None,
+            Vec::new(),
+        );
 
         let page_map = vec![(ROOT_PAGE_ID, page1b)].into_iter().collect();
         let value_map = vec![(key1, value1b)].into_iter().collect();
-        let b = LiveOverlay::new(Some(&a))
-            .unwrap()
-            .finish([1; 32], [2; 32], page_map, value_map, None);
+        let b = LiveOverlay::new(Some(&a)).unwrap().finish(
+            [1; 32],
+            [2; 32],
+            page_map,
+            value_map,
+            None,
+            Vec::new(),
+        );
 
         let c = LiveOverlay::new([&b, &a]).unwrap();
 
@@ -712,9 +743,14 @@ mod tests {
         for ((page_id, page), (key, value)) in pages.into_iter().zip(values) {
             let page_map = [(page_id, page)].into_iter().collect();
             let value_map = [(key, value)].into_iter().collect();
-            let overlay = LiveOverlay::new(&ancestors)
-                .unwrap()
-                .finish([0; 32], [1; 32], page_map, value_map, None);
+            let overlay = LiveOverlay::new(&ancestors).unwrap().finish(
+                [0; 32],
+                [1; 32],
+                page_map,
+                value_map,
+                None,
+                Vec::new(),
+            );
             ancestors.push_front(overlay);
         }
 
@@ -759,6 +795,7 @@ mod tests {
             vec![(ROOT_PAGE_ID, page)].into_iter().collect(),
             HashMap::new(),
             None,
+            Vec::new(),
         );
         let b = LiveOverlay::new([&a]).unwrap().finish(
             [1; 32],
@@ -766,6 +803,7 @@ mod tests {
             vec![(ROOT_PAGE_ID, page2)].into_iter().collect(),
             HashMap::new(),
             None,
+            Vec::new(),
         );
         a.mark_committed();
 
@@ -820,15 +858,25 @@ mod tests {
         let value_map = vec![(key_1, val_1.clone()), (key_2, val_2.clone())]
             .into_iter()
             .collect();
-        let a = LiveOverlay::new(None)
-            .unwrap()
-            .finish([0; 32], [1; 32], page_map, value_map, None);
+        let a = LiveOverlay::new(None).unwrap().finish(
+            [0; 32],
+            [1; 32],
+            page_map,
+            value_map,
+            None,
+            Vec::new(),
+        );
 
         let page_map = vec![(page_id_2.clone(), page_2b)].into_iter().collect();
         let value_map = vec![(key_2, val_2b.clone())].into_iter().collect();
-        let b = LiveOverlay::new([&a])
-            .unwrap()
-            .finish([0; 32], [1; 32], page_map, value_map, None);
+        let b = LiveOverlay::new([&a]).unwrap().finish(
+            [0; 32],
+            [1; 32],
+            page_map,
+            value_map,
+            None,
+            Vec::new(),
+        );
 
         a.mark_committed();
 
@@ -838,6 +886,7 @@ mod tests {
             HashMap::new(),
             HashMap::new(),
             None,
+            Vec::new(),
         );
 
         // ensure everything from seqn 0 has been pruned.
@@ -2,10 +2,13 @@ use crate::{
     bitbox::BucketIndex,
     io::{page_pool::FatPage, PagePool, PAGE_SIZE},
     metrics::{Metric, Metrics},
+    page_header,
+    page_heatmap::{PageAccessReport, PageHeatmap},
     page_region::PageRegion,
     rw_pass_cell::{Region, RegionContains, RwPassDomain, WritePass},
     Options,
 };
+use crossbeam::epoch::{self, Atomic, Guard, Owned, Shared as EpochShared};
 use fxhash::FxBuildHasher;
 use lru::LruCache;
 use nomt_core::{
@@ -13,8 +16,13 @@ use nomt_core::{
     page_id::{ChildPageIndex, PageId, NUM_CHILDREN, ROOT_PAGE_ID},
     trie::Node,
 };
-use parking_lot::{Mutex, RwLock};
-use std::{collections::HashMap, fmt, num::NonZeroUsize, sync::Arc};
+use parking_lot::{Mutex, MutexGuard};
+use std::{
+    collections::HashMap,
+    fmt,
+    num::NonZeroUsize,
+    sync::{atomic::Ordering, Arc},
+};
 
 // Total number of nodes stored in one Page. It depends on the `DEPTH`
 // of the rootless sub-binary tree stored in a page following this formula:
@@ -73,10 +81,20 @@ impl PageMut {
         read_node(&self.inner, index)
     }
 
+    /// Write the page header, covering the current contents of the page. See [`page_header`].
+    pub fn stamp_header(&mut self) {
+        page_header::stamp(&mut self.inner);
+    }
+
     /// Write the node at the given index.
     pub fn set_node(&mut self, index: usize, node: Node) {
         set_node(&mut self.inner, index, node)
     }
+
+    /// Get a reference to the underlying page data.
+    pub fn page_data(&self) -> &FatPage {
+        &self.inner
+    }
 }
 
 impl From<FatPage> for PageMut {
@@ -123,6 +141,7 @@ impl fmt::Debug for Page {
     }
 }
 
+#[derive(Clone)]
 struct CacheEntry {
     page_data: Arc<FatPage>,
     bucket_index: BucketIndex,
@@ -135,74 +154,129 @@ impl CacheEntry {
             bucket_index,
         }
     }
+
+    fn to_page(&self) -> (Page, BucketIndex) {
+        (
+            Page {
+                inner: self.page_data.clone(),
+            },
+            self.bucket_index,
+        )
+    }
 }
 
+// Storage for pages in the levels of the tree which we always cache.
+//
+// This is a persistent map, so publishing a modified copy is cheap.
+type FixedLevelCache = imbl::HashMap<PageId, CacheEntry>;
+
 // Each shard has its own domain and handles a sub-tree of the page tree, defined by a
 // continuous set of children of the root page.
 struct CacheShard {
     region: PageRegion,
     locked: Mutex<CacheShardLocked>,
+    // The fixed levels are traversed by every lookup but change only during commits. They are
+    // published with epoch-based reclamation so lookups never block on the shard lock.
+    //
+    // Never null. Only swapped while `locked` is held.
+    fixed_level_cache: Atomic<FixedLevelCache>,
     page_limit: NonZeroUsize,
 }
 
+impl CacheShard {
+    fn get_fixed(&self, page_id: &PageId) -> Option<CacheEntry> {
+        let guard = epoch::pin();
+        let fixed = self.fixed_level_cache.load(Ordering::Acquire, &guard);
+        // SAFETY: the pointer is never null and is only destroyed once all guards which might
+        // have observed it are dropped.
+        unsafe { fixed.deref() }.get(page_id).cloned()
+    }
+
+    // Publish a modified copy of the fixed level cache.
+    //
+    // Requires the shard lock to be held, which serializes all writers.
+    fn update_fixed<R>(
+        &self,
+        _locked: &mut CacheShardLocked,
+        f: impl FnOnce(&mut FixedLevelCache) -> R,
+    ) -> R {
+        let guard = epoch::pin();
+        let current = self.fixed_level_cache.load(Ordering::Acquire, &guard);
+        // SAFETY: see `get_fixed`.
+        let mut next = unsafe { current.deref() }.clone();
+        let res = f(&mut next);
+        publish(&self.fixed_level_cache, Owned::new(next), &guard);
+        res
+    }
+}
+
+impl Drop for CacheShard {
+    fn drop(&mut self) {
+        // SAFETY: we have exclusive access, so no other thread can observe the pointer.
+        unsafe { drop_atomic(&self.fixed_level_cache) }
+    }
+}
+
 struct CacheShardLocked {
-    // storage for pages in the levels of the tree which we always cache.
-    fixed_level_cache: HashMap<PageId, CacheEntry, FxBuildHasher>,
     cached: LruCache<PageId, CacheEntry, FxBuildHasher>,
+    // Pages which must not be evicted, with the number of times they were pinned.
+    pinned: HashMap<PageId, usize, FxBuildHasher>,
 }
 
 impl CacheShardLocked {
-    fn get(&mut self, fixed_levels: usize, page_id: &PageId) -> Option<&CacheEntry> {
-        if page_id.depth() <= fixed_levels {
-            self.fixed_level_cache.get(page_id)
-        } else {
-            self.cached.get(page_id)
-        }
-    }
-
-    fn get_or_insert(
-        &mut self,
-        fixed_levels: usize,
-        page_id: PageId,
-        entry: impl FnOnce() -> CacheEntry,
-    ) -> &CacheEntry {
-        if page_id.depth() <= fixed_levels {
-            &*self.fixed_level_cache.entry(page_id).or_insert_with(entry)
-        } else {
-            self.cached.get_or_insert(page_id, entry)
+    fn evict(&mut self, limit: usize) {
+        // preserve everything in the fixed level cache, removing only the variable cache.
+        //
+        // pinned pages count against the limit, but are kept.
+        let mut kept = Vec::new();
+        while self.cached.len() + kept.len() > limit {
+            let Some((page_id, entry)) = self.cached.pop_lru() else {
+                break;
+            };
+            if self.pinned.contains_key(&page_id) {
+                kept.push((page_id, entry));
+            }
         }
-    }
-
-    fn insert(&mut self, fixed_levels: usize, page_id: PageId, entry: CacheEntry) {
-        if page_id.depth() <= fixed_levels {
-            self.fixed_level_cache.insert(page_id, entry);
-        } else {
+        for (page_id, entry) in kept {
             self.cached.put(page_id, entry);
         }
     }
-
-    fn remove(&mut self, fixed_levels: usize, page_id: &PageId) {
-        if page_id.depth() <= fixed_levels {
-            self.fixed_level_cache.remove(page_id);
-        } else {
-            self.cached.pop(page_id);
-        }
-    }
-
-    fn evict(&mut self, limit: NonZeroUsize) {
-        // preserve everything in the fixed level cache, removing only the variable cache.
-        while self.cached.len() > limit.get() {
-            let _ = self.cached.pop_lru();
-        }
-    }
 }
 
 struct Shared {
     shards: Vec<CacheShard>,
-    root_page: RwLock<Option<CacheEntry>>,
+    // Null if there is no root page. Published with epoch-based reclamation, like the fixed
+    // levels.
+    root_page: Atomic<CacheEntry>,
     page_rw_pass_domain: RwPassDomain,
     fixed_levels: usize,
     metrics: Metrics,
+    heatmap: Option<PageHeatmap>,
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        // SAFETY: we have exclusive access, so no other thread can observe the pointer.
+        unsafe { drop_atomic(&self.root_page) }
+    }
+}
+
+// Swap in a new value and defer destruction of the old one until no reader can observe it.
+fn publish<T>(atomic: &Atomic<T>, next: impl epoch::Pointer<T>, guard: &Guard) {
+    let prev = atomic.swap(next, Ordering::AcqRel, guard);
+    if !prev.is_null() {
+        // SAFETY: `prev` has been unlinked and is unreachable for any reader pinned later.
+        unsafe { guard.defer_destroy(prev) };
+    }
+}
+
+// SAFETY: must only be called with exclusive access to the atomic.
+unsafe fn drop_atomic<T>(atomic: &Atomic<T>) {
+    let guard = epoch::unprotected();
+    let ptr = atomic.swap(EpochShared::null(), Ordering::Relaxed, guard);
+    if !ptr.is_null() {
+        drop(ptr.into_owned());
+    }
 }
 
 fn shard_regions(num_shards: usize) -> Vec<(PageRegion, usize)> {
@@ -257,15 +331,32 @@ fn make_shards(num_shards: usize, page_cache_size: usize) -> Vec<CacheShard> {
         .map(|(region, count)| CacheShard {
             region,
             locked: Mutex::new(CacheShardLocked {
-                fixed_level_cache: HashMap::with_hasher(FxBuildHasher::default()),
                 cached: LruCache::unbounded_with_hasher(FxBuildHasher::default()),
+                pinned: HashMap::default(),
             }),
+            fixed_level_cache: Atomic::new(FixedLevelCache::default()),
             // UNWRAP: both factors are non-zero
             page_limit: NonZeroUsize::new(page_limit_per_root_child * count).unwrap(),
         })
         .collect()
 }
 
+/// Describes the contents of the page cache at a point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageCacheStats {
+    /// The number of upper levels of the page tree, below the root page, which are permanently
+    /// resident. See [`crate::Options::page_cache_upper_levels`].
+    pub resident_levels: usize,
+    /// The number of permanently resident pages: the root page and the pages of the upper levels.
+    pub resident_pages: usize,
+    /// The number of pages below the upper levels, which are subject to eviction.
+    pub cached_pages: usize,
+    /// The number of pinned pages. See [`crate::Nomt::pin_page`].
+    pub pinned_pages: usize,
+    /// The number of pages below the upper levels which are kept after eviction.
+    pub page_limit: usize,
+}
+
 /// The index of a shard of a page cache.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ShardIndex {
@@ -277,12 +368,22 @@ pub enum ShardIndex {
 
 /// The page-cache stores full pages and can be shared between threads.
 ///
-/// It has a sharded representation for efficient concurrent access.
+/// It has a sharded representation for efficient concurrent access. The root page and the
+/// always-cached upper levels are read without taking any locks: they are only replaced during
+/// commits and old versions are reclaimed once no reader can observe them. The cache holds no
+/// per-thread state, so handles are `Send + Sync` and can be cloned into other threads or async
+/// tasks freely.
 #[derive(Clone)]
 pub struct PageCache {
     shared: Arc<Shared>,
 }
 
+// Handles are shared with the commit workers and readers. Keep them `Send + Sync`.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<PageCache>();
+};
+
 impl PageCache {
     /// Create a new `PageCache`.
     pub fn new(
@@ -292,16 +393,19 @@ impl PageCache {
     ) -> Self {
         let domain = RwPassDomain::new();
 
-        let root_page_entry =
-            root_page_data.map(|(page, bucket)| CacheEntry::init(Arc::new(page), bucket));
+        let root_page_entry = match root_page_data {
+            Some((page, bucket)) => Atomic::new(CacheEntry::init(Arc::new(page), bucket)),
+            None => Atomic::null(),
+        };
 
         Self {
             shared: Arc::new(Shared {
                 shards: make_shards(o.commit_concurrency, o.page_cache_size),
-                root_page: RwLock::new(root_page_entry),
+                root_page: root_page_entry,
                 page_rw_pass_domain: domain,
                 metrics: metrics.into().unwrap_or(Metrics::new(false)),
                 fixed_levels: o.page_cache_upper_levels,
+                heatmap: PageHeatmap::new(o.page_access_sampling),
             }),
         }
     }
@@ -326,26 +430,32 @@ impl PageCache {
         self.shared.metrics.count(Metric::PageRequests);
         let shard_index = match self.shard_index_for(&page_id) {
             None => {
-                let cache_item = self.shared.root_page.read();
-                let cache_item = cache_item.as_ref()?;
-                return Some((
-                    Page {
-                        inner: cache_item.page_data.clone(),
-                    },
-                    cache_item.bucket_index,
-                ));
+                let guard = epoch::pin();
+                let root_page = self.shared.root_page.load(Ordering::Acquire, &guard);
+                // SAFETY: the root page is only destroyed once all guards which might have
+                // observed it are dropped.
+                let page = unsafe { root_page.as_ref() }.map(CacheEntry::to_page);
+                if let Some(ref heatmap) = self.shared.heatmap {
+                    heatmap.accessed(&page_id, page.is_some());
+                }
+                return page;
             }
             Some(i) => i,
         };
 
-        let mut shard = self.shard(shard_index).locked.lock();
-        match shard.get(self.shared.fixed_levels, &page_id) {
-            Some(cache_item) => Some((
-                Page {
-                    inner: cache_item.page_data.clone(),
-                },
-                cache_item.bucket_index,
-            )),
+        let shard = self.shard(shard_index);
+        let cache_item = if page_id.depth() <= self.shared.fixed_levels {
+            shard.get_fixed(&page_id)
+        } else {
+            self.lock_shard(shard).cached.get(&page_id).cloned()
+        };
+
+        if let Some(ref heatmap) = self.shared.heatmap {
+            heatmap.accessed(&page_id, cache_item.is_some());
+        }
+
+        match cache_item {
+            Some(cache_item) => Some(cache_item.to_page()),
             None => {
                 self.shared.metrics.count(Metric::PageCacheMisses);
                 None
@@ -353,6 +463,22 @@ impl PageCache {
         }
     }
 
+    // Lock a shard, counting the lookups which find it held by another thread.
+    fn lock_shard<'a>(&self, shard: &'a CacheShard) -> MutexGuard<'a, CacheShardLocked> {
+        match shard.locked.try_lock() {
+            Some(locked) => locked,
+            None => {
+                self.shared.metrics.count(Metric::PageCacheLockContention);
+                shard.locked.lock()
+            }
+        }
+    }
+
+    /// Produce a report of the sampled page accesses, if sampling is enabled.
+    pub fn access_report(&self) -> Option<PageAccessReport> {
+        self.shared.heatmap.as_ref().map(PageHeatmap::report)
+    }
+
     /// Acquire a write pass for all pages in the cache.
     pub fn new_write_pass(&self) -> WritePass<ShardIndex> {
         self.shared
@@ -382,27 +508,44 @@ impl PageCache {
     pub fn insert(&self, page_id: PageId, page: Page, bucket_index: BucketIndex) -> Page {
         let shard_index = match self.shard_index_for(&page_id) {
             None => {
-                let mut cache_item = self.shared.root_page.write();
-                if let Some(root_page) = cache_item.as_ref() {
-                    return Page {
-                        inner: root_page.page_data.clone(),
-                    };
-                } else {
-                    *cache_item = Some(CacheEntry::init(page.inner.clone(), bucket_index));
-                    return page;
-                }
+                let guard = epoch::pin();
+                let entry = Owned::new(CacheEntry::init(page.inner.clone(), bucket_index));
+                return match self.shared.root_page.compare_exchange(
+                    EpochShared::null(),
+                    entry,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                    &guard,
+                ) {
+                    Ok(_) => page,
+                    // SAFETY: `current` is non-null on failure and protected by the guard.
+                    Err(e) => unsafe { e.current.deref() }.to_page().0,
+                };
             }
             Some(i) => i,
         };
 
-        let mut shard = self.shard(shard_index).locked.lock();
-        let cache_entry = shard.get_or_insert(self.shared.fixed_levels, page_id, || {
-            CacheEntry::init(page.inner, bucket_index)
-        });
+        let shard = self.shard(shard_index);
+        let mut locked = self.lock_shard(shard);
+        let cache_entry = if page_id.depth() <= self.shared.fixed_levels {
+            match shard.get_fixed(&page_id) {
+                Some(cache_entry) => cache_entry,
+                None => {
+                    let cache_entry = CacheEntry::init(page.inner, bucket_index);
+                    shard.update_fixed(&mut locked, |fixed| {
+                        fixed.insert(page_id, cache_entry.clone())
+                    });
+                    cache_entry
+                }
+            }
+        } else {
+            locked
+                .cached
+                .get_or_insert(page_id, || CacheEntry::init(page.inner, bucket_index))
+                .clone()
+        };
 
-        Page {
-            inner: cache_entry.page_data.clone(),
-        }
+        cache_entry.to_page().0
     }
 
     /// Absorb a set of altered pages into the cache.
@@ -416,26 +559,100 @@ impl PageCache {
             .map(|s| s.locked.lock())
             .collect::<Vec<_>>();
 
+        let guard = epoch::pin();
         for (page_id, maybe_page) in updated_pages {
             if page_id == ROOT_PAGE_ID {
-                let mut root_page = self.shared.root_page.write();
-                *root_page = maybe_page
-                    .map(|(page, bucket_index)| CacheEntry::init(page.inner, bucket_index));
+                match maybe_page {
+                    Some((page, bucket_index)) => publish(
+                        &self.shared.root_page,
+                        Owned::new(CacheEntry::init(page.inner, bucket_index)),
+                        &guard,
+                    ),
+                    None => publish(&self.shared.root_page, EpochShared::null(), &guard),
+                }
 
                 continue;
             }
 
             // UNWRAP: all pages which are not the root page are in a shard.
             let shard_index = self.shard_index_for(&page_id).unwrap();
-
-            if let Some((page, bucket_index)) = maybe_page {
-                shard_guards[shard_index].insert(
-                    self.shared.fixed_levels,
-                    page_id.clone(),
-                    CacheEntry::init(page.inner, bucket_index),
-                );
+            let shard = self.shard(shard_index);
+            let locked = &mut shard_guards[shard_index];
+
+            let entry =
+                maybe_page.map(|(page, bucket_index)| CacheEntry::init(page.inner, bucket_index));
+            if page_id.depth() <= self.shared.fixed_levels {
+                shard.update_fixed(locked, |fixed| match entry {
+                    Some(entry) => {
+                        fixed.insert(page_id, entry);
+                    }
+                    None => {
+                        fixed.remove(&page_id);
+                    }
+                });
+            } else if let Some(entry) = entry {
+                locked.cached.put(page_id, entry);
             } else {
-                shard_guards[shard_index].remove(self.shared.fixed_levels, &page_id)
+                locked.cached.pop(&page_id);
+            }
+        }
+    }
+
+    /// Get statistics about the contents of the cache.
+    pub fn stats(&self) -> PageCacheStats {
+        let guard = epoch::pin();
+        let root_page = self.shared.root_page.load(Ordering::Acquire, &guard);
+        let mut stats = PageCacheStats {
+            resident_levels: self.shared.fixed_levels,
+            resident_pages: usize::from(!root_page.is_null()),
+            cached_pages: 0,
+            pinned_pages: 0,
+            page_limit: 0,
+        };
+        for shard in &self.shared.shards {
+            let locked = shard.locked.lock();
+            let fixed = shard.fixed_level_cache.load(Ordering::Acquire, &guard);
+            // SAFETY: see `CacheShard::get_fixed`.
+            stats.resident_pages += unsafe { fixed.deref() }.len();
+            stats.cached_pages += locked.cached.len();
+            stats.pinned_pages += locked.pinned.len();
+            stats.page_limit += shard.page_limit.get();
+        }
+        stats
+    }
+
+    /// Pin a page, so that it is never evicted until it is unpinned as many times as it was pinned.
+    ///
+    /// The page need not be in the cache: it is kept once it enters it. Pinned pages count against
+    /// the size of the cache. The root page and the pages of the always-cached upper levels are
+    /// never evicted anyway.
+    pub fn pin(&self, page_id: PageId) {
+        if let Some(shard_index) = self.shard_index_for(&page_id) {
+            if page_id.depth() > self.shared.fixed_levels {
+                let mut locked = self.shard(shard_index).locked.lock();
+                *locked.pinned.entry(page_id).or_default() += 1;
+            }
+        }
+    }
+
+    /// Unpin a page pinned with [`PageCache::pin`]. Returns `false` if the page wasn't pinned.
+    pub fn unpin(&self, page_id: &PageId) -> bool {
+        let Some(shard_index) = self.shard_index_for(page_id) else {
+            return true;
+        };
+        if page_id.depth() <= self.shared.fixed_levels {
+            return true;
+        }
+        let mut locked = self.shard(shard_index).locked.lock();
+        match locked.pinned.get_mut(page_id) {
+            None => false,
+            Some(1) => {
+                locked.pinned.remove(page_id);
+                true
+            }
+            Some(count) => {
+                *count -= 1;
+                true
             }
         }
     }
@@ -451,10 +668,30 @@ impl PageCache {
             .collect::<Vec<_>>();
 
         for (shard, mut guard) in self.shared.shards.iter().zip(shard_guards) {
-            guard.evict(shard.page_limit);
+            guard.evict(shard.page_limit.get());
+        }
+    }
+
+    /// Evict the least recently used pages until the pages subject to eviction take up at most
+    /// `bytes`. Pinned pages count against the size, but are kept. The cache grows back to its
+    /// configured size as pages are inserted.
+    ///
+    /// Like [`PageCache::evict`], this must not be used while pages are being updated.
+    pub fn shrink_to(&self, bytes: usize) {
+        let pages = bytes / PAGE_SIZE;
+        let num_shards = self.shared.shards.len();
+        for (i, shard) in self.shared.shards.iter().enumerate() {
+            // the first shards take the remainder.
+            let limit = pages / num_shards + usize::from(i < pages % num_shards);
+            shard.locked.lock().evict(limit.min(shard.page_limit.get()));
         }
     }
 
+    /// Evict every page subject to eviction which isn't pinned. See [`PageCache::shrink_to`].
+    pub fn clear(&self) {
+        self.shrink_to(0);
+    }
+
     fn shard(&self, index: usize) -> &CacheShard {
         &self.shared.shards[index]
     }
@@ -497,3 +734,233 @@ unsafe impl RegionContains<ShardIndex> for ShardIndex {
         }
     }
 }
+
+/// Concurrent lookups against a populated cache, reporting throughput and how often a lookup
+/// found its shard locked by another thread.
+#[cfg(feature = "benchmarks")]
+pub mod benches {
+    use super::{PageCache, PageMut};
+    use crate::{
+        bitbox::BucketIndex,
+        io::PagePool,
+        metrics::{Metric, Metrics},
+        Options,
+    };
+    use criterion::{BenchmarkId, Criterion, Throughput};
+    use nomt_core::{
+        page::NODES_PER_PAGE,
+        page_id::{ChildPageIndex, PageId, ROOT_PAGE_ID},
+    };
+    use std::time::{Duration, Instant};
+
+    // The number of pages in the cache. Lookups which are meant to miss query as many pages
+    // which were never inserted.
+    const CACHED_PAGES: usize = 1 << 14;
+
+    const THREADS: [usize; 4] = [1, 2, 4, 8];
+    const HIT_RATES: [u64; 3] = [100, 90, 50];
+
+    // A distinct page at depth 3, below the always-cached levels. Consecutive pages are spread
+    // across the shards.
+    fn page_id(i: usize) -> PageId {
+        let child = |index: usize| ChildPageIndex::new((index % 64) as u8).unwrap();
+        ROOT_PAGE_ID
+            .child_page_id(child(i))
+            .unwrap()
+            .child_page_id(child(i / 64))
+            .unwrap()
+            .child_page_id(child(i / 4096))
+            .unwrap()
+    }
+
+    // splitmix64, to pick lookups without sharing an RNG between threads.
+    fn mix(mut x: u64) -> u64 {
+        x = x.wrapping_add(0x9e3779b97f4a7c15);
+        x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+        x ^ (x >> 31)
+    }
+
+    /// Look up pages and read a node from each, from 1 to 8 threads and with varying hit rates.
+    pub fn page_cache_benchmark(c: &mut Criterion) {
+        let mut o = Options::new();
+        o.commit_concurrency(8);
+        o.page_cache_size(2 * CACHED_PAGES * 4096 / (1024 * 1024));
+        o.page_cache_upper_levels(2);
+        let metrics = Metrics::new(true);
+        let page_cache = PageCache::new(None, &o, metrics.clone());
+        let page_pool = PagePool::new();
+
+        for i in 0..CACHED_PAGES {
+            let mut page = PageMut::pristine_empty(&page_pool, &page_id(i));
+            page.set_node(i % NODES_PER_PAGE, [1; 32]);
+            page_cache.insert(page_id(i), page.freeze(), BucketIndex::new(i as u64));
+        }
+        // stands in for a page loaded from disk after a miss.
+        let loaded = PageMut::pristine_empty(&page_pool, &ROOT_PAGE_ID).freeze();
+
+        let mut group = c.benchmark_group("page_cache_read");
+        group.throughput(Throughput::Elements(1));
+        for hit_rate in HIT_RATES {
+            for threads in THREADS {
+                let requests = metrics.counter(Metric::PageRequests).unwrap();
+                let contended = metrics.counter(Metric::PageCacheLockContention).unwrap();
+
+                group.bench_with_input(
+                    BenchmarkId::new(format!("hit_{hit_rate}"), threads),
+                    &threads,
+                    |b, &threads| {
+                        b.iter_custom(|iters| {
+                            read_from_threads(&page_cache, &loaded, threads, hit_rate, iters)
+                        })
+                    },
+                );
+
+                let requests = metrics.counter(Metric::PageRequests).unwrap() - requests;
+                let contended =
+                    metrics.counter(Metric::PageCacheLockContention).unwrap() - contended;
+                println!(
+                    "page_cache_read/hit_{hit_rate}/{threads}: {contended} of {requests} lookups \
+                    contended ({:.3}%)",
+                    contended as f64 * 100.0 / requests.max(1) as f64,
+                );
+            }
+        }
+        group.finish();
+    }
+
+    // Perform `iters` lookups split between the given number of threads and return the time it
+    // took for all of them to finish.
+    fn read_from_threads(
+        page_cache: &PageCache,
+        loaded: &super::Page,
+        threads: usize,
+        hit_rate: u64,
+        iters: u64,
+    ) -> Duration {
+        let per_thread = iters.div_ceil(threads as u64);
+        let barrier = std::sync::Barrier::new(threads + 1);
+        std::thread::scope(|s| {
+            for thread in 0..threads {
+                let barrier = &barrier;
+                s.spawn(move || {
+                    let seed = (thread as u64) << 48;
+                    barrier.wait();
+                    for j in 0..per_thread {
+                        let r = mix(seed + j);
+                        let i = (r >> 8) as usize % CACHED_PAGES;
+                        let page_id = if r % 100 < hit_rate {
+                            page_id(i)
+                        } else {
+                            page_id(CACHED_PAGES + i)
+                        };
+                        let node = match page_cache.get(page_id) {
+                            Some((page, _)) => page.node(i % NODES_PER_PAGE),
+                            None => loaded.node(i % NODES_PER_PAGE),
+                        };
+                        criterion::black_box(node);
+                    }
+                    barrier.wait();
+                });
+            }
+            barrier.wait();
+            let start = Instant::now();
+            barrier.wait();
+            start.elapsed()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PageCache;
+    use crate::{
+        bitbox::BucketIndex,
+        io::{PagePool, PAGE_SIZE},
+        page_cache::PageMut,
+        Options,
+    };
+    use nomt_core::page_id::{ChildPageIndex, PageId, ROOT_PAGE_ID};
+
+    // A distinct page at depth 3, below the always-cached levels.
+    fn page_id(i: usize) -> PageId {
+        let child = |index: usize| ChildPageIndex::new(index as u8).unwrap();
+        ROOT_PAGE_ID
+            .child_page_id(child(0))
+            .unwrap()
+            .child_page_id(child(i / 64))
+            .unwrap()
+            .child_page_id(child(i % 64))
+            .unwrap()
+    }
+
+    #[test]
+    fn pinned_pages_survive_eviction() {
+        let mut o = Options::new();
+        o.commit_concurrency(1);
+        o.page_cache_size(1);
+        o.page_cache_upper_levels(2);
+        let page_cache = PageCache::new(None, &o, None);
+        let page_pool = PagePool::new();
+        let insert = |i| {
+            let page = PageMut::pristine_empty(&page_pool, &page_id(i)).freeze();
+            page_cache.insert(page_id(i), page, BucketIndex::new(i as u64));
+        };
+
+        page_cache.pin(page_id(0));
+        page_cache.pin(page_id(0));
+        page_cache.pin(page_id(1));
+        // 1MiB holds 256 pages.
+        for i in 0..1024 {
+            insert(i);
+        }
+        page_cache.evict();
+        assert!(page_cache.get(page_id(0)).is_some());
+        assert!(page_cache.get(page_id(1)).is_some());
+        assert!(page_cache.get(page_id(2)).is_none());
+        assert!(page_cache.get(page_id(1023)).is_some());
+
+        assert!(page_cache.unpin(&page_id(0)));
+        assert!(page_cache.unpin(&page_id(1)));
+        assert!(!page_cache.unpin(&page_id(1)));
+        for i in 1024..2048 {
+            insert(i);
+        }
+        page_cache.evict();
+        assert!(page_cache.get(page_id(0)).is_some());
+        assert!(page_cache.get(page_id(1)).is_none());
+
+        assert!(page_cache.unpin(&page_id(0)));
+        for i in 2048..3072 {
+            insert(i);
+        }
+        page_cache.evict();
+        assert!(page_cache.get(page_id(0)).is_none());
+    }
+
+    #[test]
+    fn shrink_keeps_most_recently_used_pages() {
+        let mut o = Options::new();
+        o.commit_concurrency(1);
+        o.page_cache_size(1);
+        o.page_cache_upper_levels(2);
+        let page_cache = PageCache::new(None, &o, None);
+        let page_pool = PagePool::new();
+        for i in 0..256 {
+            let page = PageMut::pristine_empty(&page_pool, &page_id(i)).freeze();
+            page_cache.insert(page_id(i), page, BucketIndex::new(i as u64));
+        }
+        page_cache.pin(page_id(0));
+
+        page_cache.shrink_to(16 * PAGE_SIZE);
+        assert_eq!(page_cache.stats().cached_pages, 16);
+        assert!(page_cache.get(page_id(0)).is_some());
+        assert!(page_cache.get(page_id(1)).is_none());
+        assert!(page_cache.get(page_id(255)).is_some());
+
+        page_cache.clear();
+        assert_eq!(page_cache.stats().cached_pages, 1);
+        assert!(page_cache.get(page_id(0)).is_some());
+        assert!(page_cache.get(page_id(255)).is_none());
+    }
+}
@@ -34,7 +34,7 @@ impl PageDiff {
     /// Note that some 32-byte slot in the page data has changed.
     ///
     /// The acceptable range is 0..NODES_PER_PAGE. Erases the clear bit.
-    pub fn set_changed(&mut self, slot_index: usize) {
+    pub(crate) fn set_changed(&mut self, slot_index: usize) {
         assert!(slot_index < NODES_PER_PAGE);
         let word = slot_index / 64;
         let index = slot_index % 64;
@@ -52,7 +52,7 @@ impl PageDiff {
     }
 
     /// Mark the page as having been cleared.
-    pub fn set_cleared(&mut self) {
+    pub(crate) fn set_cleared(&mut self) {
         self.changed_nodes[1] |= CLEAR_BIT;
     }
 
@@ -0,0 +1,91 @@
+//! The header stored in the spare bytes of every page, between the nodes and the page ID.
+//!
+//! ```text
+//! [nodes: 4032][magic: 4][version: 2][reserved: 2][checksum: 8][reserved: 16][page ID: 32]
+//! ```
+//!
+//! The checksum covers the whole page except for the checksum itself. It is validated when a page
+//! is loaded, so a page which was torn, corrupted or written to the wrong place is reported
+//! instead of used. Pages written before headers were introduced don't have the magic and aren't
+//! checked.
+
+use crate::io::PAGE_SIZE;
+use nomt_core::page::NODES_PER_PAGE;
+
+const HEADER_START: usize = NODES_PER_PAGE * 32;
+const CHECKSUM_START: usize = HEADER_START + 8;
+const PAGE_ID_START: usize = PAGE_SIZE - 32;
+
+const MAGIC: [u8; 4] = *b"NPGH";
+
+/// The current version of the page format.
+pub const VERSION: u16 = 1;
+
+/// Write the header of a labeled page.
+pub fn stamp(page: &mut [u8]) {
+    page[HEADER_START..HEADER_START + 4].copy_from_slice(&MAGIC);
+    page[HEADER_START + 4..HEADER_START + 6].copy_from_slice(&VERSION.to_le_bytes());
+    page[HEADER_START + 6..CHECKSUM_START].fill(0);
+    page[CHECKSUM_START + 8..PAGE_ID_START].fill(0);
+    let checksum = checksum(page);
+    page[CHECKSUM_START..CHECKSUM_START + 8].copy_from_slice(&checksum);
+}
+
+/// Check the header of a loaded page.
+pub fn check(page: &[u8]) -> std::io::Result<()> {
+    if page[HEADER_START..HEADER_START + 4] != MAGIC {
+        return Ok(());
+    }
+
+    let version = u16::from_le_bytes([page[HEADER_START + 4], page[HEADER_START + 5]]);
+    if version > VERSION {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("unsupported page format version {version}"),
+        ));
+    }
+    if page[CHECKSUM_START..CHECKSUM_START + 8] != checksum(page) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "page checksum mismatch",
+        ));
+    }
+    Ok(())
+}
+
+fn checksum(page: &[u8]) -> [u8; 8] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&page[..CHECKSUM_START]);
+    hasher.update(&page[CHECKSUM_START + 8..]);
+    // UNWRAP: a hash is longer than 8 bytes.
+    hasher.finalize().as_bytes()[..8].try_into().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, stamp, HEADER_START, PAGE_ID_START};
+    use crate::io::PAGE_SIZE;
+
+    #[test]
+    fn stamped_page_checks() {
+        let mut page = vec![7u8; PAGE_SIZE];
+        // a page without a header isn't checked.
+        assert!(check(&page).is_ok());
+
+        stamp(&mut page);
+        assert!(check(&page).is_ok());
+
+        let offsets = [
+            0,
+            HEADER_START - 1,
+            HEADER_START + 4,
+            PAGE_ID_START - 1,
+            PAGE_SIZE - 1,
+        ];
+        for offset in offsets {
+            let mut corrupted = page.clone();
+            corrupted[offset] ^= 1;
+            assert!(check(&corrupted).is_err());
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Sampling of page cache accesses.
+//!
+//! When enabled, one in every `sample_rate` page cache lookups is recorded. Samples are aggregated
+//! by page depth and by subtree, where a subtree is rooted at a page at most
+//! [`SUBTREE_DEPTH`] levels deep. This shows which parts of the state dominate I/O.
+
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use nomt_core::{
+    page_id::{PageId, MAX_PAGE_DEPTH},
+    trie::KeyPath,
+};
+use parking_lot::Mutex;
+
+/// The depth of the pages rooting the subtrees which samples are aggregated by.
+const SUBTREE_DEPTH: usize = 2;
+
+/// A report of sampled page cache accesses. See [`crate::Options::page_access_sampling`].
+#[derive(Debug, Clone)]
+pub struct PageAccessReport {
+    /// One in every `sample_rate` accesses was sampled.
+    pub sample_rate: u32,
+    /// The number of sampled accesses, indexed by page depth.
+    pub accesses_by_depth: Vec<u64>,
+    /// The number of sampled accesses which missed the cache, indexed by page depth.
+    pub misses_by_depth: Vec<u64>,
+    /// Sampled accesses aggregated by subtree, sorted by descending number of accesses.
+    pub subtrees: Vec<SubtreeAccesses>,
+}
+
+/// Sampled accesses to the pages of a subtree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubtreeAccesses {
+    /// The depth of the page rooting the subtree. The root page has depth 0.
+    pub depth: usize,
+    /// The minimum key path covered by the subtree.
+    pub min_key_path: KeyPath,
+    /// The maximum key path covered by the subtree.
+    pub max_key_path: KeyPath,
+    /// The number of sampled accesses.
+    pub accesses: u64,
+    /// The number of sampled accesses which missed the cache.
+    pub misses: u64,
+}
+
+#[derive(Default)]
+struct Counts {
+    accesses: u64,
+    misses: u64,
+}
+
+pub(crate) struct PageHeatmap {
+    sample_rate: u64,
+    counter: AtomicU64,
+    by_depth: Mutex<Vec<Counts>>,
+    by_subtree: Mutex<HashMap<PageId, Counts>>,
+}
+
+impl PageHeatmap {
+    /// Create a new heatmap sampling one in every `sample_rate` accesses. Returns `None` if the
+    /// sample rate is zero.
+    pub fn new(sample_rate: u32) -> Option<Self> {
+        if sample_rate == 0 {
+            return None;
+        }
+
+        Some(PageHeatmap {
+            sample_rate: sample_rate as u64,
+            counter: AtomicU64::new(0),
+            by_depth: Mutex::new((0..=MAX_PAGE_DEPTH).map(|_| Counts::default()).collect()),
+            by_subtree: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Note an access to the given page.
+    pub fn accessed(&self, page_id: &PageId, hit: bool) {
+        if !self
+            .counter
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(self.sample_rate)
+        {
+            return;
+        }
+
+        let miss = if hit { 0 } else { 1 };
+        {
+            let mut by_depth = self.by_depth.lock();
+            let counts = &mut by_depth[page_id.depth()];
+            counts.accesses += 1;
+            counts.misses += miss;
+        }
+
+        let mut subtree = page_id.clone();
+        while subtree.depth() > SUBTREE_DEPTH {
+            subtree = subtree.parent_page_id();
+        }
+        let mut by_subtree = self.by_subtree.lock();
+        let counts = by_subtree.entry(subtree).or_default();
+        counts.accesses += 1;
+        counts.misses += miss;
+    }
+
+    /// Produce a report of the accesses sampled so far.
+    pub fn report(&self) -> PageAccessReport {
+        let (accesses_by_depth, misses_by_depth) = self
+            .by_depth
+            .lock()
+            .iter()
+            .map(|counts| (counts.accesses, counts.misses))
+            .unzip();
+
+        let mut subtrees: Vec<_> = self
+            .by_subtree
+            .lock()
+            .iter()
+            .map(|(page_id, counts)| SubtreeAccesses {
+                depth: page_id.depth(),
+                min_key_path: page_id.min_key_path(),
+                max_key_path: page_id.max_key_path(),
+                accesses: counts.accesses,
+                misses: counts.misses,
+            })
+            .collect();
+        subtrees.sort_by(|a, b| {
+            b.accesses
+                .cmp(&a.accesses)
+                .then(a.min_key_path.cmp(&b.min_key_path))
+                .then(a.depth.cmp(&b.depth))
+        });
+
+        PageAccessReport {
+            sample_rate: self.sample_rate as u32,
+            accesses_by_depth,
+            misses_by_depth,
+            subtrees,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PageHeatmap;
+    use nomt_core::page_id::{ChildPageIndex, ROOT_PAGE_ID};
+
+    #[test]
+    fn aggregates_by_subtree() {
+        let child = |parent: &nomt_core::page_id::PageId, i| {
+            parent
+                .child_page_id(ChildPageIndex::new(i).unwrap())
+                .unwrap()
+        };
+        let a = child(&child(&ROOT_PAGE_ID, 1), 2);
+        let a_deep = child(&child(&a, 3), 4);
+        let b = child(&ROOT_PAGE_ID, 5);
+
+        let heatmap = PageHeatmap::new(1).unwrap();
+        heatmap.accessed(&ROOT_PAGE_ID, true);
+        heatmap.accessed(&a, true);
+        heatmap.accessed(&a_deep, false);
+        heatmap.accessed(&a_deep, false);
+        heatmap.accessed(&b, true);
+
+        let report = heatmap.report();
+        assert_eq!(report.accesses_by_depth[..5], [1, 1, 1, 0, 2]);
+        assert_eq!(report.misses_by_depth[..5], [0, 0, 0, 0, 2]);
+
+        assert_eq!(report.subtrees.len(), 3);
+        assert_eq!(report.subtrees[0].depth, 2);
+        assert_eq!(report.subtrees[0].min_key_path, a.min_key_path());
+        assert_eq!(report.subtrees[0].accesses, 3);
+        assert_eq!(report.subtrees[0].misses, 2);
+    }
+
+    #[test]
+    fn samples_one_in_rate() {
+        let heatmap = PageHeatmap::new(4).unwrap();
+        for _ in 0..16 {
+            heatmap.accessed(&ROOT_PAGE_ID, true);
+        }
+        assert_eq!(heatmap.report().accesses_by_depth[0], 4);
+        assert!(PageHeatmap::new(0).is_none());
+    }
+}
@@ -0,0 +1,115 @@
+//! A report of how full the stored pages are.
+//!
+//! Every page has room for [`NODES_PER_PAGE`] nodes, but a page only holds the part of the trie
+//! which passes through it. Pages along sparse parts of the trie, for example below a single pair
+//! of keys sharing a long prefix, use a handful of slots and waste the rest. The report is built
+//! by walking the page tree from the root page and counting the occupied slots of every page.
+
+use nomt_core::{
+    page::NODES_PER_PAGE,
+    page_id::{ChildPageIndex, ROOT_PAGE_ID},
+    trie::TERMINATOR,
+};
+
+use crate::{page_cache::PageMut, store::Store};
+
+// The index of the first node of the bottom layer of a page.
+pub(crate) const BOTTOM_LAYER_START: usize = NODES_PER_PAGE / 2 - 1;
+
+/// How full the stored pages are. See [`crate::Nomt::page_utilization`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageUtilization {
+    /// The number of stored pages.
+    pub pages: u64,
+    /// The number of pages, indexed by the number of occupied node slots. Has
+    /// `NODES_PER_PAGE + 1` entries.
+    pub slot_histogram: Vec<u64>,
+    /// The number of pages, indexed by their depth in the page tree. The root page has depth 0.
+    pub depth_histogram: Vec<u64>,
+}
+
+impl PageUtilization {
+    /// The total number of occupied node slots across all pages.
+    pub fn occupied_slots(&self) -> u64 {
+        self.slot_histogram
+            .iter()
+            .enumerate()
+            .map(|(slots, pages)| slots as u64 * pages)
+            .sum()
+    }
+
+    /// The fraction of node slots which are occupied, between 0 and 1.
+    pub fn fill_rate(&self) -> f64 {
+        if self.pages == 0 {
+            return 0.0;
+        }
+        self.occupied_slots() as f64 / (self.pages * NODES_PER_PAGE as u64) as f64
+    }
+
+    /// The number of pages with at most the given number of occupied slots.
+    pub fn pages_with_at_most(&self, slots: usize) -> u64 {
+        self.slot_histogram.iter().take(slots + 1).sum()
+    }
+}
+
+pub(crate) fn page_utilization(store: &Store) -> anyhow::Result<PageUtilization> {
+    let mut report = PageUtilization {
+        pages: 0,
+        slot_histogram: vec![0; NODES_PER_PAGE + 1],
+        depth_histogram: Vec::new(),
+    };
+
+    let mut stack = vec![ROOT_PAGE_ID];
+    while let Some(page_id) = stack.pop() {
+        let Some((page, _)) = store.load_page(page_id.clone())? else {
+            continue;
+        };
+        let page = PageMut::pristine_with_data(page);
+
+        let occupied = (0..NODES_PER_PAGE)
+            .filter(|i| page.node(*i) != TERMINATOR)
+            .count();
+        report.pages += 1;
+        report.slot_histogram[occupied] += 1;
+        let depth = page_id.depth();
+        if report.depth_histogram.len() <= depth {
+            report.depth_histogram.resize(depth + 1, 0);
+        }
+        report.depth_histogram[depth] += 1;
+
+        // internal nodes of the bottom layer continue in a child page. There is none below a
+        // leaf, which only costs a probe.
+        for child_index in ChildPageIndex::all() {
+            if page.node(BOTTOM_LAYER_START + usize::from(child_index)) == TERMINATOR {
+                continue;
+            }
+            if let Ok(child_page_id) = page_id.child_page_id(child_index) {
+                stack.push(child_page_id);
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PageUtilization;
+
+    #[test]
+    fn summaries() {
+        let mut slot_histogram = vec![0; 127];
+        slot_histogram[2] = 3;
+        slot_histogram[126] = 1;
+        let report = PageUtilization {
+            pages: 4,
+            slot_histogram,
+            depth_histogram: vec![1, 3],
+        };
+        assert_eq!(report.occupied_slots(), 132);
+        assert_eq!(report.fill_rate(), 132.0 / 504.0);
+        assert_eq!(report.pages_with_at_most(1), 0);
+        assert_eq!(report.pages_with_at_most(2), 3);
+        assert_eq!(report.pages_with_at_most(126), 4);
+    }
+}
@@ -0,0 +1,360 @@
+//! A worker pool for serving proofs.
+//!
+//! Proving the current values of keys takes a read lock on the database, which a commit has to
+//! wait for. When proofs are served to many clients, e.g. over RPC, unbounded concurrency would
+//! let proof traffic stall commits. [`ProofPool`] proves on a fixed number of dedicated threads,
+//! apart from the threads updating the trie during commits, and bounds both the number of waiting
+//! requests and, optionally, the rate at which requests are started. A commit is thus only ever
+//! delayed by the proofs in flight, of which there are at most as many as workers.
+//!
+//! Every [`ServedProof`] reports how long the request waited in the queue and how long it took to
+//! prove, and [`ProofPool::stats`] aggregates them.
+//!
+//! With [`ProofPoolParams::cache_size`], the proofs of recently requested keys are kept and served
+//! again without walking the trie until the root changes, which helps when many clients request
+//! the same hot keys.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use bitvec::prelude::*;
+use crossbeam_channel::Receiver;
+use lru::LruCache;
+use nomt_core::{proof::PathProof, trie::KeyPath};
+use parking_lot::Mutex;
+use threadpool::ThreadPool;
+
+use crate::{BlockWitness, HashAlgorithm, Nomt, Root, Value};
+
+/// Parameters for creating a [`ProofPool`].
+pub struct ProofPoolParams {
+    workers: usize,
+    max_queued: usize,
+    max_rate: Option<u32>,
+    cache_size: usize,
+}
+
+impl Default for ProofPoolParams {
+    fn default() -> Self {
+        ProofPoolParams {
+            workers: 2,
+            max_queued: 1024,
+            max_rate: None,
+            cache_size: 0,
+        }
+    }
+}
+
+impl ProofPoolParams {
+    /// The number of threads proving requests. Default: 2
+    ///
+    /// This is also the maximum number of proofs in flight at once.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// The maximum number of requests waiting for a worker. Default: 1024
+    ///
+    /// Requests submitted while the queue is full are rejected.
+    pub fn max_queued(mut self, max_queued: usize) -> Self {
+        self.max_queued = max_queued;
+        self
+    }
+
+    /// The maximum number of requests started per second, across all workers. Default: None
+    ///
+    /// Requests over the rate wait in the queue.
+    pub fn max_rate(mut self, requests_per_second: u32) -> Self {
+        self.max_rate = Some(requests_per_second.max(1));
+        self
+    }
+
+    /// The number of keys whose proofs are kept, least recently requested first out. Default: 0
+    ///
+    /// Proofs are only served from the cache at the root they were generated at. The cache is
+    /// emptied when a request finds the root has changed. 0 disables the cache.
+    pub fn cache_size(mut self, keys: usize) -> Self {
+        self.cache_size = keys;
+        self
+    }
+}
+
+/// A proof served by a [`ProofPool`].
+pub struct ServedProof {
+    /// The proof of the requested keys.
+    pub witness: BlockWitness,
+    /// How long the request waited before a worker started proving it.
+    pub queue_time: Duration,
+    /// How long it took to prove the request.
+    pub prove_time: Duration,
+}
+
+/// Statistics about the requests submitted to a [`ProofPool`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProofPoolStats {
+    /// The number of requests waiting for a worker.
+    pub queued: usize,
+    /// The number of requests being proven.
+    pub in_flight: usize,
+    /// The number of requests proven, successfully or not.
+    pub served: u64,
+    /// The number of requests rejected because the queue was full.
+    pub rejected: u64,
+    /// The total time spent in the queue by the requests started so far.
+    pub total_queue_time: Duration,
+    /// The longest time spent in the queue by a request started so far.
+    pub max_queue_time: Duration,
+    /// The number of requested keys served from the cache.
+    pub cache_hits: u64,
+    /// The number of requested keys proven, with the cache enabled.
+    pub cache_misses: u64,
+}
+
+/// A handle on a request submitted to a [`ProofPool`].
+pub struct ProofTicket {
+    rx: Receiver<anyhow::Result<ServedProof>>,
+}
+
+impl ProofTicket {
+    /// Block until the request is served.
+    pub fn wait(self) -> anyhow::Result<ServedProof> {
+        match self.rx.recv() {
+            Ok(result) => result,
+            Err(_) => anyhow::bail!("proof pool: worker panicked"),
+        }
+    }
+}
+
+/// A pool of threads dedicated to proving the current values of keys.
+///
+/// Dropping the pool doesn't cancel the requests already submitted.
+pub struct ProofPool<T: HashAlgorithm> {
+    shared: Arc<Shared<T>>,
+    tp: ThreadPool,
+}
+
+struct Shared<T: HashAlgorithm> {
+    nomt: Arc<Nomt<T>>,
+    max_queued: usize,
+    // the time between the starts of two requests, if rate limited.
+    interval: Option<Duration>,
+    // the earliest time the next request may start.
+    next_start: Mutex<Instant>,
+    queued: AtomicUsize,
+    in_flight: AtomicUsize,
+    served: AtomicU64,
+    rejected: AtomicU64,
+    total_queue_nanos: AtomicU64,
+    max_queue_nanos: AtomicU64,
+    cache: Option<Mutex<ProofCache>>,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+}
+
+// The proofs of recently requested keys, along with their values, at a single root.
+struct ProofCache {
+    root: Option<Root>,
+    entries: LruCache<KeyPath, (PathProof, Option<Value>)>,
+}
+
+impl<T: HashAlgorithm + Send + Sync + 'static> ProofPool<T> {
+    /// Create a new pool serving proofs from the given database.
+    pub fn new(nomt: Arc<Nomt<T>>, params: ProofPoolParams) -> Self {
+        let shared = Arc::new(Shared {
+            nomt,
+            max_queued: params.max_queued,
+            interval: params.max_rate.map(|rate| Duration::from_secs(1) / rate),
+            next_start: Mutex::new(Instant::now()),
+            queued: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+            served: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            total_queue_nanos: AtomicU64::new(0),
+            max_queue_nanos: AtomicU64::new(0),
+            cache: std::num::NonZeroUsize::new(params.cache_size).map(|size| {
+                Mutex::new(ProofCache {
+                    root: None,
+                    entries: LruCache::new(size),
+                })
+            }),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+        });
+        ProofPool {
+            shared,
+            tp: ThreadPool::with_name("nomt-proof".into(), params.workers),
+        }
+    }
+
+    /// Submit a request to prove the current values of the given keys. See
+    /// [`Nomt::block_witness`].
+    ///
+    /// Fails without queueing the request if the queue is full.
+    pub fn submit(&self, keys: impl IntoIterator<Item = KeyPath>) -> anyhow::Result<ProofTicket> {
+        let shared = &self.shared;
+        let reserved = shared
+            .queued
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |queued| {
+                (queued < shared.max_queued).then_some(queued + 1)
+            });
+        if let Err(queued) = reserved {
+            shared.rejected.fetch_add(1, Ordering::Relaxed);
+            anyhow::bail!("proof pool: {} requests already queued", queued);
+        }
+
+        let keys: Vec<KeyPath> = keys.into_iter().collect();
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        let submitted_at = Instant::now();
+        let shared = shared.clone();
+        self.tp.execute(move || {
+            shared.wait_for_start();
+            let queue_time = submitted_at.elapsed();
+            shared.start(queue_time);
+
+            let started_at = Instant::now();
+            let result = shared.prove(keys).map(|witness| ServedProof {
+                witness,
+                queue_time,
+                prove_time: started_at.elapsed(),
+            });
+
+            shared.in_flight.fetch_sub(1, Ordering::Relaxed);
+            shared.served.fetch_add(1, Ordering::Relaxed);
+            let _ = tx.send(result);
+        });
+        Ok(ProofTicket { rx })
+    }
+
+    /// Submit a request and block until it is served. See [`ProofPool::submit`].
+    pub fn prove(&self, keys: impl IntoIterator<Item = KeyPath>) -> anyhow::Result<ServedProof> {
+        self.submit(keys)?.wait()
+    }
+
+    /// Statistics about the requests submitted so far.
+    pub fn stats(&self) -> ProofPoolStats {
+        let shared = &self.shared;
+        ProofPoolStats {
+            queued: shared.queued.load(Ordering::Relaxed),
+            in_flight: shared.in_flight.load(Ordering::Relaxed),
+            served: shared.served.load(Ordering::Relaxed),
+            rejected: shared.rejected.load(Ordering::Relaxed),
+            total_queue_time: Duration::from_nanos(
+                shared.total_queue_nanos.load(Ordering::Relaxed),
+            ),
+            max_queue_time: Duration::from_nanos(shared.max_queue_nanos.load(Ordering::Relaxed)),
+            cache_hits: shared.cache_hits.load(Ordering::Relaxed),
+            cache_misses: shared.cache_misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<T: HashAlgorithm> Shared<T> {
+    // Prove the keys, taking the proofs of the cached ones from the cache.
+    fn prove(&self, mut keys: Vec<KeyPath>) -> anyhow::Result<BlockWitness> {
+        let Some(ref cache) = self.cache else {
+            return self.nomt.block_witness(keys);
+        };
+        keys.sort_unstable();
+        keys.dedup();
+
+        let root = self.nomt.root();
+        let mut hits = Vec::new();
+        let mut misses = Vec::new();
+        {
+            let mut cache = cache.lock();
+            cache.reset_if_stale(root);
+            for key in keys.iter() {
+                match cache.entries.get(key) {
+                    Some(entry) => hits.push((*key, entry.clone())),
+                    None => misses.push(*key),
+                }
+            }
+        }
+        let witness = if misses.is_empty() {
+            BlockWitness {
+                prev_root: root,
+                path_proofs: Vec::new(),
+                values: Vec::new(),
+            }
+        } else {
+            self.nomt.block_witness(misses)?
+        };
+        let mut witness = if witness.prev_root != root && !hits.is_empty() {
+            // a commit landed since the cache was consulted, so the cached proofs are stale.
+            hits.clear();
+            self.nomt.block_witness(keys.iter().copied())?
+        } else {
+            witness
+        };
+
+        self.cache_hits
+            .fetch_add(hits.len() as u64, Ordering::Relaxed);
+        self.cache_misses
+            .fetch_add((keys.len() - hits.len()) as u64, Ordering::Relaxed);
+
+        // the cache may have moved on to another root in the meantime.
+        let mut cache = cache.lock();
+        if cache.root == Some(witness.prev_root) {
+            for (key, value) in witness.values.iter() {
+                if let Some(proof) = witness.path_proofs.iter().find(|proof| proves(proof, key)) {
+                    cache.entries.put(*key, (proof.clone(), value.clone()));
+                }
+            }
+        }
+        drop(cache);
+
+        if !hits.is_empty() {
+            for (key, (proof, value)) in hits {
+                witness.path_proofs.push(proof);
+                witness.values.push((key, value));
+            }
+            witness.values.sort_by_key(|(key, _)| *key);
+        }
+        Ok(witness)
+    }
+
+    // Block until the rate limit allows another request to start.
+    fn wait_for_start(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let now = Instant::now();
+        let start = {
+            let mut next_start = self.next_start.lock();
+            let start = (*next_start).max(now);
+            *next_start = start + interval;
+            start
+        };
+        std::thread::sleep(start - now);
+    }
+
+    // Move a request from the queue to the requests in flight.
+    fn start(&self, queue_time: Duration) {
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        let nanos = queue_time.as_nanos() as u64;
+        self.total_queue_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_queue_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+}
+
+impl ProofCache {
+    fn reset_if_stale(&mut self, root: Root) {
+        if self.root != Some(root) {
+            self.entries.clear();
+            self.root = Some(root);
+        }
+    }
+}
+
+// Whether the path proof is of the path to the key.
+fn proves(proof: &PathProof, key: &KeyPath) -> bool {
+    let depth = proof.siblings.len();
+    proof.terminal.path()[..depth] == key.view_bits::<Msb0>()[..depth]
+}
@@ -0,0 +1,224 @@
+//! Replaying the commits recorded in the rollback log, for debugging.
+//!
+//! The rollback log holds a record for every retained commit: the prior value of every key the
+//! commit wrote and the root it was made on top of. [`Replay`] copies a database and undoes its
+//! commits one at a time on the copy, most recent first, checking that undoing a commit leads
+//! back to the root recorded for it. The first commit failing the check is the one whose recorded
+//! root transition the state doesn't reproduce, which narrows down where a bad root came from.
+//!
+//! The WAL of the hash-table only holds the pages of the sync in progress and is emptied once
+//! the sync completes, so it records no commits and isn't replayed.
+//!
+//! Commits are only logged when the database is opened with [`crate::Options::rollback`].
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use nomt::{hasher::Blake3Hasher, replay::Replay};
+//!
+//! let mut replay = Replay::<Blake3Hasher>::open("nomt_db", "nomt_db_replay")?;
+//! if let Some(bad) = replay.find_inconsistent(|undone| print!("{}", undone))? {
+//!     println!("first inconsistent commit: {}", bad.commit);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{fmt, path::Path};
+
+use crate::{
+    options::RetentionPolicy, store::Flock, trie::KeyPath, HashAlgorithm, Nomt, Options, Root,
+    Value,
+};
+
+// Files left out of the copy: rolling back a copy must not be prevented by the pins of the
+// original.
+const SKIPPED_FILES: &[&str] = &[".lock", "pinned_roots", "pinned_roots.tmp"];
+
+/// A commit recorded in the rollback log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoggedCommit {
+    /// The ID of the record in the log. IDs increase with every commit.
+    pub id: u64,
+    /// The root the commit was made on top of. `None` for records written by versions which
+    /// didn't record it.
+    pub prev_root: Option<Root>,
+    /// The time of the commit, in seconds since the UNIX epoch.
+    pub committed_at: u64,
+    /// The value of every key written by the commit before the commit, in key order. `None`
+    /// means the key didn't exist.
+    pub priors: Vec<(KeyPath, Option<Value>)>,
+}
+
+impl fmt::Display for LoggedCommit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "commit {} at {}: {} keys",
+            self.id,
+            self.committed_at,
+            self.priors.len()
+        )?;
+        match self.prev_root {
+            Some(prev_root) => write!(f, ", on top of {}", prev_root),
+            None => write!(f, ", on top of an unrecorded root"),
+        }
+    }
+}
+
+/// A commit undone by [`Replay::undo`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndoneCommit {
+    /// The record of the commit.
+    pub commit: LoggedCommit,
+    /// The root before undoing the commit.
+    pub root: Root,
+    /// The value of every key written by the commit, in key order. `None` means the commit
+    /// deleted the key.
+    pub writes: Vec<(KeyPath, Option<Value>)>,
+    /// The root after undoing the commit.
+    pub undone_root: Root,
+}
+
+impl UndoneCommit {
+    /// Whether undoing the commit led back to the root it was recorded to be made on top of.
+    ///
+    /// Always true for commits without a recorded root.
+    pub fn is_consistent(&self) -> bool {
+        self.commit
+            .prev_root
+            .is_none_or(|prev_root| prev_root == self.undone_root)
+    }
+}
+
+impl fmt::Display for UndoneCommit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.commit)?;
+        writeln!(f, "  root {} undone to {}", self.root, self.undone_root)?;
+        if !self.is_consistent() {
+            writeln!(
+                f,
+                "  MISMATCH: the undone root differs from the recorded one"
+            )?;
+        }
+        for ((key, prior), (_, value)) in self.commit.priors.iter().zip(&self.writes) {
+            writeln!(
+                f,
+                "  {}: {} -> {}",
+                hex(key),
+                describe(prior.as_deref()),
+                describe(value.as_deref()),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Undoes the logged commits of a copy of a database, one at a time.
+pub struct Replay<T: HashAlgorithm> {
+    nomt: Nomt<T>,
+    commits: Vec<LoggedCommit>,
+}
+
+impl<T: HashAlgorithm> Replay<T> {
+    /// Copy the database at `path` to `copy_path` and open the copy.
+    ///
+    /// `copy_path` must not exist. The database must not be open for writing while it's copied;
+    /// the original is never modified.
+    pub fn open(path: impl AsRef<Path>, copy_path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let (path, copy_path) = (path.as_ref(), copy_path.as_ref());
+        if copy_path.exists() {
+            anyhow::bail!("replay: {} already exists", copy_path.display());
+        }
+
+        {
+            // keep writers out while copying.
+            let _flock = Flock::lock_shared(path, ".lock")?;
+            std::fs::create_dir_all(copy_path)?;
+            for entry in std::fs::read_dir(path)? {
+                let entry = entry?;
+                let name = entry.file_name();
+                if !entry.file_type()?.is_file()
+                    || SKIPPED_FILES.iter().any(|skipped| name == *skipped)
+                {
+                    continue;
+                }
+                std::fs::copy(entry.path(), copy_path.join(&name))?;
+            }
+        }
+
+        let mut o = Options::new();
+        o.path(copy_path);
+        o.rollback(true);
+        // undoing commits must not make older ones fall out of the log.
+        o.rollback_retention(RetentionPolicy::Commits(u32::MAX));
+        let nomt = Nomt::open(o)?;
+        let commits = nomt
+            .store
+            .rollback()
+            .map_or_else(Vec::new, |rollback| rollback.logged_commits());
+        Ok(Replay { nomt, commits })
+    }
+
+    /// The logged commits which haven't been undone, oldest first.
+    pub fn commits(&self) -> &[LoggedCommit] {
+        &self.commits
+    }
+
+    /// The current root of the copy.
+    pub fn root(&self) -> Root {
+        self.nomt.root()
+    }
+
+    /// Undo the most recent commit which hasn't been undone. Returns `None` if there is none.
+    pub fn undo(&mut self) -> anyhow::Result<Option<UndoneCommit>> {
+        let Some(commit) = self.commits.pop() else {
+            return Ok(None);
+        };
+        let root = self.nomt.root();
+        let writes = commit
+            .priors
+            .iter()
+            .map(|(key, _)| Ok((*key, self.nomt.read(*key)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        self.nomt.rollback(1)?;
+        Ok(Some(UndoneCommit {
+            commit,
+            root,
+            writes,
+            undone_root: self.nomt.root(),
+        }))
+    }
+
+    /// Undo commits until one is inconsistent with the log and return it, or `None` if every
+    /// logged commit is consistent.
+    ///
+    /// `inspect` is called with every commit undone along the way, including the inconsistent
+    /// one.
+    pub fn find_inconsistent(
+        &mut self,
+        mut inspect: impl FnMut(&UndoneCommit),
+    ) -> anyhow::Result<Option<UndoneCommit>> {
+        while let Some(undone) = self.undo()? {
+            inspect(&undone);
+            if !undone.is_consistent() {
+                return Ok(Some(undone));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// A short description of a value, showing at most its first bytes.
+fn describe(value: Option<&[u8]>) -> String {
+    const SHOWN: usize = 16;
+    match value {
+        None => "none".to_string(),
+        Some(value) if value.len() <= SHOWN => format!("0x{}", hex(value)),
+        Some(value) => format!("0x{}... ({} bytes)", hex(&value[..SHOWN]), value.len()),
+    }
+}
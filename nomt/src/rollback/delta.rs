@@ -1,3 +1,4 @@
+use crate::fatal::FatalError;
 use nomt_core::trie::KeyPath;
 use std::{
     collections::HashMap,
@@ -97,7 +98,16 @@ impl Delta {
             // Read the value.
             let mut value = Vec::new();
             reader.read_exact(&mut buf)?;
-            let value_len = u32::from_le_bytes(buf);
+            let value_len = u32::from_le_bytes(buf) as u64;
+            let remaining =
+                (reader.get_ref().as_ref().len() as u64).saturating_sub(reader.position());
+            if value_len > remaining {
+                return Err(FatalError::Corrupted(format!(
+                    "rollback delta value of {} bytes with {} bytes left",
+                    value_len, remaining
+                ))
+                .into());
+            }
             value.resize(value_len as usize, 0);
             reader.read_exact(&mut value)?;
             let preempted = priors.insert(key_path, Some(value)).is_some();
@@ -126,6 +136,21 @@ mod tests {
         assert_eq!(delta.priors, delta2.priors);
     }
 
+    #[test]
+    fn delta_with_oversized_value_is_corrupted() {
+        let mut delta = Delta::empty();
+        delta.priors.insert([1; 32], Some(b"value1".to_vec()));
+
+        let mut buf = delta.encode();
+        // the length of the value follows the erase count, the reinstate count and the key.
+        buf[4 + 4 + 32..4 + 4 + 32 + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = Delta::decode(&mut Cursor::new(&mut buf)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<FatalError>(),
+            Some(FatalError::Corrupted(_))
+        ));
+    }
+
     #[test]
     fn delta_roundtrip_empty() {
         let delta = Delta::empty();
@@ -3,22 +3,26 @@
 //! The rollback log maintains a list of reverse deltas. A reverse delta contains the prior value
 //! for every key that was modified or deleted.
 //!
-//! The deltas are stored in an in-memory ring buffer. When the buffer size reaches the limit, the
-//! oldest deltas are discarded to make space for new ones.
+//! The deltas are stored in an in-memory ring buffer. The oldest deltas are discarded according
+//! to the [`RetentionPolicy`], unless they are needed to roll back to a pinned root.
 //!
-//! The deltas are also persisted on disk in a [`seglog`].
+//! The deltas are also persisted on disk in a [`seglog`]. Every record consists of the encoded
+//! delta followed by a trailer holding the root the delta reverts to and the commit time.
 
 use std::{
     collections::{BTreeMap, HashMap, VecDeque},
     fs::File,
-    io::Cursor,
+    io::{Cursor, Read as _},
     path::PathBuf,
     sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
+    options::RetentionPolicy,
     overlay::LiveOverlay,
-    task::{join_task, spawn_task, TaskResult},
+    store::RootPins,
+    task::{join_fallible_task, join_task, spawn_task, TaskResult},
 };
 use crossbeam::channel::Sender;
 use crossbeam_channel::Receiver;
@@ -42,15 +46,24 @@ mod tests;
 
 const MAX_SEGMENT_SIZE: u64 = 64 * 1024 * 1024; // 64 MiB
 
+struct LogEntry {
+    record_id: RecordId,
+    delta: Delta,
+    /// The root the delta reverts to. `None` for deltas written without a trailer.
+    prev_root: Option<[u8; 32]>,
+    /// The commit time in seconds since the UNIX epoch.
+    committed_at: u64,
+}
+
 struct InMemory {
     /// The log of deltas that we have accumulated so far.
     ///
-    /// The items are pushed onto the back and popped from the front. When the log reaches
-    /// [`Shared::max_rollback_log_len`], the oldest delta is discarded.
+    /// The items are pushed onto the back and popped from the front. Deltas falling out of
+    /// [`Shared::retention`] are discarded from the front.
     ///
     /// The deltas are stored in-memory even after they are dumped on disk. Upon restart, the deltas
     /// are re-read from disk and stored here.
-    log: VecDeque<(RecordId, Delta)>,
+    log: VecDeque<LogEntry>,
 
     /// If this is set, then the next writeout will truncate the log at this offset.
     pending_truncate: Option<u64>,
@@ -61,9 +74,10 @@ struct Shared {
     sync_tp: ThreadPool,
     in_memory: Mutex<InMemory>,
     seglog: Mutex<SegmentedLog>,
-    /// The number of items that we should keep in the log. Deltas that are past this limit are
-    /// discarded.
-    max_rollback_log_len: usize,
+    /// Which deltas we should keep in the log. Deltas that fall out of it are discarded.
+    retention: RetentionPolicy,
+    /// Roots which must remain reachable by rolling back.
+    pins: Arc<RootPins>,
 }
 
 impl InMemory {
@@ -75,15 +89,15 @@ impl InMemory {
     }
 
     /// Push a delta into the in-memory cache.
-    fn push_recent(&mut self, record_id: RecordId, delta: Delta) {
-        self.log.push_back((record_id, delta));
+    fn push_recent(&mut self, entry: LogEntry) {
+        self.log.push_back(entry);
     }
 
-    fn pop_recent(&mut self) -> Option<(RecordId, Delta)> {
+    fn pop_recent(&mut self) -> Option<LogEntry> {
         self.log.pop_back()
     }
 
-    fn pop_oldest(&mut self) -> Option<(RecordId, Delta)> {
+    fn pop_oldest(&mut self) -> Option<LogEntry> {
         self.log.pop_front()
     }
 
@@ -104,11 +118,12 @@ pub struct Rollback {
 
 impl Rollback {
     pub fn read(
-        max_rollback_log_len: u32,
+        retention: RetentionPolicy,
         db_dir_path: PathBuf,
         db_dir_fd: Arc<File>,
         rollback_start_active: u64,
         rollback_end_active: u64,
+        pins: Arc<RootPins>,
     ) -> anyhow::Result<Self> {
         let mut in_memory = InMemory::new();
         let seglog = seglog::open(
@@ -121,7 +136,13 @@ impl Rollback {
             |record_id, payload| {
                 let mut cursor = Cursor::new(payload);
                 let delta = Delta::decode(&mut cursor)?;
-                in_memory.push_recent(record_id, delta);
+                let (prev_root, committed_at) = decode_trailer(&mut cursor);
+                in_memory.push_recent(LogEntry {
+                    record_id,
+                    delta,
+                    prev_root,
+                    committed_at,
+                });
                 Ok(())
             },
         )?;
@@ -130,7 +151,8 @@ impl Rollback {
             sync_tp: ThreadPool::with_name("rollback-sync".into(), 1),
             in_memory: Mutex::new(in_memory),
             seglog: Mutex::new(seglog),
-            max_rollback_log_len: max_rollback_log_len as usize,
+            retention,
+            pins,
         });
         Ok(Self { shared })
     }
@@ -157,18 +179,26 @@ impl Rollback {
         }
     }
 
-    /// Saves the delta into the log.
+    /// Saves the delta of a commit made on top of `prev_root` into the log.
     ///
     /// This function accepts the final list of operations that should be performed sorted by the
     /// key paths in ascending order.
-    pub fn commit(&self, delta: Delta) -> anyhow::Result<()> {
-        let delta_bytes = delta.encode();
+    pub fn commit(&self, prev_root: [u8; 32], delta: Delta) -> anyhow::Result<()> {
+        let committed_at = unix_now();
+        let mut delta_bytes = delta.encode();
+        delta_bytes.extend_from_slice(&prev_root);
+        delta_bytes.extend_from_slice(&committed_at.to_le_bytes());
 
         let mut in_memory = self.shared.in_memory.lock();
         let mut seglog = self.shared.seglog.lock();
 
         let record_id = seglog.append(&delta_bytes)?;
-        in_memory.push_recent(record_id, delta);
+        in_memory.push_recent(LogEntry {
+            record_id,
+            delta,
+            prev_root: Some(prev_root),
+            committed_at,
+        });
         Ok(())
     }
 
@@ -178,9 +208,13 @@ impl Rollback {
     /// the state as it was before the last `n` deltas were applied.
     ///
     /// This function is destructive and consumes the rollback log.
+    ///
+    /// Fails without touching the log if rolling back from `current_root` would discard a pinned
+    /// root, that is, if the current root or a root between it and the restored one is pinned.
     pub fn truncate(
         &self,
         mut n: usize,
+        current_root: [u8; 32],
     ) -> anyhow::Result<Option<BTreeMap<KeyPath, Option<Vec<u8>>>>> {
         assert!(n > 0);
         let mut in_memory = self.shared.in_memory.lock();
@@ -188,6 +222,24 @@ impl Rollback {
             return Ok(None);
         }
 
+        // the root each removed delta reverts to is discarded too, except for the oldest one's.
+        let discarded = std::iter::once(Some(current_root)).chain(
+            in_memory
+                .log
+                .iter()
+                .rev()
+                .take(n - 1)
+                .map(|entry| entry.prev_root),
+        );
+        for root in discarded.flatten() {
+            if self.shared.pins.is_pinned(&root) {
+                anyhow::bail!(
+                    "rollback: would discard the pinned root {}",
+                    crate::Root::from(root)
+                );
+            }
+        }
+
         let mut traceback = BTreeMap::new();
         let mut earliest_record_id = None;
         while n > 0 {
@@ -196,9 +248,9 @@ impl Rollback {
             //
             // UNWRAP: we checked above that `n` is greater or equal to the total number of deltas
             //         and `n` is strictly decreasing.
-            let (record_id, delta) = in_memory.pop_recent().unwrap();
-            earliest_record_id = Some(record_id);
-            for (key, value) in delta.priors {
+            let entry = in_memory.pop_recent().unwrap();
+            earliest_record_id = Some(entry.record_id);
+            for (key, value) in entry.delta.priors {
                 traceback.insert(key, value);
             }
             n -= 1;
@@ -223,6 +275,49 @@ impl Rollback {
         Ok(Some(traceback))
     }
 
+    /// Returns the keys and values that we should apply to the database to restore the state as
+    /// it was at `root`, without touching the log.
+    ///
+    /// Returns `None` if no logged delta reverts to `root`.
+    pub fn traceback_to(&self, root: [u8; 32]) -> Option<BTreeMap<KeyPath, Option<Vec<u8>>>> {
+        let in_memory = self.shared.in_memory.lock();
+        let mut traceback = BTreeMap::new();
+        for entry in in_memory.log.iter().rev() {
+            // older deltas overwrite the values of more recent ones, like in `truncate`.
+            for (key, value) in &entry.delta.priors {
+                traceback.insert(*key, value.clone());
+            }
+            if entry.prev_root == Some(root) {
+                return Some(traceback);
+            }
+        }
+        None
+    }
+
+    /// Returns every commit in the log, oldest first.
+    pub fn logged_commits(&self) -> Vec<crate::replay::LoggedCommit> {
+        let in_memory = self.shared.in_memory.lock();
+        in_memory
+            .log
+            .iter()
+            .map(|entry| {
+                let mut priors = entry
+                    .delta
+                    .priors
+                    .iter()
+                    .map(|(key, value)| (*key, value.clone()))
+                    .collect::<Vec<_>>();
+                priors.sort_by_key(|(key, _)| *key);
+                crate::replay::LoggedCommit {
+                    id: entry.record_id.0,
+                    prev_root: entry.prev_root.map(crate::Root::from),
+                    committed_at: entry.committed_at,
+                    priors,
+                }
+            })
+            .collect()
+    }
+
     /// Returns a controller for the sync process.
     pub fn sync(&self) -> SyncController {
         SyncController::new(self.clone())
@@ -246,11 +341,27 @@ impl Rollback {
             };
         }
 
-        let prune_to_new_start_live = if in_memory.total_len() > self.shared.max_rollback_log_len {
-            Some(in_memory.pop_oldest().unwrap().0.next().0)
-        } else {
-            None
-        };
+        let now = unix_now();
+        let mut prune_to_new_start_live = None;
+        while let Some(oldest) = in_memory.log.front() {
+            let expired = match self.shared.retention {
+                RetentionPolicy::Commits(n) => in_memory.total_len() > n as usize,
+                RetentionPolicy::Duration(d) => {
+                    oldest.committed_at.saturating_add(d.as_secs()) < now
+                }
+            };
+            // rolling back to a pinned root requires all deltas from the one reverting to it.
+            let is_pinned = oldest
+                .prev_root
+                .is_some_and(|root| self.shared.pins.is_pinned(&root));
+            if !expired || is_pinned {
+                break;
+            }
+
+            // UNWRAP: checked above that the log is not empty.
+            let entry = in_memory.pop_oldest().unwrap();
+            prune_to_new_start_live = Some(entry.record_id.next().0);
+        }
 
         let (rollback_start_live, rollback_end_live) = seglog.live_range();
 
@@ -291,6 +402,23 @@ impl Rollback {
     }
 }
 
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+// Decode the trailer following a delta. Deltas written without a trailer are treated as if they
+// had been committed just now.
+fn decode_trailer(cursor: &mut Cursor<impl AsRef<[u8]>>) -> (Option<[u8; 32]>, u64) {
+    let mut prev_root = [0; 32];
+    let mut committed_at = [0; 8];
+    if cursor.read_exact(&mut prev_root).is_err() || cursor.read_exact(&mut committed_at).is_err() {
+        return (None, unix_now());
+    }
+    (Some(prev_root), u64::from_le_bytes(committed_at))
+}
+
 pub struct SyncController {
     rollback: Rollback,
     writeout_data: Option<WriteoutData>,
@@ -344,7 +472,7 @@ impl SyncController {
 
     /// Wait until the post-meta writeout completes.
     pub fn wait_post_meta(&self) -> std::io::Result<()> {
-        join_task(&self.post_meta_result_rx)
+        join_fallible_task(&self.post_meta_result_rx)
     }
 }
 
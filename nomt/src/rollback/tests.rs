@@ -1,13 +1,25 @@
-use std::{collections::BTreeSet, fs::OpenOptions, sync::Arc};
+use std::{
+    collections::BTreeSet,
+    fs::{File, OpenOptions},
+    path::Path,
+    sync::Arc,
+};
 
 use super::{
-    reverse_delta_worker::AsyncPending, BTreeMap, KeyPath, KeyReadWrite, LoadValueAsync, Rollback,
+    reverse_delta_worker::AsyncPending, BTreeMap, KeyPath, KeyReadWrite, LoadValueAsync,
+    RetentionPolicy, Rollback,
 };
+use crate::store::RootPins;
 use crossbeam::channel::{Receiver, Sender};
 use hex_literal::hex;
 
 const MAX_ROLLBACK_LOG_LEN: u32 = 100;
 
+fn root_pins(db_dir_path: &Path) -> Arc<RootPins> {
+    let db_dir_fd = Arc::new(File::open(db_dir_path).unwrap());
+    Arc::new(RootPins::open(db_dir_path, db_dir_fd, false).unwrap())
+}
+
 /// A mock implementation of `LoadValue` for testing. Describes the "current" state of the
 /// database.
 #[derive(Clone)]
@@ -133,8 +145,15 @@ fn truncate_works() {
         Some(b"old_value3".to_vec()),
     );
 
-    let rollback =
-        Rollback::read(MAX_ROLLBACK_LOG_LEN, db_dir_path, Arc::new(db_dir_fd), 0, 0).unwrap();
+    let rollback = Rollback::read(
+        RetentionPolicy::Commits(MAX_ROLLBACK_LOG_LEN),
+        db_dir_path.clone(),
+        Arc::new(db_dir_fd),
+        0,
+        0,
+        root_pins(&db_dir_path),
+    )
+    .unwrap();
     let builder = rollback.delta_builder_inner(store.async_reader());
     builder.tentative_preserve_prior([1; 32]);
     builder.tentative_preserve_prior([2; 32]);
@@ -149,10 +168,10 @@ fn truncate_works() {
             KeyReadWrite::Write(Some(b"new_value2".to_vec())),
         ),
     ]);
-    rollback.commit(delta).unwrap();
+    rollback.commit([0; 32], delta).unwrap();
 
     // We want to see the old values for all the keys that have been changed during the commit.
-    let traceback = rollback.truncate(1).unwrap().unwrap();
+    let traceback = rollback.truncate(1, [0xff; 32]).unwrap().unwrap();
     assert_eq!(traceback.len(), 2);
     assert_eq!(
         traceback
@@ -200,8 +219,15 @@ fn without_tentative_preserve_prior() {
         Some(b"old_value3".to_vec()),
     );
 
-    let rollback =
-        Rollback::read(MAX_ROLLBACK_LOG_LEN, db_dir_path, Arc::new(db_dir_fd), 0, 0).unwrap();
+    let rollback = Rollback::read(
+        RetentionPolicy::Commits(MAX_ROLLBACK_LOG_LEN),
+        db_dir_path.clone(),
+        Arc::new(db_dir_fd),
+        0,
+        0,
+        root_pins(&db_dir_path),
+    )
+    .unwrap();
     let builder = rollback.delta_builder_inner(store.async_reader());
     let delta = builder.finalize(&[
         (
@@ -213,10 +239,10 @@ fn without_tentative_preserve_prior() {
             KeyReadWrite::Write(Some(b"new_value2".to_vec())),
         ),
     ]);
-    rollback.commit(delta).unwrap();
+    rollback.commit([0; 32], delta).unwrap();
 
     // We want to see the old values for all the keys that have been changed during the commit.
-    let traceback = rollback.truncate(1).unwrap().unwrap();
+    let traceback = rollback.truncate(1, [0xff; 32]).unwrap().unwrap();
     assert_eq!(traceback.len(), 2);
     assert_eq!(
         traceback
@@ -256,8 +282,15 @@ fn delta_builder_doesnt_load_read_then_write_priors() {
     let mut store = MockStore::new();
     store.trap(key_1);
 
-    let rollback =
-        Rollback::read(MAX_ROLLBACK_LOG_LEN, db_dir_path, Arc::new(db_dir_fd), 0, 0).unwrap();
+    let rollback = Rollback::read(
+        RetentionPolicy::Commits(MAX_ROLLBACK_LOG_LEN),
+        db_dir_path.clone(),
+        Arc::new(db_dir_fd),
+        0,
+        0,
+        root_pins(&db_dir_path),
+    )
+    .unwrap();
     let builder = rollback.delta_builder_inner(store.async_reader());
     let delta = builder.finalize(&[(
         key_1,
@@ -265,13 +298,13 @@ fn delta_builder_doesnt_load_read_then_write_priors() {
     )]);
 
     rollback
-        .commit(delta)
+        .commit([0; 32], delta)
         // This will panic if the delta builder attempts to load from store the prior value for
         // key_1.
         .unwrap();
 
     // We expect that the traceback will contain the specified prior value for key_1.
-    let traceback = rollback.truncate(1).unwrap().unwrap();
+    let traceback = rollback.truncate(1, [0xff; 32]).unwrap().unwrap();
     assert_eq!(
         traceback.get(&key_1).unwrap(),
         &Some(b"prior_value".to_vec())
@@ -289,14 +322,21 @@ fn rollback_writeout_start_and_end() {
         .unwrap();
     let store = MockStore::new();
 
-    let rollback =
-        Rollback::read(MAX_ROLLBACK_LOG_LEN, db_dir_path, Arc::new(db_dir_fd), 0, 0).unwrap();
+    let rollback = Rollback::read(
+        RetentionPolicy::Commits(MAX_ROLLBACK_LOG_LEN),
+        db_dir_path.clone(),
+        Arc::new(db_dir_fd),
+        0,
+        0,
+        root_pins(&db_dir_path),
+    )
+    .unwrap();
 
     // fill the rollback with the max amount of deltas + 1
     for _ in 0..MAX_ROLLBACK_LOG_LEN + 1 {
         let builder = rollback.delta_builder_inner(store.async_reader());
         let delta = builder.finalize(&[]);
-        rollback.commit(delta).unwrap();
+        rollback.commit([0; 32], delta).unwrap();
     }
 
     // expected prune of oldest delta
@@ -316,7 +356,7 @@ fn rollback_writeout_start_and_end() {
     // expected prune of oldest delta
     let builder = rollback.delta_builder_inner(store.async_reader());
     let delta = builder.finalize(&[]);
-    rollback.commit(delta).unwrap();
+    rollback.commit([0; 32], delta).unwrap();
 
     let wa = rollback.writeout_start();
     assert_eq!(wa.rollback_start_live, 2);
@@ -331,7 +371,7 @@ fn rollback_writeout_start_and_end() {
     assert_eq!(rollback_start_live, 3.into());
     assert_eq!(rollback_end_live, 102.into());
 
-    rollback.truncate(5).unwrap();
+    rollback.truncate(5, [0xff; 32]).unwrap();
 
     // expected prune of 5 newest deltas
     let wa = rollback.writeout_start();
@@ -347,3 +387,87 @@ fn rollback_writeout_start_and_end() {
     assert_eq!(rollback_start_live, 3.into());
     assert_eq!(rollback_end_live, 97.into());
 }
+
+#[test]
+fn pinned_root_blocks_pruning() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db_dir_path = temp_dir.path().join("db");
+    std::fs::create_dir_all(&db_dir_path).unwrap();
+    let db_dir_fd = OpenOptions::new()
+        .read(true)
+        .open(db_dir_path.clone())
+        .unwrap();
+    let store = MockStore::new();
+    let pins = root_pins(&db_dir_path);
+
+    let rollback = Rollback::read(
+        RetentionPolicy::Commits(2),
+        db_dir_path.clone(),
+        Arc::new(db_dir_fd),
+        0,
+        0,
+        pins.clone(),
+    )
+    .unwrap();
+
+    for i in 1..=4 {
+        let builder = rollback.delta_builder_inner(store.async_reader());
+        let delta = builder.finalize(&[]);
+        rollback.commit([i; 32], delta).unwrap();
+    }
+
+    // rolling back to [2; 32] requires the deltas from the second one onwards.
+    pins.pin([2; 32]).unwrap();
+    let wa = rollback.writeout_start();
+    assert_eq!(wa.prune_to_new_start_live, Some(2));
+    rollback
+        .writeout_end(wa.prune_to_new_start_live, wa.prune_to_new_end_live)
+        .unwrap();
+
+    let wa = rollback.writeout_start();
+    assert_eq!(wa.prune_to_new_start_live, None);
+
+    pins.unpin([2; 32]).unwrap();
+    let wa = rollback.writeout_start();
+    assert_eq!(wa.prune_to_new_start_live, Some(3));
+}
+
+#[test]
+fn pinned_root_blocks_truncate() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db_dir_path = temp_dir.path().join("db");
+    std::fs::create_dir_all(&db_dir_path).unwrap();
+    let db_dir_fd = OpenOptions::new()
+        .read(true)
+        .open(db_dir_path.clone())
+        .unwrap();
+    let store = MockStore::new();
+    let pins = root_pins(&db_dir_path);
+
+    let rollback = Rollback::read(
+        RetentionPolicy::Commits(MAX_ROLLBACK_LOG_LEN),
+        db_dir_path.clone(),
+        Arc::new(db_dir_fd),
+        0,
+        0,
+        pins.clone(),
+    )
+    .unwrap();
+
+    for i in 1..=4 {
+        let builder = rollback.delta_builder_inner(store.async_reader());
+        let delta = builder.finalize(&[]);
+        rollback.commit([i; 32], delta).unwrap();
+    }
+
+    // rolling back two commits from [5; 32] discards it and [4; 32], restoring [3; 32].
+    pins.pin([4; 32]).unwrap();
+    pins.pin([3; 32]).unwrap();
+    assert!(rollback.truncate(2, [5; 32]).is_err());
+    pins.pin([5; 32]).unwrap();
+    assert!(rollback.truncate(1, [5; 32]).is_err());
+    pins.unpin([5; 32]).unwrap();
+
+    // rolling back to a pinned root is fine.
+    assert!(rollback.truncate(1, [5; 32]).unwrap().is_some());
+}
@@ -0,0 +1,218 @@
+//! An index of the roots produced by past commits.
+//!
+//! When enabled with [`crate::Options::root_index`], every commit records the root it produced
+//! along with the sequence number it is assigned (see [`crate::Nomt::current_sequence`]), the
+//! root it was applied to and its identifier, if any. The index is kept in memory so a root
+//! received from a peer or found in a block can be resolved to the commit which produced it,
+//! e.g. to decide how far to roll back or where to resume replication.
+//!
+//! The index is persisted in the `root_index` file of the database directory. The file begins with
+//! `MAGIC` and a version byte, followed by fixed-size records laid out as:
+//!   - sequence number of the commit (8 bytes, little-endian)
+//!   - root (32 bytes)
+//!   - flags (1 byte): bit 0 is set if the previous root is known, bit 1 if the commit has an
+//!     identifier
+//!   - previous root (32 bytes, zero if unknown)
+//!   - commit identifier (8 bytes, little-endian, zero if none)
+//!
+//! A record is appended before the commit it describes is synced. When opening, records of
+//! commits which didn't land and a torn record at the end of the file are discarded. The file
+//! grows by one record per commit and is never compacted.
+//!
+//! Enabling the index on an existing database records the current root against the last commit,
+//! without a previous root.
+
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use parking_lot::Mutex;
+
+use crate::{trie::Node, Root};
+
+const ROOT_INDEX_FILE: &str = "root_index";
+const MAGIC: [u8; 8] = *b"NOMTROOT";
+const VERSION: u8 = 1;
+const HEADER_LEN: u64 = 9;
+const RECORD_LEN: u64 = 8 + 32 + 1 + 32 + 8;
+
+const HAS_PREV_ROOT: u8 = 1 << 0;
+const HAS_COMMIT_ID: u8 = 1 << 1;
+
+/// The commit which produced a root. See [`crate::Nomt::lookup_root`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RootInfo {
+    /// The sequence number of the commit. See [`crate::Nomt::current_sequence`].
+    pub sequence: u64,
+    /// The root the commit was applied to, or `None` if the root was recorded when the index was
+    /// enabled.
+    pub prev_root: Option<Root>,
+    /// The identifier of the commit, if it had one. See [`crate::SessionParams::commit_id`].
+    pub commit_id: Option<u64>,
+}
+
+/// A commit to be applied to the index once it is synced.
+pub(crate) struct Recorded {
+    root: Node,
+    info: RootInfo,
+}
+
+pub(crate) struct RootIndex {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    file: File,
+    by_root: HashMap<Node, RootInfo>,
+}
+
+impl RootIndex {
+    /// Open the index of the database at `db_dir_path`, whose last commit has the given sequence
+    /// number and produced the given root, creating it if it doesn't exist.
+    pub fn open(db_dir_path: &Path, root: Root, sequence: u64) -> anyhow::Result<Self> {
+        let path = db_dir_path.join(ROOT_INDEX_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        let file_len = file.metadata()?.len();
+
+        let mut inner = Inner {
+            file: file.try_clone()?,
+            by_root: HashMap::new(),
+        };
+
+        if file_len < HEADER_LEN {
+            // the file is new or its creation was interrupted.
+            file.set_len(0)?;
+            let mut header = MAGIC.to_vec();
+            header.push(VERSION);
+            file.write_all(&header)?;
+
+            let recorded = Recorded {
+                root: root.into_inner(),
+                info: RootInfo {
+                    sequence,
+                    prev_root: None,
+                    commit_id: None,
+                },
+            };
+            file.write_all(&encode_record(&recorded))?;
+            file.sync_all()?;
+            inner.apply(recorded);
+        } else {
+            let mut reader = BufReader::new(&file);
+            let mut header = [0u8; HEADER_LEN as usize];
+            reader.read_exact(&mut header)?;
+            if header[..8] != MAGIC {
+                anyhow::bail!("root index: bad magic");
+            }
+            if header[8] != VERSION {
+                anyhow::bail!("root index: unsupported version {}", header[8]);
+            }
+
+            let mut valid_len = HEADER_LEN;
+            let mut record = [0u8; RECORD_LEN as usize];
+            while valid_len + RECORD_LEN <= file_len {
+                reader.read_exact(&mut record)?;
+                let recorded = decode_record(&record);
+                if recorded.info.sequence > sequence {
+                    // the commit didn't land. neither did any after it.
+                    break;
+                }
+                inner.apply(recorded);
+                valid_len += RECORD_LEN;
+            }
+            if valid_len != file_len {
+                file.set_len(valid_len)?;
+                file.sync_all()?;
+            }
+        }
+        inner.file.seek(SeekFrom::End(0))?;
+
+        Ok(RootIndex {
+            inner: Mutex::new(inner),
+        })
+    }
+
+    /// Persist the root produced by the commit which will be assigned the given sequence number.
+    ///
+    /// The returned record must be passed to [`Self::apply`] once the commit is synced.
+    pub fn append(
+        &self,
+        sequence: u64,
+        prev_root: Root,
+        root: Root,
+        commit_id: Option<u64>,
+    ) -> anyhow::Result<Recorded> {
+        let recorded = Recorded {
+            root: root.into_inner(),
+            info: RootInfo {
+                sequence,
+                prev_root: Some(prev_root),
+                commit_id,
+            },
+        };
+        let mut inner = self.inner.lock();
+        inner.file.write_all(&encode_record(&recorded))?;
+        inner.file.sync_data()?;
+        Ok(recorded)
+    }
+
+    /// Apply the root produced by a synced commit to the index.
+    pub fn apply(&self, recorded: Recorded) {
+        self.inner.lock().apply(recorded);
+    }
+
+    /// The last commit which produced the given root.
+    pub fn lookup(&self, root: Root) -> Option<RootInfo> {
+        self.inner.lock().by_root.get(&root.into_inner()).copied()
+    }
+}
+
+impl Inner {
+    fn apply(&mut self, recorded: Recorded) {
+        self.by_root.insert(recorded.root, recorded.info);
+    }
+}
+
+fn encode_record(recorded: &Recorded) -> Vec<u8> {
+    let info = &recorded.info;
+    let mut flags = 0;
+    if info.prev_root.is_some() {
+        flags |= HAS_PREV_ROOT;
+    }
+    if info.commit_id.is_some() {
+        flags |= HAS_COMMIT_ID;
+    }
+
+    let mut record = Vec::with_capacity(RECORD_LEN as usize);
+    record.extend_from_slice(&info.sequence.to_le_bytes());
+    record.extend_from_slice(&recorded.root);
+    record.push(flags);
+    record.extend_from_slice(&info.prev_root.map_or([0; 32], Root::into_inner));
+    record.extend_from_slice(&info.commit_id.unwrap_or(0).to_le_bytes());
+    record
+}
+
+fn decode_record(record: &[u8; RECORD_LEN as usize]) -> Recorded {
+    // UNWRAP: the slices have the lengths of the fields.
+    let sequence = u64::from_le_bytes(record[0..8].try_into().unwrap());
+    let root: Node = record[8..40].try_into().unwrap();
+    let flags = record[40];
+    let prev_root: Node = record[41..73].try_into().unwrap();
+    let commit_id = u64::from_le_bytes(record[73..81].try_into().unwrap());
+    Recorded {
+        root,
+        info: RootInfo {
+            sequence,
+            prev_root: (flags & HAS_PREV_ROOT != 0).then_some(Root(prev_root)),
+            commit_id: (flags & HAS_COMMIT_ID != 0).then_some(commit_id),
+        },
+    }
+}
@@ -0,0 +1,161 @@
+//! Statistics about the reads made through a session, and a log of the slow ones.
+//!
+//! Reads are served from the overlays, the recently committed changes or the leaf cache when
+//! possible. Otherwise they wait on leaf pages being read from disk. The statistics tell how often
+//! that happened and how long the session spent waiting on reads, which helps telling a cold cache
+//! apart from a slow disk. The slow read log names the keys behind the worst stalls.
+
+use crate::beatree::LeafAccesses;
+use nomt_core::trie::KeyPath;
+use parking_lot::Mutex;
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// The most slow reads kept by a session. Further slow reads are only counted.
+pub const MAX_SLOW_READS: usize = 1024;
+
+/// Statistics about the reads made through a session. See [`crate::Session::stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SessionStats {
+    /// The number of keys read.
+    pub keys_read: u64,
+    /// The number of leaf pages found in the leaf cache.
+    pub leaf_cache_hits: u64,
+    /// The number of leaf pages read from disk.
+    pub leaf_pages_fetched: u64,
+    /// The total time spent waiting on reads.
+    pub stall_time: Duration,
+    /// The number of reads which took longer than the slow read threshold, including those which
+    /// didn't fit in the log.
+    pub slow_reads: u64,
+}
+
+impl SessionStats {
+    /// The fraction of the accessed leaf pages which were found in the leaf cache, between 0 and 1.
+    /// This is 1 if no leaf page was accessed.
+    pub fn cache_hit_ratio(&self) -> f64 {
+        let accessed = self.leaf_cache_hits + self.leaf_pages_fetched;
+        if accessed == 0 {
+            return 1.0;
+        }
+        self.leaf_cache_hits as f64 / accessed as f64
+    }
+}
+
+/// A read which took longer than the threshold set with
+/// [`crate::SessionParams::slow_read_threshold`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlowRead {
+    /// The keys read. More than one for reads of many keys at once.
+    pub key_paths: Vec<KeyPath>,
+    /// How long the read took.
+    pub duration: Duration,
+    /// The number of leaf pages read from disk by the read.
+    pub leaf_pages_fetched: u64,
+}
+
+pub(crate) struct SessionCounters {
+    slow_read_threshold: Option<Duration>,
+    keys_read: AtomicU64,
+    leaf_cache_hits: AtomicU64,
+    leaf_pages_fetched: AtomicU64,
+    stall_nanos: AtomicU64,
+    slow_reads: AtomicU64,
+    slow_read_log: Mutex<Vec<SlowRead>>,
+}
+
+impl SessionCounters {
+    pub fn new(slow_read_threshold: Option<Duration>) -> Self {
+        SessionCounters {
+            slow_read_threshold,
+            keys_read: AtomicU64::new(0),
+            leaf_cache_hits: AtomicU64::new(0),
+            leaf_pages_fetched: AtomicU64::new(0),
+            stall_nanos: AtomicU64::new(0),
+            slow_reads: AtomicU64::new(0),
+            slow_read_log: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Record a read of the given keys.
+    pub fn record(&self, key_paths: &[KeyPath], accesses: LeafAccesses, duration: Duration) {
+        self.keys_read
+            .fetch_add(key_paths.len() as u64, Ordering::Relaxed);
+        self.leaf_cache_hits
+            .fetch_add(accesses.cached as u64, Ordering::Relaxed);
+        self.leaf_pages_fetched
+            .fetch_add(accesses.fetched as u64, Ordering::Relaxed);
+        self.stall_nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+
+        if self.slow_read_threshold.is_some_and(|t| duration > t) {
+            self.slow_reads.fetch_add(1, Ordering::Relaxed);
+            let mut log = self.slow_read_log.lock();
+            if log.len() < MAX_SLOW_READS {
+                log.push(SlowRead {
+                    key_paths: key_paths.to_vec(),
+                    duration,
+                    leaf_pages_fetched: accesses.fetched as u64,
+                });
+            }
+        }
+    }
+
+    pub fn stats(&self) -> SessionStats {
+        SessionStats {
+            keys_read: self.keys_read.load(Ordering::Relaxed),
+            leaf_cache_hits: self.leaf_cache_hits.load(Ordering::Relaxed),
+            leaf_pages_fetched: self.leaf_pages_fetched.load(Ordering::Relaxed),
+            stall_time: Duration::from_nanos(self.stall_nanos.load(Ordering::Relaxed)),
+            slow_reads: self.slow_reads.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn slow_reads(&self) -> Vec<SlowRead> {
+        self.slow_read_log.lock().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SessionCounters, MAX_SLOW_READS};
+    use crate::beatree::LeafAccesses;
+    use std::time::Duration;
+
+    #[test]
+    fn slow_reads_are_logged() {
+        let counters = SessionCounters::new(Some(Duration::from_millis(10)));
+        let fetched = LeafAccesses {
+            cached: 1,
+            fetched: 3,
+        };
+        counters.record(
+            &[[1; 32]],
+            LeafAccesses::default(),
+            Duration::from_millis(1),
+        );
+        counters.record(&[[2; 32], [3; 32]], fetched, Duration::from_millis(20));
+        for _ in 0..MAX_SLOW_READS {
+            counters.record(
+                &[[4; 32]],
+                LeafAccesses::default(),
+                Duration::from_millis(11),
+            );
+        }
+
+        let stats = counters.stats();
+        assert_eq!(stats.keys_read, 3 + MAX_SLOW_READS as u64);
+        assert_eq!(stats.leaf_cache_hits, 1);
+        assert_eq!(stats.leaf_pages_fetched, 3);
+        assert_eq!(stats.cache_hit_ratio(), 0.25);
+        assert_eq!(stats.slow_reads, 1 + MAX_SLOW_READS as u64);
+
+        let slow_reads = counters.slow_reads();
+        assert_eq!(slow_reads.len(), MAX_SLOW_READS);
+        assert_eq!(slow_reads[0].key_paths, vec![[2; 32], [3; 32]]);
+        assert_eq!(slow_reads[0].duration, Duration::from_millis(20));
+        assert_eq!(slow_reads[0].leaf_pages_fetched, 3);
+    }
+}
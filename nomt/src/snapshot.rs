@@ -0,0 +1,318 @@
+//! Snapshots of the key-value state laid out for random access through a memory map.
+//!
+//! Unlike a [`crate::dump`], which must be read sequentially, a snapshot can be opened with
+//! [`Snapshot::open`] and queried directly, without a database, page cache or I/O workers. This
+//! suits analytics jobs working on a frozen copy of the state.
+//!
+//! A snapshot is made of pages of [`PAGE_SIZE`] bytes. The first page is the header:
+//!   - `MAGIC` (8 bytes)
+//!   - format version (1 byte), followed by padding up to byte 16
+//!   - root (32 bytes)
+//!   - number of entries (8 bytes, little-endian)
+//!   - length of the data region in bytes (8 bytes, little-endian)
+//!   - number of page table entries (8 bytes, little-endian)
+//!
+//! The data region starts at the second page and holds every (key, value) pair in key order,
+//! each laid out as in a dump: the key path (32 bytes), the value length (4 bytes, little-endian)
+//! and the value. Entries may span page boundaries.
+//!
+//! The page table starts at the first page boundary after the data region. It has an entry for
+//! every page of the data region in which an entry starts, made of the key of the first entry
+//! starting in the page (32 bytes) and its offset within the data region (8 bytes,
+//! little-endian). A lookup binary-searches the page table and scans at most one page of entries,
+//! plus the value spilling over from it.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    os::fd::AsRawFd as _,
+    path::Path,
+};
+
+use crate::{beatree, dump, io::PAGE_SIZE, manifest, store::Store, trie::KeyPath, Root};
+
+/// The magic bytes at the beginning of every snapshot.
+pub const MAGIC: [u8; 8] = *b"NOMTSNAP";
+
+const VERSION: u8 = 1;
+const ENTRY_HEADER_LEN: usize = 32 + 4;
+const TABLE_ENTRY_LEN: usize = 32 + 8;
+
+/// Write all (key, value) pairs visible through the read transaction to a snapshot at `path`,
+/// along with its manifest.
+///
+/// `root` must be the root of the trie at the time the read transaction was created.
+/// Returns the number of exported entries.
+pub(crate) fn export(
+    store: &Store,
+    read_tx: beatree::ReadTransaction,
+    root: Root,
+    path: &Path,
+) -> anyhow::Result<u64> {
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(&[0; PAGE_SIZE])?;
+
+    let mut data_len = 0u64;
+    let mut table = Vec::new();
+    let mut last_page = None;
+    let entries = dump::for_each_entry(store, &read_tx, |key, value| {
+        let value_len: u32 = value
+            .len()
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("value too large for snapshot"))?;
+
+        let page = data_len / PAGE_SIZE as u64;
+        if last_page != Some(page) {
+            table.extend_from_slice(key);
+            table.extend_from_slice(&data_len.to_le_bytes());
+            last_page = Some(page);
+        }
+
+        file.write_all(key)?;
+        file.write_all(&value_len.to_le_bytes())?;
+        file.write_all(value)?;
+        data_len += (ENTRY_HEADER_LEN + value.len()) as u64;
+        Ok(())
+    })?;
+
+    file.write_all(&vec![0; padding(data_len)])?;
+    let table_entries = (table.len() / TABLE_ENTRY_LEN) as u64;
+    file.write_all(&table)?;
+    file.write_all(&vec![0; padding(table.len() as u64)])?;
+
+    let mut header = [0; PAGE_SIZE];
+    header[..8].copy_from_slice(&MAGIC);
+    header[8] = VERSION;
+    header[16..48].copy_from_slice(&root.into_inner());
+    header[48..56].copy_from_slice(&entries.to_le_bytes());
+    header[56..64].copy_from_slice(&data_len.to_le_bytes());
+    header[64..72].copy_from_slice(&table_entries.to_le_bytes());
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&header)?;
+
+    file.flush()?;
+    file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    manifest::emit(path, root)?;
+
+    Ok(entries)
+}
+
+// The number of bytes needed to pad `len` to a multiple of the page size.
+fn padding(len: u64) -> usize {
+    (len.next_multiple_of(PAGE_SIZE as u64) - len) as usize
+}
+
+/// A read-only, memory-mapped snapshot produced by [`crate::Nomt::export_snapshot`].
+///
+/// The contents are paged in by the operating system on access. A corrupted snapshot may give
+/// incomplete results but is never read out of bounds.
+pub struct Snapshot {
+    ptr: *mut u8,
+    len: usize,
+    root: Root,
+    entries: u64,
+    data_len: usize,
+    table_entries: usize,
+    // Retained for the lifetime of the mapping.
+    _file: File,
+}
+
+// SAFETY: the mapping is read-only and owned by the snapshot.
+unsafe impl Send for Snapshot {}
+unsafe impl Sync for Snapshot {}
+
+impl Snapshot {
+    /// Open and map the snapshot at the given path.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len < PAGE_SIZE || !len.is_multiple_of(PAGE_SIZE) {
+            anyhow::bail!("not a NOMT snapshot");
+        }
+
+        let ptr = unsafe {
+            // SAFETY: the file is mapped read-only and privately, and unmapped on drop.
+            let addr = libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            );
+            if addr == libc::MAP_FAILED {
+                anyhow::bail!("mmap failed: {}", std::io::Error::last_os_error());
+            }
+            // lookups touch pages in no particular order; read-ahead would be wasted.
+            let _ = libc::madvise(addr, len, libc::MADV_RANDOM);
+            addr as *mut u8
+        };
+
+        let mut snapshot = Snapshot {
+            ptr,
+            len,
+            root: Root([0; 32]),
+            entries: 0,
+            data_len: 0,
+            table_entries: 0,
+            _file: file,
+        };
+
+        let header = &snapshot.bytes()[..PAGE_SIZE];
+        if header[..8] != MAGIC {
+            anyhow::bail!("not a NOMT snapshot");
+        }
+        if header[8] != VERSION {
+            anyhow::bail!("unsupported snapshot version {}", header[8]);
+        }
+        let read_u64 =
+            |offset: usize| u64::from_le_bytes(header[offset..offset + 8].try_into().unwrap());
+        let root = Root(header[16..48].try_into().unwrap());
+        let entries = read_u64(48);
+        let data_len = read_u64(56);
+        let table_entries = read_u64(64);
+
+        let data_pages = data_len.div_ceil(PAGE_SIZE as u64);
+        let table_pages = table_entries
+            .checked_mul(TABLE_ENTRY_LEN as u64)
+            .map(|table_len| table_len.div_ceil(PAGE_SIZE as u64));
+        if table_pages.and_then(|table_pages| table_pages.checked_add(data_pages))
+            != Some((len / PAGE_SIZE) as u64 - 1)
+        {
+            anyhow::bail!("snapshot corrupted; unexpected file length");
+        }
+
+        snapshot.root = root;
+        snapshot.entries = entries;
+        snapshot.data_len = data_len as usize;
+        snapshot.table_entries = table_entries as usize;
+        Ok(snapshot)
+    }
+
+    /// The root of the trie the snapshot was taken from.
+    pub fn root(&self) -> Root {
+        self.root
+    }
+
+    /// The number of entries in the snapshot.
+    pub fn len(&self) -> u64 {
+        self.entries
+    }
+
+    /// Whether the snapshot has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    /// Returns the value stored under the given key, if any.
+    pub fn get(&self, key: &KeyPath) -> Option<&[u8]> {
+        self.iter_from(key)
+            .next()
+            .filter(|(k, _)| *k == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Iterate over all entries, in key order.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            data: self.data(),
+            offset: 0,
+        }
+    }
+
+    /// Iterate over the entries with keys greater than or equal to `start`, in key order.
+    pub fn iter_from(&self, start: &KeyPath) -> Iter<'_> {
+        // the last page whose first entry is at or before `start`.
+        let index = self
+            .partition_point(|table_key| table_key <= start)
+            .saturating_sub(1);
+        let mut iter = Iter {
+            data: self.data(),
+            offset: self.table_entry(index).map_or(0, |(_, offset)| offset),
+        };
+        while iter.peek_key().is_some_and(|key| key < start) {
+            iter.next();
+        }
+        iter
+    }
+
+    fn bytes(&self) -> &[u8] {
+        // SAFETY: the mapping is `len` bytes long and lives as long as `self`.
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.bytes()[PAGE_SIZE..PAGE_SIZE + self.data_len]
+    }
+
+    fn table_entry(&self, index: usize) -> Option<(&KeyPath, usize)> {
+        if index >= self.table_entries {
+            return None;
+        }
+        let table_start = PAGE_SIZE + self.data_len.next_multiple_of(PAGE_SIZE);
+        let start = table_start + index * TABLE_ENTRY_LEN;
+        let entry = &self.bytes()[start..start + TABLE_ENTRY_LEN];
+        // UNWRAP: the slices have the right lengths.
+        let key = entry[..32].try_into().unwrap();
+        let offset = u64::from_le_bytes(entry[32..].try_into().unwrap());
+        Some((key, offset.min(self.data_len as u64) as usize))
+    }
+
+    // The index of the first table entry whose key doesn't satisfy `pred`, assuming the table is
+    // partitioned by it.
+    fn partition_point(&self, mut pred: impl FnMut(&KeyPath) -> bool) -> usize {
+        let (mut low, mut high) = (0, self.table_entries);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            // UNWRAP: `mid` is less than the number of table entries.
+            if pred(self.table_entry(mid).unwrap().0) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = libc::munmap(self.ptr as *mut _, self.len);
+        }
+    }
+}
+
+/// An iterator over the entries of a [`Snapshot`], in key order.
+pub struct Iter<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iter<'a> {
+    fn peek_key(&self) -> Option<&'a KeyPath> {
+        let data = self.data;
+        data.get(self.offset..self.offset + 32)
+            .map(|key| key.try_into().unwrap())
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a KeyPath, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let data = self.data;
+        let header = data.get(self.offset..self.offset + ENTRY_HEADER_LEN)?;
+        // UNWRAP: the slices have the right lengths.
+        let key = header[..32].try_into().unwrap();
+        let value_len = u32::from_le_bytes(header[32..].try_into().unwrap()) as usize;
+        let value_start = self.offset + ENTRY_HEADER_LEN;
+        let Some(value) = data.get(value_start..value_start + value_len) else {
+            // corrupted. end the iteration.
+            self.offset = data.len();
+            return None;
+        };
+        self.offset = value_start + value_len;
+        Some((key, value))
+    }
+}
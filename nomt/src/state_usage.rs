@@ -0,0 +1,530 @@
+//! Accounting of the keys and value bytes stored under prefixes of the key space.
+//!
+//! When enabled with [`crate::Options::state_usage`], the number of keys and value bytes is
+//! tracked for every prefix of the configured length, and every commit records what it added to
+//! and removed from each of them. Embedders can then charge rent or enforce quotas on the state
+//! of an account, typically stored under a prefix of its own, from NOMT's own data: see
+//! [`crate::Nomt::state_usage`] and [`crate::Nomt::state_usage_since`]. Usage under shorter
+//! prefixes is summed from the tracked ones. Committing looks up the prior values of the written
+//! keys.
+//!
+//! The accounting is persisted in the `state_usage` file of the database directory. The file
+//! begins with `MAGIC`, a version byte and the length of the tracked prefixes in bits, followed by
+//! records. Each record is laid out as:
+//!   - payload length (8 bytes, little-endian)
+//!   - kind (1 byte): 0 for a snapshot of the usage of every prefix, 1 for the changes of a commit
+//!   - sequence number of the commit (8 bytes, little-endian)
+//!   - number of prefixes (4 bytes, little-endian)
+//!   - prefixes, each made of the prefix (8 bytes), keys added, keys removed, value bytes added
+//!     and value bytes removed (8 bytes each, little-endian). Snapshots only add.
+//!
+//! A record is appended before the commit it describes is synced. When opening, records of
+//! commits which didn't land and a torn record at the end of the file are discarded. Once the file
+//! holds twice as many records as the retained history, it is rewritten as a snapshot followed by
+//! the retained commits.
+//!
+//! Enabling the accounting on a database with keys, or changing the prefix length, scans the
+//! entire flat store.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs::{File, OpenOptions},
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use bitvec::prelude::*;
+use parking_lot::Mutex;
+
+use crate::{beatree::ValueChange, dump, io, store::Store, trie::KeyPath};
+
+const STATE_USAGE_FILE: &str = "state_usage";
+const STATE_USAGE_TMP_FILE: &str = "state_usage.tmp";
+const MAGIC: [u8; 8] = *b"NOMTUSAG";
+const VERSION: u8 = 1;
+const HEADER_LEN: u64 = 10;
+const RECORD_HEADER_LEN: u64 = 1 + 8 + 4;
+const ENTRY_LEN: u64 = 40;
+
+const KIND_SNAPSHOT: u8 = 0;
+const KIND_COMMIT: u8 = 1;
+
+// The number of records the file may hold in addition to twice the retained history before being
+// rewritten.
+const COMPACTION_SLACK: usize = 16;
+
+/// The keys and value bytes stored under a prefix. See [`crate::Nomt::state_usage`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StateUsage {
+    /// The number of keys.
+    pub keys: u64,
+    /// The total length of the values.
+    pub value_bytes: u64,
+}
+
+/// The keys and value bytes added and removed under a prefix by a range of commits. See
+/// [`crate::Nomt::state_usage_since`].
+///
+/// Overwriting a value counts as removing the prior value and adding the new one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StateUsageDelta {
+    /// The number of keys inserted.
+    pub keys_added: u64,
+    /// The number of keys deleted.
+    pub keys_removed: u64,
+    /// The total length of the values written.
+    pub bytes_added: u64,
+    /// The total length of the values deleted or overwritten.
+    pub bytes_removed: u64,
+}
+
+impl StateUsageDelta {
+    /// The change in the number of keys.
+    pub fn net_keys(&self) -> i64 {
+        self.keys_added as i64 - self.keys_removed as i64
+    }
+
+    /// The change in the total length of the values.
+    pub fn net_bytes(&self) -> i64 {
+        self.bytes_added as i64 - self.bytes_removed as i64
+    }
+
+    fn add(&mut self, other: &StateUsageDelta) {
+        self.keys_added += other.keys_added;
+        self.keys_removed += other.keys_removed;
+        self.bytes_added += other.bytes_added;
+        self.bytes_removed += other.bytes_removed;
+    }
+}
+
+impl StateUsage {
+    fn apply(&mut self, delta: &StateUsageDelta) {
+        self.keys = self.keys + delta.keys_added - delta.keys_removed;
+        self.value_bytes = self.value_bytes + delta.bytes_added - delta.bytes_removed;
+    }
+
+    fn revert(&mut self, delta: &StateUsageDelta) {
+        self.keys = self.keys + delta.keys_removed - delta.keys_added;
+        self.value_bytes = self.value_bytes + delta.bytes_removed - delta.bytes_added;
+    }
+}
+
+/// The changes made by a commit, to be applied to the accounting once the commit is synced.
+pub(crate) struct Changes {
+    sequence: u64,
+    deltas: BTreeMap<u64, StateUsageDelta>,
+}
+
+pub(crate) struct StateUsageIndex {
+    db_dir_path: PathBuf,
+    prefix_len: u8,
+    max_history: usize,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    file: File,
+    file_len: u64,
+    records: usize,
+    totals: BTreeMap<u64, StateUsage>,
+    // the sequence number of the last commit not in the history.
+    base_sequence: u64,
+    history: VecDeque<Changes>,
+}
+
+impl StateUsageIndex {
+    /// Open the accounting of the database at `db_dir_path`, whose last commit has the given
+    /// sequence number, creating it if it doesn't exist or tracks prefixes of another length.
+    ///
+    /// The changes of the last `max_history` commits are retained.
+    pub fn open(
+        db_dir_path: &Path,
+        store: &Store,
+        sequence: u64,
+        prefix_len: u8,
+        max_history: usize,
+    ) -> anyhow::Result<Self> {
+        let path = db_dir_path.join(STATE_USAGE_FILE);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        let mut inner = Inner {
+            file_len: file.metadata()?.len(),
+            file: file.try_clone()?,
+            records: 0,
+            totals: BTreeMap::new(),
+            base_sequence: sequence,
+            history: VecDeque::new(),
+        };
+
+        let reader = if inner.file_len < HEADER_LEN {
+            None
+        } else {
+            Some(RecordReader::new(BufReader::new(&file))?).filter(|r| r.prefix_len == prefix_len)
+        };
+        match reader {
+            Some(mut reader) => {
+                while let Some((kind, changes)) = reader.next_record()? {
+                    if changes.sequence > sequence {
+                        // the commit didn't land. neither did any after it.
+                        break;
+                    }
+                    if kind == KIND_SNAPSHOT {
+                        inner.totals = changes
+                            .deltas
+                            .iter()
+                            .map(|(prefix, delta)| {
+                                let usage = StateUsage {
+                                    keys: delta.keys_added,
+                                    value_bytes: delta.bytes_added,
+                                };
+                                (*prefix, usage)
+                            })
+                            .collect();
+                        inner.history.clear();
+                        inner.base_sequence = changes.sequence;
+                    } else {
+                        inner.apply(changes);
+                        inner.trim_history(max_history);
+                    }
+                    inner.records += 1;
+                }
+                let valid_len = reader.offset;
+                if valid_len != inner.file_len {
+                    file.set_len(valid_len)?;
+                    file.sync_all()?;
+                    inner.file_len = valid_len;
+                }
+            }
+            None => {
+                // the file is new, its creation was interrupted or the prefix length changed.
+                let read_tx = store.read_transaction();
+                dump::for_each_entry(store, &read_tx, |key, value| {
+                    let usage = inner.totals.entry(prefix_of(key, prefix_len)).or_default();
+                    usage.keys += 1;
+                    usage.value_bytes += value.len() as u64;
+                    Ok(())
+                })?;
+                inner.compact(db_dir_path, prefix_len)?;
+            }
+        }
+        inner.file.seek(SeekFrom::End(0))?;
+
+        Ok(StateUsageIndex {
+            db_dir_path: db_dir_path.to_path_buf(),
+            prefix_len,
+            max_history,
+            inner: Mutex::new(inner),
+        })
+    }
+
+    /// Persist the changes made by the commit which will be assigned the given sequence number,
+    /// looking up the prior values of the written keys in the store.
+    ///
+    /// The returned changes must be passed to [`Self::apply`] once the commit is synced.
+    pub fn append(
+        &self,
+        store: &Store,
+        sequence: u64,
+        values: &[(KeyPath, ValueChange)],
+    ) -> anyhow::Result<Changes> {
+        let keys: Vec<KeyPath> = values.iter().map(|(key, _)| *key).collect();
+        let io_handle = store
+            .io_pool()
+            .make_handle()
+            .with_priority(io::IoPriority::Read);
+        let (priors, _) = store.read_transaction().lookup_many(&keys, &io_handle)?;
+
+        let mut deltas = BTreeMap::<u64, StateUsageDelta>::new();
+        for ((key, change), prior) in values.iter().zip(priors) {
+            let delta = deltas.entry(prefix_of(key, self.prefix_len)).or_default();
+            let new_len = match change {
+                ValueChange::Delete => None,
+                ValueChange::Insert(value) | ValueChange::InsertOverflow(value, _) => {
+                    Some(value.len() as u64)
+                }
+            };
+            match (prior, new_len) {
+                (None, None) => {}
+                (None, Some(new_len)) => {
+                    delta.keys_added += 1;
+                    delta.bytes_added += new_len;
+                }
+                (Some(prior), None) => {
+                    delta.keys_removed += 1;
+                    delta.bytes_removed += prior.len() as u64;
+                }
+                (Some(prior), Some(new_len)) => {
+                    delta.bytes_removed += prior.len() as u64;
+                    delta.bytes_added += new_len;
+                }
+            }
+        }
+        deltas.retain(|_, delta| *delta != StateUsageDelta::default());
+
+        let changes = Changes { sequence, deltas };
+        let mut inner = self.inner.lock();
+        let record = encode_record(KIND_COMMIT, sequence, &changes.deltas);
+        inner.file.write_all(&record)?;
+        inner.file.sync_data()?;
+        inner.file_len += record.len() as u64;
+        inner.records += 1;
+        Ok(changes)
+    }
+
+    /// Apply the changes made by a synced commit to the accounting.
+    pub fn apply(&self, changes: Changes) -> anyhow::Result<()> {
+        let mut inner = self.inner.lock();
+        inner.apply(changes);
+        inner.trim_history(self.max_history);
+
+        if inner.records > 2 * inner.history.len() + COMPACTION_SLACK {
+            inner.compact(&self.db_dir_path, self.prefix_len)?;
+        }
+        Ok(())
+    }
+
+    /// The usage under the given prefix.
+    pub fn usage(&self, prefix: &BitSlice<u8, Msb0>) -> anyhow::Result<StateUsage> {
+        let (start, end) = self.prefix_range(prefix)?;
+        let inner = self.inner.lock();
+        let mut usage = StateUsage::default();
+        for (_, prefix_usage) in range(&inner.totals, start, end) {
+            usage.keys += prefix_usage.keys;
+            usage.value_bytes += prefix_usage.value_bytes;
+        }
+        Ok(usage)
+    }
+
+    /// The changes made under the given prefix by the commits with sequence numbers greater than
+    /// `sequence`.
+    pub fn delta_since(
+        &self,
+        prefix: &BitSlice<u8, Msb0>,
+        sequence: u64,
+    ) -> anyhow::Result<StateUsageDelta> {
+        let (start, end) = self.prefix_range(prefix)?;
+        let inner = self.inner.lock();
+        if sequence < inner.base_sequence {
+            anyhow::bail!(
+                "state usage: the changes since commit {} are no longer retained (oldest: {})",
+                sequence,
+                inner.base_sequence,
+            );
+        }
+        let mut delta = StateUsageDelta::default();
+        for changes in inner.history.iter().filter(|c| c.sequence > sequence) {
+            for (_, prefix_delta) in range(&changes.deltas, start, end) {
+                delta.add(prefix_delta);
+            }
+        }
+        Ok(delta)
+    }
+
+    // The range of tracked prefixes under the given prefix, the end being exclusive.
+    fn prefix_range(&self, prefix: &BitSlice<u8, Msb0>) -> anyhow::Result<(u64, Option<u64>)> {
+        if prefix.len() > self.prefix_len as usize {
+            anyhow::bail!(
+                "state usage is tracked for prefixes of up to {} bits, not {}",
+                self.prefix_len,
+                prefix.len(),
+            );
+        }
+        let shift = self.prefix_len as usize - prefix.len();
+        let value = prefix
+            .iter()
+            .fold(0u128, |acc, bit| (acc << 1) | *bit as u128);
+        let start = value << shift;
+        let end = (value + 1) << shift;
+        Ok((start as u64, u64::try_from(end).ok()))
+    }
+}
+
+impl Inner {
+    fn apply(&mut self, changes: Changes) {
+        for (prefix, delta) in changes.deltas.iter() {
+            let usage = self.totals.entry(*prefix).or_default();
+            usage.apply(delta);
+            if *usage == StateUsage::default() {
+                self.totals.remove(prefix);
+            }
+        }
+        self.history.push_back(changes);
+    }
+
+    fn trim_history(&mut self, max_history: usize) {
+        while self.history.len() > max_history {
+            // UNWRAP: the history is not empty.
+            self.base_sequence = self.history.pop_front().unwrap().sequence;
+        }
+    }
+
+    // Replace the file atomically with a snapshot of the usage as of the base sequence number,
+    // followed by the retained history.
+    fn compact(&mut self, db_dir_path: &Path, prefix_len: u8) -> anyhow::Result<()> {
+        let mut base = self.totals.clone();
+        for changes in self.history.iter() {
+            for (prefix, delta) in changes.deltas.iter() {
+                base.entry(*prefix).or_default().revert(delta);
+            }
+        }
+        let snapshot = base
+            .into_iter()
+            .filter(|(_, usage)| *usage != StateUsage::default())
+            .map(|(prefix, usage)| {
+                let delta = StateUsageDelta {
+                    keys_added: usage.keys,
+                    bytes_added: usage.value_bytes,
+                    ..Default::default()
+                };
+                (prefix, delta)
+            })
+            .collect();
+
+        let mut buf = MAGIC.to_vec();
+        buf.push(VERSION);
+        buf.push(prefix_len);
+        buf.extend(encode_record(KIND_SNAPSHOT, self.base_sequence, &snapshot));
+        for changes in self.history.iter() {
+            buf.extend(encode_record(
+                KIND_COMMIT,
+                changes.sequence,
+                &changes.deltas,
+            ));
+        }
+
+        let tmp_path = db_dir_path.join(STATE_USAGE_TMP_FILE);
+        let mut tmp_file = File::create(&tmp_path)?;
+        tmp_file.write_all(&buf)?;
+        tmp_file.sync_all()?;
+        std::fs::rename(&tmp_path, db_dir_path.join(STATE_USAGE_FILE))?;
+        File::open(db_dir_path)?.sync_all()?;
+
+        tmp_file.seek(SeekFrom::End(0))?;
+        self.file = tmp_file;
+        self.file_len = buf.len() as u64;
+        self.records = 1 + self.history.len();
+        Ok(())
+    }
+}
+
+// The tracked prefix the key falls under.
+fn prefix_of(key: &KeyPath, prefix_len: u8) -> u64 {
+    if prefix_len == 0 {
+        return 0;
+    }
+    // UNWRAP: a key path is 32 bytes long.
+    u64::from_be_bytes(key[..8].try_into().unwrap()) >> (64 - prefix_len as u32)
+}
+
+fn range<V>(
+    map: &BTreeMap<u64, V>,
+    start: u64,
+    end: Option<u64>,
+) -> impl Iterator<Item = (&u64, &V)> {
+    match end {
+        Some(end) => map.range(start..end),
+        None => map.range(start..),
+    }
+}
+
+fn encode_record(kind: u8, sequence: u64, deltas: &BTreeMap<u64, StateUsageDelta>) -> Vec<u8> {
+    let payload_len = RECORD_HEADER_LEN + deltas.len() as u64 * ENTRY_LEN;
+    let mut record = Vec::with_capacity(8 + payload_len as usize);
+    record.extend_from_slice(&payload_len.to_le_bytes());
+    record.push(kind);
+    record.extend_from_slice(&sequence.to_le_bytes());
+    record.extend_from_slice(&(deltas.len() as u32).to_le_bytes());
+    for (prefix, delta) in deltas {
+        record.extend_from_slice(&prefix.to_le_bytes());
+        record.extend_from_slice(&delta.keys_added.to_le_bytes());
+        record.extend_from_slice(&delta.keys_removed.to_le_bytes());
+        record.extend_from_slice(&delta.bytes_added.to_le_bytes());
+        record.extend_from_slice(&delta.bytes_removed.to_le_bytes());
+    }
+    record
+}
+
+// A reader over the records of the accounting file.
+struct RecordReader<R> {
+    inner: R,
+    prefix_len: u8,
+    // the offset just past the last complete record.
+    offset: u64,
+}
+
+impl<R: Read> RecordReader<R> {
+    fn new(mut inner: R) -> anyhow::Result<Self> {
+        let mut header = [0u8; HEADER_LEN as usize];
+        inner.read_exact(&mut header)?;
+        if header[..8] != MAGIC {
+            anyhow::bail!("state usage: bad magic");
+        }
+        if header[8] != VERSION {
+            anyhow::bail!("state usage: unsupported version {}", header[8]);
+        }
+        Ok(RecordReader {
+            inner,
+            prefix_len: header[9],
+            offset: HEADER_LEN,
+        })
+    }
+
+    // Read the next record along with its kind. Returns `None` at the end of the file or at a
+    // torn record.
+    fn next_record(&mut self) -> anyhow::Result<Option<(u8, Changes)>> {
+        let mut len_buf = [0u8; 8];
+        if !read_full(&mut self.inner, &mut len_buf)? {
+            return Ok(None);
+        }
+        let payload_len = u64::from_le_bytes(len_buf);
+        if payload_len < RECORD_HEADER_LEN {
+            return Ok(None);
+        }
+        let mut payload = vec![0u8; payload_len as usize];
+        if !read_full(&mut self.inner, &mut payload)? {
+            return Ok(None);
+        }
+
+        // UNWRAP: the offsets read are within the payload, whose length is checked below.
+        let read_u64 = |at: usize| u64::from_le_bytes(payload[at..at + 8].try_into().unwrap());
+        let kind = payload[0];
+        let sequence = read_u64(1);
+        // UNWRAP: the payload holds at least a record header.
+        let count = u32::from_le_bytes(payload[9..13].try_into().unwrap()) as u64;
+        if kind > KIND_COMMIT || payload_len != RECORD_HEADER_LEN + count * ENTRY_LEN {
+            anyhow::bail!("state usage: malformed record at offset {}", self.offset);
+        }
+        let deltas = (0..count as usize)
+            .map(|i| {
+                let at = RECORD_HEADER_LEN as usize + i * ENTRY_LEN as usize;
+                let delta = StateUsageDelta {
+                    keys_added: read_u64(at + 8),
+                    keys_removed: read_u64(at + 16),
+                    bytes_added: read_u64(at + 24),
+                    bytes_removed: read_u64(at + 32),
+                };
+                (read_u64(at), delta)
+            })
+            .collect();
+
+        self.offset += 8 + payload_len;
+        Ok(Some((kind, Changes { sequence, deltas })))
+    }
+}
+
+// Fill the buffer. Returns `false` if the end of the input is reached first.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..])? {
+            0 => return Ok(false),
+            n => read += n,
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,114 @@
+//! Fencing of writers, for deployments where a standby takes over a database from a primary.
+//!
+//! The directory lock keeps two writers on one host apart, but a primary cut off by a network
+//! partition may keep running after its lock on shared storage was broken, and resume writing
+//! after a new primary took over. To prevent this, every writer holds a fencing token, a number
+//! which grows with every writer. The token of the latest writer is stored in the `fence` file of
+//! the database directory, and a writer checks that it is still its own before each sync. A
+//! writer which finds a greater token has been fenced off and fails to commit.
+//!
+//! The file holds the token as 8 little-endian bytes.
+
+use anyhow::Result;
+use std::{
+    fs::{File, OpenOptions},
+    os::unix::fs::FileExt as _,
+    path::Path,
+};
+
+const FENCE_FILE: &str = "fence";
+
+pub struct Fence {
+    fd: File,
+    token: u64,
+}
+
+impl Fence {
+    /// Take over the database as its writer.
+    ///
+    /// The writer gets the given token, which must not be less than the token stored by the
+    /// previous writer, or the stored token plus one if `None`.
+    pub fn acquire(db_dir_path: &Path, token: Option<u64>) -> Result<Self> {
+        let fd = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(db_dir_path.join(FENCE_FILE))?;
+        let stored = read_token(&fd)?;
+        let token = match token {
+            Some(token) if stored.is_some_and(|stored| token < stored) => {
+                anyhow::bail!(
+                    "fencing token {} is stale, the database was taken over with token {}",
+                    token,
+                    // UNWRAP: checked above.
+                    stored.unwrap(),
+                )
+            }
+            Some(token) => token,
+            None => stored.map_or(0, |stored| stored + 1),
+        };
+        fd.write_all_at(&token.to_le_bytes(), 0)?;
+        fd.sync_data()?;
+        Ok(Fence { fd, token })
+    }
+
+    /// The token of this writer.
+    pub fn token(&self) -> u64 {
+        self.token
+    }
+
+    /// Check that no other writer took over the database since this one did.
+    pub fn check(&self) -> Result<()> {
+        match read_token(&self.fd)? {
+            Some(stored) if stored == self.token => Ok(()),
+            stored => anyhow::bail!(
+                "writer with fencing token {} was fenced off, the database is held with token {}",
+                self.token,
+                stored.map_or_else(|| "none".to_string(), |stored| stored.to_string()),
+            ),
+        }
+    }
+}
+
+// Read the stored token. `None` if the file is empty.
+fn read_token(fd: &File) -> Result<Option<u64>> {
+    let mut buf = [0; 8];
+    match fd.metadata()?.len() {
+        0 => Ok(None),
+        8 => {
+            fd.read_exact_at(&mut buf, 0)?;
+            Ok(Some(u64::from_le_bytes(buf)))
+        }
+        len => anyhow::bail!("fence corrupted; unexpected length {}", len),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Fence;
+
+    #[test]
+    fn newer_writer_fences_older() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let first = Fence::acquire(dir.path(), None).unwrap();
+        assert_eq!(first.token(), 0);
+        first.check().unwrap();
+
+        let second = Fence::acquire(dir.path(), None).unwrap();
+        assert_eq!(second.token(), 1);
+        assert!(first.check().is_err());
+        second.check().unwrap();
+
+        assert!(Fence::acquire(dir.path(), Some(0)).is_err());
+        second.check().unwrap();
+        let third = Fence::acquire(dir.path(), Some(10)).unwrap();
+        assert!(second.check().is_err());
+        third.check().unwrap();
+
+        // the same writer can take over again with the same token, for example after a restart.
+        let third = Fence::acquire(dir.path(), Some(10)).unwrap();
+        third.check().unwrap();
+    }
+}
@@ -27,6 +27,22 @@ impl Flock {
             }
         }
     }
+
+    /// Take a lock which can be shared with other shared locks, but not with an exclusive one.
+    ///
+    /// The lock file must already exist.
+    pub fn lock_shared(db_dir: &Path, lock_filename: &str) -> anyhow::Result<Self> {
+        let lock_path = db_dir.join(lock_filename);
+
+        let lock_fd = OpenOptions::new().read(true).open(lock_path)?;
+
+        match crate::sys::unix::try_lock_shared(&lock_fd) {
+            Ok(_) => Ok(Self { lock_fd }),
+            Err(e) => {
+                anyhow::bail!("Failed to lock directory: {e}");
+            }
+        }
+    }
 }
 
 impl Drop for Flock {
@@ -1,13 +1,28 @@
 /// The utility functions for handling the metadata file.
+///
+/// The metadata is double-buffered: it is written alternately to the first and the second page of
+/// the file, depending on the parity of the sync sequence number, with a checksum. A write torn by
+/// a power loss leaves the other page, describing the previous sync, intact.
 use anyhow::Result;
 use std::fs::File;
 use std::os::unix::fs::FileExt as _;
 
-use crate::io::{self, PagePool};
+use crate::{
+    commit_signer::MAX_SIGNATURE_LEN,
+    io::{self, PagePool},
+};
 
 pub(crate) const MAGIC: [u8; 4] = *b"NOMT";
-pub(crate) const VERSION: u32 = 1;
-pub(crate) const META_SIZE: usize = 64;
+pub(crate) const VERSION: u32 = 6;
+pub(crate) const META_SIZE: usize = 456;
+// The size of the metadata in version 1, which had neither a root nor a checksum.
+const META_SIZE_V1: usize = 64;
+// The size of the metadata in version 2, which had no record of the last commit.
+const META_SIZE_V2: usize = 104;
+// The size of the metadata in version 3, which had no signature of the root.
+const META_SIZE_V3: usize = 152;
+// The size of the metadata in version 4, which had no Merkle mountain range.
+const META_SIZE_V4: usize = 416;
 
 /// This data structure describes the state of the btree.
 #[derive(Clone, Debug)]
@@ -38,16 +53,34 @@ pub struct Meta {
     pub bitbox_num_pages: u32,
     /// The random seed used for populating the hash-table in a unique way.
     pub bitbox_seed: [u8; 16],
+    /// Whether the hash-table is shadow-paged rather than updated through the WAL. Always false
+    /// for metadata written before version 6.
+    pub bitbox_shadow_paging: bool,
     /// The first live record ID in the rollback seglog.
     pub rollback_start_live: u64,
     /// The last live record ID in the rollback seglog.
     pub rollback_end_live: u64,
+    /// The root of the trie as of the sync. `None` if the metadata was written by version 1.
+    pub root: Option<[u8; 32]>,
+    /// The identifier and the parent root of the commit made by the sync, if it was given an
+    /// identifier.
+    pub last_commit: Option<(u64, [u8; 32])>,
+    /// The signature of the root made by the commit signer, if one was set when the sync was made.
+    pub signature: Option<Vec<u8>>,
+    /// The number of leaves of the Merkle mountain range.
+    pub mmr_leaves: u64,
+    /// The root of the Merkle mountain range.
+    pub mmr_root: [u8; 32],
 }
 
 impl Meta {
-    /// Returns a newly initialized [`Meta`] instance with the given bitbox seed and number of
-    /// pages.
-    pub fn create_new(bitbox_seed: [u8; 16], bitbox_num_pages: u32) -> Self {
+    /// Returns a newly initialized [`Meta`] instance with the given bitbox seed, number of pages
+    /// and commit strategy.
+    pub fn create_new(
+        bitbox_seed: [u8; 16],
+        bitbox_num_pages: u32,
+        bitbox_shadow_paging: bool,
+    ) -> Self {
         Self {
             magic: MAGIC,
             version: VERSION,
@@ -58,8 +91,14 @@ impl Meta {
             sync_seqn: 0,
             bitbox_num_pages,
             bitbox_seed,
+            bitbox_shadow_paging,
             rollback_start_live: 0,
             rollback_end_live: 0,
+            root: Some(nomt_core::trie::TERMINATOR),
+            last_commit: None,
+            signature: None,
+            mmr_leaves: 0,
+            mmr_root: nomt_core::trie::TERMINATOR,
         }
     }
 
@@ -76,10 +115,45 @@ impl Meta {
         buf[32..48].copy_from_slice(&self.bitbox_seed);
         buf[48..56].copy_from_slice(&self.rollback_start_live.to_le_bytes());
         buf[56..64].copy_from_slice(&self.rollback_end_live.to_le_bytes());
+        // UNWRAP: only metadata read from version 1 has no root, and it is never written back.
+        buf[64..96].copy_from_slice(&self.root.unwrap());
+        let (id, parent_root) = self.last_commit.unwrap_or_default();
+        buf[96..104].copy_from_slice(&id.to_le_bytes());
+        buf[104..136].copy_from_slice(&parent_root);
+        buf[136..144].fill(0);
+        buf[136] = self.last_commit.is_some() as u8;
+        buf[137] = self.bitbox_shadow_paging as u8;
+        let signature = self.signature.as_deref().unwrap_or_default();
+        assert!(signature.len() <= MAX_SIGNATURE_LEN);
+        buf[144..146].copy_from_slice(&(signature.len() as u16).to_le_bytes());
+        buf[146..146 + signature.len()].copy_from_slice(signature);
+        buf[146 + signature.len()..408].fill(0);
+        buf[408..416].copy_from_slice(&self.mmr_leaves.to_le_bytes());
+        buf[416..448].copy_from_slice(&self.mmr_root);
+        let checksum = checksum(&buf[..448]);
+        buf[448..456].copy_from_slice(&checksum);
+    }
+
+    /// Decode the metadata, returning `None` if the checksum doesn't match.
+    pub fn decode_checked(buf: &[u8]) -> Option<Self> {
+        let version = u32::from_le_bytes(buf[4..8].try_into().unwrap());
+        if version < 2 {
+            return Some(Self::decode(&buf[..META_SIZE_V1]));
+        }
+        let size = match version {
+            2 => META_SIZE_V2,
+            3 => META_SIZE_V3,
+            4 => META_SIZE_V4,
+            _ => META_SIZE,
+        };
+        if buf[size - 8..size] != checksum(&buf[..size - 8]) {
+            return None;
+        }
+        Some(Self::decode(&buf[..size]))
     }
 
     pub fn decode(buf: &[u8]) -> Self {
-        assert!(buf.len() >= META_SIZE);
+        assert!(buf.len() >= META_SIZE_V1);
         let magic = buf[0..4].try_into().unwrap();
         let version = u32::from_le_bytes(buf[4..8].try_into().unwrap());
         let ln_freelist_pn = u32::from_le_bytes(buf[8..12].try_into().unwrap());
@@ -91,6 +165,24 @@ impl Meta {
         let bitbox_seed = buf[32..48].try_into().unwrap();
         let rollback_start_live = u64::from_le_bytes(buf[48..56].try_into().unwrap());
         let rollback_end_live = u64::from_le_bytes(buf[56..64].try_into().unwrap());
+        let root = (version >= 2).then(|| buf[64..96].try_into().unwrap());
+        let last_commit = (version >= 3 && buf[136] == 1).then(|| {
+            let id = u64::from_le_bytes(buf[96..104].try_into().unwrap());
+            (id, buf[104..136].try_into().unwrap())
+        });
+        let bitbox_shadow_paging = version >= 6 && buf[137] == 1;
+        let signature = if version >= 4 {
+            let len = u16::from_le_bytes(buf[144..146].try_into().unwrap()) as usize;
+            (len > 0).then(|| buf[146..146 + len.min(MAX_SIGNATURE_LEN)].to_vec())
+        } else {
+            None
+        };
+        let (mmr_leaves, mmr_root) = if version >= 5 {
+            let mmr_leaves = u64::from_le_bytes(buf[408..416].try_into().unwrap());
+            (mmr_leaves, buf[416..448].try_into().unwrap())
+        } else {
+            (0, nomt_core::trie::TERMINATOR)
+        };
         Self {
             magic,
             version,
@@ -101,8 +193,14 @@ impl Meta {
             sync_seqn,
             bitbox_num_pages,
             bitbox_seed,
+            bitbox_shadow_paging,
             rollback_start_live,
             rollback_end_live,
+            root,
+            last_commit,
+            signature,
+            mmr_leaves,
+            mmr_root,
         }
     }
 
@@ -137,31 +235,62 @@ impl Meta {
         }
     }
 
-    pub fn read(page_pool: &PagePool, fd: &File) -> std::io::Result<Self> {
-        let page = io::read_page(page_pool, fd, 0)?;
-        let meta = Meta::decode(&page[..META_SIZE]);
-        Ok(meta)
+    /// Read the metadata of the latest sync, from whichever page holds the latest valid copy.
+    pub fn read(page_pool: &PagePool, fd: &File) -> Result<Self> {
+        let mut latest: Option<Meta> = None;
+        for pn in 0..2 {
+            let page = match io::read_page(page_pool, fd, pn) {
+                Ok(page) => page,
+                // the second page is only written by the first odd sync.
+                Err(e) if pn == 1 && e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            };
+            let Some(meta) = Meta::decode_checked(&page[..META_SIZE]) else {
+                continue;
+            };
+            if meta.magic != MAGIC {
+                // a page never written.
+                continue;
+            }
+            if latest.as_ref().is_none_or(|l| meta.sync_seqn > l.sync_seqn) {
+                latest = Some(meta);
+            }
+        }
+
+        latest.ok_or_else(|| anyhow::anyhow!("metadata corrupted: no valid copy"))
     }
 
+    /// Write the metadata to the page for its sync sequence number, and sync it.
     pub fn write(page_pool: &PagePool, fd: &File, meta: &Meta) -> std::io::Result<()> {
         let mut page = page_pool.alloc_fat_page();
         meta.encode_to(&mut page.as_mut()[..META_SIZE]);
-        fd.write_all_at(&page[..], 0)?;
+        let offset = (meta.sync_seqn % 2) as u64 * io::PAGE_SIZE as u64;
+        fd.write_all_at(&page[..], offset)?;
         fd.sync_all()?;
         Ok(())
     }
 }
 
+fn checksum(data: &[u8]) -> [u8; 8] {
+    // UNWRAP: a hash is longer than 8 bytes.
+    blake3::hash(data).as_bytes()[..8].try_into().unwrap()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Meta, META_SIZE};
+    use super::{
+        checksum, Meta, MAX_SIGNATURE_LEN, META_SIZE, META_SIZE_V1, META_SIZE_V2, META_SIZE_V3,
+        META_SIZE_V4, VERSION,
+    };
+    use crate::io::{PagePool, PAGE_SIZE};
     use quickcheck::quickcheck;
+    use std::os::unix::fs::FileExt as _;
 
     impl quickcheck::Arbitrary for Meta {
         fn arbitrary(g: &mut quickcheck::Gen) -> Self {
             Meta {
                 magic: u32::arbitrary(g).to_le_bytes(),
-                version: u32::arbitrary(g),
+                version: VERSION,
                 ln_freelist_pn: u32::arbitrary(g),
                 ln_bump: u32::arbitrary(g),
                 bbn_freelist_pn: u32::arbitrary(g),
@@ -169,8 +298,20 @@ mod tests {
                 sync_seqn: u32::arbitrary(g),
                 bitbox_num_pages: u32::arbitrary(g),
                 bitbox_seed: u128::arbitrary(g).to_le_bytes(),
+                bitbox_shadow_paging: bool::arbitrary(g),
                 rollback_start_live: u64::arbitrary(g),
                 rollback_end_live: u64::arbitrary(g),
+                root: Some(std::array::from_fn(|_| u8::arbitrary(g))),
+                last_commit: Option::<u64>::arbitrary(g)
+                    .map(|id| (id, std::array::from_fn(|_| u8::arbitrary(g)))),
+                signature: Option::<Vec<u8>>::arbitrary(g)
+                    .map(|mut signature| {
+                        signature.truncate(MAX_SIGNATURE_LEN);
+                        signature
+                    })
+                    .filter(|signature| !signature.is_empty()),
+                mmr_leaves: u64::arbitrary(g),
+                mmr_root: std::array::from_fn(|_| u8::arbitrary(g)),
             }
         }
     }
@@ -179,7 +320,7 @@ mod tests {
         fn encode_decode_roundtrip(meta: Meta) -> bool {
             let mut buf = vec![0u8; META_SIZE];
             meta.encode_to(&mut buf);
-            let decoded = Meta::decode(&buf);
+            let decoded = Meta::decode_checked(&buf).unwrap();
 
             meta.magic == decoded.magic &&
             meta.version == decoded.version &&
@@ -190,8 +331,148 @@ mod tests {
             meta.sync_seqn == decoded.sync_seqn &&
             meta.bitbox_num_pages == decoded.bitbox_num_pages &&
             meta.bitbox_seed == decoded.bitbox_seed &&
+            meta.bitbox_shadow_paging == decoded.bitbox_shadow_paging &&
             meta.rollback_start_live == decoded.rollback_start_live &&
-            meta.rollback_end_live == decoded.rollback_end_live
+            meta.rollback_end_live == decoded.rollback_end_live &&
+            meta.root == decoded.root &&
+            meta.last_commit == decoded.last_commit &&
+            meta.signature == decoded.signature &&
+            meta.mmr_leaves == decoded.mmr_leaves &&
+            meta.mmr_root == decoded.mmr_root
         }
     }
+
+    #[test]
+    fn latest_valid_copy_is_read() {
+        let page_pool = PagePool::new();
+        let file = tempfile::tempfile().unwrap();
+
+        let mut meta = Meta::create_new([1; 16], 100, false);
+        Meta::write(&page_pool, &file, &meta).unwrap();
+        assert_eq!(Meta::read(&page_pool, &file).unwrap().sync_seqn, 0);
+
+        meta.sync_seqn = 1;
+        meta.root = Some([1; 32]);
+        Meta::write(&page_pool, &file, &meta).unwrap();
+        meta.sync_seqn = 2;
+        meta.root = Some([2; 32]);
+        Meta::write(&page_pool, &file, &meta).unwrap();
+        let read = Meta::read(&page_pool, &file).unwrap();
+        assert_eq!((read.sync_seqn, read.root), (2, Some([2; 32])));
+
+        // a torn write of the latest copy falls back to the previous one.
+        file.write_all_at(&[0xff; 16], 40).unwrap();
+        let read = Meta::read(&page_pool, &file).unwrap();
+        assert_eq!((read.sync_seqn, read.root), (1, Some([1; 32])));
+
+        file.write_all_at(&[0xff; 16], PAGE_SIZE as u64 + 40)
+            .unwrap();
+        assert!(Meta::read(&page_pool, &file).is_err());
+    }
+
+    #[test]
+    fn version_1_is_read() {
+        let page_pool = PagePool::new();
+        let file = tempfile::tempfile().unwrap();
+
+        let mut meta = Meta::create_new([1; 16], 100, false);
+        meta.sync_seqn = 7;
+        let mut buf = vec![0u8; PAGE_SIZE];
+        meta.encode_to(&mut buf[..META_SIZE]);
+        buf[4..8].copy_from_slice(&1u32.to_le_bytes());
+        buf[META_SIZE_V1..].fill(0);
+        file.write_all_at(&buf, 0).unwrap();
+
+        let read = Meta::read(&page_pool, &file).unwrap();
+        assert_eq!((read.version, read.sync_seqn, read.root), (1, 7, None));
+    }
+
+    #[test]
+    fn version_2_is_read() {
+        let page_pool = PagePool::new();
+        let file = tempfile::tempfile().unwrap();
+
+        let mut meta = Meta::create_new([1; 16], 100, false);
+        meta.sync_seqn = 7;
+        meta.root = Some([3; 32]);
+        meta.last_commit = Some((1, [2; 32]));
+        let mut buf = vec![0u8; PAGE_SIZE];
+        meta.encode_to(&mut buf[..META_SIZE]);
+        buf[4..8].copy_from_slice(&2u32.to_le_bytes());
+        let checksum = checksum(&buf[..META_SIZE_V2 - 8]);
+        buf[META_SIZE_V2 - 8..META_SIZE_V2].copy_from_slice(&checksum);
+        buf[META_SIZE_V2..].fill(0);
+        file.write_all_at(&buf, 0).unwrap();
+
+        let read = Meta::read(&page_pool, &file).unwrap();
+        assert_eq!((read.version, read.sync_seqn), (2, 7));
+        assert_eq!((read.root, read.last_commit), (Some([3; 32]), None));
+    }
+
+    #[test]
+    fn version_3_is_read() {
+        let page_pool = PagePool::new();
+        let file = tempfile::tempfile().unwrap();
+
+        let mut meta = Meta::create_new([1; 16], 100, false);
+        meta.sync_seqn = 7;
+        meta.last_commit = Some((1, [2; 32]));
+        let mut buf = vec![0u8; PAGE_SIZE];
+        meta.encode_to(&mut buf[..META_SIZE]);
+        buf[4..8].copy_from_slice(&3u32.to_le_bytes());
+        let checksum = checksum(&buf[..META_SIZE_V3 - 8]);
+        buf[META_SIZE_V3 - 8..META_SIZE_V3].copy_from_slice(&checksum);
+        buf[META_SIZE_V3..].fill(0xff);
+        file.write_all_at(&buf, 0).unwrap();
+
+        let read = Meta::read(&page_pool, &file).unwrap();
+        assert_eq!((read.version, read.sync_seqn), (3, 7));
+        assert_eq!(
+            (read.last_commit, read.signature),
+            (Some((1, [2; 32])), None)
+        );
+    }
+
+    #[test]
+    fn version_4_is_read() {
+        let page_pool = PagePool::new();
+        let file = tempfile::tempfile().unwrap();
+
+        let mut meta = Meta::create_new([1; 16], 100, false);
+        meta.sync_seqn = 7;
+        meta.signature = Some(vec![4; 64]);
+        meta.mmr_leaves = 5;
+        let mut buf = vec![0u8; PAGE_SIZE];
+        meta.encode_to(&mut buf[..META_SIZE]);
+        buf[4..8].copy_from_slice(&4u32.to_le_bytes());
+        let checksum = checksum(&buf[..META_SIZE_V4 - 8]);
+        buf[META_SIZE_V4 - 8..META_SIZE_V4].copy_from_slice(&checksum);
+        buf[META_SIZE_V4..].fill(0xff);
+        file.write_all_at(&buf, 0).unwrap();
+
+        let read = Meta::read(&page_pool, &file).unwrap();
+        assert_eq!((read.version, read.sync_seqn), (4, 7));
+        assert_eq!(read.signature, Some(vec![4; 64]));
+        assert_eq!((read.mmr_leaves, read.mmr_root), (0, [0; 32]));
+    }
+
+    #[test]
+    fn version_5_is_read() {
+        let page_pool = PagePool::new();
+        let file = tempfile::tempfile().unwrap();
+
+        let mut meta = Meta::create_new([1; 16], 100, true);
+        meta.sync_seqn = 7;
+        let mut buf = vec![0u8; PAGE_SIZE];
+        meta.encode_to(&mut buf[..META_SIZE]);
+        buf[4..8].copy_from_slice(&5u32.to_le_bytes());
+        let checksum = checksum(&buf[..META_SIZE - 8]);
+        buf[META_SIZE - 8..META_SIZE].copy_from_slice(&checksum);
+        file.write_all_at(&buf, 0).unwrap();
+
+        // the byte of the commit strategy is not read before version 6.
+        let read = Meta::read(&page_pool, &file).unwrap();
+        assert_eq!((read.version, read.sync_seqn), (5, 7));
+        assert!(!read.bitbox_shadow_paging);
+    }
 }
@@ -0,0 +1,156 @@
+//! The storage of the Merkle mountain range kept alongside the trie.
+//!
+//! The nodes of the range are stored in the `mmr` file in order of position, 128 to a page. Nodes
+//! are only ever appended, and the nodes of a sync are written and synced before the metadata
+//! which records the new number of leaves. Nodes written past that number by an interrupted sync
+//! are ignored, and overwritten by the next sync.
+
+use anyhow::Result;
+use nomt_core::{
+    hasher::ValueHasher,
+    mmr::{self, MmrProof},
+    trie::Node,
+};
+use std::{fs::File, os::unix::fs::FileExt as _};
+
+use crate::io::{self, PagePool, PAGE_SIZE};
+
+const NODE_SIZE: usize = 32;
+const NODES_PER_PAGE: u64 = (PAGE_SIZE / NODE_SIZE) as u64;
+
+/// The state of the range as of the last sync.
+pub struct MmrState {
+    leaves: u64,
+    peaks: Vec<Node>,
+    root: Node,
+}
+
+impl MmrState {
+    /// Load the state of a range with the given number of leaves and root.
+    pub fn load(file: Option<&MmrFile>, leaves: u64, root: Node) -> Result<Self> {
+        let peaks = match file {
+            Some(file) => mmr::peak_positions(leaves)
+                .into_iter()
+                .map(|position| file.read_node(position))
+                .collect::<std::io::Result<_>>()?,
+            None if leaves == 0 => Vec::new(),
+            None => anyhow::bail!("Merkle mountain range file is missing"),
+        };
+        Ok(MmrState {
+            leaves,
+            peaks,
+            root,
+        })
+    }
+
+    pub fn leaves(&self) -> u64 {
+        self.leaves
+    }
+
+    pub fn root(&self) -> Node {
+        self.root
+    }
+
+    /// Append the given leaf hashes, returning the nodes to write. `None` if there are no leaves.
+    pub fn append<H: ValueHasher>(&self, leaf_hashes: &[Node]) -> Option<MmrAppend> {
+        if leaf_hashes.is_empty() {
+            return None;
+        }
+        let mut peaks = self.peaks.clone();
+        let mut nodes = Vec::new();
+        for (i, leaf) in leaf_hashes.iter().enumerate() {
+            nodes.extend(mmr::append::<H>(self.leaves + i as u64, &mut peaks, *leaf));
+        }
+        let leaves = self.leaves + leaf_hashes.len() as u64;
+        Some(MmrAppend {
+            prev_leaves: self.leaves,
+            leaves,
+            nodes,
+            root: mmr::root::<H>(leaves, &peaks),
+            peaks,
+        })
+    }
+
+    /// Apply an append, once its nodes are written.
+    pub fn apply(&mut self, append: MmrAppend) {
+        self.leaves = append.leaves;
+        self.peaks = append.peaks;
+        self.root = append.root;
+    }
+}
+
+/// The leaves appended by a commit, along with the nodes they create.
+pub struct MmrAppend {
+    /// The number of leaves the append was computed against.
+    pub prev_leaves: u64,
+    pub leaves: u64,
+    pub nodes: Vec<Node>,
+    pub peaks: Vec<Node>,
+    pub root: Node,
+}
+
+/// The file holding the nodes of the range.
+pub struct MmrFile {
+    fd: File,
+    page_pool: PagePool,
+}
+
+impl MmrFile {
+    pub fn new(fd: File, page_pool: PagePool) -> Self {
+        MmrFile { fd, page_pool }
+    }
+
+    fn read_node(&self, position: u64) -> std::io::Result<Node> {
+        let page = io::read_page(&self.page_pool, &self.fd, position / NODES_PER_PAGE)?;
+        let offset = (position % NODES_PER_PAGE) as usize * NODE_SIZE;
+        // UNWRAP: the slice is a node long.
+        Ok(page[offset..offset + NODE_SIZE].try_into().unwrap())
+    }
+
+    /// Build the proof of a leaf in a range with the given number of leaves. `None` if the leaf
+    /// is out of range.
+    pub fn read_proof(&self, leaf_index: u64, leaves: u64) -> Result<Option<MmrProof>> {
+        let Some((siblings, peaks)) = mmr::proof_positions(leaf_index, leaves) else {
+            return Ok(None);
+        };
+        let read_nodes = |positions: Vec<u64>| {
+            positions
+                .into_iter()
+                .map(|position| self.read_node(position))
+                .collect::<std::io::Result<Vec<_>>>()
+        };
+        Ok(Some(MmrProof {
+            leaf_index,
+            leaves,
+            siblings: read_nodes(siblings)?,
+            peaks: read_nodes(peaks)?,
+        }))
+    }
+
+    /// Write the nodes of an append and sync them.
+    pub fn write(&self, append: &MmrAppend) -> std::io::Result<()> {
+        let mut position = mmr::node_count(append.prev_leaves);
+        let mut nodes = &append.nodes[..];
+        while !nodes.is_empty() {
+            let pn = position / NODES_PER_PAGE;
+            let first = (position % NODES_PER_PAGE) as usize;
+            // the nodes before the first one were written by previous syncs and are kept.
+            let mut page = if first == 0 {
+                self.page_pool.alloc_fat_page()
+            } else {
+                io::read_page(&self.page_pool, &self.fd, pn)?
+            };
+            let count = nodes.len().min(NODES_PER_PAGE as usize - first);
+            for (i, node) in nodes[..count].iter().enumerate() {
+                let offset = (first + i) * NODE_SIZE;
+                page[offset..offset + NODE_SIZE].copy_from_slice(node);
+            }
+            page[(first + count) * NODE_SIZE..].fill(0);
+            self.fd.write_all_at(&page[..], pn * PAGE_SIZE as u64)?;
+
+            position += count as u64;
+            nodes = &nodes[count..];
+        }
+        self.fd.sync_data()
+    }
+}
@@ -4,31 +4,44 @@
 //! b-tree key-value storage (beatree).
 
 use crate::{
-    beatree, bitbox,
+    beatree, bitbox, fatal,
     io::{self, page_pool::FatPage, IoPool, PagePool},
+    metrics::Metrics,
     page_cache::{Page, PageCache},
     page_diff::PageDiff,
     rollback::Rollback,
     ValueHasher,
 };
-use flock::Flock;
+use fence::Fence;
 use meta::Meta;
-use nomt_core::{page_id::PageId, trie::KeyPath};
-use parking_lot::Mutex;
+use mmr::{MmrAppend, MmrFile, MmrState};
+use nomt_core::{
+    mmr::MmrProof,
+    page_id::PageId,
+    trie::{KeyPath, Node},
+};
+use parking_lot::{Condvar, Mutex};
 use std::{
     fs::{File, OpenOptions},
+    io::Write as _,
     sync::{atomic::AtomicBool, Arc},
+    time::{Duration, SystemTime},
 };
 
 #[cfg(target_os = "linux")]
 use std::os::unix::fs::OpenOptionsExt as _;
 
+pub use self::flock::Flock;
 pub use self::page_loader::{PageLoad, PageLoader};
+pub use self::root_pins::RootPins;
 pub use bitbox::{BucketIndex, HashTableUtilization, SharedMaybeBucketIndex};
 
+mod fence;
 mod flock;
 mod meta;
+mod mmr;
 mod page_loader;
+mod root_pins;
 mod sync;
 
 /// This is a lightweight handle and can be cloned cheaply.
@@ -36,37 +49,63 @@ mod sync;
 pub struct Store {
     shared: Arc<Shared>,
     sync: Arc<Mutex<sync::Sync>>,
+    /// Notified after every successful sync.
+    synced: Arc<Condvar>,
 }
 
 struct Shared {
     values: beatree::Tree,
     pages: bitbox::DB,
     rollback: Option<Rollback>,
+    root_pins: Arc<RootPins>,
     io_pool: IoPool,
     meta_fd: File,
+    /// The file holding the Merkle mountain range. `None` in read-only mode if it doesn't exist.
+    mmr_file: Option<MmrFile>,
     flock: Option<flock::Flock>,
+    /// The fencing token of this writer. `None` in read-only mode.
+    fence: Option<Fence>,
     poisoned: AtomicBool,
+    /// The time the last sync of this process completed. Kept apart from the sync state, which
+    /// is locked for the duration of a sync.
+    last_sync: Mutex<Option<SystemTime>>,
+    read_only: bool,
+    /// Whether page placement depends only on the commits, see [`crate::Options::deterministic_layout`].
+    deterministic_layout: bool,
+    key_secret: Option<[u8; 32]>,
 
     // Retained for the lifetime of the store.
-    _db_dir_fd: Arc<File>,
+    db_dir_fd: Arc<File>,
 }
 
 impl Store {
     /// Open the store with the provided `Options`.
-    pub fn open(o: &crate::Options, page_pool: PagePool) -> anyhow::Result<Self> {
+    pub fn open(o: &crate::Options, page_pool: PagePool, metrics: Metrics) -> anyhow::Result<Self> {
         let db_dir_fd;
         let flock;
 
         if !o.path.exists() {
+            if o.read_only {
+                anyhow::bail!("database at {} does not exist", o.path.display());
+            }
             // NB: note TOCTOU here. Deemed acceptable for this case.
             (db_dir_fd, flock) = create(&page_pool, &o)?;
         } else {
             let mut options = OpenOptions::new();
             options.read(true);
             db_dir_fd = options.open(&o.path)?;
-            flock = flock::Flock::lock(&o.path, ".lock")?;
+            // Readers share the lock with each other, but not with a writer, which updates the
+            // files in place.
+            flock = if o.read_only {
+                flock::Flock::lock_shared(&o.path, ".lock")?
+            } else {
+                flock::Flock::lock(&o.path, ".lock")?
+            };
         }
         let db_dir_fd = Arc::new(db_dir_fd);
+        let fence = (!o.read_only)
+            .then(|| Fence::acquire(&o.path, o.fencing_token))
+            .transpose()?;
 
         cfg_if::cfg_if! {
             if #[cfg(target_os = "linux")] {
@@ -83,11 +122,20 @@ impl Store {
             }
         }
 
-        let io_pool = io::start_io_pool(o.io_workers, page_pool.clone());
+        let io_pool = match o.shared_io_pool {
+            Some((ref pool, weight)) => pool.io_pool(weight, metrics),
+            None => io::start_io_pool(
+                o.io_workers,
+                o.io_queue_depth,
+                page_pool.clone(),
+                o.io_retry_policy,
+                metrics,
+            ),
+        };
 
         let meta_fd = {
             let mut options = OpenOptions::new();
-            options.read(true).write(true);
+            options.read(true).write(!o.read_only);
             #[cfg(target_os = "linux")]
             if o_direct {
                 options.custom_flags(libc::O_DIRECT);
@@ -97,7 +145,7 @@ impl Store {
 
         let ln_fd = {
             let mut options = OpenOptions::new();
-            options.read(true).write(true);
+            options.read(true).write(!o.read_only);
             #[cfg(target_os = "linux")]
             if o_direct {
                 options.custom_flags(libc::O_DIRECT);
@@ -106,7 +154,7 @@ impl Store {
         };
         let bbn_fd = {
             let mut options = OpenOptions::new();
-            options.read(true).write(true);
+            options.read(true).write(!o.read_only);
             #[cfg(target_os = "linux")]
             if o_direct {
                 options.custom_flags(libc::O_DIRECT);
@@ -115,7 +163,7 @@ impl Store {
         };
         let ht_fd = {
             let mut options = OpenOptions::new();
-            options.read(true).write(true);
+            options.read(true).write(!o.read_only);
             #[cfg(target_os = "linux")]
             if o_direct {
                 options.custom_flags(libc::O_DIRECT);
@@ -124,7 +172,7 @@ impl Store {
         };
         let wal_fd = {
             let options = &mut OpenOptions::new();
-            options.read(true).write(true);
+            options.read(true).write(!o.read_only);
             #[cfg(target_os = "linux")]
             if o_direct {
                 options.custom_flags(libc::O_DIRECT);
@@ -132,6 +180,20 @@ impl Store {
             options.open(&o.path.join("wal"))?
         };
 
+        let mmr_fd = {
+            let mut options = OpenOptions::new();
+            options.read(true).write(!o.read_only).create(!o.read_only);
+            #[cfg(target_os = "linux")]
+            if o_direct {
+                options.custom_flags(libc::O_DIRECT);
+            }
+            match options.open(o.path.join("mmr")) {
+                Ok(fd) => Some(fd),
+                Err(e) if o.read_only && e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e.into()),
+            }
+        };
+
         #[cfg(target_os = "macos")]
         {
             use std::os::fd::AsRawFd as _;
@@ -141,11 +203,21 @@ impl Store {
                 libc::fcntl(bbn_fd.as_raw_fd(), libc::F_NOCACHE, 1);
                 libc::fcntl(ht_fd.as_raw_fd(), libc::F_NOCACHE, 1);
                 libc::fcntl(wal_fd.as_raw_fd(), libc::F_NOCACHE, 1);
+                if let Some(ref mmr_fd) = mmr_fd {
+                    libc::fcntl(mmr_fd.as_raw_fd(), libc::F_NOCACHE, 1);
+                }
             }
         }
 
+        if o.read_only && wal_fd.metadata()?.len() > 0 {
+            anyhow::bail!("database needs recovery, open it for writing first");
+        }
+
         let meta = meta::Meta::read(&page_pool, &meta_fd)?;
         meta.validate()?;
+        let key_secret = read_key_secret(&o.path)?;
+        let mmr_file = mmr_fd.map(|fd| MmrFile::new(fd, page_pool.clone()));
+        let mmr = MmrState::load(mmr_file.as_ref(), meta.mmr_leaves, meta.mmr_root)?;
         let values = beatree::Tree::open(
             page_pool.clone(),
             &io_pool,
@@ -155,45 +227,70 @@ impl Store {
             meta.bbn_bump,
             bbn_fd,
             ln_fd,
-            o.commit_concurrency,
+            // concurrent workers race for pages of the value store, so a deterministic layout
+            // requires a single one.
+            if o.deterministic_layout {
+                1
+            } else {
+                o.commit_concurrency
+            },
             o.leaf_cache_size,
+            beatree::GrowthPolicy {
+                extent_pages: mib_to_pages(o.values_growth_extent),
+                allocate: o.preallocate_values,
+            },
+            if o.read_only {
+                0
+            } else {
+                mib_to_pages(o.values_preallocate_size)
+            },
+            o.discard_freed_pages,
         )?;
         let pages = bitbox::DB::open(
             meta.sync_seqn,
             meta.bitbox_num_pages,
             meta.bitbox_seed,
+            meta.bitbox_shadow_paging,
             page_pool.clone(),
             ht_fd,
             wal_fd,
         )?;
-        let rollback = o
-            .rollback
+        let root_pins = Arc::new(RootPins::open(
+            &o.path,
+            Arc::clone(&db_dir_fd),
+            o.read_only,
+        )?);
+        let rollback = (o.rollback && !o.read_only)
             .then(|| {
                 Rollback::read(
-                    o.max_rollback_log_len,
+                    o.rollback_retention,
                     o.path.clone(),
                     Arc::clone(&db_dir_fd),
                     meta.rollback_start_live,
                     meta.rollback_end_live,
+                    Arc::clone(&root_pins),
                 )
             })
             .transpose()?;
         Ok(Self {
-            sync: Arc::new(Mutex::new(sync::Sync::new(
-                meta.sync_seqn,
-                meta.bitbox_num_pages,
-                meta.bitbox_seed,
-                o.panic_on_sync,
-            ))),
+            sync: Arc::new(Mutex::new(sync::Sync::new(meta, mmr, o.panic_on_sync))),
+            synced: Arc::new(Condvar::new()),
             shared: Arc::new(Shared {
                 rollback,
+                root_pins,
                 values,
                 pages,
                 io_pool,
-                _db_dir_fd: db_dir_fd,
+                db_dir_fd,
                 meta_fd,
+                mmr_file,
                 flock: Some(flock),
+                fence,
                 poisoned: false.into(),
+                last_sync: Mutex::new(None),
+                read_only: o.read_only,
+                deterministic_layout: o.deterministic_layout,
+                key_secret,
             }),
         })
     }
@@ -204,20 +301,134 @@ impl Store {
             .load(std::sync::atomic::Ordering::Relaxed)
     }
 
+    /// Whether the store was opened read-only.
+    pub fn is_read_only(&self) -> bool {
+        self.shared.read_only
+    }
+
+    /// The time the last sync completed, if there was one since the store was opened.
+    pub fn last_sync(&self) -> Option<SystemTime> {
+        *self.shared.last_sync.lock()
+    }
+
+    /// The number of bytes available to unprivileged users on the file system holding the
+    /// database directory.
+    pub fn disk_available(&self) -> std::io::Result<u64> {
+        use std::os::fd::AsRawFd as _;
+
+        let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+        // SAFETY: the file descriptor is open for the lifetime of the store and `stat` is valid
+        //         for writes.
+        let res = unsafe { libc::fstatvfs(self.shared.db_dir_fd.as_raw_fd(), stat.as_mut_ptr()) };
+        if res != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        // SAFETY: initialized by the successful call.
+        let stat = unsafe { stat.assume_init() };
+        // the field types differ across platforms.
+        #[allow(clippy::unnecessary_cast)]
+        Ok((stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64))
+    }
+
+    /// The secret for deriving key paths, if the database was created with one.
+    pub fn key_secret(&self) -> Option<&[u8; 32]> {
+        self.shared.key_secret.as_ref()
+    }
+
     pub fn sync_seqn(&self) -> u32 {
         self.sync.lock().sync_seqn
     }
 
+    /// Block until the sync sequence number reaches `sync_seqn` or the timeout elapses. Returns
+    /// whether it was reached.
+    pub fn wait_sync_seqn(&self, sync_seqn: u32, timeout: Duration) -> bool {
+        let mut sync = self.sync.lock();
+        let _ = self
+            .synced
+            .wait_while_for(&mut sync, |sync| sync.sync_seqn < sync_seqn, timeout);
+        sync.sync_seqn >= sync_seqn
+    }
+
+    /// The root recorded by the last sync. `None` if the last sync was made by a version which
+    /// didn't record it.
+    pub fn committed_root(&self) -> Option<Node> {
+        self.sync.lock().root
+    }
+
+    /// The identifier and the parent root of the commit made by the last sync, if it was given an
+    /// identifier.
+    pub fn last_commit(&self) -> Option<(u64, Node)> {
+        self.sync.lock().last_commit
+    }
+
+    /// The signature of the root recorded by the last sync, if it was signed.
+    pub fn root_signature(&self) -> Option<Vec<u8>> {
+        self.sync.lock().signature.clone()
+    }
+
+    /// The number of leaves and the root of the Merkle mountain range as of the last sync.
+    pub fn mmr(&self) -> (u64, Node) {
+        let sync = self.sync.lock();
+        (sync.mmr.leaves(), sync.mmr.root())
+    }
+
+    /// Compute the append of the given leaf hashes to the Merkle mountain range, to be passed to
+    /// [`Self::commit`]. `None` if there are no leaves.
+    pub fn mmr_append<H: ValueHasher>(&self, leaf_hashes: &[Node]) -> Option<MmrAppend> {
+        self.sync.lock().mmr.append::<H>(leaf_hashes)
+    }
+
+    /// Build the proof of a leaf of the Merkle mountain range as of the last sync. `None` if the
+    /// leaf is out of range.
+    pub fn mmr_proof(&self, leaf_index: u64) -> anyhow::Result<Option<MmrProof>> {
+        let leaves = self.sync.lock().mmr.leaves();
+        match self.shared.mmr_file {
+            Some(ref file) => file.read_proof(leaf_index, leaves),
+            None => Ok(None),
+        }
+    }
+
     /// Returns a handle to the rollback object. `None` if the rollback feature is not enabled.
     pub fn rollback(&self) -> Option<&Rollback> {
         self.shared.rollback.as_ref()
     }
 
-    /// Loads the flat value stored under the given key.
-    pub fn load_value(&self, key: KeyPath) -> anyhow::Result<Option<Vec<u8>>> {
+    pub fn root_pins(&self) -> &RootPins {
+        &self.shared.root_pins
+    }
+
+    /// The fencing token of this writer. `None` in read-only mode.
+    pub fn fencing_token(&self) -> Option<u64> {
+        self.shared.fence.as_ref().map(Fence::token)
+    }
+
+    /// Loads the flat value stored under the given key, along with the leaves accessed to find it.
+    pub fn load_value(
+        &self,
+        key: KeyPath,
+    ) -> anyhow::Result<(Option<Vec<u8>>, beatree::LeafAccesses)> {
         Ok(self.shared.values.lookup(key))
     }
 
+    /// Loads the value of the given key without reading the pages of an overflow value, whose
+    /// hash is returned instead.
+    pub fn load_value_shallow(&self, key: KeyPath) -> Option<beatree::ShallowValue> {
+        self.shared.values.lookup_shallow(key).0
+    }
+
+    /// Scan the value store for orphaned pages and report how its space is used, freeing the
+    /// orphaned pages with the next sync if `reclaim` is true. Fails if the store is poisoned, or
+    /// read-only and asked to reclaim.
+    pub fn collect_value_garbage(&self, reclaim: bool) -> anyhow::Result<beatree::ValueStoreStats> {
+        if reclaim && self.is_read_only() {
+            anyhow::bail!("Database is opened read-only");
+        }
+        if self.is_poisoned() {
+            anyhow::bail!("Store is poisoned due to prior error");
+        }
+        self.shared.values.collect_garbage(reclaim)
+    }
+
     /// Loads the given page, blocking the current thread.
     pub fn load_page(&self, page_id: PageId) -> anyhow::Result<Option<(FatPage, BucketIndex)>> {
         let page_loader = self.page_loader();
@@ -235,7 +446,7 @@ impl Store {
             // UNWRAP: page loader always submits a `Read` command that yields a fat page.
             let page = completion.command.kind.unwrap_buf();
 
-            if let Some(res) = page_load.try_complete(page) {
+            if let Some(res) = page_load.try_complete(page)? {
                 return Ok(Some(res));
             }
         }
@@ -270,13 +481,18 @@ impl Store {
     /// Atomically apply the given transaction.
     ///
     /// After this function returns, accessor methods such as [`Self::load_page`] will return the
-    /// updated values.
+    /// updated values. Returns the sync sequence number of the commit.
     pub fn commit(
         &self,
+        record: CommitRecord,
         value_tx: impl IntoIterator<Item = (beatree::Key, beatree::ValueChange)> + Send + 'static,
         page_cache: PageCache,
         updated_pages: impl IntoIterator<Item = (PageId, DirtyPage)> + Send + 'static,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<u32> {
+        if self.shared.read_only {
+            anyhow::bail!("Store is opened read-only");
+        }
+
         let mut sync = self.sync.lock();
 
         if self
@@ -287,21 +503,45 @@ impl Store {
             anyhow::bail!("Store is poisoned due to prior error");
         }
 
-        if let Err(e) = sync.sync(
-            &self.shared,
-            value_tx,
-            self.shared.pages.clone(),
-            self.shared.values.clone(),
-            self.shared.rollback.clone(),
-            page_cache,
-            updated_pages,
-        ) {
+        if record
+            .mmr_append
+            .as_ref()
+            .is_some_and(|append| append.prev_leaves != sync.mmr.leaves())
+        {
+            anyhow::bail!("Merkle mountain range changed since the append was computed");
+        }
+
+        // buckets are allocated in the order the pages are given, which otherwise depends on the
+        // order the update workers finished in.
+        let mut updated_pages: Vec<_> = updated_pages.into_iter().collect();
+        if self.shared.deterministic_layout {
+            updated_pages.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        }
+
+        let synced = fatal::catch(|| {
+            sync.sync(
+                &self.shared,
+                record,
+                value_tx,
+                self.shared.pages.clone(),
+                self.shared.values.clone(),
+                self.shared.rollback.clone(),
+                page_cache,
+                updated_pages,
+            )
+        });
+        if let Err(e) = synced
+            .map_err(anyhow::Error::from)
+            .and_then(|synced| synced)
+        {
             self.shared
                 .poisoned
                 .store(true, std::sync::atomic::Ordering::Relaxed);
             return Err(e);
         }
-        Ok(())
+        *self.shared.last_sync.lock() = Some(SystemTime::now());
+        self.synced.notify_all();
+        Ok(sync.sync_seqn)
     }
 }
 
@@ -317,6 +557,18 @@ impl Drop for Shared {
     }
 }
 
+/// What a commit records in the metadata, besides the changes themselves.
+pub struct CommitRecord {
+    /// The root of the trie after the commit.
+    pub root: Node,
+    /// The identifier and the parent root of the commit, if it has an identifier.
+    pub last_commit: Option<(u64, Node)>,
+    /// The signature of the root, if it was signed.
+    pub signature: Option<Vec<u8>>,
+    /// The append to the Merkle mountain range, as computed by [`Store::mmr_append`].
+    pub mmr_append: Option<MmrAppend>,
+}
+
 /// An atomic transaction on raw key/value pairs to be applied against the store
 /// with [`Store::commit`].
 pub struct ValueTransaction {
@@ -330,6 +582,11 @@ impl ValueTransaction {
             .push((path, beatree::ValueChange::from_option::<T>(value)))
     }
 
+    /// Iterate all the changed values by reference.
+    pub fn iter(&self) -> impl Iterator<Item = &(beatree::Key, beatree::ValueChange)> {
+        self.batch.iter()
+    }
+
     /// Iterate all the changed values.
     pub fn into_iter(self) -> impl Iterator<Item = (beatree::Key, beatree::ValueChange)> {
         self.batch.into_iter()
@@ -394,15 +651,49 @@ fn create(page_pool: &PagePool, o: &crate::Options) -> anyhow::Result<(File, Flo
     let flock = Flock::lock(&o.path, ".lock")?;
 
     let meta_fd = std::fs::File::create(o.path.join("meta"))?;
-    let meta = Meta::create_new(o.bitbox_seed, o.bitbox_num_pages);
+    let shadow_paging = o.commit_strategy == crate::CommitStrategy::ShadowPaging;
+    let meta = Meta::create_new(o.bitbox_seed, o.bitbox_num_pages, shadow_paging);
     Meta::write(page_pool, &meta_fd, &meta)?;
     drop(meta_fd);
 
-    bitbox::create(o.path.clone(), o.bitbox_num_pages, o.preallocate_ht)?;
+    bitbox::create(
+        o.path.clone(),
+        o.bitbox_num_pages,
+        o.preallocate_ht,
+        shadow_paging,
+    )?;
     beatree::create(&o.path)?;
 
+    if o.keyed_key_paths {
+        use rand::Rng as _;
+        let mut key_secret = [0u8; 32];
+        rand::rngs::OsRng.fill(&mut key_secret);
+        let mut key_secret_fd = std::fs::File::create(o.path.join(KEY_SECRET_FILE))?;
+        key_secret_fd.write_all(&key_secret)?;
+        key_secret_fd.sync_all()?;
+    }
+
     // As the last step, sync the directory. This makes sure that the directory is properly
     // written to disk.
     db_dir_fd.sync_all()?;
     Ok((db_dir_fd, flock))
 }
+
+// The number of pages in the given number of MiB.
+fn mib_to_pages(mib: usize) -> u32 {
+    (mib * 1024 * 1024 / io::PAGE_SIZE) as u32
+}
+
+const KEY_SECRET_FILE: &str = "key_secret";
+
+/// Reads the secret for deriving key paths, if the database has one.
+fn read_key_secret(path: &std::path::Path) -> anyhow::Result<Option<[u8; 32]>> {
+    match std::fs::read(path.join(KEY_SECRET_FILE)) {
+        Ok(bytes) => match bytes.try_into() {
+            Ok(key_secret) => Ok(Some(key_secret)),
+            Err(_) => anyhow::bail!("key secret corrupted; unexpected length"),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
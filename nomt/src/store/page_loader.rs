@@ -22,4 +22,15 @@ impl PageLoader {
     pub fn probe(&self, load: &mut PageLoad, io_handle: &IoHandle, user_data: u64) -> bool {
         self.inner.probe(load, io_handle, user_data)
     }
+
+    /// Like [`Self::probe`], but gives up instead of submitting the read while the I/O pool is
+    /// under backpressure. Returns `None` in that case, and the page load must be discarded.
+    pub fn try_probe(
+        &self,
+        load: &mut PageLoad,
+        io_handle: &IoHandle,
+        user_data: u64,
+    ) -> Option<bool> {
+        self.inner.try_probe(load, io_handle, user_data)
+    }
 }
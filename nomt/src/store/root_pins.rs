@@ -0,0 +1,157 @@
+//! The registry of pinned roots: historical roots which must remain reconstructible.
+//!
+//! The rollback log keeps the deltas needed to roll back to a pinned root regardless of the
+//! retention policy, and rolling back refuses to discard a pinned root. The backup log is never
+//! pruned, so it can always restore a pinned root it recorded.
+//!
+//! Pins are persisted in the `pinned_roots` file of the database directory, which holds the roots
+//! in ascending order followed by a checksum. The file is replaced atomically on every change.
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::Write as _,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+const PINNED_ROOTS_FILE: &str = "pinned_roots";
+const PINNED_ROOTS_TMP_FILE: &str = "pinned_roots.tmp";
+const CHECKSUM_LEN: usize = 8;
+
+pub struct RootPins {
+    db_dir_path: PathBuf,
+    db_dir_fd: Arc<File>,
+    read_only: bool,
+    pinned: Mutex<BTreeSet<[u8; 32]>>,
+}
+
+impl RootPins {
+    /// Load the pins persisted in the database directory.
+    pub fn open(db_dir_path: &Path, db_dir_fd: Arc<File>, read_only: bool) -> Result<Self> {
+        let pinned = match std::fs::read(db_dir_path.join(PINNED_ROOTS_FILE)) {
+            Ok(bytes) => decode(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeSet::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(RootPins {
+            db_dir_path: db_dir_path.to_path_buf(),
+            db_dir_fd,
+            read_only,
+            pinned: Mutex::new(pinned),
+        })
+    }
+
+    /// Pin a root. Returns `false` if it was pinned already.
+    pub fn pin(&self, root: [u8; 32]) -> Result<bool> {
+        self.update(|pinned| pinned.insert(root))
+    }
+
+    /// Unpin a root. Returns `false` if it wasn't pinned.
+    pub fn unpin(&self, root: [u8; 32]) -> Result<bool> {
+        self.update(|pinned| pinned.remove(&root))
+    }
+
+    pub fn is_pinned(&self, root: &[u8; 32]) -> bool {
+        self.pinned.lock().contains(root)
+    }
+
+    /// All pinned roots, in ascending order.
+    pub fn roots(&self) -> Vec<[u8; 32]> {
+        self.pinned.lock().iter().copied().collect()
+    }
+
+    // Apply a change to the pins and persist them if it changed anything. The pins are left
+    // untouched if persisting fails.
+    fn update(&self, change: impl FnOnce(&mut BTreeSet<[u8; 32]>) -> bool) -> Result<bool> {
+        if self.read_only {
+            anyhow::bail!("cannot change pinned roots in read-only mode");
+        }
+        let mut pinned = self.pinned.lock();
+        let mut updated = pinned.clone();
+        if !change(&mut updated) {
+            return Ok(false);
+        }
+
+        let tmp_path = self.db_dir_path.join(PINNED_ROOTS_TMP_FILE);
+        let mut tmp_file = File::create(&tmp_path)?;
+        tmp_file.write_all(&encode(&updated))?;
+        tmp_file.sync_all()?;
+        std::fs::rename(&tmp_path, self.db_dir_path.join(PINNED_ROOTS_FILE))?;
+        self.db_dir_fd.sync_all()?;
+
+        *pinned = updated;
+        Ok(true)
+    }
+}
+
+fn checksum(data: &[u8]) -> [u8; CHECKSUM_LEN] {
+    // UNWRAP: a hash is longer than the checksum.
+    blake3::hash(data).as_bytes()[..CHECKSUM_LEN]
+        .try_into()
+        .unwrap()
+}
+
+fn encode(pinned: &BTreeSet<[u8; 32]>) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(pinned.len() * 32 + CHECKSUM_LEN);
+    for root in pinned {
+        buf.extend_from_slice(root);
+    }
+    let checksum = checksum(&buf);
+    buf.extend_from_slice(&checksum);
+    buf
+}
+
+fn decode(buf: &[u8]) -> Result<BTreeSet<[u8; 32]>> {
+    if buf.len() < CHECKSUM_LEN || !(buf.len() - CHECKSUM_LEN).is_multiple_of(32) {
+        anyhow::bail!("pinned roots corrupted; unexpected length {}", buf.len());
+    }
+    let (roots, stored_checksum) = buf.split_at(buf.len() - CHECKSUM_LEN);
+    if checksum(roots) != stored_checksum {
+        anyhow::bail!("pinned roots corrupted; checksum mismatch");
+    }
+    Ok(roots
+        .chunks_exact(32)
+        // UNWRAP: the chunks are 32 bytes long.
+        .map(|root| root.try_into().unwrap())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, RootPins};
+    use std::sync::Arc;
+
+    #[test]
+    fn pins_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let open = |read_only| {
+            let db_dir_fd = Arc::new(std::fs::File::open(dir.path()).unwrap());
+            RootPins::open(dir.path(), db_dir_fd, read_only).unwrap()
+        };
+
+        let pins = open(false);
+        assert!(pins.roots().is_empty());
+        assert!(pins.pin([2; 32]).unwrap());
+        assert!(pins.pin([1; 32]).unwrap());
+        assert!(!pins.pin([1; 32]).unwrap());
+        assert!(pins.unpin([2; 32]).unwrap());
+        assert!(!pins.unpin([3; 32]).unwrap());
+        assert!(pins.pin([3; 32]).unwrap());
+        drop(pins);
+
+        let pins = open(true);
+        assert_eq!(pins.roots(), vec![[1; 32], [3; 32]]);
+        assert!(pins.is_pinned(&[3; 32]));
+        assert!(!pins.is_pinned(&[2; 32]));
+        assert!(pins.pin([4; 32]).is_err());
+
+        let mut encoded = encode(&[[5; 32]].into_iter().collect());
+        assert_eq!(decode(&encoded).unwrap().len(), 1);
+        encoded[0] ^= 1;
+        assert!(decode(&encoded).is_err());
+        assert!(decode(&encoded[1..]).is_err());
+    }
+}
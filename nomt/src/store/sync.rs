@@ -1,29 +1,36 @@
-use nomt_core::page_id::PageId;
+use nomt_core::{page_id::PageId, trie::Node};
 
 use super::{
     meta::{self, Meta},
-    DirtyPage, Shared,
+    mmr::MmrState,
+    CommitRecord, DirtyPage, Shared,
 };
 use crate::{beatree, bitbox, options::PanicOnSyncMode, page_cache::PageCache, rollback};
 
 pub struct Sync {
     pub(crate) sync_seqn: u32,
+    pub(crate) root: Option<Node>,
+    pub(crate) last_commit: Option<(u64, Node)>,
+    pub(crate) signature: Option<Vec<u8>>,
+    pub(crate) mmr: MmrState,
     pub(crate) bitbox_num_pages: u32,
     pub(crate) bitbox_seed: [u8; 16],
+    pub(crate) bitbox_shadow_paging: bool,
     pub(crate) panic_on_sync: Option<PanicOnSyncMode>,
 }
 
 impl Sync {
-    pub fn new(
-        sync_seqn: u32,
-        bitbox_num_pages: u32,
-        bitbox_seed: [u8; 16],
-        panic_on_sync: Option<PanicOnSyncMode>,
-    ) -> Self {
+    /// Create the state of the syncs as of the sync described by the metadata.
+    pub fn new(meta: Meta, mmr: MmrState, panic_on_sync: Option<PanicOnSyncMode>) -> Self {
         Self {
-            sync_seqn,
-            bitbox_num_pages,
-            bitbox_seed,
+            sync_seqn: meta.sync_seqn,
+            root: meta.root,
+            last_commit: meta.last_commit,
+            signature: meta.signature,
+            mmr,
+            bitbox_num_pages: meta.bitbox_num_pages,
+            bitbox_seed: meta.bitbox_seed,
+            bitbox_shadow_paging: meta.bitbox_shadow_paging,
             panic_on_sync,
         }
     }
@@ -31,6 +38,7 @@ impl Sync {
     pub fn sync(
         &mut self,
         shared: &Shared,
+        record: CommitRecord,
         value_tx: impl IntoIterator<Item = (beatree::Key, beatree::ValueChange)> + Send + 'static,
         bitbox: bitbox::DB,
         beatree: beatree::Tree,
@@ -38,13 +46,28 @@ impl Sync {
         page_cache: PageCache,
         updated_pages: impl IntoIterator<Item = (PageId, DirtyPage)> + Send + 'static,
     ) -> anyhow::Result<()> {
+        let CommitRecord {
+            root,
+            last_commit,
+            signature,
+            mmr_append,
+        } = record;
         let sync_seqn = self.sync_seqn + 1;
 
+        // check that no other writer took over before writing anything, and again before the
+        // meta, which makes the sync visible.
+        check_fence(shared)?;
+
         let mut bitbox_sync = bitbox.sync();
         let mut beatree_sync = beatree.sync();
         let mut rollback_sync = rollback.map(|rollback| rollback.sync());
 
-        bitbox_sync.begin_sync(sync_seqn, page_cache, updated_pages);
+        bitbox_sync.begin_sync(
+            sync_seqn,
+            page_cache,
+            updated_pages,
+            shared.io_pool.make_handle(),
+        );
         beatree_sync.begin_sync(value_tx);
         let (rollback_start_live, rollback_end_live) = match rollback_sync {
             Some(ref mut rollback) => rollback.begin_sync(),
@@ -58,6 +81,16 @@ impl Sync {
             panic!("panic_on_sync is true (post-wal)")
         }
 
+        if let Some(ref append) = mmr_append {
+            // UNWRAP: the file is only missing in read-only mode, which never syncs.
+            shared.mmr_file.as_ref().unwrap().write(append)?;
+        }
+        let (mmr_leaves, mmr_root) = match mmr_append {
+            Some(ref append) => (append.leaves, append.root),
+            None => (self.mmr.leaves(), self.mmr.root()),
+        };
+
+        check_fence(shared)?;
         let new_meta = Meta {
             magic: meta::MAGIC,
             version: meta::VERSION,
@@ -68,11 +101,23 @@ impl Sync {
             sync_seqn,
             bitbox_num_pages: self.bitbox_num_pages,
             bitbox_seed: self.bitbox_seed,
+            bitbox_shadow_paging: self.bitbox_shadow_paging,
             rollback_start_live,
             rollback_end_live,
+            root: Some(root),
+            last_commit,
+            signature,
+            mmr_leaves,
+            mmr_root,
         };
         Meta::write(&shared.io_pool.page_pool(), &shared.meta_fd, &new_meta)?;
         self.sync_seqn += 1;
+        self.root = Some(root);
+        self.last_commit = last_commit;
+        self.signature = new_meta.signature;
+        if let Some(append) = mmr_append {
+            self.mmr.apply(append);
+        }
 
         if let Some(PanicOnSyncMode::PostMeta) = self.panic_on_sync {
             panic!("panic_on_sync is true (post-meta)");
@@ -91,3 +136,10 @@ impl Sync {
         Ok(())
     }
 }
+
+fn check_fence(shared: &Shared) -> anyhow::Result<()> {
+    match shared.fence {
+        Some(ref fence) => fence.check(),
+        None => Ok(()),
+    }
+}
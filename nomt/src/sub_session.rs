@@ -0,0 +1,152 @@
+//! Speculative sub-sessions for optimistic parallel execution.
+//!
+//! An execution engine runs the transactions of a block in parallel, each against its own
+//! [`SubSession`] of the same [`Session`]. A sub-session reads the state of the session and keeps
+//! its writes to itself, recording the keys it read and wrote. [`Session::merge`] then combines
+//! the sub-sessions in the order of their transactions, provided that executing them in parallel
+//! was equivalent to executing them one after another.
+
+use std::collections::{btree_map::Entry, BTreeMap};
+
+use nomt_core::trie::KeyPath;
+
+use crate::{HashAlgorithm, KeyReadWrite, Session, Value};
+
+/// A speculative view of a [`Session`], created with [`Session::sub_session`].
+pub struct SubSession<'a, T: HashAlgorithm> {
+    session: &'a Session<T>,
+    accesses: BTreeMap<KeyPath, KeyReadWrite>,
+}
+
+impl<'a, T: HashAlgorithm> SubSession<'a, T> {
+    pub(crate) fn new(session: &'a Session<T>) -> Self {
+        SubSession {
+            session,
+            accesses: BTreeMap::new(),
+        }
+    }
+
+    /// Read the value stored under the given key: the value last written by this sub-session, or
+    /// else the value as of the session. Values written by other sub-sessions or with
+    /// [`Session::update`] aren't observed.
+    ///
+    /// Fails only if I/O fails.
+    pub fn read(&mut self, path: KeyPath) -> anyhow::Result<Option<Value>> {
+        match self.accesses.entry(path) {
+            Entry::Occupied(o) => Ok(o.get().last_value().map(|v| v.to_vec())),
+            Entry::Vacant(v) => {
+                let value = self.session.read(path)?;
+                v.insert(KeyReadWrite::Read(value.clone()));
+                Ok(value)
+            }
+        }
+    }
+
+    /// Write the value of the given key. `None` deletes the key.
+    pub fn write(&mut self, path: KeyPath, value: Option<Value>) {
+        match self.accesses.entry(path) {
+            Entry::Occupied(mut o) => o.get_mut().write(value),
+            Entry::Vacant(v) => {
+                self.session.warm_up(path);
+                v.insert(KeyReadWrite::Write(value));
+            }
+        }
+    }
+
+    /// The keys read and written by this sub-session so far, in key order.
+    pub fn accesses(&self) -> &BTreeMap<KeyPath, KeyReadWrite> {
+        &self.accesses
+    }
+}
+
+/// The kind of a [`Conflict`] between two sub-sessions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// The later sub-session read a key written by the earlier one, so it read a stale value.
+    ReadWrite,
+    /// Both sub-sessions wrote the same key.
+    WriteWrite,
+}
+
+/// A conflict between two sub-sessions accessing the same key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conflict {
+    /// The key accessed by both sub-sessions.
+    pub key: KeyPath,
+    /// The kind of the conflict.
+    pub kind: ConflictKind,
+    /// The index of the earlier sub-session, which wrote the key.
+    pub earlier: usize,
+    /// The index of the later sub-session.
+    pub later: usize,
+}
+
+/// The error returned by [`Session::merge`] when sub-sessions conflict.
+///
+/// The later sub-session of every conflict has to be executed again, after the earlier one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeConflicts {
+    /// All conflicts between the sub-sessions, ordered by key and then by sub-session.
+    pub conflicts: Vec<Conflict>,
+}
+
+impl std::fmt::Display for MergeConflicts {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} conflicts between sub-sessions", self.conflicts.len())
+    }
+}
+
+impl std::error::Error for MergeConflicts {}
+
+// Merge the accesses of sub-sessions, given in order.
+pub(crate) fn merge<'a, T: HashAlgorithm + 'a>(
+    sub_sessions: impl IntoIterator<Item = SubSession<'a, T>>,
+) -> Result<Vec<(KeyPath, KeyReadWrite)>, MergeConflicts> {
+    // the merged access of every key, along with the last sub-session which wrote it.
+    let mut merged: BTreeMap<KeyPath, (KeyReadWrite, Option<usize>)> = BTreeMap::new();
+    let mut conflicts = Vec::new();
+
+    for (index, sub_session) in sub_sessions.into_iter().enumerate() {
+        for (key, access) in sub_session.accesses {
+            let (merged_access, writer) = match merged.entry(key) {
+                Entry::Vacant(v) => {
+                    let writer = access.is_write().then_some(index);
+                    v.insert((access, writer));
+                    continue;
+                }
+                Entry::Occupied(o) => o.into_mut(),
+            };
+
+            if let Some(earlier) = *writer {
+                let kind = match access {
+                    KeyReadWrite::Read(_) | KeyReadWrite::ReadThenWrite(_, _) => {
+                        ConflictKind::ReadWrite
+                    }
+                    KeyReadWrite::Write(_) => ConflictKind::WriteWrite,
+                };
+                conflicts.push(Conflict {
+                    key,
+                    kind,
+                    earlier,
+                    later: index,
+                });
+            }
+
+            // all reads before the first write are of the same value, the one in the session.
+            if let KeyReadWrite::Write(value) | KeyReadWrite::ReadThenWrite(_, value) = access {
+                merged_access.write(value);
+                *writer = Some(index);
+            }
+        }
+    }
+
+    if !conflicts.is_empty() {
+        conflicts.sort_by_key(|c| (c.key, c.later));
+        return Err(MergeConflicts { conflicts });
+    }
+
+    Ok(merged
+        .into_iter()
+        .map(|(key, (access, _))| (key, access))
+        .collect())
+}
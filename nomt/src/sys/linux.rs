@@ -28,6 +28,33 @@ impl FsCheck {
     }
 }
 
+/// Allocates the blocks of the given range of the file, extending the file if the range goes
+/// beyond its end. Fails if the filesystem doesn't support it.
+pub fn falloc_extend(file: &File, offset: u64, len: u64) -> std::io::Result<()> {
+    cvt_r(|| unsafe {
+        // SAFETY: unsafe because ffi call. This should be IO-safe because the file is passed
+        //         by reference.
+        libc::fallocate(file.as_raw_fd(), 0, offset as _, len as _)
+    })
+    .map(drop)
+}
+
+/// Deallocates the blocks of the given range of the file, which then reads as zeroes. The size of
+/// the file doesn't change. Fails if the filesystem doesn't support it.
+pub fn punch_hole(file: &File, offset: u64, len: u64) -> std::io::Result<()> {
+    cvt_r(|| unsafe {
+        // SAFETY: unsafe because ffi call. This should be IO-safe because the file is passed
+        //         by reference.
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            offset as _,
+            len as _,
+        )
+    })
+    .map(drop)
+}
+
 /// fallocate changes the size of the file to the given length if it's less than the current size.
 /// If the file is larger than the given length, the file is not truncated.
 ///
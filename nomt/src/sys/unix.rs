@@ -6,6 +6,10 @@ pub fn try_lock_exclusive(file: &File) -> std::io::Result<()> {
     cvt_r(|| unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) }).map(drop)
 }
 
+pub fn try_lock_shared(file: &File) -> std::io::Result<()> {
+    cvt_r(|| unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_SH | libc::LOCK_NB) }).map(drop)
+}
+
 pub fn unlock(file: &File) -> std::io::Result<()> {
     unsafe { cvt_r(|| libc::flock(file.as_raw_fd(), libc::LOCK_UN)).map(drop) }
 }
@@ -1,3 +1,5 @@
+use crate::fatal::{self, FatalError};
+
 pub type TaskResult<R> = std::thread::Result<R>;
 
 /// Spawn the given task within the given ThreadPool.
@@ -34,3 +36,25 @@ where
         Err(err_payload) => std::panic::resume_unwind(err_payload),
     }
 }
+
+/// Blocks waiting for completion of a fallible task spawned with [`spawn_task`].
+///
+/// Like [`join_task`], except that with the `panic-free` feature a panic in the task is returned
+/// as a [`crate::FatalError::Panicked`] error instead of being resumed.
+pub fn join_fallible_task<T, E>(
+    receiver: &crossbeam_channel::Receiver<TaskResult<Result<T, E>>>,
+) -> Result<T, E>
+where
+    T: Send + 'static,
+    E: From<FatalError> + Send + 'static,
+{
+    // UNWRAP: The sender is not expected to be dropped by the spawned task.
+    let res = receiver.recv().unwrap();
+    match res {
+        Ok(res) => res,
+        Err(err_payload) if cfg!(feature = "panic-free") => {
+            Err(fatal::from_panic(err_payload).into())
+        }
+        Err(err_payload) => std::panic::resume_unwind(err_payload),
+    }
+}
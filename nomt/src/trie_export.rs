@@ -0,0 +1,313 @@
+//! Export of a region of the trie for visual inspection.
+//!
+//! The region is rooted at the node at a given prefix and extends a bounded number of levels
+//! below it. Every node of the region is exported with its kind, hash and the page holding it,
+//! and leaves with their key path, which is looked up in the value store. Terminators are
+//! included, so that the empty parts of the region are visible too.
+//!
+//! In the DOT format, the nodes of every page are grouped in a cluster labelled with the page ID,
+//! which shows how the region is split across pages. The root node of the trie isn't stored in any
+//! page. The JSON format is an object holding the root of the trie, the prefix, the depth, the
+//! pages touched by the region and the list of nodes, ordered by depth and then by path.
+
+use std::{collections::BTreeMap, fmt::Write as _};
+
+use bitvec::prelude::*;
+use nomt_core::{
+    page_id::{PageId, ROOT_PAGE_ID},
+    trie::{KeyPath, Node, NodeKind, TERMINATOR},
+    trie_pos::TriePosition,
+};
+
+use crate::{beatree, dump, page_cache::PageMut, store::Store, HashAlgorithm};
+
+/// The largest depth of a region which can be exported. See [`crate::Nomt::export_trie`].
+pub const MAX_TRIE_EXPORT_DEPTH: usize = 16;
+
+/// The format of a trie export. See [`crate::Nomt::export_trie`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrieExportFormat {
+    /// A graphviz digraph, with a cluster per page.
+    Dot,
+    /// A JSON object.
+    Json,
+}
+
+// A node of the exported region.
+struct ExportedNode {
+    position: TriePosition,
+    kind: NodeKind,
+    hash: Node,
+    // the page holding the node and its index within the page. `None` for the root node.
+    location: Option<(PageId, usize)>,
+    // the key path of a leaf.
+    key_path: Option<KeyPath>,
+}
+
+pub(crate) fn export_trie<H: HashAlgorithm>(
+    store: &Store,
+    read_tx: &beatree::ReadTransaction,
+    root: Node,
+    prefix: &BitSlice<u8, Msb0>,
+    depth: usize,
+    format: TrieExportFormat,
+) -> anyhow::Result<String> {
+    if depth > MAX_TRIE_EXPORT_DEPTH {
+        anyhow::bail!(
+            "export depth ({}) must be at most {}",
+            depth,
+            MAX_TRIE_EXPORT_DEPTH
+        );
+    }
+    if prefix.len() > 256 {
+        anyhow::bail!("prefix of {} bits is longer than a key path", prefix.len());
+    }
+
+    let mut pages = BTreeMap::new();
+    let mut load_node =
+        |position: &TriePosition| -> anyhow::Result<(Node, Option<(PageId, usize)>)> {
+            let Some((page_id, index)) = position.page_id_and_node_index() else {
+                return Ok((root, None));
+            };
+            if !pages.contains_key(&page_id) {
+                let page = store
+                    .load_page(page_id.clone())?
+                    .map(|(page, _)| PageMut::pristine_with_data(page));
+                pages.insert(page_id.clone(), page);
+            }
+            // a page which doesn't exist holds nothing but terminators.
+            let node = pages[&page_id]
+                .as_ref()
+                .map_or(TERMINATOR, |page| page.node(index));
+            Ok((node, Some((page_id, index))))
+        };
+
+    // the nodes above the prefix must all be internal.
+    let mut position = TriePosition::new();
+    for bit in prefix.iter().by_vals() {
+        let (node, _) = load_node(&position)?;
+        if H::node_kind(&node) != NodeKind::Internal {
+            anyhow::bail!(
+                "no node at the prefix: the trie ends in a {} at depth {}",
+                kind_name(H::node_kind(&node)),
+                position.depth(),
+            );
+        }
+        position.down(bit);
+    }
+
+    let mut nodes = Vec::new();
+    let mut level = vec![position];
+    for level_depth in 0..=depth {
+        let mut next_level = Vec::new();
+        for position in level {
+            let (hash, location) = load_node(&position)?;
+            let kind = H::node_kind(&hash);
+            let key_path = match kind {
+                NodeKind::Leaf => Some(leaf_key_path(store, read_tx, &position)?),
+                _ => None,
+            };
+            if kind == NodeKind::Internal && level_depth < depth && position.depth() < 256 {
+                next_level.push(position.child(false));
+                next_level.push(position.child(true));
+            }
+            nodes.push(ExportedNode {
+                position,
+                kind,
+                hash,
+                location,
+                key_path,
+            });
+        }
+        level = next_level;
+    }
+
+    Ok(match format {
+        TrieExportFormat::Dot => to_dot(&nodes),
+        TrieExportFormat::Json => to_json(root, prefix, depth, &nodes),
+    })
+}
+
+// The key path of the leaf at the position: the only key in the sub-trie below it.
+fn leaf_key_path(
+    store: &Store,
+    read_tx: &beatree::ReadTransaction,
+    position: &TriePosition,
+) -> anyhow::Result<KeyPath> {
+    let (start, _) = position.to_path_and_depth();
+    let mut key_path = None;
+    dump::scan_keys(store, read_tx, start, |key| {
+        key_path = Some(key);
+        false
+    })?;
+    key_path
+        .ok_or_else(|| anyhow::anyhow!("no key found for the leaf at {}", path_string(position)))
+}
+
+fn to_dot(nodes: &[ExportedNode]) -> String {
+    let mut out = String::new();
+    out.push_str("digraph trie {\n");
+    out.push_str("  node [shape=box, fontname=\"monospace\"];\n");
+
+    let mut by_page: BTreeMap<Option<&PageId>, Vec<&ExportedNode>> = BTreeMap::new();
+    for node in nodes {
+        by_page
+            .entry(node.location.as_ref().map(|(page_id, _)| page_id))
+            .or_default()
+            .push(node);
+    }
+    for (cluster, (page_id, nodes)) in by_page.into_iter().enumerate() {
+        let indent = match page_id {
+            Some(page_id) => {
+                let _ = writeln!(out, "  subgraph cluster_{} {{", cluster);
+                let _ = writeln!(out, "    label=\"page {}\";", page_id_string(page_id));
+                "    "
+            }
+            None => "  ",
+        };
+        for node in nodes {
+            let mut label = format!(
+                "{}\\n{} {}",
+                path_string(&node.position),
+                kind_name(node.kind),
+                hex(&node.hash[..4]),
+            );
+            if let Some(ref key_path) = node.key_path {
+                let _ = write!(label, "\\nkey {}", hex(key_path));
+            }
+            let _ = writeln!(
+                out,
+                "{}\"{}\" [label=\"{}\"{}];",
+                indent,
+                path_string(&node.position),
+                label,
+                match node.kind {
+                    NodeKind::Leaf => ", style=filled, fillcolor=\"#d0f0d0\"",
+                    NodeKind::Terminator => ", style=dashed",
+                    NodeKind::Internal => "",
+                },
+            );
+        }
+        if page_id.is_some() {
+            out.push_str("  }\n");
+        }
+    }
+
+    // every node but the one at the prefix was reached from its parent within the region.
+    for node in nodes.iter().skip(1) {
+        // UNWRAP: only the root has no parent, and it can only be the node at the prefix.
+        let parent = node.position.parent().unwrap();
+        let _ = writeln!(
+            out,
+            "  \"{}\" -> \"{}\" [label=\"{}\"];",
+            path_string(&parent),
+            path_string(&node.position),
+            node.position.peek_last_bit() as u8,
+        );
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn to_json(
+    root: Node,
+    prefix: &BitSlice<u8, Msb0>,
+    depth: usize,
+    nodes: &[ExportedNode],
+) -> String {
+    let mut out = String::new();
+    let prefix: String = prefix
+        .iter()
+        .map(|bit| if *bit { '1' } else { '0' })
+        .collect();
+    let _ = write!(
+        out,
+        "{{\"root\":\"{}\",\"prefix\":\"{}\",\"depth\":{},\"pages\":[",
+        hex(&root),
+        prefix,
+        depth
+    );
+    let mut pages: Vec<&PageId> = nodes
+        .iter()
+        .filter_map(|node| node.location.as_ref().map(|(page_id, _)| page_id))
+        .collect();
+    pages.sort();
+    pages.dedup();
+    for (i, page_id) in pages.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(
+            out,
+            "{{\"id\":\"{}\",\"depth\":{}}}",
+            page_id_string(page_id),
+            page_id.depth()
+        );
+    }
+    out.push_str("],\"nodes\":[");
+    for (i, node) in nodes.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(
+            out,
+            "{{\"path\":\"{}\",\"depth\":{},\"kind\":\"{}\",\"hash\":\"{}\"",
+            path_string(&node.position),
+            node.position.depth(),
+            kind_name(node.kind),
+            hex(&node.hash),
+        );
+        match node.location {
+            Some((ref page_id, index)) => {
+                let _ = write!(
+                    out,
+                    ",\"page\":\"{}\",\"index\":{}",
+                    page_id_string(page_id),
+                    index
+                );
+            }
+            None => out.push_str(",\"page\":null,\"index\":null"),
+        }
+        if let Some(ref key_path) = node.key_path {
+            let _ = write!(out, ",\"key_path\":\"{}\"", hex(key_path));
+        }
+        out.push('}');
+    }
+    out.push_str("]}");
+    out
+}
+
+fn kind_name(kind: NodeKind) -> &'static str {
+    match kind {
+        NodeKind::Terminator => "terminator",
+        NodeKind::Leaf => "leaf",
+        NodeKind::Internal => "internal",
+    }
+}
+
+// The path to the node as a string of bits, or "root" for the root node.
+fn path_string(position: &TriePosition) -> String {
+    if position.is_root() {
+        return "root".to_string();
+    }
+    position
+        .path()
+        .iter()
+        .map(|bit| if *bit { '1' } else { '0' })
+        .collect()
+}
+
+// The page ID as the child indices leading to it, separated by slashes, or "root".
+fn page_id_string(page_id: &PageId) -> String {
+    if *page_id == ROOT_PAGE_ID {
+        return "root".to_string();
+    }
+    (0..page_id.depth())
+        .map(|level| page_id.child_index_at_level(level).to_u8().to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
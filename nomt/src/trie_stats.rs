@@ -0,0 +1,376 @@
+//! Statistics about the shape of the trie.
+//!
+//! The shape of the compact binary trie is fully determined by the set of key paths: a leaf is
+//! placed right below the longest prefix it shares with either of its neighbors in key order, and
+//! there is an internal node at every prefix shared by at least two keys. Statistics can therefore
+//! be computed by scanning the keys of the flat store alone, without touching any page.
+
+use nomt_core::{page::DEPTH, trie::KeyPath};
+
+use crate::{beatree, dump::scan_keys, store::Store};
+
+// The number of consecutive keys read for every sample.
+const SAMPLE_WINDOW: usize = 16;
+
+/// How [`crate::Nomt::trie_stats`] computes its statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrieStatsMode {
+    /// Scan every key. This reads the entire flat store.
+    Exact,
+    /// Estimate the statistics from the given number of samples taken at random positions of the
+    /// key space. Every sample reads a handful of consecutive keys.
+    ///
+    /// Small databases are scanned exactly.
+    Sampled(u32),
+}
+
+/// Statistics about the shape of the trie. See [`crate::Nomt::trie_stats`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrieStats {
+    /// The number of leaves, which is the number of stored keys. Estimated unless `exact`.
+    pub leaves: u64,
+    /// The number of internal nodes. Estimated unless `exact`.
+    pub internal_nodes: u64,
+    /// The number of inspected leaves, indexed by their depth. The root has depth 0.
+    ///
+    /// In exact mode this accounts for every leaf.
+    pub depth_histogram: Vec<u64>,
+    /// Whether the statistics were computed from every key.
+    pub exact: bool,
+}
+
+impl TrieStats {
+    /// The average depth of a leaf.
+    pub fn average_depth(&self) -> f64 {
+        let (count, sum) = self.depth_sums(|depth| depth as u64);
+        if count == 0 {
+            return 0.0;
+        }
+        sum as f64 / count as f64
+    }
+
+    /// The depth which the given fraction of leaves, between 0 and 1, are at or above. For
+    /// example, `depth_percentile(0.99)` is the 99th percentile of leaf depth.
+    pub fn depth_percentile(&self, fraction: f64) -> usize {
+        let count: u64 = self.depth_histogram.iter().sum();
+        let threshold = (count as f64 * fraction.clamp(0.0, 1.0)).ceil() as u64;
+        let mut seen = 0;
+        for (depth, leaves) in self.depth_histogram.iter().enumerate() {
+            seen += leaves;
+            if seen >= threshold.max(1) {
+                return depth;
+            }
+        }
+        0
+    }
+
+    /// The depth of the deepest inspected leaf.
+    pub fn max_depth(&self) -> usize {
+        self.depth_histogram
+            .iter()
+            .rposition(|leaves| *leaves > 0)
+            .unwrap_or(0)
+    }
+
+    /// The average number of pages which must be loaded to reach a leaf from the root.
+    pub fn pages_per_lookup(&self) -> f64 {
+        let (count, sum) = self.depth_sums(|depth| pages_for_depth(depth) as u64);
+        if count == 0 {
+            return 0.0;
+        }
+        sum as f64 / count as f64
+    }
+
+    fn depth_sums(&self, f: impl Fn(usize) -> u64) -> (u64, u64) {
+        self.depth_histogram
+            .iter()
+            .enumerate()
+            .fold((0, 0), |(count, sum), (depth, leaves)| {
+                (count + leaves, sum + leaves * f(depth))
+            })
+    }
+}
+
+// The number of pages holding the path to a node at the given depth. The root node is not stored
+// in any page and every page holds `DEPTH` levels of nodes below its own root.
+fn pages_for_depth(depth: usize) -> usize {
+    if depth == 0 {
+        0
+    } else {
+        (depth - 1) / DEPTH + 1
+    }
+}
+
+fn shared_prefix_len(a: &KeyPath, b: &KeyPath) -> usize {
+    for (i, (x, y)) in a.iter().zip(b).enumerate() {
+        let diff = x ^ y;
+        if diff != 0 {
+            return i * 8 + diff.leading_zeros() as usize;
+        }
+    }
+    256
+}
+
+// What is known about a neighbor of a key in key order.
+#[derive(Clone, Copy)]
+enum Neighbor {
+    // There is no neighbor: the key is the first or the last one.
+    Absent,
+    // The neighbor hasn't been read.
+    Unknown,
+    // The length of the prefix shared with the neighbor.
+    SharedPrefix(usize),
+}
+
+// Accumulates the shape of the trie from runs of consecutive keys.
+//
+// A key is accounted for once both of its neighbors are known. Internal nodes are attributed to
+// keys such that summing over all keys gives the exact count: every adjacent pair of keys adds
+// the nodes at the prefixes they share, minus those already added by the previous pair.
+struct ShapeScan {
+    // The last key of the current run and what is known about its predecessor.
+    prev: Option<(KeyPath, Neighbor)>,
+    // What is known about the predecessor of the first key of the current run.
+    run_start: Neighbor,
+    depth_histogram: Vec<u64>,
+    leaves: u64,
+    internal_nodes: u64,
+}
+
+impl ShapeScan {
+    fn new() -> Self {
+        ShapeScan {
+            prev: None,
+            run_start: Neighbor::Absent,
+            depth_histogram: vec![0; 257],
+            leaves: 0,
+            internal_nodes: 0,
+        }
+    }
+
+    // Start a new run. `at_start` indicates whether the first key of the run is the first key of
+    // the trie.
+    fn start_run(&mut self, at_start: bool) {
+        self.prev = None;
+        self.run_start = if at_start {
+            Neighbor::Absent
+        } else {
+            Neighbor::Unknown
+        };
+    }
+
+    fn push(&mut self, key: KeyPath) {
+        let pred = match self.prev {
+            None => self.run_start,
+            Some((prev, prev_pred)) => {
+                let next = Neighbor::SharedPrefix(shared_prefix_len(&prev, &key));
+                self.record(prev_pred, next);
+                next
+            }
+        };
+        self.prev = Some((key, pred));
+    }
+
+    // End the current run. `at_end` indicates whether the last key of the run is the last key of
+    // the trie.
+    fn end_run(&mut self, at_end: bool) {
+        if let Some((_, pred)) = self.prev.take() {
+            if at_end {
+                self.record(pred, Neighbor::Absent);
+            }
+        }
+    }
+
+    fn record(&mut self, pred: Neighbor, next: Neighbor) {
+        let known = |neighbor| match neighbor {
+            Neighbor::Unknown => None,
+            Neighbor::Absent => Some(None),
+            Neighbor::SharedPrefix(len) => Some(Some(len)),
+        };
+        let (Some(pred), Some(next)) = (known(pred), known(next)) else {
+            return;
+        };
+
+        let depth = pred.max(next).map_or(0, |len| len + 1);
+        self.depth_histogram[depth] += 1;
+        self.leaves += 1;
+
+        self.internal_nodes += match (pred, next) {
+            (_, None) => 0,
+            (None, Some(b)) => b as u64 + 1,
+            (Some(a), Some(b)) => (b - a.min(b)) as u64,
+        };
+    }
+}
+
+/// Compute statistics about the shape of the trie from the keys visible through the read
+/// transaction.
+pub(crate) fn trie_stats(
+    store: &Store,
+    read_tx: beatree::ReadTransaction,
+    mode: TrieStatsMode,
+) -> anyhow::Result<TrieStats> {
+    let samples = match mode {
+        TrieStatsMode::Exact => None,
+        TrieStatsMode::Sampled(samples) => Some(samples.max(1) as usize),
+    };
+
+    // Scan from the start. Without sampling, or if the database is small, this covers every key.
+    let limit = samples.map(|samples| samples * (SAMPLE_WINDOW + 2));
+    let mut scan = ShapeScan::new();
+    scan.start_run(true);
+    let mut read = 0;
+    let exhausted = scan_keys(store, &read_tx, [0; 32], |key| {
+        scan.push(key);
+        read += 1;
+        limit.is_none_or(|limit| read < limit)
+    })?;
+
+    if exhausted {
+        scan.end_run(true);
+        return Ok(TrieStats {
+            leaves: scan.leaves,
+            internal_nodes: scan.internal_nodes,
+            depth_histogram: scan.depth_histogram,
+            exact: true,
+        });
+    }
+
+    // UNWRAP: not exhausting the keys implies a limit, which implies sampling.
+    let samples = samples.unwrap();
+    let mut scan = ShapeScan::new();
+    let mut spanned_keys = 0u64;
+    let mut span = 0.0;
+    for _ in 0..samples {
+        let start: KeyPath = rand::random();
+        let mut window = Vec::with_capacity(SAMPLE_WINDOW + 2);
+        let exhausted = scan_keys(store, &read_tx, start, |key| {
+            window.push(key);
+            window.len() < SAMPLE_WINDOW + 2
+        })?;
+
+        scan.start_run(false);
+        for key in &window {
+            scan.push(*key);
+        }
+        scan.end_run(exhausted);
+
+        if let (Some(first), Some(last)) = (window.first(), window.last()) {
+            spanned_keys += window.len() as u64 - 1;
+            span += key_space_fraction(last) - key_space_fraction(first);
+        }
+    }
+
+    // Estimate the number of leaves from the density of keys within the windows.
+    let leaves = if span > 0.0 {
+        (spanned_keys as f64 / span) as u64
+    } else {
+        scan.leaves
+    };
+    let internal_nodes = if scan.leaves > 0 {
+        (scan.internal_nodes as f64 / scan.leaves as f64 * leaves as f64) as u64
+    } else {
+        0
+    };
+
+    Ok(TrieStats {
+        leaves,
+        internal_nodes,
+        depth_histogram: scan.depth_histogram,
+        exact: false,
+    })
+}
+
+// The position of the key within the key space, between 0 and 1.
+fn key_space_fraction(key: &KeyPath) -> f64 {
+    // UNWRAP: the slice is 8 bytes long.
+    u64::from_be_bytes(key[..8].try_into().unwrap()) as f64 / 2f64.powi(64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ShapeScan, TrieStats};
+    use crate::hasher::Blake3Hasher;
+    use nomt_core::{trie::KeyPath, update::WriteNode};
+
+    fn exact_stats(keys: &[KeyPath]) -> TrieStats {
+        let mut scan = ShapeScan::new();
+        scan.start_run(true);
+        for key in keys {
+            scan.push(*key);
+        }
+        scan.end_run(true);
+        TrieStats {
+            leaves: scan.leaves,
+            internal_nodes: scan.internal_nodes,
+            depth_histogram: scan.depth_histogram,
+            exact: true,
+        }
+    }
+
+    // Build the trie and count the nodes it is made of, tracking the depth of every leaf.
+    fn built_shape(keys: &[KeyPath]) -> (u64, Vec<u64>) {
+        let mut internal_nodes = 0;
+        let mut depth_histogram = vec![0; 257];
+        let mut depth = 0usize;
+        nomt_core::update::build_trie::<Blake3Hasher>(
+            0,
+            keys.iter().map(|key| (*key, [1; 32])),
+            |control| {
+                if control.up() {
+                    depth -= 1;
+                }
+                depth += control.down().len();
+                match control {
+                    WriteNode::Leaf { .. } => depth_histogram[depth] += 1,
+                    WriteNode::Internal { .. } => internal_nodes += 1,
+                    WriteNode::Terminator => {}
+                }
+            },
+        );
+        (internal_nodes, depth_histogram)
+    }
+
+    #[test]
+    fn matches_built_trie() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand_pcg::Lcg64Xsh32::from_seed([3; 16]);
+
+        for len in [0, 1, 2, 3, 100, 1000] {
+            let mut keys: Vec<KeyPath> = (0..len).map(|_| rng.gen()).collect();
+            // make some keys share long prefixes.
+            for i in (1..keys.len()).step_by(7) {
+                let prefix = keys[i - 1];
+                keys[i][..4].copy_from_slice(&prefix[..4]);
+            }
+            keys.sort();
+            keys.dedup();
+
+            let stats = exact_stats(&keys);
+            let (internal_nodes, depth_histogram) = built_shape(&keys);
+            assert_eq!(stats.leaves, keys.len() as u64);
+            assert_eq!(stats.internal_nodes, internal_nodes, "{} keys", len);
+            assert_eq!(stats.depth_histogram, depth_histogram, "{} keys", len);
+        }
+    }
+
+    #[test]
+    fn summaries() {
+        let mut depth_histogram = vec![0; 257];
+        depth_histogram[1] = 2;
+        depth_histogram[7] = 1;
+        depth_histogram[13] = 1;
+        let stats = TrieStats {
+            leaves: 4,
+            internal_nodes: 13,
+            depth_histogram,
+            exact: true,
+        };
+        assert_eq!(stats.average_depth(), 22.0 / 4.0);
+        assert_eq!(stats.depth_percentile(0.5), 1);
+        assert_eq!(stats.depth_percentile(0.75), 7);
+        assert_eq!(stats.depth_percentile(1.0), 13);
+        assert_eq!(stats.max_depth(), 13);
+        assert_eq!(stats.pages_per_lookup(), (1.0 + 1.0 + 2.0 + 3.0) / 4.0);
+    }
+}
@@ -0,0 +1,75 @@
+//! Background collection of orphaned pages of the value store.
+//!
+//! Every interval, the collector scans the value store for pages which are neither reachable nor
+//! free, hands them to the store to be freed by the next commit and keeps the statistics of the
+//! scan. See [`crate::Options::value_gc_interval`].
+
+use crossbeam_channel::{RecvTimeoutError, Sender};
+use parking_lot::Mutex;
+use std::{sync::Arc, thread::JoinHandle, time::Duration};
+
+use crate::{beatree::ValueStoreStats, store::Store};
+
+/// A handle to the collector thread, which is stopped and joined when this is dropped.
+pub(crate) struct ValueGc {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+    last_stats: Arc<Mutex<Option<ValueStoreStats>>>,
+}
+
+impl ValueGc {
+    /// Spawn the collector thread, scanning the store every `interval`.
+    pub fn start(store: Store, interval: Duration) -> std::io::Result<Self> {
+        let (stop_tx, stop_rx) = crossbeam_channel::bounded::<()>(0);
+        let last_stats = Arc::new(Mutex::new(None));
+        let thread = std::thread::Builder::new()
+            .name("nomt-value-gc".into())
+            .spawn({
+                let last_stats = last_stats.clone();
+                move || {
+                    // the handle dropping the sender disconnects the channel.
+                    while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+                        match store.collect_value_garbage(true) {
+                            Ok(stats) => {
+                                tracing::debug!(
+                                    target: "nomt::value_gc",
+                                    orphaned_pages = stats.orphaned_pages,
+                                    reclaimable_bytes = stats.reclaimable_bytes(),
+                                    "value store scanned",
+                                );
+                                *last_stats.lock() = Some(stats);
+                            }
+                            Err(err) => {
+                                tracing::warn!(
+                                    target: "nomt::value_gc",
+                                    "value store scan failed: {:#}",
+                                    err,
+                                );
+                            }
+                        }
+                    }
+                }
+            })?;
+
+        Ok(ValueGc {
+            stop: Some(stop_tx),
+            thread: Some(thread),
+            last_stats,
+        })
+    }
+
+    /// The statistics of the last successful scan, if any.
+    pub fn last_stats(&self) -> Option<ValueStoreStats> {
+        self.last_stats.lock().clone()
+    }
+}
+
+impl Drop for ValueGc {
+    fn drop(&mut self) {
+        // the thread holds the store, which must be released before the database is reopened.
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Verification of values read from the value store against the trie.
+//!
+//! The leaf of a key commits to the hash of its value. A value returned by the value store is
+//! verified by hashing it into the leaf it must correspond to and comparing that with the
+//! terminal node on the path of the key in the committed trie, so that a corrupted value store
+//! is reported as a [`ValueCorruption`] rather than returning a wrong value. Only values are
+//! verified: the absence of a value is not checked against the trie.
+
+use bitvec::prelude::*;
+use nomt_core::{
+    page_id::PageId,
+    trie::{self, KeyPath, LeafData, Node, TERMINATOR},
+    trie_pos::TriePosition,
+};
+
+use crate::{
+    page_cache::{Page, PageCache, PageMut},
+    store::Store,
+    HashAlgorithm, Root,
+};
+
+/// The error returned by a read when the value of a key doesn't match the trie.
+///
+/// Reads return [`anyhow::Error`], from which this can be recovered with
+/// [`anyhow::Error::downcast_ref`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueCorruption {
+    /// The key read.
+    pub key: KeyPath,
+    /// The leaf hashed from the key and the value returned by the value store.
+    pub expected: Node,
+    /// The terminal node on the path of the key in the trie.
+    pub found: Node,
+}
+
+impl std::fmt::Display for ValueCorruption {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let hex = |bytes: &[u8]| -> String { bytes.iter().map(|b| format!("{:02x}", b)).collect() };
+        write!(
+            f,
+            "value store corruption: the value of key {} hashes to leaf {}, but the trie has {}",
+            hex(&self.key),
+            hex(&self.expected),
+            hex(&self.found),
+        )
+    }
+}
+
+impl std::error::Error for ValueCorruption {}
+
+/// Verifies values read from the value store against a committed trie.
+#[derive(Clone)]
+pub(crate) struct ValueVerifier {
+    page_cache: PageCache,
+    store: Store,
+    root: Node,
+}
+
+impl ValueVerifier {
+    /// Create a verifier against the trie with the given root, which must be the committed one
+    /// for as long as the verifier is used.
+    pub fn new(page_cache: PageCache, store: Store, root: Root) -> Self {
+        ValueVerifier {
+            page_cache,
+            store,
+            root: root.into_inner(),
+        }
+    }
+
+    /// Verify the value returned by the value store for the key. Fails with a [`ValueCorruption`]
+    /// if it doesn't match the trie, or if I/O fails.
+    pub fn verify<H: HashAlgorithm>(&self, key: KeyPath, value: &[u8]) -> anyhow::Result<()> {
+        let expected = H::hash_leaf(&LeafData {
+            key_path: key,
+            value_hash: H::hash_value(value),
+        });
+        let found = terminal_node::<H>(&self.page_cache, &self.store, self.root, &key)?;
+        if found != expected {
+            return Err(ValueCorruption {
+                key,
+                expected,
+                found,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// The terminal node on the path of the key: a leaf or a terminator. Pages are taken from the page
+/// cache if present and loaded from the store otherwise.
+pub(crate) fn terminal_node<H: HashAlgorithm>(
+    page_cache: &PageCache,
+    store: &Store,
+    root: Node,
+    key: &KeyPath,
+) -> anyhow::Result<Node> {
+    let mut node = root;
+    let mut position = TriePosition::new();
+    let mut page: Option<(PageId, Option<Page>)> = None;
+    for bit in key.view_bits::<Msb0>().iter().by_vals() {
+        if !trie::is_internal::<H>(&node) {
+            break;
+        }
+        position.down(bit);
+        // UNWRAP: the position is below the root.
+        let (page_id, index) = position.page_id_and_node_index().unwrap();
+        if page.as_ref().is_none_or(|(loaded, _)| *loaded != page_id) {
+            let loaded = match page_cache.get(page_id.clone()) {
+                Some((page, _)) => Some(page),
+                None => store
+                    .load_page(page_id.clone())?
+                    .map(|(page, _)| PageMut::pristine_with_data(page).freeze()),
+            };
+            page = Some((page_id, loaded));
+        }
+        node = match page {
+            Some((_, Some(ref page))) => page.node(index),
+            _ => TERMINATOR,
+        };
+    }
+    Ok(node)
+}
@@ -0,0 +1,190 @@
+//! Read-only views of the trie at historical roots.
+//!
+//! A [`HistoricalView`] is created with [`crate::Nomt::view_at`]. The prior values recorded in
+//! the rollback log for every commit made since the requested root are applied to an in-memory
+//! overlay on top of the current state, which is never written to disk. Reads of keys unchanged
+//! since then go to the database as usual, and the trie pages unchanged since then are shared with
+//! the page cache, so the cost of a view is proportional to the number of keys changed since its
+//! root rather than to the size of the database.
+//!
+//! How far back views can reach is determined by [`crate::Options::rollback_retention`].
+
+use std::collections::VecDeque;
+
+use bitvec::prelude::*;
+use nomt_core::trie::KeyPath;
+use parking_lot::ArcRwLockReadGuard;
+
+use crate::{
+    block_witness, merkle, overlay::LiveOverlay, BlockWitness, HashAlgorithm, KeyReadWrite,
+    KeyValueIter, Nomt, Overlay, Root, SessionParams, Value,
+};
+
+/// A read-only handle on the trie as of a historical root.
+///
+/// Like [`crate::ReadSession`]s, views prevent writes to the database while they are live.
+pub struct HistoricalView<'a, T: HashAlgorithm> {
+    nomt: &'a Nomt<T>,
+    overlay: Overlay,
+    live_overlay: LiveOverlay,
+    _access_guard: ArcRwLockReadGuard<parking_lot::RawRwLock, ()>,
+}
+
+impl<'a, T: HashAlgorithm> HistoricalView<'a, T> {
+    pub(crate) fn new(
+        nomt: &'a Nomt<T>,
+        root: Root,
+        access_guard: ArcRwLockReadGuard<parking_lot::RawRwLock, ()>,
+    ) -> anyhow::Result<Self> {
+        let traceback = if root == nomt.root() {
+            Default::default()
+        } else {
+            let Some(rollback) = nomt.store.rollback() else {
+                anyhow::bail!("view: rollback not enabled");
+            };
+            let Some(traceback) = rollback.traceback_to(root.into_inner()) else {
+                anyhow::bail!("view: root {} is not in the rollback log", root);
+            };
+            traceback
+        };
+
+        // the session never records a delta and is never committed, so it doesn't interfere with
+        // the rollback log. we hold a read guard and don't need the session to take another one.
+        let sess = nomt.begin_session(view_session_params());
+        let mut actuals = Vec::with_capacity(traceback.len());
+        for (key, value) in traceback {
+            sess.warm_up(key);
+            actuals.push((key, KeyReadWrite::Write(value)));
+        }
+        let overlay = sess.finish(actuals)?.into_overlay();
+        if overlay.root() != root {
+            anyhow::bail!(
+                "view: rolling back the log led to root {} instead of {}",
+                overlay.root(),
+                root
+            );
+        }
+
+        // UNWRAP: the overlay's parent is the committed state.
+        let live_overlay = LiveOverlay::new([&overlay]).unwrap();
+        Ok(HistoricalView {
+            nomt,
+            overlay,
+            live_overlay,
+            _access_guard: access_guard,
+        })
+    }
+
+    /// The root of the trie observed by this view.
+    pub fn root(&self) -> Root {
+        self.overlay.root()
+    }
+
+    /// Synchronously read the value stored under the given key as of the root of the view.
+    ///
+    /// Returns `None` if the value is not stored under the given key. Fails only if I/O fails.
+    pub fn read(&self, path: KeyPath) -> anyhow::Result<Option<Value>> {
+        Ok(crate::read_value(
+            &self.nomt.store,
+            &self.live_overlay,
+            &self.nomt.metrics,
+            path,
+        )?
+        .0)
+    }
+
+    /// Iterate the entries with keys from `start` up to, but excluding, `end`, in ascending key
+    /// order, as of the root of the view. The range extends to the last key if `end` is `None`.
+    pub fn iter_range(&self, start: KeyPath, end: Option<KeyPath>) -> HistoricalIter<'_> {
+        let changes = self
+            .live_overlay
+            .value_iter(start, end)
+            .map(|(key, change)| (key, change.as_option().map(|v| v.to_vec())))
+            .collect();
+        HistoricalIter {
+            // the cursor of the iterator over the current state is never exposed, so its root
+            // doesn't matter.
+            inner: KeyValueIter::new_range(&self.nomt.store, self.nomt.root(), start, end),
+            inner_next: None,
+            inner_done: false,
+            changes,
+        }
+    }
+
+    /// Iterate the entries with keys starting with the given prefix, in ascending key order, as
+    /// of the root of the view.
+    ///
+    /// Fails if the prefix is longer than a key.
+    pub fn iter_prefix(&self, prefix: &BitSlice<u8, Msb0>) -> anyhow::Result<HistoricalIter<'_>> {
+        if prefix.len() > 256 {
+            anyhow::bail!("prefix of {} bits is longer than a key", prefix.len());
+        }
+        let mut raw_path = KeyPath::default();
+        raw_path.view_bits_mut::<Msb0>()[..prefix.len()].copy_from_bitslice(prefix);
+        let (start, end) = merkle::range_bounds(raw_path, prefix.len());
+        Ok(self.iter_range(start, end))
+    }
+
+    /// Prove the values of the given keys as of the root of the view.
+    ///
+    /// The [`BlockWitness::prev_root`] of the returned witness is the root of the view.
+    pub fn prove(&self, keys: impl IntoIterator<Item = KeyPath>) -> anyhow::Result<BlockWitness> {
+        // UNWRAP: the overlay's parent is the committed state.
+        let params = view_session_params().overlay([&self.overlay]).unwrap();
+        block_witness::block_witness(self.nomt, params, keys)
+    }
+}
+
+fn view_session_params() -> SessionParams {
+    let mut params = SessionParams::default();
+    params.record_rollback_delta = false;
+    params.take_global_guard = false;
+    params
+}
+
+/// An iterator over the entries of a [`HistoricalView`], in ascending key order.
+pub struct HistoricalIter<'a> {
+    inner: KeyValueIter<'a>,
+    inner_next: Option<(KeyPath, Value)>,
+    inner_done: bool,
+    // the values changed since the root of the view, in key order. `None` means no value.
+    changes: VecDeque<(KeyPath, Option<Value>)>,
+}
+
+impl Iterator for HistoricalIter<'_> {
+    type Item = anyhow::Result<(KeyPath, Value)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.inner_next.is_none() && !self.inner_done {
+                match self.inner.next() {
+                    None => self.inner_done = true,
+                    Some(Err(e)) => return Some(Err(e)),
+                    Some(Ok(entry)) => self.inner_next = Some(entry),
+                }
+            }
+
+            let change_key = self.changes.front().map(|(key, _)| *key);
+            match (&self.inner_next, change_key) {
+                (None, None) => return None,
+                (Some((key, _)), Some(change_key)) if *key < change_key => {
+                    return self.inner_next.take().map(Ok)
+                }
+                (Some(_), None) => return self.inner_next.take().map(Ok),
+                (inner_next, Some(change_key)) => {
+                    // the changed value shadows the current one.
+                    if inner_next
+                        .as_ref()
+                        .is_some_and(|(key, _)| *key == change_key)
+                    {
+                        self.inner_next = None;
+                    }
+                    // UNWRAP: checked above that there is a change.
+                    if let (key, Some(value)) = self.changes.pop_front().unwrap() {
+                        return Some(Ok((key, value)));
+                    }
+                }
+            }
+        }
+    }
+}
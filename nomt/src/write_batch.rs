@@ -0,0 +1,185 @@
+//! A validated set of key accesses to finish a session with.
+
+use std::collections::{btree_map::Entry, BTreeMap};
+
+use crate::{
+    trie::KeyPath, CommitLimit, CommitLimitExceeded, CommitLimits, KeyReadWrite, Value,
+    MAX_VALUE_SIZE,
+};
+
+/// The reads and writes of a session, validated as they are added. See
+/// [`crate::Session::finish_batch`].
+///
+/// This is an alternative to passing a sorted list of [`KeyReadWrite`]s to
+/// [`crate::Session::finish`]. Keys are kept sorted, and a write which conflicts with an earlier
+/// one, a value larger than [`MAX_VALUE_SIZE`] or a batch exceeding its limits is reported when it
+/// is added rather than when the session is finished or committed.
+///
+/// The batch records accesses, not a sequence of operations: writing the same value twice is
+/// allowed, but writing two different values to a key is a conflict.
+#[derive(Debug, Clone, Default)]
+pub struct WriteBatch {
+    accesses: BTreeMap<KeyPath, KeyReadWrite>,
+    limits: CommitLimits,
+    writes: u64,
+    value_bytes: u64,
+}
+
+/// The error returned when adding an access to a [`WriteBatch`] fails. The batch is left as it
+/// was.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WriteBatchError {
+    /// The key was already written with a different value.
+    ConflictingWrite(KeyPath),
+    /// The key was already read with a different value.
+    ConflictingRead(KeyPath),
+    /// The value is larger than [`MAX_VALUE_SIZE`].
+    ValueTooLarge {
+        /// The key being written.
+        key: KeyPath,
+        /// The size of the value.
+        len: usize,
+    },
+    /// The batch would exceed one of its limits. See [`WriteBatch::with_limits`].
+    LimitExceeded(CommitLimitExceeded),
+}
+
+impl std::fmt::Display for WriteBatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WriteBatchError::ConflictingWrite(key) => {
+                write!(f, "conflicting writes to key {:?}", key)
+            }
+            WriteBatchError::ConflictingRead(key) => {
+                write!(f, "conflicting reads of key {:?}", key)
+            }
+            WriteBatchError::ValueTooLarge { key, len } => write!(
+                f,
+                "value of {} bytes for key {:?} exceeds the maximum of {} bytes",
+                len, key, MAX_VALUE_SIZE,
+            ),
+            WriteBatchError::LimitExceeded(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for WriteBatchError {}
+
+impl WriteBatch {
+    /// Create an empty batch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check the number of written keys and the size of the written values against the limits as
+    /// accesses are added. The page limit is ignored, as pages are only known once the session is
+    /// finished. Default: unlimited
+    pub fn with_limits(mut self, limits: CommitLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Write a value to the key.
+    pub fn put(&mut self, key: KeyPath, value: Value) -> Result<(), WriteBatchError> {
+        if value.len() > MAX_VALUE_SIZE {
+            return Err(WriteBatchError::ValueTooLarge {
+                key,
+                len: value.len(),
+            });
+        }
+        self.write(key, Some(value))
+    }
+
+    /// Delete the key.
+    pub fn delete(&mut self, key: KeyPath) -> Result<(), WriteBatchError> {
+        self.write(key, None)
+    }
+
+    /// Record that the key was read with the given value, e.g. to include it in the witness.
+    pub fn read(&mut self, key: KeyPath, value: Option<Value>) -> Result<(), WriteBatchError> {
+        match self.accesses.entry(key) {
+            Entry::Vacant(entry) => {
+                entry.insert(KeyReadWrite::Read(value));
+            }
+            Entry::Occupied(mut entry) => match entry.get_mut() {
+                KeyReadWrite::Read(prior) | KeyReadWrite::ReadThenWrite(prior, _) => {
+                    if *prior != value {
+                        return Err(WriteBatchError::ConflictingRead(key));
+                    }
+                }
+                access @ KeyReadWrite::Write(_) => access.read(value),
+            },
+        }
+        Ok(())
+    }
+
+    /// The number of keys accessed by the batch.
+    pub fn len(&self) -> usize {
+        self.accesses.len()
+    }
+
+    /// Whether the batch accesses no keys.
+    pub fn is_empty(&self) -> bool {
+        self.accesses.is_empty()
+    }
+
+    /// The number of keys written or deleted by the batch.
+    pub fn writes(&self) -> u64 {
+        self.writes
+    }
+
+    /// The total size of the values written by the batch, in bytes.
+    pub fn value_bytes(&self) -> u64 {
+        self.value_bytes
+    }
+
+    /// The access to the key, if any.
+    pub fn get(&self, key: &KeyPath) -> Option<&KeyReadWrite> {
+        self.accesses.get(key)
+    }
+
+    fn write(&mut self, key: KeyPath, value: Option<Value>) -> Result<(), WriteBatchError> {
+        let len = value.as_ref().map_or(0, |value| value.len() as u64);
+        match self.accesses.get(&key) {
+            Some(KeyReadWrite::Write(written) | KeyReadWrite::ReadThenWrite(_, written)) => {
+                if *written != value {
+                    return Err(WriteBatchError::ConflictingWrite(key));
+                }
+                return Ok(());
+            }
+            Some(KeyReadWrite::Read(_)) | None => {}
+        }
+
+        check(CommitLimit::Keys, self.writes + 1, self.limits.max_keys)?;
+        check(
+            CommitLimit::ValueBytes,
+            self.value_bytes + len,
+            self.limits.max_value_bytes,
+        )?;
+        self.writes += 1;
+        self.value_bytes += len;
+        match self.accesses.entry(key) {
+            Entry::Vacant(entry) => {
+                entry.insert(KeyReadWrite::Write(value));
+            }
+            Entry::Occupied(mut entry) => entry.get_mut().write(value),
+        }
+        Ok(())
+    }
+
+    /// The accesses of the batch, sorted by key, as accepted by [`crate::Session::finish`].
+    pub fn into_actuals(self) -> Vec<(KeyPath, KeyReadWrite)> {
+        self.accesses.into_iter().collect()
+    }
+}
+
+fn check(limit: CommitLimit, actual: u64, max: Option<u64>) -> Result<(), WriteBatchError> {
+    match max {
+        Some(max) if actual > max => Err(WriteBatchError::LimitExceeded(CommitLimitExceeded {
+            limit,
+            actual,
+            max,
+        })),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,119 @@
+use nomt::{
+    dump::Compression, hasher::Blake3Hasher, trie::KeyPath, KeyReadWrite, Nomt, Options, Root,
+    SessionParams,
+};
+use std::path::PathBuf;
+
+/// Setup a NOMT with the given path, optionally recording a backup log under the same name.
+///
+/// It's important that tests that run in parallel don't use the same path.
+fn setup_nomt(path: &str, backup: bool) -> Nomt<Blake3Hasher> {
+    let path = {
+        let mut p = PathBuf::from("test");
+        p.push(path);
+        p
+    };
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let backup_path = path.with_extension("backup");
+    if backup_path.exists() {
+        std::fs::remove_file(&backup_path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(1);
+    o.bitbox_seed([0; 16]);
+    if backup {
+        o.backup_log(backup_path);
+    }
+    Nomt::open(o).unwrap()
+}
+
+fn key(id: u8) -> KeyPath {
+    let mut key = [0; 32];
+    key[0] = id;
+    key[31] = id;
+    key
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, changes: Vec<(u8, Option<Vec<u8>>)>) -> Root {
+    let session = nomt.begin_session(SessionParams::default());
+    let actuals = changes
+        .into_iter()
+        .map(|(id, value)| (key(id), KeyReadWrite::Write(value)))
+        .collect();
+    let finished = session.finish(actuals).unwrap();
+    let root = finished.root();
+    finished.commit(nomt).unwrap();
+    root
+}
+
+#[test]
+fn restore_snapshot_and_deltas() {
+    let nomt = setup_nomt("restore_snapshot_and_deltas", true);
+
+    // commits before the snapshot are skipped on restore.
+    commit(&nomt, vec![(1, Some(vec![1]))]);
+    commit(&nomt, vec![(2, Some(vec![2])), (3, Some(vec![3]))]);
+
+    let snapshot = "test/restore_snapshot_and_deltas.dump";
+    nomt.export(snapshot, Compression::None).unwrap();
+
+    let root_a = commit(&nomt, vec![(1, None), (4, Some(vec![4; 5000]))]);
+
+    // overlays are recorded as well.
+    let session = nomt.begin_session(SessionParams::default());
+    let finished = session
+        .finish(vec![(key(5), KeyReadWrite::Write(Some(vec![5])))])
+        .unwrap();
+    let root_b = finished.root();
+    finished.into_overlay().commit(&nomt).unwrap();
+
+    let root_c = commit(&nomt, vec![(2, Some(vec![22]))]);
+    assert_eq!(nomt.root(), root_c);
+
+    let backup_log = "test/restore_snapshot_and_deltas.backup";
+
+    let restored = setup_nomt("restore_snapshot_and_deltas_full", false);
+    assert_eq!(
+        restored.restore(snapshot, backup_log, None).unwrap(),
+        root_c
+    );
+    assert_eq!(restored.root(), root_c);
+    assert_eq!(restored.read(key(2)).unwrap(), Some(vec![22]));
+    assert_eq!(restored.read(key(4)).unwrap(), Some(vec![4; 5000]));
+    assert_eq!(restored.read(key(1)).unwrap(), None);
+
+    let restored = setup_nomt("restore_snapshot_and_deltas_partial", false);
+    assert_eq!(
+        restored
+            .restore(snapshot, backup_log, Some(root_b))
+            .unwrap(),
+        root_b
+    );
+    assert_eq!(restored.root(), root_b);
+    assert_eq!(restored.read(key(2)).unwrap(), Some(vec![2]));
+    assert_eq!(restored.read(key(5)).unwrap(), Some(vec![5]));
+    assert_ne!(root_a, root_b);
+}
+
+#[test]
+fn restore_rejects_unknown_target() {
+    let nomt = setup_nomt("restore_rejects_unknown_target", true);
+    commit(&nomt, vec![(1, Some(vec![1]))]);
+
+    let snapshot = "test/restore_rejects_unknown_target.dump";
+    nomt.export(snapshot, Compression::None).unwrap();
+    commit(&nomt, vec![(2, Some(vec![2]))]);
+
+    let restored = setup_nomt("restore_rejects_unknown_target_restored", false);
+    let res = restored.restore(
+        snapshot,
+        "test/restore_rejects_unknown_target.backup",
+        Some(Root::from([0xff; 32])),
+    );
+    assert!(res.is_err());
+    // nothing is written when the target is not found.
+    assert!(restored.root().is_empty());
+}
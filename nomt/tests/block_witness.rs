@@ -0,0 +1,125 @@
+mod common;
+
+use nomt::{
+    hasher::{Blake3Hasher, ValueHasher},
+    KeyReadWrite, Nomt, Options, SessionParams,
+};
+use std::path::PathBuf;
+
+fn open(name: &str) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(4);
+    o.bitbox_seed([0; 16]);
+    Nomt::open(o).unwrap()
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, writes: impl IntoIterator<Item = (u64, Option<u64>)>) {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals = writes
+        .into_iter()
+        .map(|(id, value)| {
+            (
+                common::account_path(id),
+                KeyReadWrite::Write(value.map(|v| v.to_le_bytes().to_vec())),
+            )
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(k, _)| *k);
+    session.finish(actuals).unwrap().commit(nomt).unwrap();
+}
+
+fn value_hash(value: u64) -> [u8; 32] {
+    Blake3Hasher::hash_value(&value.to_le_bytes())
+}
+
+#[test]
+fn stateless_block_matches_commit() {
+    let nomt = open("block_witness");
+    commit(&nomt, (0..1000).map(|id| (id, Some(id))));
+
+    // the block updates some keys, deletes some and inserts new ones.
+    let updated = 0..20;
+    let deleted = 500..510;
+    let inserted = 2000..2020;
+    let keys = updated
+        .clone()
+        .chain(deleted.clone())
+        .chain(inserted.clone())
+        .map(common::account_path);
+    let witness = nomt.block_witness(keys).unwrap();
+    assert_eq!(witness.prev_root, nomt.root());
+    assert_eq!(witness.values.len(), 50);
+
+    let mut builder = witness.builder::<Blake3Hasher>().unwrap();
+    for id in updated.clone() {
+        let key = common::account_path(id);
+        assert_eq!(builder.read(&key).unwrap(), Some(value_hash(id)));
+        builder.write(key, Some(value_hash(id + 1))).unwrap();
+        assert_eq!(builder.read(&key).unwrap(), Some(value_hash(id + 1)));
+    }
+    for id in deleted.clone() {
+        builder.write(common::account_path(id), None).unwrap();
+    }
+    for id in inserted.clone() {
+        let key = common::account_path(id);
+        assert_eq!(builder.read(&key).unwrap(), None);
+        builder.write(key, Some(value_hash(id))).unwrap();
+    }
+    // keys outside of the witness can be neither read nor written.
+    assert!(builder.read(&common::account_path(700)).is_err());
+    assert!(builder.write(common::account_path(700), None).is_err());
+    let post_root = builder.finish().unwrap();
+
+    commit(
+        &nomt,
+        updated
+            .map(|id| (id, Some(id + 1)))
+            .chain(deleted.map(|id| (id, None)))
+            .chain(inserted.map(|id| (id, Some(id)))),
+    );
+    assert_eq!(post_root, nomt.root().into_inner());
+
+    // a block without writes leaves the root as it was.
+    let witness = nomt.block_witness([common::account_path(1)]).unwrap();
+    let builder = witness.builder::<Blake3Hasher>().unwrap();
+    assert_eq!(builder.finish().unwrap(), nomt.root().into_inner());
+}
+
+#[test]
+fn tampered_witness_is_rejected() {
+    let nomt = open("block_witness_tampered");
+    commit(&nomt, (0..100).map(|id| (id, Some(id))));
+
+    let keys = (0..10).map(common::account_path);
+    let mut witness = nomt.block_witness(keys).unwrap();
+    witness.values[3].1 = Some(vec![1, 2, 3]);
+    assert!(witness.builder::<Blake3Hasher>().is_err());
+
+    let keys = (0..10).map(common::account_path);
+    let mut witness = nomt.block_witness(keys).unwrap();
+    witness.path_proofs[0].siblings[0][0] ^= 1;
+    assert!(witness.builder::<Blake3Hasher>().is_err());
+}
+
+#[test]
+fn stateless_block_on_empty_trie() {
+    let nomt = open("block_witness_empty");
+    let witness = nomt
+        .block_witness((0..10).map(common::account_path))
+        .unwrap();
+    let mut builder = witness.builder::<Blake3Hasher>().unwrap();
+    for id in 0..10 {
+        builder
+            .write(common::account_path(id), Some(value_hash(id)))
+            .unwrap();
+    }
+    let post_root = builder.finish().unwrap();
+
+    commit(&nomt, (0..10).map(|id| (id, Some(id))));
+    assert_eq!(post_root, nomt.root().into_inner());
+}
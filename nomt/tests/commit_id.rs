@@ -0,0 +1,70 @@
+mod common;
+
+use nomt::{hasher::Blake3Hasher, FinishedSession, KeyReadWrite, Nomt, Options, SessionParams};
+use std::path::PathBuf;
+
+fn open(name: &str, reset: bool) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if reset && path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(1);
+    o.bitbox_seed([0; 16]);
+    Nomt::open(o).unwrap()
+}
+
+fn finish(nomt: &Nomt<Blake3Hasher>, commit_id: Option<u64>, id: u64) -> FinishedSession {
+    let mut params = SessionParams::default();
+    if let Some(commit_id) = commit_id {
+        params = params.commit_id(commit_id);
+    }
+    let session = nomt.begin_session(params);
+    let actuals = vec![(
+        common::account_path(id),
+        KeyReadWrite::Write(Some(id.to_le_bytes().to_vec())),
+    )];
+    session.finish(actuals).unwrap()
+}
+
+#[test]
+fn replayed_commit_is_a_no_op() {
+    let nomt = open("commit_id_replay", true);
+    assert_eq!(nomt.last_commit_id(), None);
+    assert_eq!(finish(&nomt, Some(1), 1).commit(&nomt).unwrap(), 1);
+    assert_eq!(nomt.last_commit_id(), Some(1));
+    let root = nomt.root();
+
+    // replaying the commit on top of its result doesn't apply it again.
+    assert_eq!(finish(&nomt, Some(1), 1).commit(&nomt).unwrap(), 1);
+    assert_eq!(nomt.root(), root);
+    assert_eq!(nomt.current_sequence(), 1);
+    drop(nomt);
+
+    // neither does it after reopening.
+    let nomt = open("commit_id_replay", false);
+    assert_eq!(nomt.last_commit_id(), Some(1));
+    assert_eq!(finish(&nomt, Some(1), 1).commit(&nomt).unwrap(), 1);
+    assert_eq!(nomt.root(), root);
+
+    assert_eq!(finish(&nomt, Some(2), 2).commit(&nomt).unwrap(), 2);
+    assert_eq!(finish(&nomt, None, 3).commit(&nomt).unwrap(), 3);
+    assert_eq!(nomt.last_commit_id(), None);
+}
+
+#[test]
+fn duplicate_commit_is_a_no_op() {
+    let nomt = open("commit_id_duplicate", true);
+    let first = finish(&nomt, Some(7), 1);
+    let duplicate = finish(&nomt, Some(7), 1);
+    let conflicting = finish(&nomt, Some(7), 2);
+
+    assert_eq!(first.commit(&nomt).unwrap(), 1);
+    let root = nomt.root();
+    assert_eq!(duplicate.commit(&nomt).unwrap(), 1);
+    assert_eq!(nomt.root(), root);
+    assert!(conflicting.commit(&nomt).is_err());
+    assert_eq!(nomt.root(), root);
+    assert_eq!(nomt.current_sequence(), 1);
+}
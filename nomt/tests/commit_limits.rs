@@ -0,0 +1,91 @@
+mod common;
+
+use nomt::{
+    hasher::Blake3Hasher, CommitLimit, CommitLimitExceeded, CommitLimits, KeyReadWrite, Nomt,
+    Options, SessionParams,
+};
+use std::path::PathBuf;
+
+fn open(name: &str, limits: CommitLimits) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(1);
+    o.bitbox_seed([0; 16]);
+    o.commit_limits(limits);
+    Nomt::open(o).unwrap()
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, ids: std::ops::Range<u64>, len: usize) -> anyhow::Result<()> {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals: Vec<_> = ids
+        .map(|id| {
+            (
+                common::account_path(id),
+                KeyReadWrite::Write(Some(vec![1; len])),
+            )
+        })
+        .collect();
+    actuals.sort_by_key(|(k, _)| *k);
+    session.finish(actuals)?.commit(nomt)?;
+    Ok(())
+}
+
+fn exceeded(res: anyhow::Result<()>) -> CommitLimitExceeded {
+    *res.unwrap_err()
+        .downcast_ref::<CommitLimitExceeded>()
+        .unwrap()
+}
+
+#[test]
+fn max_keys() {
+    let nomt = open(
+        "commit_limits_keys",
+        CommitLimits {
+            max_keys: Some(10),
+            ..Default::default()
+        },
+    );
+    commit(&nomt, 0..10, 1).unwrap();
+    let root = nomt.root();
+
+    let err = exceeded(commit(&nomt, 10..21, 1));
+    assert_eq!(err.limit, CommitLimit::Keys);
+    assert_eq!((err.actual, err.max), (11, 10));
+
+    // nothing was written.
+    assert_eq!(nomt.root(), root);
+    assert_eq!(nomt.read(common::account_path(10)).unwrap(), None);
+}
+
+#[test]
+fn max_value_bytes() {
+    let nomt = open(
+        "commit_limits_value_bytes",
+        CommitLimits {
+            max_value_bytes: Some(1000),
+            ..Default::default()
+        },
+    );
+    commit(&nomt, 0..10, 100).unwrap();
+    let err = exceeded(commit(&nomt, 0..10, 101));
+    assert_eq!(err.limit, CommitLimit::ValueBytes);
+    assert_eq!(err.actual, 1010);
+}
+
+#[test]
+fn max_pages() {
+    let nomt = open(
+        "commit_limits_pages",
+        CommitLimits {
+            max_pages: Some(1),
+            ..Default::default()
+        },
+    );
+    let err = exceeded(commit(&nomt, 0..10_000, 1));
+    assert_eq!(err.limit, CommitLimit::Pages);
+    assert!(nomt.is_empty());
+}
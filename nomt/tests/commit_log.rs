@@ -0,0 +1,112 @@
+use nomt::{hasher::Blake3Hasher, CommitLogThresholds, KeyReadWrite, Nomt, Options, SessionParams};
+use parking_lot::Mutex;
+use std::{path::PathBuf, sync::Arc};
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Metadata, Subscriber,
+};
+
+fn open(name: &str, thresholds: CommitLogThresholds) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_log_thresholds(thresholds);
+    Nomt::open(o).unwrap()
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, keys: u8) {
+    let actuals = (0..keys)
+        .map(|i| ([i; 32], KeyReadWrite::Write(Some(vec![i; 100]))))
+        .collect();
+    nomt.begin_session(SessionParams::default())
+        .finish(actuals)
+        .unwrap()
+        .commit(nomt)
+        .unwrap();
+}
+
+// Records the fields of the events with the commit log target.
+#[derive(Clone, Default)]
+struct Capture(Arc<Mutex<Vec<Vec<(String, String)>>>>);
+
+impl Subscriber for Capture {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+    fn new_span(&self, _: &span::Attributes) -> span::Id {
+        span::Id::from_u64(1)
+    }
+    fn record(&self, _: &span::Id, _: &span::Record) {}
+    fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+    fn event(&self, event: &Event) {
+        if event.metadata().target() != "nomt::commit" {
+            return;
+        }
+        let mut fields = Fields(Vec::new());
+        event.record(&mut fields);
+        self.0.lock().push(fields.0);
+    }
+    fn enter(&self, _: &span::Id) {}
+    fn exit(&self, _: &span::Id) {}
+}
+
+struct Fields(Vec<(String, String)>);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .push((field.name().to_string(), format!("{:?}", value)));
+    }
+}
+
+fn field<'a>(fields: &'a [(String, String)], name: &str) -> &'a str {
+    &fields.iter().find(|(n, _)| n == name).unwrap().1
+}
+
+#[test]
+fn commits_above_thresholds_are_logged() {
+    let nomt = open(
+        "commit_log",
+        CommitLogThresholds {
+            keys: Some(2),
+            ..CommitLogThresholds::default()
+        },
+    );
+    let capture = Capture::default();
+    tracing::subscriber::with_default(capture.clone(), || {
+        commit(&nomt, 2);
+        assert!(capture.0.lock().is_empty());
+
+        commit(&nomt, 3);
+    });
+
+    let events = capture.0.lock();
+    assert_eq!(events.len(), 1);
+    let fields = &events[0];
+    assert_eq!(field(fields, "sequence"), "2");
+    assert_eq!(field(fields, "keys"), "3");
+    assert_eq!(field(fields, "deleted_keys"), "0");
+    assert_eq!(field(fields, "value_bytes"), "300");
+    assert!(field(fields, "pages").parse::<u64>().unwrap() > 0);
+    for phase in [
+        "prepare_us",
+        "journal_us",
+        "sync_us",
+        "finish_us",
+        "duration_us",
+    ] {
+        field(fields, phase).parse::<u64>().unwrap();
+    }
+    assert!(field(fields, "largest_subtrees").starts_with("[("));
+}
+
+#[test]
+fn nothing_is_logged_by_default() {
+    let nomt = open("commit_log_default", CommitLogThresholds::default());
+    let capture = Capture::default();
+    tracing::subscriber::with_default(capture.clone(), || commit(&nomt, 10));
+    assert!(capture.0.lock().is_empty());
+}
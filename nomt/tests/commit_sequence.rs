@@ -0,0 +1,65 @@
+mod common;
+
+use nomt::{hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, SessionParams};
+use std::{path::PathBuf, time::Duration};
+
+fn open(name: &str, reset: bool) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if reset && path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(1);
+    o.bitbox_seed([0; 16]);
+    o.rollback(true);
+    Nomt::open(o).unwrap()
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, id: u64) -> u64 {
+    let session = nomt.begin_session(SessionParams::default());
+    let actuals = vec![(
+        common::account_path(id),
+        KeyReadWrite::Write(Some(id.to_le_bytes().to_vec())),
+    )];
+    session.finish(actuals).unwrap().commit(nomt).unwrap()
+}
+
+#[test]
+fn sequence_increases_and_persists() {
+    let nomt = open("commit_sequence_persist", true);
+    assert_eq!(nomt.current_sequence(), 0);
+    assert_eq!(commit(&nomt, 1), 1);
+    assert_eq!(commit(&nomt, 2), 2);
+    assert_eq!(nomt.current_sequence(), 2);
+
+    let params = SessionParams::default().overlay(None).unwrap();
+    let session = nomt.begin_session(params);
+    let overlay = session.finish(Vec::new()).unwrap().into_overlay();
+    assert_eq!(overlay.commit(&nomt).unwrap(), 3);
+
+    // a rollback is a commit too.
+    nomt.rollback(1).unwrap();
+    assert_eq!(nomt.current_sequence(), 4);
+    let root = nomt.root();
+    drop(nomt);
+
+    let nomt = open("commit_sequence_persist", false);
+    assert_eq!(nomt.current_sequence(), 4);
+    assert_eq!(nomt.root(), root);
+    assert_eq!(commit(&nomt, 3), 5);
+}
+
+#[test]
+fn wait_for_sequence() {
+    let nomt = open("commit_sequence_wait", true);
+    assert!(nomt.wait_for_sequence(0, Duration::ZERO));
+    assert!(!nomt.wait_for_sequence(1, Duration::from_millis(10)));
+
+    std::thread::scope(|s| {
+        let waiter = s.spawn(|| nomt.wait_for_sequence(2, Duration::from_secs(60)));
+        commit(&nomt, 1);
+        commit(&nomt, 2);
+        assert!(waiter.join().unwrap());
+    });
+}
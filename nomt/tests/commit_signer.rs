@@ -0,0 +1,135 @@
+mod common;
+
+use nomt::{
+    hasher::Blake3Hasher, CommitChanges, CommitSink, KeyReadWrite, Nomt, Options, Root,
+    SessionParams, MAX_SIGNATURE_LEN,
+};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+// a stand-in for a real signature scheme.
+fn sign(root: Root) -> Vec<u8> {
+    let mut signature = b"signed:".to_vec();
+    signature.extend_from_slice(&root.into_inner());
+    signature
+}
+
+#[derive(Default)]
+struct RecordingSink {
+    signatures: Mutex<Vec<Option<Vec<u8>>>>,
+}
+
+impl CommitSink for RecordingSink {
+    fn write(&self, changes: &CommitChanges) -> anyhow::Result<()> {
+        self.signatures
+            .lock()
+            .unwrap()
+            .push(changes.signature.map(|s| s.to_vec()));
+        Ok(())
+    }
+}
+
+fn open(
+    name: &str,
+    reset: bool,
+    signature_len: Option<Arc<AtomicUsize>>,
+    sink: Option<Arc<RecordingSink>>,
+) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if reset && path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(1);
+    o.bitbox_seed([0; 16]);
+    if let Some(signature_len) = signature_len {
+        // a length of zero makes the signer fail.
+        o.commit_signer(Arc::new(move |root: Root| {
+            let len = signature_len.load(Ordering::Relaxed);
+            if len == 0 {
+                anyhow::bail!("signing key unavailable");
+            }
+            let mut signature = sign(root);
+            signature.resize(len, 0);
+            Ok(signature)
+        }));
+    }
+    if let Some(sink) = sink {
+        o.commit_sink(sink);
+    }
+    Nomt::open(o).unwrap()
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, id: u64) -> anyhow::Result<Root> {
+    let session = nomt.begin_session(SessionParams::default());
+    let actuals = vec![(
+        common::account_path(id),
+        KeyReadWrite::Write(Some(id.to_le_bytes().to_vec())),
+    )];
+    let finished = session.finish(actuals)?;
+    let root = finished.root();
+    finished.commit(nomt)?;
+    Ok(root)
+}
+
+#[test]
+fn commits_are_signed() {
+    let signature_len = Arc::new(AtomicUsize::new(39));
+    let sink = Arc::new(RecordingSink::default());
+    let root = {
+        let nomt = open(
+            "commit_signer",
+            true,
+            Some(signature_len.clone()),
+            Some(sink.clone()),
+        );
+        assert_eq!(nomt.root_signature(), None);
+        let root = commit(&nomt, 1).unwrap();
+        assert_eq!(nomt.root_signature(), Some(sign(root)));
+        assert_eq!(*sink.signatures.lock().unwrap(), vec![Some(sign(root))]);
+
+        // a failing signer aborts the commit.
+        signature_len.store(0, Ordering::Relaxed);
+        assert!(commit(&nomt, 2).is_err());
+        assert_eq!(nomt.root(), root);
+
+        signature_len.store(MAX_SIGNATURE_LEN + 1, Ordering::Relaxed);
+        assert!(commit(&nomt, 2).is_err());
+        assert_eq!(nomt.root(), root);
+        assert_eq!(sink.signatures.lock().unwrap().len(), 1);
+        root
+    };
+
+    // the signature survives reopening, even without a signer.
+    let nomt = open("commit_signer", false, None, None);
+    assert_eq!(nomt.root_signature(), Some(sign(root)));
+
+    // and is cleared by a commit without one.
+    commit(&nomt, 2).unwrap();
+    assert_eq!(nomt.root_signature(), None);
+}
+
+#[test]
+fn overlay_commits_are_signed() {
+    let nomt = open(
+        "commit_signer_overlay",
+        true,
+        Some(Arc::new(AtomicUsize::new(MAX_SIGNATURE_LEN))),
+        None,
+    );
+    let session = nomt.begin_session(SessionParams::default());
+    let actuals = vec![(common::account_path(1), KeyReadWrite::Write(Some(vec![1])))];
+    let overlay = session.finish(actuals).unwrap().into_overlay();
+    let root = overlay.root();
+    overlay.commit(&nomt).unwrap();
+
+    let mut expected = sign(root);
+    expected.resize(MAX_SIGNATURE_LEN, 0);
+    assert_eq!(nomt.root_signature(), Some(expected));
+}
@@ -0,0 +1,153 @@
+mod common;
+
+use nomt::{
+    hasher::Blake3Hasher, CommitChanges, CommitSink, KeyReadWrite, Nomt, Options, Root,
+    SessionParams,
+};
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+#[derive(Default)]
+struct RecordingSink {
+    fail: AtomicBool,
+    roots: Mutex<Vec<(Root, Root)>>,
+    values: Mutex<BTreeMap<[u8; 32], Option<Vec<u8>>>>,
+    pages: Mutex<BTreeMap<[u8; 32], Option<Vec<u8>>>>,
+}
+
+impl CommitSink for RecordingSink {
+    fn write(&self, changes: &CommitChanges) -> anyhow::Result<()> {
+        if self.fail.load(Ordering::Relaxed) {
+            anyhow::bail!("sink unavailable");
+        }
+        self.roots
+            .lock()
+            .unwrap()
+            .push((changes.prev_root, changes.root));
+        let mut values = self.values.lock().unwrap();
+        for change in &changes.values {
+            values.insert(change.key_path, change.value.map(|v| v.to_vec()));
+        }
+        let mut pages = self.pages.lock().unwrap();
+        for change in &changes.pages {
+            pages.insert(change.page_id, change.data.map(|d| d.to_vec()));
+        }
+        Ok(())
+    }
+}
+
+fn open(name: &str, sink: Arc<RecordingSink>) -> Nomt<Blake3Hasher> {
+    let path = {
+        let mut p = PathBuf::from("test");
+        p.push(name);
+        p
+    };
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(1);
+    o.bitbox_seed([0; 16]);
+    o.commit_sink(sink);
+    Nomt::open(o).unwrap()
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, writes: &[(u64, Option<Vec<u8>>)]) -> anyhow::Result<Root> {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals: Vec<_> = writes
+        .iter()
+        .map(|(id, value)| {
+            (
+                common::account_path(*id),
+                KeyReadWrite::Write(value.clone()),
+            )
+        })
+        .collect();
+    actuals.sort_by_key(|(k, _)| *k);
+    let finished = session.finish(actuals)?;
+    let root = finished.root();
+    finished.commit(nomt)?;
+    Ok(root)
+}
+
+#[test]
+fn sink_receives_every_commit() {
+    let sink = Arc::new(RecordingSink::default());
+    let nomt = open("commit_sink_receives", sink.clone());
+
+    let writes: Vec<_> = (0..1000u64)
+        .map(|id| (id, Some(id.to_le_bytes().to_vec())))
+        .collect();
+    let root_1 = commit(&nomt, &writes).unwrap();
+    let root_2 = commit(&nomt, &[(3, None), (4, Some(vec![4; 10]))]).unwrap();
+
+    assert_eq!(
+        *sink.roots.lock().unwrap(),
+        vec![(Root::from([0; 32]), root_1), (root_1, root_2)]
+    );
+
+    let values = sink.values.lock().unwrap();
+    assert_eq!(values.len(), 1000);
+    assert_eq!(values[&common::account_path(3)], None);
+    assert_eq!(values[&common::account_path(4)], Some(vec![4; 10]));
+    assert_eq!(
+        values[&common::account_path(5)],
+        Some(5u64.to_le_bytes().to_vec())
+    );
+
+    let pages = sink.pages.lock().unwrap();
+    assert!(pages.values().any(|data| data.is_some()));
+    assert!(pages.values().flatten().all(|data| data.len() == 4096));
+}
+
+#[test]
+fn failing_sink_aborts_commit() {
+    let sink = Arc::new(RecordingSink::default());
+    let nomt = open("commit_sink_failing", sink.clone());
+
+    let root = commit(&nomt, &[(1, Some(vec![1]))]).unwrap();
+
+    sink.fail.store(true, Ordering::Relaxed);
+    assert!(commit(&nomt, &[(2, Some(vec![2]))]).is_err());
+    assert_eq!(nomt.root(), root);
+    assert_eq!(nomt.read(common::account_path(2)).unwrap(), None);
+
+    // the database remains usable.
+    sink.fail.store(false, Ordering::Relaxed);
+    let root = commit(&nomt, &[(2, Some(vec![2]))]).unwrap();
+    assert_eq!(nomt.root(), root);
+    assert_eq!(sink.roots.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn finished_session_exposes_page_changes() {
+    let sink = Arc::new(RecordingSink::default());
+    let nomt = open("commit_sink_page_changes", sink.clone());
+
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals: Vec<_> = (0..500u64)
+        .map(|id| (common::account_path(id), KeyReadWrite::Write(Some(vec![1]))))
+        .collect();
+    actuals.sort_by_key(|(k, _)| *k);
+    let finished = session.finish(actuals).unwrap();
+
+    let changes: BTreeMap<_, _> = finished
+        .page_changes()
+        .into_iter()
+        .map(|change| {
+            assert!(change.diff.count() > 0);
+            (change.page_id, change.data.map(|d| d.to_vec()))
+        })
+        .collect();
+    assert!(!changes.is_empty());
+
+    finished.commit(&nomt).unwrap();
+    assert_eq!(*sink.pages.lock().unwrap(), changes);
+}
@@ -1,7 +1,7 @@
 use nomt::{
     trie::{KeyPath, Node},
-    KeyReadWrite, Nomt, Options, Overlay, PanicOnSyncMode, Root, Session, SessionParams, Witness,
-    WitnessMode,
+    KeyReadWrite, Nomt, Options, Overlay, PanicOnSyncMode, ReadSession, Root, Session,
+    SessionParams, Witness, WitnessMode,
 };
 use std::{
     collections::{hash_map::Entry, HashMap},
@@ -172,6 +172,69 @@ impl Test {
     pub fn root(&self) -> Root {
         self.nomt.root()
     }
+
+    #[cfg(feature = "eth")]
+    pub fn eth_get_proof(
+        &mut self,
+        address: nomt::eth::Address,
+        slots: &[nomt::eth::StorageSlot],
+    ) -> nomt::eth::EthProof {
+        // force drop of live session before creating a new one.
+        self.access.clear();
+        self.session = None;
+        let proof = nomt::eth::get_proof(&self.nomt, address, slots).unwrap();
+        self.session = Some(
+            self.nomt
+                .begin_session(SessionParams::default().witness_mode(WitnessMode::read_write())),
+        );
+        proof
+    }
+
+    pub fn export(&self, path: impl AsRef<Path>, compression: nomt::dump::Compression) -> u64 {
+        self.nomt.export(path, compression).unwrap()
+    }
+
+    pub fn export_snapshot(&self, path: impl AsRef<Path>) -> u64 {
+        self.nomt.export_snapshot(path).unwrap()
+    }
+
+    pub fn trie_stats(&self, mode: nomt::TrieStatsMode) -> nomt::TrieStats {
+        self.nomt.trie_stats(mode).unwrap()
+    }
+
+    pub fn page_utilization(&self) -> nomt::PageUtilization {
+        self.nomt.page_utilization().unwrap()
+    }
+
+    pub fn import(&mut self, path: impl AsRef<Path>) -> anyhow::Result<u64> {
+        // force drop of live session: import commits.
+        self.access.clear();
+        self.session = None;
+        let res = self.nomt.import(path);
+        self.session = Some(
+            self.nomt
+                .begin_session(SessionParams::default().witness_mode(WitnessMode::read_write())),
+        );
+        res
+    }
+
+    pub fn reset(&mut self) {
+        // force drop of live session: reset commits.
+        self.access.clear();
+        self.session = None;
+        self.nomt.reset().unwrap();
+        self.session = Some(
+            self.nomt
+                .begin_session(SessionParams::default().witness_mode(WitnessMode::read_write())),
+        );
+    }
+
+    pub fn begin_read_session(&mut self) -> ReadSession<nomt::hasher::Blake3Hasher> {
+        // force drop of live session before creating a new one.
+        self.access.clear();
+        self.session = None;
+        self.nomt.begin_read_session()
+    }
 }
 
 pub fn read_balance(t: &mut Test, id: u64) -> Option<u64> {
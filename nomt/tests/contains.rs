@@ -0,0 +1,84 @@
+use nomt::{
+    hasher::{Blake3Hasher, ValueHasher},
+    trie::KeyPath,
+    KeyReadWrite, Nomt, Options, SessionParams,
+};
+use std::path::PathBuf;
+
+fn open(name: &str, reset: bool) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if reset && path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    Nomt::open(o).unwrap()
+}
+
+fn key(prefix: &[u8]) -> KeyPath {
+    let mut key = [0; 32];
+    key[..prefix.len()].copy_from_slice(prefix);
+    key
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, mut writes: Vec<(KeyPath, Option<Vec<u8>>)>) {
+    writes.sort_by_key(|(key, _)| *key);
+    let actuals = writes
+        .into_iter()
+        .map(|(key, value)| (key, KeyReadWrite::Write(value)))
+        .collect();
+    nomt.begin_session(SessionParams::default())
+        .finish(actuals)
+        .unwrap()
+        .commit(nomt)
+        .unwrap();
+}
+
+#[test]
+fn empty_database_contains_nothing() {
+    let nomt = open("contains_empty", true);
+    assert!(!nomt.contains(key(&[1])).unwrap());
+    assert_eq!(nomt.value_hash(key(&[1])).unwrap(), None);
+
+    // a single leaf is the root itself.
+    commit(&nomt, vec![(key(&[1]), Some(vec![1]))]);
+    assert!(nomt.contains(key(&[1])).unwrap());
+    assert!(!nomt.contains(key(&[2])).unwrap());
+}
+
+#[test]
+fn contains_matches_read() {
+    let nomt = open("contains_read", true);
+    let large = vec![7; 5000];
+    let keys = [
+        (key(&[0x00]), Some(vec![0])),
+        (key(&[0x00, 0x01]), Some(vec![])),
+        (key(&[0x80]), Some(large)),
+        (key(&[0xff, 0xff, 0xff]), Some(vec![3; 100])),
+        (key(&[0x40]), Some(vec![4])),
+    ];
+    commit(&nomt, keys.to_vec());
+    commit(&nomt, vec![(key(&[0x40]), None)]);
+
+    check(&nomt, &keys[..4]);
+    // reopening starts from a cold page cache.
+    drop(nomt);
+    check(&open("contains_read", false), &keys[..4]);
+}
+
+fn check(nomt: &Nomt<Blake3Hasher>, present: &[(KeyPath, Option<Vec<u8>>)]) {
+    // including an overflow value and a key below the root page.
+    for (key, value) in present {
+        let value = value.as_ref().unwrap();
+        assert!(nomt.contains(*key).unwrap());
+        assert_eq!(
+            nomt.value_hash(*key).unwrap(),
+            Some(Blake3Hasher::hash_value(value))
+        );
+    }
+    // a deleted key, a key ending in the leaf of another key and a key ending in a terminator.
+    for absent in [key(&[0x40]), key(&[0x81]), key(&[0x20])] {
+        assert!(!nomt.contains(absent).unwrap());
+        assert_eq!(nomt.value_hash(absent).unwrap(), None);
+    }
+}
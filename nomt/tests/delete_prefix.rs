@@ -0,0 +1,95 @@
+use bitvec::prelude::*;
+use nomt::{hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, Root, SessionParams};
+use std::path::PathBuf;
+
+fn open(name: &str) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(1);
+    o.bitbox_seed([0; 16]);
+    Nomt::open(o).unwrap()
+}
+
+fn key(first: u8, i: u16) -> [u8; 32] {
+    let mut key = [0; 32];
+    key[0] = first;
+    key[1..3].copy_from_slice(&i.to_be_bytes());
+    key
+}
+
+fn write(nomt: &Nomt<Blake3Hasher>, keys: impl IntoIterator<Item = [u8; 32]>) -> Root {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals: Vec<_> = keys
+        .into_iter()
+        .map(|k| (k, KeyReadWrite::Write(Some(k[..3].to_vec()))))
+        .collect();
+    actuals.sort_by_key(|(k, _)| *k);
+    let finished = session.finish(actuals).unwrap();
+    let root = finished.root();
+    finished.commit(nomt).unwrap();
+    root
+}
+
+#[test]
+fn delete_prefix_removes_subtree() {
+    let nomt = open("delete_prefix_subtree");
+    let kept: Vec<_> = (0..500).map(|i| key(0xAA, i)).collect();
+    let deleted: Vec<_> = (0..500).map(|i| key(0xAB, i)).collect();
+    write(&nomt, kept.iter().chain(&deleted).cloned());
+
+    let session = nomt.begin_session(SessionParams::default());
+    let prefix = &[0xABu8].view_bits::<Msb0>()[..8];
+    assert_eq!(session.delete_prefix(prefix).unwrap(), 500);
+    let finished = session.finish(vec![]).unwrap();
+    let root = finished.root();
+    finished.commit(&nomt).unwrap();
+
+    assert_eq!(nomt.read(key(0xAB, 7)).unwrap(), None);
+    assert_eq!(
+        nomt.read(key(0xAA, 7)).unwrap(),
+        Some(key(0xAA, 7)[..3].to_vec())
+    );
+
+    let expected = open("delete_prefix_subtree_expected");
+    assert_eq!(write(&expected, kept), root);
+}
+
+#[test]
+fn delete_prefix_sees_session_updates() {
+    let nomt = open("delete_prefix_updates");
+    write(&nomt, (0..10).map(|i| key(0xF0, i)));
+
+    let session = nomt.begin_session(SessionParams::default());
+    session.update(key(0xF1, 0), |_| Some(vec![1])).unwrap();
+    session.update(key(0xF0, 3), |_| None).unwrap();
+
+    // the 4-bit prefix 0xF covers both.
+    let prefix = &[0xF0u8].view_bits::<Msb0>()[..4];
+    assert_eq!(session.delete_prefix(prefix).unwrap(), 10);
+    session
+        .update(key(0xF0, 4), |v| {
+            assert_eq!(v, None);
+            Some(vec![4])
+        })
+        .unwrap();
+    session.finish(vec![]).unwrap().commit(&nomt).unwrap();
+
+    assert_eq!(nomt.read(key(0xF1, 0)).unwrap(), None);
+    assert_eq!(nomt.read(key(0xF0, 0)).unwrap(), None);
+    assert_eq!(nomt.read(key(0xF0, 4)).unwrap(), Some(vec![4]));
+}
+
+#[test]
+fn delete_empty_prefix_clears_everything() {
+    let nomt = open("delete_prefix_all");
+    write(&nomt, (0..100).map(|i| key(i as u8, i)));
+
+    let session = nomt.begin_session(SessionParams::default());
+    assert_eq!(session.delete_prefix(BitSlice::empty()).unwrap(), 100);
+    session.finish(vec![]).unwrap().commit(&nomt).unwrap();
+    assert!(nomt.is_empty());
+}
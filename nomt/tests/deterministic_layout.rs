@@ -0,0 +1,87 @@
+mod common;
+
+use nomt::{hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, SessionParams};
+use std::path::{Path, PathBuf};
+
+const STORE_FILES: &[&str] = &["meta", "ht", "wal", "ln", "bbn"];
+
+fn open(path: &Path, commit_concurrency: usize) -> Nomt<Blake3Hasher> {
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(commit_concurrency);
+    o.hashtable_buckets(10_000);
+    o.bitbox_seed([7; 16]);
+    o.deterministic_layout(true);
+    Nomt::open(o).unwrap()
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, writes: impl IntoIterator<Item = (u64, Option<Vec<u8>>)>) {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals = writes
+        .into_iter()
+        .map(|(id, value)| (common::account_path(id), KeyReadWrite::Write(value)))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(k, _)| *k);
+    session.finish(actuals).unwrap().commit(nomt).unwrap();
+}
+
+// Apply the same commits to a fresh database and return the path to it.
+fn build(name: &str, commit_concurrency: usize) -> PathBuf {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let nomt = open(&path, commit_concurrency);
+
+    commit(
+        &nomt,
+        (0..5000u64).map(|id| (id, Some(id.to_le_bytes().to_vec()))),
+    );
+    // large values spill into overflow pages.
+    commit(
+        &nomt,
+        (5000..5050).map(|id| (id, Some(vec![id as u8; 10_000]))),
+    );
+    commit(
+        &nomt,
+        (0..2000)
+            .step_by(3)
+            .map(|id| (id, None))
+            .chain((2000..2100).map(|id| (id, Some(vec![1; 100])))),
+    );
+    commit(&nomt, (5000..5025).map(|id| (id, None)));
+    drop(nomt);
+    path
+}
+
+#[test]
+fn same_commits_produce_identical_files() {
+    let a = build("deterministic_layout_a", 1);
+    let b = build("deterministic_layout_b", 4);
+
+    for file in STORE_FILES {
+        let a = std::fs::read(a.join(file)).unwrap();
+        let b = std::fs::read(b.join(file)).unwrap();
+        assert!(a == b, "{} differs", file);
+    }
+
+    // reopening yields the same state, and the databases stay identical.
+    let nomt = open(&a, 1);
+    assert_eq!(
+        nomt.read(common::account_path(1)).unwrap(),
+        Some(1u64.to_le_bytes().to_vec())
+    );
+    assert_eq!(nomt.read(common::account_path(3)).unwrap(), None);
+    commit(&nomt, (6000..6100).map(|id| (id, Some(vec![2; 50]))));
+    drop(nomt);
+
+    let nomt = open(&b, 4);
+    commit(&nomt, (6000..6100).map(|id| (id, Some(vec![2; 50]))));
+    drop(nomt);
+
+    for file in STORE_FILES {
+        let a = std::fs::read(a.join(file)).unwrap();
+        let b = std::fs::read(b.join(file)).unwrap();
+        assert!(a == b, "{} differs after reopening", file);
+    }
+}
@@ -0,0 +1,135 @@
+use nomt::{
+    differential::{BTreeReference, Batch, Differential, DivergenceKind, Reference, Repro},
+    hasher::Blake3Hasher,
+    trie::KeyPath,
+    Nomt, Options, Root, Value,
+};
+use rand::{Rng, SeedableRng};
+use std::path::PathBuf;
+
+// Opens a fresh database under `test/<name>_<n>` on every call.
+fn opener(name: &'static str) -> impl FnMut() -> anyhow::Result<Nomt<Blake3Hasher>> {
+    let mut n = 0;
+    move || {
+        let path = PathBuf::from("test").join(format!("{}_{}", name, n));
+        n += 1;
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+        let mut o = Options::new();
+        o.path(path);
+        o.commit_concurrency(1);
+        Nomt::open(o)
+    }
+}
+
+fn random_batch(rng: &mut impl Rng, keys: u8, len: usize) -> Batch {
+    (0..len)
+        .map(|_| {
+            let key = [rng.gen_range(0..keys); 32];
+            let value = match rng.gen_bool(0.2) {
+                true => None,
+                false => Some(vec![rng.gen(); rng.gen_range(0..64)]),
+            };
+            (key, value)
+        })
+        .collect()
+}
+
+#[test]
+fn agrees_with_reference() {
+    let mut differential =
+        Differential::<Blake3Hasher>::new(opener("differential_agrees")).unwrap();
+    let mut rng = rand_pcg::Lcg64Xsh32::from_seed([1; 16]);
+    for _ in 0..20 {
+        let batch = random_batch(&mut rng, 64, 32);
+        let root = differential.commit(batch).unwrap();
+        assert_eq!(root, differential.nomt().root());
+    }
+    for key in 0..64 {
+        differential.read([key; 32]).unwrap();
+    }
+}
+
+const IGNORED_KEY: KeyPath = [7; 32];
+
+// A reference which loses the writes to one key.
+#[derive(Default)]
+struct Faulty(BTreeReference<Blake3Hasher>);
+
+impl Reference for Faulty {
+    fn apply(&mut self, batch: &[(KeyPath, Option<Value>)]) {
+        let batch: Batch = batch
+            .iter()
+            .filter(|(key, _)| *key != IGNORED_KEY)
+            .cloned()
+            .collect();
+        self.0.apply(&batch);
+    }
+    fn read(&self, key: &KeyPath) -> Option<Value> {
+        self.0.read(key)
+    }
+    fn root(&self) -> Root {
+        self.0.root()
+    }
+}
+
+#[test]
+fn reports_minimized_divergence() {
+    let repro_dir = PathBuf::from("test").join("differential_repros");
+    if repro_dir.exists() {
+        std::fs::remove_dir_all(&repro_dir).unwrap();
+    }
+    std::fs::create_dir_all(&repro_dir).unwrap();
+    let mut differential =
+        Differential::<Blake3Hasher, Faulty>::new(opener("differential_diverges")).unwrap();
+    differential.write_repros_to(&repro_dir);
+    let mut rng = rand_pcg::Lcg64Xsh32::from_seed([2; 16]);
+    for _ in 0..3 {
+        let batch = random_batch(&mut rng, 6, 16);
+        differential.commit(batch).unwrap();
+    }
+
+    let mut batch = random_batch(&mut rng, 6, 16);
+    batch.push((IGNORED_KEY, Some(vec![1, 2, 3])));
+    let divergence = differential.commit(batch).unwrap_err();
+
+    assert_eq!(divergence.batch, 3);
+    assert!(matches!(divergence.kind, DivergenceKind::Root { .. }));
+    // the divergence only needs the write to the ignored key.
+    assert_eq!(
+        divergence.repro.batches,
+        vec![vec![(IGNORED_KEY, Some(vec![1, 2, 3]))]]
+    );
+    assert!(divergence
+        .to_string()
+        .starts_with("divergence at batch 3: root "));
+
+    // the repro file diverges again when replayed.
+    let repro = Repro::read(divergence.repro_file.unwrap()).unwrap();
+    assert_eq!(repro, divergence.repro);
+    let mut differential =
+        Differential::<Blake3Hasher, Faulty>::new(opener("differential_replay")).unwrap();
+    assert!(differential.commit(repro.batches[0].clone()).is_err());
+}
+
+#[test]
+fn repro_text_round_trip() {
+    let repro = Repro {
+        batches: vec![
+            vec![([1; 32], Some(vec![0xab, 0x01])), ([2; 32], None)],
+            vec![],
+            vec![([1; 32], Some(vec![]))],
+        ],
+    };
+    let text = repro.to_text();
+    assert!(text.starts_with("nomt-repro 1\nbatch\nput 0101"));
+    assert!(text.contains(&format!("\ndelete {}\nbatch\nbatch\n", "02".repeat(32))));
+    assert_eq!(Repro::parse(&text).unwrap(), repro);
+    assert_eq!(repro.writes(), 3);
+
+    assert!(Repro::parse("nomt-repro 2\n").is_err());
+    // a write must belong to a batch.
+    assert!(Repro::parse(&format!("nomt-repro 1\ndelete {}\n", "02".repeat(32))).is_err());
+    assert!(Repro::parse("nomt-repro 1\nbatch\nput 01 02\n").is_err());
+}
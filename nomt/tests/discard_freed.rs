@@ -0,0 +1,75 @@
+mod common;
+
+use nomt::{hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, SessionParams};
+use std::{os::unix::fs::MetadataExt, path::PathBuf};
+
+fn open(name: &str, discard: bool) -> (Nomt<Blake3Hasher>, PathBuf) {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(&path);
+    o.hashtable_buckets(10_000);
+    o.preallocate_values(false);
+    o.discard_freed_pages(discard);
+    (Nomt::open(o).unwrap(), path)
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, ids: std::ops::Range<u64>, value: Option<Vec<u8>>) {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals = ids
+        .map(|id| (common::account_path(id), KeyReadWrite::Write(value.clone())))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(k, _)| *k);
+    session.finish(actuals).unwrap().commit(nomt).unwrap();
+}
+
+// The bytes allocated to the leaf file after filling the store and then deleting most of it.
+fn allocated_after_deletion(name: &str, discard: bool) -> (u64, u64) {
+    let (nomt, path) = open(name, discard);
+    let ln_allocated = || std::fs::metadata(path.join("ln")).unwrap().blocks() * 512;
+
+    commit(&nomt, 0..4000, Some(vec![1; 1000]));
+    let filled = ln_allocated();
+
+    commit(&nomt, 0..3900, None);
+    // the pages freed by a commit are discarded by the one after it.
+    commit(&nomt, 10_000..10_001, Some(vec![2; 10]));
+    let emptied = ln_allocated();
+
+    for id in 3900..4000 {
+        assert_eq!(
+            nomt.read(common::account_path(id)).unwrap(),
+            Some(vec![1; 1000])
+        );
+    }
+    (filled, emptied)
+}
+
+#[test]
+fn freed_pages_are_discarded() {
+    let (filled, emptied) = allocated_after_deletion("discard_freed", true);
+    // skip where the filesystem can't punch holes.
+    if emptied < filled {
+        assert!(emptied < filled / 4, "{} -> {}", filled, emptied);
+    }
+
+    // discarded pages are reused.
+    let (nomt, _) = open("discard_freed_reuse", true);
+    for round in 0..4u8 {
+        commit(&nomt, 0..2000, Some(vec![round; 1000]));
+        commit(&nomt, 0..2000, None);
+    }
+    commit(&nomt, 0..2000, Some(vec![9; 1000]));
+    assert_eq!(
+        nomt.read(common::account_path(1234)).unwrap(),
+        Some(vec![9; 1000])
+    );
+}
+
+#[test]
+fn discard_can_be_disabled() {
+    let (filled, emptied) = allocated_after_deletion("discard_freed_disabled", false);
+    assert!(emptied >= filled);
+}
@@ -0,0 +1,56 @@
+use nomt::{hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, SessionParams};
+use std::path::PathBuf;
+
+fn open(name: &str) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    Nomt::open(o).unwrap()
+}
+
+#[test]
+fn dry_run_matches_commit_without_persisting() {
+    let nomt = open("dry_run");
+    nomt.begin_session(SessionParams::default())
+        .finish(vec![([1; 32], KeyReadWrite::Write(Some(vec![1; 10])))])
+        .unwrap()
+        .commit(&nomt)
+        .unwrap();
+    let root = nomt.root();
+    let sequence = nomt.current_sequence();
+
+    let actuals = vec![
+        ([1; 32], KeyReadWrite::Write(None)),
+        ([2; 32], KeyReadWrite::Write(Some(vec![2; 100]))),
+        ([3; 32], KeyReadWrite::Write(Some(vec![3; 5000]))),
+    ];
+    let dry_run = nomt
+        .begin_session(SessionParams::default())
+        .dry_run(actuals.clone())
+        .unwrap();
+    assert_eq!(nomt.root(), root);
+    assert_eq!(nomt.current_sequence(), sequence);
+    assert_eq!(nomt.read([1; 32]).unwrap(), Some(vec![1; 10]));
+    assert_eq!(nomt.read([2; 32]).unwrap(), None);
+
+    assert_eq!(dry_run.value_writes, 2);
+    assert_eq!(dry_run.value_deletes, 1);
+    assert_eq!(dry_run.value_bytes, 5100);
+    assert!(dry_run.pages > 0);
+    assert_eq!(
+        dry_run.estimated_bytes(),
+        dry_run.pages as u64 * 4096 + 5100
+    );
+
+    let finished = nomt
+        .begin_session(SessionParams::default())
+        .finish(actuals)
+        .unwrap();
+    assert_eq!(finished.root(), dry_run.root);
+    assert_eq!(finished.page_changes().len(), dry_run.pages);
+    finished.commit(&nomt).unwrap();
+    assert_eq!(nomt.root(), dry_run.root);
+}
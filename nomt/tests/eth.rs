@@ -0,0 +1,54 @@
+#![cfg(feature = "eth")]
+
+mod common;
+
+use common::Test;
+use nomt::{eth, hasher::Blake3Hasher, hasher::ValueHasher};
+
+#[test]
+fn eth_get_proof_verifies() {
+    let mut t = Test::new("eth_get_proof_verifies");
+    let address = [1; 20];
+    t.write(eth::account_key_path(&address), Some(vec![1, 2, 3]));
+    t.write(
+        eth::storage_key_path(&address, &[0; 32]),
+        Some(vec![4, 5, 6]),
+    );
+    for id in 0..100 {
+        t.write_id(id, Some(id.to_le_bytes().to_vec()));
+    }
+    t.commit();
+
+    let proof = t.eth_get_proof(address, &[[0; 32], [1; 32]]);
+    let root = proof.root.into_inner();
+
+    assert_eq!(proof.account_value, Some(vec![1, 2, 3]));
+    assert_eq!(
+        eth::verify_proof_nodes::<Blake3Hasher>(
+            root,
+            &eth::account_key_path(&address),
+            &proof.account_proof
+        ),
+        Ok(Some(Blake3Hasher::hash_value(&[1, 2, 3])))
+    );
+
+    assert_eq!(proof.storage_proof[0].value, Some(vec![4, 5, 6]));
+    assert_eq!(
+        eth::verify_proof_nodes::<Blake3Hasher>(
+            root,
+            &eth::storage_key_path(&address, &[0; 32]),
+            &proof.storage_proof[0].proof
+        ),
+        Ok(Some(Blake3Hasher::hash_value(&[4, 5, 6])))
+    );
+
+    assert_eq!(proof.storage_proof[1].value, None);
+    assert_eq!(
+        eth::verify_proof_nodes::<Blake3Hasher>(
+            root,
+            &eth::storage_key_path(&address, &[1; 32]),
+            &proof.storage_proof[1].proof
+        ),
+        Ok(None)
+    );
+}
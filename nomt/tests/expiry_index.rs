@@ -0,0 +1,100 @@
+use nomt::{hasher::Blake3Hasher, trie::KeyPath, KeyReadWrite, Nomt, Options, SessionParams};
+use std::path::PathBuf;
+
+fn open(name: &str, reset: bool, expiry_index: bool) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if reset && path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.rollback(true);
+    o.expiry_index(expiry_index);
+    Nomt::open(o).unwrap()
+}
+
+fn key(id: u8) -> KeyPath {
+    let mut key = [0; 32];
+    key[0] = id;
+    key
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, changes: Vec<(u8, Option<Vec<u8>>)>) -> u64 {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals = changes
+        .into_iter()
+        .map(|(id, value)| (key(id), KeyReadWrite::Write(value)))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(k, _)| *k);
+    session.finish(actuals).unwrap().commit(nomt).unwrap()
+}
+
+#[test]
+fn keys_are_enumerated_least_recently_written_first() {
+    let nomt = open("expiry_index_order", true, true);
+    let first = commit(
+        &nomt,
+        vec![(1, Some(vec![1])), (2, Some(vec![2])), (3, Some(vec![3]))],
+    );
+    let second = commit(&nomt, vec![(2, Some(vec![20])), (4, Some(vec![4]))]);
+    let third = commit(&nomt, vec![(3, None), (5, Some(vec![5]))]);
+
+    assert_eq!(nomt.last_written(key(2)).unwrap(), Some(second));
+    assert_eq!(nomt.last_written(key(3)).unwrap(), None);
+    assert_eq!(nomt.expired_keys(first, 10).unwrap(), vec![]);
+    assert_eq!(
+        nomt.expired_keys(third, 10).unwrap(),
+        vec![(key(1), first), (key(2), second), (key(4), second)]
+    );
+    assert_eq!(
+        nomt.expired_keys(third + 1, 2).unwrap(),
+        vec![(key(1), first), (key(2), second)]
+    );
+
+    // a rollback is a commit of its own.
+    nomt.rollback(1).unwrap();
+    let rollback = nomt.current_sequence();
+    assert_eq!(nomt.last_written(key(3)).unwrap(), Some(rollback));
+    assert_eq!(nomt.last_written(key(5)).unwrap(), None);
+    assert_eq!(
+        nomt.expired_keys(rollback, 10).unwrap(),
+        vec![(key(1), first), (key(2), second), (key(4), second)]
+    );
+}
+
+#[test]
+fn index_survives_reopening() {
+    let nomt = open("expiry_index_reopen", true, true);
+    let first = commit(&nomt, vec![(1, Some(vec![1])), (2, Some(vec![2]))]);
+    // enough rewrites of the same key to compact the index.
+    let mut last = first;
+    for i in 0..20 {
+        last = commit(&nomt, vec![(2, Some(vec![i]))]);
+    }
+    let expected = nomt.expired_keys(u64::MAX, 10).unwrap();
+    assert_eq!(expected, vec![(key(1), first), (key(2), last)]);
+    drop(nomt);
+
+    let nomt = open("expiry_index_reopen", false, true);
+    assert_eq!(nomt.expired_keys(u64::MAX, 10).unwrap(), expected);
+    let next = commit(&nomt, vec![(1, Some(vec![10]))]);
+    assert_eq!(
+        nomt.expired_keys(u64::MAX, 10).unwrap(),
+        vec![(key(2), last), (key(1), next)]
+    );
+}
+
+#[test]
+fn enabling_the_index_records_existing_keys() {
+    let nomt = open("expiry_index_enable", true, false);
+    commit(&nomt, vec![(1, Some(vec![1])), (2, Some(vec![2]))]);
+    let last = commit(&nomt, vec![(3, Some(vec![3]))]);
+    assert!(nomt.expired_keys(u64::MAX, 10).is_err());
+    drop(nomt);
+
+    let nomt = open("expiry_index_enable", false, true);
+    assert_eq!(
+        nomt.expired_keys(u64::MAX, 10).unwrap(),
+        vec![(key(1), last), (key(2), last), (key(3), last)]
+    );
+}
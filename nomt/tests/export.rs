@@ -0,0 +1,121 @@
+mod common;
+
+use common::Test;
+use nomt::{
+    dump::{Compression, Reader},
+    manifest::{self, Manifest},
+};
+use std::collections::BTreeMap;
+
+fn export_and_check(name: &str, compression: Compression) {
+    let mut t = Test::new(name);
+
+    let mut expected = BTreeMap::new();
+    for id in 0..1000u64 {
+        let value = id.to_le_bytes().to_vec();
+        t.write_id(id, Some(value.clone()));
+        expected.insert(common::account_path(id), value);
+    }
+    let large = vec![7; 4096 * 20];
+    t.write_id(1000, Some(large.clone()));
+    expected.insert(common::account_path(1000), large);
+    let _ = t.commit();
+
+    // deleted values must not show up in the dump.
+    t.write_id(5, None);
+    expected.remove(&common::account_path(5));
+    let (root, _) = t.commit();
+
+    let path = format!("test/{}.dump", name);
+    assert_eq!(t.export(&path, compression), expected.len() as u64);
+
+    let reader = Reader::open(&path).unwrap();
+    assert_eq!(reader.header().root, root);
+    assert_eq!(reader.header().compression, compression);
+
+    let entries = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(entries, expected.into_iter().collect::<Vec<_>>());
+
+    // the manifest verifies the dump chunk by chunk.
+    let dump = std::fs::read(&path).unwrap();
+    let manifest = Manifest::read(manifest::manifest_path(&path)).unwrap();
+    assert_eq!(manifest.root(), root);
+    assert_eq!(manifest.file_len(), dump.len() as u64);
+    let chunk_size = manifest.chunk_size() as usize;
+    for (index, chunk) in dump.chunks(chunk_size).enumerate() {
+        assert!(manifest.verify_chunk(index, chunk));
+    }
+
+    let manifest = Manifest::build(&path, root, 4096).unwrap();
+    assert!(manifest.chunk_count() > 1);
+    for (index, chunk) in dump.chunks(4096).enumerate() {
+        assert!(manifest.verify_chunk(index, chunk));
+    }
+    let mut corrupt = dump[4096..8192].to_vec();
+    corrupt[0] ^= 1;
+    assert!(!manifest.verify_chunk(1, &corrupt));
+}
+
+#[test]
+fn export_uncompressed() {
+    export_and_check("export_uncompressed", Compression::None);
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn export_zstd() {
+    export_and_check("export_zstd", Compression::Zstd);
+}
+
+#[test]
+fn export_empty() {
+    let t = Test::new("export_empty");
+    let path = "test/export_empty.dump";
+    assert_eq!(t.export(path, Compression::None), 0);
+
+    let mut reader = Reader::open(path).unwrap();
+    assert!(reader.header().root.is_empty());
+    assert!(reader.next_entry().unwrap().is_none());
+}
+
+#[test]
+fn import_roundtrip() {
+    let mut source = Test::new("import_roundtrip_source");
+    for id in 0..1000u64 {
+        source.write_id(id, Some(id.to_le_bytes().to_vec()));
+    }
+    source.write_id(1000, Some(vec![7; 4096 * 20]));
+    let (root, _) = source.commit();
+
+    let path = "test/import_roundtrip.dump";
+    source.export(path, Compression::None);
+
+    let mut target = Test::new("import_roundtrip_target");
+    assert_eq!(target.import(path).unwrap(), 1001);
+    assert_eq!(target.root(), root);
+    assert_eq!(target.read_id(1), Some(1u64.to_le_bytes().to_vec()));
+    assert_eq!(target.read_id(1000), Some(vec![7; 4096 * 20]));
+
+    // importing into a non-empty database is refused.
+    assert!(target.import(path).is_err());
+}
+
+#[test]
+fn import_rejects_root_mismatch() {
+    let mut source = Test::new("import_rejects_root_mismatch_source");
+    source.write_id(1, Some(vec![1]));
+    source.write_id(2, Some(vec![2]));
+    let _ = source.commit();
+
+    let path = "test/import_rejects_root_mismatch.dump";
+    source.export(path, Compression::None);
+
+    // corrupt the value of the last entry.
+    let mut dump = std::fs::read(path).unwrap();
+    *dump.last_mut().unwrap() ^= 0xff;
+    std::fs::write(path, dump).unwrap();
+
+    let mut target = Test::new("import_rejects_root_mismatch_target");
+    assert!(target.import(path).is_err());
+    assert!(target.root().is_empty());
+}
@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use nomt::{hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, SessionParams};
+
+fn open(
+    path: &str,
+    should_clean_up: bool,
+    fencing_token: Option<u64>,
+) -> anyhow::Result<Nomt<Blake3Hasher>> {
+    let path = PathBuf::from("test").join(path);
+    if should_clean_up && path.exists() {
+        std::fs::remove_dir_all(&path)?;
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.bitbox_seed([0; 16]);
+    if let Some(fencing_token) = fencing_token {
+        o.fencing_token(fencing_token);
+    }
+    Nomt::open(o)
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, k: u8) -> anyhow::Result<()> {
+    let session = nomt.begin_session(SessionParams::default());
+    let actuals = vec![([k; 32], KeyReadWrite::Write(Some(vec![k])))];
+    session.finish(actuals)?.commit(nomt)?;
+    Ok(())
+}
+
+#[test]
+fn tokens_increase_with_every_writer() {
+    let nomt = open("fencing_tokens", true, None).unwrap();
+    assert_eq!(nomt.fencing_token(), Some(0));
+    drop(nomt);
+
+    let nomt = open("fencing_tokens", false, None).unwrap();
+    assert_eq!(nomt.fencing_token(), Some(1));
+    drop(nomt);
+
+    let nomt = open("fencing_tokens", false, Some(7)).unwrap();
+    assert_eq!(nomt.fencing_token(), Some(7));
+    commit(&nomt, 1).unwrap();
+    drop(nomt);
+
+    // a stale token is refused.
+    assert!(open("fencing_tokens", false, Some(6)).is_err());
+    let nomt = open("fencing_tokens", false, Some(7)).unwrap();
+    assert_eq!(nomt.read([1; 32]).unwrap(), Some(vec![1]));
+}
+
+#[test]
+fn fenced_writer_cannot_commit() {
+    let nomt = open("fenced_writer", true, Some(3)).unwrap();
+    commit(&nomt, 1).unwrap();
+    let root = nomt.root();
+
+    // another host takes over the database, while this writer still has it open.
+    std::fs::write("test/fenced_writer/fence", 4u64.to_le_bytes()).unwrap();
+    assert!(commit(&nomt, 2).is_err());
+    drop(nomt);
+
+    let nomt = open("fenced_writer", false, Some(4)).unwrap();
+    assert_eq!(nomt.root(), root);
+    assert_eq!(nomt.read([2; 32]).unwrap(), None);
+    commit(&nomt, 2).unwrap();
+}
@@ -0,0 +1,107 @@
+use nomt::{
+    hasher::Blake3Hasher, trie::KeyPath, ForkTree, KeyReadWrite, Nomt, Options, Overlay,
+    SessionParams,
+};
+use std::path::PathBuf;
+
+fn open(name: &str) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    Nomt::open(o).unwrap()
+}
+
+// Build a block writing `value` under `key` on top of `parent`.
+fn build(
+    nomt: &Nomt<Blake3Hasher>,
+    tree: &ForkTree<&'static str>,
+    parent: Option<&'static str>,
+    key: KeyPath,
+    value: u8,
+) -> Overlay {
+    let ancestors = parent
+        .map(|p| tree.ancestors(&p).unwrap())
+        .unwrap_or_default();
+    let params = SessionParams::default().overlay(ancestors).unwrap();
+    let session = nomt.begin_session(params);
+    session
+        .finish(vec![(key, KeyReadWrite::Write(Some(vec![value])))])
+        .unwrap()
+        .into_overlay()
+}
+
+fn read(nomt: &Nomt<Blake3Hasher>, tree: &ForkTree<&'static str>, head: &str, key: KeyPath) -> u8 {
+    let params = SessionParams::default()
+        .overlay(tree.ancestors(&head).unwrap())
+        .unwrap();
+    nomt.begin_session(params).read(key).unwrap().unwrap()[0]
+}
+
+#[test]
+fn canonical_chain_is_committed_and_forks_discarded() {
+    let nomt = open("fork_tree_canonical");
+    let mut tree = ForkTree::new();
+
+    //   a - b1 - c
+    //     \ b2
+    // d
+    let a = build(&nomt, &tree, None, [1; 32], 1);
+    tree.insert("a", None, a).unwrap();
+    let b1 = build(&nomt, &tree, Some("a"), [2; 32], 2);
+    tree.insert("b1", Some("a"), b1).unwrap();
+    let b2 = build(&nomt, &tree, Some("a"), [2; 32], 3);
+    tree.insert("b2", Some("a"), b2).unwrap();
+    let c = build(&nomt, &tree, Some("b1"), [3; 32], 4);
+    let c_root = c.root();
+    tree.insert("c", Some("b1"), c).unwrap();
+    let d = build(&nomt, &tree, None, [1; 32], 5);
+    tree.insert("d", None, d).unwrap();
+
+    let mut tips = tree.tips().copied().collect::<Vec<_>>();
+    tips.sort();
+    assert_eq!(tips, vec!["b2", "c", "d"]);
+    assert_eq!(read(&nomt, &tree, "c", [2; 32]), 2);
+    assert_eq!(read(&nomt, &tree, "b2", [2; 32]), 3);
+
+    let mut discarded = tree.set_canonical(&nomt, &"b1").unwrap();
+    discarded.sort();
+    assert_eq!(discarded, vec!["b2", "d"]);
+    assert_eq!(tree.len(), 1);
+    assert_eq!(tree.parent(&"c"), None);
+    assert_eq!(nomt.read([2; 32]).unwrap(), Some(vec![2]));
+
+    // the retained descendant can still be built on and committed.
+    assert_eq!(read(&nomt, &tree, "c", [1; 32]), 1);
+    let e = build(&nomt, &tree, Some("c"), [4; 32], 6);
+    tree.insert("e", Some("c"), e).unwrap();
+    assert!(tree.set_canonical(&nomt, &"e").unwrap().is_empty());
+    assert!(tree.is_empty());
+    assert_ne!(nomt.root(), c_root);
+    assert_eq!(nomt.read([3; 32]).unwrap(), Some(vec![4]));
+    assert_eq!(nomt.read([4; 32]).unwrap(), Some(vec![6]));
+}
+
+#[test]
+fn invalid_heads_are_rejected() {
+    let nomt = open("fork_tree_invalid");
+    let mut tree = ForkTree::new();
+
+    let a = build(&nomt, &tree, None, [1; 32], 1);
+    tree.insert("a", None, a).unwrap();
+    let b = build(&nomt, &tree, None, [2; 32], 2);
+    assert!(tree.insert("a", None, b).is_err());
+    let b = build(&nomt, &tree, None, [2; 32], 2);
+    assert!(tree.insert("b", Some("missing"), b).is_err());
+    // built on the committed state, not on `a`.
+    let b = build(&nomt, &tree, None, [2; 32], 2);
+    assert!(tree.insert("b", Some("a"), b).is_err());
+    assert!(tree.set_canonical(&nomt, &"missing").is_err());
+
+    let b = build(&nomt, &tree, Some("a"), [2; 32], 2);
+    tree.insert("b", Some("a"), b).unwrap();
+    assert_eq!(tree.remove(&"a"), vec!["a", "b"]);
+    assert!(tree.is_empty());
+}
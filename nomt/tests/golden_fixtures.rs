@@ -0,0 +1,85 @@
+//! Checks the stored format against the fixtures in `tests/fixtures`.
+//!
+//! A change to the format fails this test with the differences from the fixtures. If the change
+//! is intended, regenerate the fixtures with `NOMT_BLESS_FIXTURES=1 cargo test --test
+//! golden_fixtures` and check in the updated files along with it.
+
+use nomt::{fixture::Fixture, hasher::Blake3Hasher, trie::KeyPath, Value};
+use std::path::{Path, PathBuf};
+
+fn key(prefix: &[u8]) -> KeyPath {
+    let mut key = [0; 32];
+    key[..prefix.len()].copy_from_slice(prefix);
+    key
+}
+
+// The entries of every fixture, by name.
+fn cases() -> Vec<(&'static str, Vec<(KeyPath, Value)>)> {
+    vec![
+        ("empty", vec![]),
+        ("single_leaf", vec![(key(&[0x42]), vec![1, 2, 3])]),
+        (
+            "root_page",
+            vec![
+                (key(&[0x00]), vec![0]),
+                (key(&[0x40]), vec![1; 100]),
+                (key(&[0x80]), vec![]),
+            ],
+        ),
+        (
+            // keys sharing 7 bits continue into a child page of the root page.
+            "child_page",
+            vec![
+                (key(&[0x00]), vec![0]),
+                (key(&[0x01]), vec![1]),
+                (key(&[0xff, 0xff]), vec![2]),
+                (key(&[0xff, 0xfe]), vec![3]),
+            ],
+        ),
+        (
+            "overflow_value",
+            vec![(key(&[0x10]), vec![7; 5000]), (key(&[0x20]), vec![8])],
+        ),
+    ]
+}
+
+#[test]
+fn stored_format_matches_fixtures() {
+    let bless = std::env::var("NOMT_BLESS_FIXTURES").is_ok_and(|v| v == "1");
+    let fixtures_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+
+    let mut failures = Vec::new();
+    for (name, entries) in cases() {
+        let db_dir = PathBuf::from("test").join(format!("golden_fixture_{}", name));
+        if db_dir.exists() {
+            std::fs::remove_dir_all(&db_dir).unwrap();
+        }
+        let fixture = Fixture::generate::<Blake3Hasher>(&db_dir, entries).unwrap();
+        let path = fixtures_dir.join(format!("{}.fixture", name));
+
+        if bless {
+            std::fs::create_dir_all(&fixtures_dir).unwrap();
+            std::fs::write(&path, fixture.to_text()).unwrap();
+            continue;
+        }
+
+        let text = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("missing fixture {}: {}", path.display(), e));
+        let expected = Fixture::parse(&text).unwrap();
+        // the text must be canonical too, so that regenerating the fixtures causes no diff.
+        if fixture.to_text() != text || fixture != expected {
+            failures.push(format!(
+                "{}:\n  {}",
+                name,
+                fixture.diff(&expected).join("\n  ")
+            ));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "the stored format differs from the fixtures. If this is intended, run with \
+         NOMT_BLESS_FIXTURES=1 and check in the fixtures.\n{}",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,38 @@
+use nomt::{hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, SessionParams};
+use std::path::PathBuf;
+
+fn open(name: &str) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    Nomt::open(o).unwrap()
+}
+
+#[test]
+fn health_tracks_syncs() {
+    let nomt = open("health");
+    let health = nomt.health();
+    assert!(health.is_live());
+    assert!(health.is_ready(0));
+    assert!(!health.is_ready(u64::MAX));
+    assert!(health.disk_available.is_some_and(|available| available > 0));
+    assert_eq!(health.io_in_flight, 0);
+    assert!(health.io_in_flight_limit > 0);
+    assert_eq!(health.io_queued, 0);
+    assert_eq!(health.last_sync, None);
+    assert!(!health.poisoned);
+    assert!(!health.read_only);
+
+    nomt.begin_session(SessionParams::default())
+        .finish(vec![([1; 32], KeyReadWrite::Write(Some(vec![1])))])
+        .unwrap()
+        .commit(&nomt)
+        .unwrap();
+    let health = nomt.health();
+    assert!(health.last_sync.is_some());
+    assert!(health.since_last_sync().unwrap() < std::time::Duration::from_secs(60));
+    assert!(health.is_ready(0));
+}
@@ -0,0 +1,171 @@
+mod common;
+
+use bitvec::prelude::*;
+use common::Test;
+use nomt::{Cursor, KeyValueIter};
+
+fn collect(iter: &mut KeyValueIter<'_>, limit: usize) -> Vec<([u8; 32], Vec<u8>)> {
+    iter.take(limit).map(|item| item.unwrap()).collect()
+}
+
+fn setup(name: &str, cleanup_dir: bool) -> Test {
+    let mut t = Test::new_with_params(name, 1, 64_000, None, cleanup_dir);
+    if cleanup_dir {
+        for id in 0..1000u64 {
+            t.write_id(id, Some(id.to_le_bytes().to_vec()));
+        }
+        // an overflow value.
+        t.write_id(1000, Some(vec![7; 4096 * 3]));
+        let _ = t.commit();
+    }
+    t
+}
+
+#[test]
+fn paginated_iteration_matches_full_iteration() {
+    let mut expected: Vec<_> = (0..1000u64)
+        .map(|id| (common::account_path(id), id.to_le_bytes().to_vec()))
+        .chain(std::iter::once((
+            common::account_path(1000),
+            vec![7; 4096 * 3],
+        )))
+        .collect();
+    expected.sort();
+
+    let token = {
+        let mut t = setup("paginated_iteration", true);
+        let read_session = t.begin_read_session();
+        let mut iter = read_session.iter_range([0; 32], None);
+        assert_eq!(collect(&mut iter, usize::MAX), expected);
+        assert!(iter.cursor().is_complete());
+
+        // page through the first half with encoded cursors.
+        let mut paged = Vec::new();
+        let mut token = read_session.iter_range([0; 32], None).cursor().encode();
+        for _ in 0..5 {
+            let cursor = Cursor::decode(&token).unwrap();
+            let mut iter = read_session.resume(&cursor).unwrap();
+            paged.extend(collect(&mut iter, 100));
+            token = iter.cursor().encode();
+        }
+        assert_eq!(paged, expected[..500]);
+        token
+    };
+
+    // resume after reopening the database at the same root.
+    let mut t = setup("paginated_iteration", false);
+    let read_session = t.begin_read_session();
+    let cursor = Cursor::decode(&token).unwrap();
+    assert_eq!(
+        cursor.next_key(),
+        Some(expected[499].0).map(|k| { nomt::key_path::successor(&k).unwrap() })
+    );
+    let mut iter = read_session.resume(&cursor).unwrap();
+    assert_eq!(collect(&mut iter, usize::MAX), expected[500..]);
+    drop(iter);
+    drop(read_session);
+
+    // but not once the root changed.
+    t.start_overlay_session([]);
+    t.write_id(2000, Some(vec![1]));
+    let _ = t.commit();
+    let read_session = t.begin_read_session();
+    assert!(read_session.resume(&cursor).is_err());
+}
+
+#[test]
+fn iteration_within_bounds() {
+    let mut t = setup("iteration_within_bounds", true);
+    let mut keys: Vec<_> = (0..=1000u64).map(common::account_path).collect();
+    keys.sort();
+    let read_session = t.begin_read_session();
+
+    let mut iter = read_session.iter_range(keys[10], Some(keys[20]));
+    let found: Vec<_> = collect(&mut iter, usize::MAX)
+        .into_iter()
+        .map(|(k, _)| k)
+        .collect();
+    assert_eq!(found, keys[10..20]);
+
+    // an empty range.
+    let mut iter = read_session.iter_range(keys[20], Some(keys[20]));
+    assert!(iter.cursor().is_complete());
+    assert!(iter.next().is_none());
+
+    let prefix = &keys[0].view_bits::<Msb0>()[..4];
+    let mut iter = read_session.iter_prefix(prefix).unwrap();
+    let found: Vec<_> = collect(&mut iter, usize::MAX)
+        .into_iter()
+        .map(|(k, _)| k)
+        .collect();
+    let expected: Vec<_> = keys
+        .iter()
+        .copied()
+        .filter(|k| k.view_bits::<Msb0>()[..4] == *prefix)
+        .collect();
+    assert!(!expected.is_empty());
+    assert_eq!(found, expected);
+
+    assert!(read_session.iter_prefix(bits![u8, Msb0; 0; 257]).is_err());
+}
+
+#[test]
+fn read_ahead_iteration_matches_plain_iteration() {
+    let expected = {
+        let mut t = Test::new_with_params("read_ahead_iteration", 1, 64_000, None, true);
+        for id in 0..5000u64 {
+            t.write_id(id, Some(vec![id as u8; 100]));
+        }
+        let _ = t.commit();
+        let read_session = t.begin_read_session();
+        let mut iter = read_session.iter_range([0; 32], None);
+        collect(&mut iter, usize::MAX)
+    };
+    assert_eq!(expected.len(), 5000);
+
+    // reopen, so that the leaves are loaded from disk rather than the cache.
+    let mut t = Test::new_with_params("read_ahead_iteration", 1, 64_000, None, false);
+    let read_session = t.begin_read_session();
+    for read_ahead in [0, 1, 4, 64] {
+        let mut iter = read_session
+            .iter_range([0; 32], None)
+            .read_ahead(read_ahead);
+        let mut found = collect(&mut iter, 1234);
+        // stopping early leaves loads in flight, which are dropped with the iterator.
+        let cursor = iter.cursor();
+        drop(iter);
+
+        let mut iter = read_session.resume(&cursor).unwrap().read_ahead(read_ahead);
+        found.extend(collect(&mut iter, usize::MAX));
+        assert!(iter.cursor().is_complete());
+        assert_eq!(found, expected);
+    }
+}
+
+#[test]
+fn sharded_iteration_matches_full_iteration() {
+    let mut t = setup("sharded_iteration", true);
+    let read_session = t.begin_read_session();
+    let mut iter = read_session.iter_range([0; 32], None);
+    let expected = collect(&mut iter, usize::MAX);
+    drop(iter);
+
+    for num_shards in [1, 3, 16] {
+        let shards = read_session.iter_shards(num_shards).unwrap();
+        assert_eq!(shards.len(), num_shards);
+        let found: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = shards
+                .into_iter()
+                .map(|mut iter| scope.spawn(move || collect(&mut iter, usize::MAX)))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        assert_eq!(found, expected);
+    }
+
+    assert!(read_session.iter_shards(0).is_err());
+    assert!(read_session.iter_shards(nomt::MAX_ITER_SHARDS + 1).is_err());
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use nomt::{hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, SessionParams};
+use std::path::PathBuf;
+
+fn open(path: &PathBuf, keyed_key_paths: bool) -> Nomt<Blake3Hasher> {
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(1);
+    o.keyed_key_paths(keyed_key_paths);
+    Nomt::open(o).unwrap()
+}
+
+fn fresh_path(name: &str) -> PathBuf {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    path
+}
+
+#[test]
+fn keyed_key_paths_are_per_database() {
+    let path_a = fresh_path("key_path_keyed_a");
+    let path_b = fresh_path("key_path_keyed_b");
+    let nomt_a = open(&path_a, true);
+    let nomt_b = open(&path_b, true);
+
+    let key_path = nomt_a.key_path(b"key");
+    assert_ne!(key_path, nomt_b.key_path(b"key"));
+    assert_ne!(key_path, nomt_a.key_path(b"other key"));
+
+    let session = nomt_a.begin_session(SessionParams::default());
+    let finished = session
+        .finish(vec![(key_path, KeyReadWrite::Write(Some(vec![1])))])
+        .unwrap();
+    finished.commit(&nomt_a).unwrap();
+    drop(nomt_a);
+
+    // the secret is kept by the database, whatever the options it is reopened with.
+    let nomt_a = open(&path_a, false);
+    assert_eq!(nomt_a.key_path(b"key"), key_path);
+    assert_eq!(nomt_a.read(key_path).unwrap(), Some(vec![1]));
+}
+
+#[test]
+fn unkeyed_key_paths_are_plain_hashes() {
+    let nomt_a = open(&fresh_path("key_path_plain_a"), false);
+    let nomt_b = open(&fresh_path("key_path_plain_b"), false);
+    assert_eq!(nomt_a.key_path(b"key"), nomt_b.key_path(b"key"));
+}
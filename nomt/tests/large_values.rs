@@ -1,6 +1,8 @@
 mod common;
 
 use common::Test;
+use nomt::{hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, SessionParams, MAX_VALUE_SIZE};
+use std::io::Read;
 
 #[test]
 fn large_values() {
@@ -19,3 +21,65 @@ fn large_values() {
     assert_eq!(&*t.read_id(0).unwrap(), &large1);
     assert!(t.read_id(1).is_none());
 }
+
+#[test]
+fn stream_large_values() {
+    let mut t = Test::new("stream_large_values");
+
+    let large: Vec<u8> = (0..4096 * 50 + 17).map(|i| (i % 251) as u8).collect();
+    let small = vec![3; 100];
+    t.write_id(0, Some(large.clone()));
+    t.write_id(1, Some(small.clone()));
+    let _ = t.commit();
+
+    let session = t.begin_read_session();
+    let mut reader = session
+        .read_stream(common::account_path(0))
+        .unwrap()
+        .unwrap();
+    assert_eq!(reader.len(), large.len());
+
+    // read in chunks which don't line up with pages.
+    let mut read = Vec::new();
+    let mut buf = [0; 1000];
+    loop {
+        let n = reader.read(&mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        read.extend_from_slice(&buf[..n]);
+    }
+    assert_eq!(read, large);
+
+    let mut read = Vec::new();
+    let mut reader = session
+        .read_stream(common::account_path(1))
+        .unwrap()
+        .unwrap();
+    reader.read_to_end(&mut read).unwrap();
+    assert_eq!(read, small);
+
+    assert!(session
+        .read_stream(common::account_path(2))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn oversized_value_rejected() {
+    let path = std::path::PathBuf::from("test/oversized_value_rejected");
+    let _ = std::fs::remove_dir_all(&path);
+    let mut o = Options::new();
+    o.path(path);
+    o.bitbox_seed([0; 16]);
+    let nomt = Nomt::<Blake3Hasher>::open(o).unwrap();
+
+    let session = nomt.begin_session(SessionParams::default());
+    let oversized = vec![0; MAX_VALUE_SIZE + 1];
+    let res = session.finish(vec![(
+        common::account_path(0),
+        KeyReadWrite::Write(Some(oversized)),
+    )]);
+    assert!(res.is_err());
+    assert!(nomt.is_empty());
+}
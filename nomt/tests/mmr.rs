@@ -0,0 +1,104 @@
+mod common;
+
+use nomt::{hasher::Blake3Hasher, mmr, trie::Node, KeyReadWrite, Nomt, Options, SessionParams};
+use std::path::PathBuf;
+
+fn open(name: &str, reset: bool) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if reset && path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(1);
+    o.bitbox_seed([0; 16]);
+    Nomt::open(o).unwrap()
+}
+
+fn leaf_data(i: u64) -> Vec<u8> {
+    format!("leaf {}", i).into_bytes()
+}
+
+// the root of a range of the leaves `0..leaves`, built from scratch.
+fn expected_root(leaves: u64) -> Node {
+    let mut peaks = Vec::new();
+    for i in 0..leaves {
+        let leaf = mmr::hash_leaf::<Blake3Hasher>(&leaf_data(i));
+        mmr::append::<Blake3Hasher>(i, &mut peaks, leaf);
+    }
+    mmr::root::<Blake3Hasher>(leaves, &peaks)
+}
+
+fn append(nomt: &Nomt<Blake3Hasher>, leaves: std::ops::Range<u64>) {
+    let session = nomt.begin_session(SessionParams::default());
+    let first = leaves.start;
+    for i in leaves {
+        session.append_mmr_leaf(&leaf_data(i));
+    }
+    let actuals = vec![(
+        common::account_path(first),
+        KeyReadWrite::Write(Some(vec![1])),
+    )];
+    session.finish(actuals).unwrap().commit(nomt).unwrap();
+}
+
+fn check_proofs(nomt: &Nomt<Blake3Hasher>) {
+    let root = nomt.mmr_root();
+    for i in 0..nomt.mmr_leaves() {
+        let proof = nomt.mmr_proof(i).unwrap().unwrap();
+        let leaf = mmr::hash_leaf::<Blake3Hasher>(&leaf_data(i));
+        assert!(proof.verify::<Blake3Hasher>(leaf, root), "leaf {}", i);
+    }
+    assert!(nomt.mmr_proof(nomt.mmr_leaves()).unwrap().is_none());
+}
+
+#[test]
+fn leaves_are_appended_and_proven() {
+    {
+        let nomt = open("mmr", true);
+        assert_eq!(nomt.mmr_leaves(), 0);
+        assert_eq!(nomt.mmr_root(), [0; 32]);
+
+        append(&nomt, 0..5);
+        assert_eq!(nomt.mmr_leaves(), 5);
+        assert_eq!(nomt.mmr_root(), expected_root(5));
+        check_proofs(&nomt);
+
+        // spans several pages of nodes.
+        append(&nomt, 5..300);
+        assert_eq!(nomt.mmr_root(), expected_root(300));
+        check_proofs(&nomt);
+
+        // a session without leaves leaves the range unchanged.
+        let session = nomt.begin_session(SessionParams::default());
+        let actuals = vec![(common::account_path(1), KeyReadWrite::Write(None))];
+        session.finish(actuals).unwrap().commit(&nomt).unwrap();
+        assert_eq!(nomt.mmr_leaves(), 300);
+    }
+
+    let nomt = open("mmr", false);
+    assert_eq!(nomt.mmr_leaves(), 300);
+    assert_eq!(nomt.mmr_root(), expected_root(300));
+    check_proofs(&nomt);
+
+    append(&nomt, 300..301);
+    assert_eq!(nomt.mmr_root(), expected_root(301));
+    check_proofs(&nomt);
+}
+
+#[test]
+fn overlay_leaves_are_appended() {
+    let nomt = open("mmr_overlay", true);
+    let session = nomt.begin_session(SessionParams::default());
+    for i in 0..3 {
+        session.append_mmr_leaf(&leaf_data(i));
+    }
+    let actuals = vec![(common::account_path(1), KeyReadWrite::Write(Some(vec![1])))];
+    let overlay = session.finish(actuals).unwrap().into_overlay();
+    assert_eq!(nomt.mmr_leaves(), 0);
+
+    overlay.commit(&nomt).unwrap();
+    assert_eq!(nomt.mmr_leaves(), 3);
+    assert_eq!(nomt.mmr_root(), expected_root(3));
+    check_proofs(&nomt);
+}
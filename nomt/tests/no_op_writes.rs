@@ -0,0 +1,63 @@
+mod common;
+
+use nomt::{hasher::Blake3Hasher, FinishedSession, KeyReadWrite, Nomt, Options, SessionParams};
+use std::path::PathBuf;
+
+fn open(name: &str) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(1);
+    o.bitbox_seed([0; 16]);
+    Nomt::open(o).unwrap()
+}
+
+fn finish(nomt: &Nomt<Blake3Hasher>, writes: &[(u64, Option<u64>)]) -> FinishedSession {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals: Vec<_> = writes
+        .iter()
+        .map(|(id, value)| {
+            (
+                common::account_path(*id),
+                KeyReadWrite::Write(value.map(|v| v.to_le_bytes().to_vec())),
+            )
+        })
+        .collect();
+    actuals.sort_by_key(|(k, _)| *k);
+    session.finish(actuals).unwrap()
+}
+
+#[test]
+fn no_op_writes_are_skipped() {
+    let nomt = open("no_op_writes");
+    let writes: Vec<_> = (0..1000).map(|id| (id, Some(id))).collect();
+    let finished = finish(&nomt, &writes);
+    assert_eq!(finished.stats().skipped_writes, 0);
+    finished.commit(&nomt).unwrap();
+    let root = nomt.root();
+
+    // rewriting existing values and deleting absent keys changes nothing.
+    let mut writes: Vec<_> = (0..50).map(|id| (id, Some(id))).collect();
+    writes.extend((5000..5010).map(|id| (id, None)));
+    let finished = finish(&nomt, &writes);
+    assert_eq!(finished.stats().skipped_writes, 60);
+    assert_eq!(finished.root(), root);
+    assert!(finished.page_changes().is_empty());
+    finished.commit(&nomt).unwrap();
+    assert_eq!(nomt.root(), root);
+
+    // only the effective writes are applied alongside the no-ops.
+    let writes = vec![(1, Some(1)), (2, Some(3)), (3, None), (5000, None)];
+    let finished = finish(&nomt, &writes);
+    assert_eq!(finished.stats().skipped_writes, 2);
+    assert_ne!(finished.root(), root);
+    finished.commit(&nomt).unwrap();
+    assert_eq!(
+        nomt.read(common::account_path(2)).unwrap(),
+        Some(3u64.to_le_bytes().to_vec())
+    );
+    assert_eq!(nomt.read(common::account_path(3)).unwrap(), None);
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use nomt::{
+    hasher::{Blake3Hasher, NodeHasher, ValueHasher},
+    trie::{self, InternalData, LeafData, Node},
+    KeyReadWrite, NodePreimage, Nomt, Options, SessionParams,
+};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+#[derive(Clone)]
+enum Preimage {
+    Leaf(LeafData),
+    Internal(InternalData),
+}
+
+type Preimages = Arc<Mutex<HashMap<Node, Preimage>>>;
+
+fn open(name: &str, preimages: Preimages) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(4);
+    o.bitbox_seed([0; 16]);
+    o.node_preimage_hook(Arc::new(move |node: Node, preimage: NodePreimage<'_>| {
+        let preimage = match preimage {
+            NodePreimage::Leaf(leaf) => Preimage::Leaf(leaf.clone()),
+            NodePreimage::Internal(internal) => Preimage::Internal(internal.clone()),
+        };
+        preimages.lock().unwrap().insert(node, preimage);
+    }));
+    Nomt::open(o).unwrap()
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, writes: impl IntoIterator<Item = (u64, Option<u64>)>) {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals = writes
+        .into_iter()
+        .map(|(id, value)| {
+            (
+                common::account_path(id),
+                KeyReadWrite::Write(value.map(|v| v.to_le_bytes().to_vec())),
+            )
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(k, _)| *k);
+    session.finish(actuals).unwrap().commit(nomt).unwrap();
+}
+
+// Rebuilds the trie under `node` from the recorded preimages, checking every hash along the way,
+// and returns its leaves.
+fn collect_leaves(preimages: &HashMap<Node, Preimage>, node: Node, leaves: &mut Vec<LeafData>) {
+    if trie::is_terminator::<Blake3Hasher>(&node) {
+        return;
+    }
+    match preimages.get(&node).expect("no preimage for node") {
+        Preimage::Leaf(leaf) => {
+            assert_eq!(Blake3Hasher::hash_leaf(leaf), node);
+            leaves.push(leaf.clone());
+        }
+        Preimage::Internal(internal) => {
+            assert_eq!(Blake3Hasher::hash_internal(internal), node);
+            collect_leaves(preimages, internal.left, leaves);
+            collect_leaves(preimages, internal.right, leaves);
+        }
+    }
+}
+
+fn assert_trie(nomt: &Nomt<Blake3Hasher>, preimages: &Preimages, values: &HashMap<u64, u64>) {
+    let mut leaves = Vec::new();
+    collect_leaves(
+        &preimages.lock().unwrap(),
+        nomt.root().into_inner(),
+        &mut leaves,
+    );
+
+    let mut expected = values
+        .iter()
+        .map(|(id, value)| LeafData {
+            key_path: common::account_path(*id),
+            value_hash: Blake3Hasher::hash_value(&value.to_le_bytes()),
+        })
+        .collect::<Vec<_>>();
+    expected.sort_by_key(|leaf| leaf.key_path);
+    assert_eq!(leaves, expected);
+}
+
+#[test]
+fn preimages_cover_the_trie() {
+    let preimages = Preimages::default();
+    let nomt = open("node_preimage_hook", preimages.clone());
+
+    let mut values = HashMap::new();
+    commit(&nomt, (0..1000).map(|id| (id, Some(id))));
+    values.extend((0..1000).map(|id| (id, id)));
+    assert_trie(&nomt, &preimages, &values);
+
+    // nodes which the commit left untouched were recorded by the previous one.
+    commit(
+        &nomt,
+        (0..1000)
+            .step_by(7)
+            .map(|id| (id, (id % 2 == 0).then_some(id + 1))),
+    );
+    for id in (0..1000).step_by(7) {
+        if id % 2 == 0 {
+            values.insert(id, id + 1);
+        } else {
+            values.remove(&id);
+        }
+    }
+    assert_trie(&nomt, &preimages, &values);
+}
@@ -160,3 +160,30 @@ fn overlay_uncommitted_not_on_disk() {
     assert_eq!(test.read([2; 32]), None);
     assert_eq!(test.read([3; 32]), None);
 }
+
+#[test]
+fn overlay_deletion_shadows_committed_leaf() {
+    let mut test = Test::new("overlay_deletion_shadows_committed_leaf");
+    let mut a = [0; 32];
+    let mut b = [0; 32];
+    let mut c = [0; 32];
+    a[0] = 0b0000_0000;
+    b[0] = 0b0100_0000;
+    c[0] = 0b0010_0000;
+
+    test.write(a, Some(vec![1]));
+    test.write(b, Some(vec![2]));
+    test.commit();
+
+    // deleting `a` leaves `b` as the only leaf, but `a` is still on disk.
+    test.write(a, None);
+    let overlay = test.update().0;
+
+    test.start_overlay_session([&overlay]);
+    test.write(c, Some(vec![3]));
+    let overlay = test.update().0;
+    assert_eq!(
+        overlay.root().into_inner(),
+        expected_root(vec![(b, vec![2]), (c, vec![3])]),
+    );
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use nomt::{hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, SessionParams};
+use std::path::PathBuf;
+
+fn open(name: &str, upper_levels: usize) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(1);
+    o.bitbox_seed([0; 16]);
+    o.page_cache_upper_levels(upper_levels);
+    Nomt::open(o).unwrap()
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, ids: std::ops::Range<u64>) {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals: Vec<_> = ids
+        .map(|id| {
+            (
+                common::account_path(id),
+                KeyReadWrite::Write(Some(id.to_le_bytes().to_vec())),
+            )
+        })
+        .collect();
+    actuals.sort_by_key(|(k, _)| *k);
+    session.finish(actuals).unwrap().commit(nomt).unwrap();
+}
+
+#[test]
+fn upper_levels_are_resident() {
+    let nomt = open("page_cache_stats_resident", 1);
+    let stats = nomt.page_cache_stats();
+    assert_eq!(stats.resident_levels, 1);
+    assert_eq!(stats.resident_pages, 0);
+    assert_eq!(stats.cached_pages, 0);
+
+    commit(&nomt, 0..10_000);
+    let stats = nomt.page_cache_stats();
+    // the root page and all of its children.
+    assert_eq!(stats.resident_pages, 65);
+    assert!(stats.cached_pages > 0);
+    assert!(stats.cached_pages <= stats.page_limit);
+    assert_eq!(stats.pinned_pages, 0);
+}
+
+#[test]
+fn cache_can_be_shrunk_and_cleared() {
+    let nomt = open("page_cache_stats_shrink", 1);
+    commit(&nomt, 0..10_000);
+    let cached_pages = nomt.page_cache_stats().cached_pages;
+    assert!(cached_pages > 16);
+
+    nomt.shrink_page_cache(16 * 4096);
+    assert_eq!(nomt.page_cache_stats().cached_pages, 16);
+
+    nomt.clear_page_cache();
+    let stats = nomt.page_cache_stats();
+    assert_eq!((stats.cached_pages, stats.resident_pages), (0, 65));
+
+    // pages are loaded back as needed.
+    commit(&nomt, 10_000..10_100);
+    assert!(nomt.page_cache_stats().cached_pages > 0);
+    assert_eq!(
+        nomt.read(common::account_path(5)).unwrap(),
+        Some(5u64.to_le_bytes().to_vec())
+    );
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::Test;
+use std::{fs::OpenOptions, os::unix::fs::FileExt as _, path::PathBuf};
+
+const PAGE_SIZE: usize = 4096;
+const HEADER_START: usize = 126 * 32;
+
+#[test]
+fn corrupted_page_is_reported_on_load() {
+    {
+        let mut t = Test::new_with_params("page_header_corrupted", 1, 1000, None, true);
+        for id in 0..1000 {
+            t.write_id(id, Some(vec![1; 8]));
+        }
+        t.commit();
+    }
+
+    // flip a bit in the nodes of every page with a header.
+    let ht = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(PathBuf::from("test/page_header_corrupted/ht"))
+        .unwrap();
+    let len = ht.metadata().unwrap().len();
+    let mut corrupted = 0;
+    let mut page = vec![0; PAGE_SIZE];
+    for offset in (0..len).step_by(PAGE_SIZE) {
+        ht.read_exact_at(&mut page, offset).unwrap();
+        if &page[HEADER_START..HEADER_START + 4] == b"NPGH" {
+            page[0] ^= 1;
+            ht.write_all_at(&page, offset).unwrap();
+            corrupted += 1;
+        }
+    }
+    assert!(corrupted > 0);
+    drop(ht);
+
+    let mut o = nomt::Options::new();
+    o.path("test/page_header_corrupted");
+    let err = nomt::Nomt::<nomt::hasher::Blake3Hasher>::open(o)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("page checksum mismatch"));
+}
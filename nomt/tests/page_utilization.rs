@@ -0,0 +1,28 @@
+mod common;
+
+use common::Test;
+
+#[test]
+fn reports_stored_pages() {
+    let mut t = Test::new("page_utilization");
+    let report = t.page_utilization();
+    assert_eq!(report.pages, 0);
+    assert_eq!(report.fill_rate(), 0.0);
+
+    for id in 0..5000u64 {
+        t.write_id(id, Some(id.to_le_bytes().to_vec()));
+    }
+    let _ = t.commit();
+
+    let report = t.page_utilization();
+    assert_eq!(report.slot_histogram.iter().sum::<u64>(), report.pages);
+    assert_eq!(report.depth_histogram.iter().sum::<u64>(), report.pages);
+    // the root page is full with this many keys, and has all of its children.
+    assert_eq!(report.depth_histogram[0], 1);
+    assert_eq!(report.depth_histogram[1], 64);
+    assert!(report.slot_histogram[126] >= 1);
+    // every leaf and internal node below the root occupies a slot.
+    assert!(report.occupied_slots() >= 2 * 5000 - 2);
+    assert!(report.fill_rate() > 0.0 && report.fill_rate() < 1.0);
+    assert!(report.pages_with_at_most(10) > 0);
+}
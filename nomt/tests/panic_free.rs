@@ -0,0 +1,30 @@
+#![cfg(feature = "panic-free")]
+
+use nomt::{
+    hasher::Blake3Hasher, FatalError, KeyReadWrite, Nomt, Options, PanicOnSyncMode, SessionParams,
+};
+use std::path::PathBuf;
+
+#[test]
+fn panic_during_commit_poisons_the_database() {
+    let path = PathBuf::from("test").join("panic_free_commit");
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.panic_on_sync(PanicOnSyncMode::PostWal);
+    let nomt = Nomt::<Blake3Hasher>::open(o).unwrap();
+
+    let session = nomt.begin_session(SessionParams::default());
+    let err = session
+        .finish(vec![([1; 32], KeyReadWrite::Write(Some(vec![1])))])
+        .unwrap()
+        .commit(&nomt)
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<FatalError>(),
+        Some(FatalError::Panicked(message)) if message.contains("panic_on_sync")
+    ));
+    assert!(nomt.is_poisoned());
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use nomt::{hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, Root, SessionParams};
+use std::path::PathBuf;
+
+fn open(
+    name: &str,
+    prefetch_depth: usize,
+    warm_up: bool,
+    io_queue_depth: usize,
+    clean_up: bool,
+) -> Nomt<Blake3Hasher> {
+    let path = {
+        let mut p = PathBuf::from("test");
+        p.push(name);
+        p
+    };
+    if clean_up && path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(2);
+    o.bitbox_seed([0; 16]);
+    o.page_prefetch_depth(prefetch_depth);
+    o.warm_up(warm_up);
+    o.io_queue_depth(io_queue_depth);
+    Nomt::open(o).unwrap()
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, ids: impl Iterator<Item = u64>, round: u64) -> Root {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals: Vec<_> = ids
+        .map(|id| {
+            let key = common::account_path(id);
+            session.warm_up(key);
+            (
+                key,
+                KeyReadWrite::Write(Some((id + round).to_le_bytes().to_vec())),
+            )
+        })
+        .collect();
+    actuals.sort_by_key(|(k, _)| *k);
+    session.finish(actuals).unwrap().commit(nomt).unwrap();
+    nomt.root()
+}
+
+// Updating a database with a cold page cache must give the same results regardless of how many
+// pages are fetched speculatively.
+fn run(name: &str, prefetch_depth: usize, warm_up: bool, io_queue_depth: usize) -> Vec<Root> {
+    let nomt = open(name, prefetch_depth, warm_up, io_queue_depth, true);
+    let mut roots = vec![commit(&nomt, 0..20_000, 0)];
+    drop(nomt);
+
+    for round in 1..4 {
+        // reopen to start with a cold page cache.
+        let nomt = open(name, prefetch_depth, warm_up, io_queue_depth, false);
+        roots.push(commit(
+            &nomt,
+            (0..25_000).step_by(round as usize * 7),
+            round,
+        ));
+        drop(nomt);
+    }
+    roots
+}
+
+#[test]
+fn prefetch_does_not_change_results() {
+    let expected = run("prefetch_disabled", 0, false, 1024);
+    assert_eq!(run("prefetch_depth_1", 1, false, 1024), expected);
+    assert_eq!(run("prefetch_depth_4", 4, false, 1024), expected);
+    assert_eq!(run("prefetch_depth_4_warm_up", 4, true, 1024), expected);
+    // prefetches are skipped while the I/O queue is full.
+    assert_eq!(run("prefetch_depth_4_shallow_queue", 4, false, 2), expected);
+}
+
+fn finish(session: nomt::Session<Blake3Hasher>, ids: impl Iterator<Item = u64>) -> Root {
+    let mut actuals: Vec<_> = ids
+        .map(|id| {
+            let key = common::account_path(id);
+            (key, KeyReadWrite::Write(Some(id.to_be_bytes().to_vec())))
+        })
+        .collect();
+    actuals.sort_by_key(|(k, _)| *k);
+    session.finish(actuals).unwrap().root()
+}
+
+// Sessions warming up overlapping keys at the same time share speculative loads. Neither may be
+// affected by the other cancelling its loads.
+#[test]
+fn concurrent_sessions_share_prefetches() {
+    let name = "prefetch_concurrent_sessions";
+    let nomt = open(name, 4, true, 1024, true);
+    commit(&nomt, 0..20_000, 0);
+    drop(nomt);
+
+    let a_ids = || (0..12_000).step_by(3);
+    let b_ids = || (6_000..18_000).step_by(3);
+
+    let nomt = open(name, 4, true, 1024, false);
+    let a = nomt.begin_session(SessionParams::default());
+    let b = nomt.begin_session(SessionParams::default());
+    for (a_id, b_id) in a_ids().zip(b_ids()) {
+        a.warm_up(common::account_path(a_id));
+        b.warm_up(common::account_path(b_id));
+    }
+    let (a_root, b_root) = (finish(a, a_ids()), finish(b, b_ids()));
+    drop(nomt);
+
+    // the same sessions on their own, each with a cold page cache.
+    for (ids, root) in [(a_ids as fn() -> _, a_root), (b_ids as fn() -> _, b_root)] {
+        let nomt = open(name, 4, true, 1024, false);
+        let session = nomt.begin_session(SessionParams::default());
+        for id in ids() {
+            session.warm_up(common::account_path(id));
+        }
+        assert_eq!(finish(session, ids()), root);
+    }
+}
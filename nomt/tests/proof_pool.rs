@@ -0,0 +1,161 @@
+mod common;
+
+use nomt::{
+    hasher::Blake3Hasher,
+    proof_pool::{ProofPool, ProofPoolParams},
+    KeyReadWrite, Nomt, Options, SessionParams,
+};
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+fn open(name: &str) -> Arc<Nomt<Blake3Hasher>> {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    let nomt = Nomt::open(o).unwrap();
+
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals = (0..1000)
+        .map(|id| {
+            (
+                common::account_path(id),
+                KeyReadWrite::Write(Some(id.to_le_bytes().to_vec())),
+            )
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(k, _)| *k);
+    session.finish(actuals).unwrap().commit(&nomt).unwrap();
+    Arc::new(nomt)
+}
+
+#[test]
+fn proofs_are_served_concurrently_with_commits() {
+    let nomt = open("proof_pool_serve");
+    let pool = ProofPool::new(nomt.clone(), ProofPoolParams::default().workers(4));
+
+    let tickets = (0..100u64)
+        .map(|i| {
+            let keys = (0..5).map(|j| common::account_path(i * 10 + j));
+            pool.submit(keys).unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    // commits proceed while proofs are served.
+    let session = nomt.begin_session(SessionParams::default());
+    let key = common::account_path(5000);
+    session
+        .finish(vec![(key, KeyReadWrite::Write(Some(vec![1])))])
+        .unwrap()
+        .commit(&nomt)
+        .unwrap();
+
+    for ticket in tickets {
+        let served = ticket.wait().unwrap();
+        let builder = served.witness.builder::<Blake3Hasher>().unwrap();
+        for (key, value) in &served.witness.values {
+            assert!(value.is_some());
+            assert!(builder.read(key).unwrap().is_some());
+        }
+    }
+
+    let stats = pool.stats();
+    assert_eq!(stats.served, 100);
+    assert_eq!((stats.queued, stats.in_flight, stats.rejected), (0, 0, 0));
+    assert!(stats.max_queue_time <= stats.total_queue_time);
+
+    let served = pool.prove([key]).unwrap();
+    assert_eq!(served.witness.prev_root, nomt.root());
+    assert_eq!(served.witness.values, vec![(key, Some(vec![1]))]);
+}
+
+#[test]
+fn requests_are_rate_limited_and_bounded() {
+    let nomt = open("proof_pool_limits");
+    let pool = ProofPool::new(
+        nomt,
+        ProofPoolParams::default()
+            .workers(1)
+            .max_queued(2)
+            .max_rate(2),
+    );
+
+    // the first request starts right away and the next ones half a second apart each.
+    let first = pool.prove([common::account_path(1)]).unwrap();
+    assert!(first.queue_time < Duration::from_millis(250));
+    let second = pool.submit([common::account_path(2)]).unwrap();
+    let third = pool.submit([common::account_path(3)]).unwrap();
+    assert!(pool.submit([common::account_path(4)]).is_err());
+    assert_eq!(pool.stats().rejected, 1);
+
+    let second = second.wait().unwrap();
+    let third = third.wait().unwrap();
+    assert!(second.queue_time >= Duration::from_millis(250));
+    assert!(third.queue_time >= Duration::from_millis(750));
+
+    let stats = pool.stats();
+    assert_eq!(stats.served, 3);
+    assert_eq!(stats.max_queue_time, third.queue_time);
+}
+
+#[test]
+fn cached_proofs_are_served_until_the_root_changes() {
+    let nomt = open("proof_pool_cache");
+    let pool = ProofPool::new(nomt.clone(), ProofPoolParams::default().cache_size(100));
+    let key = common::account_path;
+    let check = |served: &nomt::proof_pool::ServedProof, values: Vec<(u64, Vec<u8>)>| {
+        assert_eq!(served.witness.prev_root, nomt.root());
+        served.witness.builder::<Blake3Hasher>().unwrap();
+        let mut values = values
+            .into_iter()
+            .map(|(id, value)| (key(id), Some(value)))
+            .collect::<Vec<_>>();
+        values.sort_by_key(|(k, _)| *k);
+        assert_eq!(served.witness.values, values);
+    };
+
+    let served = pool.prove([key(1), key(2)]).unwrap();
+    check(
+        &served,
+        vec![
+            (1, 1u64.to_le_bytes().to_vec()),
+            (2, 2u64.to_le_bytes().to_vec()),
+        ],
+    );
+    assert_eq!((pool.stats().cache_hits, pool.stats().cache_misses), (0, 2));
+
+    let served = pool.prove([key(2), key(3), key(2)]).unwrap();
+    check(
+        &served,
+        vec![
+            (2, 2u64.to_le_bytes().to_vec()),
+            (3, 3u64.to_le_bytes().to_vec()),
+        ],
+    );
+    assert_eq!((pool.stats().cache_hits, pool.stats().cache_misses), (1, 3));
+
+    let served = pool.prove([key(1), key(3)]).unwrap();
+    check(
+        &served,
+        vec![
+            (1, 1u64.to_le_bytes().to_vec()),
+            (3, 3u64.to_le_bytes().to_vec()),
+        ],
+    );
+    assert_eq!((pool.stats().cache_hits, pool.stats().cache_misses), (3, 3));
+
+    // a commit invalidates the cached proofs.
+    let session = nomt.begin_session(SessionParams::default());
+    session
+        .finish(vec![(key(2), KeyReadWrite::Write(Some(vec![9])))])
+        .unwrap()
+        .commit(&nomt)
+        .unwrap();
+    let served = pool.prove([key(1), key(2)]).unwrap();
+    check(
+        &served,
+        vec![(1, 1u64.to_le_bytes().to_vec()), (2, vec![9])],
+    );
+    assert_eq!((pool.stats().cache_hits, pool.stats().cache_misses), (3, 5));
+}
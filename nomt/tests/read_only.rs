@@ -0,0 +1,87 @@
+mod common;
+
+use nomt::{hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, Root, SessionParams};
+use std::path::PathBuf;
+
+fn open(path: &PathBuf, read_only: bool) -> anyhow::Result<Nomt<Blake3Hasher>> {
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(1);
+    o.bitbox_seed([0; 16]);
+    o.read_only(read_only);
+    Nomt::open(o)
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, writes: &[(u64, Option<Vec<u8>>)]) -> anyhow::Result<Root> {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals: Vec<_> = writes
+        .iter()
+        .map(|(id, value)| {
+            (
+                common::account_path(*id),
+                KeyReadWrite::Write(value.clone()),
+            )
+        })
+        .collect();
+    actuals.sort_by_key(|(k, _)| *k);
+    let finished = session.finish(actuals)?;
+    let root = finished.root();
+    finished.commit(nomt)?;
+    Ok(root)
+}
+
+fn fresh_path(name: &str) -> PathBuf {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    path
+}
+
+#[test]
+fn readers_share_the_database() {
+    let path = fresh_path("read_only_share");
+    let writes: Vec<_> = (0..100u64)
+        .map(|id| (id, Some(id.to_le_bytes().to_vec())))
+        .collect();
+    let root = {
+        let nomt = open(&path, false).unwrap();
+        commit(&nomt, &writes).unwrap()
+    };
+
+    let reader_1 = open(&path, true).unwrap();
+    let reader_2 = open(&path, true).unwrap();
+    for reader in [&reader_1, &reader_2] {
+        assert!(reader.is_read_only());
+        assert_eq!(reader.root(), root);
+        assert_eq!(
+            reader.read(common::account_path(7)).unwrap(),
+            Some(7u64.to_le_bytes().to_vec())
+        );
+    }
+
+    // a writer can't open the database while it's being read.
+    assert!(open(&path, false).is_err());
+
+    assert!(commit(&reader_1, &[(1, None)]).is_err());
+    assert_eq!(reader_1.root(), root);
+    assert!(reader_1.rollback(1).is_err());
+
+    drop(reader_1);
+    drop(reader_2);
+    let _nomt = open(&path, false).unwrap();
+}
+
+#[test]
+fn reader_excluded_by_writer() {
+    let path = fresh_path("read_only_excluded");
+    let _nomt = open(&path, false).unwrap();
+    assert!(open(&path, true).is_err());
+}
+
+#[test]
+fn reader_requires_existing_database() {
+    let path = fresh_path("read_only_missing");
+    assert!(open(&path, true).is_err());
+    assert!(!path.exists());
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use common::Test;
+
+#[test]
+fn read_session_observes_committed_state() {
+    let mut t = Test::new("read_session_observes_committed_state");
+    t.write_id(1, Some(vec![1, 2, 3]));
+    t.write_id(2, Some(vec![4, 5, 6]));
+    let (root, _) = t.commit();
+
+    let read_session = t.begin_read_session();
+    assert_eq!(read_session.root(), root);
+    assert_eq!(
+        read_session.read(common::account_path(1)).unwrap(),
+        Some(vec![1, 2, 3])
+    );
+    assert_eq!(
+        read_session.read(common::account_path(2)).unwrap(),
+        Some(vec![4, 5, 6])
+    );
+    assert_eq!(read_session.read(common::account_path(3)).unwrap(), None);
+}
+
+#[test]
+fn read_many_matches_single_reads() {
+    let mut t = Test::new("read_many_matches_single_reads");
+    for id in 0..5000u64 {
+        t.write_id(id, Some(id.to_le_bytes().to_vec()));
+    }
+    // overflow values.
+    t.write_id(5000, Some(vec![7; 4096 * 10]));
+    t.write_id(5001, Some(vec![8; 4096 * 3 + 5]));
+    let _ = t.commit();
+    t.write_id(10, None);
+    let _ = t.commit();
+
+    let read_session = t.begin_read_session();
+    // include missing keys and duplicates.
+    let paths: Vec<_> = (0..6000u64)
+        .step_by(3)
+        .chain([5000, 5001, 10, 42, 42])
+        .map(common::account_path)
+        .collect();
+    let values = read_session.read_many(&paths).unwrap();
+    assert_eq!(values.len(), paths.len());
+    for (path, value) in paths.iter().zip(values) {
+        assert_eq!(value, read_session.read(*path).unwrap());
+    }
+    assert!(read_session.read_many(&[]).unwrap().is_empty());
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use nomt::{
+    hasher::Blake3Hasher, replay::Replay, KeyReadWrite, Nomt, Options, Root, SessionParams,
+};
+use std::path::{Path, PathBuf};
+
+fn fresh(name: &str) -> PathBuf {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    path
+}
+
+fn open(path: &Path) -> Nomt<Blake3Hasher> {
+    let mut o = Options::new();
+    o.path(path);
+    o.rollback(true);
+    Nomt::open(o).unwrap()
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, writes: impl IntoIterator<Item = (u64, Option<Vec<u8>>)>) {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals = writes
+        .into_iter()
+        .map(|(id, value)| (common::account_path(id), KeyReadWrite::Write(value)))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(k, _)| *k);
+    session.finish(actuals).unwrap().commit(nomt).unwrap();
+}
+
+// Make three commits and return the roots before each and after the last.
+fn make_commits(path: &Path) -> Vec<Root> {
+    let nomt = open(path);
+    let mut roots = vec![nomt.root()];
+    commit(&nomt, (0..10).map(|id| (id, Some(b"original".to_vec()))));
+    roots.push(nomt.root());
+    commit(&nomt, (0..5).map(|id| (id, Some(b"updated".to_vec()))));
+    roots.push(nomt.root());
+    commit(&nomt, [(3, None), (20, Some(vec![7; 100]))]);
+    roots.push(nomt.root());
+    roots
+}
+
+#[test]
+fn commits_are_undone_in_order() {
+    let path = fresh("replay_undo");
+    let roots = make_commits(&path);
+
+    let mut replay = Replay::<Blake3Hasher>::open(&path, fresh("replay_undo_copy")).unwrap();
+    assert_eq!(replay.commits().len(), 3);
+    assert_eq!(replay.root(), roots[3]);
+
+    let undone = replay.undo().unwrap().unwrap();
+    assert!(undone.is_consistent());
+    assert_eq!((undone.root, undone.undone_root), (roots[3], roots[2]));
+    let key = common::account_path(3);
+    let i = undone
+        .commit
+        .priors
+        .iter()
+        .position(|(k, _)| *k == key)
+        .unwrap();
+    assert_eq!(undone.commit.priors[i].1, Some(b"updated".to_vec()));
+    assert_eq!(undone.writes[i], (key, None));
+    assert!(undone.to_string().contains(" -> none"));
+
+    assert_eq!(replay.find_inconsistent(|_| {}).unwrap(), None);
+    assert_eq!(replay.root(), roots[0]);
+    assert!(replay.undo().unwrap().is_none());
+
+    // the original is left as it was.
+    assert_eq!(open(&path).root(), roots[3]);
+}
+
+#[test]
+fn inconsistent_commit_is_found() {
+    let path = fresh("replay_inconsistent");
+    let roots = make_commits(&path);
+
+    // corrupt the prior values recorded for the second commit.
+    for entry in std::fs::read_dir(&path).unwrap() {
+        let entry = entry.unwrap();
+        if !entry.file_name().to_string_lossy().starts_with("rollback") {
+            continue;
+        }
+        let mut bytes = std::fs::read(entry.path()).unwrap();
+        let mut corrupted = false;
+        for i in 0..bytes.len().saturating_sub(8) {
+            if &bytes[i..i + 8] == b"original" {
+                bytes[i..i + 8].copy_from_slice(b"tampered");
+                corrupted = true;
+            }
+        }
+        if corrupted {
+            std::fs::write(entry.path(), bytes).unwrap();
+        }
+    }
+
+    let mut replay =
+        Replay::<Blake3Hasher>::open(&path, fresh("replay_inconsistent_copy")).unwrap();
+    let mut undone_ids = Vec::new();
+    let bad = replay
+        .find_inconsistent(|undone| undone_ids.push(undone.commit.id))
+        .unwrap()
+        .unwrap();
+    assert_eq!(undone_ids.len(), 2);
+    assert_eq!(bad.commit.prev_root, Some(roots[1]));
+    assert_ne!(bad.undone_root, roots[1]);
+    assert!(bad.to_string().contains("MISMATCH"));
+    assert_eq!(replay.commits().len(), 1);
+}
@@ -0,0 +1,27 @@
+mod common;
+
+use common::Test;
+
+#[test]
+fn reset_clears_everything() {
+    let mut t = Test::new("reset_clears");
+    for id in 0..1000u64 {
+        t.write_id(id, Some(id.to_le_bytes().to_vec()));
+    }
+    t.write_id(1000, Some(vec![7; 4096 * 5]));
+    let _ = t.commit();
+    assert!(!t.root().is_empty());
+
+    t.reset();
+    assert!(t.root().is_empty());
+    assert_eq!(t.read_id(5), None);
+    assert_eq!(t.read_id(1000), None);
+
+    // the database is usable afterwards, and ends up like a fresh one.
+    t.write_id(5, Some(vec![5]));
+    let (root, _) = t.commit();
+
+    let mut fresh = Test::new("reset_clears_fresh");
+    fresh.write_id(5, Some(vec![5]));
+    assert_eq!(fresh.commit().0, root);
+}
@@ -494,3 +494,118 @@ fn test_rollback_read_then_write() {
     nomt.rollback(1).unwrap();
     assert_eq!(nomt.read(key).unwrap(), None);
 }
+
+#[test]
+fn test_rollback_then_commit_with_warm_caches() {
+    // Rollback is applied as a regular commit, which updates the cached pages and leaves like any
+    // other. This test ensures that commits after a rollback, built on the cached pages rather
+    // than on pages loaded from disk, match a database which never saw the rolled back commit.
+    fn commit(nomt: &Nomt<Blake3Hasher>, changes: impl IntoIterator<Item = (u8, Option<u8>)>) {
+        let session = nomt.begin_session(SessionParams::default());
+        let mut actuals: Vec<_> = changes
+            .into_iter()
+            .map(|(k, v)| {
+                let key = [k; 32];
+                session.warm_up(key);
+                (key, KeyReadWrite::Write(v.map(|v| vec![v; 64])))
+            })
+            .collect();
+        actuals.sort_by_key(|(k, _)| *k);
+        session.finish(actuals).unwrap().commit(nomt).unwrap();
+    }
+
+    let first = || (0..200).map(|k| (k, Some(k)));
+    let rolled_back = || (100..250).map(|k| (k, (k % 2 == 0).then_some(!k)));
+    let last = || (150..230).map(|k| (k, Some(k ^ 0x55)));
+
+    let nomt = setup_nomt(
+        "rollback_warm_caches",
+        /* rollback_enabled */ true,
+        /* commit_concurrency */ 1,
+        /* should_clean_up */ true,
+    );
+    commit(&nomt, first());
+    commit(&nomt, rolled_back());
+    // load the pages and leaves touched by the rolled back commit.
+    for k in 0..=255 {
+        let _ = nomt.read([k; 32]).unwrap();
+    }
+    nomt.rollback(1).unwrap();
+    commit(&nomt, last());
+
+    let expected = setup_nomt(
+        "rollback_warm_caches_expected",
+        /* rollback_enabled */ false,
+        /* commit_concurrency */ 1,
+        /* should_clean_up */ true,
+    );
+    commit(&expected, first());
+    commit(&expected, last());
+
+    assert_eq!(nomt.root(), expected.root());
+    for k in 0..=255 {
+        assert_eq!(nomt.read([k; 32]).unwrap(), expected.read([k; 32]).unwrap());
+    }
+
+    // and the state on disk agrees once the caches are gone.
+    let root = nomt.root();
+    drop(nomt);
+    let nomt = setup_nomt(
+        "rollback_warm_caches",
+        /* rollback_enabled */ true,
+        /* commit_concurrency */ 1,
+        /* should_clean_up */ false,
+    );
+    assert_eq!(nomt.root(), root);
+    for k in 0..=255 {
+        assert_eq!(nomt.read([k; 32]).unwrap(), expected.read([k; 32]).unwrap());
+    }
+}
+
+#[test]
+fn test_pinned_roots_persist_and_block_rollback() {
+    fn commit(nomt: &Nomt<Blake3Hasher>, k: u8) {
+        let session = nomt.begin_session(SessionParams::default());
+        let actuals = vec![([k; 32], KeyReadWrite::Write(Some(vec![k])))];
+        session.finish(actuals).unwrap().commit(nomt).unwrap();
+    }
+
+    let nomt = setup_nomt(
+        "rollback_pinned_roots",
+        /* rollback_enabled */ true,
+        /* commit_concurrency */ 1,
+        /* should_clean_up */ true,
+    );
+    let mut roots = vec![nomt.root()];
+    for k in 1..=4 {
+        commit(&nomt, k);
+        roots.push(nomt.root());
+    }
+    assert!(nomt.pin_root(roots[2]).unwrap());
+    assert!(nomt.pin_root(roots[3]).unwrap());
+    assert!(!nomt.pin_root(roots[3]).unwrap());
+    drop(nomt);
+
+    let nomt = setup_nomt(
+        "rollback_pinned_roots",
+        /* rollback_enabled */ true,
+        /* commit_concurrency */ 1,
+        /* should_clean_up */ false,
+    );
+    let mut expected = vec![roots[2], roots[3]];
+    expected.sort_by_key(|root| root.into_inner());
+    assert_eq!(nomt.pinned_roots(), expected);
+
+    // rolling back to roots[2] would discard roots[3].
+    assert!(nomt.rollback(2).is_err());
+    assert_eq!(nomt.root(), roots[4]);
+    nomt.rollback(1).unwrap();
+    assert_eq!(nomt.root(), roots[3]);
+
+    assert!(nomt.unpin_root(roots[3]).unwrap());
+    assert!(!nomt.unpin_root(roots[3]).unwrap());
+    nomt.rollback(1).unwrap();
+    assert_eq!(nomt.root(), roots[2]);
+    assert!(nomt.rollback(1).is_err());
+    assert_eq!(nomt.pinned_roots(), vec![roots[2]]);
+}
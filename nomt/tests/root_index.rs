@@ -0,0 +1,102 @@
+use nomt::{
+    hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, Overlay, Root, RootInfo, SessionParams,
+};
+use std::path::PathBuf;
+
+fn open(name: &str, reset: bool, root_index: bool) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if reset && path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.rollback(true);
+    o.root_index(root_index);
+    Nomt::open(o).unwrap()
+}
+
+fn session(nomt: &Nomt<Blake3Hasher>, id: Option<u64>, value: u8) -> nomt::FinishedSession {
+    let mut params = SessionParams::default();
+    if let Some(id) = id {
+        params = params.commit_id(id);
+    }
+    nomt.begin_session(params)
+        .finish(vec![([1; 32], KeyReadWrite::Write(Some(vec![value])))])
+        .unwrap()
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, id: Option<u64>, value: u8) -> (Root, u64) {
+    let finished = session(nomt, id, value);
+    let root = finished.root();
+    (root, finished.commit(nomt).unwrap())
+}
+
+#[test]
+fn roots_resolve_to_their_commits() {
+    let nomt = open("root_index_lookup", true, true);
+    let empty = nomt.root();
+    assert_eq!(
+        nomt.lookup_root(empty).unwrap(),
+        Some(RootInfo {
+            sequence: 0,
+            prev_root: None,
+            commit_id: None,
+        })
+    );
+
+    let (first, first_seq) = commit(&nomt, Some(7), 1);
+    let overlay: Overlay = session(&nomt, None, 2).into_overlay();
+    let second = overlay.root();
+    let second_seq = overlay.commit(&nomt).unwrap();
+    assert_eq!(
+        nomt.lookup_root(first).unwrap(),
+        Some(RootInfo {
+            sequence: first_seq,
+            prev_root: Some(empty),
+            commit_id: Some(7),
+        })
+    );
+    assert_eq!(
+        nomt.lookup_root(second).unwrap(),
+        Some(RootInfo {
+            sequence: second_seq,
+            prev_root: Some(first),
+            commit_id: None,
+        })
+    );
+    assert_eq!(nomt.lookup_root(Root::from([9; 32])).unwrap(), None);
+
+    // rolling back produces the old root again, under a new sequence number.
+    nomt.rollback(1).unwrap();
+    let info = nomt.lookup_root(first).unwrap().unwrap();
+    assert_eq!(info.sequence, nomt.current_sequence());
+    assert_eq!(info.prev_root, Some(second));
+    drop(nomt);
+
+    let nomt = open("root_index_lookup", false, true);
+    assert_eq!(nomt.lookup_root(first).unwrap(), Some(info));
+    assert_eq!(
+        nomt.lookup_root(second).unwrap().unwrap().sequence,
+        second_seq
+    );
+}
+
+#[test]
+fn enabling_the_index_records_the_current_root() {
+    let nomt = open("root_index_enable", true, false);
+    let (first, _) = commit(&nomt, None, 1);
+    let (second, second_seq) = commit(&nomt, None, 2);
+    assert!(nomt.lookup_root(second).is_err());
+    drop(nomt);
+
+    let nomt = open("root_index_enable", false, true);
+    assert_eq!(nomt.lookup_root(first).unwrap(), None);
+    assert_eq!(
+        nomt.lookup_root(second).unwrap(),
+        Some(RootInfo {
+            sequence: second_seq,
+            prev_root: None,
+            commit_id: None,
+        })
+    );
+}
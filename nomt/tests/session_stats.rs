@@ -0,0 +1,72 @@
+mod common;
+
+use nomt::{hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, SessionParams};
+use std::{path::PathBuf, time::Duration};
+
+fn open(name: &str, reset: bool) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if reset && path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(1);
+    o.bitbox_seed([0; 16]);
+    Nomt::open(o).unwrap()
+}
+
+#[test]
+fn session_reads_are_counted() {
+    {
+        let nomt = open("session_stats", true);
+        let session = nomt.begin_session(SessionParams::default());
+        let mut actuals: Vec<_> = (0..5000)
+            .map(|id| {
+                (
+                    common::account_path(id),
+                    KeyReadWrite::Write(Some(id.to_le_bytes().to_vec())),
+                )
+            })
+            .collect();
+        actuals.sort_by_key(|(k, _)| *k);
+        session.finish(actuals).unwrap().commit(&nomt).unwrap();
+    }
+
+    // reopen to start with a cold leaf cache.
+    let nomt = open("session_stats", false);
+    let session = nomt.begin_session(SessionParams::default().slow_read_threshold(Duration::ZERO));
+    let key = common::account_path(1);
+    assert_eq!(
+        session.read(key).unwrap(),
+        Some(1u64.to_le_bytes().to_vec())
+    );
+    let stats = session.stats();
+    assert_eq!(stats.keys_read, 1);
+    assert_eq!(stats.leaf_pages_fetched, 1);
+    assert_eq!(stats.leaf_cache_hits, 0);
+
+    // the leaf is cached now.
+    session.read(key).unwrap();
+    let keys: Vec<_> = (100..200).map(common::account_path).collect();
+    session.read_many(&keys).unwrap();
+    let stats = session.stats();
+    assert_eq!(stats.keys_read, 102);
+    assert!(stats.leaf_cache_hits >= 1);
+    assert!(stats.leaf_pages_fetched > 1);
+    assert!(stats.cache_hit_ratio() > 0.0 && stats.cache_hit_ratio() < 1.0);
+    assert!(stats.stall_time > Duration::ZERO);
+
+    let slow_reads = session.slow_reads();
+    assert_eq!(stats.slow_reads, 3);
+    assert_eq!(slow_reads.len(), 3);
+    assert_eq!(slow_reads[0].key_paths, vec![key]);
+    assert_eq!(slow_reads[0].leaf_pages_fetched, 1);
+    assert_eq!(slow_reads[1].leaf_pages_fetched, 0);
+    assert_eq!(slow_reads[2].key_paths, keys);
+
+    // without a threshold, nothing is logged.
+    let session = nomt.begin_session(SessionParams::default());
+    session.read(key).unwrap();
+    assert_eq!(session.stats().slow_reads, 0);
+    assert!(session.slow_reads().is_empty());
+}
@@ -0,0 +1,148 @@
+mod common;
+
+use nomt::{
+    hasher::Blake3Hasher, CommitStrategy, KeyReadWrite, Nomt, Options, PanicOnSyncMode,
+    SessionParams,
+};
+use std::path::{Path, PathBuf};
+
+fn open(path: &Path, panic_on_sync: Option<PanicOnSyncMode>) -> Nomt<Blake3Hasher> {
+    let mut o = Options::new();
+    o.path(path);
+    o.hashtable_buckets(10_000);
+    o.commit_strategy(CommitStrategy::ShadowPaging);
+    if let Some(mode) = panic_on_sync {
+        o.panic_on_sync(mode);
+    }
+    Nomt::open(o).unwrap()
+}
+
+fn fresh(name: &str) -> PathBuf {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    path
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, writes: impl IntoIterator<Item = (u64, Option<Vec<u8>>)>) {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals = writes
+        .into_iter()
+        .map(|(id, value)| (common::account_path(id), KeyReadWrite::Write(value)))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(k, _)| *k);
+    session.finish(actuals).unwrap().commit(nomt).unwrap();
+}
+
+fn read(nomt: &Nomt<Blake3Hasher>, id: u64) -> Option<Vec<u8>> {
+    nomt.read(common::account_path(id)).unwrap()
+}
+
+#[test]
+fn commits_survive_reopening() {
+    let path = fresh("shadow_paging_reopen");
+    let nomt = open(&path, None);
+    commit(&nomt, (0..2000).map(|id| (id, Some(vec![1; 8]))));
+    // pages are rewritten by consecutive commits, alternating between their slots.
+    commit(&nomt, (0..1000).map(|id| (id, Some(vec![2; 8]))));
+    commit(
+        &nomt,
+        (0..500)
+            .map(|id| (id, Some(vec![3; 8])))
+            .chain((1500..2000).map(|id| (id, None))),
+    );
+    let root = nomt.root();
+    drop(nomt);
+
+    // nothing is ever written to the WAL.
+    assert_eq!(std::fs::metadata(path.join("wal")).unwrap().len(), 0);
+
+    // the strategy the database was created with is kept.
+    let mut o = Options::new();
+    o.path(&path);
+    o.commit_strategy(CommitStrategy::Wal);
+    let nomt = Nomt::<Blake3Hasher>::open(o).unwrap();
+    assert_eq!(nomt.root(), root);
+    assert_eq!(read(&nomt, 0), Some(vec![3; 8]));
+    assert_eq!(read(&nomt, 700), Some(vec![2; 8]));
+    assert_eq!(read(&nomt, 1200), Some(vec![1; 8]));
+    assert_eq!(read(&nomt, 1700), None);
+
+    commit(&nomt, (0..2000).map(|id| (id, Some(vec![4; 8]))));
+    drop(nomt);
+    let nomt = open(&path, None);
+    for id in (0..2000).step_by(100) {
+        assert_eq!(read(&nomt, id), Some(vec![4; 8]));
+    }
+    assert_eq!(std::fs::metadata(path.join("wal")).unwrap().len(), 0);
+}
+
+fn base_commits(nomt: &Nomt<Blake3Hasher>) {
+    commit(nomt, (0..1000).map(|id| (id, Some(vec![1; 8]))));
+    commit(nomt, (0..500).map(|id| (id, Some(vec![2; 8]))));
+}
+
+fn interrupted_commit(nomt: &Nomt<Blake3Hasher>) {
+    commit(
+        nomt,
+        (0..1000)
+            .step_by(2)
+            .map(|id| (id, Some(vec![3; 8])))
+            .chain((1..1000).step_by(4).map(|id| (id, None)))
+            .chain((1000..2000).map(|id| (id, Some(vec![3; 8])))),
+    );
+}
+
+fn next_commit(nomt: &Nomt<Blake3Hasher>) {
+    commit(nomt, (0..3000).step_by(3).map(|id| (id, Some(vec![5; 8]))));
+}
+
+// Crash at the given point of a commit, recover and make another commit. The root must match
+// that of a database which made the same commits, with or without the interrupted one.
+fn crash_during_commit(name: &str, mode: PanicOnSyncMode, survives: bool) -> Nomt<Blake3Hasher> {
+    let path = fresh(name);
+    let nomt = open(&path, None);
+    base_commits(&nomt);
+    drop(nomt);
+
+    let nomt = open(&path, Some(mode));
+    let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| interrupted_commit(&nomt)));
+    assert!(r.is_err());
+    drop(nomt);
+
+    let nomt = open(&path, None);
+    next_commit(&nomt);
+
+    let mut o = Options::new();
+    o.path(fresh(&format!("{}_reference", name)));
+    o.hashtable_buckets(10_000);
+    let reference = Nomt::<Blake3Hasher>::open(o).unwrap();
+    base_commits(&reference);
+    if survives {
+        interrupted_commit(&reference);
+    }
+    next_commit(&reference);
+    assert_eq!(nomt.root(), reference.root());
+
+    nomt
+}
+
+#[test]
+fn crash_before_meta_keeps_last_commit() {
+    let nomt = crash_during_commit("shadow_paging_pre_meta", PanicOnSyncMode::PostWal, false);
+    assert_eq!(read(&nomt, 0), Some(vec![5; 8]));
+    assert_eq!(read(&nomt, 1), Some(vec![2; 8]));
+    assert_eq!(read(&nomt, 502), Some(vec![1; 8]));
+    assert_eq!(read(&nomt, 1001), None);
+}
+
+#[test]
+fn crash_after_meta_keeps_new_commit() {
+    let nomt = crash_during_commit("shadow_paging_post_meta", PanicOnSyncMode::PostMeta, true);
+    assert_eq!(read(&nomt, 0), Some(vec![5; 8]));
+    assert_eq!(read(&nomt, 1), None);
+    assert_eq!(read(&nomt, 2), Some(vec![3; 8]));
+    assert_eq!(read(&nomt, 503), Some(vec![1; 8]));
+    assert_eq!(read(&nomt, 1001), Some(vec![3; 8]));
+}
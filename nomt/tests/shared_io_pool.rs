@@ -0,0 +1,69 @@
+mod common;
+
+use nomt::{
+    hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, RetryPolicy, SessionParams, SharedIoPool,
+};
+use std::path::PathBuf;
+
+fn open(name: &str, pool: &SharedIoPool, weight: u32) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(1);
+    o.bitbox_seed([0; 16]);
+    o.hashtable_buckets(10_000);
+    o.io_pool(pool.clone(), weight);
+    Nomt::open(o).unwrap()
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, ids: std::ops::Range<u64>) {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals: Vec<_> = ids
+        .map(|id| {
+            (
+                common::account_path(id),
+                KeyReadWrite::Write(Some(id.to_le_bytes().to_vec())),
+            )
+        })
+        .collect();
+    actuals.sort_by_key(|(k, _)| *k);
+    session.finish(actuals).unwrap().commit(nomt).unwrap();
+}
+
+#[test]
+fn instances_share_io_pool() {
+    let pool = SharedIoPool::new(2, RetryPolicy::default());
+    let a = open("shared_io_pool_a", &pool, 2);
+    let b = open("shared_io_pool_b", &pool, 1);
+    let health = a.io_health();
+    assert_eq!(health.workers, 2);
+    assert_eq!(health.restarts, 0);
+    assert!(health.is_healthy());
+    assert_eq!(b.io_health(), health);
+
+    std::thread::scope(|s| {
+        s.spawn(|| commit(&a, 0..2000));
+        s.spawn(|| commit(&b, 1000..3000));
+    });
+    assert_eq!(
+        a.read(common::account_path(1)).unwrap(),
+        Some(1u64.to_le_bytes().to_vec())
+    );
+    assert_eq!(a.read(common::account_path(2500)).unwrap(), None);
+    assert_eq!(
+        b.read(common::account_path(2500)).unwrap(),
+        Some(2500u64.to_le_bytes().to_vec())
+    );
+
+    // the other instance keeps working once one is closed, and the pool is dropped.
+    drop(a);
+    drop(pool);
+    commit(&b, 3000..4000);
+    assert_eq!(
+        b.read(common::account_path(3500)).unwrap(),
+        Some(3500u64.to_le_bytes().to_vec())
+    );
+}
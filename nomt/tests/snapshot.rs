@@ -0,0 +1,71 @@
+mod common;
+
+use common::Test;
+use nomt::{manifest::Manifest, snapshot::Snapshot};
+use std::collections::BTreeMap;
+
+#[test]
+fn snapshot_lookups() {
+    let mut t = Test::new("snapshot_lookups");
+
+    let mut expected = BTreeMap::new();
+    for id in 0..5000u64 {
+        let value = vec![id as u8; (id % 300) as usize + 1];
+        t.write_id(id, Some(value.clone()));
+        expected.insert(common::account_path(id), value);
+    }
+    // values larger than a page span several pages of the snapshot.
+    let large = vec![7; 4096 * 20];
+    t.write_id(5000, Some(large.clone()));
+    expected.insert(common::account_path(5000), large);
+    let (root, _) = t.commit();
+
+    let path = "test/snapshot_lookups.snapshot";
+    assert_eq!(t.export_snapshot(path), expected.len() as u64);
+    drop(t);
+
+    let snapshot = Snapshot::open(path).unwrap();
+    assert_eq!(snapshot.root(), root);
+    assert_eq!(snapshot.len(), expected.len() as u64);
+
+    let manifest = Manifest::read(nomt::manifest::manifest_path(path)).unwrap();
+    assert_eq!(manifest.root(), root);
+    let data = std::fs::read(path).unwrap();
+    for (index, chunk) in data.chunks(manifest.chunk_size() as usize).enumerate() {
+        assert!(manifest.verify_chunk(index, chunk));
+    }
+
+    for (key, value) in &expected {
+        assert_eq!(snapshot.get(key), Some(&value[..]));
+    }
+    assert_eq!(snapshot.get(&common::account_path(5001)), None);
+
+    let entries: Vec<_> = snapshot.iter().map(|(k, v)| (*k, v.to_vec())).collect();
+    assert_eq!(entries, expected.clone().into_iter().collect::<Vec<_>>());
+
+    let start = common::account_path(1234);
+    let from: Vec<_> = snapshot.iter_from(&start).map(|(k, _)| *k).collect();
+    let expected_from: Vec<_> = expected.range(start..).map(|(k, _)| *k).collect();
+    assert_eq!(from, expected_from);
+}
+
+#[test]
+fn empty_snapshot() {
+    let t = Test::new("snapshot_empty");
+    let path = "test/snapshot_empty.snapshot";
+    assert_eq!(t.export_snapshot(path), 0);
+
+    let snapshot = Snapshot::open(path).unwrap();
+    assert!(snapshot.is_empty());
+    assert!(snapshot.root().is_empty());
+    assert_eq!(snapshot.get(&[0; 32]), None);
+    assert_eq!(snapshot.iter().count(), 0);
+}
+
+#[test]
+fn rejects_other_files() {
+    let path = "test/snapshot_rejects.dump";
+    let t = Test::new("snapshot_rejects");
+    t.export(path, nomt::dump::Compression::None);
+    assert!(Snapshot::open(path).is_err());
+}
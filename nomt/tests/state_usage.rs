@@ -0,0 +1,150 @@
+use bitvec::prelude::*;
+use nomt::{
+    hasher::Blake3Hasher, trie::KeyPath, KeyReadWrite, Nomt, Options, SessionParams, StateUsage,
+    StateUsageDelta,
+};
+use std::path::PathBuf;
+
+fn open(name: &str, reset: bool, prefix_len: Option<u8>) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if reset && path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    if let Some(prefix_len) = prefix_len {
+        o.state_usage(prefix_len);
+        o.state_usage_history(4);
+    }
+    Nomt::open(o).unwrap()
+}
+
+// The key of a slot of an account, the account being the first byte.
+fn key(account: u8, slot: u8) -> KeyPath {
+    let mut key = [0; 32];
+    key[0] = account;
+    key[31] = slot;
+    key
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, changes: Vec<(KeyPath, Option<Vec<u8>>)>) -> u64 {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals = changes
+        .into_iter()
+        .map(|(key, value)| (key, KeyReadWrite::Write(value)))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(k, _)| *k);
+    session.finish(actuals).unwrap().commit(nomt).unwrap()
+}
+
+fn account(account: u8) -> BitVec<u8, Msb0> {
+    BitVec::from_slice(&[account])
+}
+
+fn usage(keys: u64, value_bytes: u64) -> StateUsage {
+    StateUsage { keys, value_bytes }
+}
+
+#[test]
+fn usage_is_tracked_per_prefix() {
+    let nomt = open("state_usage_prefixes", true, Some(8));
+    let first = commit(
+        &nomt,
+        vec![
+            (key(1, 0), Some(vec![0; 3])),
+            (key(1, 1), Some(vec![0; 5])),
+            (key(2, 0), Some(vec![0; 10])),
+        ],
+    );
+    assert_eq!(nomt.state_usage(&account(1)).unwrap(), usage(2, 8));
+    assert_eq!(nomt.state_usage(&account(2)).unwrap(), usage(1, 10));
+    assert_eq!(nomt.state_usage(&account(3)).unwrap(), usage(0, 0));
+    assert_eq!(nomt.state_usage(bits![u8, Msb0;]).unwrap(), usage(3, 18));
+    assert_eq!(
+        nomt.state_usage(bits![u8, Msb0; 0, 0, 0, 0, 0, 0]).unwrap(),
+        usage(3, 18)
+    );
+    assert!(nomt
+        .state_usage(&BitVec::<u8, Msb0>::repeat(false, 9))
+        .is_err());
+
+    commit(
+        &nomt,
+        vec![
+            (key(1, 0), Some(vec![0; 7])),
+            (key(2, 0), None),
+            (key(2, 1), None),
+        ],
+    );
+    assert_eq!(nomt.state_usage(&account(1)).unwrap(), usage(2, 12));
+    assert_eq!(nomt.state_usage(&account(2)).unwrap(), usage(0, 0));
+
+    let delta = nomt.state_usage_since(&account(1), first).unwrap();
+    assert_eq!(
+        delta,
+        StateUsageDelta {
+            keys_added: 0,
+            keys_removed: 0,
+            bytes_added: 7,
+            bytes_removed: 3,
+        }
+    );
+    let delta = nomt.state_usage_since(bits![u8, Msb0;], 0).unwrap();
+    assert_eq!((delta.net_keys(), delta.net_bytes()), (2, 12));
+    assert_eq!(delta.keys_removed, 1);
+}
+
+#[test]
+fn usage_survives_reopening() {
+    let nomt = open("state_usage_reopen", true, Some(8));
+    // enough commits to drop old changes and compact the file.
+    for i in 0..30u8 {
+        commit(&nomt, vec![(key(i % 3, i), Some(vec![0; i as usize + 1]))]);
+    }
+    let last = nomt.current_sequence();
+    assert!(nomt.state_usage_since(&account(0), last - 5).is_err());
+    let usages = (0..3)
+        .map(|a| nomt.state_usage(&account(a)).unwrap())
+        .collect::<Vec<_>>();
+    let delta = nomt.state_usage_since(&account(1), last - 4).unwrap();
+    assert_eq!(usages[1], usage(10, (2..=30).step_by(3).sum()));
+    drop(nomt);
+
+    let nomt = open("state_usage_reopen", false, Some(8));
+    for a in 0..3 {
+        assert_eq!(nomt.state_usage(&account(a)).unwrap(), usages[a as usize]);
+    }
+    assert_eq!(
+        nomt.state_usage_since(&account(1), last - 4).unwrap(),
+        delta
+    );
+
+    // changing the prefix length rebuilds the accounting.
+    drop(nomt);
+    let nomt = open("state_usage_reopen", false, Some(4));
+    assert_eq!(
+        nomt.state_usage(bits![u8, Msb0; 0, 0, 0, 0]).unwrap(),
+        usage(30, (1..=30).sum())
+    );
+    assert!(nomt.state_usage(&account(1)).is_err());
+    assert!(nomt.state_usage_since(bits![u8, Msb0;], last - 1).is_err());
+    assert_eq!(
+        nomt.state_usage_since(bits![u8, Msb0;], last).unwrap(),
+        StateUsageDelta::default()
+    );
+}
+
+#[test]
+fn enabling_usage_tracking_scans_existing_keys() {
+    let nomt = open("state_usage_enable", true, None);
+    commit(
+        &nomt,
+        vec![(key(1, 0), Some(vec![0; 4])), (key(2, 0), Some(vec![0; 6]))],
+    );
+    assert!(nomt.state_usage(bits![u8, Msb0;]).is_err());
+    drop(nomt);
+
+    let nomt = open("state_usage_enable", false, Some(16));
+    assert_eq!(nomt.state_usage(&account(1)).unwrap(), usage(1, 4));
+    assert_eq!(nomt.state_usage(&account(2)).unwrap(), usage(1, 6));
+}
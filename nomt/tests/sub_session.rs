@@ -0,0 +1,125 @@
+mod common;
+
+use nomt::{
+    hasher::Blake3Hasher, Conflict, ConflictKind, KeyReadWrite, Nomt, Options, SessionParams,
+};
+use std::path::PathBuf;
+
+fn open(name: &str) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(1);
+    o.bitbox_seed([0; 16]);
+    let nomt = Nomt::open(o).unwrap();
+
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals = (0..10)
+        .map(|id| {
+            (
+                common::account_path(id),
+                KeyReadWrite::Write(Some(vec![id as u8])),
+            )
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(k, _)| *k);
+    session.finish(actuals).unwrap().commit(&nomt).unwrap();
+    nomt
+}
+
+#[test]
+fn independent_sub_sessions_merge() {
+    let nomt = open("sub_session_merge");
+    let session = nomt.begin_session(SessionParams::default());
+
+    // each sub-session moves the value of one key to another, in parallel.
+    let sub_sessions = std::thread::scope(|scope| {
+        let handles = (0..5)
+            .map(|i| {
+                let mut sub = session.sub_session();
+                scope.spawn(move || {
+                    let value = sub.read(common::account_path(i)).unwrap();
+                    sub.write(common::account_path(i), None);
+                    sub.write(common::account_path(100 + i), value);
+                    // a sub-session reads its own writes.
+                    assert_eq!(sub.read(common::account_path(i)).unwrap(), None);
+                    sub
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    let actuals = session.merge(sub_sessions).unwrap();
+    assert_eq!(actuals.len(), 10);
+    session.finish(actuals).unwrap().commit(&nomt).unwrap();
+
+    for i in 0..5 {
+        assert_eq!(nomt.read(common::account_path(i)).unwrap(), None);
+        assert_eq!(
+            nomt.read(common::account_path(100 + i)).unwrap(),
+            Some(vec![i as u8])
+        );
+    }
+}
+
+#[test]
+fn conflicts_are_detected() {
+    let nomt = open("sub_session_conflicts");
+    let session = nomt.begin_session(SessionParams::default());
+    let (a, b, c) = (
+        common::account_path(0),
+        common::account_path(1),
+        common::account_path(2),
+    );
+
+    let mut first = session.sub_session();
+    first.read(a).unwrap();
+    first.write(b, Some(vec![1]));
+
+    // reading a key before a later sub-session writes it is fine.
+    let mut second = session.sub_session();
+    second.write(a, Some(vec![2]));
+    second.read(c).unwrap();
+
+    // reading a key written by an earlier sub-session isn't.
+    let mut third = session.sub_session();
+    third.read(b).unwrap();
+    third.write(a, Some(vec![3]));
+
+    let err = session.merge([first, second, third]).unwrap_err();
+    let mut expected = vec![
+        Conflict {
+            key: a,
+            kind: ConflictKind::WriteWrite,
+            earlier: 1,
+            later: 2,
+        },
+        Conflict {
+            key: b,
+            kind: ConflictKind::ReadWrite,
+            earlier: 0,
+            later: 2,
+        },
+    ];
+    expected.sort_by_key(|c| c.key);
+    assert_eq!(err.conflicts, expected);
+
+    // without the third, the first two merge into a read-then-write of `a`.
+    let mut first = session.sub_session();
+    first.read(a).unwrap();
+    let mut second = session.sub_session();
+    second.write(a, Some(vec![2]));
+    let actuals = session.merge([first, second]).unwrap();
+    assert!(matches!(
+        actuals[..],
+        [(key, KeyReadWrite::ReadThenWrite(Some(ref old), Some(ref new)))]
+            if key == a && old == &[0] && new == &[2]
+    ));
+}
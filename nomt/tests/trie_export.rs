@@ -0,0 +1,150 @@
+use bitvec::prelude::*;
+use nomt::{
+    hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, SessionParams, TrieExportFormat,
+    MAX_TRIE_EXPORT_DEPTH,
+};
+use std::path::PathBuf;
+
+fn open(name: &str) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    Nomt::open(o).unwrap()
+}
+
+fn key(first: u8) -> [u8; 32] {
+    let mut key = [0; 32];
+    key[0] = first;
+    key
+}
+
+// a trie with the leaves `00`, `01` and `1`.
+fn populate(nomt: &Nomt<Blake3Hasher>) {
+    let actuals = [0x00, 0x40, 0x80]
+        .into_iter()
+        .map(|first| (key(first), KeyReadWrite::Write(Some(vec![first]))))
+        .collect();
+    nomt.begin_session(SessionParams::default())
+        .finish(actuals)
+        .unwrap()
+        .commit(nomt)
+        .unwrap();
+}
+
+#[test]
+fn exports_json() {
+    let nomt = open("trie_export_json");
+    populate(&nomt);
+
+    let json = nomt
+        .export_trie(BitSlice::empty(), 4, TrieExportFormat::Json)
+        .unwrap();
+    let root_hash: String = nomt
+        .root()
+        .into_inner()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    assert!(json.starts_with(&format!(
+        "{{\"root\":\"{}\",\"prefix\":\"\",\"depth\":4,\"pages\":[{{\"id\":\"root\",\"depth\":0}}],",
+        root_hash
+    )));
+    assert!(json.contains("{\"path\":\"root\",\"depth\":0,\"kind\":\"internal\""));
+    assert!(json.contains("\"page\":null,\"index\":null}"));
+    assert!(json.contains("{\"path\":\"0\",\"depth\":1,\"kind\":\"internal\""));
+    let leaf_key = |first: u8| {
+        key(first)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    };
+    for (path, first) in [("1", 0x80), ("00", 0x00), ("01", 0x40)] {
+        let start = json.find(&format!("{{\"path\":\"{}\",", path)).unwrap();
+        let node = &json[start..start + json[start..].find('}').unwrap()];
+        assert!(node.contains("\"kind\":\"leaf\""), "{}", node);
+        assert!(node.contains("\"page\":\"root\""), "{}", node);
+        assert!(
+            node.ends_with(&format!("\"key_path\":\"{}\"", leaf_key(first))),
+            "{}",
+            node
+        );
+    }
+    // leaves aren't expanded.
+    assert_eq!(json.matches("\"path\"").count(), 5);
+
+    // a region below the root.
+    let json = nomt
+        .export_trie(bits![u8, Msb0; 0], 1, TrieExportFormat::Json)
+        .unwrap();
+    assert_eq!(json.matches("\"path\"").count(), 3);
+    assert!(json.contains("\"prefix\":\"0\""));
+    assert!(!json.contains("\"path\":\"root\""));
+}
+
+#[test]
+fn exports_dot() {
+    let nomt = open("trie_export_dot");
+    populate(&nomt);
+
+    let dot = nomt
+        .export_trie(BitSlice::empty(), 2, TrieExportFormat::Dot)
+        .unwrap();
+    assert!(dot.starts_with("digraph trie {\n"));
+    assert!(dot.ends_with("}\n"));
+    assert!(dot.contains("label=\"page root\";"));
+    assert!(dot.contains("\"root\" -> \"0\" [label=\"0\"];"));
+    assert!(dot.contains("\"root\" -> \"1\" [label=\"1\"];"));
+    assert!(dot.contains("\"0\" -> \"01\" [label=\"1\"];"));
+}
+
+#[test]
+fn exports_page_boundaries() {
+    let nomt = open("trie_export_pages");
+    // every 8-bit prefix has a single key, so the trie is complete down to depth 8.
+    let actuals = (0..=255u8)
+        .map(|i| ([i; 32], KeyReadWrite::Write(Some(vec![i]))))
+        .collect();
+    nomt.begin_session(SessionParams::default())
+        .finish(actuals)
+        .unwrap()
+        .commit(&nomt)
+        .unwrap();
+
+    let dot = nomt
+        .export_trie(BitSlice::empty(), 8, TrieExportFormat::Dot)
+        .unwrap();
+    // the root page and its 64 children.
+    assert_eq!(dot.matches("subgraph cluster_").count(), 65);
+    assert!(dot.contains("label=\"page 63\";"));
+    assert_eq!(dot.matches("leaf ").count(), 256);
+
+    // a node in the last layer of the root page has its children in a child page.
+    let json = nomt
+        .export_trie(bits![u8, Msb0; 1, 1, 1, 1, 1, 1], 1, TrieExportFormat::Json)
+        .unwrap();
+    assert!(json.contains("\"pages\":[{\"id\":\"root\",\"depth\":0},{\"id\":\"63\",\"depth\":1}]"));
+    assert!(json.contains("{\"path\":\"1111110\",\"depth\":7,\"kind\":\"internal\""));
+    assert!(json.contains("\"page\":\"63\",\"index\":0}"));
+}
+
+#[test]
+fn rejects_invalid_regions() {
+    let nomt = open("trie_export_invalid");
+    populate(&nomt);
+
+    assert!(nomt
+        .export_trie(
+            BitSlice::empty(),
+            MAX_TRIE_EXPORT_DEPTH + 1,
+            TrieExportFormat::Json
+        )
+        .is_err());
+    // `1` is a leaf.
+    let err = nomt
+        .export_trie(bits![u8, Msb0; 1, 0], 1, TrieExportFormat::Dot)
+        .unwrap_err();
+    assert!(err.to_string().contains("leaf at depth 1"), "{}", err);
+}
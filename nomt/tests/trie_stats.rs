@@ -0,0 +1,61 @@
+mod common;
+
+use common::Test;
+use nomt::TrieStatsMode;
+
+#[test]
+fn exact_stats() {
+    let mut t = Test::new("trie_stats_exact");
+    let stats = t.trie_stats(TrieStatsMode::Exact);
+    assert_eq!(stats.leaves, 0);
+    assert_eq!(stats.internal_nodes, 0);
+
+    for id in 0..5000u64 {
+        t.write_id(id, Some(id.to_le_bytes().to_vec()));
+    }
+    let _ = t.commit();
+
+    let stats = t.trie_stats(TrieStatsMode::Exact);
+    assert!(stats.exact);
+    assert_eq!(stats.leaves, 5000);
+    assert_eq!(stats.depth_histogram.iter().sum::<u64>(), 5000);
+    assert!(stats.internal_nodes >= 4999);
+    // uniformly distributed keys give a well-balanced trie.
+    let average_depth = stats.average_depth();
+    assert!(
+        average_depth > 10.0 && average_depth < 16.0,
+        "{}",
+        average_depth
+    );
+    assert!(stats.depth_percentile(0.5) <= stats.max_depth());
+    assert!(stats.pages_per_lookup() >= 2.0 && stats.pages_per_lookup() <= 3.0);
+}
+
+#[test]
+fn sampled_stats() {
+    let mut t = Test::new("trie_stats_sampled");
+    for id in 0..100u64 {
+        t.write_id(id, Some(id.to_le_bytes().to_vec()));
+    }
+    let _ = t.commit();
+
+    // small databases are scanned exactly.
+    let stats = t.trie_stats(TrieStatsMode::Sampled(100));
+    assert!(stats.exact);
+    assert_eq!(stats.leaves, 100);
+
+    for id in 100..20000u64 {
+        t.write_id(id, Some(id.to_le_bytes().to_vec()));
+    }
+    let _ = t.commit();
+
+    let exact = t.trie_stats(TrieStatsMode::Exact);
+    let sampled = t.trie_stats(TrieStatsMode::Sampled(100));
+    assert!(!sampled.exact);
+    assert!(
+        sampled.leaves > 10000 && sampled.leaves < 40000,
+        "{}",
+        sampled.leaves
+    );
+    assert!((sampled.average_depth() - exact.average_depth()).abs() < 1.5);
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use nomt::{hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, SessionParams};
+use std::path::PathBuf;
+
+fn open(name: &str) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(1);
+    o.bitbox_seed([0; 16]);
+    Nomt::open(o).unwrap()
+}
+
+fn add(value: Option<Vec<u8>>, amount: u64) -> Option<Vec<u8>> {
+    let balance = value.map_or(0, |v| u64::from_le_bytes(v.try_into().unwrap()));
+    Some((balance + amount).to_le_bytes().to_vec())
+}
+
+#[test]
+fn updates_see_latest_value() {
+    let nomt = open("update_latest");
+
+    let session = nomt.begin_session(SessionParams::default());
+    session
+        .update(common::account_path(1), |v| add(v, 5))
+        .unwrap();
+    session
+        .update(common::account_path(1), |v| add(v, 7))
+        .unwrap();
+    session
+        .update(common::account_path(2), |v| add(v, 1))
+        .unwrap();
+    session
+        .finish(vec![(
+            common::account_path(3),
+            KeyReadWrite::Write(Some(vec![3])),
+        )])
+        .unwrap()
+        .commit(&nomt)
+        .unwrap();
+
+    assert_eq!(nomt.read(common::account_path(1)).unwrap(), add(None, 12));
+    assert_eq!(nomt.read(common::account_path(2)).unwrap(), add(None, 1));
+    assert_eq!(nomt.read(common::account_path(3)).unwrap(), Some(vec![3]));
+
+    // the stored value is seen by the next session, and `None` deletes.
+    let session = nomt.begin_session(SessionParams::default());
+    session
+        .update(common::account_path(1), |v| add(v, 1))
+        .unwrap();
+    session.update(common::account_path(2), |_| None).unwrap();
+    session.finish(vec![]).unwrap().commit(&nomt).unwrap();
+
+    assert_eq!(nomt.read(common::account_path(1)).unwrap(), add(None, 13));
+    assert_eq!(nomt.read(common::account_path(2)).unwrap(), None);
+}
+
+#[test]
+fn concurrent_updates_are_not_lost() {
+    let nomt = open("update_concurrent");
+
+    let session = nomt.begin_session(SessionParams::default());
+    std::thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                for _ in 0..100 {
+                    session
+                        .update(common::account_path(0), |v| add(v, 1))
+                        .unwrap();
+                }
+            });
+        }
+    });
+    session.finish(vec![]).unwrap().commit(&nomt).unwrap();
+
+    assert_eq!(nomt.read(common::account_path(0)).unwrap(), add(None, 800));
+}
+
+#[test]
+fn updated_key_in_actuals_is_rejected() {
+    let nomt = open("update_conflict");
+
+    let session = nomt.begin_session(SessionParams::default());
+    session
+        .update(common::account_path(1), |v| add(v, 1))
+        .unwrap();
+    let res = session.finish(vec![(
+        common::account_path(1),
+        KeyReadWrite::Write(Some(vec![1])),
+    )]);
+    assert!(res.is_err());
+}
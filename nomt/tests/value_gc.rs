@@ -0,0 +1,170 @@
+use nomt::{hasher::Blake3Hasher, trie::KeyPath, KeyReadWrite, Nomt, Options, SessionParams};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+const PAGE_SIZE: usize = 4096;
+const LARGE_VALUE_LEN: usize = 50_000;
+
+fn key(i: u8) -> KeyPath {
+    [i; 32]
+}
+
+fn open(path: &Path, value_gc_interval: Option<Duration>) -> Nomt<Blake3Hasher> {
+    let mut o = Options::new();
+    o.path(path);
+    o.value_gc_interval(value_gc_interval);
+    Nomt::open(o).unwrap()
+}
+
+fn reset(name: &str) -> PathBuf {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    path
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, writes: Vec<(KeyPath, Option<Vec<u8>>)>) {
+    let actuals = writes
+        .into_iter()
+        .map(|(key, value)| (key, KeyReadWrite::Write(value)))
+        .collect();
+    nomt.begin_session(SessionParams::default())
+        .finish(actuals)
+        .unwrap()
+        .commit(nomt)
+        .unwrap();
+}
+
+// Leak pages of the value store by moving its bump forward in every meta slot.
+fn leak_pages(path: &Path, pages: u32) {
+    let meta = path.join("meta");
+    let mut data = std::fs::read(&meta).unwrap();
+    for slot in data.chunks_mut(PAGE_SIZE) {
+        if slot.len() < 456 || slot[448..456] != blake3::hash(&slot[..448]).as_bytes()[..8] {
+            continue;
+        }
+        let bump = u32::from_le_bytes(slot[12..16].try_into().unwrap());
+        slot[12..16].copy_from_slice(&(bump + pages).to_le_bytes());
+        let checksum = blake3::hash(&slot[..448]);
+        slot[448..456].copy_from_slice(&checksum.as_bytes()[..8]);
+    }
+    std::fs::write(&meta, data).unwrap();
+}
+
+#[test]
+fn stats_account_for_values_and_freed_pages() {
+    let path = reset("value_gc_stats");
+    let nomt = open(&path, None);
+    assert_eq!(nomt.collect_value_garbage(false).unwrap().values, 0);
+
+    commit(
+        &nomt,
+        vec![
+            (key(1), Some(vec![1; 10])),
+            (key(2), Some(vec![2; LARGE_VALUE_LEN])),
+            (key(3), Some(vec![3; 20])),
+        ],
+    );
+    let stats = nomt.collect_value_garbage(false).unwrap();
+    assert_eq!(stats.values, 3);
+    assert_eq!(stats.overflow_values, 1);
+    assert_eq!(stats.value_bytes, (LARGE_VALUE_LEN + 30) as u64);
+    assert_eq!(stats.leaf_pages, 1);
+    assert!(stats.overflow_pages * PAGE_SIZE as u64 >= LARGE_VALUE_LEN as u64);
+    assert_eq!(stats.orphaned_pages, 0);
+    let overflow_pages = stats.overflow_pages;
+
+    // the pages of the deleted value are freed, not orphaned.
+    commit(&nomt, vec![(key(2), None)]);
+    let stats = nomt.collect_value_garbage(true).unwrap();
+    assert_eq!(stats.values, 2);
+    assert_eq!(stats.overflow_values, 0);
+    assert_eq!(stats.value_bytes, 30);
+    assert_eq!(stats.orphaned_pages, 0);
+    assert_eq!(stats.reclaiming_pages, 0);
+    assert!(stats.free_pages >= overflow_pages);
+    assert!(stats.reclaimable_bytes() >= overflow_pages * PAGE_SIZE as u64);
+    assert_eq!(
+        stats.allocated_pages,
+        stats.leaf_pages + stats.overflow_pages + stats.free_pages + stats.free_list_pages
+    );
+}
+
+#[test]
+fn orphaned_pages_are_reclaimed() {
+    let path = reset("value_gc_reclaim");
+    let nomt = open(&path, None);
+    commit(&nomt, vec![(key(1), Some(vec![1; 10]))]);
+    drop(nomt);
+    leak_pages(&path, 10);
+
+    let nomt = open(&path, None);
+    let stats = nomt.collect_value_garbage(false).unwrap();
+    assert_eq!(stats.orphaned_pages, 10);
+    assert_eq!(stats.orphaned_bytes(), 10 * PAGE_SIZE as u64);
+    assert_eq!(stats.reclaiming_pages, 0);
+
+    // reclaiming is idempotent until the next commit frees the pages.
+    assert_eq!(
+        nomt.collect_value_garbage(true).unwrap().reclaiming_pages,
+        10
+    );
+    assert_eq!(
+        nomt.collect_value_garbage(true).unwrap().reclaiming_pages,
+        10
+    );
+    commit(&nomt, vec![(key(2), Some(vec![2; 10]))]);
+
+    let stats = nomt.collect_value_garbage(false).unwrap();
+    assert_eq!(stats.orphaned_pages, 0);
+    assert_eq!(stats.reclaiming_pages, 0);
+    assert!(stats.free_pages + stats.free_list_pages >= 10);
+    assert_eq!(stats.values, 2);
+
+    // the freed pages are reused and the values survive reopening.
+    commit(&nomt, vec![(key(3), Some(vec![3; LARGE_VALUE_LEN]))]);
+    drop(nomt);
+    let nomt = open(&path, None);
+    assert_eq!(nomt.read(key(3)).unwrap(), Some(vec![3; LARGE_VALUE_LEN]));
+    assert_eq!(nomt.collect_value_garbage(false).unwrap().orphaned_pages, 0);
+}
+
+#[test]
+fn background_gc_reclaims_orphaned_pages() {
+    let path = reset("value_gc_background");
+    let nomt = open(&path, None);
+    commit(&nomt, vec![(key(1), Some(vec![1; 10]))]);
+    drop(nomt);
+    leak_pages(&path, 4);
+
+    let nomt = open(&path, Some(Duration::from_millis(10)));
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let stats = loop {
+        if let Some(stats) = nomt.value_gc_stats() {
+            break stats;
+        }
+        assert!(Instant::now() < deadline, "no background scan completed");
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    assert_eq!(stats.orphaned_pages, 4);
+    assert_eq!(stats.reclaiming_pages, 4);
+
+    commit(&nomt, vec![(key(2), Some(vec![2; 10]))]);
+    assert_eq!(nomt.collect_value_garbage(false).unwrap().orphaned_pages, 0);
+}
+
+#[test]
+fn read_only_databases_are_only_scanned() {
+    let path = reset("value_gc_read_only");
+    drop(open(&path, None));
+
+    let mut o = Options::new();
+    o.path(&path);
+    o.read_only(true);
+    let nomt = Nomt::<Blake3Hasher>::open(o).unwrap();
+    assert!(nomt.collect_value_garbage(true).is_err());
+    assert_eq!(nomt.collect_value_garbage(false).unwrap().orphaned_pages, 0);
+}
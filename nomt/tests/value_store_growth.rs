@@ -0,0 +1,80 @@
+mod common;
+
+use nomt::{hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, SessionParams};
+use std::path::PathBuf;
+
+const MIB: u64 = 1024 * 1024;
+
+fn open(name: &str, configure: impl FnOnce(&mut Options)) -> (Nomt<Blake3Hasher>, PathBuf) {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(&path);
+    o.hashtable_buckets(10_000);
+    configure(&mut o);
+    (Nomt::open(o).unwrap(), path)
+}
+
+fn commit_values(nomt: &Nomt<Blake3Hasher>, ids: std::ops::Range<u64>, len: usize) {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals = ids
+        .map(|id| {
+            (
+                common::account_path(id),
+                KeyReadWrite::Write(Some(vec![id as u8; len])),
+            )
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(k, _)| *k);
+    session.finish(actuals).unwrap().commit(nomt).unwrap();
+}
+
+#[test]
+fn leaf_file_is_preallocated() {
+    let (nomt, path) = open("values_preallocate", |o| o.values_preallocate_size(64));
+    let ln = std::fs::metadata(path.join("ln")).unwrap();
+    assert_eq!(ln.len(), 64 * MIB);
+
+    // the preallocated space is used before the file grows.
+    commit_values(&nomt, 0..1000, 1000);
+    assert_eq!(std::fs::metadata(path.join("ln")).unwrap().len(), 64 * MIB);
+    drop(nomt);
+
+    // reopening with a smaller size leaves the file as it is.
+    let mut o = Options::new();
+    o.path(&path);
+    o.values_preallocate_size(16);
+    let nomt = Nomt::<Blake3Hasher>::open(o).unwrap();
+    assert_eq!(std::fs::metadata(path.join("ln")).unwrap().len(), 64 * MIB);
+    assert_eq!(
+        nomt.read(common::account_path(5)).unwrap(),
+        Some(vec![5; 1000])
+    );
+}
+
+#[test]
+fn files_grow_by_extents() {
+    let (nomt, path) = open("values_growth_extent", |o| {
+        o.values_growth_extent(1);
+        o.preallocate_values(false);
+    });
+    // values beyond the capacity of a single extent.
+    commit_values(&nomt, 0..1000, 3000);
+
+    let ln = std::fs::metadata(path.join("ln")).unwrap();
+    assert!(ln.len() > MIB);
+    assert_eq!(ln.len() % MIB, 0);
+    let bbn = std::fs::metadata(path.join("bbn")).unwrap();
+    assert_eq!(bbn.len() % MIB, 0);
+}
+
+#[test]
+fn invalid_growth_extent_is_rejected() {
+    let mut o = Options::new();
+    o.values_growth_extent(0);
+    assert!(o.validate().is_err());
+    o.values_growth_extent(8192);
+    assert!(o.validate().is_err());
+}
@@ -0,0 +1,109 @@
+use nomt::{
+    hasher::Blake3Hasher, trie::KeyPath, KeyReadWrite, Nomt, Options, SessionParams,
+    ValueCorruption,
+};
+use std::path::{Path, PathBuf};
+
+const CORRUPTED: KeyPath = [1; 32];
+const INTACT: KeyPath = [2; 32];
+
+fn value(key: &KeyPath) -> Vec<u8> {
+    let mut value = b"value-verify-marker-".repeat(4);
+    value.push(key[0]);
+    value
+}
+
+fn open(path: &Path, verify_values: bool) -> Nomt<Blake3Hasher> {
+    let mut o = Options::new();
+    o.path(path);
+    o.verify_values(verify_values);
+    Nomt::open(o).unwrap()
+}
+
+// Create a database holding both keys and flip a byte of the value of `CORRUPTED` in the value
+// store.
+fn corrupted_db(name: &str) -> PathBuf {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let nomt = open(&path, false);
+    let actuals = [CORRUPTED, INTACT]
+        .into_iter()
+        .map(|key| (key, KeyReadWrite::Write(Some(value(&key)))))
+        .collect();
+    nomt.begin_session(SessionParams::default())
+        .finish(actuals)
+        .unwrap()
+        .commit(&nomt)
+        .unwrap();
+    drop(nomt);
+
+    let ln = path.join("ln");
+    let mut data = std::fs::read(&ln).unwrap();
+    let needle = value(&CORRUPTED);
+    let mut found = 0;
+    for start in 0..data.len() - needle.len() {
+        if data[start..start + needle.len()] == needle[..] {
+            data[start] ^= 0xff;
+            found += 1;
+        }
+    }
+    assert!(found > 0);
+    std::fs::write(&ln, data).unwrap();
+    path
+}
+
+fn corruption(res: anyhow::Result<impl std::fmt::Debug>) -> ValueCorruption {
+    res.unwrap_err()
+        .downcast_ref::<ValueCorruption>()
+        .unwrap()
+        .clone()
+}
+
+#[test]
+fn verified_reads_detect_corruption() {
+    let path = corrupted_db("value_verify_per_read");
+    let nomt = open(&path, false);
+
+    // unverified reads return the corrupted value.
+    assert_ne!(nomt.read(CORRUPTED).unwrap(), Some(value(&CORRUPTED)));
+    let session = nomt.begin_session(SessionParams::default());
+    assert_ne!(session.read(CORRUPTED).unwrap(), Some(value(&CORRUPTED)));
+
+    let err = corruption(session.read_verified(CORRUPTED));
+    assert_eq!(err.key, CORRUPTED);
+    assert_ne!(err.expected, err.found);
+    assert_eq!(session.read_verified(INTACT).unwrap(), Some(value(&INTACT)));
+    assert_eq!(session.read_verified([3; 32]).unwrap(), None);
+    drop(session);
+
+    corruption(nomt.read_verified(CORRUPTED));
+    let read_session = nomt.begin_read_session();
+    corruption(read_session.read_verified(CORRUPTED));
+    assert_eq!(
+        read_session.read_verified(INTACT).unwrap(),
+        Some(value(&INTACT))
+    );
+}
+
+#[test]
+fn global_option_verifies_every_read() {
+    let path = corrupted_db("value_verify_global");
+    let nomt = open(&path, true);
+
+    corruption(nomt.read(CORRUPTED));
+    let session = nomt.begin_session(SessionParams::default());
+    corruption(session.read(CORRUPTED));
+    corruption(session.read_many(&[INTACT, CORRUPTED]));
+    assert_eq!(session.read(INTACT).unwrap(), Some(value(&INTACT)));
+    drop(session);
+
+    let read_session = nomt.begin_read_session();
+    corruption(read_session.read(CORRUPTED));
+    corruption(read_session.read_many(&[CORRUPTED]));
+    assert_eq!(
+        read_session.read_many(&[INTACT, [3; 32]]).unwrap(),
+        vec![Some(value(&INTACT)), None]
+    );
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use nomt::{hasher::Blake3Hasher, KeyReadWrite, Nomt, Options, Overlay, Root, SessionParams};
+use std::path::PathBuf;
+
+fn open(name: &str, verify_commits: bool) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(1);
+    o.bitbox_seed([0; 16]);
+    o.verify_commits(verify_commits);
+    Nomt::open(o).unwrap()
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, writes: &[(u64, Option<Vec<u8>>)]) -> Root {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals: Vec<_> = writes
+        .iter()
+        .map(|(id, value)| {
+            (
+                common::account_path(*id),
+                KeyReadWrite::Write(value.clone()),
+            )
+        })
+        .collect();
+    actuals.sort_by_key(|(k, _)| *k);
+    let finished = session.finish(actuals).unwrap();
+    let root = finished.root();
+    finished.commit(nomt).unwrap();
+    root
+}
+
+#[test]
+fn verified_commits_match_unverified() {
+    let verified = open("verify_commits_on", true);
+    let unverified = open("verify_commits_off", false);
+
+    let batches: Vec<Vec<(u64, Option<Vec<u8>>)>> = vec![
+        vec![(0, Some(vec![1]))],
+        (0..1000u64)
+            .map(|id| (id, Some(id.to_le_bytes().to_vec())))
+            .collect(),
+        (0..1000).step_by(3).map(|id| (id, None)).collect(),
+        (500..1500).map(|id| (id, Some(vec![2; 64]))).collect(),
+        (0..1500).map(|id| (id, None)).collect(),
+        vec![(42, Some(vec![3]))],
+    ];
+
+    for writes in &batches {
+        let root = commit(&verified, writes);
+        assert_eq!(root, commit(&unverified, writes));
+        assert_eq!(verified.root(), root);
+    }
+}
+
+#[test]
+fn verified_overlay_commits() {
+    let nomt = open("verify_commits_overlays", true);
+
+    let overlay = |parent: Option<&Overlay>, ids: std::ops::Range<u64>| {
+        let params = SessionParams::default().overlay(parent).unwrap();
+        let session = nomt.begin_session(params);
+        let actuals = ids
+            .map(|id| (common::account_path(id), KeyReadWrite::Write(Some(vec![1]))))
+            .collect::<std::collections::BTreeMap<_, _>>()
+            .into_iter()
+            .collect();
+        session.finish(actuals).unwrap().into_overlay()
+    };
+
+    // the second overlay builds on pages created fresh by the first.
+    let first = overlay(None, 0..500);
+    let second = overlay(Some(&first), 500..1000);
+    let root = second.root();
+    first.commit(&nomt).unwrap();
+    second.commit(&nomt).unwrap();
+    assert_eq!(nomt.root(), root);
+}
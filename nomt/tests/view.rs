@@ -0,0 +1,141 @@
+mod common;
+
+use nomt::{hasher::Blake3Hasher, trie::KeyPath, KeyReadWrite, Nomt, Options, Root, SessionParams};
+use std::path::PathBuf;
+
+fn open(name: &str, rollback: bool) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    o.rollback(rollback);
+    Nomt::open(o).unwrap()
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, writes: impl IntoIterator<Item = (u64, Option<Vec<u8>>)>) {
+    let session = nomt.begin_session(SessionParams::default());
+    let mut actuals = writes
+        .into_iter()
+        .map(|(id, value)| (common::account_path(id), KeyReadWrite::Write(value)))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(k, _)| *k);
+    session.finish(actuals).unwrap().commit(nomt).unwrap();
+}
+
+// Make the first `n` of three commits and return the root after each.
+fn make_commits(nomt: &Nomt<Blake3Hasher>, n: usize) -> Vec<Root> {
+    let commits: [Vec<(u64, Option<Vec<u8>>)>; 3] = [
+        (0..100).map(|id| (id, Some(vec![1; 8]))).collect(),
+        (0..50)
+            .map(|id| (id, Some(vec![2; 8])))
+            .chain((80..100).map(|id| (id, None)))
+            .collect(),
+        (0..10)
+            .map(|id| (id, None))
+            .chain((100..120).map(|id| (id, Some(vec![3; 8]))))
+            .collect(),
+    ];
+    commits
+        .into_iter()
+        .take(n)
+        .map(|writes| {
+            commit(nomt, writes);
+            nomt.root()
+        })
+        .collect()
+}
+
+fn all_entries<E: std::fmt::Debug>(
+    iter: impl Iterator<Item = Result<(KeyPath, Vec<u8>), E>>,
+) -> Vec<(KeyPath, Vec<u8>)> {
+    iter.map(|entry| entry.unwrap()).collect()
+}
+
+#[test]
+fn view_reads_historical_state() {
+    let nomt = open("view_reads", true);
+    let roots = make_commits(&nomt, 3);
+
+    let view = nomt.view_at(roots[0]).unwrap();
+    assert_eq!(view.root(), roots[0]);
+    let read = |id| view.read(common::account_path(id)).unwrap();
+    assert_eq!(read(5), Some(vec![1; 8]));
+    assert_eq!(read(60), Some(vec![1; 8]));
+    assert_eq!(read(90), Some(vec![1; 8]));
+    assert_eq!(read(110), None);
+    drop(view);
+
+    let view = nomt.view_at(roots[1]).unwrap();
+    assert_eq!(
+        view.read(common::account_path(5)).unwrap(),
+        Some(vec![2; 8])
+    );
+    assert_eq!(view.read(common::account_path(90)).unwrap(), None);
+    drop(view);
+
+    // the current root needs no rollback.
+    let view = nomt.view_at(roots[2]).unwrap();
+    assert_eq!(view.read(common::account_path(5)).unwrap(), None);
+    assert_eq!(
+        view.read(common::account_path(110)).unwrap(),
+        Some(vec![3; 8])
+    );
+    drop(view);
+
+    // the database itself is unchanged.
+    assert_eq!(nomt.root(), roots[2]);
+    assert_eq!(nomt.read(common::account_path(5)).unwrap(), None);
+    assert!(nomt.view_at(Root::from([7; 32])).is_err());
+}
+
+#[test]
+fn view_iterates_and_proves_historical_state() {
+    let nomt = open("view_iter", true);
+    let roots = make_commits(&nomt, 3);
+
+    for n in 1..=3 {
+        // a database where only the first `n` commits were made.
+        let reference = open(&format!("view_iter_reference_{}", n), false);
+        make_commits(&reference, n);
+        let expected = all_entries(
+            reference
+                .begin_read_session()
+                .iter_range(KeyPath::default(), None),
+        );
+
+        let view = nomt.view_at(roots[n - 1]).unwrap();
+        assert_eq!(
+            all_entries(view.iter_range(KeyPath::default(), None)),
+            expected
+        );
+        let (start, end) = (expected[10].0, Some(expected[20].0));
+        assert_eq!(
+            all_entries(view.iter_range(start, end)),
+            expected[10..20].to_vec()
+        );
+
+        let keys = [0, 5, 20, 60, 90, 110].map(common::account_path);
+        let witness = view.prove(keys).unwrap();
+        assert_eq!(witness.prev_root, roots[n - 1]);
+        witness.builder::<Blake3Hasher>().unwrap();
+        for (key, value) in &witness.values {
+            assert_eq!(*value, reference.read(*key).unwrap());
+        }
+    }
+}
+
+#[test]
+fn view_requires_rollback() {
+    let nomt = open("view_no_rollback", false);
+    let roots = make_commits(&nomt, 2);
+    assert!(nomt.view_at(roots[0]).is_err());
+    assert_eq!(
+        nomt.view_at(roots[1])
+            .unwrap()
+            .read(common::account_path(5))
+            .unwrap(),
+        Some(vec![2; 8])
+    );
+}
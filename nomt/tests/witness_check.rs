@@ -1,7 +1,11 @@
 mod common;
 
 use common::Test;
-use nomt::{hasher::Blake3Hasher, proof, trie::LeafData};
+use nomt::{
+    hasher::Blake3Hasher, proof, trie::LeafData, KeyReadWrite, Nomt, Options, SessionParams,
+    WitnessMode,
+};
+use std::path::PathBuf;
 
 #[test]
 fn produced_witness_validity() {
@@ -92,3 +96,54 @@ fn produced_witness_validity() {
         new_root.into_inner(),
     );
 }
+
+#[test]
+fn witness_size_is_reported() {
+    let path = PathBuf::from("test/witness_size");
+    let _ = std::fs::remove_dir_all(&path);
+    let mut o = Options::new();
+    o.path(path);
+    o.commit_concurrency(2);
+    o.bitbox_seed([0; 16]);
+    let nomt = Nomt::<Blake3Hasher>::open(o).unwrap();
+
+    let writes = |ids: std::ops::Range<u64>| {
+        let mut actuals = ids
+            .map(|id| {
+                (
+                    common::account_path(id),
+                    KeyReadWrite::Write(Some(id.to_le_bytes().to_vec())),
+                )
+            })
+            .collect::<Vec<_>>();
+        actuals.sort_by_key(|(k, _)| *k);
+        actuals
+    };
+
+    let session = nomt.begin_session(SessionParams::default());
+    let finished = session.finish(writes(0..100)).unwrap();
+    assert_eq!(finished.stats().witness_size, None);
+    finished.commit(&nomt).unwrap();
+
+    let session =
+        nomt.begin_session(SessionParams::default().witness_mode(WitnessMode::read_write()));
+    let mut finished = session.finish(writes(50..60)).unwrap();
+    let witness_size = finished.stats().witness_size.unwrap();
+
+    // the paths of different workers aren't in order.
+    let witness = finished.take_witness().unwrap();
+    let mut path_proofs = witness
+        .path_proofs
+        .into_iter()
+        .map(|p| p.inner)
+        .collect::<Vec<_>>();
+    path_proofs.sort_by(|a, b| a.terminal.path().cmp(b.terminal.path()));
+    path_proofs.dedup_by(|a, b| a.terminal.path() == b.terminal.path());
+    let multi_proof = proof::MultiProof::from_path_proofs(path_proofs);
+    let encoded = proof::compact::encode_multi_proof(&multi_proof);
+    assert_eq!(encoded.len(), witness_size);
+    assert_eq!(
+        proof::compact::decode_multi_proof(&encoded).unwrap(),
+        multi_proof
+    );
+}
@@ -0,0 +1,104 @@
+use nomt::{
+    hasher::Blake3Hasher, CommitLimit, CommitLimits, KeyReadWrite, Nomt, Options, SessionParams,
+    WitnessMode, WriteBatch, WriteBatchError, MAX_VALUE_SIZE,
+};
+use std::path::PathBuf;
+
+fn open(name: &str) -> Nomt<Blake3Hasher> {
+    let path = PathBuf::from("test").join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    let mut o = Options::new();
+    o.path(path);
+    Nomt::open(o).unwrap()
+}
+
+#[test]
+fn batch_is_committed() {
+    let nomt = open("write_batch_commit");
+    let mut batch = WriteBatch::new();
+    batch.put([3; 32], vec![3]).unwrap();
+    batch.put([1; 32], vec![1, 1]).unwrap();
+    batch.put([2; 32], vec![2]).unwrap();
+    nomt.begin_session(SessionParams::default())
+        .finish_batch(batch)
+        .unwrap()
+        .commit(&nomt)
+        .unwrap();
+
+    let mut batch = WriteBatch::new();
+    batch.read([1; 32], Some(vec![1, 1])).unwrap();
+    batch.delete([2; 32]).unwrap();
+    batch.put([1; 32], vec![10]).unwrap();
+    // writing the same value twice is allowed.
+    batch.delete([2; 32]).unwrap();
+    assert_eq!(batch.len(), 2);
+    assert_eq!(batch.writes(), 2);
+    assert_eq!(batch.value_bytes(), 1);
+    assert!(matches!(
+        batch.get(&[1; 32]),
+        Some(KeyReadWrite::ReadThenWrite(Some(prior), Some(value))) if prior == &vec![1, 1] && value == &vec![10]
+    ));
+
+    let session =
+        nomt.begin_session(SessionParams::default().witness_mode(WitnessMode::read_write()));
+    let mut finished = session.finish_batch(batch).unwrap();
+    assert!(finished.take_witness().is_some());
+    finished.commit(&nomt).unwrap();
+    assert_eq!(nomt.read([1; 32]).unwrap(), Some(vec![10]));
+    assert_eq!(nomt.read([2; 32]).unwrap(), None);
+    assert_eq!(nomt.read([3; 32]).unwrap(), Some(vec![3]));
+}
+
+#[test]
+fn misuse_is_rejected_when_added() {
+    let mut batch = WriteBatch::new().with_limits(CommitLimits {
+        max_keys: Some(2),
+        max_value_bytes: Some(10),
+        ..CommitLimits::default()
+    });
+    batch.put([1; 32], vec![1]).unwrap();
+    assert_eq!(
+        batch.put([1; 32], vec![2]),
+        Err(WriteBatchError::ConflictingWrite([1; 32]))
+    );
+    assert_eq!(
+        batch.delete([1; 32]),
+        Err(WriteBatchError::ConflictingWrite([1; 32]))
+    );
+    assert_eq!(
+        batch.put([2; 32], vec![0; MAX_VALUE_SIZE + 1]),
+        Err(WriteBatchError::ValueTooLarge {
+            key: [2; 32],
+            len: MAX_VALUE_SIZE + 1,
+        })
+    );
+
+    batch.read([3; 32], None).unwrap();
+    assert_eq!(
+        batch.read([3; 32], Some(vec![3])),
+        Err(WriteBatchError::ConflictingRead([3; 32]))
+    );
+
+    let err = batch.put([2; 32], vec![0; 10]).unwrap_err();
+    assert!(matches!(
+        err,
+        WriteBatchError::LimitExceeded(e) if e.limit == CommitLimit::ValueBytes && e.actual == 11
+    ));
+    batch.put([2; 32], vec![0; 9]).unwrap();
+    let err = batch.delete([3; 32]).unwrap_err();
+    assert!(matches!(
+        err,
+        WriteBatchError::LimitExceeded(e) if e.limit == CommitLimit::Keys && e.actual == 3
+    ));
+
+    // failed additions leave the batch as it was.
+    assert_eq!(batch.len(), 3);
+    assert_eq!(batch.writes(), 2);
+    assert_eq!(batch.value_bytes(), 10);
+    assert!(matches!(
+        batch.get(&[3; 32]),
+        Some(KeyReadWrite::Read(None))
+    ));
+}
@@ -0,0 +1,284 @@
+//! A JSON-RPC 2.0 server backed by a NOMT database.
+//!
+//! The server speaks newline-delimited JSON-RPC over TCP: every line received is a request and
+//! every line sent is a response. Keys, values and hashes are hex-encoded with a `0x` prefix.
+//!
+//! Methods:
+//!   - `nomt_get(key)`: the value stored under `key`, or `null`.
+//!   - `nomt_multiGet([key, ..])`: the values stored under each key, or `null`.
+//!   - `nomt_prove(key)`: a path proof for `key` against the current root.
+//!   - `nomt_commitMetadata()`: the current root and sync sequence number.
+//!   - `nomt_subscribe()`: returns the current commit metadata and then pushes a
+//!     `nomt_commit` notification with the new commit metadata whenever it changes.
+//!
+//! The database is opened exclusively and never written to by the server.
+
+use anyhow::{anyhow, bail, Result};
+use clap::Parser;
+use nomt::{
+    hasher::Blake3Hasher, key_path, proof::PathProofTerminal, trie::KeyPath, KeyReadWrite, Nomt,
+    Options, SessionParams, WitnessMode,
+};
+use serde_json::{json, Value};
+use std::{
+    io::{BufRead as _, BufReader, Write as _},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+#[derive(Parser)]
+struct Cli {
+    /// The path to the NOMT database.
+    #[clap(long)]
+    path: PathBuf,
+
+    /// The address to listen on.
+    #[clap(long, default_value = "127.0.0.1:9944")]
+    listen: String,
+
+    /// The size of the page cache in MiB.
+    #[clap(long, default_value_t = 256)]
+    page_cache_size: usize,
+
+    /// How often subscriptions poll for new commits, in milliseconds.
+    #[clap(long, default_value_t = 500)]
+    subscription_interval: u64,
+}
+
+// JSON-RPC error codes.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn invalid_params(e: impl std::fmt::Display) -> Self {
+        RpcError {
+            code: INVALID_PARAMS,
+            message: e.to_string(),
+        }
+    }
+
+    fn internal(e: impl std::fmt::Display) -> Self {
+        RpcError {
+            code: INTERNAL_ERROR,
+            message: e.to_string(),
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    if !cli.path.exists() {
+        bail!("database does not exist at {}", cli.path.display());
+    }
+
+    let mut o = Options::new();
+    o.path(cli.path);
+    o.page_cache_size(cli.page_cache_size);
+    let nomt = Arc::new(Nomt::<Blake3Hasher>::open(o)?);
+
+    let listener = TcpListener::bind(&cli.listen)?;
+    let interval = Duration::from_millis(cli.subscription_interval);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let nomt = nomt.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = serve(nomt, stream, interval) {
+                eprintln!("connection closed: {}", e);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn serve(nomt: Arc<Nomt<Blake3Hasher>>, stream: TcpStream, interval: Duration) -> Result<()> {
+    let reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (id, result, subscribe) = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => {
+                let id = request.get("id").cloned().unwrap_or(Value::Null);
+                let subscribe = request.get("method") == Some(&json!("nomt_subscribe"));
+                (id, handle(&nomt, &request), subscribe)
+            }
+            Err(e) => (
+                Value::Null,
+                Err(RpcError {
+                    code: PARSE_ERROR,
+                    message: e.to_string(),
+                }),
+                false,
+            ),
+        };
+
+        let subscribe = subscribe && result.is_ok();
+        write_response(&mut writer, id, result)?;
+
+        if subscribe {
+            // The connection is dedicated to the subscription from now on.
+            return run_subscription(&nomt, &mut writer, interval);
+        }
+    }
+
+    Ok(())
+}
+
+fn write_response(
+    writer: &mut TcpStream,
+    id: Value,
+    result: Result<Value, RpcError>,
+) -> Result<()> {
+    let response = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": e.code, "message": e.message },
+        }),
+    };
+    writeln!(writer, "{}", response)?;
+    Ok(())
+}
+
+fn run_subscription(
+    nomt: &Nomt<Blake3Hasher>,
+    writer: &mut TcpStream,
+    interval: Duration,
+) -> Result<()> {
+    let mut last = commit_metadata(nomt);
+    loop {
+        std::thread::sleep(interval);
+        let current = commit_metadata(nomt);
+        if current != last {
+            let notification = json!({
+                "jsonrpc": "2.0",
+                "method": "nomt_commit",
+                "params": current,
+            });
+            writeln!(writer, "{}", notification)?;
+            last = current;
+        }
+    }
+}
+
+fn handle(nomt: &Nomt<Blake3Hasher>, request: &Value) -> Result<Value, RpcError> {
+    let method = request
+        .get("method")
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError {
+            code: INVALID_REQUEST,
+            message: "missing method".to_string(),
+        })?;
+    let params = request.get("params").cloned().unwrap_or(json!([]));
+
+    match method {
+        "nomt_get" => {
+            let key = parse_key(param(&params, 0)?).map_err(RpcError::invalid_params)?;
+            let session = nomt.begin_read_session();
+            let value = session.read(key).map_err(RpcError::internal)?;
+            Ok(value.map_or(Value::Null, |v| encode(&v)))
+        }
+        "nomt_multiGet" => {
+            let keys = param(&params, 0)?
+                .as_array()
+                .ok_or_else(|| RpcError::invalid_params("expected an array of keys"))?
+                .iter()
+                .map(parse_key)
+                .collect::<Result<Vec<_>>>()
+                .map_err(RpcError::invalid_params)?;
+            let session = nomt.begin_read_session();
+            let mut values = Vec::with_capacity(keys.len());
+            for key in keys {
+                let value = session.read(key).map_err(RpcError::internal)?;
+                values.push(value.map_or(Value::Null, |v| encode(&v)));
+            }
+            Ok(Value::Array(values))
+        }
+        "nomt_prove" => {
+            let key = parse_key(param(&params, 0)?).map_err(RpcError::invalid_params)?;
+            prove(nomt, key).map_err(RpcError::internal)
+        }
+        "nomt_commitMetadata" | "nomt_subscribe" => Ok(commit_metadata(nomt)),
+        _ => Err(RpcError {
+            code: METHOD_NOT_FOUND,
+            message: format!("unknown method: {}", method),
+        }),
+    }
+}
+
+fn param(params: &Value, index: usize) -> Result<&Value, RpcError> {
+    params
+        .get(index)
+        .ok_or_else(|| RpcError::invalid_params(format!("missing parameter {}", index)))
+}
+
+fn commit_metadata(nomt: &Nomt<Blake3Hasher>) -> Value {
+    json!({
+        "root": encode(&nomt.root().into_inner()),
+        "syncSeqn": nomt.sync_seqn(),
+    })
+}
+
+fn prove(nomt: &Nomt<Blake3Hasher>, key: KeyPath) -> Result<Value> {
+    let session =
+        nomt.begin_session(SessionParams::default().witness_mode(WitnessMode::read_write()));
+    let value = session.read(key)?;
+    session.warm_up(key);
+    let mut finished = session.finish(vec![(key, KeyReadWrite::Read(value))])?;
+    let root = finished.root();
+    let witness = finished
+        .take_witness()
+        .ok_or_else(|| anyhow!("no witness produced"))?;
+    let path = witness
+        .path_proofs
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("no path proof produced"))?;
+
+    let terminal = match path.inner.terminal {
+        PathProofTerminal::Leaf(leaf) => json!({
+            "leaf": {
+                "keyPath": encode(&leaf.key_path),
+                "valueHash": encode(&leaf.value_hash),
+            }
+        }),
+        PathProofTerminal::Terminator(pos) => json!({
+            "terminator": { "depth": pos.depth() }
+        }),
+    };
+    let siblings: Vec<Value> = path.inner.siblings.iter().map(|s| encode(s)).collect();
+
+    Ok(json!({
+        "root": encode(&root.into_inner()),
+        "terminal": terminal,
+        "siblings": siblings,
+    }))
+}
+
+fn parse_key(value: &Value) -> Result<KeyPath> {
+    let s = value
+        .as_str()
+        .ok_or_else(|| anyhow!("expected a hex string"))?;
+    key_path::from_hex(s).map_err(|_| anyhow!("key paths must be 32 hex-encoded bytes"))
+}
+
+fn encode(bytes: &[u8]) -> Value {
+    Value::String(format!("0x{}", hex::encode(bytes)))
+}
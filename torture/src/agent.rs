@@ -366,7 +366,8 @@ impl Agent {
 
         // Perform the commit.
         let commit_result =
-            block_in_place(|| session.finish(actuals)?.commit(&nomt), "Panic in commit");
+            block_in_place(|| session.finish(actuals)?.commit(&nomt), "Panic in commit")
+                .map(|_| ());
         let commit_outcome = classify_result(commit_result);
 
         // Log the outcome if it was not successful.